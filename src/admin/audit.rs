@@ -0,0 +1,47 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+
+/// One admin action (or denied attempt) performed through a control interface
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub date: DateTime<Utc>,
+    pub operator: String,
+    pub action: String,
+    pub details: String,
+    pub allowed: bool,
+    pub outcome: String,
+}
+
+pub async fn record_audit_entry(
+    collection: &Collection<AuditEntry>,
+    operator: &str,
+    action: &str,
+    details: &str,
+    allowed: bool,
+    outcome: &str,
+) -> Result<()> {
+    tracing::info!(
+        "Admin audit: operator={} action={} details={} allowed={} outcome={}",
+        operator,
+        action,
+        details,
+        allowed,
+        outcome
+    );
+    collection
+        .insert_one(
+            AuditEntry {
+                date: Utc::now(),
+                operator: operator.to_string(),
+                action: action.to_string(),
+                details: details.to_string(),
+                allowed,
+                outcome: outcome.to_string(),
+            },
+            None,
+        )
+        .await?;
+    Ok(())
+}
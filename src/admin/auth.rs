@@ -0,0 +1,294 @@
+use anyhow::anyhow;
+use mongodb::Collection;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use subtle::ConstantTimeEq;
+use thiserror::Error;
+
+use crate::config::AdminConfig;
+
+use super::audit::{record_audit_entry, AuditEntry};
+
+const DESTRUCTIVE_WINDOW: Duration = Duration::from_secs(3600);
+
+/// Compare a presented secret with the expected one in constant time, so
+/// the response time doesn't tell how much of it was right
+pub fn secret_eq(presented: &str, expected: &str) -> bool {
+    presented.as_bytes().ct_eq(expected.as_bytes()).into()
+}
+
+/// Permission level of an operator, each scope includes the ones below it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    Read,
+    Trade,
+    Admin,
+}
+
+impl FromStr for Scope {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "read" => Ok(Scope::Read),
+            "trade" => Ok(Scope::Trade),
+            "admin" => Ok(Scope::Admin),
+            other => Err(anyhow!(
+                "Unknown scope '{}', expected read/trade/admin",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::Read => write!(f, "read"),
+            Scope::Trade => write!(f, "trade"),
+            Scope::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+/// What a request presents to prove who sent it
+#[derive(Debug, Clone, Copy)]
+pub enum Credential<'a> {
+    TelegramUser(i64),
+    ApiToken(&'a str),
+}
+
+/// An authenticated operator
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operator {
+    pub id: String,
+    pub scope: Scope,
+}
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("operator is not on the allowlist")]
+    UnknownOperator,
+    #[error("{operator} has scope {granted}, {required} is required")]
+    InsufficientScope {
+        operator: String,
+        granted: Scope,
+        required: Scope,
+    },
+    #[error("{operator} exceeded the destructive command limit, retry in {retry_after_secs} s")]
+    RateLimited {
+        operator: String,
+        retry_after_secs: u64,
+    },
+}
+
+/// Sliding window limit on how often each operator may run an action
+pub struct RateLimiter {
+    max: usize,
+    window: Duration,
+    hits: HashMap<String, VecDeque<Instant>>,
+}
+
+impl RateLimiter {
+    pub fn new(max: usize, window: Duration) -> Self {
+        Self {
+            max,
+            window,
+            hits: HashMap::new(),
+        }
+    }
+
+    /// Count a hit for `key` at `now`, or return how long to wait until the
+    /// oldest hit leaves the window
+    pub fn check(&mut self, key: &str, now: Instant) -> Result<(), Duration> {
+        let hits = self.hits.entry(key.to_string()).or_default();
+        while hits
+            .front()
+            .is_some_and(|t| now.duration_since(*t) >= self.window)
+        {
+            hits.pop_front();
+        }
+        if hits.len() >= self.max {
+            let oldest = hits.front().copied().unwrap_or(now);
+            return Err(self.window.saturating_sub(now.duration_since(oldest)));
+        }
+        hits.push_back(now);
+        Ok(())
+    }
+}
+
+/// Allowlist based authorization shared by the control interfaces. Every
+/// decision and every executed action ends up in the audit collection.
+pub struct AccessControl {
+    telegram_users: HashMap<i64, Scope>,
+    /// (token, name, scope)
+    api_tokens: Vec<(String, String, Scope)>,
+    destructive_limiter: Mutex<RateLimiter>,
+    audit: Collection<AuditEntry>,
+}
+
+impl AccessControl {
+    pub fn new(cfg: AdminConfig, audit: Collection<AuditEntry>) -> Self {
+        Self {
+            telegram_users: cfg.telegram_users.into_iter().collect(),
+            api_tokens: cfg
+                .api_tokens
+                .into_iter()
+                .map(|t| (t.token, t.name, t.scope))
+                .collect(),
+            destructive_limiter: Mutex::new(RateLimiter::new(
+                cfg.destructive_max_per_hour as usize,
+                DESTRUCTIVE_WINDOW,
+            )),
+            audit,
+        }
+    }
+
+    fn authenticate(&self, credential: Credential<'_>) -> Option<Operator> {
+        match credential {
+            Credential::TelegramUser(user_id) => {
+                self.telegram_users.get(&user_id).map(|scope| Operator {
+                    id: format!("tg:{}", user_id),
+                    scope: *scope,
+                })
+            }
+            // Every token is compared, none is skipped after a match
+            Credential::ApiToken(token) => {
+                self.api_tokens
+                    .iter()
+                    .fold(None, |found, (candidate, name, scope)| {
+                        if secret_eq(token, candidate) {
+                            Some(Operator {
+                                id: format!("api:{}", name),
+                                scope: *scope,
+                            })
+                        } else {
+                            found
+                        }
+                    })
+            }
+        }
+    }
+
+    /// Check that the sender of `action` is allowed to run it. Destructive
+    /// actions (e.g. sell-all) are additionally rate limited per operator.
+    /// Denials are audited here, executed actions via [`Self::audit`].
+    pub async fn authorize(
+        &self,
+        credential: Credential<'_>,
+        action: &str,
+        required: Scope,
+        destructive: bool,
+    ) -> Result<Operator, AuthError> {
+        let result = self.check(credential, required, destructive);
+        if let Err(e) = &result {
+            let operator = match credential {
+                Credential::TelegramUser(user_id) => format!("tg:{}", user_id),
+                Credential::ApiToken(_) => self
+                    .authenticate(credential)
+                    .map_or("api:<unknown token>".to_string(), |o| o.id),
+            };
+            self.record(&operator, action, "", false, &e.to_string())
+                .await;
+        }
+        result
+    }
+
+    fn check(
+        &self,
+        credential: Credential<'_>,
+        required: Scope,
+        destructive: bool,
+    ) -> Result<Operator, AuthError> {
+        let operator = self
+            .authenticate(credential)
+            .ok_or(AuthError::UnknownOperator)?;
+        if operator.scope < required {
+            return Err(AuthError::InsufficientScope {
+                operator: operator.id,
+                granted: operator.scope,
+                required,
+            });
+        }
+        if destructive {
+            let mut limiter = self
+                .destructive_limiter
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            if let Err(wait) = limiter.check(&operator.id, Instant::now()) {
+                return Err(AuthError::RateLimited {
+                    operator: operator.id,
+                    retry_after_secs: wait.as_secs().max(1),
+                });
+            }
+        }
+        Ok(operator)
+    }
+
+    /// Record an authorized action and how it went
+    pub async fn audit(&self, operator: &Operator, action: &str, details: &str, outcome: &str) {
+        self.record(&operator.id, action, details, true, outcome)
+            .await;
+    }
+
+    async fn record(
+        &self,
+        operator: &str,
+        action: &str,
+        details: &str,
+        allowed: bool,
+        outcome: &str,
+    ) {
+        if let Err(e) =
+            record_audit_entry(&self.audit, operator, action, details, allowed, outcome).await
+        {
+            tracing::error!("Failed to write admin audit entry: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scope_ordering() {
+        assert!(Scope::Admin > Scope::Trade);
+        assert!(Scope::Trade > Scope::Read);
+        assert_eq!("Admin".parse::<Scope>().unwrap(), Scope::Admin);
+        assert!("root".parse::<Scope>().is_err());
+    }
+
+    #[test]
+    fn test_secret_eq() {
+        assert!(secret_eq("s3cret", "s3cret"));
+        assert!(!secret_eq("s3cres", "s3cret"));
+        assert!(!secret_eq("s3cre", "s3cret"));
+        assert!(!secret_eq("", "s3cret"));
+    }
+
+    #[test]
+    fn test_rate_limiter_window() {
+        let mut limiter = RateLimiter::new(2, Duration::from_secs(60));
+        let start = Instant::now();
+        assert!(limiter.check("tg:1", start).is_ok());
+        assert!(limiter
+            .check("tg:1", start + Duration::from_secs(10))
+            .is_ok());
+        let wait = limiter
+            .check("tg:1", start + Duration::from_secs(20))
+            .unwrap_err();
+        assert_eq!(wait, Duration::from_secs(40));
+        // other operators have their own budget
+        assert!(limiter
+            .check("tg:2", start + Duration::from_secs(20))
+            .is_ok());
+        // the first hit has left the window
+        assert!(limiter
+            .check("tg:1", start + Duration::from_secs(60))
+            .is_ok());
+    }
+}
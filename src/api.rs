@@ -0,0 +1,599 @@
+use anyhow::{anyhow, Result};
+use axum::{
+    extract::{Path, Query, State},
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{Duration as ChronoDuration, Utc};
+use listen_kit::signer::{SignerContext, TransactionSigner};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::watch;
+
+use crate::admin::auth::{secret_eq, AccessControl, AuthError, Credential, Operator, Scope};
+use crate::config::LatencySloConfig;
+use crate::notify::TradeEvent;
+use crate::solana::scan::{get_portfolio, Portfolio};
+use crate::solana::wallet_events::{HeliusTransaction, WalletTouch};
+use crate::storage::Storage;
+use crate::tg_copy::active_trade::ActiveTrade;
+use crate::tg_copy::db::TradeDocument;
+use crate::tg_copy::flood::{FloodWaitMetrics, FloodWaitStats};
+use crate::tg_copy::parse_trade::Trade;
+use crate::tg_copy::relogin::{LoginStep, TelegramLogin};
+use crate::trade::bot_state::{BotState, BotStateStore};
+use crate::trade::execution::ExecutionLog;
+use crate::trade::latency::{slo_report, SloReport};
+use crate::trade::meme_trader::{sell_outcome, MemeTrader};
+use crate::trade::risk::realized_pnl_today;
+use crate::trade::tasks::{TaskManager, TaskMetrics};
+
+const DEFAULT_TRADES_LIMIT: i64 = 50;
+const MAX_TRADES_LIMIT: i64 = 500;
+/// Header carrying the key of signal webhook calls
+const API_KEY_HEADER: &str = "x-api-key";
+/// Signal source of `POST /signal`
+const WEBHOOK_SOURCE: &str = "webhook";
+
+#[derive(Clone)]
+struct ApiState {
+    trader: Arc<MemeTrader>,
+    access: Arc<AccessControl>,
+    storage: Arc<dyn Storage>,
+    tasks: Arc<TaskManager>,
+    /// Buys of the last 24 hours are checked against the SLO
+    latency_slo: Option<(ExecutionLog, LatencySloConfig)>,
+    login: Arc<TelegramLogin>,
+    flood_waits: Arc<FloodWaitStats>,
+    /// Handlers run on their own tasks, manual sells re-enter the context
+    signer: Arc<dyn TransactionSigner>,
+    tip_lamports: u64,
+    /// `Authorization` header expected on wallet webhook calls
+    wallet_webhook_auth: Option<String>,
+    /// Key expected on signal webhook calls and the queue of the copier
+    signal_webhook: Option<(String, mpsc::Sender<(&'static str, Trade)>)>,
+}
+
+enum ApiError {
+    Auth(AuthError),
+    BadRequest(String),
+    Internal(anyhow::Error),
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        ApiError::Internal(e)
+    }
+}
+
+impl From<mongodb::error::Error> for ApiError {
+    fn from(e: mongodb::error::Error) -> Self {
+        ApiError::Internal(e.into())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, message) = match self {
+            ApiError::Auth(e @ AuthError::UnknownOperator) => {
+                (StatusCode::UNAUTHORIZED, e.to_string())
+            }
+            ApiError::Auth(e @ AuthError::InsufficientScope { .. }) => {
+                (StatusCode::FORBIDDEN, e.to_string())
+            }
+            ApiError::Auth(e @ AuthError::RateLimited { .. }) => {
+                (StatusCode::TOO_MANY_REQUESTS, e.to_string())
+            }
+            ApiError::BadRequest(message) => (StatusCode::BAD_REQUEST, message),
+            ApiError::Internal(e) => {
+                tracing::error!("API request failed: {:?}", e);
+                (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+            }
+        };
+        (status, Json(json!({ "error": message }))).into_response()
+    }
+}
+
+type ApiResult<T> = Result<Json<T>, ApiError>;
+
+async fn authorize(
+    state: &ApiState,
+    headers: &HeaderMap,
+    action: &str,
+    required: Scope,
+) -> Result<Operator, ApiError> {
+    let token = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    state
+        .access
+        .authorize(Credential::ApiToken(token), action, required, false)
+        .await
+        .map_err(ApiError::Auth)
+}
+
+async fn positions(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+) -> ApiResult<Vec<ActiveTrade>> {
+    authorize(&state, &headers, "positions", Scope::Read).await?;
+    Ok(Json(state.trader.active_trades().load_all_trades().await?))
+}
+
+/// What the trading wallet holds, valued at current prices
+async fn portfolio(State(state): State<ApiState>, headers: HeaderMap) -> ApiResult<Portfolio> {
+    authorize(&state, &headers, "portfolio", Scope::Read).await?;
+    let owner = Pubkey::from_str(&state.signer.pubkey()).map_err(|e| anyhow!(e))?;
+    Ok(Json(get_portfolio(&owner).await?))
+}
+
+#[derive(Deserialize)]
+struct TradesQuery {
+    strategy: Option<String>,
+    limit: Option<i64>,
+}
+
+async fn trades(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Query(query): Query<TradesQuery>,
+) -> ApiResult<Vec<TradeDocument>> {
+    authorize(&state, &headers, "trades", Scope::Read).await?;
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_TRADES_LIMIT)
+        .clamp(1, MAX_TRADES_LIMIT);
+    Ok(Json(
+        state
+            .storage
+            .recent_trades(query.strategy.as_deref(), limit)
+            .await?,
+    ))
+}
+
+#[derive(Serialize)]
+struct PnlReport {
+    realized_today_lamports: i64,
+    realized_total_lamports: i64,
+    open_positions: usize,
+    /// Cost basis of the remaining holdings of open positions
+    open_cost_lamports: u64,
+}
+
+async fn pnl(State(state): State<ApiState>, headers: HeaderMap) -> ApiResult<PnlReport> {
+    authorize(&state, &headers, "pnl", Scope::Read).await?;
+    let active_trades = state.trader.active_trades();
+    let open = active_trades.load_all_trades().await?;
+    Ok(Json(PnlReport {
+        realized_today_lamports: realized_pnl_today(&state.trader).await?,
+        realized_total_lamports: active_trades
+            .load_closed_since(0)
+            .await?
+            .iter()
+            .map(ActiveTrade::realized_pnl_lamports)
+            .sum(),
+        open_positions: open.len(),
+        open_cost_lamports: open.iter().map(ActiveTrade::remaining_cost_lamports).sum(),
+    }))
+}
+
+#[derive(Serialize)]
+struct Metrics {
+    #[serde(flatten)]
+    tasks: TaskMetrics,
+    /// Telegram flood waits slept off by the listener
+    #[serde(flatten)]
+    telegram: FloodWaitMetrics,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency_slo: Option<SloReport>,
+}
+
+async fn metrics(State(state): State<ApiState>, headers: HeaderMap) -> ApiResult<Metrics> {
+    authorize(&state, &headers, "metrics", Scope::Read).await?;
+    let latency_slo = match &state.latency_slo {
+        Some((executions, cfg)) => {
+            let buys = executions
+                .buys_since(Utc::now() - ChronoDuration::days(1))
+                .await?;
+            Some(slo_report(cfg, &buys))
+        }
+        None => None,
+    };
+    Ok(Json(Metrics {
+        tasks: state.tasks.metrics(),
+        telegram: state.flood_waits.metrics(),
+        latency_slo,
+    }))
+}
+
+#[derive(Deserialize)]
+struct SellQuery {
+    pct: Option<f64>,
+    strategy: Option<String>,
+}
+
+/// A position sold, or why it couldn't be
+#[derive(Serialize)]
+struct SellResult {
+    strategy: String,
+    token_amount: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tx_sig: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+async fn sell(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Path(mint): Path<String>,
+    Query(query): Query<SellQuery>,
+) -> ApiResult<Vec<SellResult>> {
+    let operator = authorize(&state, &headers, "sell", Scope::Trade).await?;
+    let pct = query.pct.unwrap_or(100.0);
+    if !(pct > 0.0 && pct <= 100.0) {
+        return Err(ApiError::BadRequest(format!(
+            "pct must be within (0, 100], got {}",
+            pct
+        )));
+    }
+    let details = format!(
+        "{} {}% {}",
+        mint,
+        pct,
+        query.strategy.as_deref().unwrap_or("all")
+    );
+
+    let trader = Arc::clone(&state.trader);
+    let tip_lamports = state.tip_lamports;
+    let result = SignerContext::with_signer(Arc::clone(&state.signer), async move {
+        trader
+            .sell_mint(
+                &mint,
+                pct,
+                query.strategy.as_deref(),
+                tip_lamports,
+                "Manual sell (API)",
+            )
+            .await
+    })
+    .await;
+
+    let outcome = match &result {
+        Ok(results) => sell_outcome(results),
+        Err(e) => format!("failed: {}", e),
+    };
+    state
+        .access
+        .audit(&operator, "sell", &details, &outcome)
+        .await;
+
+    Ok(Json(
+        result?
+            .into_iter()
+            .map(|(trade, fill)| match fill {
+                Ok(fill) => SellResult {
+                    strategy: trade.strategy_id,
+                    token_amount: fill.token_amount,
+                    tx_sig: Some(fill.tx_sig),
+                    error: None,
+                },
+                Err(e) => SellResult {
+                    strategy: trade.strategy_id,
+                    token_amount: 0,
+                    tx_sig: None,
+                    error: Some(format!("{:#}", e)),
+                },
+            })
+            .collect(),
+    ))
+}
+
+fn bot_state_store(state: &ApiState) -> Result<&BotStateStore, ApiError> {
+    state
+        .trader
+        .bot_state()
+        .ok_or_else(|| ApiError::Internal(anyhow!("Pause switches are not kept")))
+}
+
+/// Operator pauses and those of the gate (maintenance, daily loss)
+fn pause_status(state: &ApiState, bot_state: &BotState) -> serde_json::Value {
+    json!({
+        "status": state.trader.buy_gate().to_string(),
+        "bot_state": bot_state,
+    })
+}
+
+async fn get_bot_state(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+) -> ApiResult<serde_json::Value> {
+    authorize(&state, &headers, "bot_state", Scope::Read).await?;
+    let bot_state = bot_state_store(&state)?.load().await?;
+    Ok(Json(pause_status(&state, &bot_state)))
+}
+
+#[derive(Deserialize)]
+struct PauseQuery {
+    reason: Option<String>,
+    strategy: Option<String>,
+}
+
+async fn pause(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Query(query): Query<PauseQuery>,
+) -> ApiResult<serde_json::Value> {
+    let operator = authorize(&state, &headers, "pause", Scope::Trade).await?;
+    let reason = format!(
+        "paused by {}{}",
+        operator.id,
+        query.reason.map_or(String::new(), |r| format!(": {}", r))
+    );
+    let strategy = query.strategy.as_deref();
+    let (bot_state, changed) = bot_state_store(&state)?.pause(strategy, &reason).await?;
+    if changed {
+        state.trader.notify(TradeEvent::BuysPaused {
+            reason: match strategy {
+                Some(strategy) => format!("{} {}", strategy, reason),
+                None => reason.clone(),
+            },
+            breaker: None,
+        });
+    }
+    state
+        .access
+        .audit(
+            &operator,
+            "pause",
+            &format!("{} ({})", reason, strategy.unwrap_or("all strategies")),
+            if changed { "paused" } else { "already paused" },
+        )
+        .await;
+    Ok(Json(pause_status(&state, &bot_state)))
+}
+
+#[derive(Deserialize)]
+struct ResumeQuery {
+    strategy: Option<String>,
+}
+
+async fn resume(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Query(query): Query<ResumeQuery>,
+) -> ApiResult<serde_json::Value> {
+    let operator = authorize(&state, &headers, "resume", Scope::Trade).await?;
+    let strategy = query.strategy.as_deref();
+    let (bot_state, changed) = bot_state_store(&state)?.resume(strategy).await?;
+    if changed {
+        state.trader.notify(TradeEvent::BuysResumed {
+            reason: match strategy {
+                Some(strategy) => format!("{} resumed by {}", strategy, operator.id),
+                None => format!("resumed by {}", operator.id),
+            },
+        });
+    }
+    state
+        .access
+        .audit(
+            &operator,
+            "resume",
+            strategy.unwrap_or("all strategies"),
+            if changed { "resumed" } else { "was not paused" },
+        )
+        .await;
+    // Other sources (maintenance, daily loss) may still hold a pause
+    Ok(Json(pause_status(&state, &bot_state)))
+}
+
+#[derive(Deserialize)]
+struct LoginCodeRequest {
+    phone: String,
+}
+
+#[derive(Deserialize)]
+struct SignInRequest {
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct PasswordRequest {
+    password: String,
+}
+
+/// Audit a login step, its secrets stay out of the record
+async fn audit_login(
+    state: &ApiState,
+    operator: &Operator,
+    action: &str,
+    result: &anyhow::Result<LoginStep>,
+) {
+    let outcome = match result {
+        Ok(step) => format!("{:?}", step),
+        Err(e) => format!("failed: {}", e),
+    };
+    state.access.audit(operator, action, "", &outcome).await;
+}
+
+fn login_result(result: anyhow::Result<LoginStep>) -> ApiResult<LoginStep> {
+    // Wrong codes and passwords are the operator's to fix
+    result
+        .map(Json)
+        .map_err(|e| ApiError::BadRequest(e.to_string()))
+}
+
+async fn login_code(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Json(request): Json<LoginCodeRequest>,
+) -> ApiResult<LoginStep> {
+    let operator = authorize(&state, &headers, "telegram_login_code", Scope::Admin).await?;
+    let result = state.login.request_code(&request.phone).await;
+    audit_login(&state, &operator, "telegram_login_code", &result).await;
+    login_result(result)
+}
+
+async fn login_sign_in(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Json(request): Json<SignInRequest>,
+) -> ApiResult<LoginStep> {
+    let operator = authorize(&state, &headers, "telegram_sign_in", Scope::Admin).await?;
+    let result = state.login.sign_in(&request.code).await;
+    audit_login(&state, &operator, "telegram_sign_in", &result).await;
+    login_result(result)
+}
+
+async fn login_password(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Json(request): Json<PasswordRequest>,
+) -> ApiResult<LoginStep> {
+    let operator = authorize(&state, &headers, "telegram_password", Scope::Admin).await?;
+    let result = state.login.check_password(&request.password).await;
+    audit_login(&state, &operator, "telegram_password", &result).await;
+    login_result(result)
+}
+
+/// Helius enhanced transaction webhook for the signer wallet. Answers at
+/// once, Helius retries slow deliveries; the balances are refreshed on a
+/// task of their own.
+async fn wallet_webhook(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Json(transactions): Json<Vec<HeliusTransaction>>,
+) -> StatusCode {
+    let authorization = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    let authorized = authorization
+        .zip(state.wallet_webhook_auth.as_deref())
+        .is_some_and(|(presented, expected)| secret_eq(presented, expected));
+    if !authorized {
+        return StatusCode::UNAUTHORIZED;
+    }
+
+    let wallet = state.signer.pubkey();
+    let mut touch = WalletTouch::default();
+    for tx in &transactions {
+        let Some(tx_touch) = tx.touched(&wallet) else {
+            continue;
+        };
+        tracing::debug!("Wallet webhook: {} touched {:?}", tx.signature, tx_touch);
+        touch.sol |= tx_touch.sol;
+        for mint in tx_touch.mints {
+            if !touch.mints.contains(&mint) {
+                touch.mints.push(mint);
+            }
+        }
+    }
+    if touch != WalletTouch::default() {
+        tokio::spawn(async move {
+            if let Err(e) = state.trader.apply_wallet_event(&wallet, &touch).await {
+                tracing::error!("Failed to apply wallet webhook event: {:?}", e);
+            }
+        });
+    }
+    StatusCode::OK
+}
+
+/// Open or close signal from outside Telegram, a TradingView alert or
+/// another bot. It is queued for the copier and goes through the same
+/// dedup, checks and execution as the group's signals; `202` only means
+/// it was queued.
+async fn post_signal(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Json(trade): Json<Trade>,
+) -> StatusCode {
+    let Some((api_key, signals)) = &state.signal_webhook else {
+        return StatusCode::NOT_FOUND;
+    };
+    let key = headers
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok());
+    if !key.is_some_and(|key| secret_eq(key, api_key)) {
+        return StatusCode::UNAUTHORIZED;
+    }
+
+    tracing::info!("Signal webhook: {:?}", trade);
+    match signals.try_send((WEBHOOK_SOURCE, trade)) {
+        Ok(()) => StatusCode::ACCEPTED,
+        Err(TrySendError::Full((_, trade))) => {
+            tracing::warn!("Signal queue full, rejected {:?}", trade);
+            StatusCode::TOO_MANY_REQUESTS
+        }
+        Err(TrySendError::Closed(_)) => StatusCode::SERVICE_UNAVAILABLE,
+    }
+}
+
+/// Serve the control API on `bind_addr` until shutdown. Manual sells go
+/// through the signer of the calling context.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_api_server(
+    bind_addr: String,
+    trader: Arc<MemeTrader>,
+    access: Arc<AccessControl>,
+    storage: Arc<dyn Storage>,
+    tasks: Arc<TaskManager>,
+    latency_slo: Option<(ExecutionLog, LatencySloConfig)>,
+    login: Arc<TelegramLogin>,
+    flood_waits: Arc<FloodWaitStats>,
+    tip_lamports: u64,
+    wallet_webhook_auth: Option<String>,
+    signal_webhook: Option<(String, mpsc::Sender<(&'static str, Trade)>)>,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    let state = ApiState {
+        trader,
+        access,
+        storage,
+        tasks,
+        latency_slo,
+        login,
+        flood_waits,
+        signer: SignerContext::current().await,
+        tip_lamports,
+        wallet_webhook_auth,
+        signal_webhook,
+    };
+    let mut app = Router::new()
+        .route("/positions", get(positions))
+        .route("/portfolio", get(portfolio))
+        .route("/trades", get(trades))
+        .route("/pnl", get(pnl))
+        .route("/metrics", get(metrics))
+        .route("/sell/{mint}", post(sell))
+        .route("/bot_state", get(get_bot_state))
+        .route("/pause", post(pause))
+        .route("/resume", post(resume))
+        .route("/telegram/login/code", post(login_code))
+        .route("/telegram/login/sign_in", post(login_sign_in))
+        .route("/telegram/login/password", post(login_password));
+    if state.wallet_webhook_auth.is_some() {
+        app = app.route("/webhooks/helius", post(wallet_webhook));
+    }
+    if state.signal_webhook.is_some() {
+        app = app.route("/signal", post(post_signal));
+    }
+    let app = app.with_state(state);
+
+    let listener = tokio::net::TcpListener::bind(&bind_addr).await?;
+    tracing::info!("API listening on {}", bind_addr);
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move {
+            let _ = shutdown.changed().await;
+        })
+        .await?;
+    tracing::info!("API stopped");
+    Ok(())
+}
@@ -0,0 +1,35 @@
+//! Tags historical trade signals with their strategy documents.
+//!
+//! ```sh
+//! cargo run --bin backfill_strategies
+//! ```
+use anyhow::Result;
+use copy_trade_telegram::config::DbConfig;
+use copy_trade_telegram::storage::open_storage;
+use copy_trade_telegram::tg_copy::backfill::backfill_strategy_tags;
+use dotenv::dotenv;
+use tracing_subscriber::{filter::LevelFilter, EnvFilter};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenv().ok();
+
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::builder()
+                .with_default_directive(LevelFilter::INFO.into())
+                .from_env_lossy(),
+        )
+        .init();
+
+    let db_config = DbConfig::from_env()?;
+    let client = mongodb::Client::with_uri_str(&db_config.mongodb_uri).await?;
+    let db = client.database(&db_config.db_name);
+
+    let storage = open_storage(&db_config, &db).await?;
+
+    let report = backfill_strategy_tags(&*storage).await?;
+    tracing::info!("Strategy backfill done: {}", report);
+
+    Ok(())
+}
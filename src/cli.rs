@@ -0,0 +1,825 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand};
+use listen_kit::signer::SignerContext;
+use listen_kit::solana::balance::get_balance;
+use solana_sdk::{native_token::sol_to_lamports, pubkey::Pubkey};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::config::{
+    redact_url, ApprovalConfig, CapitalConfig, ComputeBudgetConfig, DbConfig, JitoConfig,
+    JupiterSwapConfig, PriceFeedConfig, PrivyConfig, ProfilesConfig, RpcConfig, SellRetryConfig,
+    TradingConfig, VenueSelectionConfig,
+};
+use crate::format;
+use crate::solana::housekeeping::sweep_token_accounts;
+use crate::solana::price::{self, PriceAggregator};
+use crate::solana::quoter::{Quoter, Side};
+use crate::solana::route::PoolProgram;
+use crate::solana::rpc::make_rpc_client;
+use crate::solana::scan::get_portfolio;
+use crate::solana::transfer::transfer_sol;
+use crate::solana::wallet_manager::WalletManager;
+use crate::storage::open_storage;
+use crate::tg_copy::copier::is_strategy_traded;
+use crate::tg_copy::copy_trader::CopyTraderConfig;
+use crate::tg_copy::db::{is_duplicate_trade, store_trade_db};
+use crate::tg_copy::strategy_manager::StrategyManager;
+use crate::tg_copy::unparsed::{self, UnparsedMessages};
+use crate::trade::bot_state::BotStateStore;
+use crate::trade::capital::{CapitalStore, WITHDRAWAL_CONFIRM_SECS};
+use crate::trade::execution::ExecutionLog;
+use crate::trade::exposure::exposure_by_mint;
+use crate::trade::meme_trader::MemeTrader;
+use crate::trade::report::{aggregate, closed_trades, to_csv, Period};
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "copy-trade",
+    version,
+    about = "Copy trades from Telegram signals on Solana"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Trade on devnet with airdropped SOL, mainnet-only lookups are mocked
+    #[arg(long, global = true)]
+    pub devnet: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Copy signals from the configured group (default)
+    Run,
+    /// Log in to Telegram on the terminal, save the session and exit
+    Login,
+    /// Store the trades posted in a chat without trading on them
+    Download {
+        /// Chat name as shown in your dialogs, numeric chat ID or invite link
+        chat: String,
+        /// Only store the messages of this forum topic
+        #[arg(long)]
+        topic: Option<i32>,
+    },
+    /// Feed an archive recorded with RAW_UPDATE_LOG through the listener
+    Replay {
+        /// Archive file, gzip compressed JSON lines
+        file: String,
+        /// Database the replay stores into, DB_NAME with a `_replay` suffix
+        /// by default. Never the live one.
+        #[arg(long)]
+        db: Option<String>,
+        /// Trade the replayed signals (with TRADE_ON=true), otherwise they
+        /// are only parsed and stored
+        #[arg(long)]
+        trade: bool,
+    },
+    /// Parse the kept messages that didn't parse again, with the current
+    /// parser
+    Reparse {
+        /// Only messages kept for this reason, `unknown_format` or
+        /// `no_address`
+        #[arg(long)]
+        reason: Option<String>,
+        /// Store the trades read in the trades collection and drop their
+        /// messages, they are not traded
+        #[arg(long)]
+        store: bool,
+    },
+    /// List open positions and what the wallet holds
+    Positions,
+    /// Open exposure per mint, summed across strategies and providers
+    Exposure {
+        /// Only show mints held by more than one strategy or provider
+        #[arg(long)]
+        overlapping: bool,
+    },
+    /// Sell an open position right away
+    Sell {
+        /// Token mint address
+        mint: String,
+        /// Share of the remaining holdings to sell
+        #[arg(long, default_value_t = 100.0)]
+        pct: f64,
+        /// Only sell the position of this strategy
+        #[arg(long)]
+        strategy: Option<String>,
+    },
+    /// Show SOL and token balances of the trading wallet
+    Balance,
+    /// Quote a swap on every venue of a token and print the best
+    Quote {
+        /// Token mint address
+        mint: String,
+        /// SOL to buy with
+        #[arg(long, required_unless_present = "sell", conflicts_with = "sell")]
+        sol: Option<f64>,
+        /// Raw token amount to sell
+        #[arg(long)]
+        sell: Option<u64>,
+    },
+    /// Launch a token on pump.fun from the trading wallet, to test trading
+    /// on a token you control
+    Deploy {
+        name: String,
+        symbol: String,
+        /// Image file of the token
+        image: PathBuf,
+        /// SOL to buy the token with in the creating transaction
+        #[arg(long, default_value_t = 0.0)]
+        buy_sol: f64,
+    },
+    /// Close empty token accounts and unwrap stray wrapped SOL, recovering
+    /// their rent. Mints of open positions are kept.
+    Housekeeping {
+        /// Only list what would be closed
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Pause or resume buys of every or one strategy, for the running copier
+    /// too. Open positions keep being managed.
+    Trading {
+        #[command(subcommand)]
+        action: TradingAction,
+    },
+    /// Lock SOL so trading can't spend it
+    Capital {
+        #[command(subcommand)]
+        action: CapitalAction,
+    },
+    /// Withdraw SOL to an allow-listed address in two steps
+    Withdraw {
+        #[command(subcommand)]
+        action: WithdrawAction,
+    },
+    /// Realized PnL of the closed trades per strategy, from the executions
+    Report {
+        #[arg(long, value_enum, default_value_t = Period::All)]
+        period: Period,
+        /// Only trades closed in the last this many days
+        #[arg(long)]
+        days: Option<i64>,
+        /// Also write the report to this CSV file
+        #[arg(long)]
+        csv: Option<String>,
+    },
+    /// Create a Solana server wallet in the Privy app of PRIVY_APP_ID, to
+    /// sign with through SIGNER_BACKEND=privy
+    PrivyWallet {
+        /// Key quorum ID allowed to sign with the wallet as a session
+        /// signer, repeat for several
+        #[arg(long)]
+        session_signer: Vec<String>,
+        /// Set the session signers of this existing wallet instead of
+        /// creating one
+        #[arg(long, requires = "session_signer")]
+        wallet: Option<String>,
+    },
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TradingAction {
+    /// Stop new buys
+    Pause {
+        /// Only pause this strategy
+        #[arg(long)]
+        strategy: Option<String>,
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Lift a pause, strategies paused on their own stay paused
+    Resume {
+        /// Only resume this strategy
+        #[arg(long)]
+        strategy: Option<String>,
+    },
+    /// Show the pauses
+    Status,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CapitalAction {
+    /// Keep this much SOL out of reach of trading, replacing the current lock
+    Lock { sol: f64 },
+    /// Release the locked SOL
+    Unlock,
+    /// Show the locked SOL and the pending withdrawal
+    Status,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum WithdrawAction {
+    /// Start a withdrawal, prints the code that confirms it
+    Request {
+        /// Destination, has to be in WITHDRAW_ALLOWLIST
+        destination: String,
+        sol: f64,
+    },
+    /// Send the pending withdrawal
+    Confirm { code: String },
+    /// Drop the pending withdrawal
+    Cancel,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigAction {
+    /// Print the configuration from `.env`, the environment and the
+    /// defaults, secrets redacted
+    Show {
+        /// Also print what it resolves to: the endpoints used, the
+        /// strategies the filter lets through, the venues swaps route to
+        #[arg(long)]
+        resolved: bool,
+    },
+}
+
+impl Command {
+    /// Whether the command can send transactions
+    pub fn trades(&self) -> bool {
+        matches!(
+            self,
+            Command::Run
+                | Command::Replay { trade: true, .. }
+                | Command::Sell { .. }
+                | Command::Deploy { .. }
+                | Command::Withdraw { .. }
+                | Command::Housekeeping { dry_run: false }
+        )
+    }
+}
+
+async fn open_db() -> Result<mongodb::Database> {
+    let db_config = DbConfig::from_env()?;
+    let client = mongodb::Client::with_uri_str(&db_config.mongodb_uri).await?;
+    Ok(client.database(&db_config.db_name))
+}
+
+async fn open_trader() -> Result<MemeTrader> {
+    let db_config = DbConfig::from_env()?;
+    let db = open_db().await?;
+    let storage = open_storage(&db_config, &db).await?;
+    let mut trader = MemeTrader::new(storage)
+        .with_executions(ExecutionLog::new(db.collection("executions")))
+        .with_sell_retry(SellRetryConfig::from_env()?)
+        .with_compute_budget(ComputeBudgetConfig::from_env()?);
+    if let Some(venue_selection) = VenueSelectionConfig::from_env()? {
+        trader = trader.with_venue_selection(venue_selection);
+    }
+    if let Some(jupiter) = JupiterSwapConfig::from_env()? {
+        trader = trader.with_jupiter(jupiter);
+    }
+    price::install(PriceAggregator::new(
+        &PriceFeedConfig::from_env()?,
+        Some(trader.price_cache()),
+    ));
+    Ok(trader)
+}
+
+pub async fn open_capital() -> Result<CapitalStore> {
+    Ok(CapitalStore::new(open_db().await?.collection("capital")))
+}
+
+pub async fn positions() -> Result<()> {
+    let trader = open_trader().await?;
+    let trades = trader.active_trades().load_all_trades().await?;
+    if trades.is_empty() {
+        println!("No open positions");
+    } else {
+        println!(
+            "{:<12} {:<44} {:<16} {:>12} {:>12} {:>14}",
+            "TOKEN", "MINT", "STRATEGY", "REMAINING", "ENTRY", "SPENT"
+        );
+    }
+    for trade in trades {
+        println!(
+            "{:<12} {:<44} {:<16} {:>12} {:>12} {:>14}",
+            trade.token_name,
+            trade.token_address,
+            trade.strategy_id,
+            format!(
+                "{:.0}%",
+                trade.remaining_holdings as f64 / trade.initial_holdings.max(1) as f64 * 100.0
+            ),
+            format::price(trade.entry_price),
+            format::lamports(trade.sol_spent_lamports)
+        );
+    }
+
+    let owner = Pubkey::from_str(&SignerContext::current().await.pubkey())?;
+    let portfolio = get_portfolio(&owner).await?;
+    println!(
+        "\nWallet {}: {}, {} in total{}",
+        portfolio.owner,
+        format::lamports(portfolio.sol_lamports),
+        format::sol(portfolio.total_sol),
+        portfolio
+            .total_usd
+            .map_or(String::new(), |usd| format!(" ({})", format::usd(usd)))
+    );
+    if portfolio.tokens.is_empty() {
+        return Ok(());
+    }
+    println!(
+        "{:<12} {:<44} {:>12} {:>12} {:>12} {:>14}",
+        "TOKEN", "MINT", "AMOUNT", "PRICE", "VALUE", "VALUE SOL"
+    );
+    for token in &portfolio.tokens {
+        println!(
+            "{:<12} {:<44} {:>12} {:>12} {:>12} {:>14}",
+            token.symbol.as_deref().unwrap_or("-"),
+            token.mint,
+            format::compact(token.ui_amount()),
+            token.price_usd.map_or("-".to_string(), format::price),
+            token.value_usd.map_or("-".to_string(), format::usd),
+            token.value_sol.map_or("-".to_string(), format::sol)
+        );
+    }
+    Ok(())
+}
+
+pub async fn exposure(overlapping_only: bool) -> Result<()> {
+    let trader = open_trader().await?;
+    let trades = trader.active_trades().load_all_trades().await?;
+    let exposures = exposure_by_mint(&trades);
+    let total: u64 = exposures.iter().map(|e| e.cost_lamports).sum();
+
+    println!(
+        "{:<12} {:<44} {:>14} {:>7} {:<24} {:<24}",
+        "TOKEN", "MINT", "COST", "SHARE", "STRATEGIES", "PROVIDERS"
+    );
+    for exposure in exposures
+        .iter()
+        .filter(|e| !overlapping_only || e.is_overlapping())
+    {
+        println!(
+            "{:<12} {:<44} {:>14} {:>7} {:<24} {:<24}",
+            exposure.token_name,
+            exposure.token_address,
+            format::lamports(exposure.cost_lamports),
+            format!(
+                "{:.1}%",
+                exposure.cost_lamports as f64 / total.max(1) as f64 * 100.0
+            ),
+            exposure
+                .strategies
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(","),
+            exposure
+                .providers
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+    }
+    Ok(())
+}
+
+pub async fn sell(mint: &str, pct: f64, strategy: Option<&str>) -> Result<()> {
+    let trading_config = TradingConfig::from_env()?;
+    let trader = open_trader().await?;
+
+    let results = trader
+        .sell_mint(
+            mint,
+            pct,
+            strategy,
+            trading_config.tip_lamports,
+            "Manual sell",
+        )
+        .await?;
+    for (trade, fill) in results {
+        match fill {
+            Ok(fill) => println!(
+                "Sold {} of {} ({}): https://solscan.io/tx/{}",
+                fill.token_amount, trade.token_name, trade.strategy_id, fill.tx_sig
+            ),
+            Err(e) => println!(
+                "Selling {} ({}) failed: {:#}",
+                trade.token_name, trade.strategy_id, e
+            ),
+        }
+    }
+    Ok(())
+}
+
+pub async fn deploy(name: &str, symbol: &str, image: &Path, buy_sol: f64) -> Result<()> {
+    let trader = open_trader().await?;
+    let mint = trader
+        .deploy_pump_token(name, symbol, image, buy_sol)
+        .await?;
+    println!("Deployed {}: https://pump.fun/coin/{}", symbol, mint);
+    Ok(())
+}
+
+pub async fn balance() -> Result<()> {
+    let owner = Pubkey::from_str(&SignerContext::current().await.pubkey())?;
+    let rpc_client = make_rpc_client();
+    println!("Wallet: {}", owner);
+    println!(
+        "SOL: {}",
+        format::lamports(rpc_client.get_balance(&owner).await?)
+    );
+
+    let trader = open_trader().await?;
+    for trade in trader.active_trades().load_all_trades().await? {
+        let holdings = get_balance(
+            &rpc_client,
+            &owner,
+            &Pubkey::from_str(&trade.token_address)?,
+        )
+        .await?;
+        println!(
+            "{} ({}): {} (tracked {})",
+            trade.token_name, trade.token_address, holdings, trade.remaining_holdings
+        );
+    }
+    Ok(())
+}
+
+pub async fn quote(mint: &str, sol: Option<f64>, sell: Option<u64>) -> Result<()> {
+    let mut quoter = Quoter::new(make_rpc_client());
+    if let Ok(quote_url) = env::var("JUPITER_QUOTE_URL") {
+        quoter = quoter.with_jupiter(quote_url);
+    }
+    let quote = match (sol, sell) {
+        (_, Some(tokens)) => quoter.get_quote(mint, Side::Sell, tokens).await?,
+        (Some(sol), None) => {
+            quoter
+                .get_quote(mint, Side::Buy, sol_to_lamports(sol))
+                .await?
+        }
+        (None, None) => return Err(anyhow!("Pass --sol or --sell")),
+    };
+    println!("{}", quote);
+    Ok(())
+}
+
+pub async fn reparse(reason: Option<&str>, store: bool) -> Result<()> {
+    let db_config = DbConfig::from_env()?;
+    let db = open_db().await?;
+    let unparsed = UnparsedMessages::new(db.collection("unparsed_messages"));
+    let messages = unparsed.load(reason).await?;
+    let kept = messages.len();
+    let parsed = unparsed::reparse(messages);
+    let storage = match store {
+        true => Some(open_storage(&db_config, &db).await?),
+        false => None,
+    };
+
+    for (message, trade) in &parsed {
+        println!(
+            "Message {} of chat {} ({}): {:?}",
+            message.message_id, message.chat_id, message.date, trade
+        );
+        let Some(storage) = &storage else {
+            continue;
+        };
+        match store_trade_db(
+            &**storage,
+            trade.clone(),
+            message.message_id,
+            message.text.clone(),
+            message.date,
+        )
+        .await
+        {
+            Ok(()) => {}
+            Err(e) if is_duplicate_trade(&e) => {}
+            Err(e) => return Err(e),
+        }
+        unparsed.remove(message).await?;
+    }
+    println!(
+        "{} of {} kept messages parse now{}",
+        parsed.len(),
+        kept,
+        match store {
+            true => ", stored",
+            false => ", --store to store them",
+        }
+    );
+    Ok(())
+}
+
+pub async fn housekeeping(dry_run: bool) -> Result<()> {
+    let trader = open_trader().await?;
+    let keep = trader
+        .active_trades()
+        .load_all_trades()
+        .await?
+        .into_iter()
+        .map(|trade| trade.token_address)
+        .collect();
+    let sweep =
+        sweep_token_accounts(&keep, TradingConfig::from_env()?.tip_lamports, dry_run).await?;
+    println!(
+        "{} {} token accounts, {} recovered",
+        if dry_run { "Would close" } else { "Closed" },
+        sweep.closed,
+        format::lamports(sweep.recovered_lamports)
+    );
+    for tx_sig in sweep.tx_sigs {
+        println!("https://solscan.io/tx/{}", tx_sig);
+    }
+    Ok(())
+}
+
+pub async fn trading(action: TradingAction) -> Result<()> {
+    let bot_state = BotStateStore::new(open_db().await?.collection("bot_state"));
+    let state = match action {
+        TradingAction::Pause { strategy, reason } => {
+            let reason = reason.unwrap_or_else(|| "paused from the CLI".to_string());
+            let (state, changed) = bot_state.pause(strategy.as_deref(), &reason).await?;
+            if !changed {
+                println!("Already paused, reason updated");
+            }
+            state
+        }
+        TradingAction::Resume { strategy } => {
+            let (state, changed) = bot_state.resume(strategy.as_deref()).await?;
+            if !changed {
+                println!("Was not paused");
+            }
+            state
+        }
+        TradingAction::Status => bot_state.load().await?,
+    };
+    println!("{}", state);
+    Ok(())
+}
+
+pub async fn capital(action: CapitalAction) -> Result<()> {
+    let capital = open_capital().await?;
+    match action {
+        CapitalAction::Lock { sol } => {
+            if !(sol >= 0.0) {
+                return Err(anyhow!("Locked amount must not be negative, got {}", sol));
+            }
+            capital.lock(sol_to_lamports(sol)).await?;
+            println!("Locked {} SOL", sol);
+        }
+        CapitalAction::Unlock => {
+            capital.lock(0).await?;
+            println!("Unlocked all capital");
+        }
+        CapitalAction::Status => {
+            let state = capital.load().await?;
+            println!("Locked: {}", format::lamports(state.locked_lamports));
+            match state.pending_withdrawal {
+                Some(w) => println!(
+                    "Pending withdrawal: {} to {}{}",
+                    format::lamports(w.lamports),
+                    w.destination,
+                    if w.confirmed { " (confirmed)" } else { "" }
+                ),
+                None => println!("No pending withdrawal"),
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn withdraw(action: WithdrawAction) -> Result<()> {
+    let capital = open_capital().await?;
+    match action {
+        WithdrawAction::Request { destination, sol } => {
+            let allowlist = CapitalConfig::from_env()?.withdraw_allowlist;
+            let withdrawal = capital
+                .request_withdrawal(
+                    &Pubkey::from_str(&destination)?,
+                    sol_to_lamports(sol),
+                    &allowlist,
+                )
+                .await?;
+            println!(
+                "Withdrawal of {} to {} requested, send it within {} minutes with:\n  copy-trade withdraw confirm {}",
+                format::lamports(withdrawal.lamports),
+                withdrawal.destination,
+                WITHDRAWAL_CONFIRM_SECS / 60,
+                withdrawal.code
+            );
+        }
+        WithdrawAction::Confirm { code } => {
+            let withdrawal = capital.confirm_withdrawal(&code).await?;
+            let destination = Pubkey::from_str(&withdrawal.destination)?;
+            let lamports = withdrawal.lamports;
+            let tip_lamports = TradingConfig::from_env()?.tip_lamports;
+            let tx_sig = transfer_sol(destination, lamports, tip_lamports).await?;
+            println!(
+                "Withdrew {} to {}: https://solscan.io/tx/{}",
+                format::lamports(lamports),
+                destination,
+                tx_sig
+            );
+        }
+        WithdrawAction::Cancel => {
+            if capital.cancel_withdrawal().await? {
+                println!("Pending withdrawal cancelled");
+            } else {
+                println!("No pending withdrawal");
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn privy_wallet(session_signers: &[String], wallet_id: Option<&str>) -> Result<()> {
+    let config = PrivyConfig::from_env()?
+        .ok_or_else(|| anyhow!("Set SIGNER_BACKEND=privy, PRIVY_APP_ID and PRIVY_APP_SECRET"))?;
+    let wallets = WalletManager::new(config);
+    if let Some(wallet_id) = wallet_id {
+        let wallet = wallets
+            .set_session_signers(wallet_id, session_signers)
+            .await?;
+        println!(
+            "Session signers of Privy wallet {}: {}",
+            wallet.id,
+            session_signers.join(", ")
+        );
+        return Ok(());
+    }
+    let wallet = wallets.create_wallet(session_signers).await?;
+    println!("Created Privy wallet {}", wallet.id);
+    println!("Address: {}", wallet.address);
+    if !session_signers.is_empty() {
+        println!("Session signers: {}", session_signers.join(", "));
+    }
+    println!(
+        "Set PRIVY_WALLET_ID={} and fund the address to trade",
+        wallet.id
+    );
+    Ok(())
+}
+
+pub async fn config(action: ConfigAction, devnet: bool) -> Result<()> {
+    let ConfigAction::Show { resolved } = action;
+    let config = CopyTraderConfig::from_env()?;
+    let jito = JitoConfig::from_env()?;
+    println!("{}", config);
+    if let Some(jito) = &jito {
+        println!("{}", jito);
+    }
+    if let Some(profiles) = ProfilesConfig::from_env()? {
+        println!("{}", profiles);
+    }
+    let privy = PrivyConfig::from_env()?;
+    if let Some(privy) = &privy {
+        println!("{}", privy);
+    }
+    if let Some(approval) = ApprovalConfig::from_env()? {
+        println!("{}", approval);
+    }
+    println!("{}", CapitalConfig::from_env()?);
+    if !resolved {
+        return Ok(());
+    }
+
+    println!("\nResolved:");
+    println!(
+        "  env_file: {}",
+        dotenv::dotenv().map_or("none".to_string(), |path| path.display().to_string())
+    );
+    println!("  network: {}", if devnet { "devnet" } else { "mainnet" });
+    // --devnet already swapped the endpoints in the environment
+    match RpcConfig::from_env()? {
+        Some(rpc) => println!(
+            "  rpc_urls: {}",
+            rpc.urls
+                .iter()
+                .map(|url| redact_url(url))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        None => println!(
+            "  rpc_url: {}",
+            env::var("SOLANA_RPC_URL").map_or("not set".to_string(), |url| redact_url(&url))
+        ),
+    }
+    println!(
+        "  ws_url: {}",
+        env::var("SOLANA_WS_URL").map_or("off".to_string(), |url| redact_url(&url))
+    );
+    println!(
+        "  sends: {}",
+        match &jito {
+            Some(jito) if !devnet && privy.is_none() => {
+                format!("Jito bundles to {}", jito.regions.join(", "))
+            }
+            _ => "RPC".to_string(),
+        }
+    );
+    let venues: Vec<String> = std::iter::once("pump.fun".to_string())
+        .chain(
+            PoolProgram::ALL
+                .iter()
+                .filter(|program| program.is_supported())
+                .map(|program| program.to_string()),
+        )
+        .collect();
+    println!("  venues: {}", venues.join(", "));
+    println!(
+        "  venue_selection: {}",
+        if config.venue_selection.is_some() {
+            "best quote"
+        } else {
+            "most liquid pool"
+        }
+    );
+    println!(
+        "  jupiter_fallback: {}",
+        config
+            .jupiter
+            .as_ref()
+            .map_or("off", |jupiter| jupiter.api_url.as_str())
+    );
+
+    let db = open_db().await?;
+    let strategies = StrategyManager::load(open_storage(&config.db, &db).await?).await?;
+    println!("  strategies:");
+    for strategy in strategies.all().iter() {
+        println!(
+            "    {}: {}{}",
+            strategy.strategy_id,
+            if is_strategy_traded(&strategy.strategy_id, &config.trading, &strategies) {
+                "traded"
+            } else {
+                "filtered out"
+            },
+            if strategy.is_placeholder {
+                " (placeholder)"
+            } else {
+                ""
+            }
+        );
+    }
+    if config.trading.strategy_filter_on {
+        for name in &config.trading.filter_strategies {
+            if strategies.find(name).is_none() {
+                println!(
+                    "    {}: traded, not in the strategies collection, closes sell everything",
+                    name
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn report(period: Period, days: Option<i64>, csv: Option<&str>) -> Result<()> {
+    let executions = ExecutionLog::new(open_db().await?.collection("executions"));
+    // Trades closing in the window may have opened before it
+    let since = days.map_or(DateTime::UNIX_EPOCH, |days| {
+        Utc::now() - chrono::Duration::days(days)
+    });
+    let trades: Vec<_> = closed_trades(&executions.since(DateTime::UNIX_EPOCH).await?)
+        .into_iter()
+        .filter(|trade| trade.closed_at >= since)
+        .collect();
+    let reports = aggregate(&trades, period);
+    if reports.is_empty() {
+        println!("No closed trades");
+        return Ok(());
+    }
+
+    println!(
+        "{:<10} {:<16} {:>6} {:>6} {:>14} {:>12} {:>8} {:<24} {:<24}",
+        "PERIOD", "STRATEGY", "TRADES", "HIT", "PNL", "FEES", "HOLD", "BEST", "WORST"
+    );
+    for report in &reports {
+        println!(
+            "{:<10} {:<16} {:>6} {:>6} {:>14} {:>12} {:>8} {:<24} {:<24}",
+            report.period,
+            report.strategy,
+            report.trades,
+            format!("{:.0}%", report.hit_rate_pct()),
+            format::signed_lamports(report.pnl_lamports),
+            format::lamports(report.fees_lamports),
+            format!("{}m", report.avg_hold_secs / 60),
+            format!(
+                "{} {}",
+                report.best.0,
+                format::signed_lamports(report.best.1)
+            ),
+            format!(
+                "{} {}",
+                report.worst.0,
+                format::signed_lamports(report.worst.1)
+            )
+        );
+    }
+    if let Some(path) = csv {
+        std::fs::write(path, to_csv(&reports))?;
+        println!("Wrote {}", path);
+    }
+    Ok(())
+}
@@ -1,49 +1,239 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use solana_sdk::pubkey::Pubkey;
 use std::env;
 use std::fmt;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+use std::str::FromStr;
 
-#[derive(Debug)]
+use crate::admin::auth::Scope;
+use crate::notify::webhook;
+use crate::solana::blockhash;
+use crate::solana::price::PriceSourceKind;
+use crate::solana::wallet_watcher::WatchedWallet;
+use crate::tg_copy::cooldown::{parse_cooldown_secs, ReentryPolicy, TRADE_COOLDOWN_SECS};
+use crate::trade::candles::{interval_name, parse_interval};
+use crate::trade::execution::Venue;
+use crate::trade::latency::{parse_stage_budgets, StageBudget};
+use crate::trade::maintenance::MaintenanceWindow;
+use crate::trade::risk::StreakAction;
+use crate::trade::sizing::{parse_size_bands, BandMarketCap, CopyRatio, SizeBand};
+
+/// Where trades, positions and strategies are stored
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DbBackend {
+    Mongo,
+    Sqlite,
+}
+
+impl FromStr for DbBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "mongo" | "mongodb" => Ok(DbBackend::Mongo),
+            "sqlite" => Ok(DbBackend::Sqlite),
+            other => Err(anyhow!(
+                "Unknown DB_BACKEND '{}', expected mongo or sqlite",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for DbBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbBackend::Mongo => write!(f, "mongo"),
+            DbBackend::Sqlite => write!(f, "sqlite"),
+        }
+    }
+}
+
+/// `url` with its credentials and query replaced, RPC providers put their
+/// API key in either
+pub fn redact_url(url: &str) -> String {
+    let (url, query) = match url.split_once('?') {
+        Some((url, _)) => (url, "?<redacted>"),
+        None => (url, ""),
+    };
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return format!("{}{}", url, query);
+    };
+    let host_start = rest.find('/').unwrap_or(rest.len());
+    match rest[..host_start].rfind('@') {
+        Some(at) => format!("{}://<redacted>{}{}", scheme, &rest[at..], query),
+        None => format!("{}://{}{}", scheme, rest, query),
+    }
+}
+
+/// Whether swaps are sent
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TradeMode {
+    Live,
+    /// Build and simulate every transaction, never send one
+    Simulate,
+}
+
+impl FromStr for TradeMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "live" => Ok(TradeMode::Live),
+            "simulate" => Ok(TradeMode::Simulate),
+            other => Err(anyhow!(
+                "Unknown TRADE_MODE '{}', expected live or simulate",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for TradeMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TradeMode::Live => write!(f, "live"),
+            TradeMode::Simulate => write!(f, "simulate"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct DbConfig {
     pub mongodb_uri: String,
     pub db_name: String,
+    pub backend: DbBackend,
+    /// SQLite database file of `DbBackend::Sqlite`
+    pub sqlite_path: String,
 }
 
 impl fmt::Display for DbConfig {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "\nDB Config:\n  mongodb_uri: {}\n  db_name: {}",
-            self.mongodb_uri, self.db_name
-        )
+            "\nDB Config:\n  mongodb_uri: {}\n  db_name: {}\n  backend: {}",
+            redact_url(&self.mongodb_uri),
+            self.db_name,
+            self.backend
+        )?;
+        if self.backend == DbBackend::Sqlite {
+            write!(f, "\n  sqlite_path: {}", self.sqlite_path)?;
+        }
+        Ok(())
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TelegramConfig {
     pub api_id: i32,
     pub api_hash: String,
+    /// Name, numeric ID or invite link of the chat to copy
     pub group_name: String,
+    /// Only copy this topic of a forum group
+    pub topic_id: Option<i32>,
     pub pool_frequency: u64,
+    pub login: TelegramLoginConfig,
+    /// Read the contract address from the photo of signals whose caption
+    /// has none, needs the `ocr` feature
+    pub ocr_images: bool,
+}
+
+/// How a session that isn't logged in gets logged in: prompted on the
+/// terminal, or headless with the code from a file or the API
+#[derive(Debug, Clone)]
+pub struct TelegramLoginConfig {
+    pub headless: bool,
+    /// Login code requested for this number at startup when headless
+    pub phone: Option<String>,
+    /// Polled for the login code when headless, removed once read
+    pub code_file: Option<PathBuf>,
+    /// Answers the 2FA password step of any login
+    pub password: Option<String>,
 }
 
 impl fmt::Display for TelegramConfig {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "\nTelegram Config:\n  group_name: {}\n  pool_frequency: {} s",
-            self.group_name, self.pool_frequency
+            "\nTelegram Config:\n  group_name: {}\n  topic_id: {}\n  pool_frequency: {} s\n  ocr_images: {}\n  login: {}\n  phone: {}\n  code_file: {}\n  password: {}",
+            self.group_name,
+            self.topic_id
+                .map_or_else(|| "all".to_string(), |id| id.to_string()),
+            self.pool_frequency,
+            self.ocr_images,
+            if self.login.headless { "headless" } else { "interactive" },
+            self.login.phone.as_deref().unwrap_or("none"),
+            self.login
+                .code_file
+                .as_ref()
+                .map_or_else(|| "none".to_string(), |path| path.display().to_string()),
+            if self.login.password.is_some() { "<redacted>" } else { "none" }
         )
     }
 }
 
 #[derive(Debug, Clone)]
+pub struct HistoryConfig {
+    /// Messages older than this many days are not downloaded
+    pub max_days: Option<i64>,
+    pub chunk_size: usize,
+    pub chunk_delay_ms: u64,
+}
+
+impl fmt::Display for HistoryConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\nHistory Config:\n  max_days: {}\n  chunk_size: {}\n  chunk_delay: {} ms",
+            self.max_days
+                .map_or_else(|| "unlimited".to_string(), |days| days.to_string()),
+            self.chunk_size,
+            self.chunk_delay_ms
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct TradingConfig {
     pub trade_on: bool,
+    pub trade_mode: TradeMode,
     pub position_size_sol: f64,
+    pub size_from_strategy: bool,
+    /// Position sizes by market cap, empty when off
+    pub size_bands: Vec<SizeBand>,
+    pub size_bands_market_cap: BandMarketCap,
+    /// Sizes proportional to the signal's stated buys, `None` when off
+    pub copy_ratio: Option<CopyRatio>,
     pub slippage_bps: u16,
     pub tip_lamports: u64,
+    /// Strategies traded with the filter on, those of the strategies
+    /// collection when empty
     pub filter_strategies: Vec<String>,
     pub strategy_filter_on: bool,
+    /// Seconds between reloads of the strategies collection, 0 for never
+    pub strategy_refresh_secs: u64,
+    pub max_cost_fraction: Option<f64>,
+    pub max_transfer_fee_bps: Option<u16>,
+    /// Skip buys once the market cap is above this multiple of the signal's
+    pub max_mc_multiple: Option<f64>,
+    /// SOL the open positions of one mint may cost together, over every
+    /// strategy
+    pub max_exposure_per_token_sol: Option<f64>,
+    pub gap_close_sell: bool,
+    /// Sell up to this much SOL worth of an untracked wallet balance on a
+    /// close signal without a position, `None` to skip such closes
+    pub untracked_close_max_sol: Option<f64>,
+    /// Open signals for a token bought this recently are skipped, unless
+    /// the strategy sets its own
+    pub trade_cooldown_secs: i64,
+    /// Unless the strategy sets its own
+    pub reentry_policy: ReentryPolicy,
+    /// Workers trading signals at the same time
+    pub max_concurrent_trades: usize,
+    /// Signals waiting for a worker before the listener stops reading
+    pub trade_queue_capacity: usize,
 }
 
 impl fmt::Display for TradingConfig {
@@ -52,68 +242,1843 @@ impl fmt::Display for TradingConfig {
             f,
             "\nTrading Config:\n  \
              trade_on: {}\n  \
+             trade_mode: {}\n  \
              position_size_sol: {}\n  \
+             size_from_strategy: {}\n  \
+             size_bands: {}\n  \
+             size_bands_market_cap: {}\n  \
+             copy_ratio: {}\n  \
              slippage_bps: {}\n  \
              tip_lamports: {}\n  \
              strategy_filter_on: {}\n  \
-             filter_strategies: {}",
+             filter_strategies: {}\n  \
+             strategy_refresh_secs: {}\n  \
+             max_cost_fraction: {}\n  \
+             max_transfer_fee_bps: {}\n  \
+             max_mc_multiple: {}\n  \
+             max_exposure_per_token_sol: {}\n  \
+             gap_close_sell: {}\n  \
+             untracked_close_max_sol: {}\n  \
+             trade_cooldown_secs: {} s\n  \
+             reentry_policy: {}\n  \
+             max_concurrent_trades: {}\n  \
+             trade_queue_capacity: {}",
             self.trade_on,
+            self.trade_mode,
             self.position_size_sol,
+            self.size_from_strategy,
+            if self.size_bands.is_empty() {
+                "off".to_string()
+            } else {
+                self.size_bands
+                    .iter()
+                    .map(SizeBand::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            },
+            self.size_bands_market_cap,
+            self.copy_ratio
+                .as_ref()
+                .map_or("off".to_string(), CopyRatio::to_string),
             self.slippage_bps,
             self.tip_lamports,
             self.strategy_filter_on,
-            self.filter_strategies.join(", ")
+            if self.filter_strategies.is_empty() {
+                "from the strategies collection".to_string()
+            } else {
+                self.filter_strategies.join(", ")
+            },
+            self.strategy_refresh_secs,
+            self.max_cost_fraction
+                .map_or("off".to_string(), |f| f.to_string()),
+            self.max_transfer_fee_bps
+                .map_or("off".to_string(), |b| b.to_string()),
+            self.max_mc_multiple
+                .map_or("off".to_string(), |m| m.to_string()),
+            self.max_exposure_per_token_sol
+                .map_or("off".to_string(), |s| s.to_string()),
+            self.gap_close_sell,
+            self.untracked_close_max_sol
+                .map_or("off".to_string(), |s| s.to_string()),
+            self.trade_cooldown_secs,
+            self.reentry_policy,
+            self.max_concurrent_trades,
+            self.trade_queue_capacity
         )
     }
 }
 
-impl DbConfig {
-    pub fn from_env() -> Result<Self> {
-        Ok(Self {
-            mongodb_uri: env::var("MONGODB_URI").expect("MONGODB_URI not set."),
-            db_name: env::var("DB_NAME").expect("DB_NAME not set."),
-        })
+#[derive(Debug, Clone)]
+pub struct SellRetryConfig {
+    pub slippage_bps: u16,
+    pub max_slippage_bps: u16,
+    pub attempts: u32,
+    pub force_exit: bool,
+}
+
+impl fmt::Display for SellRetryConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\nSell Retry Config:\n  \
+             slippage_bps: {}\n  \
+             max_slippage_bps: {}\n  \
+             attempts: {}\n  \
+             force_exit: {}",
+            self.slippage_bps, self.max_slippage_bps, self.attempts, self.force_exit
+        )
     }
 }
 
-impl TelegramConfig {
-    pub fn from_env() -> Result<Self> {
-        Ok(Self {
-            api_id: env::var("TG_ID").expect("TG_ID not set.").parse()?,
-            api_hash: env::var("TG_HASH").expect("TG_HASH not set."),
-            group_name: env::var("GROUP_NAME").expect("GROUP_NAME not set."),
-            pool_frequency: env::var("TG_POOL_FREQUENCY")
-                .expect("TG_POOL_FREQUENCY not set.")
-                .parse()?,
-        })
+/// Compute unit limit and price of the swap transactions. The limit is
+/// what the swap's simulation consumed plus a margin, the venue's preset
+/// when the simulation doesn't tell.
+#[derive(Debug, Clone)]
+pub struct ComputeBudgetConfig {
+    /// Priority fee per compute unit, none when 0
+    pub unit_price_micro_lamports: u64,
+    pub unit_limit_margin_pct: u32,
+    pub pump_buy_units: u32,
+    pub pump_sell_units: u32,
+    pub raydium_units: u32,
+    pub orca_units: u32,
+    pub meteora_units: u32,
+    pub jupiter_units: u32,
+}
+
+impl ComputeBudgetConfig {
+    pub fn preset(&self, venue: Venue, buy: bool) -> u32 {
+        match venue {
+            Venue::PumpFun if buy => self.pump_buy_units,
+            Venue::PumpFun => self.pump_sell_units,
+            Venue::Raydium => self.raydium_units,
+            Venue::Orca => self.orca_units,
+            Venue::Meteora => self.meteora_units,
+            Venue::Jupiter => self.jupiter_units,
+        }
     }
 }
 
-impl TradingConfig {
-    pub fn from_env() -> Result<Self> {
-        Ok(Self {
-            trade_on: env::var("TRADE_ON")
-                .expect("TRADE_ON not set.")
-                .to_lowercase()
-                == "true",
-            position_size_sol: env::var("POSITION_SIZE_SOL")
-                .expect("POSITION_SIZE_SOL not set.")
-                .parse()?,
-            slippage_bps: env::var("SLIPPAGE_BPS")
-                .expect("SLIPPAGE_BPS not set.")
-                .parse()?,
-            tip_lamports: env::var("TIP_LAMPORTS")
-                .expect("TIP_LAMPORTS not set.")
-                .parse()?,
-            filter_strategies: env::var("FILTER_STRATEGIES")
-                .expect("FILTER_STRATEGIES not set.")
-                .split(',')
-                .map(|s| s.trim().to_string())
-                .collect(),
-            strategy_filter_on: env::var("STRATEGY_FILTER_ON")
-                .expect("STRATEGY_FILTER_ON not set.")
-                .to_lowercase()
-                == "true",
-        })
+impl fmt::Display for ComputeBudgetConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\nCompute Budget Config:\n  \
+             unit_price_micro_lamports: {}\n  \
+             unit_limit_margin_pct: {}%\n  \
+             pump_buy_units: {}\n  \
+             pump_sell_units: {}\n  \
+             raydium_units: {}\n  \
+             orca_units: {}\n  \
+             meteora_units: {}\n  \
+             jupiter_units: {}",
+            self.unit_price_micro_lamports,
+            self.unit_limit_margin_pct,
+            self.pump_buy_units,
+            self.pump_sell_units,
+            self.raydium_units,
+            self.orca_units,
+            self.meteora_units,
+            self.jupiter_units
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LiquidityMonitorConfig {
+    pub max_pool_share_pct: f64,
+    pub exit_tranche_pct: Option<f64>,
+    pub check_interval_secs: u64,
+}
+
+impl fmt::Display for LiquidityMonitorConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\nLiquidity Monitor Config:\n  \
+             max_pool_share_pct: {}\n  \
+             exit_tranche_pct: {}\n  \
+             check_interval_secs: {} s",
+            self.max_pool_share_pct,
+            self.exit_tranche_pct
+                .map_or("off".to_string(), |p| p.to_string()),
+            self.check_interval_secs
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HousekeepingConfig {
+    pub interval_secs: u64,
+}
+
+impl fmt::Display for HousekeepingConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\nHousekeeping Config:\n  interval_secs: {} s",
+            self.interval_secs
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VenueSelectionConfig {
+    /// Jupiter quote endpoint the pools are compared against
+    pub jupiter_quote_url: Option<String>,
+}
+
+/// Jupiter swap API taking the swaps of tokens without a supported pool
+#[derive(Debug, Clone)]
+pub struct JupiterSwapConfig {
+    pub api_url: String,
+}
+
+impl fmt::Display for JupiterSwapConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\nJupiter Swap Config:\n  api_url: {}", self.api_url)
+    }
+}
+
+impl fmt::Display for VenueSelectionConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\nVenue Selection Config:\n  jupiter_quote_url: {}",
+            self.jupiter_quote_url.as_deref().unwrap_or("off")
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UsdBackfillConfig {
+    /// Binance compatible klines endpoint
+    pub price_url: String,
+    pub requests_per_min: u32,
+    pub batch_size: i64,
+    pub interval_secs: u64,
+}
+
+impl fmt::Display for UsdBackfillConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\nUSD Backfill Config:\n  \
+             price_url: {}\n  \
+             requests_per_min: {}\n  \
+             batch_size: {}\n  \
+             interval_secs: {} s",
+            self.price_url, self.requests_per_min, self.batch_size, self.interval_secs
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CandleConfig {
+    /// Candle intervals recorded, shortest first
+    pub intervals_secs: Vec<u64>,
+}
+
+impl fmt::Display for CandleConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\nCandle Config:\n  \
+             intervals: {}",
+            self.intervals_secs
+                .iter()
+                .map(|secs| interval_name(*secs))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+}
+
+/// Realized slippage of every fill against its quote, and the buy
+/// slippage adapted to it per token
+#[derive(Debug, Clone)]
+pub struct SlippageConfig {
+    pub adaptive: bool,
+    pub min_bps: u16,
+    pub max_bps: u16,
+    /// Added to the worst recent slippage of the token
+    pub margin_bps: u16,
+}
+
+impl fmt::Display for SlippageConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\nSlippage Config:\n  \
+             adaptive: {}\n  \
+             min_bps: {}\n  \
+             max_bps: {}\n  \
+             margin_bps: {}",
+            self.adaptive, self.min_bps, self.max_bps, self.margin_bps
+        )
+    }
+}
+
+/// Moves SOL above the threshold to a cold wallet after profitable closes
+#[derive(Debug, Clone)]
+pub struct SweepConfig {
+    /// Balance the hot wallet is brought back to
+    pub threshold_sol: f64,
+    pub cold_wallet: Pubkey,
+    /// Smaller excesses wait for the next profitable close
+    pub min_sol: f64,
+}
+
+impl fmt::Display for SweepConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\nSweep Config:\n  \
+             threshold_sol: {}\n  \
+             cold_wallet: {}\n  \
+             min_sol: {}",
+            self.threshold_sol, self.cold_wallet, self.min_sol
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LatencySloConfig {
+    /// Signal-to-fill latency a buy should stay within
+    pub target_ms: i64,
+    /// Share of buys that must stay within `target_ms`
+    pub objective: f64,
+    pub stage_budgets: Vec<StageBudget>,
+    /// UTC hour the daily digest is sent
+    pub digest_hour: u32,
+}
+
+impl fmt::Display for LatencySloConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\nLatency SLO Config:\n  \
+             target_ms: {} ms\n  \
+             objective: {}\n  \
+             stage_budgets: {}\n  \
+             digest_hour: {:02}:00 UTC",
+            self.target_ms,
+            self.objective,
+            if self.stage_budgets.is_empty() {
+                "none".to_string()
+            } else {
+                self.stage_budgets
+                    .iter()
+                    .map(StageBudget::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            },
+            self.digest_hour
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NotifierConfig {
+    pub bot_token: String,
+    pub chat_id: String,
+    pub language: String,
+    pub template_dir: Option<String>,
+}
+
+impl fmt::Display for NotifierConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\nNotifier Config:\n  bot_token: <redacted>\n  chat_id: {}\n  language: {}\n  template_dir: {}",
+            self.chat_id,
+            self.language,
+            self.template_dir.as_deref().unwrap_or("built-in")
+        )
+    }
+}
+
+/// Discord channel receiving trade events as embeds
+#[derive(Debug, Clone)]
+pub struct DiscordConfig {
+    pub webhook_url: String,
+}
+
+impl fmt::Display for DiscordConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\nDiscord Config:\n  webhook_url: <redacted>")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EventWebhookConfig {
+    pub urls: Vec<String>,
+    pub secret: Option<String>,
+    pub events: Vec<String>,
+    pub max_retries: u32,
+}
+
+impl fmt::Display for EventWebhookConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\nEvent Webhook Config:\n  urls: {}\n  secret: {}\n  events: {}\n  max_retries: {}",
+            self.urls.len(),
+            if self.secret.is_some() {
+                "<redacted>"
+            } else {
+                "none"
+            },
+            self.events.join(", "),
+            self.max_retries
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PriceFeedConfig {
+    /// In order of preference
+    pub sources: Vec<PriceSourceKind>,
+    pub birdeye_api_key: Option<String>,
+    pub birdeye_max_rps: f64,
+    pub pyth_url: String,
+    pub cache_ttl_ms: u64,
+}
+
+impl Default for PriceFeedConfig {
+    fn default() -> Self {
+        Self {
+            sources: vec![
+                PriceSourceKind::Pyth,
+                PriceSourceKind::Pool,
+                PriceSourceKind::DexScreener,
+            ],
+            birdeye_api_key: None,
+            birdeye_max_rps: 1.0,
+            pyth_url: "https://hermes.pyth.network".to_string(),
+            cache_ttl_ms: 2_000,
+        }
+    }
+}
+
+impl fmt::Display for PriceFeedConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\nPrice Feed Config:\n  sources: {}\n  birdeye_api_key: {}\n  birdeye_max_rps: {}\n  pyth_url: {}\n  cache_ttl_ms: {}",
+            self.sources
+                .iter()
+                .map(PriceSourceKind::to_string)
+                .collect::<Vec<_>>()
+                .join(", "),
+            if self.birdeye_api_key.is_some() { "<redacted>" } else { "none" },
+            self.birdeye_max_rps,
+            self.pyth_url,
+            self.cache_ttl_ms
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PriceStreamConfig {
+    pub ws_url: String,
+}
+
+impl fmt::Display for PriceStreamConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\nPrice Stream Config:\n  ws_url: {}", self.ws_url)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WalletWatchConfig {
+    pub ws_url: String,
+    pub wallets: Vec<WatchedWallet>,
+}
+
+impl fmt::Display for WalletWatchConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\nWallet Watch Config:\n  ws_url: {}\n  wallets: {}",
+            self.ws_url,
+            self.wallets
+                .iter()
+                .map(|w| format!("{}={}", w.strategy, w.address))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UpdateArchiveConfig {
+    pub path: String,
+}
+
+impl fmt::Display for UpdateArchiveConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\nUpdate Archive Config:\n  path: {}", self.path)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DecisionJournalConfig {
+    pub path: String,
+}
+
+impl fmt::Display for DecisionJournalConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\nDecision Journal Config:\n  path: {}", self.path)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WalletWebhookConfig {
+    pub auth_header: String,
+    pub balance_max_age_secs: u64,
+}
+
+impl fmt::Display for WalletWebhookConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\nWallet Webhook Config:\n  auth_header: <redacted>\n  balance_max_age_secs: {}",
+            self.balance_max_age_secs
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SignalWebhookConfig {
+    pub api_key: String,
+}
+
+impl fmt::Display for SignalWebhookConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\nSignal Webhook Config:\n  api_key: <redacted>")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct JitoConfig {
+    pub regions: Vec<String>,
+    pub tip_lamports: u64,
+}
+
+impl fmt::Display for JitoConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\nJito Config:\n  regions: {}\n  tip_lamports: {}",
+            self.regions.join(", "),
+            self.tip_lamports
+        )
+    }
+}
+
+/// How old a blockhash may get, in slots. A blockhash is valid for 150
+/// blocks.
+#[derive(Debug, Clone)]
+pub struct BlockhashConfig {
+    /// Signers fetch a new blockhash instead of signing with an older one
+    pub max_age_slots: u64,
+    /// The cached blockhash is replaced once it is this old
+    pub refresh_slots: u64,
+}
+
+impl fmt::Display for BlockhashConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\nBlockhash Config:\n  max_age_slots: {}\n  refresh_slots: {}",
+            self.max_age_slots, self.refresh_slots
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NonceConfig {
+    /// Durable nonce accounts of the wallet, used in turn
+    pub accounts: usize,
+}
+
+impl fmt::Display for NonceConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\nNonce Config:\n  accounts: {}", self.accounts)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RpcConfig {
+    pub urls: Vec<String>,
+}
+
+impl fmt::Display for RpcConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let urls: Vec<String> = self.urls.iter().map(|url| redact_url(url)).collect();
+        write!(f, "\nRPC Config:\n  urls: {}", urls.join(", "))
+    }
+}
+
+/// Copy-trade profiles run side by side by one process
+#[derive(Debug, Clone)]
+pub struct ProfilesConfig {
+    pub ids: Vec<String>,
+}
+
+impl fmt::Display for ProfilesConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\nProfiles Config:\n  profiles: {}", self.ids.join(", "))
+    }
+}
+
+/// Privy app of `SIGNER_BACKEND=privy`, its server wallet signs instead of
+/// `SOLANA_PRIVATE_KEY`
+#[derive(Debug, Clone)]
+pub struct PrivyConfig {
+    pub app_id: String,
+    pub app_secret: String,
+    /// Wallet that signs, only `privy-wallet` runs without it
+    pub wallet_id: Option<String>,
+    pub api_url: String,
+}
+
+impl fmt::Display for PrivyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\nPrivy Config:\n  app_id: {}\n  app_secret: <redacted>\n  wallet_id: {}\n  api_url: {}",
+            self.app_id,
+            self.wallet_id.as_deref().unwrap_or("none"),
+            self.api_url
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DevnetConfig {
+    pub rpc_url: String,
+    pub ws_url: String,
+    /// Airdrop when the wallet holds less than this
+    pub min_balance_sol: f64,
+    pub airdrop_sol: f64,
+    /// Pool the mocked DexScreener reports for every token
+    pub pool: Option<String>,
+}
+
+impl fmt::Display for DevnetConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\nDevnet Config:\n  \
+             rpc_url: {}\n  \
+             ws_url: {}\n  \
+             min_balance_sol: {} SOL\n  \
+             airdrop_sol: {} SOL\n  \
+             pool: {}",
+            self.rpc_url,
+            self.ws_url,
+            self.min_balance_sol,
+            self.airdrop_sol,
+            self.pool.as_deref().unwrap_or("none")
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ApprovalConfig {
+    pub redirect_url: String,
+    pub callback_addr: String,
+    pub app_url: String,
+    pub threshold_sol: Option<f64>,
+    pub timeout_secs: u64,
+}
+
+impl fmt::Display for ApprovalConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\nApproval Config:\n  \
+             redirect_url: {}\n  \
+             callback_addr: {}\n  \
+             threshold_sol: {}\n  \
+             timeout_secs: {} s",
+            self.redirect_url,
+            self.callback_addr,
+            self.threshold_sol
+                .map_or("every transaction".to_string(), |t| t.to_string()),
+            self.timeout_secs
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RiskConfig {
+    pub max_daily_loss_sol: f64,
+}
+
+impl fmt::Display for RiskConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\nRisk Config:\n  max_daily_loss_sol: {}",
+            self.max_daily_loss_sol
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LossStreakConfig {
+    pub max_losses: u32,
+    pub action: StreakAction,
+    pub cooldown_secs: i64,
+}
+
+impl fmt::Display for LossStreakConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\nLoss Streak Config:\n  max_losses: {}\n  action: {}\n  cooldown_secs: {}",
+            self.max_losses, self.action, self.cooldown_secs
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BalanceGuardConfig {
+    /// SOL kept in the wallet for fees and rent, buys never spend it
+    pub reserve_sol: f64,
+    /// Size buys at this percentage of the free balance instead of
+    /// `POSITION_SIZE_SOL`
+    pub size_pct: Option<f64>,
+}
+
+impl fmt::Display for BalanceGuardConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\nBalance Guard Config:\n  reserve_sol: {}\n  size_pct: {:?}",
+            self.reserve_sol, self.size_pct
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TokenFilterConfig {
+    pub blacklist_file: Option<String>,
+    pub whitelist_file: Option<String>,
+}
+
+impl fmt::Display for TokenFilterConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\nToken Filter Config:\n  blacklist_file: {}\n  whitelist_file: {}",
+            self.blacklist_file.as_deref().unwrap_or("none"),
+            self.whitelist_file.as_deref().unwrap_or("none")
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CapitalConfig {
+    pub withdraw_allowlist: Vec<Pubkey>,
+}
+
+impl fmt::Display for CapitalConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\nCapital Config:\n  withdraw_allowlist: {:?}",
+            self.withdraw_allowlist
+                .iter()
+                .map(Pubkey::to_string)
+                .collect::<Vec<_>>()
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MaintenanceConfig {
+    pub windows: Vec<MaintenanceWindow>,
+}
+
+impl fmt::Display for MaintenanceConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\nMaintenance Config:")?;
+        for window in &self.windows {
+            write!(f, "\n  {}", window)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ApiConfig {
+    pub bind_addr: String,
+}
+
+impl fmt::Display for ApiConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\nAPI Config:\n  bind_addr: {}", self.bind_addr)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GrpcConfig {
+    pub bind_addr: String,
+}
+
+impl fmt::Display for GrpcConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\ngRPC Config:\n  bind_addr: {}", self.bind_addr)
+    }
+}
+
+/// Chat the operators send commands to, the bot answers there
+#[derive(Debug, Clone)]
+pub struct CommandChatConfig {
+    /// Name, numeric ID, username or invite link, like `GROUP_NAME`
+    pub chat: String,
+}
+
+impl fmt::Display for CommandChatConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\nCommand Chat Config:\n  chat: {}", self.chat)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ApiTokenConfig {
+    pub name: String,
+    pub token: String,
+    pub scope: Scope,
+}
+
+#[derive(Debug, Clone)]
+pub struct AdminConfig {
+    pub telegram_users: Vec<(i64, Scope)>,
+    pub api_tokens: Vec<ApiTokenConfig>,
+    pub destructive_max_per_hour: u32,
+}
+
+impl fmt::Display for AdminConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\nAdmin Config:\n  \
+             telegram_users: {}\n  \
+             api_tokens: {}\n  \
+             destructive_max_per_hour: {}",
+            self.telegram_users
+                .iter()
+                .map(|(id, scope)| format!("{}:{}", id, scope))
+                .collect::<Vec<_>>()
+                .join(", "),
+            self.api_tokens
+                .iter()
+                .map(|t| format!("{}:<redacted>:{}", t.name, t.scope))
+                .collect::<Vec<_>>()
+                .join(", "),
+            self.destructive_max_per_hour
+        )
+    }
+}
+
+impl DbConfig {
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            mongodb_uri: env::var("MONGODB_URI").expect("MONGODB_URI not set."),
+            db_name: env::var("DB_NAME").expect("DB_NAME not set."),
+            backend: env::var("DB_BACKEND").map_or(Ok(DbBackend::Mongo), |v| v.parse())?,
+            sqlite_path: env::var("SQLITE_PATH").unwrap_or_else(|_| "copy_trade.db".to_string()),
+        })
+    }
+
+    /// The same backend on the database `db_name`, for SQLite a file of
+    /// that name next to `SQLITE_PATH`
+    pub fn with_db_name(&self, db_name: &str) -> Self {
+        let sqlite_path = std::path::Path::new(&self.sqlite_path)
+            .with_file_name(format!("{}.db", db_name))
+            .to_string_lossy()
+            .into_owned();
+        Self {
+            db_name: db_name.to_string(),
+            sqlite_path,
+            ..self.clone()
+        }
+    }
+}
+
+impl TelegramConfig {
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            api_id: env::var("TG_ID").expect("TG_ID not set.").parse()?,
+            api_hash: env::var("TG_HASH").expect("TG_HASH not set."),
+            group_name: env::var("GROUP_NAME").expect("GROUP_NAME not set."),
+            topic_id: env::var("GROUP_TOPIC_ID")
+                .ok()
+                .map(|v| v.parse())
+                .transpose()?,
+            pool_frequency: env::var("TG_POOL_FREQUENCY")
+                .expect("TG_POOL_FREQUENCY not set.")
+                .parse()?,
+            login: TelegramLoginConfig::from_env()?,
+            ocr_images: env::var("OCR_IMAGES").map_or(Ok(false), |v| v.parse())?,
+        })
+    }
+}
+
+impl TelegramLoginConfig {
+    /// Headless with `TG_LOGIN_MODE=headless`, or by default when stdin is
+    /// not a terminal (systemd, Docker)
+    pub fn from_env() -> Result<Self> {
+        let headless = match env::var("TG_LOGIN_MODE") {
+            Ok(mode) => match mode.to_lowercase().as_str() {
+                "headless" => true,
+                "interactive" => false,
+                other => return Err(anyhow!("Unknown TG_LOGIN_MODE: {}", other)),
+            },
+            Err(_) => !io::stdin().is_terminal(),
+        };
+        let password = match env::var("TG_PASSWORD_FILE") {
+            Ok(path) => Some(
+                fs::read_to_string(&path)
+                    .map_err(|e| anyhow!("Failed to read TG_PASSWORD_FILE {}: {}", path, e))?
+                    .trim()
+                    .to_string(),
+            ),
+            Err(_) => env::var("TG_PASSWORD").ok(),
+        };
+        Ok(Self {
+            headless,
+            phone: env::var("TG_PHONE").ok(),
+            code_file: env::var("TG_LOGIN_CODE_FILE").ok().map(PathBuf::from),
+            password: password.filter(|password| !password.is_empty()),
+        })
+    }
+}
+
+impl HistoryConfig {
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            max_days: env::var("HISTORY_MAX_DAYS")
+                .ok()
+                .map(|v| v.parse())
+                .transpose()?,
+            chunk_size: env::var("HISTORY_CHUNK_SIZE").map_or(Ok(100), |v| v.parse())?,
+            chunk_delay_ms: env::var("HISTORY_CHUNK_DELAY_MS").map_or(Ok(1000), |v| v.parse())?,
+        })
+    }
+}
+
+impl TradingConfig {
+    pub fn from_env() -> Result<Self> {
+        Self::from_vars(|name| env::var(name).ok())
+    }
+
+    /// The settings out of the variables `var` looks up, the environment
+    /// or a config file read again
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        Ok(Self {
+            trade_on: var("TRADE_ON")
+                .ok_or_else(|| anyhow!("TRADE_ON not set"))?
+                .to_lowercase()
+                == "true",
+            trade_mode: var("TRADE_MODE").map_or(Ok(TradeMode::Live), |v| v.parse())?,
+            position_size_sol: var("POSITION_SIZE_SOL")
+                .ok_or_else(|| anyhow!("POSITION_SIZE_SOL not set"))?
+                .parse()?,
+            size_from_strategy: var("SIZE_FROM_STRATEGY")
+                .map_or(false, |v| v.to_lowercase() == "true"),
+            size_bands: var("SIZE_BANDS").map_or(Ok(Vec::new()), |v| parse_size_bands(&v))?,
+            size_bands_market_cap: var("SIZE_BANDS_MARKET_CAP")
+                .map_or(Ok(BandMarketCap::Signal), |v| v.parse())?,
+            copy_ratio: match var("COPY_RATIO") {
+                Some(ratio) => Some(CopyRatio::new(
+                    ratio.parse()?,
+                    var("COPY_RATIO_MIN_SOL").map_or(Ok(0.0), |v| v.parse())?,
+                    var("COPY_RATIO_MAX_SOL").map(|v| v.parse()).transpose()?,
+                )?),
+                None => None,
+            },
+            slippage_bps: var("SLIPPAGE_BPS")
+                .ok_or_else(|| anyhow!("SLIPPAGE_BPS not set"))?
+                .parse()?,
+            tip_lamports: var("TIP_LAMPORTS")
+                .ok_or_else(|| anyhow!("TIP_LAMPORTS not set"))?
+                .parse()?,
+            filter_strategies: var("FILTER_STRATEGIES").map_or(Vec::new(), |v| {
+                v.split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect()
+            }),
+            strategy_filter_on: var("STRATEGY_FILTER_ON")
+                .ok_or_else(|| anyhow!("STRATEGY_FILTER_ON not set"))?
+                .to_lowercase()
+                == "true",
+            strategy_refresh_secs: var("STRATEGY_REFRESH_SECS").map_or(Ok(300), |v| v.parse())?,
+            max_cost_fraction: var("MAX_COST_FRACTION").map(|v| v.parse()).transpose()?,
+            max_transfer_fee_bps: var("MAX_TRANSFER_FEE_BPS").map(|v| v.parse()).transpose()?,
+            max_mc_multiple: var("MAX_MC_MULTIPLE").map(|v| v.parse()).transpose()?,
+            max_exposure_per_token_sol: var("MAX_EXPOSURE_PER_TOKEN_SOL")
+                .map(|v| v.parse())
+                .transpose()?,
+            gap_close_sell: var("GAP_CLOSE_SELL").map_or(true, |v| v.to_lowercase() == "true"),
+            untracked_close_max_sol: var("UNTRACKED_CLOSE_MAX_SOL")
+                .map(|v| v.parse())
+                .transpose()?,
+            trade_cooldown_secs: var("TRADE_COOLDOWN_SECS")
+                .map_or(Ok(TRADE_COOLDOWN_SECS), |v| parse_cooldown_secs(&v))?,
+            reentry_policy: var("REENTRY_POLICY")
+                .map_or(Ok(ReentryPolicy::Unlimited), |v| v.parse())?,
+            max_concurrent_trades: var("MAX_CONCURRENT_TRADES").map_or(Ok(4), |v| v.parse())?,
+            trade_queue_capacity: var("TRADE_QUEUE_CAPACITY").map_or(Ok(64), |v| v.parse())?,
+        })
+    }
+}
+
+impl SellRetryConfig {
+    /// Sells start at `SELL_SLIPPAGE_BPS` (default `SLIPPAGE_BPS`) and are
+    /// retried `SELL_RETRY_ATTEMPTS - 1` times with doubled slippage up to
+    /// `SELL_MAX_SLIPPAGE_BPS`, then once more without any minimum output
+    /// unless `SELL_FORCE_EXIT=false`.
+    pub fn from_env() -> Result<Self> {
+        let slippage_bps = match env::var("SELL_SLIPPAGE_BPS").or_else(|_| env::var("SLIPPAGE_BPS"))
+        {
+            Ok(v) => v.parse()?,
+            Err(_) => 500,
+        };
+        let attempts = env::var("SELL_RETRY_ATTEMPTS").map_or(Ok(3), |v| v.parse())?;
+        if attempts == 0 {
+            return Err(anyhow!("SELL_RETRY_ATTEMPTS must be at least 1"));
+        }
+        Ok(Self {
+            slippage_bps,
+            max_slippage_bps: env::var("SELL_MAX_SLIPPAGE_BPS").map_or(Ok(5_000), |v| v.parse())?,
+            attempts,
+            force_exit: env::var("SELL_FORCE_EXIT").map_or(true, |v| v.to_lowercase() == "true"),
+        })
+    }
+}
+
+impl ComputeBudgetConfig {
+    /// The presets default to what simulations of each venue's swap,
+    /// account creation included, consumed with some headroom
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            unit_price_micro_lamports: env::var("CU_PRICE_MICRO_LAMPORTS")
+                .map_or(Ok(0), |v| v.parse())?,
+            unit_limit_margin_pct: env::var("CU_LIMIT_MARGIN_PCT").map_or(Ok(20), |v| v.parse())?,
+            pump_buy_units: env::var("CU_LIMIT_PUMP_BUY").map_or(Ok(120_000), |v| v.parse())?,
+            pump_sell_units: env::var("CU_LIMIT_PUMP_SELL").map_or(Ok(80_000), |v| v.parse())?,
+            raydium_units: env::var("CU_LIMIT_RAYDIUM").map_or(Ok(150_000), |v| v.parse())?,
+            orca_units: env::var("CU_LIMIT_ORCA").map_or(Ok(200_000), |v| v.parse())?,
+            meteora_units: env::var("CU_LIMIT_METEORA").map_or(Ok(250_000), |v| v.parse())?,
+            jupiter_units: env::var("CU_LIMIT_JUPITER").map_or(Ok(400_000), |v| v.parse())?,
+        })
+    }
+}
+
+impl LiquidityMonitorConfig {
+    /// Returns `None` when `LIQUIDITY_MAX_POOL_SHARE_PCT` is not set, which
+    /// disables the monitor.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(max_pool_share_pct) = env::var("LIQUIDITY_MAX_POOL_SHARE_PCT") else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            max_pool_share_pct: max_pool_share_pct.parse()?,
+            exit_tranche_pct: env::var("LIQUIDITY_EXIT_TRANCHE_PCT")
+                .ok()
+                .map(|v| v.parse())
+                .transpose()?,
+            check_interval_secs: env::var("LIQUIDITY_CHECK_INTERVAL_SECS")
+                .map_or(Ok(60), |v| v.parse())?,
+        }))
+    }
+}
+
+impl HousekeepingConfig {
+    /// Returns `None` when `HOUSEKEEPING_INTERVAL_SECS` is not set, token
+    /// accounts are then only closed by the `housekeeping` command
+    pub fn from_env() -> Result<Option<Self>> {
+        Ok(env::var("HOUSEKEEPING_INTERVAL_SECS")
+            .ok()
+            .map(|v| v.parse())
+            .transpose()?
+            .map(|interval_secs| Self { interval_secs }))
+    }
+}
+
+impl VenueSelectionConfig {
+    /// Returns `None` unless `BEST_VENUE=true`, swaps then go through the
+    /// most liquid supported pool
+    pub fn from_env() -> Result<Option<Self>> {
+        if !env::var("BEST_VENUE").map_or(false, |v| v.to_lowercase() == "true") {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            jupiter_quote_url: env::var("JUPITER_QUOTE_URL").ok(),
+        }))
+    }
+}
+
+impl JupiterSwapConfig {
+    /// Returns `None` unless `JUPITER_FALLBACK=true`, tokens without a
+    /// supported pool can't be traded then
+    pub fn from_env() -> Result<Option<Self>> {
+        if !env::var("JUPITER_FALLBACK").map_or(false, |v| v.to_lowercase() == "true") {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            api_url: env::var("JUPITER_API_URL")
+                .unwrap_or_else(|_| "https://lite-api.jup.ag/swap/v1".to_string()),
+        }))
+    }
+}
+
+impl UsdBackfillConfig {
+    /// Returns `None` unless `USD_BACKFILL=true`
+    pub fn from_env() -> Result<Option<Self>> {
+        if !env::var("USD_BACKFILL").map_or(false, |v| v.to_lowercase() == "true") {
+            return Ok(None);
+        }
+        let requests_per_min =
+            env::var("USD_BACKFILL_REQUESTS_PER_MIN").map_or(Ok(30), |v| v.parse())?;
+        if requests_per_min == 0 {
+            return Err(anyhow!("USD_BACKFILL_REQUESTS_PER_MIN must be at least 1"));
+        }
+        Ok(Some(Self {
+            price_url: env::var("USD_BACKFILL_PRICE_URL")
+                .unwrap_or_else(|_| "https://api.binance.com/api/v3/klines".to_string()),
+            requests_per_min,
+            batch_size: env::var("USD_BACKFILL_BATCH_SIZE").map_or(Ok(100), |v| v.parse())?,
+            interval_secs: env::var("USD_BACKFILL_INTERVAL_SECS")
+                .map_or(Ok(3600), |v| v.parse())?,
+        }))
+    }
+}
+
+impl CandleConfig {
+    /// Returns `None` when `CANDLE_INTERVALS` is not set, e.g. `1s,5s,1m`
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(value) = env::var("CANDLE_INTERVALS") else {
+            return Ok(None);
+        };
+        let mut intervals_secs = list_entries(&value)
+            .map(parse_interval)
+            .collect::<Result<Vec<_>>>()?;
+        intervals_secs.sort_unstable();
+        intervals_secs.dedup();
+        if intervals_secs.is_empty() {
+            return Err(anyhow!("CANDLE_INTERVALS names no interval"));
+        }
+        Ok(Some(Self { intervals_secs }))
+    }
+}
+
+impl SlippageConfig {
+    /// Returns `None` unless `SLIPPAGE_TRACKING=true`. Buys only adapt with
+    /// `ADAPTIVE_SLIPPAGE=true`, within `ADAPTIVE_SLIPPAGE_MIN_BPS` and
+    /// `ADAPTIVE_SLIPPAGE_MAX_BPS`.
+    pub fn from_env() -> Result<Option<Self>> {
+        if !env::var("SLIPPAGE_TRACKING").map_or(false, |v| v.to_lowercase() == "true") {
+            return Ok(None);
+        }
+        let min_bps = env::var("ADAPTIVE_SLIPPAGE_MIN_BPS").map_or(Ok(100), |v| v.parse())?;
+        let max_bps = env::var("ADAPTIVE_SLIPPAGE_MAX_BPS").map_or(Ok(3_000), |v| v.parse())?;
+        if min_bps > max_bps {
+            return Err(anyhow!(
+                "ADAPTIVE_SLIPPAGE_MIN_BPS must not be above ADAPTIVE_SLIPPAGE_MAX_BPS"
+            ));
+        }
+        Ok(Some(Self {
+            adaptive: env::var("ADAPTIVE_SLIPPAGE").map_or(false, |v| v.to_lowercase() == "true"),
+            min_bps,
+            max_bps,
+            margin_bps: env::var("ADAPTIVE_SLIPPAGE_MARGIN_BPS").map_or(Ok(100), |v| v.parse())?,
+        }))
+    }
+}
+
+impl SweepConfig {
+    /// Returns `None` when `SWEEP_THRESHOLD_SOL` is not set, it needs
+    /// `COLD_WALLET_ADDRESS`
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(threshold_sol) = env::var("SWEEP_THRESHOLD_SOL") else {
+            return Ok(None);
+        };
+        let cold_wallet = env::var("COLD_WALLET_ADDRESS")
+            .map_err(|_| anyhow!("SWEEP_THRESHOLD_SOL needs COLD_WALLET_ADDRESS"))?;
+        let threshold_sol: f64 = threshold_sol.parse()?;
+        if threshold_sol <= 0.0 {
+            return Err(anyhow!("SWEEP_THRESHOLD_SOL must be positive"));
+        }
+        Ok(Some(Self {
+            threshold_sol,
+            cold_wallet: Pubkey::from_str(&cold_wallet)?,
+            min_sol: env::var("SWEEP_MIN_SOL").map_or(Ok(0.01), |v| v.parse())?,
+        }))
+    }
+}
+
+impl LatencySloConfig {
+    /// Returns `None` when `LATENCY_SLO_MS` is not set. By default 95% of
+    /// buys must fill within it (`LATENCY_SLO_OBJECTIVE`), stages are only
+    /// held to the budgets of `LATENCY_STAGE_BUDGETS`.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(target_ms) = env::var("LATENCY_SLO_MS") else {
+            return Ok(None);
+        };
+        let objective = env::var("LATENCY_SLO_OBJECTIVE").map_or(Ok(0.95), |v| v.parse())?;
+        if !(0.0..=1.0).contains(&objective) {
+            return Err(anyhow!("LATENCY_SLO_OBJECTIVE must be between 0 and 1"));
+        }
+        let digest_hour = env::var("LATENCY_DIGEST_HOUR").map_or(Ok(0), |v| v.parse())?;
+        if digest_hour > 23 {
+            return Err(anyhow!(
+                "LATENCY_DIGEST_HOUR must be a UTC hour from 0 to 23"
+            ));
+        }
+        Ok(Some(Self {
+            target_ms: target_ms.parse()?,
+            objective,
+            stage_budgets: env::var("LATENCY_STAGE_BUDGETS")
+                .map_or(Ok(Vec::new()), |v| parse_stage_budgets(&v))?,
+            digest_hour,
+        }))
+    }
+}
+
+impl NotifierConfig {
+    /// Returns `None` unless both `NOTIFY_BOT_TOKEN` and `NOTIFY_CHAT_ID` are set
+    pub fn from_env() -> Result<Option<Self>> {
+        match (env::var("NOTIFY_BOT_TOKEN"), env::var("NOTIFY_CHAT_ID")) {
+            (Ok(bot_token), Ok(chat_id)) => Ok(Some(Self {
+                bot_token,
+                chat_id,
+                language: env::var("NOTIFY_LANGUAGE").unwrap_or_else(|_| "en".to_string()),
+                template_dir: env::var("NOTIFY_TEMPLATE_DIR").ok(),
+            })),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl DiscordConfig {
+    /// Returns `None` when `DISCORD_WEBHOOK_URL` is not set
+    pub fn from_env() -> Result<Option<Self>> {
+        Ok(env::var("DISCORD_WEBHOOK_URL")
+            .ok()
+            .filter(|url| !url.trim().is_empty())
+            .map(|webhook_url| Self { webhook_url }))
+    }
+}
+
+impl EventWebhookConfig {
+    /// Returns `None` when `EVENT_WEBHOOK_URLS` is not set. URLs are comma
+    /// separated and receive the events of `EVENT_WEBHOOK_EVENTS`, all of
+    /// them by default.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(urls) = env::var("EVENT_WEBHOOK_URLS") else {
+            return Ok(None);
+        };
+        let urls: Vec<String> = urls
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+        if urls.is_empty() {
+            return Err(anyhow!("EVENT_WEBHOOK_URLS is set but lists no URL"));
+        }
+        let events = match env::var("EVENT_WEBHOOK_EVENTS") {
+            Ok(events) => events
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(|event| {
+                    if webhook::EVENTS.contains(&event) {
+                        Ok(event.to_string())
+                    } else {
+                        Err(anyhow!(
+                            "Unknown webhook event '{}', expected one of {}",
+                            event,
+                            webhook::EVENTS.join(", ")
+                        ))
+                    }
+                })
+                .collect::<Result<Vec<String>>>()?,
+            Err(_) => webhook::EVENTS
+                .iter()
+                .map(|event| event.to_string())
+                .collect(),
+        };
+        Ok(Some(Self {
+            urls,
+            secret: env::var("EVENT_WEBHOOK_SECRET")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            events,
+            max_retries: env::var("EVENT_WEBHOOK_RETRIES").map_or(Ok(3), |v| v.parse())?,
+        }))
+    }
+}
+
+impl PriceFeedConfig {
+    /// Sources are taken from `PRICE_SOURCES`, by default Pyth for SOL/USD,
+    /// then the pool, Birdeye when `BIRDEYE_API_KEY` is set and DexScreener
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+        let birdeye_api_key = env::var("BIRDEYE_API_KEY").ok().filter(|s| !s.is_empty());
+        let sources = match env::var("PRICE_SOURCES") {
+            Ok(sources) => sources
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::parse)
+                .collect::<Result<Vec<PriceSourceKind>>>()?,
+            Err(_) => {
+                let mut sources = defaults.sources.clone();
+                if birdeye_api_key.is_some() {
+                    sources.insert(sources.len() - 1, PriceSourceKind::Birdeye);
+                }
+                sources
+            }
+        };
+        if sources.is_empty() {
+            return Err(anyhow!("PRICE_SOURCES is set but lists no source"));
+        }
+        if sources.contains(&PriceSourceKind::Birdeye) && birdeye_api_key.is_none() {
+            return Err(anyhow!(
+                "PRICE_SOURCES lists birdeye but BIRDEYE_API_KEY is not set"
+            ));
+        }
+        let birdeye_max_rps: f64 =
+            env::var("BIRDEYE_MAX_RPS").map_or(Ok(defaults.birdeye_max_rps), |v| v.parse())?;
+        if birdeye_max_rps <= 0.0 {
+            return Err(anyhow!("BIRDEYE_MAX_RPS must be above 0"));
+        }
+        Ok(Self {
+            sources,
+            birdeye_api_key,
+            birdeye_max_rps,
+            pyth_url: env::var("PYTH_HERMES_URL").unwrap_or(defaults.pyth_url),
+            cache_ttl_ms: env::var("PRICE_CACHE_TTL_MS")
+                .map_or(Ok(defaults.cache_ttl_ms), |v| v.parse())?,
+        })
+    }
+}
+
+impl PriceStreamConfig {
+    /// Returns `None` when `SOLANA_WS_URL` is not set, which disables the
+    /// account subscriptions.
+    pub fn from_env() -> Result<Option<Self>> {
+        Ok(env::var("SOLANA_WS_URL").ok().map(|ws_url| Self { ws_url }))
+    }
+}
+
+impl WalletWatchConfig {
+    /// Returns `None` when `WATCH_WALLETS` is not set, which disables the
+    /// wallet watcher. `WATCH_WALLETS` is a comma separated list of
+    /// `strategy=address`, the logs are subscribed to at `SOLANA_WS_URL`.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(wallets) = env::var("WATCH_WALLETS") else {
+            return Ok(None);
+        };
+        let wallets = wallets
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::parse)
+            .collect::<Result<Vec<WatchedWallet>>>()?;
+        let ws_url = env::var("SOLANA_WS_URL")
+            .map_err(|_| anyhow!("WATCH_WALLETS is set but SOLANA_WS_URL is not"))?;
+        Ok(Some(Self { ws_url, wallets }))
+    }
+}
+
+impl UpdateArchiveConfig {
+    /// Returns `None` when `RAW_UPDATE_LOG` is not set, which disables the
+    /// recording of raw Telegram updates.
+    pub fn from_env() -> Result<Option<Self>> {
+        Ok(env::var("RAW_UPDATE_LOG").ok().map(|path| Self { path }))
+    }
+}
+
+impl DecisionJournalConfig {
+    /// Returns `None` when `DECISION_LOG` is not set, which disables the
+    /// decision journal.
+    pub fn from_env() -> Result<Option<Self>> {
+        Ok(env::var("DECISION_LOG").ok().map(|path| Self { path }))
+    }
+}
+
+impl WalletWebhookConfig {
+    /// Returns `None` when `HELIUS_WEBHOOK_AUTH` is not set, balances are
+    /// then read from the RPC before every buy. Helius sends the value back
+    /// as the `Authorization` header of its webhook calls.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(auth_header) = env::var("HELIUS_WEBHOOK_AUTH") else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            auth_header,
+            balance_max_age_secs: env::var("WALLET_BALANCE_MAX_AGE_SECS")
+                .map_or(Ok(60), |v| v.parse())?,
+        }))
+    }
+}
+
+impl SignalWebhookConfig {
+    /// Returns `None` when `SIGNAL_WEBHOOK_KEY` is not set. Callers of
+    /// `POST /signal` send it as the `X-Api-Key` header.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(api_key) = env::var("SIGNAL_WEBHOOK_KEY") else {
+            return Ok(None);
+        };
+        if api_key.is_empty() {
+            return Err(anyhow!("SIGNAL_WEBHOOK_KEY is set but empty"));
+        }
+        Ok(Some(Self { api_key }))
+    }
+}
+
+impl JitoConfig {
+    /// Returns `None` when `JITO_REGIONS` is not set, transactions are then
+    /// sent one by one. Regions are block engine names (`mainnet`, `ny`,
+    /// `amsterdam`, ...) or full bundle endpoint URLs.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(regions) = env::var("JITO_REGIONS") else {
+            return Ok(None);
+        };
+        let regions: Vec<String> = regions
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+        if regions.is_empty() {
+            return Err(anyhow!("JITO_REGIONS is set but lists no region"));
+        }
+        Ok(Some(Self {
+            regions,
+            tip_lamports: env::var("JITO_TIP_LAMPORTS").map_or(Ok(10_000), |v| v.parse())?,
+        }))
+    }
+}
+
+impl BlockhashConfig {
+    pub fn from_env() -> Result<Self> {
+        let config = Self {
+            max_age_slots: env::var("BLOCKHASH_MAX_AGE_SLOTS").map_or(Ok(60), |v| v.parse())?,
+            refresh_slots: env::var("BLOCKHASH_REFRESH_SLOTS")
+                .map_or(Ok(blockhash::DEFAULT_REFRESH_SLOTS), |v| v.parse())?,
+        };
+        if config.max_age_slots >= 150 {
+            return Err(anyhow!(
+                "BLOCKHASH_MAX_AGE_SLOTS must be below the 150 slots a blockhash lives"
+            ));
+        }
+        if config.refresh_slots > config.max_age_slots {
+            return Err(anyhow!(
+                "BLOCKHASH_REFRESH_SLOTS must not exceed BLOCKHASH_MAX_AGE_SLOTS"
+            ));
+        }
+        Ok(config)
+    }
+}
+
+impl NonceConfig {
+    /// Returns `None` unless `DURABLE_NONCE=true`. `DURABLE_NONCE_ACCOUNTS`
+    /// should be at least `MAX_CONCURRENT_TRADES`, transactions sharing a
+    /// nonce can't both land.
+    pub fn from_env() -> Result<Option<Self>> {
+        if !env::var("DURABLE_NONCE").map_or(false, |v| v.to_lowercase() == "true") {
+            return Ok(None);
+        }
+        let accounts = env::var("DURABLE_NONCE_ACCOUNTS").map_or(Ok(4), |v| v.parse())?;
+        if accounts == 0 {
+            return Err(anyhow!("DURABLE_NONCE_ACCOUNTS must be at least 1"));
+        }
+        Ok(Some(Self { accounts }))
+    }
+}
+
+impl RpcConfig {
+    /// Returns `None` when `SOLANA_RPC_URLS` is not set, clients then use
+    /// `SOLANA_RPC_URL` alone
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(urls) = env::var("SOLANA_RPC_URLS") else {
+            return Ok(None);
+        };
+        let urls: Vec<String> = list_entries(&urls).map(str::to_string).collect();
+        if urls.is_empty() {
+            return Err(anyhow!("SOLANA_RPC_URLS is set but lists no URL"));
+        }
+        Ok(Some(Self { urls }))
+    }
+}
+
+impl ProfilesConfig {
+    /// Returns `None` when `PROFILES` is not set, the copier then runs the
+    /// settings of the environment alone. IDs are letters and digits, their
+    /// settings are read from `PROFILE_<ID>_*`.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(profiles) = env::var("PROFILES") else {
+            return Ok(None);
+        };
+        let mut ids: Vec<String> = Vec::new();
+        for id in list_entries(&profiles) {
+            let id = id.to_lowercase();
+            if !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+                return Err(anyhow!(
+                    "Profile ID '{}' of PROFILES has other characters than letters and digits",
+                    id
+                ));
+            }
+            if ids.contains(&id) {
+                return Err(anyhow!("Profile {} is listed twice in PROFILES", id));
+            }
+            ids.push(id);
+        }
+        if ids.is_empty() {
+            return Err(anyhow!("PROFILES is set but lists no profile"));
+        }
+        Ok(Some(Self { ids }))
+    }
+}
+
+impl PrivyConfig {
+    /// Returns `None` unless `SIGNER_BACKEND=privy`, transactions are then
+    /// signed with `SOLANA_PRIVATE_KEY`
+    pub fn from_env() -> Result<Option<Self>> {
+        match env::var("SIGNER_BACKEND")
+            .unwrap_or_default()
+            .to_lowercase()
+            .as_str()
+        {
+            "" | "local" => return Ok(None),
+            "privy" => {}
+            other => {
+                return Err(anyhow!(
+                    "Unknown SIGNER_BACKEND '{}', expected local or privy",
+                    other
+                ))
+            }
+        }
+        let required = |name: &str| {
+            env::var(name).map_err(|_| anyhow!("SIGNER_BACKEND=privy but {} is not set", name))
+        };
+        Ok(Some(Self {
+            app_id: required("PRIVY_APP_ID")?,
+            app_secret: required("PRIVY_APP_SECRET")?,
+            wallet_id: env::var("PRIVY_WALLET_ID").ok(),
+            api_url: env::var("PRIVY_API_URL")
+                .unwrap_or_else(|_| "https://api.privy.io".to_string()),
+        }))
+    }
+}
+
+impl DevnetConfig {
+    /// Only read with `--devnet`
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            rpc_url: env::var("DEVNET_RPC_URL")
+                .unwrap_or_else(|_| "https://api.devnet.solana.com".to_string()),
+            ws_url: env::var("DEVNET_WS_URL")
+                .unwrap_or_else(|_| "wss://api.devnet.solana.com".to_string()),
+            min_balance_sol: env::var("DEVNET_MIN_BALANCE_SOL").map_or(Ok(1.0), |v| v.parse())?,
+            airdrop_sol: env::var("DEVNET_AIRDROP_SOL").map_or(Ok(1.0), |v| v.parse())?,
+            pool: env::var("DEVNET_POOL").ok(),
+        })
+    }
+}
+
+impl ApprovalConfig {
+    /// Returns `None` when `APPROVAL_REDIRECT_URL` is not set, in which case
+    /// transactions are signed automatically.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(redirect_url) = env::var("APPROVAL_REDIRECT_URL") else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            callback_addr: env::var("APPROVAL_CALLBACK_ADDR")
+                .unwrap_or_else(|_| "0.0.0.0:8787".to_string()),
+            app_url: env::var("APPROVAL_APP_URL").unwrap_or_else(|_| redirect_url.clone()),
+            threshold_sol: env::var("APPROVAL_THRESHOLD_SOL")
+                .ok()
+                .map(|v| v.parse())
+                .transpose()?,
+            timeout_secs: env::var("APPROVAL_TIMEOUT_SECS").map_or(Ok(60), |v| v.parse())?,
+            redirect_url,
+        }))
+    }
+}
+
+impl RiskConfig {
+    /// Returns `None` when `MAX_DAILY_LOSS_SOL` is not set, which disables
+    /// the daily loss circuit breaker.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(max_daily_loss_sol) = env::var("MAX_DAILY_LOSS_SOL") else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            max_daily_loss_sol: max_daily_loss_sol.parse()?,
+        }))
+    }
+}
+
+impl LossStreakConfig {
+    /// Returns `None` when `LOSS_STREAK_MAX` is not set. After that many
+    /// consecutive losers of a strategy its position size is halved
+    /// (`LOSS_STREAK_ACTION=halve`, the default) or its buys are paused for
+    /// `LOSS_STREAK_COOLDOWN_SECS` (`pause`).
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(max_losses) = env::var("LOSS_STREAK_MAX") else {
+            return Ok(None);
+        };
+        let max_losses: u32 = max_losses.parse()?;
+        if max_losses == 0 {
+            return Err(anyhow!("LOSS_STREAK_MAX must be at least 1"));
+        }
+        Ok(Some(Self {
+            max_losses,
+            action: env::var("LOSS_STREAK_ACTION")
+                .map_or(Ok(StreakAction::Halve), |v| v.parse())?,
+            cooldown_secs: env::var("LOSS_STREAK_COOLDOWN_SECS").map_or(Ok(3600), |v| v.parse())?,
+        }))
+    }
+}
+
+impl BalanceGuardConfig {
+    /// Returns `None` when neither `BALANCE_RESERVE_SOL` nor
+    /// `POSITION_SIZE_PCT` is set, buys then only fail once the wallet is
+    /// empty.
+    pub fn from_env() -> Result<Option<Self>> {
+        let reserve_sol = env::var("BALANCE_RESERVE_SOL").ok();
+        let size_pct = env::var("POSITION_SIZE_PCT").ok();
+        if reserve_sol.is_none() && size_pct.is_none() {
+            return Ok(None);
+        }
+        let size_pct = size_pct.map(|v| v.parse::<f64>()).transpose()?;
+        if size_pct.is_some_and(|pct| !(pct > 0.0 && pct <= 100.0)) {
+            return Err(anyhow!("POSITION_SIZE_PCT must be within (0, 100]"));
+        }
+        Ok(Some(Self {
+            reserve_sol: reserve_sol.map_or(Ok(0.05), |v| v.parse())?,
+            size_pct,
+        }))
+    }
+}
+
+impl TokenFilterConfig {
+    /// `TOKEN_BLACKLIST` and `TOKEN_WHITELIST` are paths to files with one
+    /// mint, symbol or deployer wallet per line. Entries of the
+    /// `token_filters` collection apply either way.
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            blacklist_file: env::var("TOKEN_BLACKLIST").ok(),
+            whitelist_file: env::var("TOKEN_WHITELIST").ok(),
+        })
+    }
+}
+
+impl CapitalConfig {
+    /// `WITHDRAW_ALLOWLIST` is a comma separated list of addresses SOL may
+    /// be withdrawn to, withdrawals are impossible while it is empty
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            withdraw_allowlist: env::var("WITHDRAW_ALLOWLIST")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(Pubkey::from_str)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl MaintenanceConfig {
+    /// Returns `None` when `MAINTENANCE_WINDOWS` is not set. Windows are
+    /// separated by `;`, see [`MaintenanceWindow`] for the format.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(windows) = env::var("MAINTENANCE_WINDOWS") else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            windows: windows
+                .split(';')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::parse)
+                .collect::<Result<_>>()?,
+        }))
+    }
+}
+
+impl ApiConfig {
+    /// Returns `None` when `API_BIND_ADDR` is not set, which disables the
+    /// REST API
+    pub fn from_env() -> Result<Option<Self>> {
+        Ok(env::var("API_BIND_ADDR")
+            .ok()
+            .map(|bind_addr| Self { bind_addr }))
+    }
+}
+
+impl GrpcConfig {
+    /// Returns `None` when `GRPC_BIND_ADDR` is not set, which disables the
+    /// gRPC control plane. Only builds with the `grpc` feature serve it.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(bind_addr) = env::var("GRPC_BIND_ADDR") else {
+            return Ok(None);
+        };
+        if !cfg!(feature = "grpc") {
+            return Err(anyhow!(
+                "GRPC_BIND_ADDR is set but the copier was built without the grpc feature"
+            ));
+        }
+        Ok(Some(Self { bind_addr }))
+    }
+}
+
+impl CommandChatConfig {
+    /// Returns `None` when `COMMAND_CHAT` is not set, which leaves the bot
+    /// deaf to commands
+    pub fn from_env() -> Result<Option<Self>> {
+        match env::var("COMMAND_CHAT") {
+            Ok(chat) if chat.trim().is_empty() => Err(anyhow!("COMMAND_CHAT is set but empty")),
+            Ok(chat) => Ok(Some(Self { chat })),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+impl AdminConfig {
+    /// Returns `None` when neither `ADMIN_TELEGRAM_USERS` nor
+    /// `ADMIN_API_TOKENS` is set, which leaves the control interfaces closed.
+    ///
+    /// `ADMIN_TELEGRAM_USERS` is a list of `user_id:scope` and
+    /// `ADMIN_API_TOKENS` a list of `name:token:scope`, both comma separated.
+    pub fn from_env() -> Result<Option<Self>> {
+        let telegram_users = env::var("ADMIN_TELEGRAM_USERS").ok();
+        let api_tokens = env::var("ADMIN_API_TOKENS").ok();
+        if telegram_users.is_none() && api_tokens.is_none() {
+            return Ok(None);
+        }
+        let destructive_max_per_hour =
+            env::var("ADMIN_DESTRUCTIVE_MAX_PER_HOUR").map_or(Ok(3), |v| v.parse())?;
+        if destructive_max_per_hour == 0 {
+            return Err(anyhow!(
+                "ADMIN_DESTRUCTIVE_MAX_PER_HOUR must be at least 1, 0 would block every destructive command"
+            ));
+        }
+
+        Ok(Some(Self {
+            telegram_users: telegram_users
+                .as_deref()
+                .map_or(Ok(Vec::new()), parse_telegram_users)?,
+            api_tokens: api_tokens
+                .as_deref()
+                .map_or(Ok(Vec::new()), parse_api_tokens)?,
+            destructive_max_per_hour,
+        }))
+    }
+}
+
+fn list_entries(value: &str) -> impl Iterator<Item = &str> {
+    value.split(',').map(str::trim).filter(|s| !s.is_empty())
+}
+
+fn parse_telegram_users(value: &str) -> Result<Vec<(i64, Scope)>> {
+    list_entries(value)
+        .map(|entry| {
+            let (id, scope) = entry
+                .split_once(':')
+                .ok_or_else(|| anyhow!("Expected user_id:scope, got '{}'", entry))?;
+            Ok((id.trim().parse()?, scope.parse()?))
+        })
+        .collect()
+}
+
+/// Entries of `name:token:scope`. The name ends at the first colon and the
+/// scope starts after the last, so tokens may contain colons. Errors never
+/// quote the entry, it holds the token.
+fn parse_api_tokens(value: &str) -> Result<Vec<ApiTokenConfig>> {
+    list_entries(value)
+        .enumerate()
+        .map(|(i, entry)| {
+            let (name, rest) = entry.split_once(':').ok_or_else(|| {
+                anyhow!(
+                    "Entry {} of ADMIN_API_TOKENS has no colon, expected name:token:scope",
+                    i + 1
+                )
+            })?;
+            let (token, scope) = rest.rsplit_once(':').ok_or_else(|| {
+                anyhow!(
+                    "Entry {} of ADMIN_API_TOKENS ({}) has no scope, expected name:token:scope",
+                    i + 1,
+                    name.trim()
+                )
+            })?;
+            let (name, token) = (name.trim(), token.trim());
+            if name.is_empty() || token.is_empty() {
+                return Err(anyhow!(
+                    "Entry {} of ADMIN_API_TOKENS has an empty name or token",
+                    i + 1
+                ));
+            }
+            Ok(ApiTokenConfig {
+                name: name.to_string(),
+                token: token.to_string(),
+                scope: scope.trim().parse()?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_url() {
+        assert_eq!(
+            redact_url("mongodb://bot:hunter2@db:27017/copy"),
+            "mongodb://<redacted>@db:27017/copy"
+        );
+        assert_eq!(
+            redact_url("https://mainnet.helius-rpc.com/?api-key=abc"),
+            "https://mainnet.helius-rpc.com/?<redacted>"
+        );
+        assert_eq!(
+            redact_url("wss://api.mainnet-beta.solana.com"),
+            "wss://api.mainnet-beta.solana.com"
+        );
+    }
+
+    #[test]
+    fn test_parse_api_tokens() {
+        let tokens = parse_api_tokens("dashboard:a:b:c:read, bot : secret : trade").unwrap();
+        assert_eq!(tokens[0].name, "dashboard");
+        assert_eq!(tokens[0].token, "a:b:c");
+        assert_eq!(tokens[0].scope, Scope::Read);
+        assert_eq!(tokens[1].name, "bot");
+        assert_eq!(tokens[1].token, "secret");
+        assert_eq!(tokens[1].scope, Scope::Trade);
+
+        for invalid in [
+            "dashboard",
+            "dashboard:read",
+            ":secret:read",
+            "dashboard::read",
+        ] {
+            let e = parse_api_tokens(invalid).unwrap_err().to_string();
+            assert!(!e.contains("secret"), "{}", e);
+        }
+        assert!(parse_api_tokens("dashboard:secret:owner").is_err());
     }
 }
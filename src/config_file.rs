@@ -0,0 +1,393 @@
+use anyhow::{anyhow, Result};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::sync::watch;
+use tokio::time;
+
+use crate::config::TradingConfig;
+use crate::tg_copy::profile::{profile_prefix, profile_vars};
+
+pub const DEFAULT_CONFIG_FILE: &str = "config.toml";
+const DEFAULT_RELOAD_SECS: u64 = 5;
+
+static LOADED: OnceLock<ConfigFile> = OnceLock::new();
+
+/// Environment variables of a config file: the keys of every section
+/// uppercased, `[trading] position_size_sol = 0.1` sets
+/// `POSITION_SIZE_SOL=0.1`. Sections only group the keys, arrays are joined
+/// with commas. `[profiles.aggressive] position_size_sol = 0.5` sets
+/// `PROFILE_AGGRESSIVE_POSITION_SIZE_SOL=0.5`.
+pub fn file_vars(text: &str) -> Result<Vec<(String, String)>> {
+    let toml::Value::Table(root) = text.parse::<toml::Value>()? else {
+        return Err(anyhow!("Config file is not a table"));
+    };
+    let mut vars = Vec::new();
+    for (key, value) in root {
+        match value {
+            // `[profiles.<id>]` sections set `PROFILE_<ID>_*` and list the
+            // profile in `PROFILES`
+            toml::Value::Table(profiles) if key == "profiles" => {
+                let mut ids = Vec::new();
+                for (id, section) in profiles {
+                    let toml::Value::Table(section) = section else {
+                        return Err(anyhow!("profiles.{} is not a section", id));
+                    };
+                    for (key, value) in section {
+                        vars.push((
+                            format!("{}{}", profile_prefix(&id), key.to_uppercase()),
+                            var_value(&key, value)?,
+                        ));
+                    }
+                    ids.push(id);
+                }
+                vars.push(("PROFILES".to_string(), ids.join(",")));
+            }
+            toml::Value::Table(section) => {
+                for (key, value) in section {
+                    vars.push((key.to_uppercase(), var_value(&key, value)?));
+                }
+            }
+            value => vars.push((key.to_uppercase(), var_value(&key, value)?)),
+        }
+    }
+    Ok(vars)
+}
+
+fn var_value(key: &str, value: toml::Value) -> Result<String> {
+    match value {
+        toml::Value::String(value) => Ok(value),
+        toml::Value::Integer(value) => Ok(value.to_string()),
+        toml::Value::Float(value) => Ok(value.to_string()),
+        toml::Value::Boolean(value) => Ok(value.to_string()),
+        toml::Value::Array(values) => Ok(values
+            .into_iter()
+            .map(|value| var_value(key, value))
+            .collect::<Result<Vec<_>>>()?
+            .join(",")),
+        other => Err(anyhow!("Unsupported value of {}: {}", key, other)),
+    }
+}
+
+/// The config file read at startup
+#[derive(Debug)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    /// The environment before the file was read
+    environment: Vec<(String, String)>,
+    /// Set in the environment before the file was read, the file doesn't
+    /// change them
+    overridden: HashSet<String>,
+    /// Seconds between checks for changes, 0 for never
+    pub reload_secs: u64,
+}
+
+impl ConfigFile {
+    /// The environment the process started with and the variables of
+    /// `text`, the file as it is now, where the environment doesn't set
+    /// them. A setting removed from the file is unset again.
+    fn vars(&self, text: &str) -> Result<Vec<(String, String)>> {
+        let mut vars = self.environment.clone();
+        vars.extend(
+            file_vars(text)?
+                .into_iter()
+                .filter(|(name, _)| !self.overridden.contains(name)),
+        );
+        Ok(vars)
+    }
+
+    /// Trading settings of the file as it is now, with the settings of
+    /// `profile` over the shared ones
+    fn trading_config(&self, profile: Option<&str>) -> Result<TradingConfig> {
+        let text = fs::read_to_string(&self.path)?;
+        let mut vars = self.vars(&text)?;
+        if let Some(profile) = profile {
+            vars.extend(profile_vars(profile, &vars));
+        }
+        // Later variables win, the profile's over the shared ones
+        let vars: HashMap<String, String> = vars.into_iter().collect();
+        TradingConfig::from_vars(|name| vars.get(name).cloned())
+    }
+}
+
+/// Read `CONFIG_FILE`, `config.toml` by default, into the environment where
+/// it doesn't set the variable already. A missing default file is fine,
+/// a missing `CONFIG_FILE` is an error. Call before any config is read.
+pub fn load() -> Result<Option<&'static ConfigFile>> {
+    let path = match env::var("CONFIG_FILE") {
+        Ok(path) => PathBuf::from(path),
+        Err(_) if Path::new(DEFAULT_CONFIG_FILE).exists() => PathBuf::from(DEFAULT_CONFIG_FILE),
+        Err(_) => return Ok(None),
+    };
+    let text = fs::read_to_string(&path)
+        .map_err(|e| anyhow!("Failed to read config file {}: {}", path.display(), e))?;
+    let vars = file_vars(&text)?;
+    let overridden: HashSet<String> = vars
+        .iter()
+        .map(|(name, _)| name)
+        .filter(|name| env::var_os(name).is_some())
+        .cloned()
+        .collect();
+    let environment = env::vars().collect();
+    // Nothing reads the environment yet, later reloads leave it alone
+    for (name, value) in vars {
+        if !overridden.contains(&name) {
+            env::set_var(name, value);
+        }
+    }
+    let file = ConfigFile {
+        path,
+        environment,
+        overridden,
+        reload_secs: env::var("CONFIG_RELOAD_SECS")
+            .map_or(Ok(DEFAULT_RELOAD_SECS), |v| v.parse())?,
+    };
+    tracing::info!(
+        "Config file {} loaded, {} settings overridden by the environment, reloaded every {} s",
+        file.path.display(),
+        file.overridden.len(),
+        file.reload_secs
+    );
+    Ok(Some(LOADED.get_or_init(|| file)))
+}
+
+/// The config file read by [`load`], if any
+pub fn loaded() -> Option<&'static ConfigFile> {
+    LOADED.get()
+}
+
+/// Trading settings of a run, changed by [`run_config_reload`] while it
+/// runs. Clones share the settings.
+#[derive(Clone)]
+pub struct LiveTradingConfig {
+    current: Arc<RwLock<Arc<TradingConfig>>>,
+}
+
+impl LiveTradingConfig {
+    pub fn new(config: TradingConfig) -> Self {
+        Self {
+            current: Arc::new(RwLock::new(Arc::new(config))),
+        }
+    }
+
+    /// The settings as of now, later reloads don't change them
+    pub fn load(&self) -> Arc<TradingConfig> {
+        Arc::clone(&self.current.read().unwrap())
+    }
+
+    fn store(&self, config: TradingConfig) {
+        *self.current.write().unwrap() = Arc::new(config);
+    }
+
+    /// Change the settings in place, until the config file changes them
+    /// again
+    pub fn update(&self, change: impl FnOnce(&mut TradingConfig)) -> Arc<TradingConfig> {
+        let mut current = self.current.write().unwrap();
+        let mut config = TradingConfig::clone(&current);
+        change(&mut config);
+        *current = Arc::new(config);
+        Arc::clone(&current)
+    }
+}
+
+/// `current` with the settings of `new` that are safe to change while
+/// running, the names of those that changed, and the names of the changed
+/// settings that only apply after a restart
+pub fn hot_update(
+    current: &TradingConfig,
+    new: &TradingConfig,
+) -> (TradingConfig, Vec<&'static str>, Vec<&'static str>) {
+    let mut updated = current.clone();
+    let mut applied = Vec::new();
+    let mut restart = Vec::new();
+    macro_rules! hot {
+        ($($field:ident),*) => {$(
+            if updated.$field != new.$field {
+                updated.$field = new.$field.clone();
+                applied.push(stringify!($field));
+            }
+        )*};
+    }
+    macro_rules! cold {
+        ($($field:ident),*) => {$(
+            if current.$field != new.$field {
+                restart.push(stringify!($field));
+            }
+        )*};
+    }
+    hot!(
+        trade_on,
+        position_size_sol,
+        size_from_strategy,
+        size_bands,
+        size_bands_market_cap,
+        copy_ratio,
+        slippage_bps,
+        filter_strategies,
+        strategy_filter_on,
+        max_cost_fraction,
+        max_transfer_fee_bps,
+        max_mc_multiple,
+        max_exposure_per_token_sol,
+        untracked_close_max_sol,
+        trade_cooldown_secs,
+        reentry_policy
+    );
+    cold!(
+        trade_mode,
+        tip_lamports,
+        strategy_refresh_secs,
+        gap_close_sell,
+        max_concurrent_trades,
+        trade_queue_capacity
+    );
+    (updated, applied, restart)
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// Re-read `file` whenever it changes until `shutdown` flips, and apply the
+/// trading settings that are safe to change to `trading`, with the
+/// settings of `profile` over the shared ones. The environment is left
+/// alone, the settings are parsed from the file and swapped in. A file
+/// that doesn't parse keeps the settings in use.
+pub async fn run_config_reload(
+    file: &'static ConfigFile,
+    trading: LiveTradingConfig,
+    profile: Option<String>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut interval = time::interval(Duration::from_secs(file.reload_secs));
+    let mut last_modified = modified(&file.path);
+    tracing::info!("Watching {} for changes", file.path.display());
+    loop {
+        tokio::select! {
+            biased;
+            _ = shutdown.changed() => {
+                tracing::info!("Config reload stopped");
+                return;
+            }
+            _ = interval.tick() => {}
+        }
+        let now_modified = modified(&file.path);
+        if now_modified == last_modified {
+            continue;
+        }
+        last_modified = now_modified;
+
+        let new = match file.trading_config(profile.as_deref()) {
+            Ok(new) => new,
+            Err(e) => {
+                tracing::error!(
+                    "Config file {} not reloaded, the settings in use are kept: {:?}",
+                    file.path.display(),
+                    e
+                );
+                continue;
+            }
+        };
+        let (updated, applied, restart) = hot_update(&trading.load(), &new);
+        if !restart.is_empty() {
+            tracing::warn!(
+                "Changed settings only apply after a restart: {}",
+                restart.join(", ")
+            );
+        }
+        if applied.is_empty() {
+            continue;
+        }
+        tracing::info!(
+            "Config file reloaded, applied {}:{}",
+            applied.join(", "),
+            updated
+        );
+        trading.store(updated);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_vars() {
+        let vars = file_vars(
+            r#"
+            config_reload_secs = 10
+
+            [telegram]
+            tg_id = 12345
+            group_name = "Signals"
+
+            [trading]
+            trade_on = true
+            position_size_sol = 0.25
+            filter_strategies = ["degen", "safe"]
+            "#,
+        )
+        .unwrap();
+        let get = |name: &str| {
+            vars.iter()
+                .find(|(var, _)| var == name)
+                .map(|(_, value)| value.as_str())
+        };
+        assert_eq!(get("CONFIG_RELOAD_SECS"), Some("10"));
+        assert_eq!(get("TG_ID"), Some("12345"));
+        assert_eq!(get("GROUP_NAME"), Some("Signals"));
+        assert_eq!(get("TRADE_ON"), Some("true"));
+        assert_eq!(get("POSITION_SIZE_SOL"), Some("0.25"));
+        assert_eq!(get("FILTER_STRATEGIES"), Some("degen,safe"));
+
+        assert!(file_vars("[trading.nested]\nkey = 1").is_err());
+
+        let vars = file_vars(
+            r#"
+            [profiles.safe]
+            position_size_sol = 0.05
+
+            [profiles.aggressive]
+            position_size_sol = 0.5
+            filter_strategies = ["degen"]
+            "#,
+        )
+        .unwrap();
+        let get = |name: &str| {
+            vars.iter()
+                .find(|(var, _)| var == name)
+                .map(|(_, value)| value.as_str())
+        };
+        assert_eq!(get("PROFILES"), Some("aggressive,safe"));
+        assert_eq!(get("PROFILE_SAFE_POSITION_SIZE_SOL"), Some("0.05"));
+        assert_eq!(get("PROFILE_AGGRESSIVE_FILTER_STRATEGIES"), Some("degen"));
+    }
+
+    #[test]
+    fn test_reloaded_vars() {
+        let file = ConfigFile {
+            path: PathBuf::from(DEFAULT_CONFIG_FILE),
+            environment: vec![("SLIPPAGE_BPS".to_string(), "300".to_string())],
+            overridden: HashSet::from(["SLIPPAGE_BPS".to_string()]),
+            reload_secs: DEFAULT_RELOAD_SECS,
+        };
+        let vars = file
+            .vars("[trading]\nslippage_bps = 100\nposition_size_sol = 0.2")
+            .unwrap();
+        let settings = |vars: &[(String, String)]| {
+            vars.iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect::<Vec<_>>()
+        };
+        // The environment wins
+        assert_eq!(
+            settings(&vars),
+            vec!["SLIPPAGE_BPS=300", "POSITION_SIZE_SOL=0.2"]
+        );
+        // Removed from the file, unset again
+        assert_eq!(settings(&file.vars("").unwrap()), vec!["SLIPPAGE_BPS=300"]);
+    }
+}
@@ -0,0 +1,211 @@
+//! Human readable formatting of amounts and prices for logs and notifications.
+//!
+//! Output never depends on the system locale: `.` is always the decimal
+//! separator and no thousands separators are used.
+
+const LAMPORTS_PER_SOL: f64 = 1_000_000_000.0;
+const SUBSCRIPT_DIGITS: [char; 10] = ['₀', '₁', '₂', '₃', '₄', '₅', '₆', '₇', '₈', '₉'];
+
+/// Format with at most `max_decimals` decimals, dropping trailing zeros
+fn trim_decimals(value: f64, max_decimals: usize) -> String {
+    let s = format!("{:.*}", max_decimals, value);
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.').to_string()
+    } else {
+        s
+    }
+}
+
+fn subscript(n: usize) -> String {
+    n.to_string()
+        .chars()
+        .map(|c| SUBSCRIPT_DIGITS[c.to_digit(10).unwrap_or(0) as usize])
+        .collect()
+}
+
+/// `1234.5` -> `1.23k`, `4_500_000` -> `4.5M`
+pub fn compact(value: f64) -> String {
+    if !value.is_finite() {
+        return value.to_string();
+    }
+    const UNITS: [(f64, &str); 4] = [(1e12, "T"), (1e9, "B"), (1e6, "M"), (1e3, "k")];
+
+    let abs = value.abs();
+    for (i, (scale, suffix)) in UNITS.iter().enumerate() {
+        if abs >= *scale {
+            let scaled = value / scale;
+            // 999_999 would round to "1000k", promote it to the next unit
+            if scaled.abs() >= 999.995 && i > 0 {
+                let (scale, suffix) = UNITS[i - 1];
+                return format!("{}{}", trim_decimals(value / scale, 2), suffix);
+            }
+            return format!("{}{}", trim_decimals(scaled, 2), suffix);
+        }
+    }
+    trim_decimals(value, 2)
+}
+
+/// `4_500_000` -> `$4.5M`
+pub fn market_cap(value: f64) -> String {
+    format!("${}", compact(value))
+}
+
+/// Token price with enough precision to be useful for meme tokens. Prices
+/// below 0.001 collapse their leading zeros: `0.0000123` -> `0.0₄123`.
+pub fn price(value: f64) -> String {
+    if !value.is_finite() {
+        return value.to_string();
+    }
+    if value == 0.0 {
+        return "0".to_string();
+    }
+
+    let sign = if value < 0.0 { "-" } else { "" };
+    let abs = value.abs();
+    if abs >= 1.0 {
+        return format!("{}{}", sign, trim_decimals(abs, 4));
+    }
+    if abs >= 0.001 {
+        return format!("{}{}", sign, trim_decimals(abs, 6));
+    }
+
+    // Number of zeros between the decimal point and the first significant digit
+    let mut zeros = (-abs.log10()).ceil() as usize - 1;
+    let mut digits = (abs * 10f64.powi(zeros as i32 + 3)).round() as u64;
+    if digits >= 1000 {
+        zeros -= 1;
+        digits /= 10;
+    }
+    let digits = digits.to_string();
+    format!(
+        "{}0.0{}{}",
+        sign,
+        subscript(zeros),
+        digits.trim_end_matches('0')
+    )
+}
+
+/// `5_010_000` -> `0.00501 SOL`
+pub fn lamports(lamports: u64) -> String {
+    sol(lamports as f64 / LAMPORTS_PER_SOL)
+}
+
+/// `-5_010_000` -> `-0.00501 SOL`, gains get a `+`
+pub fn signed_lamports(lamports: i64) -> String {
+    let sign = if lamports > 0 {
+        "+"
+    } else if lamports < 0 {
+        "-"
+    } else {
+        ""
+    };
+    format!("{}{}", sign, self::lamports(lamports.unsigned_abs()))
+}
+
+/// `5_000_000` with SOL at $150 -> `5000000 lamports ($0.75)`, without
+/// the USD value when the price is unknown
+pub fn lamports_usd(lamports: u64, sol_price_usd: Option<f64>) -> String {
+    match sol_price_usd {
+        Some(price) => format!(
+            "{} lamports ({})",
+            lamports,
+            usd(lamports as f64 / LAMPORTS_PER_SOL * price)
+        ),
+        None => format!("{} lamports", lamports),
+    }
+}
+
+/// `12.5` -> `$12.50`, `0.003126` -> `$0.0031`
+pub fn usd(value: f64) -> String {
+    if value.abs() >= 1.0 {
+        format!("${:.2}", value)
+    } else {
+        format!("${}", trim_decimals(value, 4))
+    }
+}
+
+/// `0.25` -> `0.25 SOL`
+pub fn sol(sol: f64) -> String {
+    format!("{} SOL", trim_decimals(sol, 9))
+}
+
+/// Raw token amount scaled by the mint decimals, `1_234_500_000` with 6
+/// decimals -> `1.23k`
+pub fn token_amount(raw_amount: u64, decimals: u8) -> String {
+    compact(raw_amount as f64 / 10f64.powi(decimals as i32))
+}
+
+/// `850` -> `850ms`, `4300` -> `4.3s`
+pub fn millis(ms: i64) -> String {
+    if ms.abs() < 1000 {
+        format!("{}ms", ms)
+    } else {
+        format!("{}s", trim_decimals(ms as f64 / 1000.0, 2))
+    }
+}
+
+/// `0.9167` -> `91.7%`
+pub fn share(fraction: f64) -> String {
+    format!("{}%", trim_decimals(fraction * 100.0, 1))
+}
+
+/// `12.345` -> `+12.3%`
+pub fn percent(value: f64) -> String {
+    format!("{:+.1}%", value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compact() {
+        assert_eq!(compact(999.0), "999");
+        assert_eq!(compact(1234.5), "1.23k");
+        assert_eq!(compact(4_500_000.0), "4.5M");
+        assert_eq!(compact(999_999.0), "1M");
+        assert_eq!(compact(-2_000.0), "-2k");
+        assert_eq!(market_cap(120_000.0), "$120k");
+    }
+
+    #[test]
+    fn test_price() {
+        assert_eq!(price(1.5), "1.5");
+        assert_eq!(price(0.001122), "0.001122");
+        assert_eq!(price(0.0000123), "0.0₄123");
+        assert_eq!(price(0.0001), "0.0₃1");
+        assert_eq!(price(0.00009999), "0.0₃1");
+        assert_eq!(price(0.0), "0");
+    }
+
+    #[test]
+    fn test_sol_amounts() {
+        assert_eq!(lamports(5_010_000), "0.00501 SOL");
+        assert_eq!(lamports(1_000_000_000), "1 SOL");
+        assert_eq!(signed_lamports(-5_010_000), "-0.00501 SOL");
+        assert_eq!(token_amount(1_234_500_000, 6), "1.23k");
+        assert_eq!(percent(12.345), "+12.3%");
+        assert_eq!(percent(-85.3), "-85.3%");
+    }
+
+    #[test]
+    fn test_usd_amounts() {
+        assert_eq!(usd(12.5), "$12.50");
+        assert_eq!(usd(0.75), "$0.75");
+        assert_eq!(usd(0.003126), "$0.0031");
+        assert_eq!(
+            lamports_usd(5_000_000, Some(150.0)),
+            "5000000 lamports ($0.75)"
+        );
+        assert_eq!(lamports_usd(20_000, Some(150.0)), "20000 lamports ($0.003)");
+        assert_eq!(lamports_usd(15_000, None), "15000 lamports");
+    }
+
+    #[test]
+    fn test_durations_and_shares() {
+        assert_eq!(millis(850), "850ms");
+        assert_eq!(millis(4300), "4.3s");
+        assert_eq!(share(0.91666), "91.7%");
+        assert_eq!(share(1.0), "100%");
+    }
+}
@@ -0,0 +1,511 @@
+use anyhow::Result;
+use listen_kit::signer::{SignerContext, TransactionSigner};
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::{broadcast, watch};
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+use tokio_stream::{Stream, StreamExt};
+use tonic::{metadata::MetadataMap, transport::Server, Request, Response, Status};
+
+use crate::admin::auth::{AccessControl, AuthError, Credential, Operator, Scope};
+use crate::notify::TradeEvent;
+use crate::tg_copy::active_trade::ActiveTrade;
+use crate::tg_copy::parse_trade::{OpenTrade, Trade};
+use crate::trade::bot_state::{BotState, BotStateStore};
+use crate::trade::meme_trader::{sell_outcome, MemeTrader};
+
+pub mod proto {
+    tonic::include_proto!("copytrade.control.v1");
+}
+
+use proto::control_server::{Control, ControlServer};
+use proto::{submit_order_request::Order, trade_event::Detail};
+
+/// Events kept for a slow stream before it starts missing some
+const EVENT_CAPACITY: usize = 256;
+/// Source of the buys submitted, for dedup and the journal
+const GRPC_SOURCE: &str = "grpc";
+
+impl From<&TradeEvent> for proto::TradeEvent {
+    fn from(event: &TradeEvent) -> Self {
+        let detail = match event.clone() {
+            TradeEvent::BuyExecuted {
+                token,
+                token_address,
+                strategy,
+                sol_amount,
+                token_amount,
+                price,
+                tx_sig,
+                ..
+            } => Some(Detail::BuyExecuted(proto::BuyExecuted {
+                token,
+                token_address,
+                strategy,
+                sol_amount,
+                token_amount,
+                price,
+                tx_sig,
+            })),
+            TradeEvent::SellExecuted {
+                token,
+                token_address,
+                strategy,
+                reason,
+                token_amount,
+                remaining_amount,
+                tx_sig,
+                pnl,
+                ..
+            } => Some(Detail::SellExecuted(proto::SellExecuted {
+                token,
+                token_address,
+                strategy,
+                reason,
+                token_amount,
+                remaining_amount,
+                tx_sig,
+                pnl_lamports: pnl.as_ref().map(|pnl| pnl.lamports),
+                pnl_pct: pnl.and_then(|pnl| pnl.pct),
+            })),
+            TradeEvent::BuyFailed {
+                token,
+                token_address,
+                strategy,
+                error,
+            } => Some(Detail::BuyFailed(proto::TradeFailed {
+                token,
+                token_address,
+                strategy,
+                reason: String::new(),
+                error,
+            })),
+            TradeEvent::SellFailed {
+                token,
+                token_address,
+                strategy,
+                reason,
+                error,
+            } => Some(Detail::SellFailed(proto::TradeFailed {
+                token,
+                token_address,
+                strategy,
+                reason,
+                error,
+            })),
+            _ => None,
+        };
+        Self {
+            event: event.template_name().to_string(),
+            json: serde_json::to_string(event).unwrap_or_default(),
+            detail,
+            lagged: 0,
+        }
+    }
+}
+
+impl From<ActiveTrade> for proto::Position {
+    fn from(trade: ActiveTrade) -> Self {
+        Self {
+            token: trade.token_name,
+            token_address: trade.token_address,
+            strategy: trade.strategy_id,
+            initial_holdings: trade.initial_holdings,
+            remaining_holdings: trade.remaining_holdings,
+            entry_price: trade.entry_price,
+            highest_price: trade.highest_price,
+            stop_price: trade.stop_price,
+            sol_spent_lamports: trade.sol_spent_lamports,
+            sol_received_lamports: trade.sol_received_lamports,
+            created_at: trade.created_at,
+        }
+    }
+}
+
+fn auth_status(e: AuthError) -> Status {
+    match e {
+        AuthError::UnknownOperator => Status::unauthenticated(e.to_string()),
+        AuthError::InsufficientScope { .. } => Status::permission_denied(e.to_string()),
+        AuthError::RateLimited { .. } => Status::resource_exhausted(e.to_string()),
+    }
+}
+
+fn internal(e: anyhow::Error) -> Status {
+    tracing::error!("gRPC request failed: {:?}", e);
+    Status::internal(e.to_string())
+}
+
+struct ControlService {
+    trader: Arc<MemeTrader>,
+    access: Arc<AccessControl>,
+    events: broadcast::Sender<TradeEvent>,
+    /// Queue of the copier, submitted buys are traded like signals
+    signals: mpsc::Sender<(&'static str, Trade)>,
+    /// Handlers run on tonic's tasks, manual sells re-enter the context
+    signer: Arc<dyn TransactionSigner>,
+    tip_lamports: u64,
+}
+
+impl ControlService {
+    async fn authorize(
+        &self,
+        metadata: &MetadataMap,
+        action: &str,
+        required: Scope,
+    ) -> Result<Operator, Status> {
+        let token = metadata
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        self.access
+            .authorize(Credential::ApiToken(token), action, required, false)
+            .await
+            .map_err(auth_status)
+    }
+
+    fn bot_state_store(&self) -> Result<&BotStateStore, Status> {
+        self.trader
+            .bot_state()
+            .ok_or_else(|| Status::failed_precondition("Pause switches are not kept"))
+    }
+
+    fn status(&self, bot_state: BotState) -> proto::BotStatus {
+        proto::BotStatus {
+            status: self.trader.buy_gate().to_string(),
+            global_paused: bot_state.global_paused,
+            global_reason: bot_state.global_reason,
+            paused_strategies: bot_state
+                .paused_strategies
+                .into_iter()
+                .map(|paused| proto::PausedStrategy {
+                    strategy: paused.strategy,
+                    reason: paused.reason,
+                })
+                .collect(),
+        }
+    }
+
+    async fn buy(&self, operator: &Operator, order: proto::BuyOrder) -> Result<(), Status> {
+        if order.contract_address.is_empty() || order.strategy.is_empty() {
+            return Err(Status::invalid_argument(
+                "A buy needs a strategy and a contract address",
+            ));
+        }
+        let details = format!("{} {}", order.contract_address, order.strategy);
+        let trade = Trade::Open(OpenTrade {
+            strategy: order.strategy,
+            token: order.token,
+            buy_price: order.price,
+            num_buys: 1,
+            total_buys: None,
+            time_window: 0,
+            contract_address: order.contract_address,
+            market_cap: order.market_cap,
+        });
+        let result = self.signals.try_send((GRPC_SOURCE, trade));
+        let outcome = match &result {
+            Ok(()) => "queued",
+            Err(TrySendError::Full(_)) => "queue full",
+            Err(TrySendError::Closed(_)) => "copier stopped",
+        };
+        self.access.audit(operator, "buy", &details, outcome).await;
+        match result {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => Err(Status::resource_exhausted("Signal queue full")),
+            Err(TrySendError::Closed(_)) => Err(Status::unavailable("The copier stopped")),
+        }
+    }
+
+    async fn sell(
+        &self,
+        operator: &Operator,
+        order: proto::SellOrder,
+    ) -> Result<Vec<proto::SellFill>, Status> {
+        let pct = order.pct.unwrap_or(100.0);
+        if !(pct > 0.0 && pct <= 100.0) {
+            return Err(Status::invalid_argument(format!(
+                "pct must be within (0, 100], got {}",
+                pct
+            )));
+        }
+        let details = format!(
+            "{} {}% {}",
+            order.mint,
+            pct,
+            order.strategy.as_deref().unwrap_or("all")
+        );
+
+        let trader = Arc::clone(&self.trader);
+        let tip_lamports = self.tip_lamports;
+        let result = SignerContext::with_signer(Arc::clone(&self.signer), async move {
+            trader
+                .sell_mint(
+                    &order.mint,
+                    pct,
+                    order.strategy.as_deref(),
+                    tip_lamports,
+                    "Manual sell (gRPC)",
+                )
+                .await
+        })
+        .await;
+
+        let outcome = match &result {
+            Ok(results) => sell_outcome(results),
+            Err(e) => format!("failed: {}", e),
+        };
+        self.access
+            .audit(operator, "sell", &details, &outcome)
+            .await;
+
+        Ok(result
+            .map_err(internal)?
+            .into_iter()
+            .map(|(trade, fill)| match fill {
+                Ok(fill) => proto::SellFill {
+                    strategy: trade.strategy_id,
+                    token_amount: fill.token_amount,
+                    tx_sig: fill.tx_sig,
+                    error: String::new(),
+                },
+                Err(e) => proto::SellFill {
+                    strategy: trade.strategy_id,
+                    token_amount: 0,
+                    tx_sig: String::new(),
+                    error: format!("{:#}", e),
+                },
+            })
+            .collect())
+    }
+}
+
+type EventStream = Pin<Box<dyn Stream<Item = Result<proto::TradeEvent, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Control for ControlService {
+    type StreamEventsStream = EventStream;
+
+    async fn stream_events(
+        &self,
+        request: Request<proto::StreamEventsRequest>,
+    ) -> Result<Response<Self::StreamEventsStream>, Status> {
+        self.authorize(request.metadata(), "stream_events", Scope::Read)
+            .await?;
+        let mut lagged = 0;
+        let stream =
+            BroadcastStream::new(self.events.subscribe()).filter_map(move |event| match event {
+                Ok(event) => {
+                    let mut event = proto::TradeEvent::from(&event);
+                    event.lagged = std::mem::take(&mut lagged);
+                    Some(Ok(event))
+                }
+                Err(BroadcastStreamRecvError::Lagged(missed)) => {
+                    lagged += missed;
+                    None
+                }
+            });
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn submit_order(
+        &self,
+        request: Request<proto::SubmitOrderRequest>,
+    ) -> Result<Response<proto::SubmitOrderResponse>, Status> {
+        let operator = self
+            .authorize(request.metadata(), "order", Scope::Trade)
+            .await?;
+        let response = match request.into_inner().order {
+            Some(Order::Buy(order)) => {
+                self.buy(&operator, order).await?;
+                proto::SubmitOrderResponse {
+                    queued: true,
+                    fills: Vec::new(),
+                }
+            }
+            Some(Order::Sell(order)) => proto::SubmitOrderResponse {
+                queued: false,
+                fills: self.sell(&operator, order).await?,
+            },
+            None => return Err(Status::invalid_argument("No order")),
+        };
+        Ok(Response::new(response))
+    }
+
+    async fn list_positions(
+        &self,
+        request: Request<proto::ListPositionsRequest>,
+    ) -> Result<Response<proto::ListPositionsResponse>, Status> {
+        self.authorize(request.metadata(), "positions", Scope::Read)
+            .await?;
+        let positions = self
+            .trader
+            .active_trades()
+            .load_all_trades()
+            .await
+            .map_err(internal)?;
+        Ok(Response::new(proto::ListPositionsResponse {
+            positions: positions.into_iter().map(proto::Position::from).collect(),
+        }))
+    }
+
+    async fn get_bot_state(
+        &self,
+        request: Request<proto::GetBotStateRequest>,
+    ) -> Result<Response<proto::BotStatus>, Status> {
+        self.authorize(request.metadata(), "bot_state", Scope::Read)
+            .await?;
+        let bot_state = self.bot_state_store()?.load().await.map_err(internal)?;
+        Ok(Response::new(self.status(bot_state)))
+    }
+
+    async fn pause(
+        &self,
+        request: Request<proto::PauseRequest>,
+    ) -> Result<Response<proto::BotStatus>, Status> {
+        let operator = self
+            .authorize(request.metadata(), "pause", Scope::Trade)
+            .await?;
+        let request = request.into_inner();
+        let reason = format!(
+            "paused by {}{}",
+            operator.id,
+            request.reason.map_or(String::new(), |r| format!(": {}", r))
+        );
+        let strategy = request.strategy.as_deref();
+        let (bot_state, changed) = self
+            .bot_state_store()?
+            .pause(strategy, &reason)
+            .await
+            .map_err(internal)?;
+        if changed {
+            self.trader.notify(TradeEvent::BuysPaused {
+                reason: match strategy {
+                    Some(strategy) => format!("{} {}", strategy, reason),
+                    None => reason.clone(),
+                },
+                breaker: None,
+            });
+        }
+        self.access
+            .audit(
+                &operator,
+                "pause",
+                &format!("{} ({})", reason, strategy.unwrap_or("all strategies")),
+                if changed { "paused" } else { "already paused" },
+            )
+            .await;
+        Ok(Response::new(self.status(bot_state)))
+    }
+
+    async fn resume(
+        &self,
+        request: Request<proto::ResumeRequest>,
+    ) -> Result<Response<proto::BotStatus>, Status> {
+        let operator = self
+            .authorize(request.metadata(), "resume", Scope::Trade)
+            .await?;
+        let strategy = request.get_ref().strategy.as_deref();
+        let (bot_state, changed) = self
+            .bot_state_store()?
+            .resume(strategy)
+            .await
+            .map_err(internal)?;
+        if changed {
+            self.trader.notify(TradeEvent::BuysResumed {
+                reason: match strategy {
+                    Some(strategy) => format!("{} resumed by {}", strategy, operator.id),
+                    None => format!("resumed by {}", operator.id),
+                },
+            });
+        }
+        self.access
+            .audit(
+                &operator,
+                "resume",
+                strategy.unwrap_or("all strategies"),
+                if changed { "resumed" } else { "was not paused" },
+            )
+            .await;
+        Ok(Response::new(self.status(bot_state)))
+    }
+}
+
+/// Serve the gRPC control plane on `bind_addr` until shutdown. Every trade
+/// event of `trader` is streamed to subscribers from now on, submitted buys
+/// go to `signals`, and manual sells are signed by the signer of the
+/// calling context.
+pub async fn run_grpc_server(
+    bind_addr: String,
+    trader: Arc<MemeTrader>,
+    access: Arc<AccessControl>,
+    signals: mpsc::Sender<(&'static str, Trade)>,
+    tip_lamports: u64,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    let (events, _) = broadcast::channel(EVENT_CAPACITY);
+    let hook_events = events.clone();
+    trader.add_event_hook(Arc::new(move |event: &TradeEvent| {
+        // Nobody listening is fine
+        let _ = hook_events.send(event.clone());
+    }));
+    let service = ControlService {
+        trader,
+        access,
+        events,
+        signals,
+        signer: SignerContext::current().await,
+        tip_lamports,
+    };
+
+    let addr = bind_addr.parse()?;
+    tracing::info!("gRPC control plane listening on {}", bind_addr);
+    Server::builder()
+        .add_service(ControlServer::new(service))
+        .serve_with_shutdown(addr, async move {
+            let _ = shutdown.changed().await;
+        })
+        .await?;
+    tracing::info!("gRPC control plane stopped");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tg_copy::active_trade::RealizedPnl;
+
+    #[test]
+    fn test_trade_event() {
+        let event = proto::TradeEvent::from(&TradeEvent::SellExecuted {
+            token: "WIF".to_string(),
+            token_address: "mint".to_string(),
+            strategy: "degen".to_string(),
+            reason: "Close signal".to_string(),
+            token_amount: 1_000,
+            remaining_amount: 0,
+            tx_sig: "sig".to_string(),
+            costs: None,
+            pnl: Some(RealizedPnl {
+                lamports: 20_000_000,
+                pct: Some(10.0),
+            }),
+        });
+        assert_eq!(event.event, "sell_executed");
+        let Some(Detail::SellExecuted(sell)) = event.detail else {
+            panic!("expected a sell, got {:?}", event.detail);
+        };
+        assert_eq!(sell.pnl_lamports, Some(20_000_000));
+        assert_eq!(sell.pnl_pct, Some(10.0));
+        let json: serde_json::Value = serde_json::from_str(&event.json).unwrap();
+        assert_eq!(json["event"], "sell_executed");
+
+        let paused = proto::TradeEvent::from(&TradeEvent::BuysPaused {
+            reason: "maintenance".to_string(),
+            breaker: None,
+        });
+        assert_eq!(paused.event, "buys_paused");
+        assert!(paused.detail.is_none());
+    }
+}
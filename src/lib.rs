@@ -1,5 +1,15 @@
+pub mod admin;
+pub mod api;
+pub mod cli;
 pub mod common;
 pub mod config;
+pub mod config_file;
+pub mod format;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod notify;
+pub mod parser;
 pub mod solana;
+pub mod storage;
 pub mod tg_copy;
 pub mod trade;
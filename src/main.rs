@@ -1,9 +1,32 @@
 use anyhow::Result;
-use copy_trade_telegram::tg_copy::copier::async_main;
+use clap::Parser;
+use copy_trade_telegram::cli::{self, Cli, Command};
+use copy_trade_telegram::common::shutdown_signal;
+use copy_trade_telegram::config::{
+    ApprovalConfig, BlockhashConfig, CapitalConfig, DevnetConfig, JitoConfig, NonceConfig,
+    NotifierConfig, PrivyConfig, ProfilesConfig, SweepConfig, TradeMode, TradingConfig,
+};
+use copy_trade_telegram::config_file;
+use copy_trade_telegram::notify::telegram::TelegramNotifier;
+use copy_trade_telegram::solana::approval::ManualApprovalSigner;
+use copy_trade_telegram::solana::attempts::AttemptTrackingSigner;
+use copy_trade_telegram::solana::blockhash::{run_blockhash_refresher, FreshBlockhashSigner};
+use copy_trade_telegram::solana::devnet;
+use copy_trade_telegram::solana::jito::JitoBundleSigner;
+use copy_trade_telegram::solana::keypair::KeypairSigner;
+use copy_trade_telegram::solana::policy::PolicySigner;
+use copy_trade_telegram::solana::privy::PrivySigner;
+use copy_trade_telegram::solana::rpc::{make_rpc_client, rpc_pool};
+use copy_trade_telegram::solana::simulate::SimulatingSigner;
+use copy_trade_telegram::solana::transaction::NoncePool;
+use copy_trade_telegram::tg_copy::copier::{async_main, download_chat, login, replay_updates};
+use copy_trade_telegram::tg_copy::profile::{run_profiles, setup_profiles};
 use dotenv::dotenv;
-use listen_kit::signer::{solana::LocalSolanaSigner, SignerContext};
+use listen_kit::signer::{SignerContext, TransactionSigner};
 use listen_kit::solana::util::env;
-use std::{io, sync::Arc};
+use solana_sdk::{pubkey::Pubkey, signature::Keypair};
+use std::{io, str::FromStr, sync::Arc};
+use tokio::sync::watch;
 use tracing_appender::rolling::{RollingFileAppender, Rotation};
 use tracing_subscriber::{
     filter::LevelFilter, fmt, prelude::*, util::SubscriberInitExt, EnvFilter,
@@ -12,6 +35,8 @@ use tracing_subscriber::{
 #[tokio::main]
 async fn main() -> Result<()> {
     dotenv().ok();
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or(Command::Run);
 
     let file_appender = RollingFileAppender::new(Rotation::DAILY, "logs", "trade-bot.log");
     let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
@@ -26,8 +51,219 @@ async fn main() -> Result<()> {
         .with(filter)
         .init();
 
-    let signer = LocalSolanaSigner::new(env("SOLANA_PRIVATE_KEY"));
-    SignerContext::with_signer(Arc::new(signer), async { async_main().await }).await?;
+    // Before any config is read from the environment
+    config_file::load()?;
+
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        let _ = shutdown_tx.send(true);
+    });
+
+    // Before anything makes an RPC client
+    let devnet_config = if cli.devnet {
+        let devnet_config = DevnetConfig::from_env()?;
+        tracing::info!("{}", devnet_config);
+        devnet::enable(&devnet_config);
+        Some(devnet_config)
+    } else {
+        None
+    };
+
+    // Creates the wallet the signer below needs
+    if let Command::PrivyWallet {
+        session_signer,
+        wallet,
+    } = &command
+    {
+        return cli::privy_wallet(session_signer, wallet.as_deref()).await;
+    }
+
+    if matches!(command, Command::Run) {
+        // Every profile signs with its own wallet. They are set up in their
+        // own environment before any task runs that could read it.
+        let profiles = match ProfilesConfig::from_env()? {
+            Some(profiles) => {
+                // Made first, the RPC pool is every profile's and not only
+                // the first one's
+                rpc_pool();
+                Some(
+                    setup_profiles(profiles, || make_signer(&command, devnet_config.as_ref()))
+                        .await?,
+                )
+            }
+            None => None,
+        };
+        tokio::spawn(run_blockhash_refresher(
+            BlockhashConfig::from_env()?,
+            shutdown_rx.clone(),
+        ));
+        if let Some(profiles) = profiles {
+            for profile in &profiles {
+                spawn_faucet(devnet_config.as_ref(), &profile.signer, &shutdown_rx)?;
+            }
+            return run_profiles(profiles, shutdown_rx.clone()).await;
+        }
+    }
+    let signer = make_signer(&command, devnet_config.as_ref()).await?;
+    spawn_faucet(devnet_config.as_ref(), &signer, &shutdown_rx)?;
+    SignerContext::with_signer(signer, async {
+        match command {
+            Command::Run => async_main(shutdown_rx).await,
+            Command::Login => login().await,
+            Command::Download { chat, topic } => download_chat(&chat, topic).await,
+            Command::Replay { file, db, trade } => {
+                replay_updates(&file, db.as_deref(), trade).await
+            }
+            Command::Reparse { reason, store } => cli::reparse(reason.as_deref(), store).await,
+            Command::Positions => cli::positions().await,
+            Command::Exposure { overlapping } => cli::exposure(overlapping).await,
+            Command::Sell {
+                mint,
+                pct,
+                strategy,
+            } => cli::sell(&mint, pct, strategy.as_deref()).await,
+            Command::Balance => cli::balance().await,
+            Command::Quote { mint, sol, sell } => cli::quote(&mint, sol, sell).await,
+            Command::Deploy {
+                name,
+                symbol,
+                image,
+                buy_sol,
+            } => cli::deploy(&name, &symbol, &image, buy_sol).await,
+            Command::Housekeeping { dry_run } => cli::housekeeping(dry_run).await,
+            Command::Trading { action } => cli::trading(action).await,
+            Command::Capital { action } => cli::capital(action).await,
+            Command::Withdraw { action } => cli::withdraw(action).await,
+            Command::Report { period, days, csv } => {
+                cli::report(period, days, csv.as_deref()).await
+            }
+            Command::PrivyWallet { .. } => unreachable!("handled before the signer is set up"),
+            Command::Config { action } => cli::config(action, cli.devnet).await,
+        }
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// The signer `command` trades with, set up from the environment
+async fn make_signer(
+    command: &Command,
+    devnet_config: Option<&DevnetConfig>,
+) -> Result<Arc<dyn TransactionSigner>> {
+    let simulate = command.trades() && TradingConfig::from_env()?.trade_mode == TradeMode::Simulate;
+
+    let privy_config = PrivyConfig::from_env()?;
+    // Only the copier races launches, other commands send plain transactions.
+    // Jito only runs on mainnet and needs the private key to sign the tip.
+    let jito_config = match command {
+        Command::Run if devnet_config.is_none() && !simulate && privy_config.is_none() => {
+            JitoConfig::from_env()?
+        }
+        _ => None,
+    };
+    // Bundles go to Jito's block engine only, never to public RPC
+    let private_sends = privy_config.is_none() && jito_config.is_some();
+    // Every other signer sends through our RPC client, over `SOLANA_RPC_URLS`
+    let signer: Arc<dyn TransactionSigner> = match (privy_config, jito_config) {
+        (Some(privy_config), _) => {
+            tracing::info!("{}", privy_config);
+            Arc::new(PrivySigner::connect(privy_config).await?)
+        }
+        (None, Some(jito_config)) => {
+            tracing::info!("{}", jito_config);
+            let keypair = Keypair::from_base58_string(&env("SOLANA_PRIVATE_KEY"));
+            Arc::new(JitoBundleSigner::new(keypair, jito_config))
+        }
+        (None, None) => {
+            let keypair = Keypair::from_base58_string(&env("SOLANA_PRIVATE_KEY"));
+            Arc::new(KeypairSigner::new(keypair))
+        }
+    };
+    let nonce_config = if command.trades() && !simulate {
+        NonceConfig::from_env()?
+    } else {
+        None
+    };
+    let nonces = match &nonce_config {
+        Some(nonce_config) => {
+            tracing::info!("{}", nonce_config);
+            let owner = Pubkey::from_str(&signer.pubkey())?;
+            Some(NoncePool::new(owner, nonce_config.accounts)?)
+        }
+        None => None,
+    };
+    let fresh_blockhash = FreshBlockhashSigner::new(signer, &BlockhashConfig::from_env()?);
+    let signer: Arc<dyn TransactionSigner> = match nonces.clone() {
+        Some(nonces) => Arc::new(fresh_blockhash.with_nonces(nonces)),
+        None => Arc::new(fresh_blockhash),
+    };
+    let signer: Arc<dyn TransactionSigner> = if simulate {
+        tracing::warn!("TRADE_MODE=simulate, transactions are simulated and never sent");
+        Arc::new(SimulatingSigner::new(signer.pubkey()))
+    } else {
+        signer
+    };
+    let approval_config = if command.trades() && !simulate {
+        ApprovalConfig::from_env()?
+    } else {
+        None
+    };
+    let signer: Arc<dyn TransactionSigner> = match approval_config {
+        Some(approval_config) => {
+            tracing::info!("{}", approval_config);
+            let notifier = NotifierConfig::from_env()?
+                .map(TelegramNotifier::new)
+                .transpose()?;
+            Arc::new(ManualApprovalSigner::connect(signer, approval_config, notifier).await?)
+        }
+        None => signer,
+    };
+    // The capital policy wraps everything else so no signing path bypasses it
+    let signer: Arc<dyn TransactionSigner> = if command.trades() {
+        let capital_config = CapitalConfig::from_env()?;
+        tracing::info!("{}", capital_config);
+        Arc::new(PolicySigner::new(
+            signer,
+            cli::open_capital().await?,
+            capital_config.withdraw_allowlist,
+            SweepConfig::from_env()?.map(|sweep| sweep.cold_wallet),
+        ))
+    } else {
+        signer
+    };
+    // Outermost to see the signature of every send, failed ones included
+    let tracking = AttemptTrackingSigner::new(signer);
+    let signer: Arc<dyn TransactionSigner> = if private_sends {
+        Arc::new(tracking.without_resends())
+    } else {
+        Arc::new(tracking)
+    };
+    if let Some(nonces) = nonces {
+        let tip_lamports = TradingConfig::from_env()?.tip_lamports;
+        SignerContext::with_signer(
+            Arc::clone(&signer),
+            nonces.create_missing(&make_rpc_client(), tip_lamports),
+        )
+        .await?;
+    }
+    Ok(signer)
+}
 
+/// Keep the wallet of `signer` funded on devnet
+fn spawn_faucet(
+    devnet_config: Option<&DevnetConfig>,
+    signer: &Arc<dyn TransactionSigner>,
+    shutdown: &watch::Receiver<bool>,
+) -> Result<()> {
+    if let Some(devnet_config) = devnet_config {
+        let owner = Pubkey::from_str(&signer.pubkey())?;
+        tokio::spawn(devnet::run_faucet(
+            devnet_config.clone(),
+            owner,
+            shutdown.clone(),
+        ));
+    }
     Ok(())
 }
@@ -0,0 +1,266 @@
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+use crate::config::DiscordConfig;
+use crate::format;
+
+use super::{Notifier, TradeEvent};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const GREEN: u32 = 0x2ecc71;
+const RED: u32 = 0xe74c3c;
+const ORANGE: u32 = 0xe67e22;
+/// Discord refuses the whole message over a longer field value
+const MAX_FIELD_CHARS: usize = 1024;
+
+fn field(name: &str, value: impl ToString, inline: bool) -> Value {
+    let value = truncate(&value.to_string(), MAX_FIELD_CHARS);
+    json!({ "name": name, "value": value, "inline": inline })
+}
+
+/// `value` cut to `max` chars, marked with an ellipsis when cut
+fn truncate(value: &str, max: usize) -> String {
+    if value.chars().count() <= max {
+        return value.to_string();
+    }
+    let mut truncated: String = value.chars().take(max - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
+fn solscan(tx_sig: &str) -> String {
+    format!("https://solscan.io/tx/{}", tx_sig)
+}
+
+/// Discord embed of `event`, `None` for events the channel doesn't get.
+/// Buys, sells and failures are posted, sells closing a position with
+/// their PnL.
+pub fn embed(event: &TradeEvent) -> Option<Value> {
+    let (title, color, fields, url) = match event {
+        TradeEvent::BuyExecuted {
+            token,
+            token_address,
+            strategy,
+            sol_amount,
+            token_amount,
+            price,
+            tx_sig,
+            ..
+        } => (
+            format!("🟢 BUY {}", token),
+            GREEN,
+            vec![
+                field("Strategy", strategy, true),
+                field("Spent", format::sol(*sol_amount), true),
+                field("Received", format!("{} tokens", token_amount), true),
+                field("Signal price", format::price(*price), true),
+                field("CA", token_address, false),
+            ],
+            Some(solscan(tx_sig)),
+        ),
+        TradeEvent::SellExecuted {
+            token,
+            token_address,
+            strategy,
+            reason,
+            token_amount,
+            remaining_amount,
+            tx_sig,
+            pnl,
+            ..
+        } => {
+            let mut fields = vec![
+                field("Strategy", strategy, true),
+                field("Reason", reason, true),
+                field("Sold", format!("{} tokens", token_amount), true),
+                field("Remaining", format!("{} tokens", remaining_amount), true),
+            ];
+            let color = match pnl {
+                Some(pnl) => {
+                    let pct = pnl.pct.map(|pct| format!(" ({})", format::percent(pct)));
+                    fields.push(field(
+                        "Position PnL",
+                        format!(
+                            "{}{}",
+                            format::signed_lamports(pnl.lamports),
+                            pct.unwrap_or_default()
+                        ),
+                        false,
+                    ));
+                    if pnl.lamports < 0 {
+                        RED
+                    } else {
+                        GREEN
+                    }
+                }
+                None => ORANGE,
+            };
+            fields.push(field("CA", token_address, false));
+            (
+                format!("🔴 SELL {}", token),
+                color,
+                fields,
+                Some(solscan(tx_sig)),
+            )
+        }
+        TradeEvent::BuyFailed {
+            token,
+            token_address,
+            strategy,
+            error,
+        } => (
+            format!("❌ BUY FAILED {}", token),
+            RED,
+            vec![
+                field("Strategy", strategy, true),
+                field("Error", error, false),
+                field("CA", token_address, false),
+            ],
+            None,
+        ),
+        TradeEvent::SellFailed {
+            token,
+            token_address,
+            strategy,
+            reason,
+            error,
+        } => (
+            format!("❌ SELL FAILED {}", token),
+            RED,
+            vec![
+                field("Strategy", strategy, true),
+                field("Reason", reason, true),
+                field("Error", error, false),
+                field("CA", token_address, false),
+            ],
+            None,
+        ),
+        TradeEvent::SessionRevoked { group, error } => (
+            "❌ TELEGRAM SESSION REVOKED".to_string(),
+            RED,
+            vec![field("Group", group, true), field("Error", error, false)],
+            None,
+        ),
+        _ => return None,
+    };
+    let mut embed = json!({
+        "title": title,
+        "color": color,
+        "fields": fields,
+        "timestamp": Utc::now(),
+    });
+    if let Some(url) = url {
+        embed["url"] = json!(url);
+    }
+    Some(embed)
+}
+
+/// Posts trade events as embeds to a Discord channel through its webhook
+#[derive(Clone)]
+pub struct DiscordNotifier {
+    client: Client,
+    webhook_url: String,
+}
+
+impl DiscordNotifier {
+    pub fn new(config: DiscordConfig) -> Result<Self> {
+        Ok(Self {
+            client: Client::builder().timeout(REQUEST_TIMEOUT).build()?,
+            webhook_url: config.webhook_url,
+        })
+    }
+
+    async fn post(&self, embed: Value) -> Result<()> {
+        // The webhook URL carries its token, it stays out of the errors
+        let response = self
+            .client
+            .post(&self.webhook_url)
+            .json(&json!({ "embeds": [embed] }))
+            .send()
+            .await
+            .map_err(reqwest::Error::without_url)?;
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Discord webhook returned {}: {}",
+                response.status(),
+                response.text().await.unwrap_or_default()
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Notifier for DiscordNotifier {
+    fn notify(&self, event: &TradeEvent) {
+        let Some(embed) = embed(event) else {
+            return;
+        };
+        let notifier = self.clone();
+        let template = event.template_name();
+        tokio::spawn(async move {
+            if let Err(e) = notifier.post(embed).await {
+                tracing::error!("Failed to send {} to Discord: {:?}", template, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tg_copy::active_trade::RealizedPnl;
+
+    fn sell(pnl: Option<RealizedPnl>) -> TradeEvent {
+        TradeEvent::SellExecuted {
+            token: "WIF".to_string(),
+            token_address: "mint".to_string(),
+            strategy: "degen".to_string(),
+            reason: "Close signal".to_string(),
+            token_amount: 1_000,
+            remaining_amount: 0,
+            tx_sig: "sig".to_string(),
+            costs: None,
+            pnl,
+        }
+    }
+
+    #[test]
+    fn test_embed() {
+        let closed = embed(&sell(Some(RealizedPnl {
+            lamports: -50_000_000,
+            pct: Some(-25.0),
+        })))
+        .unwrap();
+        assert_eq!(closed["title"], "🔴 SELL WIF");
+        assert_eq!(closed["color"], RED);
+        assert_eq!(closed["url"], "https://solscan.io/tx/sig");
+        assert_eq!(
+            closed["fields"][4],
+            field("Position PnL", "-0.05 SOL (-25.0%)", false)
+        );
+
+        let partial = embed(&sell(None)).unwrap();
+        assert_eq!(partial["color"], ORANGE);
+        assert_eq!(partial["fields"].as_array().unwrap().len(), 5);
+
+        let long_error = "x".repeat(5_000);
+        let failed = embed(&TradeEvent::BuyFailed {
+            token: "WIF".to_string(),
+            token_address: "mint".to_string(),
+            strategy: "degen".to_string(),
+            error: long_error,
+        })
+        .unwrap();
+        let error = failed["fields"][1]["value"].as_str().unwrap();
+        assert_eq!(error.chars().count(), MAX_FIELD_CHARS);
+        assert!(error.ends_with('…'));
+
+        assert!(embed(&TradeEvent::BuysResumed {
+            reason: "maintenance window ended".to_string(),
+        })
+        .is_none());
+    }
+}
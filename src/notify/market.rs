@@ -0,0 +1,51 @@
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+
+use crate::solana::cache::{cached_fetch_metadata, cached_search_ticker};
+
+/// Liquidity and market cap of a mint around the time it was traded
+#[derive(Debug, Clone, Serialize)]
+pub struct MarketSummary {
+    pub liquidity_usd: Option<f64>,
+    pub market_cap_usd: Option<f64>,
+    /// Launched on pump.fun, graduated or not
+    pub pump_fun: bool,
+}
+
+/// Summary from the token info caches, which the trade itself just filled.
+/// Bonding curve tokens have no pool liquidity, everything else is read
+/// from its most liquid DexScreener pair.
+pub async fn market_summary(token_address: &str) -> Option<MarketSummary> {
+    let pump_info = match Pubkey::from_str(token_address) {
+        Ok(mint) => cached_fetch_metadata(&mint).await.ok(),
+        Err(_) => None,
+    };
+    if let Some(info) = pump_info.as_ref().filter(|info| !info.complete) {
+        return Some(MarketSummary {
+            liquidity_usd: None,
+            market_cap_usd: Some(info.usd_market_cap),
+            pump_fun: true,
+        });
+    }
+
+    let pair = cached_search_ticker(token_address)
+        .await
+        .ok()?
+        .pairs
+        .into_iter()
+        .filter(|pair| pair.base_token.address == token_address)
+        .max_by(|a, b| a.liquidity.usd.total_cmp(&b.liquidity.usd));
+    if pair.is_none() && pump_info.is_none() {
+        return None;
+    }
+    let pump_fun = pump_info.is_some();
+    Some(MarketSummary {
+        liquidity_usd: pair.as_ref().map(|pair| pair.liquidity.usd),
+        market_cap_usd: pair
+            .as_ref()
+            .and_then(|pair| pair.market_cap.or(pair.fdv))
+            .or(pump_info.map(|info| info.usd_market_cap)),
+        pump_fun,
+    })
+}
@@ -0,0 +1,135 @@
+pub mod discord;
+pub mod market;
+pub mod telegram;
+pub mod templates;
+pub mod webhook;
+
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::tg_copy::active_trade::RealizedPnl;
+use crate::trade::costs::{CostReport, ExecutionCosts};
+use crate::trade::latency::SloReport;
+
+/// Callback receiving every trade event, for services embedding the copier
+pub type EventHook = Arc<dyn Fn(&TradeEvent) + Send + Sync>;
+
+/// A destination of trade events, any number of them can be configured.
+/// Sinks deliver in the background, trading never waits on them.
+pub trait Notifier: Send + Sync {
+    fn notify(&self, event: &TradeEvent);
+}
+
+/// Something worth telling the operator about. Serialized fields are the
+/// variables available to its template.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TradeEvent {
+    BuyExecuted {
+        token: String,
+        token_address: String,
+        strategy: String,
+        sol_amount: f64,
+        token_amount: u64,
+        price: f64,
+        tx_sig: String,
+        /// `None` when the transaction fee could not be read
+        costs: Option<ExecutionCosts>,
+    },
+    BuyFailed {
+        token: String,
+        token_address: String,
+        strategy: String,
+        error: String,
+    },
+    SellExecuted {
+        token: String,
+        token_address: String,
+        strategy: String,
+        reason: String,
+        token_amount: u64,
+        remaining_amount: u64,
+        tx_sig: String,
+        costs: Option<ExecutionCosts>,
+        /// PnL of the whole position, on the sell closing it
+        pnl: Option<RealizedPnl>,
+    },
+    SellFailed {
+        token: String,
+        token_address: String,
+        strategy: String,
+        reason: String,
+        error: String,
+    },
+    /// Close signal posted while the copier was down, position left open
+    MissedClose {
+        token: String,
+        token_address: String,
+        strategy: String,
+        reason: String,
+    },
+    /// SOL above the sweep threshold sent to the cold wallet
+    ProfitSwept {
+        lamports: u64,
+        /// Hot wallet balance before the sweep
+        balance_lamports: u64,
+        threshold_lamports: u64,
+        destination: String,
+        /// Token of the profitable close that triggered it
+        token: Option<String>,
+        tx_sig: String,
+    },
+    BuysPaused {
+        reason: String,
+        /// Circuit breaker that tripped, `daily_loss` or `loss_streak`;
+        /// `None` for pauses asked for or scheduled
+        breaker: Option<String>,
+    },
+    BuysResumed {
+        reason: String,
+    },
+    /// Telegram revoked the session, signals wait for a new login
+    SessionRevoked {
+        group: String,
+        error: String,
+    },
+    SessionRestored {
+        group: String,
+    },
+    /// Latency SLO compliance of the buys and costs of the executions of
+    /// the last 24 hours
+    DailyDigest {
+        day: String,
+        failed_buys: usize,
+        latency: SloReport,
+        costs: CostReport,
+    },
+}
+
+impl TradeEvent {
+    /// Mint of an executed trade, its notification carries a market summary
+    pub fn executed_mint(&self) -> Option<&str> {
+        match self {
+            TradeEvent::BuyExecuted { token_address, .. }
+            | TradeEvent::SellExecuted { token_address, .. } => Some(token_address),
+            _ => None,
+        }
+    }
+
+    /// Name of the template that renders this event
+    pub fn template_name(&self) -> &'static str {
+        match self {
+            TradeEvent::BuyExecuted { .. } => "buy_executed",
+            TradeEvent::BuyFailed { .. } => "buy_failed",
+            TradeEvent::SellExecuted { .. } => "sell_executed",
+            TradeEvent::SellFailed { .. } => "sell_failed",
+            TradeEvent::MissedClose { .. } => "missed_close",
+            TradeEvent::ProfitSwept { .. } => "profit_swept",
+            TradeEvent::BuysPaused { .. } => "buys_paused",
+            TradeEvent::BuysResumed { .. } => "buys_resumed",
+            TradeEvent::SessionRevoked { .. } => "session_revoked",
+            TradeEvent::SessionRestored { .. } => "session_restored",
+            TradeEvent::DailyDigest { .. } => "daily_digest",
+        }
+    }
+}
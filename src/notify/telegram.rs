@@ -0,0 +1,80 @@
+use anyhow::{anyhow, Result};
+use reqwest::Client;
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::config::NotifierConfig;
+
+use super::{market::market_summary, templates::NotificationTemplates, Notifier, TradeEvent};
+
+/// Sends trade events to a personal chat through the Telegram Bot API
+#[derive(Clone)]
+pub struct TelegramNotifier {
+    client: Client,
+    bot_token: String,
+    chat_id: String,
+    templates: Arc<NotificationTemplates>,
+}
+
+impl TelegramNotifier {
+    pub fn new(cfg: NotifierConfig) -> Result<Self> {
+        let templates = NotificationTemplates::load(&cfg.language, cfg.template_dir.as_deref())?;
+        Ok(Self {
+            client: Client::new(),
+            bot_token: cfg.bot_token,
+            chat_id: cfg.chat_id,
+            templates: Arc::new(templates),
+        })
+    }
+
+    pub async fn send(&self, text: &str) -> Result<()> {
+        let url = format!("https://api.telegram.org/bot{}/sendMessage", self.bot_token);
+        let response = self
+            .client
+            .post(&url)
+            .json(&json!({
+                "chat_id": self.chat_id,
+                "text": text,
+                "disable_web_page_preview": true,
+            }))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Telegram Bot API returned {}: {}",
+                response.status(),
+                response.text().await.unwrap_or_default()
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Notifier for TelegramNotifier {
+    /// Executed trades are rendered with the market summary of their mint
+    fn notify(&self, event: &TradeEvent) {
+        let notifier = self.clone();
+        let event = event.clone();
+        tokio::spawn(async move {
+            let market = match event.executed_mint() {
+                Some(mint) => market_summary(mint).await,
+                None => None,
+            };
+            let text = notifier
+                .templates
+                .render(&event, market.as_ref())
+                .unwrap_or_else(|e| {
+                    tracing::error!(
+                        "Failed to render {} notification: {:?}",
+                        event.template_name(),
+                        e
+                    );
+                    format!("{:?}", event)
+                });
+            if let Err(e) = notifier.send(&text).await {
+                tracing::error!("Failed to send Telegram notification: {:?}", e);
+            }
+        });
+    }
+}
@@ -0,0 +1,379 @@
+use anyhow::{anyhow, Result};
+use minijinja::{context, Environment, Value};
+use std::{fs, path::Path};
+
+use crate::format;
+
+use super::{market::MarketSummary, TradeEvent};
+
+type Bundle = (&'static str, [(&'static str, &'static str); 11]);
+
+/// Built-in language bundles, one template per event
+const BUNDLES: [Bundle; 2] = [
+    (
+        "en",
+        [
+            (
+                "buy_executed",
+                include_str!("../../templates/notify/en/buy_executed.j2"),
+            ),
+            (
+                "buy_failed",
+                include_str!("../../templates/notify/en/buy_failed.j2"),
+            ),
+            (
+                "sell_executed",
+                include_str!("../../templates/notify/en/sell_executed.j2"),
+            ),
+            (
+                "sell_failed",
+                include_str!("../../templates/notify/en/sell_failed.j2"),
+            ),
+            (
+                "missed_close",
+                include_str!("../../templates/notify/en/missed_close.j2"),
+            ),
+            (
+                "profit_swept",
+                include_str!("../../templates/notify/en/profit_swept.j2"),
+            ),
+            (
+                "buys_paused",
+                include_str!("../../templates/notify/en/buys_paused.j2"),
+            ),
+            (
+                "buys_resumed",
+                include_str!("../../templates/notify/en/buys_resumed.j2"),
+            ),
+            (
+                "session_revoked",
+                include_str!("../../templates/notify/en/session_revoked.j2"),
+            ),
+            (
+                "session_restored",
+                include_str!("../../templates/notify/en/session_restored.j2"),
+            ),
+            (
+                "daily_digest",
+                include_str!("../../templates/notify/en/daily_digest.j2"),
+            ),
+        ],
+    ),
+    (
+        "es",
+        [
+            (
+                "buy_executed",
+                include_str!("../../templates/notify/es/buy_executed.j2"),
+            ),
+            (
+                "buy_failed",
+                include_str!("../../templates/notify/es/buy_failed.j2"),
+            ),
+            (
+                "sell_executed",
+                include_str!("../../templates/notify/es/sell_executed.j2"),
+            ),
+            (
+                "sell_failed",
+                include_str!("../../templates/notify/es/sell_failed.j2"),
+            ),
+            (
+                "missed_close",
+                include_str!("../../templates/notify/es/missed_close.j2"),
+            ),
+            (
+                "profit_swept",
+                include_str!("../../templates/notify/es/profit_swept.j2"),
+            ),
+            (
+                "buys_paused",
+                include_str!("../../templates/notify/es/buys_paused.j2"),
+            ),
+            (
+                "buys_resumed",
+                include_str!("../../templates/notify/es/buys_resumed.j2"),
+            ),
+            (
+                "session_revoked",
+                include_str!("../../templates/notify/es/session_revoked.j2"),
+            ),
+            (
+                "session_restored",
+                include_str!("../../templates/notify/es/session_restored.j2"),
+            ),
+            (
+                "daily_digest",
+                include_str!("../../templates/notify/es/daily_digest.j2"),
+            ),
+        ],
+    ),
+];
+
+/// Renders trade events with the templates of the configured language.
+/// Files named `<event>.j2` in the override directory replace the built-in
+/// template of the same event.
+pub struct NotificationTemplates {
+    env: Environment<'static>,
+}
+
+impl NotificationTemplates {
+    pub fn load(language: &str, template_dir: Option<&str>) -> Result<Self> {
+        let (_, templates) = BUNDLES
+            .iter()
+            .find(|(lang, _)| *lang == language)
+            .ok_or_else(|| {
+                anyhow!(
+                    "No notification templates for language '{}', available: {}",
+                    language,
+                    BUNDLES.map(|(lang, _)| lang).join(", ")
+                )
+            })?;
+
+        let mut env = Environment::new();
+        env.add_filter("sol", format::sol);
+        env.add_filter("lamports", format::lamports);
+        env.add_filter("signed_lamports", format::signed_lamports);
+        env.add_filter("lamports_usd", format::lamports_usd);
+        env.add_filter("usd", format::usd);
+        env.add_filter("price", format::price);
+        env.add_filter("compact", format::compact);
+        env.add_filter("market_cap", format::market_cap);
+        env.add_filter("percent", format::percent);
+        env.add_filter("millis", format::millis);
+        env.add_filter("share", format::share);
+
+        for (name, source) in templates {
+            env.add_template(name, source)?;
+        }
+
+        if let Some(dir) = template_dir {
+            for (name, _) in templates {
+                let path = Path::new(dir).join(format!("{}.j2", name));
+                if path.exists() {
+                    tracing::info!("Using notification template {}", path.display());
+                    env.add_template_owned(name.to_string(), fs::read_to_string(&path)?)?;
+                }
+            }
+        }
+
+        Ok(Self { env })
+    }
+
+    /// `market` is available to templates as `market`, it is only looked
+    /// up for executed trades
+    pub fn render(&self, event: &TradeEvent, market: Option<&MarketSummary>) -> Result<String> {
+        Ok(self
+            .env
+            .get_template(event.template_name())?
+            .render(context! { market, ..Value::from_serialize(event) })?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tg_copy::active_trade::RealizedPnl;
+    use crate::trade::costs::{CostReport, ExecutionCosts};
+    use crate::trade::latency::{SloReport, SlowestFill, Stage, StageReport};
+
+    fn events() -> Vec<TradeEvent> {
+        vec![
+            TradeEvent::BuyExecuted {
+                token: "WIF".to_string(),
+                token_address: "EKpQGSJtjMFqKZ9KQanSqYXRcF8fBopzLHYxdM65zcjm".to_string(),
+                strategy: "degen".to_string(),
+                sol_amount: 0.25,
+                token_amount: 1_000,
+                price: 0.0000123,
+                tx_sig: "sig".to_string(),
+                costs: None,
+            },
+            TradeEvent::BuyFailed {
+                token: "WIF".to_string(),
+                token_address: "EKpQGSJtjMFqKZ9KQanSqYXRcF8fBopzLHYxdM65zcjm".to_string(),
+                strategy: "degen".to_string(),
+                error: "slippage exceeded".to_string(),
+            },
+            TradeEvent::SellExecuted {
+                token: "WIF".to_string(),
+                token_address: "EKpQGSJtjMFqKZ9KQanSqYXRcF8fBopzLHYxdM65zcjm".to_string(),
+                strategy: "degen".to_string(),
+                reason: "Close signal".to_string(),
+                token_amount: 1_000,
+                remaining_amount: 0,
+                tx_sig: "sig".to_string(),
+                costs: Some(ExecutionCosts {
+                    fee_lamports: 105_000,
+                    priority_fee_lamports: 100_000,
+                    tip_lamports: 1_000_000,
+                    total_lamports: 1_105_000,
+                    sol_price_usd: Some(100.0),
+                }),
+                pnl: Some(RealizedPnl {
+                    lamports: 50_000_000,
+                    pct: Some(25.0),
+                }),
+            },
+            TradeEvent::SellFailed {
+                token: "WIF".to_string(),
+                token_address: "EKpQGSJtjMFqKZ9KQanSqYXRcF8fBopzLHYxdM65zcjm".to_string(),
+                strategy: "degen".to_string(),
+                reason: "Close signal".to_string(),
+                error: "no route".to_string(),
+            },
+            TradeEvent::MissedClose {
+                token: "WIF".to_string(),
+                token_address: "EKpQGSJtjMFqKZ9KQanSqYXRcF8fBopzLHYxdM65zcjm".to_string(),
+                strategy: "degen".to_string(),
+                reason: "Close signal (Tp)".to_string(),
+            },
+            TradeEvent::ProfitSwept {
+                lamports: 2_000_000_000,
+                balance_lamports: 7_000_105_000,
+                threshold_lamports: 5_000_000_000,
+                destination: "9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM".to_string(),
+                token: Some("WIF".to_string()),
+                tx_sig: "sig".to_string(),
+            },
+            TradeEvent::BuysPaused {
+                reason: "maintenance window 'WIF' until 07:00 UTC".to_string(),
+                breaker: None,
+            },
+            TradeEvent::BuysResumed {
+                reason: "maintenance window 'WIF' ended".to_string(),
+            },
+            TradeEvent::SessionRevoked {
+                group: "WIF calls".to_string(),
+                error: "AUTH_KEY_UNREGISTERED".to_string(),
+            },
+            TradeEvent::SessionRestored {
+                group: "WIF calls".to_string(),
+            },
+            TradeEvent::DailyDigest {
+                day: "2024-05-01".to_string(),
+                failed_buys: 1,
+                latency: SloReport {
+                    target_ms: 4000,
+                    objective: 0.95,
+                    buys: 12,
+                    within_target: 11,
+                    compliance: Some(11.0 / 12.0),
+                    met: false,
+                    p50_ms: Some(1250),
+                    p95_ms: Some(4800),
+                    stages: vec![StageReport {
+                        stage: Stage::Execute,
+                        budget_ms: Some(1750),
+                        p95_ms: Some(3100),
+                        over_budget: 2,
+                    }],
+                    slowest: Some(SlowestFill {
+                        token: "WIF".to_string(),
+                        latency_ms: 6200,
+                    }),
+                },
+                costs: CostReport {
+                    fills: 20,
+                    fee_lamports: 2_100_000,
+                    priority_fee_lamports: 2_000_000,
+                    tip_lamports: 20_000_000,
+                    total_lamports: 22_100_000,
+                    total_usd: Some(3.3),
+                },
+            },
+        ]
+    }
+
+    fn market() -> MarketSummary {
+        MarketSummary {
+            liquidity_usd: Some(1_200_000.0),
+            market_cap_usd: Some(45_000_000.0),
+            pump_fun: true,
+        }
+    }
+
+    #[test]
+    fn test_every_bundle_renders_every_event() {
+        for (language, _) in BUNDLES {
+            let templates = NotificationTemplates::load(language, None).unwrap();
+            for event in events() {
+                let text = templates.render(&event, None).unwrap();
+                assert!(text.contains("WIF"), "{language}: {text}");
+                let text = templates.render(&event, Some(&market())).unwrap();
+                assert!(text.contains("WIF"), "{language}: {text}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_english_buy() {
+        let templates = NotificationTemplates::load("en", None).unwrap();
+        let text = templates.render(&events()[0], None).unwrap();
+        assert_eq!(
+            text,
+            "🟢 BUY WIF (degen)\n\
+             Spent: 0.25 SOL\n\
+             Received: 1000 tokens\n\
+             Signal price: $0.0₄123\n\
+             CA: EKpQGSJtjMFqKZ9KQanSqYXRcF8fBopzLHYxdM65zcjm\n\
+             Chart: https://dexscreener.com/solana/EKpQGSJtjMFqKZ9KQanSqYXRcF8fBopzLHYxdM65zcjm\n\
+             Birdeye: https://birdeye.so/token/EKpQGSJtjMFqKZ9KQanSqYXRcF8fBopzLHYxdM65zcjm?chain=solana\n\
+             https://solscan.io/tx/sig"
+        );
+    }
+
+    #[test]
+    fn test_english_buy_with_market() {
+        let templates = NotificationTemplates::load("en", None).unwrap();
+        let text = templates.render(&events()[0], Some(&market())).unwrap();
+        assert!(text.contains("\nLiquidity: $1.2M | MC: $45M\n"), "{text}");
+        assert!(text.contains(
+            "\npump.fun: https://pump.fun/coin/EKpQGSJtjMFqKZ9KQanSqYXRcF8fBopzLHYxdM65zcjm\n"
+        ));
+    }
+
+    #[test]
+    fn test_english_sell_costs() {
+        let templates = NotificationTemplates::load("en", None).unwrap();
+        let text = templates.render(&events()[2], None).unwrap();
+        assert!(
+            text.contains(
+                "\nCosts: 1105000 lamports ($0.1105) (priority fee 100000 lamports ($0.01), \
+                 tip 1000000 lamports ($0.1))\n"
+            ),
+            "{text}"
+        );
+    }
+
+    #[test]
+    fn test_english_sell_pnl() {
+        let templates = NotificationTemplates::load("en", None).unwrap();
+        let text = templates.render(&events()[2], None).unwrap();
+        assert!(
+            text.contains("\nRemaining: 0 tokens\nPosition PnL: +0.05 SOL (+25.0%)\n"),
+            "{text}"
+        );
+    }
+
+    #[test]
+    fn test_english_digest() {
+        let templates = NotificationTemplates::load("en", None).unwrap();
+        let text = templates.render(events().last().unwrap(), None).unwrap();
+        assert_eq!(
+            text,
+            "📊 DAILY DIGEST 2024-05-01\n\
+             Buys filled: 12, failed: 1\n\
+             Costs: 22100000 lamports ($3.30) over 20 fills, priority fees 2000000 lamports, tips 20000000 lamports\n\
+             Within 4s: 11 (91.7%), objective 95% ❌\n\
+             Latency p50 1.25s | p95 4.8s\n\
+             execute: p95 3.1s of 1.75s, 2 over\n\
+             Slowest: WIF in 6.2s"
+        );
+    }
+
+    #[test]
+    fn test_unknown_language() {
+        assert!(NotificationTemplates::load("xx", None).is_err());
+    }
+}
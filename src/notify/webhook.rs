@@ -0,0 +1,196 @@
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::time::Duration;
+use tokio::time;
+
+use crate::config::EventWebhookConfig;
+use crate::tg_copy::parse_trade::Trade;
+
+use super::{Notifier, TradeEvent};
+
+/// Header carrying the HMAC-SHA256 of the body, `sha256=<hex>`
+pub const SIGNATURE_HEADER: &str = "X-Signature-256";
+/// Header carrying the event name, also in the body
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+/// Lifecycle events webhooks can subscribe to
+pub const EVENTS: [&str; 5] = [
+    "signal_received",
+    "buy_executed",
+    "sell_executed",
+    "trade_failed",
+    "circuit_breaker_tripped",
+];
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Wait before the first retry, doubled for each one after
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Webhook event of `event`, `None` for events webhooks don't receive
+pub fn webhook_event(event: &TradeEvent) -> Option<&'static str> {
+    match event {
+        TradeEvent::BuyExecuted { .. } => Some("buy_executed"),
+        TradeEvent::SellExecuted { .. } => Some("sell_executed"),
+        TradeEvent::BuyFailed { .. } | TradeEvent::SellFailed { .. } => Some("trade_failed"),
+        TradeEvent::BuysPaused {
+            breaker: Some(_), ..
+        } => Some("circuit_breaker_tripped"),
+        _ => None,
+    }
+}
+
+/// Signature header value of `body`, `sha256=` and the hex HMAC-SHA256
+/// keyed with `secret`
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Server errors and rate limits are retried, other rejections would only
+/// be rejected again
+fn retryable(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Posts trade lifecycle events as JSON to the configured URLs,
+/// `{"event", "timestamp", "data"}`, signed when a secret is set
+#[derive(Clone)]
+pub struct WebhookNotifier {
+    client: Client,
+    config: EventWebhookConfig,
+}
+
+impl WebhookNotifier {
+    pub fn new(config: EventWebhookConfig) -> Result<Self> {
+        Ok(Self {
+            client: Client::builder().timeout(REQUEST_TIMEOUT).build()?,
+            config,
+        })
+    }
+
+    /// Deliver a parsed signal of `source` in the background, before any
+    /// check ran on it
+    pub fn signal_received(&self, source: &str, trade: &Trade) {
+        self.deliver(
+            "signal_received",
+            serde_json::to_value(trade).map(|trade| json!({ "source": source, "trade": trade })),
+        );
+    }
+
+    fn deliver(&self, name: &'static str, data: serde_json::Result<Value>) {
+        if !self.config.events.iter().any(|event| event == name) {
+            return;
+        }
+        let data = match data {
+            Ok(data) => data,
+            Err(e) => {
+                tracing::error!("Failed to serialize {} webhook: {:?}", name, e);
+                return;
+            }
+        };
+        let body = json!({
+            "event": name,
+            "timestamp": Utc::now(),
+            "data": data,
+        })
+        .to_string();
+        for url in &self.config.urls {
+            let notifier = self.clone();
+            let url = url.clone();
+            let body = body.clone();
+            tokio::spawn(async move {
+                if let Err(e) = notifier.post(&url, name, body).await {
+                    tracing::error!("Failed to deliver {} webhook to {}: {:?}", name, url, e);
+                }
+            });
+        }
+    }
+
+    /// Post `body` to `url`, retrying failed deliveries with backoff
+    async fn post(&self, url: &str, name: &str, body: String) -> Result<()> {
+        let mut delay = RETRY_BASE_DELAY;
+        let mut attempt = 0;
+        loop {
+            let mut request = self
+                .client
+                .post(url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(EVENT_HEADER, name);
+            if let Some(secret) = &self.config.secret {
+                request = request.header(SIGNATURE_HEADER, sign(secret, body.as_bytes()));
+            }
+            let error = match request.body(body.clone()).send().await {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) if !retryable(response.status()) => {
+                    return Err(anyhow!("rejected with {}", response.status()))
+                }
+                Ok(response) => anyhow!("answered {}", response.status()),
+                Err(e) => e.without_url().into(),
+            };
+            if attempt >= self.config.max_retries {
+                return Err(error.context(format!("gave up after {} attempts", attempt + 1)));
+            }
+            attempt += 1;
+            tracing::warn!(
+                "{} webhook to {} failed ({}), retry {} in {:?}",
+                name,
+                url,
+                error,
+                attempt,
+                delay
+            );
+            time::sleep(delay).await;
+            delay *= 2;
+        }
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    /// Only the events webhooks receive are delivered
+    fn notify(&self, event: &TradeEvent) {
+        if let Some(name) = webhook_event(event) {
+            self.deliver(name, serde_json::to_value(event));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign() {
+        assert_eq!(
+            sign("key", b"The quick brown fox jumps over the lazy dog"),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+
+    #[test]
+    fn test_webhook_event() {
+        let paused = |breaker: Option<&str>| TradeEvent::BuysPaused {
+            reason: "limit".to_string(),
+            breaker: breaker.map(str::to_string),
+        };
+        assert_eq!(
+            webhook_event(&paused(Some("daily_loss"))),
+            Some("circuit_breaker_tripped")
+        );
+        assert_eq!(webhook_event(&paused(None)), None);
+        assert_eq!(
+            webhook_event(&TradeEvent::SellFailed {
+                token: "PEPE".to_string(),
+                token_address: "mint".to_string(),
+                strategy: "degen".to_string(),
+                reason: "Close signal".to_string(),
+                error: "slippage".to_string(),
+            }),
+            Some("trade_failed")
+        );
+    }
+}
@@ -0,0 +1,47 @@
+//! The signal parser the copier trades with, for tools that need to read
+//! the group's messages exactly like the bot does: dashboards, backtests,
+//! notebooks going through JSON.
+//!
+//! ```
+//! use copy_trade_telegram::parser::{parse_trade, Trade};
+//!
+//! let message = "🟢 New Trade → PEPE\nMC: $120k | degen\nBuy Price: $0.0012\n\
+//!                5 buys, 2.5 SOL (30s)\nCA: mint";
+//! match parse_trade(message) {
+//!     Some(Trade::Open(open)) => assert_eq!(open.market_cap, 120_000.0),
+//!     _ => panic!("not an open signal"),
+//! }
+//! ```
+//!
+//! Everything here follows semver: the signal types are `#[non_exhaustive]`,
+//! fields and signal kinds may be added in a minor release, so matches need
+//! a wildcard arm and values only come from the parser or from JSON.
+
+pub use crate::tg_copy::parse_trade::{
+    parse_trade, parse_trade_close, parse_trade_open, CloseTrade, OpenTrade, OperationType, Trade,
+};
+
+/// Parse `message` into the JSON of its [`Trade`], `null` when it is no
+/// signal. For callers that can't link Rust types.
+pub fn parse_trade_json(message: &str) -> String {
+    serde_json::to_string(&parse_trade(message)).expect("signals serialize to JSON")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_trade_json() {
+        let message = "🟢 New Trade → PEPE\nMC: $120k | degen\nBuy Price: $0.0012\n\
+                       5 buys, 2.5 SOL (30s)\nCA: mint";
+        let json: serde_json::Value = serde_json::from_str(&parse_trade_json(message)).unwrap();
+        assert_eq!(json["signal"], "open");
+        assert_eq!(json["strategy"], "degen");
+        assert_eq!(json["num_buys"], 5);
+        let trade: Trade = serde_json::from_value(json).unwrap();
+        assert!(matches!(trade, Trade::Open(open) if open.time_window == 30));
+
+        assert_eq!(parse_trade_json("gm"), "null");
+    }
+}
@@ -0,0 +1,109 @@
+use anyhow::{anyhow, Result};
+use reqwest::Url;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+
+type Params = HashMap<String, String>;
+type Pending = Arc<Mutex<HashMap<String, oneshot::Sender<Params>>>>;
+
+const MAX_REQUEST_BYTES: usize = 16 * 1024;
+const RESPONSE: &str = "HTTP/1.1 200 OK\r\n\
+    Content-Type: text/plain; charset=utf-8\r\n\
+    Connection: close\r\n\r\n\
+    Received, you can go back to your wallet.";
+
+/// Minimal HTTP listener for wallet redirects. Every redirect link carries a
+/// `request` query parameter which routes the wallet's answer back to the
+/// waiting caller.
+pub struct CallbackServer {
+    pending: Pending,
+}
+
+impl CallbackServer {
+    pub async fn bind(addr: &str) -> Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        tracing::info!("Approval callback server listening on {}", addr);
+
+        let pending: Pending = Arc::default();
+        let accept_pending = Arc::clone(&pending);
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        let pending = Arc::clone(&accept_pending);
+                        tokio::spawn(async move {
+                            if let Err(e) = handle_connection(stream, pending).await {
+                                tracing::warn!("Bad approval callback: {:?}", e);
+                            }
+                        });
+                    }
+                    Err(e) => tracing::error!("Approval callback accept failed: {:?}", e),
+                }
+            }
+        });
+
+        Ok(Self { pending })
+    }
+
+    /// Wait for the redirect carrying `request_id`
+    pub fn register(&self, request_id: &str) -> oneshot::Receiver<Params> {
+        let (tx, rx) = oneshot::channel();
+        self.pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(request_id.to_string(), tx);
+        rx
+    }
+
+    pub fn cancel(&self, request_id: &str) {
+        self.pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(request_id);
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, pending: Pending) -> Result<()> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 || buf.len() + n > MAX_REQUEST_BYTES {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+
+    // "GET /path?query HTTP/1.1"
+    let request = String::from_utf8_lossy(&buf);
+    let target = request
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .ok_or_else(|| anyhow!("Malformed request line"))?;
+    let params: Params = Url::parse(&format!("http://callback{}", target))?
+        .query_pairs()
+        .into_owned()
+        .collect();
+
+    stream.write_all(RESPONSE.as_bytes()).await?;
+    stream.shutdown().await.ok();
+
+    let Some(request_id) = params.get("request") else {
+        return Err(anyhow!("Callback without request id"));
+    };
+    let waiter = pending
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .remove(request_id);
+    match waiter {
+        Some(waiter) => {
+            let _ = waiter.send(params);
+            Ok(())
+        }
+        None => Err(anyhow!("No pending request {}", request_id)),
+    }
+}
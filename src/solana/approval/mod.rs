@@ -0,0 +1,77 @@
+pub mod callback;
+pub mod phantom;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use listen_kit::signer::TransactionSigner;
+use solana_sdk::{native_token::sol_to_lamports, pubkey::Pubkey, transaction::Transaction};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::config::ApprovalConfig;
+use crate::format;
+use crate::notify::telegram::TelegramNotifier;
+use crate::solana::rpc::make_rpc_client;
+use crate::solana::transaction::simulate_sol_outflow;
+
+use self::phantom::PhantomWallet;
+
+/// Signer that routes transactions to the operator's phone for manual
+/// approval. Without a threshold every transaction needs approval
+/// (supervised mode), otherwise only those spending more SOL than the
+/// threshold do and the rest is signed by the wrapped local signer.
+pub struct ManualApprovalSigner {
+    inner: Arc<dyn TransactionSigner>,
+    phantom: PhantomWallet,
+    threshold_lamports: Option<u64>,
+}
+
+impl ManualApprovalSigner {
+    /// Connect the Phantom wallet, which has to hold the same keypair as the
+    /// local signer
+    pub async fn connect(
+        inner: Arc<dyn TransactionSigner>,
+        cfg: ApprovalConfig,
+        notifier: Option<TelegramNotifier>,
+    ) -> Result<Self> {
+        let phantom = PhantomWallet::connect(&cfg, notifier).await?;
+        let owner = Pubkey::from_str(&inner.pubkey())?;
+        if *phantom.wallet() != owner {
+            return Err(anyhow!(
+                "Connected Phantom wallet {} is not the trading wallet {}",
+                phantom.wallet(),
+                owner
+            ));
+        }
+        Ok(Self {
+            inner,
+            phantom,
+            threshold_lamports: cfg.threshold_sol.map(sol_to_lamports),
+        })
+    }
+}
+
+#[async_trait]
+impl TransactionSigner for ManualApprovalSigner {
+    fn pubkey(&self) -> String {
+        self.inner.pubkey()
+    }
+
+    async fn sign_and_send_solana_transaction(&self, tx: &mut Transaction) -> Result<String> {
+        let outflow = simulate_sol_outflow(&make_rpc_client(), tx, self.phantom.wallet()).await?;
+        if self
+            .threshold_lamports
+            .is_some_and(|threshold| outflow <= threshold)
+        {
+            return self.inner.sign_and_send_solana_transaction(tx).await;
+        }
+
+        let summary = format!("spends {}", format::lamports(outflow));
+        let signed = self.phantom.sign_transaction(tx, &summary).await?;
+        let signature = make_rpc_client()
+            .send_and_confirm_transaction(&signed)
+            .await?;
+        *tx = signed;
+        Ok(signature.to_string())
+    }
+}
@@ -0,0 +1,226 @@
+//! Phantom deeplink protocol, see
+//! https://docs.phantom.app/phantom-deeplinks/deeplinks-ios-and-android
+//!
+//! Payloads between the bot and the wallet are encrypted with a NaCl box
+//! derived from our ephemeral X25519 key and the wallet's key.
+
+use anyhow::{anyhow, Result};
+use crypto_box::{
+    aead::{generic_array::GenericArray, Aead, AeadCore, OsRng},
+    PublicKey, SalsaBox, SecretKey,
+};
+use reqwest::Url;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use solana_sdk::{pubkey::Pubkey, transaction::Transaction};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::config::ApprovalConfig;
+use crate::notify::telegram::TelegramNotifier;
+
+use super::callback::CallbackServer;
+
+const PHANTOM_URL: &str = "https://phantom.app/ul/v1";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Deserialize)]
+struct ConnectData {
+    public_key: String,
+    session: String,
+}
+
+#[derive(Serialize)]
+struct SignTransactionPayload<'a> {
+    transaction: String,
+    session: &'a str,
+}
+
+#[derive(Deserialize)]
+struct SignTransactionData {
+    transaction: String,
+}
+
+/// A connected Phantom wallet that signs transactions after the operator
+/// approves them on their phone
+pub struct PhantomWallet {
+    dapp_public_key: PublicKey,
+    shared: SalsaBox,
+    session: String,
+    wallet: Pubkey,
+    redirect_url: String,
+    timeout: Duration,
+    callbacks: CallbackServer,
+    notifier: Option<TelegramNotifier>,
+}
+
+impl PhantomWallet {
+    /// Push a connect link to the operator and wait until the wallet
+    /// approves the connection
+    pub async fn connect(cfg: &ApprovalConfig, notifier: Option<TelegramNotifier>) -> Result<Self> {
+        let callbacks = CallbackServer::bind(&cfg.callback_addr).await?;
+        let dapp_secret = SecretKey::generate(&mut OsRng);
+        let dapp_public_key = dapp_secret.public_key();
+
+        let request_id = new_request_id();
+        let mut url = Url::parse(&format!("{}/connect", PHANTOM_URL))?;
+        url.query_pairs_mut()
+            .append_pair("app_url", &cfg.app_url)
+            .append_pair(
+                "dapp_encryption_public_key",
+                &bs58::encode(dapp_public_key.as_bytes()).into_string(),
+            )
+            .append_pair(
+                "redirect_link",
+                &redirect_link(&cfg.redirect_url, &request_id)?,
+            )
+            .append_pair("cluster", "mainnet-beta");
+
+        let response = callbacks.register(&request_id);
+        deliver(
+            &notifier,
+            "Connect your Phantom wallet to approve trades",
+            &url,
+        )
+        .await;
+        let params = wait_for(&callbacks, &request_id, response, CONNECT_TIMEOUT).await?;
+
+        let phantom_key = decode_fixed::<32>(param(&params, "phantom_encryption_public_key")?)?;
+        let shared = SalsaBox::new(&PublicKey::from(phantom_key), &dapp_secret);
+        let data: ConnectData = decrypt(&shared, &params)?;
+        let wallet = Pubkey::from_str(&data.public_key)?;
+        tracing::info!("Phantom wallet {} connected", wallet);
+
+        Ok(Self {
+            dapp_public_key,
+            shared,
+            session: data.session,
+            wallet,
+            redirect_url: cfg.redirect_url.clone(),
+            timeout: Duration::from_secs(cfg.timeout_secs),
+            callbacks,
+            notifier,
+        })
+    }
+
+    pub fn wallet(&self) -> &Pubkey {
+        &self.wallet
+    }
+
+    /// Ask the operator to sign `tx`, `summary` is shown next to the link
+    pub async fn sign_transaction(&self, tx: &Transaction, summary: &str) -> Result<Transaction> {
+        let payload = serde_json::to_vec(&SignTransactionPayload {
+            transaction: bs58::encode(bincode::serialize(tx)?).into_string(),
+            session: &self.session,
+        })?;
+        let nonce = SalsaBox::generate_nonce(&mut OsRng);
+        let encrypted = self
+            .shared
+            .encrypt(&nonce, payload.as_slice())
+            .map_err(|_| anyhow!("Failed to encrypt Phantom payload"))?;
+
+        let request_id = new_request_id();
+        let mut url = Url::parse(&format!("{}/signTransaction", PHANTOM_URL))?;
+        url.query_pairs_mut()
+            .append_pair(
+                "dapp_encryption_public_key",
+                &bs58::encode(self.dapp_public_key.as_bytes()).into_string(),
+            )
+            .append_pair("nonce", &bs58::encode(nonce).into_string())
+            .append_pair(
+                "redirect_link",
+                &redirect_link(&self.redirect_url, &request_id)?,
+            )
+            .append_pair("payload", &bs58::encode(encrypted).into_string());
+
+        let response = self.callbacks.register(&request_id);
+        deliver(
+            &self.notifier,
+            &format!("Approve transaction: {}", summary),
+            &url,
+        )
+        .await;
+        let params = wait_for(&self.callbacks, &request_id, response, self.timeout).await?;
+
+        let data: SignTransactionData = decrypt(&self.shared, &params)?;
+        let signed: Transaction =
+            bincode::deserialize(&bs58::decode(data.transaction).into_vec()?)?;
+        if signed.message != tx.message {
+            return Err(anyhow!(
+                "Wallet returned a different transaction than requested"
+            ));
+        }
+        Ok(signed)
+    }
+}
+
+fn new_request_id() -> String {
+    format!("{:016x}", rand::random::<u64>())
+}
+
+fn redirect_link(redirect_url: &str, request_id: &str) -> Result<String> {
+    let mut url = Url::parse(redirect_url)?;
+    url.query_pairs_mut().append_pair("request", request_id);
+    Ok(url.to_string())
+}
+
+async fn deliver(notifier: &Option<TelegramNotifier>, title: &str, url: &Url) {
+    tracing::info!("{}: {}", title, url);
+    if let Some(notifier) = notifier {
+        if let Err(e) = notifier.send(&format!("🔐 {}\n{}", title, url)).await {
+            tracing::error!("Failed to send approval link: {:?}", e);
+        }
+    }
+}
+
+async fn wait_for(
+    callbacks: &CallbackServer,
+    request_id: &str,
+    response: tokio::sync::oneshot::Receiver<HashMap<String, String>>,
+    timeout: Duration,
+) -> Result<HashMap<String, String>> {
+    let params = match tokio::time::timeout(timeout, response).await {
+        Ok(Ok(params)) => params,
+        Ok(Err(_)) => return Err(anyhow!("Approval request {} dropped", request_id)),
+        Err(_) => {
+            callbacks.cancel(request_id);
+            return Err(anyhow!(
+                "Approval request {} timed out after {} s",
+                request_id,
+                timeout.as_secs()
+            ));
+        }
+    };
+    if let Some(code) = params.get("errorCode") {
+        return Err(anyhow!(
+            "Wallet rejected request {}: {} {}",
+            request_id,
+            code,
+            params.get("errorMessage").map_or("", String::as_str)
+        ));
+    }
+    Ok(params)
+}
+
+fn param<'a>(params: &'a HashMap<String, String>, name: &str) -> Result<&'a str> {
+    params
+        .get(name)
+        .map(String::as_str)
+        .ok_or_else(|| anyhow!("Wallet response is missing {}", name))
+}
+
+fn decode_fixed<const N: usize>(value: &str) -> Result<[u8; N]> {
+    bs58::decode(value)
+        .into_vec()?
+        .try_into()
+        .map_err(|v: Vec<u8>| anyhow!("Expected {} bytes, got {}", N, v.len()))
+}
+
+fn decrypt<T: DeserializeOwned>(shared: &SalsaBox, params: &HashMap<String, String>) -> Result<T> {
+    let nonce = decode_fixed::<24>(param(params, "nonce")?)?;
+    let data = bs58::decode(param(params, "data")?).into_vec()?;
+    let plain = shared
+        .decrypt(GenericArray::from_slice(&nonce), data.as_slice())
+        .map_err(|_| anyhow!("Failed to decrypt wallet response"))?;
+    Ok(serde_json::from_slice(&plain)?)
+}
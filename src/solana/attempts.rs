@@ -0,0 +1,368 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use listen_kit::signer::TransactionSigner;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig, hash::Hash, pubkey::Pubkey, signature::Signature,
+    transaction::Transaction,
+};
+use solana_transaction_status::TransactionStatus;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::time;
+
+use crate::solana::transaction::{
+    abandon_nonce, can_still_land, confirm_transaction, nonce_account_of,
+};
+use crate::trade::execution::Venue;
+
+const STATUS_POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// A blockhash is valid for ~60-90s, attempts still unresolved after that
+/// are given up on, and those on a durable nonce have it advanced
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(120);
+
+tokio::task_local! {
+    static ATTEMPTS: Arc<TxAttempts>;
+}
+
+/// A transaction sent for a buy or sell, not necessarily landed
+#[derive(Debug, Clone)]
+pub struct Attempt {
+    pub signature: Signature,
+    pub blockhash: Hash,
+    pub venue: Venue,
+    /// Durable nonce the transaction uses in place of a recent blockhash
+    pub nonce_account: Option<Pubkey>,
+    /// The signed transaction, to send again until it confirms. `None` for
+    /// signers sending their own copy and for transactions that must not
+    /// reach public RPC, like those of a Jito bundle.
+    pub transaction: Option<Transaction>,
+}
+
+/// Every transaction sent for one buy or sell, across resends and retries.
+/// Sends are recorded by [`AttemptTrackingSigner`] while the swap runs in
+/// [`track`](Self::track).
+#[derive(Debug, Default)]
+pub struct TxAttempts {
+    venue: Mutex<Option<Venue>>,
+    attempts: Mutex<Vec<Attempt>>,
+}
+
+impl TxAttempts {
+    /// Run `swap`, recording the transactions it sends
+    pub async fn track<F: Future>(self: &Arc<Self>, swap: F) -> F::Output {
+        ATTEMPTS.scope(Arc::clone(self), swap).await
+    }
+
+    fn set_venue(&self, venue: Venue) {
+        *self.venue.lock().unwrap() = Some(venue);
+    }
+
+    fn record(
+        &self,
+        signature: Signature,
+        blockhash: Hash,
+        nonce_account: Option<Pubkey>,
+        transaction: Option<Transaction>,
+    ) {
+        let Some(venue) = *self.venue.lock().unwrap() else {
+            tracing::warn!("Transaction {} sent outside of a swap", signature);
+            return;
+        };
+        let mut attempts = self.attempts.lock().unwrap();
+        if !attempts.iter().any(|a| a.signature == signature) {
+            attempts.push(Attempt {
+                signature,
+                blockhash,
+                venue,
+                nonce_account,
+                transaction,
+            });
+        }
+    }
+
+    pub fn attempts(&self) -> Vec<Attempt> {
+        self.attempts.lock().unwrap().clone()
+    }
+
+    /// Wait until the attempt `tx_sig` confirms, sending it again while its
+    /// blockhash is valid. Fails with
+    /// [`TxNotConfirmed`](crate::solana::transaction::TxNotConfirmed) when
+    /// it failed or expired. Untracked transactions are not waited for. A
+    /// nonce advanced when giving up pays `tip_lamports`.
+    pub async fn confirm(
+        &self,
+        rpc_client: &RpcClient,
+        tx_sig: &str,
+        tip_lamports: u64,
+    ) -> Result<()> {
+        let attempt = self
+            .attempts()
+            .into_iter()
+            .find(|attempt| attempt.signature.to_string() == tx_sig);
+        let Some(attempt) = attempt else {
+            tracing::debug!("Transaction {} was not tracked, not confirmed", tx_sig);
+            return Ok(());
+        };
+        confirm_transaction(
+            rpc_client,
+            &attempt.signature,
+            &attempt.blockhash,
+            attempt.nonce_account.as_ref(),
+            attempt.transaction.as_ref(),
+            tip_lamports,
+        )
+        .await
+    }
+
+    /// Wait until none of the attempts can still land, their transaction
+    /// either confirmed, failed or expired with its blockhash or nonce, and
+    /// return those that landed. Nonces of attempts still pending after
+    /// [`SETTLE_TIMEOUT`] are advanced, paying `tip_lamports`. Fails with
+    /// [`MayStillLand`] while one could still land, nothing may be sent
+    /// again for the swap then. Attempts whose status can't be read count as
+    /// pending.
+    pub async fn settle(&self, rpc_client: &RpcClient, tip_lamports: u64) -> Result<Vec<Attempt>> {
+        let started = Instant::now();
+        loop {
+            let (landed, pending) = self.check(rpc_client).await;
+            if pending.is_empty() {
+                return Ok(landed);
+            }
+            if started.elapsed() >= SETTLE_TIMEOUT {
+                return self.give_up(rpc_client, pending, tip_lamports).await;
+            }
+            time::sleep(STATUS_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Advance the nonces `pending` attempts were signed with, they can't
+    /// land once it moved, and check the attempts once more
+    async fn give_up(
+        &self,
+        rpc_client: &RpcClient,
+        pending: Vec<Attempt>,
+        tip_lamports: u64,
+    ) -> Result<Vec<Attempt>> {
+        let mut nonces: Vec<(Pubkey, Hash)> = pending
+            .iter()
+            .filter_map(|attempt| Some((attempt.nonce_account?, attempt.blockhash)))
+            .collect();
+        nonces.sort();
+        nonces.dedup();
+        if nonces.is_empty() {
+            return Err(may_still_land(&pending));
+        }
+        for (nonce_account, nonce) in nonces {
+            tracing::warn!(
+                "Attempts on nonce {} of {} still pending, advancing it",
+                nonce,
+                nonce_account
+            );
+            if let Err(e) = abandon_nonce(rpc_client, &nonce_account, &nonce, tip_lamports).await {
+                tracing::error!("Failed to advance the nonce of {}: {:?}", nonce_account, e);
+            }
+        }
+        let (landed, pending) = self.check(rpc_client).await;
+        if pending.is_empty() {
+            Ok(landed)
+        } else {
+            Err(may_still_land(&pending))
+        }
+    }
+
+    /// Read the status of every attempt and [sort them out](Self::sort_out)
+    async fn check(&self, rpc_client: &RpcClient) -> (Vec<Attempt>, Vec<Attempt>) {
+        let attempts = self.attempts();
+        if attempts.is_empty() {
+            return (Vec::new(), Vec::new());
+        }
+        let signatures: Vec<Signature> = attempts.iter().map(|a| a.signature).collect();
+        match rpc_client.get_signature_statuses(&signatures).await {
+            Ok(statuses) => Self::sort_out(rpc_client, attempts, statuses.value).await,
+            Err(e) => {
+                tracing::warn!("Could not read the status of {:?}: {:?}", signatures, e);
+                (Vec::new(), attempts)
+            }
+        }
+    }
+
+    /// The attempts that landed and those that can still land
+    async fn sort_out(
+        rpc_client: &RpcClient,
+        attempts: Vec<Attempt>,
+        statuses: Vec<Option<TransactionStatus>>,
+    ) -> (Vec<Attempt>, Vec<Attempt>) {
+        let mut landed = Vec::new();
+        let mut pending = Vec::new();
+        for (attempt, status) in attempts.into_iter().zip(statuses) {
+            match status {
+                Some(status) if status.err.is_some() => {}
+                Some(status) if status.satisfies_commitment(CommitmentConfig::confirmed()) => {
+                    landed.push(attempt)
+                }
+                _ => {
+                    let can_land = can_still_land(
+                        rpc_client,
+                        &attempt.blockhash,
+                        attempt.nonce_account.as_ref(),
+                    )
+                    .await
+                    .unwrap_or_else(|e| {
+                        tracing::warn!("Could not tell if {} can land: {:?}", attempt.signature, e);
+                        true
+                    });
+                    if can_land {
+                        pending.push(attempt);
+                    }
+                }
+            }
+        }
+        (landed, pending)
+    }
+}
+
+fn may_still_land(pending: &[Attempt]) -> anyhow::Error {
+    MayStillLand {
+        signatures: pending.iter().map(|attempt| attempt.signature).collect(),
+    }
+    .into()
+}
+
+/// A swap given up on while some of its transactions could still land,
+/// whatever it did may yet happen
+#[derive(Debug, Error)]
+#[error("transactions {:?} may still land after {:?}", .signatures, SETTLE_TIMEOUT)]
+pub struct MayStillLand {
+    pub signatures: Vec<Signature>,
+}
+
+/// Run a swap on `venue`, the transactions it sends are recorded with the
+/// venue when tracked
+pub async fn swap_on<F>(venue: Venue, swap: F) -> Result<(String, Venue)>
+where
+    F: Future<Output = Result<String>>,
+{
+    let _ = ATTEMPTS.try_with(|attempts| attempts.set_venue(venue));
+    swap.await.map(|tx_sig| (tx_sig, venue))
+}
+
+/// Tokens a duplicate fill added on top of the `received` of our buy, given
+/// the wallet balance before and after. Less than half a buy is taken for
+/// unrelated transfers rather than a second fill.
+pub fn double_buy_excess(held_before: u64, received: u64, held: u64) -> Option<u64> {
+    let excess = held.saturating_sub(held_before.saturating_add(received));
+    (excess > 0 && excess.saturating_mul(2) >= received).then_some(excess)
+}
+
+/// Signature and blockhash of a signed transaction
+fn sent_attempt(tx: &Transaction) -> Option<(Signature, Hash)> {
+    tx.signatures
+        .first()
+        .filter(|signature| **signature != Signature::default())
+        .map(|signature| (*signature, tx.message.recent_blockhash))
+}
+
+/// Records every transaction it sends in the [`TxAttempts`] of the calling
+/// task, failed sends included: a send that timed out may still land. Has
+/// to wrap the other signers to see what they signed. Signers that sign a
+/// copy of the transaction, like the manual approval, are only seen once
+/// their send succeeded.
+pub struct AttemptTrackingSigner {
+    inner: Arc<dyn TransactionSigner>,
+    resend: bool,
+}
+
+impl AttemptTrackingSigner {
+    pub fn new(inner: Arc<dyn TransactionSigner>) -> Self {
+        Self {
+            inner,
+            resend: true,
+        }
+    }
+
+    /// Keep the transactions `inner` sends off public RPC: they are
+    /// tracked but never sent again, for a signer sending privately like
+    /// the Jito bundle one
+    pub fn without_resends(mut self) -> Self {
+        self.resend = false;
+        self
+    }
+}
+
+#[async_trait]
+impl TransactionSigner for AttemptTrackingSigner {
+    fn pubkey(&self) -> String {
+        self.inner.pubkey()
+    }
+
+    async fn sign_and_send_solana_transaction(&self, tx: &mut Transaction) -> Result<String> {
+        let result = self.inner.sign_and_send_solana_transaction(tx).await;
+        if let Some((signature, blockhash)) = sent_attempt(tx) {
+            let nonce_account = nonce_account_of(tx);
+            let transaction = self.resend.then(|| tx.clone());
+            let _ = ATTEMPTS.try_with(|attempts| {
+                attempts.record(signature, blockhash, nonce_account, transaction)
+            });
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{
+        message::Message,
+        signature::{Keypair, Signer},
+        system_instruction,
+    };
+
+    #[tokio::test]
+    async fn test_records_signed_attempts() {
+        let payer = Keypair::new();
+        let message = Message::new(
+            &[system_instruction::transfer(
+                &payer.pubkey(),
+                &Keypair::new().pubkey(),
+                1,
+            )],
+            Some(&payer.pubkey()),
+        );
+        let mut tx = Transaction::new_unsigned(message);
+        assert!(sent_attempt(&tx).is_none());
+
+        let blockhash = Hash::new_unique();
+        tx.sign(&[&payer], blockhash);
+        let (signature, hash) = sent_attempt(&tx).unwrap();
+        assert_eq!(hash, blockhash);
+
+        let attempts = Arc::new(TxAttempts::default());
+        attempts
+            .track(async {
+                attempts.set_venue(Venue::Raydium);
+                ATTEMPTS.with(|a| a.record(signature, hash, None, Some(tx.clone())));
+                // A resend of the same transaction is one attempt
+                ATTEMPTS.with(|a| a.record(signature, hash, None, Some(tx.clone())));
+            })
+            .await;
+        let recorded = attempts.attempts();
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(recorded[0].signature, signature);
+        assert_eq!(recorded[0].venue, Venue::Raydium);
+        assert_eq!(recorded[0].transaction.as_ref(), Some(&tx));
+    }
+
+    #[test]
+    fn test_double_buy_excess() {
+        assert_eq!(double_buy_excess(0, 1_000, 1_000), None);
+        // Sold in the meantime
+        assert_eq!(double_buy_excess(500, 1_000, 1_200), None);
+        // The second fill got a worse price
+        assert_eq!(double_buy_excess(500, 1_000, 2_450), Some(950));
+        // Dust sent to the wallet is no double buy
+        assert_eq!(double_buy_excess(0, 1_000, 1_010), None);
+    }
+}
@@ -0,0 +1,238 @@
+use anyhow::{anyhow, Result};
+use futures::future::join_all;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+
+use crate::solana::dexscreener::PairInfo;
+use crate::solana::quoter::{jupiter_route, quote_swap, Side};
+use crate::solana::route::{detect_pool_program, sol_pairs, PoolProgram};
+use crate::trade::execution::Venue;
+
+/// Expected output of a swap through one pool
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolQuote {
+    pub pool: Pubkey,
+    pub program: PoolProgram,
+    /// Tokens for a buy, lamports for a sell
+    pub amount_out: u64,
+}
+
+impl PoolQuote {
+    pub fn venue(&self) -> Venue {
+        venue_of(self.program)
+    }
+}
+
+/// Venue trading through pools of `program`
+pub fn venue_of(program: PoolProgram) -> Venue {
+    match program {
+        PoolProgram::OrcaWhirlpool => Venue::Orca,
+        PoolProgram::MeteoraDlmm | PoolProgram::MeteoraAmm => Venue::Meteora,
+        _ => Venue::Raydium,
+    }
+}
+
+/// Highest output among `quotes`, ties go to the first
+pub fn best_quote(quotes: &[PoolQuote]) -> Option<&PoolQuote> {
+    quotes
+        .iter()
+        .rev()
+        .max_by_key(|quote| quote.amount_out)
+        .filter(|quote| quote.amount_out > 0)
+}
+
+/// How much worse `amount_out` is than `best`, in basis points
+pub fn shortfall_bps(best: u64, amount_out: u64) -> u64 {
+    if best == 0 {
+        return 0;
+    }
+    (best.saturating_sub(amount_out) as u128 * 10_000 / best as u128) as u64
+}
+
+/// Output of swapping `amount_in` through `pool`: lamports for tokens of
+/// `mint` on a buy, tokens for lamports on a sell. Quoted from the pool's
+/// current reserves or price, before slippage.
+pub async fn quote_pool(
+    rpc_client: &RpcClient,
+    pool: &Pubkey,
+    program: PoolProgram,
+    mint: &Pubkey,
+    amount_in: u64,
+    buy: bool,
+) -> Result<u64> {
+    let input_mint = if buy {
+        spl_token::native_mint::id()
+    } else {
+        *mint
+    };
+    Ok(
+        quote_swap(rpc_client, pool, program, &input_mint, amount_in)
+            .await?
+            .amount_out,
+    )
+}
+
+/// Output of the same swap routed by Jupiter, the benchmark the direct pools
+/// are compared against
+pub async fn jupiter_quote(quote_url: &str, mint: &str, amount_in: u64, buy: bool) -> Result<u64> {
+    let side = if buy { Side::Buy } else { Side::Sell };
+    let (estimate, _) = jupiter_route(quote_url, mint, amount_in, side).await?;
+    Ok(estimate.amount_out)
+}
+
+/// Where venue selection sends a swap
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Route {
+    Pool(Pubkey, PoolProgram),
+    /// Jupiter's route, through a pool we can't swap on directly or a
+    /// better split of the same pools
+    Jupiter,
+}
+
+/// Whether pools of `program` are compared: the ones swapped through
+/// directly and PumpSwap ones, which only Jupiter swaps through
+fn is_compared(program: PoolProgram) -> bool {
+    program.is_supported() || program == PoolProgram::PumpAmm
+}
+
+/// Venue of `mint` giving the most for `amount_in`. `pools` are quoted
+/// together with the SOL pairs of `pairs`; pairs with a bad address and
+/// pools that fail to quote are left out. Jupiter's route is quoted with
+/// `jupiter_quote_url`. With `jupiter_swaps` it takes the swap when it
+/// quotes more than every supported pool or when a PumpSwap pool does,
+/// otherwise it is only logged as a benchmark.
+#[allow(clippy::too_many_arguments)]
+pub async fn select_pool(
+    rpc_client: &RpcClient,
+    mint: &str,
+    pools: &[Pubkey],
+    pairs: &[PairInfo],
+    amount_in: u64,
+    buy: bool,
+    jupiter_quote_url: Option<&str>,
+    jupiter_swaps: bool,
+) -> Result<Route> {
+    let mint_key = Pubkey::from_str(mint)?;
+    let mut candidates = pools.to_vec();
+    for pair in sol_pairs(mint, pairs) {
+        match Pubkey::from_str(&pair.pair_address) {
+            Ok(pool) if !candidates.contains(&pool) => candidates.push(pool),
+            Ok(_) => {}
+            Err(e) => tracing::debug!("Pair {} of {} skipped: {}", pair.pair_address, mint, e),
+        }
+    }
+
+    let quotes = join_all(candidates.iter().map(|pool| async move {
+        let program = detect_pool_program(rpc_client, pool).await?;
+        if !is_compared(program) {
+            return Err(anyhow!("{} is not supported", program));
+        }
+        let amount_out = quote_pool(rpc_client, pool, program, &mint_key, amount_in, buy).await?;
+        Ok(PoolQuote {
+            pool: *pool,
+            program,
+            amount_out,
+        })
+    }))
+    .await;
+    let mut quoted = Vec::new();
+    for (pool, quote) in candidates.iter().zip(quotes) {
+        match quote {
+            Ok(quote) => quoted.push(quote),
+            Err(e) => tracing::debug!("Pool {} of {} not quoted: {:?}", pool, mint, e),
+        }
+    }
+    let best_any = best_quote(&quoted).cloned();
+    let direct: Vec<PoolQuote> = quoted
+        .iter()
+        .filter(|quote| quote.program.is_supported())
+        .cloned()
+        .collect();
+    let best = best_quote(&direct).cloned();
+
+    let side = if buy { "buy" } else { "sell" };
+    let best_amount_out = best.as_ref().map_or(0, |best| best.amount_out);
+    for quote in &quoted {
+        tracing::info!(
+            "Quote to {} {}: {} {} out on {} ({}){}",
+            side,
+            mint,
+            quote.amount_out,
+            if buy { "tokens" } else { "lamports" },
+            quote.pool,
+            quote.program,
+            match shortfall_bps(best_amount_out, quote.amount_out) {
+                0 => String::new(),
+                bps => format!(", {} bps worse", bps),
+            }
+        );
+    }
+    let mut jupiter_wins = jupiter_swaps
+        && best_any
+            .as_ref()
+            .is_some_and(|best_any| best_any.amount_out > best_amount_out);
+    if let Some(quote_url) = jupiter_quote_url {
+        match jupiter_quote(quote_url, mint, amount_in, buy).await {
+            Ok(jupiter) if jupiter > best_amount_out => {
+                jupiter_wins |= jupiter_swaps;
+                tracing::info!(
+                    "Jupiter routes the {} {} bps better ({}){}",
+                    side,
+                    shortfall_bps(jupiter, best_amount_out),
+                    jupiter,
+                    if jupiter_swaps { "" } else { ", not used" }
+                )
+            }
+            Ok(jupiter) => tracing::info!(
+                "Jupiter quotes {} for the {}, {} bps worse",
+                jupiter,
+                side,
+                shortfall_bps(best_amount_out, jupiter)
+            ),
+            Err(e) => tracing::warn!("Jupiter quote for {} failed: {:?}", mint, e),
+        }
+    }
+
+    if jupiter_wins {
+        tracing::info!("Best venue to {} {}: Jupiter", side, mint);
+        return Ok(Route::Jupiter);
+    }
+    let best = best.ok_or_else(|| anyhow!("No supported pool of {} could be quoted", mint))?;
+    tracing::info!(
+        "Best venue to {} {}: {} pool {}",
+        side,
+        mint,
+        best.program,
+        best.pool
+    );
+    Ok(Route::Pool(best.pool, best.program))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(program: PoolProgram, amount_out: u64) -> PoolQuote {
+        PoolQuote {
+            pool: Pubkey::new_unique(),
+            program,
+            amount_out,
+        }
+    }
+
+    #[test]
+    fn test_best_quote() {
+        let quotes = vec![
+            quote(PoolProgram::RaydiumAmmV4, 1_000),
+            quote(PoolProgram::OrcaWhirlpool, 1_200),
+            quote(PoolProgram::MeteoraDlmm, 1_200),
+        ];
+        let best = best_quote(&quotes).unwrap();
+        assert_eq!(best, &quotes[1]);
+        assert_eq!(best.venue(), Venue::Orca);
+        assert_eq!(shortfall_bps(1_200, 1_000), 1_666);
+
+        assert_eq!(best_quote(&[quote(PoolProgram::RaydiumCpmm, 0)]), None);
+    }
+}
@@ -0,0 +1,321 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use listen_kit::signer::TransactionSigner;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig, hash::Hash, signature::Signature, transaction::Transaction,
+};
+use std::collections::VecDeque;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tokio::time;
+
+use crate::config::BlockhashConfig;
+use crate::solana::rpc::make_rpc_client;
+use crate::solana::transaction::{nonce_account_of, with_durable_nonce, NoncePool};
+
+/// Target slot time of the cluster, used to tell the slot between polls
+pub const SLOT_TIME: Duration = Duration::from_millis(400);
+/// Blockhashes remembered with the slot they were fetched at
+const SEEN_HASHES: usize = 64;
+const SLOT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// ~4 s, well within the 150 blocks a blockhash is valid for
+pub const DEFAULT_REFRESH_SLOTS: u64 = 10;
+
+/// A blockhash with the slot it was fetched at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CachedBlockhash {
+    pub hash: Hash,
+    pub slot: u64,
+    pub last_valid_block_height: u64,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    /// Newest first
+    seen: VecDeque<CachedBlockhash>,
+    /// Latest slot observed and when
+    slot: Option<(u64, Instant)>,
+}
+
+impl CacheState {
+    fn current_slot(&self, now: Instant) -> Option<u64> {
+        self.slot.map(|(slot, at)| {
+            slot + (now.duration_since(at).as_millis() / SLOT_TIME.as_millis()) as u64
+        })
+    }
+
+    fn observe_slot(&mut self, slot: u64, now: Instant) {
+        if self.current_slot(now).is_none_or(|current| slot >= current) {
+            self.slot = Some((slot, now));
+        }
+    }
+
+    fn insert(&mut self, blockhash: CachedBlockhash, now: Instant) {
+        self.observe_slot(blockhash.slot, now);
+        if self
+            .seen
+            .front()
+            .is_some_and(|newest| newest.hash == blockhash.hash)
+        {
+            return;
+        }
+        self.seen.push_front(blockhash);
+        self.seen.truncate(SEEN_HASHES);
+    }
+
+    fn age_slots(&self, hash: &Hash, now: Instant) -> Option<u64> {
+        let fetched = self.seen.iter().find(|seen| seen.hash == *hash)?;
+        Some(self.current_slot(now)?.saturating_sub(fetched.slot))
+    }
+
+    /// The newest blockhash unless it's older than `max_age_slots`
+    fn fresh(&self, max_age_slots: u64, now: Instant) -> Option<CachedBlockhash> {
+        let newest = self.seen.front()?;
+        (self.age_slots(&newest.hash, now)? <= max_age_slots).then_some(*newest)
+    }
+}
+
+/// Recent blockhashes and the slots they were fetched at. The newest is
+/// handed out until the cluster moved `refresh_slots` past it, then a new
+/// one is fetched. With [`run_blockhash_refresher`] running that happens
+/// ahead of the next transaction.
+pub struct BlockhashCache {
+    state: Mutex<CacheState>,
+    refresh_slots: Mutex<u64>,
+}
+
+pub static BLOCKHASH_CACHE: LazyLock<BlockhashCache> =
+    LazyLock::new(|| BlockhashCache::new(DEFAULT_REFRESH_SLOTS));
+
+impl BlockhashCache {
+    pub fn new(refresh_slots: u64) -> Self {
+        Self {
+            state: Mutex::default(),
+            refresh_slots: Mutex::new(refresh_slots),
+        }
+    }
+
+    pub fn set_refresh_slots(&self, refresh_slots: u64) {
+        *self.refresh_slots.lock().unwrap() = refresh_slots;
+    }
+
+    fn refresh_slots(&self) -> u64 {
+        *self.refresh_slots.lock().unwrap()
+    }
+
+    /// Slots the cluster moved since `hash` was fetched, `None` for hashes
+    /// this cache didn't fetch
+    pub fn age_slots(&self, hash: &Hash) -> Option<u64> {
+        self.state.lock().unwrap().age_slots(hash, Instant::now())
+    }
+
+    pub fn observe_slot(&self, slot: u64) {
+        self.state
+            .lock()
+            .unwrap()
+            .observe_slot(slot, Instant::now());
+    }
+
+    /// Fetch the latest blockhash and the slot it is from
+    pub async fn refresh(&self, rpc_client: &RpcClient) -> Result<CachedBlockhash> {
+        let commitment = CommitmentConfig::confirmed();
+        let (hash, last_valid_block_height) = rpc_client
+            .get_latest_blockhash_with_commitment(commitment)
+            .await?;
+        let slot = rpc_client.get_slot_with_commitment(commitment).await?;
+        let blockhash = CachedBlockhash {
+            hash,
+            slot,
+            last_valid_block_height,
+        };
+        self.state.lock().unwrap().insert(blockhash, Instant::now());
+        tracing::debug!("Blockhash {} at slot {}", hash, slot);
+        Ok(blockhash)
+    }
+
+    /// The cached blockhash while it's fresh, a new one otherwise
+    pub async fn get(&self, rpc_client: &RpcClient) -> Result<CachedBlockhash> {
+        let cached = self
+            .state
+            .lock()
+            .unwrap()
+            .fresh(self.refresh_slots(), Instant::now());
+        match cached {
+            Some(cached) => Ok(cached),
+            None => self.refresh(rpc_client).await,
+        }
+    }
+}
+
+/// Follow the slot and fetch a new blockhash as soon as the cached one is
+/// `refresh_slots` old, until `shutdown` flips
+pub async fn run_blockhash_refresher(config: BlockhashConfig, mut shutdown: watch::Receiver<bool>) {
+    BLOCKHASH_CACHE.set_refresh_slots(config.refresh_slots);
+    let rpc_client = make_rpc_client();
+    let mut interval = time::interval(SLOT_POLL_INTERVAL);
+    tracing::info!("Blockhash refresher started");
+    loop {
+        tokio::select! {
+            biased;
+            _ = shutdown.changed() => {
+                tracing::info!("Blockhash refresher stopped");
+                return;
+            }
+            _ = interval.tick() => {}
+        }
+        match rpc_client
+            .get_slot_with_commitment(CommitmentConfig::confirmed())
+            .await
+        {
+            Ok(slot) => BLOCKHASH_CACHE.observe_slot(slot),
+            Err(e) => {
+                tracing::warn!("Failed to get the slot: {:?}", e);
+                continue;
+            }
+        }
+        if let Err(e) = BLOCKHASH_CACHE.get(&rpc_client).await {
+            tracing::warn!("Failed to refresh the blockhash: {:?}", e);
+        }
+    }
+}
+
+/// Signer that won't sign with a blockhash older than `max_age_slots`, or
+/// one of unknown age: the transaction gets a fresh blockhash from
+/// [`BLOCKHASH_CACHE`] first. Transactions already partly signed can't be
+/// restamped and are refused. Wraps the signer that sends, below the
+/// approval and policy signers, so the check happens right before sending.
+///
+/// With a [`NoncePool`] unsigned transactions are rebuilt on a durable nonce
+/// instead, and don't expire while the RPC is struggling to land them. They
+/// keep a blockhash while every nonce account is in use. Transactions
+/// advancing a nonce themselves are sent as they are.
+pub struct FreshBlockhashSigner {
+    inner: Arc<dyn TransactionSigner>,
+    max_age_slots: u64,
+    nonces: Option<NoncePool>,
+}
+
+impl FreshBlockhashSigner {
+    pub fn new(inner: Arc<dyn TransactionSigner>, config: &BlockhashConfig) -> Self {
+        BLOCKHASH_CACHE.set_refresh_slots(config.refresh_slots);
+        Self {
+            inner,
+            max_age_slots: config.max_age_slots,
+            nonces: None,
+        }
+    }
+
+    pub fn with_nonces(mut self, nonces: NoncePool) -> Self {
+        self.nonces = Some(nonces);
+        self
+    }
+
+    /// `tx` on the nonce of an account of the pool no other transaction
+    /// uses, `None` to stay with a blockhash
+    async fn durable(&self, nonces: &NoncePool, tx: &Transaction) -> Option<Transaction> {
+        let (nonce_account, nonce) = match nonces.lease(&make_rpc_client()).await {
+            Ok(Some(leased)) => leased,
+            Ok(None) => {
+                tracing::warn!("Every nonce account is in use, using a blockhash");
+                return None;
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "Failed to read the nonce accounts, using a blockhash: {:?}",
+                    e
+                );
+                return None;
+            }
+        };
+        let durable = with_durable_nonce(tx, &nonce_account, nonces.authority(), nonce);
+        if durable.is_none() {
+            tracing::debug!("Transaction too large for a durable nonce");
+            nonces.release(&nonce_account);
+        }
+        durable
+    }
+}
+
+#[async_trait]
+impl TransactionSigner for FreshBlockhashSigner {
+    fn pubkey(&self) -> String {
+        self.inner.pubkey()
+    }
+
+    async fn sign_and_send_solana_transaction(&self, tx: &mut Transaction) -> Result<String> {
+        if nonce_account_of(tx).is_some() {
+            return self.inner.sign_and_send_solana_transaction(tx).await;
+        }
+        let unsigned = tx.signatures.iter().all(|sig| *sig == Signature::default());
+        if let (Some(nonces), true) = (&self.nonces, unsigned) {
+            if let Some(durable) = self.durable(nonces, tx).await {
+                *tx = durable;
+                return self.inner.sign_and_send_solana_transaction(tx).await;
+            }
+        }
+
+        let blockhash = tx.message.recent_blockhash;
+        let age = BLOCKHASH_CACHE.age_slots(&blockhash);
+        if age.is_none_or(|age| age > self.max_age_slots) {
+            if tx.signatures.iter().any(|sig| *sig != Signature::default()) {
+                return Err(anyhow!(
+                    "Blockhash {} is {} and the transaction is already signed",
+                    blockhash,
+                    age.map_or("of unknown age".to_string(), |age| format!(
+                        "{} slots old",
+                        age
+                    ))
+                ));
+            }
+            let fresh = BLOCKHASH_CACHE.get(&make_rpc_client()).await?;
+            tracing::debug!(
+                "Blockhash {} ({:?} slots old) replaced by {}",
+                blockhash,
+                age,
+                fresh.hash
+            );
+            tx.message.recent_blockhash = fresh.hash;
+        }
+        self.inner.sign_and_send_solana_transaction(tx).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blockhash(slot: u64) -> CachedBlockhash {
+        CachedBlockhash {
+            hash: Hash::new_unique(),
+            slot,
+            last_valid_block_height: slot + 150,
+        }
+    }
+
+    #[test]
+    fn test_blockhash_age() {
+        let now = Instant::now();
+        let mut state = CacheState::default();
+        let old = blockhash(100);
+        state.insert(old, now);
+        assert_eq!(state.age_slots(&old.hash, now), Some(0));
+        assert_eq!(state.fresh(10, now), Some(old));
+
+        // Slots observed move the age along, and so does time between them
+        state.observe_slot(108, now);
+        assert_eq!(state.age_slots(&old.hash, now), Some(8));
+        assert_eq!(state.age_slots(&old.hash, now + SLOT_TIME * 5), Some(13));
+        assert_eq!(state.fresh(10, now + SLOT_TIME * 5), None);
+        // An older slot reported by a lagging node doesn't move it back
+        state.observe_slot(104, now);
+        assert_eq!(state.age_slots(&old.hash, now), Some(8));
+
+        let new = blockhash(120);
+        state.insert(new, now);
+        assert_eq!(state.fresh(10, now), Some(new));
+        assert_eq!(state.age_slots(&old.hash, now), Some(20));
+        assert_eq!(state.age_slots(&Hash::new_unique(), now), None);
+    }
+}
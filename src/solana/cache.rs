@@ -0,0 +1,272 @@
+use anyhow::{anyhow, Result};
+use listen_kit::solana::pump::{fetch_metadata, PumpTokenInfo};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::solana::devnet;
+use crate::solana::dexscreener::{search_ticker, DexScreenerResponse};
+
+const CAPACITY: usize = 1_000;
+/// Pump status flips once a curve completes, so entries stay short-lived
+const TTL: Duration = Duration::from_secs(30);
+const NEGATIVE_TTL: Duration = Duration::from_secs(10);
+
+static PUMP_METADATA: LazyLock<TtlCache<PumpTokenInfo>> =
+    LazyLock::new(|| TtlCache::new(CAPACITY, TTL, NEGATIVE_TTL));
+static DEXSCREENER: LazyLock<TtlCache<DexScreenerResponse>> =
+    LazyLock::new(|| TtlCache::new(CAPACITY, TTL, NEGATIVE_TTL));
+
+/// [`fetch_metadata`] shared by every caller in the process. Failures are
+/// cached too, tokens that aren't on pump.fun are asked about on every buy,
+/// sell and price check otherwise.
+pub async fn cached_fetch_metadata(mint: &Pubkey) -> Result<PumpTokenInfo> {
+    // The pump.fun API only knows mainnet, devnet tokens go to DexScreener
+    if devnet::is_enabled() {
+        return Err(anyhow!("pump.fun metadata is not available on devnet"));
+    }
+    PUMP_METADATA
+        .get_or_fetch(&mint.to_string(), fetch_metadata(mint))
+        .await
+}
+
+/// [`search_ticker`] shared by every caller in the process
+pub async fn cached_search_ticker(query: &str) -> Result<DexScreenerResponse> {
+    DEXSCREENER
+        .get_or_fetch(query, search_ticker(query.to_string()))
+        .await
+}
+
+struct Entry<V> {
+    value: Result<V, String>,
+    expires_at: Instant,
+    last_used: u64,
+}
+
+struct State<V> {
+    entries: HashMap<String, Entry<V>>,
+    clock: u64,
+}
+
+/// Least-recently-used cache whose entries also expire, with a separate TTL
+/// for failed lookups
+pub struct TtlCache<V> {
+    state: Mutex<State<V>>,
+    /// Keys being fetched by [`TtlCache::get_or_fetch`], later misses wait
+    /// for that fetch instead of starting their own
+    in_flight: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+    capacity: usize,
+    ttl: Duration,
+    negative_ttl: Duration,
+}
+
+impl<V: Clone> TtlCache<V> {
+    pub fn new(capacity: usize, ttl: Duration, negative_ttl: Duration) -> Self {
+        Self {
+            state: Mutex::new(State {
+                entries: HashMap::new(),
+                clock: 0,
+            }),
+            in_flight: Mutex::new(HashMap::new()),
+            capacity,
+            ttl,
+            negative_ttl,
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<Result<V, String>> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.clock += 1;
+        let clock = state.clock;
+        let entry = state.entries.get_mut(key)?;
+        if entry.expires_at <= Instant::now() {
+            state.entries.remove(key);
+            return None;
+        }
+        entry.last_used = clock;
+        Some(entry.value.clone())
+    }
+
+    fn insert(&self, key: &str, value: Result<V, String>) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.clock += 1;
+        if state.entries.len() >= self.capacity && !state.entries.contains_key(key) {
+            if let Some(oldest) = state
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            {
+                state.entries.remove(&oldest);
+            }
+        }
+        let ttl = if value.is_ok() {
+            self.ttl
+        } else {
+            self.negative_ttl
+        };
+        let entry = Entry {
+            value,
+            expires_at: Instant::now() + ttl,
+            last_used: state.clock,
+        };
+        state.entries.insert(key.to_string(), entry);
+    }
+
+    /// Cached value of `key`, awaiting `fetch` only on a miss. Concurrent
+    /// misses of a key share one fetch, the others are served its result.
+    pub async fn get_or_fetch(
+        &self,
+        key: &str,
+        fetch: impl Future<Output = Result<V>>,
+    ) -> Result<V> {
+        if let Some(value) = self.get(key) {
+            return value.map_err(|e| anyhow!(e));
+        }
+        let lock = Arc::clone(
+            self.in_flight
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .entry(key.to_string())
+                .or_default(),
+        );
+        let guard = lock.lock().await;
+        // Fetched while waiting for the lock
+        let value = match self.get(key) {
+            Some(value) => value,
+            None => {
+                let value = fetch.await.map_err(|e| format!("{:#}", e));
+                self.insert(key, value.clone());
+                value
+            }
+        };
+        drop(guard);
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        // Only the map and this call hold it, nobody else is waiting
+        if Arc::strong_count(&lock) == 2 {
+            in_flight.remove(key);
+        }
+        value.map_err(|e| anyhow!(e))
+    }
+
+    /// Cached values of `keys` in their order, the misses awaited together
+    /// from `fetch`, which returns one result per key it's given. Batches
+    /// aren't single-flight: concurrent ones may fetch the same misses,
+    /// which costs a duplicate request but no wrong result.
+    pub async fn get_or_fetch_many<F, Fut>(&self, keys: &[String], fetch: F) -> Vec<Result<V>>
+    where
+        F: FnOnce(Vec<String>) -> Fut,
+        Fut: Future<Output = Vec<Result<V>>>,
+    {
+        let mut values: Vec<Option<Result<V, String>>> =
+            keys.iter().map(|key| self.get(key)).collect();
+        let misses: Vec<String> = keys
+            .iter()
+            .zip(&values)
+            .filter(|(_, value)| value.is_none())
+            .map(|(key, _)| key.clone())
+            .collect();
+        if !misses.is_empty() {
+            let mut fetched = fetch(misses).await.into_iter();
+            for (key, value) in keys.iter().zip(values.iter_mut()) {
+                if value.is_some() {
+                    continue;
+                }
+                let result = fetched
+                    .next()
+                    .unwrap_or_else(|| Err(anyhow!("Nothing fetched for {}", key)))
+                    .map_err(|e| format!("{:#}", e));
+                self.insert(key, result.clone());
+                *value = Some(result);
+            }
+        }
+        values
+            .into_iter()
+            .map(|value| {
+                value
+                    .expect("every key is cached or fetched")
+                    .map_err(|e| anyhow!(e))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_hits_and_negative_results() {
+        let cache = TtlCache::new(10, TTL, NEGATIVE_TTL);
+        assert_eq!(cache.get_or_fetch("a", async { Ok(1) }).await.unwrap(), 1);
+        // Served from the cache, the fetch isn't awaited
+        assert_eq!(cache.get_or_fetch("a", async { Ok(2) }).await.unwrap(), 1);
+
+        assert!(cache
+            .get_or_fetch("b", async { Err(anyhow!("not found")) })
+            .await
+            .is_err());
+        assert!(cache.get_or_fetch("b", async { Ok(3) }).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_misses_fetch_once() {
+        let cache = TtlCache::new(10, TTL, NEGATIVE_TTL);
+        let fetches = std::sync::atomic::AtomicUsize::new(0);
+        let fetch = || async {
+            fetches.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            tokio::task::yield_now().await;
+            Ok(1)
+        };
+        let (a, b) = tokio::join!(
+            cache.get_or_fetch("a", fetch()),
+            cache.get_or_fetch("a", fetch())
+        );
+        assert_eq!(a.unwrap(), 1);
+        assert_eq!(b.unwrap(), 1);
+        assert_eq!(fetches.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert!(cache.in_flight.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_expiry_and_eviction() {
+        let expired = TtlCache::new(10, Duration::ZERO, Duration::ZERO);
+        expired.get_or_fetch("a", async { Ok(1) }).await.unwrap();
+        assert_eq!(expired.get_or_fetch("a", async { Ok(2) }).await.unwrap(), 2);
+
+        let cache = TtlCache::new(2, TTL, NEGATIVE_TTL);
+        cache.get_or_fetch("a", async { Ok(1) }).await.unwrap();
+        cache.get_or_fetch("b", async { Ok(2) }).await.unwrap();
+        // Touch "a" so "b" is the least recently used
+        cache.get_or_fetch("a", async { Ok(0) }).await.unwrap();
+        cache.get_or_fetch("c", async { Ok(3) }).await.unwrap();
+        assert_eq!(cache.get_or_fetch("a", async { Ok(0) }).await.unwrap(), 1);
+        assert_eq!(cache.get_or_fetch("b", async { Ok(0) }).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_many() {
+        let cache = TtlCache::new(10, TTL, NEGATIVE_TTL);
+        cache.get_or_fetch("a", async { Ok(1) }).await.unwrap();
+        let keys = ["a", "b", "c"].map(String::from);
+        let values = cache
+            .get_or_fetch_many(&keys, |misses| async move {
+                // Only the misses are fetched, in one call
+                assert_eq!(misses, vec!["b".to_string(), "c".to_string()]);
+                vec![Ok(2), Err(anyhow!("not found"))]
+            })
+            .await;
+        assert_eq!(values[0].as_ref().unwrap(), &1);
+        assert_eq!(values[1].as_ref().unwrap(), &2);
+        assert!(values[2].is_err());
+
+        // All cached now, failures included
+        let values = cache
+            .get_or_fetch_many(&keys, |_| async { panic!("nothing to fetch") })
+            .await;
+        assert_eq!(values.len(), 3);
+        assert!(values[2].is_err());
+    }
+}
@@ -0,0 +1,128 @@
+use solana_sdk::compute_budget::{self, ComputeBudgetInstruction};
+use solana_sdk::instruction::Instruction;
+
+use crate::config::ComputeBudgetConfig;
+use crate::solana::transaction::TxBuilder;
+use crate::trade::execution::Venue;
+
+/// Most compute units a transaction may request
+pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+/// Compute unit limit of a swap: what its simulation consumed plus
+/// `margin_pct`, or the venue's `preset` when the node didn't report it
+pub fn unit_limit(preset: u32, simulated: Option<u64>, margin_pct: u32) -> u32 {
+    let Some(units) = simulated else {
+        return preset;
+    };
+    let limit = units.saturating_mul(100 + margin_pct as u64) / 100;
+    limit.min(MAX_COMPUTE_UNIT_LIMIT as u64) as u32
+}
+
+/// Whether `ix` is a compute budget instruction of the same kind as
+/// `other`, both setting the unit price say
+fn same_budget_kind(ix: &Instruction, other: &Instruction) -> bool {
+    ix.program_id == compute_budget::id()
+        && other.program_id == compute_budget::id()
+        && ix.data.first() == other.data.first()
+}
+
+/// `ixs` of a swap on `venue` with an explicit compute unit limit in front,
+/// and the unit price of `CU_PRICE_MICRO_LAMPORTS` when set. Compute budget
+/// instructions the builders added are replaced by those, a transaction may
+/// only carry one of each; the others, like the builder's priority fee when
+/// none is configured, are kept. Without a config `ixs` are left as they
+/// are.
+pub fn with_compute_budget(
+    config: Option<&ComputeBudgetConfig>,
+    venue: Venue,
+    buy: bool,
+    simulated: Option<u64>,
+    ixs: Vec<Instruction>,
+) -> Vec<Instruction> {
+    let Some(config) = config else {
+        return ixs;
+    };
+    let preset = config.preset(venue, buy);
+    let limit = unit_limit(preset, simulated, config.unit_limit_margin_pct);
+    if simulated.is_some_and(|units| units > preset as u64) {
+        tracing::warn!(
+            "{} {} consumed {:?} compute units, above its preset of {}",
+            venue,
+            if buy { "buy" } else { "sell" },
+            simulated,
+            preset
+        );
+    }
+
+    let mut budget = vec![ComputeBudgetInstruction::set_compute_unit_limit(limit)];
+    if config.unit_price_micro_lamports > 0 {
+        budget.push(ComputeBudgetInstruction::set_compute_unit_price(
+            config.unit_price_micro_lamports,
+        ));
+    }
+    let kept: Vec<Instruction> = ixs
+        .iter()
+        .filter(|ix| ix.program_id == compute_budget::id())
+        .filter(|ix| !budget.iter().any(|set| same_budget_kind(ix, set)))
+        .cloned()
+        .collect();
+    budget.extend(kept);
+    TxBuilder::new(ixs).compute_budget(budget).build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::pubkey::Pubkey;
+
+    #[test]
+    fn test_unit_limit() {
+        assert_eq!(unit_limit(120_000, None, 20), 120_000);
+        assert_eq!(unit_limit(120_000, Some(50_000), 20), 60_000);
+        assert_eq!(
+            unit_limit(120_000, Some(1_300_000), 20),
+            MAX_COMPUTE_UNIT_LIMIT
+        );
+    }
+
+    #[test]
+    fn test_keeps_the_builders_price_unless_configured() {
+        let mut config = ComputeBudgetConfig {
+            unit_price_micro_lamports: 0,
+            unit_limit_margin_pct: 20,
+            pump_buy_units: 120_000,
+            pump_sell_units: 80_000,
+            raydium_units: 150_000,
+            orca_units: 200_000,
+            meteora_units: 250_000,
+            jupiter_units: 400_000,
+        };
+        let swap = Instruction::new_with_bytes(Pubkey::new_unique(), &[1], vec![]);
+        let ixs = vec![
+            ComputeBudgetInstruction::set_compute_unit_limit(300_000),
+            ComputeBudgetInstruction::set_compute_unit_price(50_000),
+            swap.clone(),
+        ];
+
+        let built = with_compute_budget(Some(&config), Venue::Raydium, true, None, ixs.clone());
+        assert_eq!(
+            built,
+            vec![
+                ComputeBudgetInstruction::set_compute_unit_limit(150_000),
+                ComputeBudgetInstruction::set_compute_unit_price(50_000),
+                swap.clone(),
+            ]
+        );
+
+        config.unit_price_micro_lamports = 10_000;
+        let built = with_compute_budget(Some(&config), Venue::Raydium, true, None, ixs);
+        assert_eq!(
+            built,
+            vec![
+                ComputeBudgetInstruction::set_compute_unit_limit(150_000),
+                ComputeBudgetInstruction::set_compute_unit_price(10_000),
+                swap,
+            ]
+        );
+    }
+}
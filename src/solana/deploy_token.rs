@@ -0,0 +1,308 @@
+use anyhow::{anyhow, Result};
+use listen_kit::signer::SignerContext;
+use reqwest::multipart::{Form, Part};
+use serde::Deserialize;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_program, sysvar,
+    transaction::Transaction,
+};
+use spl_associated_token_account::{
+    get_associated_token_address, instruction::create_associated_token_account_idempotent,
+};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::config::ComputeBudgetConfig;
+use crate::solana::blockhash::BLOCKHASH_CACHE;
+use crate::solana::compute_budget::with_compute_budget;
+use crate::solana::quoter::{bonding_curve_swap, Side};
+use crate::solana::rpc::make_rpc_client;
+use crate::solana::transaction::simulate_instructions;
+use crate::solana::ws::{bonding_curve_address, PUMP_PROGRAM};
+use crate::trade::execution::Venue;
+
+const PUMP_IPFS_URL: &str = "https://pump.fun/api/ipfs";
+const PUMP_FEE_RECIPIENT: &str = "CebN5WGQ4jvEPvsVU4EoHEpgzq1VV7AbicfhtW4xC9iM";
+const PUMP_FEE_PROGRAM: &str = "pfeeUxB6jkeY1Hxd7CsFCAjcbHA9rWtchMGdZ6VojVZ";
+const TOKEN_METADATA_PROGRAM: &str = "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s";
+/// Anchor discriminators of the bonding curve program's `create` and `buy`
+const PUMP_CREATE: [u8; 8] = [24, 30, 200, 40, 5, 28, 7, 119];
+const PUMP_BUY: [u8; 8] = [102, 6, 61, 18, 1, 218, 235, 234];
+/// Reserves every new bonding curve starts from
+pub const INITIAL_VIRTUAL_TOKEN_RESERVES: u64 = 1_073_000_000_000_000;
+pub const INITIAL_VIRTUAL_SOL_RESERVES: u64 = 30_000_000_000;
+pub const INITIAL_REAL_TOKEN_RESERVES: u64 = 793_100_000_000_000;
+/// Headroom of the dev buy's max SOL cost over the quote, only rounding
+/// can move the price of a curve nobody else traded yet
+const DEV_BUY_SLACK_BPS: u64 = 50;
+
+/// Metadata of a token to launch on pump.fun
+#[derive(Debug, Clone)]
+pub struct DeployTokenParams {
+    pub name: String,
+    pub symbol: String,
+    pub description: String,
+    pub twitter: Option<String>,
+    pub telegram: Option<String>,
+    pub website: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IpfsUpload {
+    metadata_uri: String,
+}
+
+/// Upload the image and metadata of a token to pump.fun's IPFS endpoint and
+/// return the metadata URI
+pub async fn upload_metadata(params: &DeployTokenParams, image: &Path) -> Result<String> {
+    let file_name = image
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("image")
+        .to_string();
+    let bytes =
+        std::fs::read(image).map_err(|e| anyhow!("Failed to read {}: {}", image.display(), e))?;
+    let mime = mime_guess::from_path(image).first_or_octet_stream();
+    let mut form = Form::new()
+        .part(
+            "file",
+            Part::bytes(bytes)
+                .file_name(file_name)
+                .mime_str(mime.as_ref())?,
+        )
+        .text("name", params.name.clone())
+        .text("symbol", params.symbol.clone())
+        .text("description", params.description.clone())
+        .text("showName", "true");
+    for (field, value) in [
+        ("twitter", &params.twitter),
+        ("telegram", &params.telegram),
+        ("website", &params.website),
+    ] {
+        if let Some(value) = value {
+            form = form.text(field, value.clone());
+        }
+    }
+
+    let response = reqwest::Client::new()
+        .post(PUMP_IPFS_URL)
+        .multipart(form)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!(
+            "Metadata upload failed: {} {}",
+            response.status(),
+            response.text().await.unwrap_or_default()
+        ));
+    }
+    Ok(response.json::<IpfsUpload>().await?.metadata_uri)
+}
+
+fn pump_pda(seeds: &[&[u8]]) -> Result<Pubkey> {
+    Ok(Pubkey::find_program_address(seeds, &Pubkey::from_str(PUMP_PROGRAM)?).0)
+}
+
+fn borsh_string(data: &mut Vec<u8>, value: &str) {
+    data.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    data.extend_from_slice(value.as_bytes());
+}
+
+/// `create` of a bonding curve for `mint`, with `creator` as the wallet
+/// earning its creator fees
+pub fn create_pump_token_ix(
+    mint: &Pubkey,
+    creator: &Pubkey,
+    name: &str,
+    symbol: &str,
+    uri: &str,
+) -> Result<Instruction> {
+    let program = Pubkey::from_str(PUMP_PROGRAM)?;
+    let metadata_program = Pubkey::from_str(TOKEN_METADATA_PROGRAM)?;
+    let bonding_curve = bonding_curve_address(mint)?;
+    let (metadata, _) = Pubkey::find_program_address(
+        &[b"metadata", metadata_program.as_ref(), mint.as_ref()],
+        &metadata_program,
+    );
+
+    let mut data = PUMP_CREATE.to_vec();
+    borsh_string(&mut data, name);
+    borsh_string(&mut data, symbol);
+    borsh_string(&mut data, uri);
+    data.extend_from_slice(creator.as_ref());
+
+    Ok(Instruction {
+        program_id: program,
+        accounts: vec![
+            AccountMeta::new(*mint, true),
+            AccountMeta::new_readonly(pump_pda(&[b"mint-authority"])?, false),
+            AccountMeta::new(bonding_curve, false),
+            AccountMeta::new(get_associated_token_address(&bonding_curve, mint), false),
+            AccountMeta::new_readonly(pump_pda(&[b"global"])?, false),
+            AccountMeta::new_readonly(metadata_program, false),
+            AccountMeta::new(metadata, false),
+            AccountMeta::new(*creator, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(pump_pda(&[b"__event_authority"])?, false),
+            AccountMeta::new_readonly(program, false),
+        ],
+        data,
+    })
+}
+
+/// `buy` of `token_amount` tokens of a curve created by `creator`, spending
+/// at most `max_sol_cost` lamports. The user's token account must exist.
+pub fn buy_pump_token_ix(
+    mint: &Pubkey,
+    creator: &Pubkey,
+    user: &Pubkey,
+    token_amount: u64,
+    max_sol_cost: u64,
+) -> Result<Instruction> {
+    let program = Pubkey::from_str(PUMP_PROGRAM)?;
+    let fee_program = Pubkey::from_str(PUMP_FEE_PROGRAM)?;
+    let bonding_curve = bonding_curve_address(mint)?;
+    let (fee_config, _) =
+        Pubkey::find_program_address(&[b"fee_config", program.as_ref()], &fee_program);
+
+    let mut data = PUMP_BUY.to_vec();
+    data.extend_from_slice(&token_amount.to_le_bytes());
+    data.extend_from_slice(&max_sol_cost.to_le_bytes());
+
+    Ok(Instruction {
+        program_id: program,
+        accounts: vec![
+            AccountMeta::new_readonly(pump_pda(&[b"global"])?, false),
+            AccountMeta::new(Pubkey::from_str(PUMP_FEE_RECIPIENT)?, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new(bonding_curve, false),
+            AccountMeta::new(get_associated_token_address(&bonding_curve, mint), false),
+            AccountMeta::new(get_associated_token_address(user, mint), false),
+            AccountMeta::new(*user, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(pump_pda(&[b"creator-vault", creator.as_ref()])?, false),
+            AccountMeta::new_readonly(pump_pda(&[b"__event_authority"])?, false),
+            AccountMeta::new_readonly(program, false),
+            AccountMeta::new(pump_pda(&[b"global_volume_accumulator"])?, false),
+            AccountMeta::new(
+                pump_pda(&[b"user_volume_accumulator", user.as_ref()])?,
+                false,
+            ),
+            AccountMeta::new_readonly(fee_config, false),
+            AccountMeta::new_readonly(fee_program, false),
+        ],
+        data,
+    })
+}
+
+/// Tokens the first buy of `sol_lamports` (fee included) gets out of a new
+/// curve, and the max SOL cost to allow for it
+pub fn dev_buy_amounts(sol_lamports: u64) -> (u64, u64) {
+    let tokens = bonding_curve_swap(
+        INITIAL_VIRTUAL_TOKEN_RESERVES,
+        INITIAL_VIRTUAL_SOL_RESERVES,
+        sol_lamports,
+        Side::Buy,
+    )
+    .amount_out
+    .min(INITIAL_REAL_TOKEN_RESERVES);
+    let max_sol_cost = sol_lamports + sol_lamports * DEV_BUY_SLACK_BPS / 10_000;
+    (tokens, max_sol_cost)
+}
+
+/// Create the token of `uri` on pump.fun from the signer wallet and buy
+/// `dev_buy_lamports` worth of it in the same transaction. The mint is a
+/// fresh keypair that signs the transaction before it's handed to the
+/// signer, which has to sign against the transaction's blockhash to keep
+/// that signature. Returns the mint and the transaction signature.
+pub async fn deploy_pump_token(
+    params: &DeployTokenParams,
+    uri: &str,
+    dev_buy_lamports: u64,
+    compute_budget: Option<&ComputeBudgetConfig>,
+) -> Result<(Pubkey, String)> {
+    let signer = SignerContext::current().await;
+    let owner = Pubkey::from_str(&signer.pubkey())?;
+    let mint = Keypair::new();
+    let mint_pubkey = mint.pubkey();
+
+    let mut ixs = vec![create_pump_token_ix(
+        &mint_pubkey,
+        &owner,
+        &params.name,
+        &params.symbol,
+        uri,
+    )?];
+    if dev_buy_lamports > 0 {
+        let (tokens, max_sol_cost) = dev_buy_amounts(dev_buy_lamports);
+        ixs.push(create_associated_token_account_idempotent(
+            &owner,
+            &owner,
+            &mint_pubkey,
+            &spl_token::id(),
+        ));
+        ixs.push(buy_pump_token_ix(
+            &mint_pubkey,
+            &owner,
+            &owner,
+            tokens,
+            max_sol_cost,
+        )?);
+    }
+
+    let rpc_client = make_rpc_client();
+    // Failures are left to the send, the simulation can't sign for the mint
+    let units = simulate_instructions(&rpc_client, &owner, &ixs)
+        .await
+        .unwrap_or(None);
+    let ixs = with_compute_budget(compute_budget, Venue::PumpFun, true, units, ixs);
+
+    let blockhash = BLOCKHASH_CACHE.get(&rpc_client).await?.hash;
+    let mut tx = Transaction::new_with_payer(&ixs, Some(&owner));
+    tx.try_partial_sign(&[&mint], blockhash)?;
+    let tx_sig = signer.sign_and_send_solana_transaction(&mut tx).await?;
+    Ok((mint_pubkey, tx_sig))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dev_buy_amounts() {
+        let (tokens, max_sol_cost) = dev_buy_amounts(1_000_000_000);
+        // ~0.99 SOL into 30 SOL of virtual reserves buys ~3.2% of the supply
+        assert!(tokens > 34_000_000_000_000 && tokens < 35_000_000_000_000);
+        assert_eq!(max_sol_cost, 1_005_000_000);
+
+        let (tokens, _) = dev_buy_amounts(1_000_000_000_000);
+        assert_eq!(tokens, INITIAL_REAL_TOKEN_RESERVES);
+    }
+
+    #[test]
+    fn test_create_pump_token_ix() {
+        let mint = Pubkey::new_unique();
+        let creator = Pubkey::new_unique();
+        let ix = create_pump_token_ix(&mint, &creator, "Test", "TST", "ipfs://x").unwrap();
+
+        assert_eq!(&ix.data[..8], &PUMP_CREATE);
+        assert_eq!(&ix.data[8..12], &4u32.to_le_bytes());
+        assert_eq!(&ix.data[12..16], b"Test");
+        assert_eq!(&ix.data[ix.data.len() - 32..], creator.as_ref());
+        let signers: Vec<Pubkey> = ix
+            .accounts
+            .iter()
+            .filter(|account| account.is_signer)
+            .map(|account| account.pubkey)
+            .collect();
+        assert_eq!(signers, vec![mint, creator]);
+    }
+}
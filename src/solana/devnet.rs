@@ -0,0 +1,159 @@
+use solana_sdk::{native_token::sol_to_lamports, pubkey::Pubkey};
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time;
+
+use crate::config::DevnetConfig;
+use crate::format;
+use crate::solana::dexscreener::{DexScreenerResponse, Liquidity, PairInfo, Token, Volume};
+use crate::solana::rpc::make_rpc_client;
+
+const FAUCET_INTERVAL: Duration = Duration::from_secs(60);
+/// Prices of the mocked DexScreener, fixed so sizing and PnL stay readable
+pub const MOCK_SOL_PRICE_USD: f64 = 150.0;
+pub const MOCK_TOKEN_PRICE_USD: f64 = 0.0001;
+const MOCK_LIQUIDITY_USD: f64 = 50_000.0;
+const MOCK_SUPPLY: f64 = 1_000_000_000.0;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static MOCK_POOL: OnceLock<Option<String>> = OnceLock::new();
+
+/// Point every RPC and websocket client at devnet and mock the mainnet-only
+/// pump.fun and DexScreener APIs for the rest of the process. Must run
+/// before any client is made.
+pub fn enable(cfg: &DevnetConfig) {
+    env::set_var("SOLANA_RPC_URL", &cfg.rpc_url);
+    env::remove_var("SOLANA_RPC_URLS");
+    if env::var("SOLANA_WS_URL").is_ok() {
+        env::set_var("SOLANA_WS_URL", &cfg.ws_url);
+    }
+    let _ = MOCK_POOL.set(cfg.pool.clone());
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// DexScreener answer for `query` on devnet: wSOL at [`MOCK_SOL_PRICE_USD`],
+/// any other mint as a single pair at [`MOCK_TOKEN_PRICE_USD`] in
+/// `DEVNET_POOL` (the mint itself when unset)
+pub fn mock_dexscreener(query: &str) -> DexScreenerResponse {
+    let wsol = Token {
+        address: spl_token::native_mint::id().to_string(),
+        name: "Wrapped SOL".to_string(),
+        symbol: "SOL".to_string(),
+    };
+    let (base_token, quote_token, price_usd, price_native) = if query == wsol.address {
+        let usdc = Token {
+            address: "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".to_string(),
+            name: "USD Coin".to_string(),
+            symbol: "USDC".to_string(),
+        };
+        (wsol, usdc, MOCK_SOL_PRICE_USD, MOCK_SOL_PRICE_USD)
+    } else {
+        let token = Token {
+            address: query.to_string(),
+            name: "Devnet token".to_string(),
+            symbol: "DEV".to_string(),
+        };
+        let price_native = MOCK_TOKEN_PRICE_USD / MOCK_SOL_PRICE_USD;
+        (token, wsol, MOCK_TOKEN_PRICE_USD, price_native)
+    };
+    let pair_address = MOCK_POOL
+        .get()
+        .cloned()
+        .flatten()
+        .unwrap_or_else(|| base_token.address.clone());
+
+    DexScreenerResponse {
+        schema_version: "1.0.0".to_string(),
+        pairs: vec![PairInfo {
+            chain_id: "solana".to_string(),
+            dex_id: "raydium".to_string(),
+            url: format!("https://dexscreener.com/solana/{}", pair_address),
+            pair_address,
+            labels: None,
+            base_token,
+            quote_token,
+            price_native: price_native.to_string(),
+            price_usd: price_usd.to_string(),
+            liquidity: Liquidity {
+                usd: MOCK_LIQUIDITY_USD,
+                base: MOCK_LIQUIDITY_USD / 2.0 / price_usd,
+                quote: MOCK_LIQUIDITY_USD / 2.0 / (price_usd / price_native),
+            },
+            volume: Volume {
+                h24: 0.0,
+                h6: 0.0,
+                h1: 0.0,
+                m5: 0.0,
+            },
+            market_cap: Some(price_usd * MOCK_SUPPLY),
+            fdv: Some(price_usd * MOCK_SUPPLY),
+        }],
+    }
+}
+
+/// Keeps the wallet funded from the devnet faucet until shutdown. Airdrops
+/// are rate limited, a failed one is retried on the next check.
+pub async fn run_faucet(cfg: DevnetConfig, owner: Pubkey, mut shutdown: watch::Receiver<bool>) {
+    let rpc = make_rpc_client();
+    let mut interval = time::interval(FAUCET_INTERVAL);
+    loop {
+        tokio::select! {
+            biased;
+            _ = shutdown.changed() => return,
+            _ = interval.tick() => {}
+        }
+
+        let balance = match rpc.get_balance(&owner).await {
+            Ok(balance) => balance,
+            Err(e) => {
+                tracing::error!("Devnet faucet failed to read the balance: {:?}", e);
+                continue;
+            }
+        };
+        if balance >= sol_to_lamports(cfg.min_balance_sol) {
+            continue;
+        }
+
+        tracing::info!(
+            "Devnet balance {} is below {}, requesting {}",
+            format::lamports(balance),
+            format::sol(cfg.min_balance_sol),
+            format::sol(cfg.airdrop_sol)
+        );
+        match rpc
+            .request_airdrop(&owner, sol_to_lamports(cfg.airdrop_sol))
+            .await
+        {
+            Ok(sig) => tracing::info!("Devnet airdrop requested: {}", sig),
+            Err(e) => tracing::warn!("Devnet airdrop failed: {:?}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_prices_agree() {
+        let sol = mock_dexscreener(&spl_token::native_mint::id().to_string());
+        assert_eq!(
+            sol.pairs[0].price_usd.parse::<f64>().unwrap(),
+            MOCK_SOL_PRICE_USD
+        );
+
+        let token = mock_dexscreener("Mint111111111111111111111111111111111111111");
+        let pair = &token.pairs[0];
+        assert_eq!(pair.base_token.address, pair.pair_address);
+        let native: f64 = pair.price_native.parse().unwrap();
+        let usd: f64 = pair.price_usd.parse().unwrap();
+        assert!((native * MOCK_SOL_PRICE_USD - usd).abs() < 1e-12);
+    }
+}
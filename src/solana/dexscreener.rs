@@ -1,7 +1,12 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
+use crate::solana::devnet;
+
+/// Mints the tokens endpoint takes in one request
+pub const MAX_TOKENS_PER_REQUEST: usize = 30;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DexScreenerResponse {
     #[serde(rename = "schemaVersion")]
@@ -29,6 +34,9 @@ pub struct PairInfo {
     pub price_usd: String,
     pub liquidity: Liquidity,
     pub volume: Volume,
+    #[serde(rename = "marketCap")]
+    pub market_cap: Option<f64>,
+    pub fdv: Option<f64>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -58,6 +66,9 @@ pub struct TickerResponse {
 }
 
 pub async fn search_ticker(ticker: String) -> Result<DexScreenerResponse> {
+    if devnet::is_enabled() {
+        return Ok(devnet::mock_dexscreener(&ticker));
+    }
     let client = Client::new();
     let url = format!(
         "https://api.dexscreener.com/latest/dex/search/?q={}",
@@ -74,6 +85,52 @@ pub async fn search_ticker(ticker: String) -> Result<DexScreenerResponse> {
     Ok(response)
 }
 
+/// Pairs of up to [`MAX_TOKENS_PER_REQUEST`] `mints` on Solana, in one
+/// request
+pub async fn token_pairs(mints: &[String]) -> Result<Vec<PairInfo>> {
+    if devnet::is_enabled() {
+        return Ok(mints
+            .iter()
+            .flat_map(|mint| devnet::mock_dexscreener(mint).pairs)
+            .collect());
+    }
+    let url = format!(
+        "https://api.dexscreener.com/tokens/v1/solana/{}",
+        mints.join(",")
+    );
+    let pairs = Client::new()
+        .get(&url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Vec<PairInfo>>()
+        .await?;
+    Ok(pairs)
+}
+
+/// USD price of the most liquid Solana pair of `mint` among `pairs`
+pub fn best_price_usd(pairs: &[PairInfo], mint: &str) -> Result<f64> {
+    let pair = pairs
+        .iter()
+        .filter(|pair| pair.chain_id == "solana" && pair.base_token.address == mint)
+        .max_by(|a, b| a.liquidity.usd.total_cmp(&b.liquidity.usd))
+        .ok_or_else(|| anyhow!("No pair of {} on DexScreener", mint))?;
+    Ok(pair.price_usd.parse()?)
+}
+
+/// USD price of SOL from the most liquid wSOL pair
+pub async fn sol_price_usd() -> Result<f64> {
+    let wsol = spl_token::native_mint::id().to_string();
+    let response = search_ticker(wsol.clone()).await?;
+    let pair = response
+        .pairs
+        .iter()
+        .filter(|p| p.chain_id == "solana" && p.base_token.address == wsol)
+        .max_by(|a, b| a.liquidity.usd.total_cmp(&b.liquidity.usd))
+        .ok_or_else(|| anyhow!("No wSOL pair found on DexScreener"))?;
+    Ok(pair.price_usd.parse()?)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,245 @@
+use anyhow::{anyhow, Result};
+use listen_kit::signer::SignerContext;
+use listen_kit::solana::util::execute_solana_transaction_with_tip;
+use serde_json::Value;
+use solana_account_decoder::UiAccountData;
+use solana_client::{nonblocking::rpc_client::RpcClient, rpc_request::TokenAccountsFilter};
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey};
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time;
+
+use crate::format;
+use crate::solana::rpc::make_rpc_client;
+use crate::trade::meme_trader::MemeTrader;
+
+/// Accounts closed per transaction, well within the size limit
+const CLOSE_BATCH: usize = 20;
+
+/// A token account of the wallet
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenAccount {
+    pub address: Pubkey,
+    pub token_program: Pubkey,
+    pub mint: String,
+    /// Raw token amount
+    pub amount: u64,
+    pub decimals: u8,
+    /// Rent, plus the wrapped SOL of native accounts
+    pub lamports: u64,
+    pub frozen: bool,
+    /// Token-2022 transfer fees held back in the account, it can't be
+    /// closed before they are harvested
+    pub withheld: u64,
+}
+
+impl TokenAccount {
+    pub fn is_wsol(&self) -> bool {
+        self.mint == spl_token::native_mint::id().to_string()
+    }
+}
+
+/// A token account from its `jsonParsed` data, `None` when it doesn't look
+/// like one
+pub fn parse_token_account(
+    address: Pubkey,
+    token_program: Pubkey,
+    lamports: u64,
+    parsed: &Value,
+) -> Option<TokenAccount> {
+    let info = &parsed["info"];
+    let withheld = info["extensions"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|extension| extension["extension"] == "transferFeeAmount")
+        .filter_map(|extension| extension["state"]["withheldAmount"].as_u64())
+        .sum();
+    Some(TokenAccount {
+        address,
+        token_program,
+        mint: info["mint"].as_str()?.to_string(),
+        amount: info["tokenAmount"]["amount"].as_str()?.parse().ok()?,
+        decimals: info["tokenAmount"]["decimals"].as_u64()?.try_into().ok()?,
+        lamports,
+        frozen: info["state"] == "frozen",
+        withheld,
+    })
+}
+
+/// Token accounts of `owner` under both token programs
+pub async fn token_accounts(rpc_client: &RpcClient, owner: &Pubkey) -> Result<Vec<TokenAccount>> {
+    let mut accounts = Vec::new();
+    for token_program in [spl_token::id(), spl_token_2022::id()] {
+        for keyed in rpc_client
+            .get_token_accounts_by_owner(owner, TokenAccountsFilter::ProgramId(token_program))
+            .await?
+        {
+            let UiAccountData::Json(data) = &keyed.account.data else {
+                return Err(anyhow!("Token account {} was not parsed", keyed.pubkey));
+            };
+            let address = Pubkey::from_str(&keyed.pubkey)?;
+            match parse_token_account(address, token_program, keyed.account.lamports, &data.parsed)
+            {
+                Some(account) => accounts.push(account),
+                None => tracing::warn!("Unexpected token account {}: {}", address, data.parsed),
+            }
+        }
+    }
+    Ok(accounts)
+}
+
+/// Accounts that can be closed: empty ones, and wrapped SOL left behind by
+/// swaps, closing it unwraps the SOL. Mints of `keep`, the open positions,
+/// are left alone.
+pub fn closable<'a>(accounts: &'a [TokenAccount], keep: &HashSet<String>) -> Vec<&'a TokenAccount> {
+    accounts
+        .iter()
+        .filter(|account| !account.frozen && account.withheld == 0)
+        .filter(|account| account.amount == 0 || account.is_wsol())
+        .filter(|account| !keep.contains(&account.mint))
+        .collect()
+}
+
+fn close_instructions(accounts: &[TokenAccount], owner: &Pubkey) -> Result<Vec<Instruction>> {
+    accounts
+        .iter()
+        .map(|account| {
+            Ok(spl_token_2022::instruction::close_account(
+                &account.token_program,
+                &account.address,
+                owner,
+                owner,
+                &[],
+            )?)
+        })
+        .collect()
+}
+
+/// What a sweep closed
+#[derive(Debug, Default)]
+pub struct Sweep {
+    pub closed: usize,
+    /// Rent and unwrapped SOL back in the wallet
+    pub recovered_lamports: u64,
+    pub tx_sigs: Vec<String>,
+}
+
+/// Close the closable token accounts of the signer wallet, in batches.
+/// With `dry_run` they are only counted.
+pub async fn sweep_token_accounts(
+    keep: &HashSet<String>,
+    tip_lamports: u64,
+    dry_run: bool,
+) -> Result<Sweep> {
+    let owner = Pubkey::from_str(&SignerContext::current().await.pubkey())?;
+    let accounts = token_accounts(&make_rpc_client(), &owner).await?;
+    let accounts: Vec<TokenAccount> = closable(&accounts, keep).into_iter().cloned().collect();
+
+    let mut sweep = Sweep::default();
+    for batch in accounts.chunks(CLOSE_BATCH) {
+        if !dry_run {
+            let ixs = close_instructions(batch, &owner)?;
+            let tx_sig = execute_solana_transaction_with_tip(
+                move |_owner| async move { Ok(ixs) },
+                tip_lamports,
+            )
+            .await?;
+            sweep.tx_sigs.push(tx_sig);
+        }
+        sweep.closed += batch.len();
+        sweep.recovered_lamports += batch.iter().map(|account| account.lamports).sum::<u64>();
+    }
+    Ok(sweep)
+}
+
+/// Sweep the wallet's token accounts every `interval` until `shutdown`
+/// flips. Mints of open positions are kept.
+pub async fn run_housekeeping(
+    trader: Arc<MemeTrader>,
+    interval: Duration,
+    tip_lamports: u64,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    let mut interval = time::interval(interval);
+    tracing::info!("Token account housekeeping started");
+    loop {
+        tokio::select! {
+            biased;
+            _ = shutdown.changed() => {
+                tracing::info!("Token account housekeeping stopped");
+                return Ok(());
+            }
+            _ = interval.tick() => {}
+        }
+
+        let keep = match trader.active_trades().load_all_trades().await {
+            Ok(trades) => trades
+                .into_iter()
+                .map(|trade| trade.token_address)
+                .collect(),
+            Err(e) => {
+                tracing::error!("Housekeeping failed to load active trades: {:?}", e);
+                continue;
+            }
+        };
+        match sweep_token_accounts(&keep, tip_lamports, false).await {
+            Ok(sweep) if sweep.closed > 0 => tracing::info!(
+                "Closed {} token accounts, recovered {}",
+                sweep.closed,
+                format::lamports(sweep.recovered_lamports)
+            ),
+            Ok(_) => {}
+            Err(e) => tracing::error!("Token account housekeeping failed: {:?}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn account(mint: &str, amount: &str, extensions: Value) -> TokenAccount {
+        parse_token_account(
+            Pubkey::new_unique(),
+            spl_token_2022::id(),
+            2_039_280,
+            &json!({
+                "type": "account",
+                "info": {
+                    "mint": mint,
+                    "state": "initialized",
+                    "tokenAmount": { "amount": amount, "decimals": 6 },
+                    "extensions": extensions,
+                }
+            }),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_closable() {
+        let wsol = spl_token::native_mint::id().to_string();
+        let accounts = vec![
+            account("empty", "0", json!([])),
+            account("held", "100", json!([])),
+            account(&wsol, "5000000", json!([])),
+            account("position", "0", json!([])),
+            account(
+                "fees",
+                "0",
+                json!([{ "extension": "transferFeeAmount", "state": { "withheldAmount": 12 } }]),
+            ),
+        ];
+        let keep = HashSet::from(["position".to_string()]);
+        let mints: Vec<&str> = closable(&accounts, &keep)
+            .iter()
+            .map(|account| account.mint.as_str())
+            .collect();
+        assert_eq!(mints, vec!["empty", wsol.as_str()]);
+    }
+}
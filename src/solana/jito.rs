@@ -0,0 +1,253 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures::future::select_ok;
+use listen_kit::signer::TransactionSigner;
+use rand::seq::SliceRandom;
+use reqwest::Client;
+use serde_json::{json, Value};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction,
+    transaction::Transaction,
+};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tokio::time;
+
+use crate::config::JitoConfig;
+
+/// Jito tip payment accounts, one is picked at random per bundle
+const TIP_ACCOUNTS: [&str; 8] = [
+    "96gYZGLnJYVFmbjzopPSU6QiEV5fGqZNyN9nmNhvrZU5",
+    "HFqU5x63VTqvQss8hp11i4wVV8bD44PvwucfZ2bU7gRe",
+    "Cw8CFyM9FkoMi7K7Crf6HNQqf4uEMzpKw6QNghXLvLkY",
+    "ADaUMid9yfUytqMBgopwjb2DTLSokTSzL1zt6iGPaS49",
+    "DfXygSm4jCyNCybVYYK6DwvWqjKee8pbDmJGcLWNDXjh",
+    "ADuUkR4vqLUMWXxW9gh6D6L8pMSawimctcNZ5pGwDcEt",
+    "DttWaMuVvTiduZRnguLF7jNxTgiMBZ1hyAumKUiL2KRL",
+    "3AVi9Tg9Uo68tJfuvoKvqKNWKkC5wPdSSdeBnizKZ6jT",
+];
+const STATUS_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// A blockhash expires after ~60s, a bundle still pending by then never lands
+const BUNDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, PartialEq)]
+enum BundleStatus {
+    Pending,
+    Landed,
+    Failed,
+    Invalid,
+}
+
+impl FromStr for BundleStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "Pending" => Ok(BundleStatus::Pending),
+            "Landed" => Ok(BundleStatus::Landed),
+            "Failed" => Ok(BundleStatus::Failed),
+            "Invalid" => Ok(BundleStatus::Invalid),
+            _ => Err(anyhow!("Unknown bundle status '{}'", s)),
+        }
+    }
+}
+
+/// Bundle endpoint of a block engine region name, full URLs are kept
+fn bundle_endpoint(region: &str) -> String {
+    if region.contains("://") {
+        region.to_string()
+    } else {
+        format!("https://{}.block-engine.jito.wtf/api/v1/bundles", region)
+    }
+}
+
+fn random_tip_account() -> Pubkey {
+    let account = TIP_ACCOUNTS
+        .choose(&mut rand::thread_rng())
+        .expect("tip accounts are not empty");
+    Pubkey::from_str(account).expect("tip accounts are valid")
+}
+
+/// Whether `account` is one of the Jito tip payment accounts
+pub fn is_tip_account(account: &Pubkey) -> bool {
+    TIP_ACCOUNTS.contains(&account.to_string().as_str())
+}
+
+/// Submits bundles to several block engine regions at once
+pub struct JitoClient {
+    client: Client,
+    endpoints: Vec<String>,
+}
+
+impl JitoClient {
+    pub fn new(regions: &[String]) -> Self {
+        Self {
+            client: Client::new(),
+            endpoints: regions.iter().map(|r| bundle_endpoint(r)).collect(),
+        }
+    }
+
+    async fn call(&self, endpoint: &str, method: &str, params: Value) -> Result<Value> {
+        let response: Value = self
+            .client
+            .post(endpoint)
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params,
+            }))
+            .send()
+            .await?
+            .json()
+            .await?;
+        if let Some(error) = response.get("error") {
+            return Err(anyhow!("{} {} failed: {}", endpoint, method, error));
+        }
+        response
+            .get("result")
+            .cloned()
+            .ok_or_else(|| anyhow!("{} {} returned no result", endpoint, method))
+    }
+
+    async fn status(&self, endpoint: &str, bundle_id: &str) -> Result<Option<BundleStatus>> {
+        let result = self
+            .call(endpoint, "getInflightBundleStatuses", json!([[bundle_id]]))
+            .await?;
+        result["value"]
+            .as_array()
+            .and_then(|statuses| statuses.first())
+            .and_then(|status| status["status"].as_str())
+            .map(str::parse)
+            .transpose()
+    }
+
+    /// Send the bundle to one region and wait until it lands there
+    async fn land_on(&self, endpoint: &str, encoded: &[String]) -> Result<String> {
+        let bundle_id = self
+            .call(
+                endpoint,
+                "sendBundle",
+                json!([encoded, { "encoding": "base64" }]),
+            )
+            .await?
+            .as_str()
+            .ok_or_else(|| anyhow!("{} returned no bundle id", endpoint))?
+            .to_string();
+
+        let started = Instant::now();
+        while started.elapsed() < BUNDLE_TIMEOUT {
+            time::sleep(STATUS_POLL_INTERVAL).await;
+            match self.status(endpoint, &bundle_id).await {
+                Ok(Some(BundleStatus::Landed)) => return Ok(bundle_id),
+                Ok(Some(status @ (BundleStatus::Failed | BundleStatus::Invalid))) => {
+                    return Err(anyhow!("Bundle {} {:?} on {}", bundle_id, status, endpoint));
+                }
+                Ok(_) => {}
+                Err(e) => tracing::debug!("Bundle status on {} failed: {:?}", endpoint, e),
+            }
+        }
+        Err(anyhow!(
+            "Bundle {} did not land on {} within {:?}",
+            bundle_id,
+            endpoint,
+            BUNDLE_TIMEOUT
+        ))
+    }
+
+    /// Submit signed transactions as one bundle to every region concurrently
+    /// and return the bundle id as soon as one of them reports it landed
+    pub async fn send_bundle_and_confirm(&self, txs: &[Transaction]) -> Result<String> {
+        let encoded = txs
+            .iter()
+            .map(|tx| Ok(STANDARD.encode(bincode::serialize(tx)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let attempts = self
+            .endpoints
+            .iter()
+            .map(|endpoint| Box::pin(self.land_on(endpoint, &encoded)));
+        let (bundle_id, _) = select_ok(attempts).await?;
+        Ok(bundle_id)
+    }
+}
+
+/// Signer that sends every transaction as a Jito bundle of the transaction
+/// followed by a tip transfer, instead of a single transaction. The tip
+/// transaction is built here, below any policy signer wrapping this one.
+pub struct JitoBundleSigner {
+    keypair: Keypair,
+    client: JitoClient,
+    tip_lamports: u64,
+}
+
+impl JitoBundleSigner {
+    pub fn new(keypair: Keypair, cfg: JitoConfig) -> Self {
+        Self {
+            keypair,
+            client: JitoClient::new(&cfg.regions),
+            tip_lamports: cfg.tip_lamports,
+        }
+    }
+}
+
+#[async_trait]
+impl TransactionSigner for JitoBundleSigner {
+    fn pubkey(&self) -> String {
+        self.keypair.pubkey().to_string()
+    }
+
+    async fn sign_and_send_solana_transaction(&self, tx: &mut Transaction) -> Result<String> {
+        let blockhash = tx.message.recent_blockhash;
+        tx.try_sign(&[&self.keypair], blockhash)?;
+        let tip = Transaction::new_signed_with_payer(
+            &[system_instruction::transfer(
+                &self.keypair.pubkey(),
+                &random_tip_account(),
+                self.tip_lamports,
+            )],
+            Some(&self.keypair.pubkey()),
+            &[&self.keypair],
+            blockhash,
+        );
+
+        let signature = tx.signatures[0].to_string();
+        let bundle_id = self
+            .client
+            .send_bundle_and_confirm(&[tx.clone(), tip])
+            .await?;
+        tracing::info!("Bundle {} landed with {}", bundle_id, signature);
+        Ok(signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundle_endpoint() {
+        assert_eq!(
+            bundle_endpoint("ny"),
+            "https://ny.block-engine.jito.wtf/api/v1/bundles"
+        );
+        assert_eq!(
+            bundle_endpoint("http://localhost:8080/api/v1/bundles"),
+            "http://localhost:8080/api/v1/bundles"
+        );
+    }
+
+    #[test]
+    fn test_tip_accounts_and_statuses() {
+        assert!(TIP_ACCOUNTS.iter().all(|a| Pubkey::from_str(a).is_ok()));
+        assert!(is_tip_account(&random_tip_account()));
+        assert!(!is_tip_account(&Pubkey::new_unique()));
+        assert_eq!(
+            "Landed".parse::<BundleStatus>().unwrap(),
+            BundleStatus::Landed
+        );
+        assert!("Dropped".parse::<BundleStatus>().is_err());
+    }
+}
@@ -0,0 +1,175 @@
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+use std::str::FromStr;
+
+use crate::solana::quoter::Side;
+
+/// Instruction as the swap API returns it
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JupiterInstruction {
+    program_id: String,
+    accounts: Vec<JupiterAccount>,
+    /// Base64
+    data: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JupiterAccount {
+    pubkey: String,
+    is_signer: bool,
+    is_writable: bool,
+}
+
+impl TryFrom<JupiterInstruction> for Instruction {
+    type Error = anyhow::Error;
+
+    fn try_from(ix: JupiterInstruction) -> Result<Self> {
+        let accounts = ix
+            .accounts
+            .into_iter()
+            .map(|account| {
+                Ok(AccountMeta {
+                    pubkey: Pubkey::from_str(&account.pubkey)?,
+                    is_signer: account.is_signer,
+                    is_writable: account.is_writable,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Instruction {
+            program_id: Pubkey::from_str(&ix.program_id)?,
+            accounts,
+            data: STANDARD.decode(ix.data)?,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SwapInstructions {
+    #[serde(default)]
+    setup_instructions: Vec<JupiterInstruction>,
+    swap_instruction: JupiterInstruction,
+    #[serde(default)]
+    cleanup_instruction: Option<JupiterInstruction>,
+    #[serde(default)]
+    address_lookup_table_addresses: Vec<String>,
+}
+
+impl SwapInstructions {
+    /// Setup, swap and cleanup. The compute budget instructions Jupiter
+    /// suggests are left out, swaps get ours.
+    fn into_instructions(self) -> Result<Vec<Instruction>> {
+        if !self.address_lookup_table_addresses.is_empty() {
+            return Err(anyhow!(
+                "Jupiter route needs address lookup tables, legacy transactions can't use them"
+            ));
+        }
+        self.setup_instructions
+            .into_iter()
+            .chain(std::iter::once(self.swap_instruction))
+            .chain(self.cleanup_instruction)
+            .map(Instruction::try_from)
+            .collect()
+    }
+}
+
+/// Instructions swapping `amount_in` of SOL for `mint` on a buy, of `mint`
+/// for SOL on a sell, along Jupiter's route. SOL is wrapped and unwrapped
+/// by the route itself. Routes are asked to fit a legacy transaction, so
+/// they may be worse than Jupiter's best.
+pub async fn create_jupiter_swap_ix(
+    api_url: &str,
+    mint: &str,
+    amount_in: u64,
+    side: Side,
+    slippage_bps: u16,
+    owner: &Pubkey,
+) -> Result<Vec<Instruction>> {
+    let wsol = spl_token::native_mint::id().to_string();
+    let (input_mint, output_mint) = match side {
+        Side::Buy => (wsol.as_str(), mint),
+        Side::Sell => (mint, wsol.as_str()),
+    };
+    let api_url = api_url.trim_end_matches('/');
+    let client = Client::new();
+    let quote: Value = client
+        .get(format!("{}/quote", api_url))
+        .query(&[
+            ("inputMint", input_mint),
+            ("outputMint", output_mint),
+            ("amount", &amount_in.to_string()),
+            ("slippageBps", &slippage_bps.to_string()),
+            ("asLegacyTransaction", "true"),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let swap: SwapInstructions = client
+        .post(format!("{}/swap-instructions", api_url))
+        .json(&json!({
+            "quoteResponse": quote,
+            "userPublicKey": owner.to_string(),
+            "wrapAndUnwrapSol": true,
+            "asLegacyTransaction": true,
+        }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    swap.into_instructions()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_swap_instructions() {
+        let program = Pubkey::new_unique();
+        let account = Pubkey::new_unique();
+        let ix = |data: &[u8]| {
+            json!({
+                "programId": program.to_string(),
+                "accounts": [{
+                    "pubkey": account.to_string(),
+                    "isSigner": true,
+                    "isWritable": false,
+                }],
+                "data": STANDARD.encode(data),
+            })
+        };
+        let response = json!({
+            "computeBudgetInstructions": [ix(&[9])],
+            "setupInstructions": [ix(&[1])],
+            "swapInstruction": ix(&[2]),
+            "cleanupInstruction": ix(&[3]),
+            "addressLookupTableAddresses": [],
+        });
+        let swap: SwapInstructions = serde_json::from_value(response.clone()).unwrap();
+        let ixs = swap.into_instructions().unwrap();
+        let data: Vec<Vec<u8>> = ixs.iter().map(|ix| ix.data.clone()).collect();
+        assert_eq!(data, vec![vec![1], vec![2], vec![3]]);
+        assert_eq!(ixs[0].program_id, program);
+        assert_eq!(
+            ixs[0].accounts,
+            vec![AccountMeta::new_readonly(account, true)]
+        );
+
+        let mut with_tables = response;
+        with_tables["addressLookupTableAddresses"] = json!([Pubkey::new_unique().to_string()]);
+        let swap: SwapInstructions = serde_json::from_value(with_tables).unwrap();
+        assert!(swap.into_instructions().is_err());
+    }
+}
@@ -0,0 +1,32 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use listen_kit::signer::TransactionSigner;
+use solana_sdk::{signature::Keypair, signer::Signer, transaction::Transaction};
+
+use crate::solana::rpc::make_rpc_client;
+
+/// Signer of the local `SOLANA_PRIVATE_KEY`. Unlike listen-kit's local
+/// signer, which sends over `SOLANA_RPC_URL` only, transactions go through
+/// our own RPC client, to every endpoint of `SOLANA_RPC_URLS` when it's set.
+pub struct KeypairSigner {
+    keypair: Keypair,
+}
+
+impl KeypairSigner {
+    pub fn new(keypair: Keypair) -> Self {
+        Self { keypair }
+    }
+}
+
+#[async_trait]
+impl TransactionSigner for KeypairSigner {
+    fn pubkey(&self) -> String {
+        self.keypair.pubkey().to_string()
+    }
+
+    async fn sign_and_send_solana_transaction(&self, tx: &mut Transaction) -> Result<String> {
+        tx.try_sign(&[&self.keypair], tx.message.recent_blockhash)?;
+        let signature = make_rpc_client().send_transaction(tx).await?;
+        Ok(signature.to_string())
+    }
+}
@@ -0,0 +1,538 @@
+use anyhow::{anyhow, Result};
+use borsh::{BorshDeserialize, BorshSerialize};
+use log::debug;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use std::str::FromStr;
+
+use crate::solana::raydium::apply_slippage;
+use crate::solana::route::METEORA_DLMM_PROGRAM;
+use crate::solana::transaction::{ata_with_create_ix, TxBuilder};
+
+/// Anchor discriminator of the DLMM `swap` instruction
+pub const METEORA_DLMM_SWAP: [u8; 8] = [248, 198, 158, 145, 225, 117, 135, 200];
+pub const MAX_BIN_PER_ARRAY: i32 = 70;
+/// Fee rates are expressed in billionths
+pub const FEE_PRECISION: u64 = 1_000_000_000;
+pub const MAX_FEE_RATE: u64 = 100_000_000;
+pub const BASIS_POINT_MAX: f64 = 10_000.0;
+/// Bin arrays passed to a swap, the active one and the next ones in the
+/// direction of the swap
+const SWAP_BIN_ARRAYS: i32 = 3;
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct StaticParameters {
+    pub base_factor: u16,
+    pub filter_period: u16,
+    pub decay_period: u16,
+    pub reduction_factor: u16,
+    pub variable_fee_control: u32,
+    pub max_volatility_accumulator: u32,
+    pub min_bin_id: i32,
+    pub max_bin_id: i32,
+    pub protocol_share: u16,
+    pub base_fee_power_factor: u8,
+    pub padding: [u8; 5],
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct VariableParameters {
+    pub volatility_accumulator: u32,
+    pub volatility_reference: u32,
+    pub index_reference: i32,
+    pub padding: [u8; 4],
+    pub last_update_timestamp: i64,
+    pub padding_1: [u8; 8],
+}
+
+/// Meteora DLMM pool (`LbPair`) state, up to the oracle which is the last
+/// field swaps need
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct LbPairLayout {
+    pub parameters: StaticParameters,
+    pub v_parameters: VariableParameters,
+    pub bump_seed: u8,
+    pub bin_step_seed: [u8; 2],
+    pub pair_type: u8,
+    /// Bin holding the current price
+    pub active_id: i32,
+    /// Price increment between bins in basis points
+    pub bin_step: u16,
+    /// 0 when swaps are enabled
+    pub status: u8,
+    pub require_base_factor_seed: u8,
+    pub base_factor_seed: [u8; 2],
+    pub activation_type: u8,
+    pub creator_pool_on_off_control: u8,
+    pub token_x_mint: Pubkey,
+    pub token_y_mint: Pubkey,
+    pub reserve_x: Pubkey,
+    pub reserve_y: Pubkey,
+    pub protocol_fee_amount_x: u64,
+    pub protocol_fee_amount_y: u64,
+    pub padding_1: [u8; 32],
+    pub reward_infos: [u8; 288],
+    pub oracle: Pubkey,
+}
+
+impl LbPairLayout {
+    pub const DISCRIMINATOR_LEN: usize = 8;
+    pub const LEN: usize = Self::DISCRIMINATOR_LEN +
+        32 + 32 + // static and variable parameters
+        1 + 2 + 1 + 4 + 2 + 1 + 1 + 2 + 1 + 1 + // bump_seed .. creator_pool_on_off_control
+        4 * 32 + // mints and reserves
+        8 + 8 + 32 + // protocol fees, padding
+        2 * 144 + // reward infos
+        32; // oracle
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < Self::LEN {
+            return Err(anyhow!("Invalid data length: {}", data.len()));
+        }
+        Ok(Self::try_from_slice(
+            &data[Self::DISCRIMINATOR_LEN..Self::LEN],
+        )?)
+    }
+
+    /// Whether a swap selling `input_mint` goes from token X to token Y
+    pub fn swap_for_y(&self, input_mint: &Pubkey) -> Result<bool> {
+        if *input_mint == self.token_x_mint {
+            Ok(true)
+        } else if *input_mint == self.token_y_mint {
+            Ok(false)
+        } else {
+            Err(anyhow!("Mint {} is not part of the pool", input_mint))
+        }
+    }
+
+    /// Base fee plus the volatility fee, in [`FEE_PRECISION`]
+    pub fn total_fee_rate(&self) -> u64 {
+        let params = &self.parameters;
+        let base_fee = params.base_factor as u128
+            * self.bin_step as u128
+            * 10
+            * 10u128.pow(params.base_fee_power_factor as u32);
+        let variable_fee = if params.variable_fee_control > 0 {
+            let volatility =
+                self.v_parameters.volatility_accumulator as u128 * self.bin_step as u128;
+            (volatility * volatility * params.variable_fee_control as u128 + 99_999_999_999)
+                / 100_000_000_000
+        } else {
+            0
+        };
+        (base_fee + variable_fee).min(MAX_FEE_RATE as u128) as u64
+    }
+
+    /// Price of a raw unit of X in raw units of Y at the active bin
+    pub fn active_price(&self) -> f64 {
+        (1.0 + self.bin_step as f64 / BASIS_POINT_MAX).powi(self.active_id)
+    }
+}
+
+pub async fn get_lb_pair(rpc_client: &RpcClient, pool_pubkey: &Pubkey) -> Result<LbPairLayout> {
+    let data = rpc_client.get_account_data(pool_pubkey).await?;
+    let layout = LbPairLayout::parse(&data)?;
+    debug!("Parsed LbPairLayout: {:?}", layout);
+    if layout.status != 0 {
+        return Err(anyhow!("Swaps in DLMM pool {} are disabled", pool_pubkey));
+    }
+    Ok(layout)
+}
+
+/// Output of `amount_in` at the price of the active bin after the fee.
+/// Exact while the active bin has the liquidity, larger swaps cross into
+/// bins with worse prices.
+pub fn dlmm_amount_out(pool: &LbPairLayout, amount_in: u64, swap_for_y: bool) -> u64 {
+    let fee_rate = pool.total_fee_rate().min(FEE_PRECISION);
+    let amount_in = amount_in as f64 * (FEE_PRECISION - fee_rate) as f64 / FEE_PRECISION as f64;
+    let price = pool.active_price();
+    if !price.is_finite() || price <= 0.0 {
+        return 0;
+    }
+    let amount_out = if swap_for_y {
+        amount_in * price
+    } else {
+        amount_in / price
+    };
+    amount_out.max(0.0) as u64
+}
+
+/// Index of the bin array holding `bin_id`
+pub fn bin_array_index(bin_id: i32) -> i32 {
+    bin_id.div_euclid(MAX_BIN_PER_ARRAY)
+}
+
+/// Indexes of the bin arrays a swap may traverse, in the direction of the
+/// swap. Selling X for Y moves the active bin down.
+pub fn swap_bin_array_indexes(active_id: i32, swap_for_y: bool) -> Vec<i32> {
+    let start = bin_array_index(active_id);
+    let step = if swap_for_y { -1 } else { 1 };
+    (0..SWAP_BIN_ARRAYS).map(|i| start + i * step).collect()
+}
+
+pub fn bin_array_address(lb_pair: &Pubkey, index: i32) -> Result<Pubkey> {
+    Ok(Pubkey::find_program_address(
+        &[
+            b"bin_array",
+            lb_pair.as_ref(),
+            &(index as i64).to_le_bytes(),
+        ],
+        &Pubkey::from_str(METEORA_DLMM_PROGRAM)?,
+    )
+    .0)
+}
+
+/// Extension of the pool's bin array bitmap, only created for pools with
+/// liquidity far away from the initial price
+pub fn bitmap_extension_address(lb_pair: &Pubkey) -> Result<Pubkey> {
+    Ok(Pubkey::find_program_address(
+        &[b"bitmap", lb_pair.as_ref()],
+        &Pubkey::from_str(METEORA_DLMM_PROGRAM)?,
+    )
+    .0)
+}
+
+pub fn event_authority_address() -> Result<Pubkey> {
+    Ok(Pubkey::find_program_address(
+        &[b"__event_authority"],
+        &Pubkey::from_str(METEORA_DLMM_PROGRAM)?,
+    )
+    .0)
+}
+
+/// Accounts of a swap that come from the chain rather than the pool state
+#[derive(Debug, Clone)]
+pub struct DlmmSwapAccounts {
+    pub token_x_program: Pubkey,
+    pub token_y_program: Pubkey,
+    pub bitmap_extension: Option<Pubkey>,
+    /// Initialized bin arrays in the direction of the swap
+    pub bin_arrays: Vec<Pubkey>,
+}
+
+impl DlmmSwapAccounts {
+    /// Token program of `mint`, which must be one side of `pool`
+    pub fn token_program(&self, pool: &LbPairLayout, mint: &Pubkey) -> Pubkey {
+        if *mint == pool.token_x_mint {
+            self.token_x_program
+        } else {
+            self.token_y_program
+        }
+    }
+}
+
+/// Token programs of both mints, the bitmap extension if the pool has one
+/// and the initialized bin arrays of a swap
+pub async fn get_dlmm_swap_accounts(
+    rpc_client: &RpcClient,
+    pool_pubkey: &Pubkey,
+    pool: &LbPairLayout,
+    swap_for_y: bool,
+) -> Result<DlmmSwapAccounts> {
+    let bin_arrays = swap_bin_array_indexes(pool.active_id, swap_for_y)
+        .into_iter()
+        .map(|index| bin_array_address(pool_pubkey, index))
+        .collect::<Result<Vec<_>>>()?;
+    let bitmap_extension = bitmap_extension_address(pool_pubkey)?;
+
+    let mut keys = vec![pool.token_x_mint, pool.token_y_mint, bitmap_extension];
+    keys.extend(&bin_arrays);
+    let accounts = rpc_client.get_multiple_accounts(&keys).await?;
+    let owner = |i: usize| {
+        accounts[i]
+            .as_ref()
+            .map(|account| account.owner)
+            .ok_or_else(|| anyhow!("DLMM account {} not found", keys[i]))
+    };
+    let token_x_program = owner(0)?;
+    let token_y_program = owner(1)?;
+
+    if accounts[3].is_none() {
+        return Err(anyhow!(
+            "Active bin array {} of pool {} is not initialized",
+            bin_arrays[0],
+            pool_pubkey
+        ));
+    }
+    // Arrays without liquidity are never created, the swap stops before them
+    let bin_arrays = bin_arrays
+        .into_iter()
+        .zip(&accounts[3..])
+        .take_while(|(_, account)| account.is_some())
+        .map(|(address, _)| address)
+        .collect();
+
+    Ok(DlmmSwapAccounts {
+        token_x_program,
+        token_y_program,
+        bitmap_extension: accounts[2].is_some().then_some(bitmap_extension),
+        bin_arrays,
+    })
+}
+
+/// Interact With Meteora DLMM (LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo), `swap`
+/// Input Accounts
+/// #1 - LB Pair (Writable)
+/// #2 - Bin Array Bitmap Extension, the program when the pool has none
+/// #3 - Reserve X (Writable)
+/// #4 - Reserve Y (Writable)
+/// #5 - User Token In (Writable)
+/// #6 - User Token Out (Writable)
+/// #7 - Token X Mint
+/// #8 - Token Y Mint
+/// #9 - Oracle (Writable)
+/// #10 - Host Fee In, the program for none
+/// #11 - User (Signer)
+/// #12 - Token X Program
+/// #13 - Token Y Program
+/// #14 - Event Authority
+/// #15 - Program
+/// Remaining - Bin Arrays (Writable)
+#[allow(clippy::too_many_arguments)]
+pub fn make_meteora_swap_ix(
+    pool_pubkey: Pubkey,
+    pool: &LbPairLayout,
+    swap_accounts: &DlmmSwapAccounts,
+    user_input_token_account: Pubkey,
+    user_output_token_account: Pubkey,
+    owner: Pubkey,
+    amount_in: u64,
+    minimum_amount_out: u64,
+) -> Result<Instruction> {
+    let program_id = Pubkey::from_str(METEORA_DLMM_PROGRAM)?;
+
+    let mut accounts = vec![
+        AccountMeta::new(pool_pubkey, false),
+        AccountMeta::new_readonly(swap_accounts.bitmap_extension.unwrap_or(program_id), false),
+        AccountMeta::new(pool.reserve_x, false),
+        AccountMeta::new(pool.reserve_y, false),
+        AccountMeta::new(user_input_token_account, false),
+        AccountMeta::new(user_output_token_account, false),
+        AccountMeta::new_readonly(pool.token_x_mint, false),
+        AccountMeta::new_readonly(pool.token_y_mint, false),
+        AccountMeta::new(pool.oracle, false),
+        AccountMeta::new_readonly(program_id, false),
+        AccountMeta::new_readonly(owner, true),
+        AccountMeta::new_readonly(swap_accounts.token_x_program, false),
+        AccountMeta::new_readonly(swap_accounts.token_y_program, false),
+        AccountMeta::new_readonly(event_authority_address()?, false),
+        AccountMeta::new_readonly(program_id, false),
+    ];
+    accounts.extend(
+        swap_accounts
+            .bin_arrays
+            .iter()
+            .map(|bin_array| AccountMeta::new(*bin_array, false)),
+    );
+
+    let mut data = METEORA_DLMM_SWAP.to_vec();
+    data.extend_from_slice(&amount_in.to_le_bytes());
+    data.extend_from_slice(&minimum_amount_out.to_le_bytes());
+
+    Ok(Instruction {
+        program_id,
+        accounts,
+        data,
+    })
+}
+
+pub async fn create_meteora_sol_swap_ix(
+    pool_address: String,
+    amount_in: u64,
+    slippage_bps: u16,
+    destination_token: Pubkey,
+    rpc_client: &RpcClient,
+    owner: &Pubkey,
+) -> Result<Vec<Instruction>> {
+    let mut ixs = vec![];
+
+    let pool_pubkey = Pubkey::from_str(&pool_address)?;
+    let pool = get_lb_pair(rpc_client, &pool_pubkey).await?;
+    let wsol = spl_token::native_mint::id();
+    let swap_for_y = pool.swap_for_y(&wsol)?;
+    let swap_accounts = get_dlmm_swap_accounts(rpc_client, &pool_pubkey, &pool, swap_for_y).await?;
+
+    let (builder, user_source_token_account) = TxBuilder::default().wrap_sol(owner, amount_in)?;
+
+    // DLMM pools may hold Token-2022 mints, so the ATA has to be derived
+    // with the mint's token program
+    let token_program = swap_accounts.token_program(&pool, &destination_token);
+    let (user_destination_token_account, create_ata) =
+        ata_with_create_ix(owner, &destination_token, &token_program);
+    ixs.push(create_ata);
+
+    let minimum_amount_out =
+        apply_slippage(dlmm_amount_out(&pool, amount_in, swap_for_y), slippage_bps);
+
+    ixs.push(make_meteora_swap_ix(
+        pool_pubkey,
+        &pool,
+        &swap_accounts,
+        user_source_token_account,
+        user_destination_token_account,
+        *owner,
+        amount_in,
+        minimum_amount_out,
+    )?);
+
+    Ok(builder.instructions(ixs).build())
+}
+
+/// Sell `amount_in` of `source_token` for SOL through a DLMM pool. Without
+/// `slippage_bps` the swap accepts any output.
+pub async fn create_meteora_token_swap_ix(
+    pool_address: String,
+    amount_in: u64,
+    slippage_bps: Option<u16>,
+    source_token: Pubkey,
+    rpc_client: &RpcClient,
+    owner: &Pubkey,
+) -> Result<Vec<Instruction>> {
+    let mut ixs = vec![];
+
+    let pool_pubkey = Pubkey::from_str(&pool_address)?;
+    let pool = get_lb_pair(rpc_client, &pool_pubkey).await?;
+    let swap_for_y = pool.swap_for_y(&source_token)?;
+    let swap_accounts = get_dlmm_swap_accounts(rpc_client, &pool_pubkey, &pool, swap_for_y).await?;
+
+    let (builder, user_destination_token_account) = TxBuilder::default().wrap_sol(owner, 0)?;
+
+    let user_source_token_account = get_associated_token_address_with_program_id(
+        owner,
+        &source_token,
+        &swap_accounts.token_program(&pool, &source_token),
+    );
+
+    let minimum_amount_out = slippage_bps.map_or(0, |bps| {
+        apply_slippage(dlmm_amount_out(&pool, amount_in, swap_for_y), bps)
+    });
+
+    ixs.push(make_meteora_swap_ix(
+        pool_pubkey,
+        &pool,
+        &swap_accounts,
+        user_source_token_account,
+        user_destination_token_account,
+        *owner,
+        amount_in,
+        minimum_amount_out,
+    )?);
+
+    Ok(builder.instructions(ixs).build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(active_id: i32, bin_step: u16, base_factor: u16) -> LbPairLayout {
+        LbPairLayout {
+            parameters: StaticParameters {
+                base_factor,
+                filter_period: 30,
+                decay_period: 600,
+                reduction_factor: 5000,
+                variable_fee_control: 0,
+                max_volatility_accumulator: 350_000,
+                min_bin_id: -443_636,
+                max_bin_id: 443_636,
+                protocol_share: 500,
+                base_fee_power_factor: 0,
+                padding: [0; 5],
+            },
+            v_parameters: VariableParameters {
+                volatility_accumulator: 0,
+                volatility_reference: 0,
+                index_reference: 0,
+                padding: [0; 4],
+                last_update_timestamp: 0,
+                padding_1: [0; 8],
+            },
+            bump_seed: 255,
+            bin_step_seed: bin_step.to_le_bytes(),
+            pair_type: 0,
+            active_id,
+            bin_step,
+            status: 0,
+            require_base_factor_seed: 0,
+            base_factor_seed: base_factor.to_le_bytes(),
+            activation_type: 0,
+            creator_pool_on_off_control: 0,
+            token_x_mint: Pubkey::new_unique(),
+            token_y_mint: Pubkey::new_unique(),
+            reserve_x: Pubkey::new_unique(),
+            reserve_y: Pubkey::new_unique(),
+            protocol_fee_amount_x: 0,
+            protocol_fee_amount_y: 0,
+            padding_1: [0; 32],
+            reward_infos: [0; 288],
+            oracle: Pubkey::new_unique(),
+        }
+    }
+
+    #[test]
+    fn test_parse_layout() {
+        let layout = pool(-1234, 25, 10_000);
+        let mut data = vec![0u8; LbPairLayout::DISCRIMINATOR_LEN];
+        data.extend(borsh::to_vec(&layout).unwrap());
+        assert_eq!(data.len(), LbPairLayout::LEN);
+        // The oracle sits at a fixed offset of the account
+        assert_eq!(&data[552..584], layout.oracle.as_ref());
+        assert_eq!(&data[76..80], &(-1234i32).to_le_bytes());
+
+        let parsed = LbPairLayout::parse(&data).unwrap();
+        assert_eq!(parsed.active_id, -1234);
+        assert_eq!(parsed.reserve_y, layout.reserve_y);
+        assert!(parsed.swap_for_y(&layout.token_x_mint).unwrap());
+        assert!(!parsed.swap_for_y(&layout.token_y_mint).unwrap());
+        assert!(parsed.swap_for_y(&Pubkey::new_unique()).is_err());
+    }
+
+    #[test]
+    fn test_fee_rate() {
+        // 10_000 * 25 bps bin step = 0.25%
+        let mut pool = pool(0, 25, 10_000);
+        assert_eq!(pool.total_fee_rate(), 2_500_000);
+        pool.parameters.base_fee_power_factor = 1;
+        assert_eq!(pool.total_fee_rate(), 25_000_000);
+        pool.parameters.variable_fee_control = 7_500;
+        pool.v_parameters.volatility_accumulator = 350_000;
+        assert_eq!(pool.total_fee_rate(), 30_742_188);
+        pool.v_parameters.volatility_accumulator = 3_500_000;
+        assert_eq!(pool.total_fee_rate(), MAX_FEE_RATE);
+    }
+
+    #[test]
+    fn test_amount_out_at_active_bin() {
+        // Bin 0 prices X at exactly 1 Y
+        let pool = pool(0, 25, 0);
+        assert_eq!(dlmm_amount_out(&pool, 1_000_000, true), 1_000_000);
+        assert_eq!(dlmm_amount_out(&pool, 1_000_000, false), 1_000_000);
+
+        // 100 bins of 1% up: 1.01^100 ~ 2.7048 Y per X
+        let pool = LbPairLayout {
+            active_id: 100,
+            bin_step: 100,
+            ..pool
+        };
+        assert_eq!(dlmm_amount_out(&pool, 1_000_000, true), 2_704_813);
+        assert_eq!(dlmm_amount_out(&pool, 2_704_813, false), 999_999);
+    }
+
+    #[test]
+    fn test_bin_arrays() {
+        assert_eq!(bin_array_index(0), 0);
+        assert_eq!(bin_array_index(69), 0);
+        assert_eq!(bin_array_index(70), 1);
+        assert_eq!(bin_array_index(-1), -1);
+        assert_eq!(bin_array_index(-70), -1);
+        assert_eq!(bin_array_index(-71), -2);
+
+        assert_eq!(swap_bin_array_indexes(75, true), vec![1, 0, -1]);
+        assert_eq!(swap_bin_array_indexes(75, false), vec![1, 2, 3]);
+    }
+}
@@ -1,4 +1,34 @@
+pub mod approval;
+pub mod attempts;
+pub mod best_venue;
+pub mod blockhash;
+pub mod cache;
+pub mod compute_budget;
+pub mod deploy_token;
+pub mod devnet;
 pub mod dexscreener;
+pub mod housekeeping;
+pub mod jito;
+pub mod jupiter;
+pub mod keypair;
+pub mod meteora;
+pub mod orca;
+pub mod policy;
+pub mod price;
+pub mod privy;
+pub mod quoter;
 pub mod raydium;
+pub mod route;
+pub mod rpc;
+pub mod scan;
+pub mod simulate;
+pub mod token_registry;
 pub mod trade_raydium;
+pub mod transaction;
+pub mod transfer;
+pub mod transfer_fee;
 pub mod util;
+pub mod wallet_events;
+pub mod wallet_manager;
+pub mod wallet_watcher;
+pub mod ws;
@@ -0,0 +1,476 @@
+use anyhow::{anyhow, Result};
+use borsh::{BorshDeserialize, BorshSerialize};
+use log::debug;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use std::str::FromStr;
+
+use crate::solana::raydium::apply_slippage;
+use crate::solana::route::ORCA_WHIRLPOOL_PROGRAM;
+use crate::solana::transaction::{ata_with_create_ix, TxBuilder};
+
+/// Anchor discriminator of `swap_v2`, which unlike `swap` accepts
+/// Token-2022 mints
+pub const ORCA_WHIRLPOOL_SWAP_V2: [u8; 8] = [43, 4, 237, 11, 26, 201, 30, 98];
+pub const WHIRLPOOL_FEE_RATE_DENOMINATOR: u64 = 1_000_000;
+pub const TICK_ARRAY_SIZE: i32 = 88;
+/// Price limits of the program, a swap limited by them runs until the input
+/// is spent
+pub const MIN_SQRT_PRICE_X64: u128 = 4_295_048_016;
+pub const MAX_SQRT_PRICE_X64: u128 = 79_226_673_515_401_279_992_447_579_055;
+const MEMO_PROGRAM: &str = "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr";
+
+/// Orca Whirlpool (concentrated liquidity) pool state, up to the reward
+/// infos which swaps don't need
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct WhirlpoolLayout {
+    pub whirlpools_config: Pubkey,
+    pub whirlpool_bump: u8,
+    pub tick_spacing: u16,
+    pub fee_tier_index_seed: [u8; 2],
+    /// Hundredths of a basis point, see [`WHIRLPOOL_FEE_RATE_DENOMINATOR`]
+    pub fee_rate: u16,
+    pub protocol_fee_rate: u16,
+    pub liquidity: u128,
+    /// Q64.64 square root of the price of token A in token B
+    pub sqrt_price: u128,
+    pub tick_current_index: i32,
+    pub protocol_fee_owed_a: u64,
+    pub protocol_fee_owed_b: u64,
+    pub token_mint_a: Pubkey,
+    pub token_vault_a: Pubkey,
+    pub fee_growth_global_a: u128,
+    pub token_mint_b: Pubkey,
+    pub token_vault_b: Pubkey,
+    pub fee_growth_global_b: u128,
+    pub reward_last_updated_timestamp: u64,
+}
+
+impl WhirlpoolLayout {
+    pub const DISCRIMINATOR_LEN: usize = 8;
+    pub const LEN: usize = Self::DISCRIMINATOR_LEN +
+        32 + // whirlpools_config
+        1 + 2 + 2 + 2 + 2 + // whirlpool_bump .. protocol_fee_rate
+        16 + 16 + // liquidity, sqrt_price
+        4 + 8 + 8 + // tick_current_index, protocol_fee_owed_a/b
+        2 * (32 + 32 + 16) + // mint, vault and fee growth of each side
+        8; // reward_last_updated_timestamp
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < Self::LEN {
+            return Err(anyhow!("Invalid data length: {}", data.len()));
+        }
+        Ok(Self::try_from_slice(
+            &data[Self::DISCRIMINATOR_LEN..Self::LEN],
+        )?)
+    }
+
+    /// Whether a swap selling `input_mint` goes from token A to token B
+    pub fn a_to_b(&self, input_mint: &Pubkey) -> Result<bool> {
+        if *input_mint == self.token_mint_a {
+            Ok(true)
+        } else if *input_mint == self.token_mint_b {
+            Ok(false)
+        } else {
+            Err(anyhow!("Mint {} is not part of the pool", input_mint))
+        }
+    }
+}
+
+pub async fn get_whirlpool(
+    rpc_client: &RpcClient,
+    pool_pubkey: &Pubkey,
+) -> Result<WhirlpoolLayout> {
+    let data = rpc_client.get_account_data(pool_pubkey).await?;
+    let layout = WhirlpoolLayout::parse(&data)?;
+    debug!("Parsed WhirlpoolLayout: {:?}", layout);
+    Ok(layout)
+}
+
+/// Output of `amount_in` at the pool's current liquidity after the fee.
+/// Exact while the swap stays within the current tick range, larger swaps
+/// cross into ranges with other liquidity.
+pub fn whirlpool_amount_out(pool: &WhirlpoolLayout, amount_in: u64, a_to_b: bool) -> u64 {
+    if pool.liquidity == 0 || pool.sqrt_price == 0 {
+        return 0;
+    }
+    let fee_rate = (pool.fee_rate as u64).min(WHIRLPOOL_FEE_RATE_DENOMINATOR);
+    let amount_in = amount_in as f64 * (WHIRLPOOL_FEE_RATE_DENOMINATOR - fee_rate) as f64
+        / WHIRLPOOL_FEE_RATE_DENOMINATOR as f64;
+    let liquidity = pool.liquidity as f64;
+    let sqrt_price = pool.sqrt_price as f64 / 2f64.powi(64);
+
+    let amount_out = if a_to_b {
+        // Selling A lowers the price: Δy = L * (√P - √P')
+        let next_sqrt_price = liquidity * sqrt_price / (liquidity + amount_in * sqrt_price);
+        liquidity * (sqrt_price - next_sqrt_price)
+    } else {
+        // Selling B raises it: Δx = L * (1/√P - 1/√P')
+        let next_sqrt_price = sqrt_price + amount_in / liquidity;
+        liquidity * (1.0 / sqrt_price - 1.0 / next_sqrt_price)
+    };
+    amount_out.max(0.0) as u64
+}
+
+/// Start tick of the tick array holding `tick_index`
+pub fn tick_array_start_index(tick_index: i32, tick_spacing: u16) -> i32 {
+    let ticks_per_array = tick_spacing as i32 * TICK_ARRAY_SIZE;
+    tick_index.div_euclid(ticks_per_array) * ticks_per_array
+}
+
+/// Start ticks of the three tick arrays a swap may traverse, in the
+/// direction of the swap
+pub fn swap_tick_array_starts(
+    tick_current_index: i32,
+    tick_spacing: u16,
+    a_to_b: bool,
+) -> [i32; 3] {
+    let ticks_per_array = tick_spacing as i32 * TICK_ARRAY_SIZE;
+    // A price right below an array boundary is already served by the next
+    // array when it moves up
+    let shift = if a_to_b { 0 } else { tick_spacing as i32 };
+    let start = tick_array_start_index(tick_current_index + shift, tick_spacing);
+    let step = if a_to_b {
+        -ticks_per_array
+    } else {
+        ticks_per_array
+    };
+    [start, start + step, start + 2 * step]
+}
+
+pub fn tick_array_address(whirlpool: &Pubkey, start_tick_index: i32) -> Result<Pubkey> {
+    Ok(Pubkey::find_program_address(
+        &[
+            b"tick_array",
+            whirlpool.as_ref(),
+            start_tick_index.to_string().as_bytes(),
+        ],
+        &Pubkey::from_str(ORCA_WHIRLPOOL_PROGRAM)?,
+    )
+    .0)
+}
+
+pub fn oracle_address(whirlpool: &Pubkey) -> Result<Pubkey> {
+    Ok(Pubkey::find_program_address(
+        &[b"oracle", whirlpool.as_ref()],
+        &Pubkey::from_str(ORCA_WHIRLPOOL_PROGRAM)?,
+    )
+    .0)
+}
+
+/// Accounts of a swap that come from the chain rather than the pool state
+#[derive(Debug, Clone, Copy)]
+pub struct WhirlpoolSwapAccounts {
+    pub token_program_a: Pubkey,
+    pub token_program_b: Pubkey,
+    pub tick_arrays: [Pubkey; 3],
+}
+
+impl WhirlpoolSwapAccounts {
+    /// Token program of `mint`, which must be one side of `pool`
+    pub fn token_program(&self, pool: &WhirlpoolLayout, mint: &Pubkey) -> Pubkey {
+        if *mint == pool.token_mint_a {
+            self.token_program_a
+        } else {
+            self.token_program_b
+        }
+    }
+}
+
+/// Token programs of both mints and the tick arrays of a swap. Arrays that
+/// were never initialized are replaced by the last one that was, the swap
+/// fails if it actually has to go that far.
+pub async fn get_whirlpool_swap_accounts(
+    rpc_client: &RpcClient,
+    pool_pubkey: &Pubkey,
+    pool: &WhirlpoolLayout,
+    a_to_b: bool,
+) -> Result<WhirlpoolSwapAccounts> {
+    let starts = swap_tick_array_starts(pool.tick_current_index, pool.tick_spacing, a_to_b);
+    let mut tick_arrays = [Pubkey::default(); 3];
+    for (address, start) in tick_arrays.iter_mut().zip(starts) {
+        *address = tick_array_address(pool_pubkey, start)?;
+    }
+
+    let mut keys = vec![pool.token_mint_a, pool.token_mint_b];
+    keys.extend(tick_arrays);
+    let accounts = rpc_client.get_multiple_accounts(&keys).await?;
+    let owner = |i: usize| {
+        accounts[i]
+            .as_ref()
+            .map(|account| account.owner)
+            .ok_or_else(|| anyhow!("Whirlpool account {} not found", keys[i]))
+    };
+    let token_program_a = owner(0)?;
+    let token_program_b = owner(1)?;
+
+    if accounts[2].is_none() {
+        return Err(anyhow!(
+            "Tick array {} of pool {} is not initialized",
+            tick_arrays[0],
+            pool_pubkey
+        ));
+    }
+    for i in 1..3 {
+        if accounts[2 + i].is_none() {
+            tick_arrays[i] = tick_arrays[i - 1];
+        }
+    }
+
+    Ok(WhirlpoolSwapAccounts {
+        token_program_a,
+        token_program_b,
+        tick_arrays,
+    })
+}
+
+/// Interact With Orca Whirlpool (whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc), `swap_v2`
+/// Input Accounts
+/// #1 - Token Program A
+/// #2 - Token Program B
+/// #3 - Memo Program
+/// #4 - Token Authority (Signer)
+/// #5 - Whirlpool (Writable)
+/// #6 - Token Mint A
+/// #7 - Token Mint B
+/// #8 - Token Owner Account A (Writable)
+/// #9 - Token Vault A (Writable)
+/// #10 - Token Owner Account B (Writable)
+/// #11 - Token Vault B (Writable)
+/// #12 - Tick Array 0 (Writable)
+/// #13 - Tick Array 1 (Writable)
+/// #14 - Tick Array 2 (Writable)
+/// #15 - Oracle (Writable)
+#[allow(clippy::too_many_arguments)]
+pub fn make_orca_swap_ix(
+    pool_pubkey: Pubkey,
+    pool: &WhirlpoolLayout,
+    swap_accounts: &WhirlpoolSwapAccounts,
+    input_mint: &Pubkey,
+    user_input_token_account: Pubkey,
+    user_output_token_account: Pubkey,
+    owner: Pubkey,
+    amount_in: u64,
+    minimum_amount_out: u64,
+) -> Result<Instruction> {
+    let a_to_b = pool.a_to_b(input_mint)?;
+    let (user_token_account_a, user_token_account_b) = if a_to_b {
+        (user_input_token_account, user_output_token_account)
+    } else {
+        (user_output_token_account, user_input_token_account)
+    };
+
+    let accounts = vec![
+        AccountMeta::new_readonly(swap_accounts.token_program_a, false),
+        AccountMeta::new_readonly(swap_accounts.token_program_b, false),
+        AccountMeta::new_readonly(Pubkey::from_str(MEMO_PROGRAM)?, false),
+        AccountMeta::new_readonly(owner, true),
+        AccountMeta::new(pool_pubkey, false),
+        AccountMeta::new_readonly(pool.token_mint_a, false),
+        AccountMeta::new_readonly(pool.token_mint_b, false),
+        AccountMeta::new(user_token_account_a, false),
+        AccountMeta::new(pool.token_vault_a, false),
+        AccountMeta::new(user_token_account_b, false),
+        AccountMeta::new(pool.token_vault_b, false),
+        AccountMeta::new(swap_accounts.tick_arrays[0], false),
+        AccountMeta::new(swap_accounts.tick_arrays[1], false),
+        AccountMeta::new(swap_accounts.tick_arrays[2], false),
+        AccountMeta::new(oracle_address(&pool_pubkey)?, false),
+    ];
+
+    let sqrt_price_limit = if a_to_b {
+        MIN_SQRT_PRICE_X64
+    } else {
+        MAX_SQRT_PRICE_X64
+    };
+    let mut data = ORCA_WHIRLPOOL_SWAP_V2.to_vec();
+    data.extend_from_slice(&amount_in.to_le_bytes());
+    data.extend_from_slice(&minimum_amount_out.to_le_bytes());
+    data.extend_from_slice(&sqrt_price_limit.to_le_bytes());
+    data.push(1); // amount_specified_is_input
+    data.push(a_to_b as u8);
+    data.push(0); // remaining_accounts_info: None
+
+    Ok(Instruction {
+        program_id: Pubkey::from_str(ORCA_WHIRLPOOL_PROGRAM)?,
+        accounts,
+        data,
+    })
+}
+
+pub async fn create_orca_sol_swap_ix(
+    pool_address: String,
+    amount_in: u64,
+    slippage_bps: u16,
+    destination_token: Pubkey,
+    rpc_client: &RpcClient,
+    owner: &Pubkey,
+) -> Result<Vec<Instruction>> {
+    let mut ixs = vec![];
+
+    let pool_pubkey = Pubkey::from_str(&pool_address)?;
+    let pool = get_whirlpool(rpc_client, &pool_pubkey).await?;
+    let wsol = spl_token::native_mint::id();
+    let a_to_b = pool.a_to_b(&wsol)?;
+    let swap_accounts =
+        get_whirlpool_swap_accounts(rpc_client, &pool_pubkey, &pool, a_to_b).await?;
+
+    let (builder, user_source_token_account) = TxBuilder::default().wrap_sol(owner, amount_in)?;
+
+    // Whirlpools may hold Token-2022 mints, so the ATA has to be derived
+    // with the mint's token program
+    let token_program = swap_accounts.token_program(&pool, &destination_token);
+    let (user_destination_token_account, create_ata) =
+        ata_with_create_ix(owner, &destination_token, &token_program);
+    ixs.push(create_ata);
+
+    let minimum_amount_out =
+        apply_slippage(whirlpool_amount_out(&pool, amount_in, a_to_b), slippage_bps);
+
+    ixs.push(make_orca_swap_ix(
+        pool_pubkey,
+        &pool,
+        &swap_accounts,
+        &wsol,
+        user_source_token_account,
+        user_destination_token_account,
+        *owner,
+        amount_in,
+        minimum_amount_out,
+    )?);
+
+    Ok(builder.instructions(ixs).build())
+}
+
+/// Sell `amount_in` of `source_token` for SOL through a Whirlpool. Without
+/// `slippage_bps` the swap accepts any output.
+pub async fn create_orca_token_swap_ix(
+    pool_address: String,
+    amount_in: u64,
+    slippage_bps: Option<u16>,
+    source_token: Pubkey,
+    rpc_client: &RpcClient,
+    owner: &Pubkey,
+) -> Result<Vec<Instruction>> {
+    let mut ixs = vec![];
+
+    let pool_pubkey = Pubkey::from_str(&pool_address)?;
+    let pool = get_whirlpool(rpc_client, &pool_pubkey).await?;
+    let a_to_b = pool.a_to_b(&source_token)?;
+    let swap_accounts =
+        get_whirlpool_swap_accounts(rpc_client, &pool_pubkey, &pool, a_to_b).await?;
+
+    let (builder, user_destination_token_account) = TxBuilder::default().wrap_sol(owner, 0)?;
+
+    let user_source_token_account = get_associated_token_address_with_program_id(
+        owner,
+        &source_token,
+        &swap_accounts.token_program(&pool, &source_token),
+    );
+
+    let minimum_amount_out = slippage_bps.map_or(0, |bps| {
+        apply_slippage(whirlpool_amount_out(&pool, amount_in, a_to_b), bps)
+    });
+
+    ixs.push(make_orca_swap_ix(
+        pool_pubkey,
+        &pool,
+        &swap_accounts,
+        &source_token,
+        user_source_token_account,
+        user_destination_token_account,
+        *owner,
+        amount_in,
+        minimum_amount_out,
+    )?);
+
+    Ok(builder.instructions(ixs).build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(sqrt_price: u128, liquidity: u128, fee_rate: u16) -> WhirlpoolLayout {
+        WhirlpoolLayout {
+            whirlpools_config: Pubkey::default(),
+            whirlpool_bump: 255,
+            tick_spacing: 64,
+            fee_tier_index_seed: [64, 0],
+            fee_rate,
+            protocol_fee_rate: 0,
+            liquidity,
+            sqrt_price,
+            tick_current_index: 0,
+            protocol_fee_owed_a: 0,
+            protocol_fee_owed_b: 0,
+            token_mint_a: Pubkey::new_unique(),
+            token_vault_a: Pubkey::new_unique(),
+            fee_growth_global_a: 0,
+            token_mint_b: Pubkey::new_unique(),
+            token_vault_b: Pubkey::new_unique(),
+            fee_growth_global_b: 0,
+            reward_last_updated_timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn test_parse_layout() {
+        let layout = pool(1 << 64, 1_000, 3000);
+        let mut data = vec![0u8; WhirlpoolLayout::DISCRIMINATOR_LEN];
+        data.extend(borsh::to_vec(&layout).unwrap());
+        // Reward infos
+        data.extend([0u8; 384]);
+        assert_eq!(data.len(), 653);
+
+        let parsed = WhirlpoolLayout::parse(&data).unwrap();
+        assert_eq!(parsed.sqrt_price, layout.sqrt_price);
+        assert_eq!(parsed.token_vault_b, layout.token_vault_b);
+        assert!(parsed.a_to_b(&layout.token_mint_a).unwrap());
+        assert!(!parsed.a_to_b(&layout.token_mint_b).unwrap());
+        assert!(parsed.a_to_b(&Pubkey::new_unique()).is_err());
+    }
+
+    #[test]
+    fn test_amount_out_near_spot_price() {
+        // Price 4 B per A, deep liquidity, no fee
+        let pool = pool(2 << 64, 1_000_000_000_000_000, 0);
+        let out = whirlpool_amount_out(&pool, 1_000, true);
+        assert!((3_999..=4_000).contains(&out), "{}", out);
+        let out = whirlpool_amount_out(&pool, 4_000, false);
+        assert!((999..=1_000).contains(&out), "{}", out);
+
+        // The fee is taken from the input, large swaps move the price
+        let pool = pool_with_fee(&pool, 10_000);
+        assert!(whirlpool_amount_out(&pool, 1_000, true) <= 3_960);
+        assert!(whirlpool_amount_out(&pool, 1 << 50, true) < (4u64 << 50) / 2);
+    }
+
+    fn pool_with_fee(pool: &WhirlpoolLayout, fee_rate: u16) -> WhirlpoolLayout {
+        WhirlpoolLayout {
+            fee_rate,
+            ..pool.clone()
+        }
+    }
+
+    #[test]
+    fn test_tick_arrays() {
+        // 64 * 88 ticks per array
+        assert_eq!(tick_array_start_index(0, 64), 0);
+        assert_eq!(tick_array_start_index(5631, 64), 0);
+        assert_eq!(tick_array_start_index(5632, 64), 5632);
+        assert_eq!(tick_array_start_index(-1, 64), -5632);
+
+        assert_eq!(swap_tick_array_starts(100, 64, true), [0, -5632, -11264]);
+        assert_eq!(swap_tick_array_starts(100, 64, false), [0, 5632, 11264]);
+        // Within a tick spacing of the upper boundary
+        assert_eq!(
+            swap_tick_array_starts(5600, 64, false),
+            [5632, 11264, 16896]
+        );
+    }
+}
@@ -0,0 +1,245 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use listen_kit::signer::TransactionSigner;
+use solana_sdk::{
+    pubkey::Pubkey, system_instruction::SystemInstruction, system_program, transaction::Transaction,
+};
+use spl_associated_token_account::get_associated_token_address;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::format;
+use crate::solana::jito::is_tip_account;
+use crate::solana::rpc::make_rpc_client;
+use crate::solana::transaction::simulate_sol_outflow;
+use crate::trade::capital::{CapitalState, CapitalStore, Withdrawal};
+
+/// Signer that enforces the capital policy on every transaction, whatever
+/// sends it: the balance may not drop below the locked amount, and SOL may
+/// only be transferred to an allow-listed address as part of a confirmed
+/// withdrawal, to the cold wallet profits are swept to, or as a tip. Has to
+/// wrap every other signer so nothing bypasses it.
+pub struct PolicySigner {
+    inner: Arc<dyn TransactionSigner>,
+    capital: CapitalStore,
+    withdraw_allowlist: Vec<Pubkey>,
+    sweep_wallet: Option<Pubkey>,
+}
+
+impl PolicySigner {
+    pub fn new(
+        inner: Arc<dyn TransactionSigner>,
+        capital: CapitalStore,
+        withdraw_allowlist: Vec<Pubkey>,
+        sweep_wallet: Option<Pubkey>,
+    ) -> Self {
+        Self {
+            inner,
+            capital,
+            withdraw_allowlist,
+            sweep_wallet,
+        }
+    }
+}
+
+/// SOL transfers out of `from` in the transaction as (destination, lamports)
+fn system_transfers(tx: &Transaction, from: &Pubkey) -> Vec<(Pubkey, u64)> {
+    let keys = &tx.message.account_keys;
+    tx.message
+        .instructions
+        .iter()
+        .filter(|ix| keys.get(ix.program_id_index as usize) == Some(&system_program::id()))
+        .filter_map(|ix| {
+            let Ok(SystemInstruction::Transfer { lamports }) = bincode::deserialize(&ix.data)
+            else {
+                return None;
+            };
+            let source = keys.get(*ix.accounts.first()? as usize)?;
+            let destination = keys.get(*ix.accounts.get(1)? as usize)?;
+            (source == from).then_some((*destination, lamports))
+        })
+        .collect()
+}
+
+/// The confirmed withdrawal a transaction executes, if any. SOL may only be
+/// transferred to a Jito tip account, to the wallet's own wrapped SOL
+/// account, to the sweep wallet, or to an allow-listed address when that
+/// matches the confirmed withdrawal exactly.
+fn withdrawal_for<'a>(
+    state: &'a CapitalState,
+    allowlist: &[Pubkey],
+    sweep_wallet: Option<&Pubkey>,
+    owner: &Pubkey,
+    transfers: &[(Pubkey, u64)],
+    now: i64,
+) -> Result<Option<&'a Withdrawal>> {
+    let wsol_account = get_associated_token_address(owner, &spl_token::native_mint::id());
+    let mut withdrawal = None;
+    for (destination, lamports) in transfers {
+        if is_tip_account(destination) || *destination == wsol_account {
+            continue;
+        }
+        let confirmed = state.pending_withdrawal.as_ref().filter(|w| {
+            w.confirmed
+                && !w.is_expired(now)
+                && w.destination == destination.to_string()
+                && w.lamports == *lamports
+        });
+        match confirmed {
+            Some(w) if allowlist.contains(destination) => {
+                if withdrawal.replace(w).is_some() {
+                    return Err(anyhow!("Only one withdrawal per transaction is allowed"));
+                }
+            }
+            // A profit sweep, it may not touch locked capital either
+            _ if sweep_wallet == Some(destination) => {}
+            _ if allowlist.contains(destination) => {
+                return Err(anyhow!(
+                    "Transfer of {} to {} is not a confirmed withdrawal",
+                    format::lamports(*lamports),
+                    destination
+                ))
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Transfer of {} to {} is neither a tip nor a withdrawal",
+                    format::lamports(*lamports),
+                    destination
+                ))
+            }
+        }
+    }
+    Ok(withdrawal)
+}
+
+#[async_trait]
+impl TransactionSigner for PolicySigner {
+    fn pubkey(&self) -> String {
+        self.inner.pubkey()
+    }
+
+    async fn sign_and_send_solana_transaction(&self, tx: &mut Transaction) -> Result<String> {
+        let owner = Pubkey::from_str(&self.inner.pubkey())?;
+        let state = self.capital.load().await?;
+        let withdrawal = withdrawal_for(
+            &state,
+            &self.withdraw_allowlist,
+            self.sweep_wallet.as_ref(),
+            &owner,
+            &system_transfers(tx, &owner),
+            chrono::Utc::now().timestamp(),
+        )?;
+        let withdrawn = withdrawal.map_or(0, |w| w.lamports);
+
+        // Withdrawals may take out locked SOL, the lock shrinks accordingly
+        let floor = state.locked_lamports.saturating_sub(withdrawn);
+        if floor > 0 {
+            let rpc_client = make_rpc_client();
+            let balance = rpc_client.get_balance(&owner).await?;
+            let outflow = simulate_sol_outflow(&rpc_client, tx, &owner).await?;
+            if balance.saturating_sub(outflow) < floor {
+                return Err(anyhow!(
+                    "Transaction spends {} of {}, which would dip into the {} of locked capital",
+                    format::lamports(outflow),
+                    format::lamports(balance),
+                    format::lamports(state.locked_lamports)
+                ));
+            }
+        }
+
+        let signature = self.inner.sign_and_send_solana_transaction(tx).await?;
+        if withdrawal.is_some() {
+            self.capital.complete_withdrawal(withdrawn).await?;
+            tracing::info!("Withdrawal of {} completed", format::lamports(withdrawn));
+        }
+        Ok(signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{message::Message, system_instruction};
+
+    fn state(destination: &Pubkey, lamports: u64, confirmed: bool) -> CapitalState {
+        CapitalState {
+            locked_lamports: 0,
+            pending_withdrawal: Some(Withdrawal {
+                code: "123456".to_string(),
+                destination: destination.to_string(),
+                lamports,
+                requested_at: 1_000,
+                confirmed,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_system_transfers() {
+        let owner = Pubkey::new_unique();
+        let tip = Pubkey::new_unique();
+        let cold = Pubkey::new_unique();
+        let tx = Transaction::new_unsigned(Message::new(
+            &[
+                system_instruction::transfer(&owner, &tip, 10_000),
+                system_instruction::transfer(&owner, &cold, 5_000_000),
+                system_instruction::transfer(&cold, &owner, 1),
+            ],
+            Some(&owner),
+        ));
+        assert_eq!(
+            system_transfers(&tx, &owner),
+            vec![(tip, 10_000), (cold, 5_000_000)]
+        );
+    }
+
+    #[test]
+    fn test_withdrawal_for() {
+        let owner = Pubkey::new_unique();
+        let tip = Pubkey::from_str("96gYZGLnJYVFmbjzopPSU6QiEV5fGqZNyN9nmNhvrZU5").unwrap();
+        let wsol = get_associated_token_address(&owner, &spl_token::native_mint::id());
+        let cold = Pubkey::new_unique();
+        let allowlist = [cold];
+        let confirmed = state(&cold, 5_000_000, true);
+        let withdrawal =
+            |state: &CapitalState, sweep: Option<&Pubkey>, transfers: &[(Pubkey, u64)], now| {
+                withdrawal_for(state, &allowlist, sweep, &owner, transfers, now)
+            };
+
+        // Tips and wrapping SOL aren't withdrawals
+        assert!(
+            withdrawal(&confirmed, None, &[(tip, 10_000), (wsol, 1_000_000)], 1_010)
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            withdrawal(&confirmed, None, &[(tip, 10_000), (cold, 5_000_000)], 1_010)
+                .unwrap()
+                .is_some()
+        );
+        // Anywhere else SOL may not go, whatever is locked
+        let unlocked = CapitalState::default();
+        assert!(withdrawal(&unlocked, None, &[(Pubkey::new_unique(), 10_000)], 1_010).is_err());
+        // Amount differs from the confirmed one
+        assert!(withdrawal(&confirmed, None, &[(cold, 6_000_000)], 1_010).is_err());
+        // Twice
+        assert!(withdrawal(
+            &confirmed,
+            None,
+            &[(cold, 5_000_000), (cold, 5_000_000)],
+            1_010
+        )
+        .is_err());
+        // Expired
+        assert!(withdrawal(&confirmed, None, &[(cold, 5_000_000)], 10_000).is_err());
+        // Requested but not confirmed
+        let unconfirmed = state(&cold, 5_000_000, false);
+        assert!(withdrawal(&unconfirmed, None, &[(cold, 5_000_000)], 1_010).is_err());
+        // Unless the address is the sweep wallet, then it's no withdrawal
+        assert!(
+            withdrawal(&unconfirmed, Some(&cold), &[(cold, 6_000_000)], 1_010)
+                .unwrap()
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,549 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
+use reqwest::Client;
+use serde::Deserialize;
+use solana_sdk::pubkey::Pubkey;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock, RwLock};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::{self, Instant};
+
+use crate::config::PriceFeedConfig;
+use crate::solana::cache::{cached_search_ticker, TtlCache};
+use crate::solana::dexscreener;
+use crate::solana::rpc::make_rpc_client;
+use crate::solana::ws::{pool_price_sol, PriceCache};
+
+const BIRDEYE_PRICE_URL: &str = "https://public-api.birdeye.so/defi/price";
+/// Pyth price feed of SOL/USD
+const PYTH_SOL_USD_FEED: &str = "ef0d8b6fda2ceba41da15d4095d1da392a0d2f8ed0c6c7bc0f4cfac8c280b56d";
+/// DexScreener allows 300 searches a minute
+const DEXSCREENER_MAX_RPS: f64 = 5.0;
+/// Hermes allows 30 requests every 10 seconds
+const PYTH_MAX_RPS: f64 = 3.0;
+/// Streamed pool prices older than this are read again from the reserves
+const MAX_STREAMED_PRICE_AGE: Duration = Duration::from_secs(60);
+const CACHE_CAPACITY: usize = 1_000;
+/// Batches of one source priced at once by [`PriceAggregator::prices_usd`],
+/// the rate limit of the source still spaces them
+const MAX_CONCURRENT_BATCHES: usize = 4;
+
+static FEED: LazyLock<RwLock<Option<Arc<PriceAggregator>>>> = LazyLock::new(RwLock::default);
+
+/// Price of one whole token
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quote {
+    Usd(f64),
+    /// Converted with the SOL/USD price by the aggregate
+    Sol(f64),
+}
+
+/// A source of token prices. Asked for wrapped SOL, it quotes SOL/USD.
+#[async_trait]
+pub trait PriceFeed: Send + Sync {
+    fn kind(&self) -> PriceSourceKind;
+
+    /// Whether `mint` can be quoted at all, others are not asked about
+    fn supports(&self, _mint: &str) -> bool {
+        true
+    }
+
+    async fn quote(&self, mint: &str) -> Result<Quote>;
+
+    /// Mints quoted by one call of [`Self::quote_many`]
+    fn batch_size(&self) -> usize {
+        1
+    }
+
+    /// Quotes of up to [`Self::batch_size`] `mints`, in their order
+    async fn quote_many(&self, mints: &[String]) -> Vec<Result<Quote>> {
+        let mut quotes = Vec::with_capacity(mints.len());
+        for mint in mints {
+            quotes.push(self.quote(mint).await);
+        }
+        quotes
+    }
+}
+
+/// The price sources `PRICE_SOURCES` picks from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PriceSourceKind {
+    /// Reserves of the token's pool, streamed or read from the RPC
+    Pool,
+    DexScreener,
+    Birdeye,
+    /// SOL/USD only
+    Pyth,
+}
+
+impl FromStr for PriceSourceKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "pool" => Ok(Self::Pool),
+            "dexscreener" => Ok(Self::DexScreener),
+            "birdeye" => Ok(Self::Birdeye),
+            "pyth" => Ok(Self::Pyth),
+            other => Err(anyhow!(
+                "Unknown price source '{}', expected pool, dexscreener, birdeye or pyth",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for PriceSourceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pool => write!(f, "pool"),
+            Self::DexScreener => write!(f, "dexscreener"),
+            Self::Birdeye => write!(f, "birdeye"),
+            Self::Pyth => write!(f, "pyth"),
+        }
+    }
+}
+
+fn is_wsol(mint: &str) -> bool {
+    mint == spl_token::native_mint::id().to_string()
+}
+
+/// Price in SOL of the token's pool: the streamed reserves while they are
+/// fresh, otherwise read once from the RPC
+pub struct PoolFeed {
+    stream: Option<Arc<PriceCache>>,
+}
+
+impl PoolFeed {
+    pub fn new(stream: Option<Arc<PriceCache>>) -> Self {
+        Self { stream }
+    }
+}
+
+#[async_trait]
+impl PriceFeed for PoolFeed {
+    fn kind(&self) -> PriceSourceKind {
+        PriceSourceKind::Pool
+    }
+
+    fn supports(&self, mint: &str) -> bool {
+        !is_wsol(mint)
+    }
+
+    async fn quote(&self, mint: &str) -> Result<Quote> {
+        let streamed = self
+            .stream
+            .as_ref()
+            .and_then(|stream| stream.price(mint, MAX_STREAMED_PRICE_AGE));
+        let price = match streamed {
+            Some(price) => price,
+            None => pool_price_sol(&make_rpc_client(), &Pubkey::from_str(mint)?).await?,
+        };
+        if price <= 0.0 {
+            return Err(anyhow!("Pool of {} has no reserves", mint));
+        }
+        Ok(Quote::Sol(price))
+    }
+}
+
+/// USD price of the token's most liquid Solana pair on DexScreener
+pub struct DexScreenerFeed;
+
+#[async_trait]
+impl PriceFeed for DexScreenerFeed {
+    fn kind(&self) -> PriceSourceKind {
+        PriceSourceKind::DexScreener
+    }
+
+    async fn quote(&self, mint: &str) -> Result<Quote> {
+        if is_wsol(mint) {
+            return Ok(Quote::Usd(dexscreener::sol_price_usd().await?));
+        }
+        let pairs = cached_search_ticker(mint).await?.pairs;
+        Ok(Quote::Usd(dexscreener::best_price_usd(&pairs, mint)?))
+    }
+
+    fn batch_size(&self) -> usize {
+        dexscreener::MAX_TOKENS_PER_REQUEST
+    }
+
+    async fn quote_many(&self, mints: &[String]) -> Vec<Result<Quote>> {
+        if let [mint] = mints {
+            return vec![self.quote(mint).await];
+        }
+        match dexscreener::token_pairs(mints).await {
+            Ok(pairs) => mints
+                .iter()
+                .map(|mint| Ok(Quote::Usd(dexscreener::best_price_usd(&pairs, mint)?)))
+                .collect(),
+            Err(e) => mints
+                .iter()
+                .map(|mint| Err(anyhow!("No pairs of {}: {:#}", mint, e)))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct BirdeyeResponse {
+    success: bool,
+    data: Option<BirdeyePrice>,
+}
+
+#[derive(Deserialize)]
+struct BirdeyePrice {
+    value: f64,
+}
+
+/// USD price from the Birdeye API
+pub struct BirdeyeFeed {
+    client: Client,
+    api_key: String,
+}
+
+impl BirdeyeFeed {
+    pub fn new(api_key: String) -> Self {
+        Self {
+            client: Client::new(),
+            api_key,
+        }
+    }
+}
+
+#[async_trait]
+impl PriceFeed for BirdeyeFeed {
+    fn kind(&self) -> PriceSourceKind {
+        PriceSourceKind::Birdeye
+    }
+
+    async fn quote(&self, mint: &str) -> Result<Quote> {
+        let response: BirdeyeResponse = self
+            .client
+            .get(BIRDEYE_PRICE_URL)
+            .header("X-API-KEY", &self.api_key)
+            .header("x-chain", "solana")
+            .query(&[("address", mint)])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        match response.data {
+            Some(price) if response.success => Ok(Quote::Usd(price.value)),
+            _ => Err(anyhow!("Birdeye has no price for {}", mint)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PythUpdate {
+    parsed: Vec<PythParsed>,
+}
+
+#[derive(Deserialize)]
+struct PythParsed {
+    price: PythPrice,
+}
+
+#[derive(Deserialize)]
+struct PythPrice {
+    price: String,
+    expo: i32,
+}
+
+/// Pyth's fixed point `price` scaled by `10^expo`
+pub fn pyth_price(price: &str, expo: i32) -> Result<f64> {
+    Ok(price.parse::<i64>()? as f64 * 10f64.powi(expo))
+}
+
+/// SOL/USD from Pyth's Hermes service
+pub struct PythFeed {
+    client: Client,
+    url: String,
+}
+
+impl PythFeed {
+    pub fn new(url: String) -> Self {
+        Self {
+            client: Client::new(),
+            url,
+        }
+    }
+}
+
+#[async_trait]
+impl PriceFeed for PythFeed {
+    fn kind(&self) -> PriceSourceKind {
+        PriceSourceKind::Pyth
+    }
+
+    fn supports(&self, mint: &str) -> bool {
+        is_wsol(mint)
+    }
+
+    async fn quote(&self, _mint: &str) -> Result<Quote> {
+        let update: PythUpdate = self
+            .client
+            .get(format!(
+                "{}/v2/updates/price/latest",
+                self.url.trim_end_matches('/')
+            ))
+            .query(&[("ids[]", PYTH_SOL_USD_FEED), ("parsed", "true")])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let parsed = update
+            .parsed
+            .first()
+            .ok_or_else(|| anyhow!("Pyth returned no SOL/USD price"))?;
+        Ok(Quote::Usd(pyth_price(
+            &parsed.price.price,
+            parsed.price.expo,
+        )?))
+    }
+}
+
+/// Spaces calls to one source at least `interval` apart
+pub struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+/// When a call asked for at `now` may go, and the slot after it
+pub fn next_slot(now: Instant, next: Instant, interval: Duration) -> (Instant, Instant) {
+    let at = next.max(now);
+    (at, at + interval)
+}
+
+impl RateLimiter {
+    pub fn per_second(max_rps: f64) -> Self {
+        Self {
+            interval: Duration::from_secs_f64(1.0 / max_rps),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Wait for the next free slot
+    pub async fn acquire(&self) {
+        let at = {
+            let mut next = self.next.lock().await;
+            let (at, after) = next_slot(Instant::now(), *next, self.interval);
+            *next = after;
+            at
+        };
+        time::sleep_until(at).await;
+    }
+}
+
+/// The configured sources in order of preference, each behind its rate
+/// limit, with their prices cached for a moment. A mint is priced by the
+/// first source that supports and quotes it; SOL prices of the pool are
+/// converted with the SOL/USD price, found the same way.
+pub struct PriceAggregator {
+    sources: Vec<(Box<dyn PriceFeed>, Option<RateLimiter>)>,
+    cache: TtlCache<f64>,
+}
+
+impl PriceAggregator {
+    /// Sources of `config`, the pool source reading the streamed reserves of
+    /// `stream` while they are fresh
+    pub fn new(config: &PriceFeedConfig, stream: Option<Arc<PriceCache>>) -> Self {
+        let mut stream = stream;
+        let sources = config
+            .sources
+            .iter()
+            .map(|kind| -> (Box<dyn PriceFeed>, Option<RateLimiter>) {
+                match kind {
+                    PriceSourceKind::Pool => (Box::new(PoolFeed::new(stream.take())), None),
+                    PriceSourceKind::DexScreener => (
+                        Box::new(DexScreenerFeed),
+                        Some(RateLimiter::per_second(DEXSCREENER_MAX_RPS)),
+                    ),
+                    PriceSourceKind::Birdeye => (
+                        Box::new(BirdeyeFeed::new(
+                            config.birdeye_api_key.clone().unwrap_or_default(),
+                        )),
+                        Some(RateLimiter::per_second(config.birdeye_max_rps)),
+                    ),
+                    PriceSourceKind::Pyth => (
+                        Box::new(PythFeed::new(config.pyth_url.clone())),
+                        Some(RateLimiter::per_second(PYTH_MAX_RPS)),
+                    ),
+                }
+            })
+            .collect();
+        let ttl = Duration::from_millis(config.cache_ttl_ms);
+        Self {
+            sources,
+            cache: TtlCache::new(CACHE_CAPACITY, ttl, ttl),
+        }
+    }
+
+    async fn first_quote(&self, mint: &str) -> Result<(Quote, PriceSourceKind)> {
+        let mut errors = Vec::new();
+        for (source, limiter) in self.sources.iter().filter(|(s, _)| s.supports(mint)) {
+            if let Some(limiter) = limiter {
+                limiter.acquire().await;
+            }
+            match source.quote(mint).await {
+                Ok(quote) => return Ok((quote, source.kind())),
+                Err(e) => {
+                    tracing::debug!("{} could not price {}: {:?}", source.kind(), mint, e);
+                    errors.push(format!("{}: {:#}", source.kind(), e));
+                }
+            }
+        }
+        if errors.is_empty() {
+            return Err(anyhow!("No price source supports {}", mint));
+        }
+        Err(anyhow!("No price for {}: {}", mint, errors.join("; ")))
+    }
+
+    /// Prices of `mints` from each source in turn, the ones it didn't price
+    /// left for the next, in batches of the source
+    async fn fetch_prices(&self, mints: Vec<String>) -> Vec<Result<f64>> {
+        let mut quotes: Vec<Option<Quote>> = vec![None; mints.len()];
+        let mut errors: Vec<Vec<String>> = vec![Vec::new(); mints.len()];
+        for (source, limiter) in &self.sources {
+            let pending: Vec<usize> = (0..mints.len())
+                .filter(|&i| quotes[i].is_none() && source.supports(&mints[i]))
+                .collect();
+            let mints = &mints;
+            let batches: Vec<(&[usize], Vec<Result<Quote>>)> =
+                stream::iter(pending.chunks(source.batch_size().max(1)))
+                    .map(|batch| async move {
+                        if let Some(limiter) = limiter {
+                            limiter.acquire().await;
+                        }
+                        let batch_mints: Vec<String> =
+                            batch.iter().map(|&i| mints[i].clone()).collect();
+                        (batch, source.quote_many(&batch_mints).await)
+                    })
+                    .buffer_unordered(MAX_CONCURRENT_BATCHES)
+                    .collect()
+                    .await;
+            for (batch, results) in batches {
+                for (&i, result) in batch.iter().zip(results) {
+                    match result {
+                        Ok(quote) => quotes[i] = Some(quote),
+                        Err(e) => {
+                            tracing::debug!(
+                                "{} could not price {}: {:?}",
+                                source.kind(),
+                                mints[i],
+                                e
+                            );
+                            errors[i].push(format!("{}: {:#}", source.kind(), e));
+                        }
+                    }
+                }
+            }
+        }
+        let sol_price_usd = if quotes.iter().any(|q| matches!(q, Some(Quote::Sol(_)))) {
+            Some(self.sol_price_usd().await)
+        } else {
+            None
+        };
+        mints
+            .iter()
+            .zip(quotes)
+            .zip(errors)
+            .map(|((mint, quote), errors)| match quote {
+                Some(Quote::Usd(price)) => Ok(price),
+                Some(Quote::Sol(price)) => match &sol_price_usd {
+                    Some(Ok(sol_price)) => Ok(price * sol_price),
+                    _ => Err(anyhow!("No SOL price to convert the price of {}", mint)),
+                },
+                None if errors.is_empty() => Err(anyhow!("No price source supports {}", mint)),
+                None => Err(anyhow!("No price for {}: {}", mint, errors.join("; "))),
+            })
+            .collect()
+    }
+
+    /// USD price of SOL
+    pub async fn sol_price_usd(&self) -> Result<f64> {
+        let wsol = spl_token::native_mint::id().to_string();
+        self.cache
+            .get_or_fetch(&wsol, async {
+                match self.first_quote(&wsol).await? {
+                    (Quote::Usd(price), _) => Ok(price),
+                    (Quote::Sol(_), kind) => Err(anyhow!("{} quoted SOL in SOL", kind)),
+                }
+            })
+            .await
+    }
+
+    /// USD price of a whole token of `mint`
+    pub async fn price_usd(&self, mint: &str) -> Result<f64> {
+        if is_wsol(mint) {
+            return self.sol_price_usd().await;
+        }
+        self.cache
+            .get_or_fetch(mint, async {
+                match self.first_quote(mint).await? {
+                    (Quote::Usd(price), _) => Ok(price),
+                    (Quote::Sol(price), _) => Ok(price * self.sol_price_usd().await?),
+                }
+            })
+            .await
+    }
+
+    /// [`Self::price_usd`] of every mint in `mints`, in their order. Prices
+    /// not cached are asked for in batches, DexScreener pricing a batch in
+    /// one request, so a wallet of many tokens doesn't take a call each.
+    pub async fn prices_usd(&self, mints: &[String]) -> Vec<Result<f64>> {
+        self.cache
+            .get_or_fetch_many(mints, |misses| self.fetch_prices(misses))
+            .await
+    }
+}
+
+/// Price the rest of the process with `feed`, replacing the one before
+pub fn install(feed: PriceAggregator) {
+    *FEED.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(feed));
+}
+
+/// The installed feed, the default sources until one is
+pub fn feed() -> Arc<PriceAggregator> {
+    if let Some(feed) = FEED.read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        return Arc::clone(feed);
+    }
+    let mut feed = FEED.write().unwrap_or_else(|e| e.into_inner());
+    Arc::clone(
+        feed.get_or_insert_with(|| {
+            Arc::new(PriceAggregator::new(&PriceFeedConfig::default(), None))
+        }),
+    )
+}
+
+/// USD price of SOL from the installed feed
+pub async fn sol_price_usd() -> Result<f64> {
+    feed().sol_price_usd().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pyth_price() {
+        assert_eq!(pyth_price("14530000000", -8).unwrap(), 145.3);
+        assert!(pyth_price("n/a", -8).is_err());
+    }
+
+    #[test]
+    fn test_next_slot() {
+        let now = Instant::now();
+        let interval = Duration::from_millis(200);
+        // Idle limiter, the call goes at once
+        assert_eq!(next_slot(now, now, interval), (now, now + interval));
+        // A call just went, the next waits for its slot
+        let busy = now + interval;
+        assert_eq!(next_slot(now, busy, interval), (busy, busy + interval));
+    }
+}
@@ -0,0 +1,113 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use listen_kit::signer::TransactionSigner;
+use solana_sdk::{pubkey::Pubkey, signature::Signature, transaction::Transaction};
+use std::str::FromStr;
+
+use crate::config::PrivyConfig;
+use crate::solana::rpc::make_rpc_client;
+use crate::solana::wallet_manager::WalletManager;
+
+/// Signer of `SIGNER_BACKEND=privy`: transactions are signed by a Privy
+/// server wallet and sent through our own RPC endpoints, the key never
+/// leaves Privy. Replaces the signer that sends, the blockhash, policy and
+/// tracking signers still wrap it.
+pub struct PrivySigner {
+    wallets: WalletManager,
+    wallet_id: String,
+    pubkey: Pubkey,
+}
+
+impl PrivySigner {
+    /// Look up the wallet of `PRIVY_WALLET_ID`
+    pub async fn connect(config: PrivyConfig) -> Result<Self> {
+        let wallet_id = config.wallet_id.clone().ok_or_else(|| {
+            anyhow!("SIGNER_BACKEND=privy needs PRIVY_WALLET_ID, create one with privy-wallet")
+        })?;
+        let wallets = WalletManager::new(config);
+        let wallet = wallets.wallet(&wallet_id).await?;
+        tracing::info!(
+            "Signing with Privy wallet {} ({})",
+            wallet.id,
+            wallet.address
+        );
+        Ok(Self {
+            wallets,
+            wallet_id,
+            pubkey: Pubkey::from_str(&wallet.address)?,
+        })
+    }
+}
+
+#[async_trait]
+impl TransactionSigner for PrivySigner {
+    fn pubkey(&self) -> String {
+        self.pubkey.to_string()
+    }
+
+    async fn sign_and_send_solana_transaction(&self, tx: &mut Transaction) -> Result<String> {
+        let position = signer_position(tx, &self.pubkey)?;
+        let signed = self.wallets.sign_transaction(&self.wallet_id, tx).await?;
+        check_signed(tx, &signed, position)?;
+        *tx = signed;
+
+        let signature = make_rpc_client().send_transaction(tx).await?;
+        Ok(signature.to_string())
+    }
+}
+
+/// Index of the signature `pubkey` owes `tx`
+fn signer_position(tx: &Transaction, pubkey: &Pubkey) -> Result<usize> {
+    tx.message
+        .account_keys
+        .iter()
+        .take(tx.message.header.num_required_signatures as usize)
+        .position(|key| key == pubkey)
+        .ok_or_else(|| anyhow!("Transaction doesn't need a signature of {}", pubkey))
+}
+
+/// Privy must return `tx` as it was sent, with a valid signature at
+/// `position`
+fn check_signed(tx: &Transaction, signed: &Transaction, position: usize) -> Result<()> {
+    if signed.message != tx.message || signed.signatures[position] == Signature::default() {
+        return Err(anyhow!(
+            "Privy returned a different or unsigned transaction"
+        ));
+    }
+    signed.verify()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{hash::Hash, signature::Keypair, signer::Signer, system_instruction};
+
+    #[test]
+    fn test_check_signed() {
+        let wallet = Keypair::new();
+        let unsigned = Transaction::new_with_payer(
+            &[system_instruction::transfer(
+                &wallet.pubkey(),
+                &Pubkey::new_unique(),
+                1,
+            )],
+            Some(&wallet.pubkey()),
+        );
+        assert_eq!(signer_position(&unsigned, &wallet.pubkey()).unwrap(), 0);
+        assert!(signer_position(&unsigned, &Pubkey::new_unique()).is_err());
+
+        let mut signed = unsigned.clone();
+        signed.sign(&[&wallet], Hash::default());
+        assert!(check_signed(&unsigned, &signed, 0).is_ok());
+        assert!(check_signed(&unsigned, &unsigned, 0).is_err());
+
+        // Signed by another key, or over another message
+        let mut forged = signed.clone();
+        forged.signatures[0] = Keypair::new().sign_message(&forged.message_data());
+        assert!(check_signed(&unsigned, &forged, 0).is_err());
+        let mut altered = signed.clone();
+        altered.message.recent_blockhash = Hash::new_unique();
+        assert!(check_signed(&unsigned, &altered, 0).is_err());
+    }
+}
@@ -0,0 +1,636 @@
+use anyhow::{anyhow, Result};
+use futures::future::join_all;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::solana::best_venue::shortfall_bps;
+use crate::solana::cache::cached_search_ticker;
+use crate::solana::meteora::{dlmm_amount_out, get_lb_pair, LbPairLayout, FEE_PRECISION};
+use crate::solana::orca::{
+    get_whirlpool, whirlpool_amount_out, WhirlpoolLayout, WHIRLPOOL_FEE_RATE_DENOMINATOR,
+};
+use crate::solana::raydium::{
+    constant_product_amount_out, get_raydium_cpmm_pool, get_raydium_cpmm_swap_reserves,
+    get_raydium_pool, get_raydium_v4_reserves, token_account_amount,
+    RAYDIUM_CPMM_FEE_RATE_DENOMINATOR,
+};
+use crate::solana::route::{detect_pool_program, PoolProgram};
+use crate::solana::ws::{bonding_curve_address, parse_bonding_curve};
+
+/// Pump.fun's fee on the SOL side of bonding curve trades
+pub const PUMP_FEE_BPS: u64 = 100;
+/// LP and protocol fee of PumpSwap pools
+pub const PUMP_AMM_FEE_BPS: u64 = 25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+    /// SOL in, tokens out
+    Buy,
+    /// Tokens in, SOL out
+    Sell,
+}
+
+impl FromStr for Side {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "buy" => Ok(Side::Buy),
+            "sell" => Ok(Side::Sell),
+            other => Err(anyhow!("Unknown side '{}', expected buy or sell", other)),
+        }
+    }
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Side::Buy => write!(f, "buy"),
+            Side::Sell => write!(f, "sell"),
+        }
+    }
+}
+
+/// Where a quoted swap goes
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum QuoteRoute {
+    BondingCurve {
+        bonding_curve: String,
+    },
+    Pool {
+        pool: String,
+        program: String,
+    },
+    /// Jupiter's route, the AMMs of its hops
+    Jupiter {
+        hops: Vec<String>,
+    },
+}
+
+impl fmt::Display for QuoteRoute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuoteRoute::BondingCurve { bonding_curve } => {
+                write!(f, "Pump.fun bonding curve {}", bonding_curve)
+            }
+            QuoteRoute::Pool { pool, program } => write!(f, "{} pool {}", program, pool),
+            QuoteRoute::Jupiter { hops } => write!(f, "Jupiter via {}", hops.join(" > ")),
+        }
+    }
+}
+
+/// Output, fee and price impact of one swap
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SwapEstimate {
+    /// Tokens for a buy, lamports for a sell
+    pub amount_out: u64,
+    pub fee_bps: u64,
+    /// How much less `amount_out` is than the same swap at the spot price,
+    /// fee aside
+    pub price_impact_bps: u64,
+}
+
+/// Best quote found for a swap
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Quote {
+    pub mint: String,
+    pub side: Side,
+    /// Lamports for a buy, tokens for a sell
+    pub amount_in: u64,
+    /// Tokens for a buy, lamports for a sell, before slippage
+    pub amount_out: u64,
+    pub price_impact_bps: u64,
+    pub fee_bps: u64,
+    pub route: QuoteRoute,
+}
+
+impl Quote {
+    fn new(
+        mint: &str,
+        side: Side,
+        amount_in: u64,
+        estimate: SwapEstimate,
+        route: QuoteRoute,
+    ) -> Self {
+        Self {
+            mint: mint.to_string(),
+            side,
+            amount_in,
+            amount_out: estimate.amount_out,
+            price_impact_bps: estimate.price_impact_bps,
+            fee_bps: estimate.fee_bps,
+            route,
+        }
+    }
+}
+
+impl fmt::Display for Quote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} of {}: {} {} out through {}, {} bps impact, {} bps fee",
+            self.side,
+            self.amount_in,
+            self.mint,
+            self.amount_out,
+            match self.side {
+                Side::Buy => "tokens",
+                Side::Sell => "lamports",
+            },
+            self.route,
+            self.price_impact_bps,
+            self.fee_bps
+        )
+    }
+}
+
+fn mul_div(amount: u64, numerator: u64, denominator: u64) -> u64 {
+    if denominator == 0 {
+        return 0;
+    }
+    (amount as u128 * numerator as u128 / denominator as u128) as u64
+}
+
+/// Swap through a constant product pool with the fee taken from the input
+pub fn constant_product_swap(
+    amount_in: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> SwapEstimate {
+    let fee_numerator = fee_numerator.min(fee_denominator);
+    let after_fee = amount_in - mul_div(amount_in, fee_numerator, fee_denominator);
+    let amount_out = constant_product_amount_out(
+        amount_in,
+        reserve_in,
+        reserve_out,
+        fee_numerator,
+        fee_denominator,
+    );
+    SwapEstimate {
+        amount_out,
+        fee_bps: mul_div(10_000, fee_numerator, fee_denominator),
+        price_impact_bps: shortfall_bps(mul_div(after_fee, reserve_out, reserve_in), amount_out),
+    }
+}
+
+/// Swap on a Pump.fun bonding curve of the given virtual reserves. Buys pay
+/// the fee on top of the SOL going into the curve, sells out of the SOL
+/// coming out of it.
+pub fn bonding_curve_swap(
+    virtual_token_reserves: u64,
+    virtual_sol_reserves: u64,
+    amount_in: u64,
+    side: Side,
+) -> SwapEstimate {
+    match side {
+        Side::Buy => {
+            let sol_in = mul_div(amount_in, 10_000, 10_000 + PUMP_FEE_BPS);
+            let estimate =
+                constant_product_swap(sol_in, virtual_sol_reserves, virtual_token_reserves, 0, 1);
+            SwapEstimate {
+                fee_bps: PUMP_FEE_BPS,
+                ..estimate
+            }
+        }
+        Side::Sell => {
+            let gross = constant_product_swap(
+                amount_in,
+                virtual_token_reserves,
+                virtual_sol_reserves,
+                0,
+                1,
+            );
+            SwapEstimate {
+                amount_out: gross.amount_out - mul_div(gross.amount_out, PUMP_FEE_BPS, 10_000),
+                fee_bps: PUMP_FEE_BPS,
+                price_impact_bps: gross.price_impact_bps,
+            }
+        }
+    }
+}
+
+/// Swap through a Whirlpool within its current tick range
+pub fn whirlpool_swap(pool: &WhirlpoolLayout, amount_in: u64, a_to_b: bool) -> SwapEstimate {
+    let fee_rate = (pool.fee_rate as u64).min(WHIRLPOOL_FEE_RATE_DENOMINATOR);
+    let after_fee = amount_in - mul_div(amount_in, fee_rate, WHIRLPOOL_FEE_RATE_DENOMINATOR);
+    let price = (pool.sqrt_price as f64 / 2f64.powi(64)).powi(2);
+    let spot = match a_to_b {
+        true => after_fee as f64 * price,
+        false if price > 0.0 => after_fee as f64 / price,
+        false => 0.0,
+    };
+    let amount_out = whirlpool_amount_out(pool, amount_in, a_to_b);
+    SwapEstimate {
+        amount_out,
+        fee_bps: mul_div(10_000, fee_rate, WHIRLPOOL_FEE_RATE_DENOMINATOR),
+        price_impact_bps: shortfall_bps(spot as u64, amount_out),
+    }
+}
+
+/// Swap through the active bin of a DLMM pool, which has no price impact
+/// until the swap leaves the bin
+pub fn dlmm_swap(pool: &LbPairLayout, amount_in: u64, swap_for_y: bool) -> SwapEstimate {
+    SwapEstimate {
+        amount_out: dlmm_amount_out(pool, amount_in, swap_for_y),
+        fee_bps: mul_div(10_000, pool.total_fee_rate(), FEE_PRECISION),
+        price_impact_bps: 0,
+    }
+}
+
+/// Mints and vaults of a PumpSwap pool: base mint, quote mint, base vault,
+/// quote vault
+fn parse_pump_amm_pool(data: &[u8]) -> Result<[Pubkey; 4]> {
+    // Discriminator, bump (u8), index (u16), creator, then the mints and
+    // vaults with the LP mint in between
+    let key = |offset: usize| -> Result<Pubkey> {
+        Ok(Pubkey::new_from_array(
+            data.get(offset..offset + 32)
+                .ok_or_else(|| anyhow!("Invalid PumpSwap pool length: {}", data.len()))?
+                .try_into()?,
+        ))
+    };
+    Ok([key(43)?, key(75)?, key(139)?, key(171)?])
+}
+
+/// Swap of `amount_in` of `input_mint` through `pool` at its current
+/// reserves or price
+pub async fn quote_swap(
+    rpc_client: &RpcClient,
+    pool: &Pubkey,
+    program: PoolProgram,
+    input_mint: &Pubkey,
+    amount_in: u64,
+) -> Result<SwapEstimate> {
+    match program {
+        PoolProgram::RaydiumAmmV4 => {
+            let layout = get_raydium_pool(rpc_client, pool).await?;
+            let (reserve_in, reserve_out) =
+                get_raydium_v4_reserves(rpc_client, &layout, input_mint).await?;
+            Ok(constant_product_swap(
+                amount_in,
+                reserve_in,
+                reserve_out,
+                layout.swap_fee_numerator,
+                layout.swap_fee_denominator,
+            ))
+        }
+        PoolProgram::RaydiumCpmm => {
+            let layout = get_raydium_cpmm_pool(rpc_client, pool).await?;
+            let (reserve_in, reserve_out, fee_rate) =
+                get_raydium_cpmm_swap_reserves(rpc_client, &layout, input_mint).await?;
+            Ok(constant_product_swap(
+                amount_in,
+                reserve_in,
+                reserve_out,
+                fee_rate,
+                RAYDIUM_CPMM_FEE_RATE_DENOMINATOR,
+            ))
+        }
+        PoolProgram::PumpAmm => {
+            let data = rpc_client.get_account_data(pool).await?;
+            let [base_mint, quote_mint, base_vault, quote_vault] = parse_pump_amm_pool(&data)?;
+            let accounts = rpc_client
+                .get_multiple_accounts(&[base_vault, quote_vault])
+                .await?;
+            let [Some(base), Some(quote)] = accounts.as_slice() else {
+                return Err(anyhow!("Vaults of pool {} not found", pool));
+            };
+            let base = token_account_amount(&base.data)?;
+            let quote = token_account_amount(&quote.data)?;
+            let (reserve_in, reserve_out) = if *input_mint == base_mint {
+                (base, quote)
+            } else if *input_mint == quote_mint {
+                (quote, base)
+            } else {
+                return Err(anyhow!("Mint {} is not part of pool {}", input_mint, pool));
+            };
+            Ok(constant_product_swap(
+                amount_in,
+                reserve_in,
+                reserve_out,
+                PUMP_AMM_FEE_BPS,
+                10_000,
+            ))
+        }
+        PoolProgram::OrcaWhirlpool => {
+            let layout = get_whirlpool(rpc_client, pool).await?;
+            let a_to_b = layout.a_to_b(input_mint)?;
+            Ok(whirlpool_swap(&layout, amount_in, a_to_b))
+        }
+        PoolProgram::MeteoraDlmm => {
+            let layout = get_lb_pair(rpc_client, pool).await?;
+            let swap_for_y = layout.swap_for_y(input_mint)?;
+            Ok(dlmm_swap(&layout, amount_in, swap_for_y))
+        }
+        other => Err(anyhow!("{} pools can't be quoted", other)),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JupiterQuote {
+    out_amount: String,
+    #[serde(default)]
+    price_impact_pct: Option<String>,
+    #[serde(default)]
+    route_plan: Vec<JupiterRoutePlan>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JupiterRoutePlan {
+    swap_info: JupiterSwapInfo,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JupiterSwapInfo {
+    #[serde(default)]
+    label: Option<String>,
+    amm_key: String,
+    input_mint: String,
+    in_amount: String,
+    #[serde(default)]
+    fee_amount: Option<String>,
+    #[serde(default)]
+    fee_mint: Option<String>,
+}
+
+/// Jupiter's route for the swap and the AMMs of its hops
+pub async fn jupiter_route(
+    quote_url: &str,
+    mint: &str,
+    amount_in: u64,
+    side: Side,
+) -> Result<(SwapEstimate, Vec<String>)> {
+    let wsol = spl_token::native_mint::id().to_string();
+    let (input_mint, output_mint) = match side {
+        Side::Buy => (wsol.as_str(), mint),
+        Side::Sell => (mint, wsol.as_str()),
+    };
+    let quote: JupiterQuote = Client::new()
+        .get(quote_url)
+        .query(&[
+            ("inputMint", input_mint),
+            ("outputMint", output_mint),
+            ("amount", &amount_in.to_string()),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    // Fees of the hops charged in their input, relative to it
+    let fee_bps = quote
+        .route_plan
+        .iter()
+        .map(|hop| &hop.swap_info)
+        .filter(|swap| swap.fee_mint.as_deref() == Some(swap.input_mint.as_str()))
+        .filter_map(|swap| {
+            let fee: u64 = swap.fee_amount.as_deref()?.parse().ok()?;
+            Some(mul_div(10_000, fee, swap.in_amount.parse().ok()?))
+        })
+        .sum();
+    let price_impact_pct: f64 = quote
+        .price_impact_pct
+        .as_deref()
+        .map_or(Ok(0.0), str::parse)?;
+    let hops = quote
+        .route_plan
+        .into_iter()
+        .map(|hop| hop.swap_info.label.unwrap_or(hop.swap_info.amm_key))
+        .collect();
+    Ok((
+        SwapEstimate {
+            amount_out: quote.out_amount.parse()?,
+            fee_bps,
+            price_impact_bps: (price_impact_pct.abs() * 10_000.0) as u64,
+        },
+        hops,
+    ))
+}
+
+/// Quotes swaps of a token against SOL on every venue it trades on: its
+/// Pump.fun bonding curve while the token is on it, otherwise its pools
+/// found on DexScreener (Raydium, PumpSwap, Orca, Meteora) and Jupiter's
+/// route when a quote URL is set. Quotes are read from the reserves or
+/// prices of the pools and carry no slippage.
+pub struct Quoter {
+    rpc_client: RpcClient,
+    jupiter_quote_url: Option<String>,
+}
+
+impl Quoter {
+    pub fn new(rpc_client: RpcClient) -> Self {
+        Self {
+            rpc_client,
+            jupiter_quote_url: None,
+        }
+    }
+
+    /// Also quote Jupiter's route at `quote_url`
+    pub fn with_jupiter(mut self, quote_url: String) -> Self {
+        self.jupiter_quote_url = Some(quote_url);
+        self
+    }
+
+    /// Best quote to swap `amount_in` of `mint` (a sell) or of lamports for
+    /// it (a buy)
+    pub async fn get_quote(&self, mint: &str, side: Side, amount_in: u64) -> Result<Quote> {
+        let mint_key = Pubkey::from_str(mint)?;
+
+        // Tokens still on their bonding curve only trade there
+        if let Some((estimate, route)) = self.bonding_curve(&mint_key, side, amount_in).await? {
+            return Ok(Quote::new(mint, side, amount_in, estimate, route));
+        }
+
+        let pairs = match cached_search_ticker(mint).await {
+            Ok(response) => response.pairs,
+            Err(e) => {
+                tracing::warn!(
+                    "No DexScreener pairs of {}, pools not quoted: {:?}",
+                    mint,
+                    e
+                );
+                Vec::new()
+            }
+        };
+        let mut pools: Vec<Pubkey> = Vec::new();
+        for pair in pairs
+            .iter()
+            .filter(|pair| pair.chain_id == "solana")
+            .filter(|pair| pair.base_token.address == mint || pair.quote_token.address == mint)
+        {
+            let pool = Pubkey::from_str(&pair.pair_address)?;
+            if !pools.contains(&pool) {
+                pools.push(pool);
+            }
+        }
+        let quotes = join_all(
+            pools
+                .iter()
+                .map(|pool| self.quote_pool(mint, side, amount_in, pool)),
+        )
+        .await;
+        let mut best: Option<Quote> = None;
+        for (pool, quote) in pools.iter().zip(quotes) {
+            match quote {
+                Ok(quote) => {
+                    if best.as_ref().map_or(quote.amount_out > 0, |best| {
+                        quote.amount_out > best.amount_out
+                    }) {
+                        best = Some(quote);
+                    }
+                }
+                Err(e) => tracing::debug!("Pool {} of {} not quoted: {:?}", pool, mint, e),
+            }
+        }
+
+        if self.jupiter_quote_url.is_some() {
+            match self.quote_jupiter(mint, side, amount_in).await {
+                Ok(quote)
+                    if best
+                        .as_ref()
+                        .is_none_or(|best| quote.amount_out > best.amount_out) =>
+                {
+                    best = Some(quote);
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Jupiter quote for {} failed: {:?}", mint, e),
+            }
+        }
+        best.ok_or_else(|| anyhow!("No venue quoted the {} of {}", side, mint))
+    }
+
+    /// Quote on the bonding curve of `mint`, an error once it completed
+    pub async fn quote_bonding_curve(
+        &self,
+        mint: &str,
+        side: Side,
+        amount_in: u64,
+    ) -> Result<Quote> {
+        let (estimate, route) = self
+            .bonding_curve(&Pubkey::from_str(mint)?, side, amount_in)
+            .await?
+            .ok_or_else(|| anyhow!("{} is not on its bonding curve", mint))?;
+        Ok(Quote::new(mint, side, amount_in, estimate, route))
+    }
+
+    /// Quote through `pool`, whatever program it belongs to
+    pub async fn quote_pool(
+        &self,
+        mint: &str,
+        side: Side,
+        amount_in: u64,
+        pool: &Pubkey,
+    ) -> Result<Quote> {
+        let input_mint = match side {
+            Side::Buy => spl_token::native_mint::id(),
+            Side::Sell => Pubkey::from_str(mint)?,
+        };
+        let program = detect_pool_program(&self.rpc_client, pool).await?;
+        let estimate = quote_swap(&self.rpc_client, pool, program, &input_mint, amount_in).await?;
+        let route = QuoteRoute::Pool {
+            pool: pool.to_string(),
+            program: program.to_string(),
+        };
+        Ok(Quote::new(mint, side, amount_in, estimate, route))
+    }
+
+    /// Quote along Jupiter's route, needs a quote URL
+    pub async fn quote_jupiter(&self, mint: &str, side: Side, amount_in: u64) -> Result<Quote> {
+        let quote_url = self
+            .jupiter_quote_url
+            .as_deref()
+            .ok_or_else(|| anyhow!("No Jupiter quote URL to quote {} with", mint))?;
+        let (estimate, hops) = jupiter_route(quote_url, mint, amount_in, side).await?;
+        Ok(Quote::new(
+            mint,
+            side,
+            amount_in,
+            estimate,
+            QuoteRoute::Jupiter { hops },
+        ))
+    }
+
+    /// Swap on the bonding curve of `mint`, `None` once it completed or
+    /// without one
+    async fn bonding_curve(
+        &self,
+        mint: &Pubkey,
+        side: Side,
+        amount_in: u64,
+    ) -> Result<Option<(SwapEstimate, QuoteRoute)>> {
+        let bonding_curve = bonding_curve_address(mint)?;
+        let Some(account) = self
+            .rpc_client
+            .get_account_with_commitment(&bonding_curve, CommitmentConfig::confirmed())
+            .await?
+            .value
+        else {
+            return Ok(None);
+        };
+        let (virtual_token_reserves, virtual_sol_reserves, complete) =
+            parse_bonding_curve(&account.data)?;
+        if complete {
+            return Ok(None);
+        }
+        Ok(Some((
+            bonding_curve_swap(
+                virtual_token_reserves,
+                virtual_sol_reserves,
+                amount_in,
+                side,
+            ),
+            QuoteRoute::BondingCurve {
+                bonding_curve: bonding_curve.to_string(),
+            },
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constant_product_swap() {
+        // 1 SOL into 100 SOL / 1M tokens at 25 bps
+        let estimate = constant_product_swap(
+            1_000_000_000,
+            100_000_000_000,
+            1_000_000_000_000,
+            25,
+            10_000,
+        );
+        assert_eq!(estimate.fee_bps, 25);
+        assert_eq!(estimate.amount_out, 9_876_482_091);
+        // The fee aside, 1% of the reserve moves the price ~1%
+        assert_eq!(estimate.price_impact_bps, 98);
+    }
+
+    #[test]
+    fn test_bonding_curve_swap() {
+        // A fresh curve: 1.073B virtual tokens against 30 virtual SOL
+        let tokens = 1_073_000_000_000_000;
+        let sol = 30_000_000_000;
+        let buy = bonding_curve_swap(tokens, sol, 1_010_000_000, Side::Buy);
+        assert_eq!(buy.fee_bps, PUMP_FEE_BPS);
+        // 1 SOL reaches the curve after the fee
+        assert_eq!(buy.amount_out, 34_612_903_225_806);
+        assert_eq!(buy.price_impact_bps, 322);
+
+        let sell = bonding_curve_swap(tokens, sol, buy.amount_out, Side::Sell);
+        assert!(sell.amount_out < 1_000_000_000);
+        assert_eq!(sell.fee_bps, PUMP_FEE_BPS);
+    }
+}
@@ -549,45 +549,90 @@ pub async fn get_serum_accounts(
     }
 }
 
-pub fn calculate_minimum_amount_out(
-    pool_state: &RaydiumPoolLayout,
-    amount_in: u64,
-    slippage_tolerance: f64, // e.g., 0.01 for 1%
-) -> u64 {
-    return 0;
-    // First get the swap fee
-    let fee_numerator = pool_state.swap_fee_numerator;
-    let fee_denominator = pool_state.swap_fee_denominator;
-
-    // Get current pool ratios
-    let base_amount = pool_state.swap_base_in_amount;
-    let quote_amount = pool_state.swap_quote_out_amount;
-
-    // Calculate the swap fee
-    let fee_amount = amount_in
-        .checked_mul(fee_numerator)
-        .unwrap()
-        .checked_div(fee_denominator)
-        .unwrap();
+/// Reserves of a V4 pool for a swap of `input_mint`, input side first, from
+/// the token balances of its vaults. PnL owed to the pool owner sits in the
+/// vaults but can't be swapped. The `swap_*_amount` fields of the pool are
+/// lifetime volumes, not reserves.
+pub fn v4_swap_reserves(
+    pool: &RaydiumPoolLayout,
+    base_vault_amount: u64,
+    quote_vault_amount: u64,
+    input_mint: &Pubkey,
+) -> Result<(u64, u64)> {
+    let base = base_vault_amount.saturating_sub(pool.base_need_take_pnl);
+    let quote = quote_vault_amount.saturating_sub(pool.quote_need_take_pnl);
+    if *input_mint == pool.base_mint {
+        Ok((base, quote))
+    } else if *input_mint == pool.quote_mint {
+        Ok((quote, base))
+    } else {
+        Err(anyhow!("Mint {} is not part of the V4 pool", input_mint))
+    }
+}
 
-    // Amount after fee
-    let amount_in_after_fees = amount_in.checked_sub(fee_amount).unwrap();
-
-    // Calculate expected output using constant product formula (x * y = k)
-    let amount_out = quote_amount
-        .checked_mul(amount_in_after_fees as u128)
-        .unwrap()
-        .checked_div(
-            base_amount
-                .checked_add(amount_in_after_fees as u128)
-                .unwrap(),
-        )
-        .unwrap();
+/// Token balances of the base and quote vaults of a V4 pool
+async fn get_raydium_v4_vault_amounts(
+    rpc_client: &RpcClient,
+    pool: &RaydiumPoolLayout,
+) -> Result<(u64, u64)> {
+    let accounts = rpc_client
+        .get_multiple_accounts(&[pool.base_vault, pool.quote_vault])
+        .await?;
+    let [Some(base_vault), Some(quote_vault)] = accounts.as_slice() else {
+        return Err(anyhow!("Vaults of V4 pool not found"));
+    };
+    Ok((
+        token_account_amount(&base_vault.data)?,
+        token_account_amount(&quote_vault.data)?,
+    ))
+}
+
+/// Reserves of a V4 pool for a swap of `input_mint`, input side first, read
+/// from its vaults
+pub async fn get_raydium_v4_reserves(
+    rpc_client: &RpcClient,
+    pool: &RaydiumPoolLayout,
+    input_mint: &Pubkey,
+) -> Result<(u64, u64)> {
+    let (base, quote) = get_raydium_v4_vault_amounts(rpc_client, pool).await?;
+    v4_swap_reserves(pool, base, quote, input_mint)
+}
 
-    // Apply slippage tolerance
-    let min_amount_out = (amount_out as f64 * (1.0 - slippage_tolerance)) as u64;
+/// Minimum output of swapping `amount_in` of `input_mint` through a V4 pool
+/// with the given vault balances, `slippage_bps` below the quote
+pub fn v4_minimum_amount_out(
+    pool: &RaydiumPoolLayout,
+    base_vault_amount: u64,
+    quote_vault_amount: u64,
+    input_mint: &Pubkey,
+    amount_in: u64,
+    slippage_bps: u16,
+) -> Result<u64> {
+    let (reserve_in, reserve_out) =
+        v4_swap_reserves(pool, base_vault_amount, quote_vault_amount, input_mint)?;
+    Ok(apply_slippage(
+        constant_product_amount_out(
+            amount_in,
+            reserve_in,
+            reserve_out,
+            pool.swap_fee_numerator,
+            pool.swap_fee_denominator,
+        ),
+        slippage_bps,
+    ))
+}
 
-    min_amount_out
+/// Minimum output of swapping `amount_in` of `input_mint` through a V4 pool,
+/// `slippage_bps` below the quote from its current vault balances
+pub async fn raydium_v4_minimum_amount_out(
+    rpc_client: &RpcClient,
+    pool: &RaydiumPoolLayout,
+    input_mint: &Pubkey,
+    amount_in: u64,
+    slippage_bps: u16,
+) -> Result<u64> {
+    let (base, quote) = get_raydium_v4_vault_amounts(rpc_client, pool).await?;
+    v4_minimum_amount_out(pool, base, quote, input_mint, amount_in, slippage_bps)
 }
 
 #[derive(BorshSerialize)]
@@ -660,3 +705,334 @@ pub fn make_raydium_swap_ix(
         accounts.to_vec(),
     ))
 }
+
+pub const RAYDIUM_CPMM_PROGRAM: &str = "CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C";
+pub const RAYDIUM_CPMM_AUTHORITY: &str = "GpMZbSM2GgvTKHJirzeGfMFoaZ8UR2X7F4v8vHTvxFbL";
+/// Anchor discriminator of `swap_base_input`
+pub const RAYDIUM_CPMM_SWAP_BASE_INPUT: [u8; 8] = [143, 190, 90, 218, 196, 30, 51, 222];
+pub const RAYDIUM_CPMM_FEE_RATE_DENOMINATOR: u64 = 1_000_000;
+
+/// Raydium CPMM (constant product, no OpenBook market) pool state
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct RaydiumCpmmLayout {
+    pub amm_config: Pubkey,
+    pub pool_creator: Pubkey,
+    pub token_0_vault: Pubkey,
+    pub token_1_vault: Pubkey,
+    pub lp_mint: Pubkey,
+    pub token_0_mint: Pubkey,
+    pub token_1_mint: Pubkey,
+    pub token_0_program: Pubkey,
+    pub token_1_program: Pubkey,
+    pub observation_key: Pubkey,
+    pub auth_bump: u8,
+    pub status: u8,
+    pub lp_mint_decimals: u8,
+    pub mint_0_decimals: u8,
+    pub mint_1_decimals: u8,
+    pub lp_supply: u64,
+    pub protocol_fees_token_0: u64,
+    pub protocol_fees_token_1: u64,
+    pub fund_fees_token_0: u64,
+    pub fund_fees_token_1: u64,
+    pub open_time: u64,
+    pub recent_epoch: u64,
+    pub padding: [u64; 31],
+}
+
+impl RaydiumCpmmLayout {
+    pub const DISCRIMINATOR_LEN: usize = 8;
+    pub const LEN: usize = Self::DISCRIMINATOR_LEN +
+        10 * 32 + // amm_config .. observation_key
+        5 + // auth_bump, status, lp_mint_decimals, mint_0_decimals, mint_1_decimals
+        7 * 8 + // lp_supply .. recent_epoch
+        31 * 8; // padding
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < Self::LEN {
+            return Err(anyhow!("Invalid data length: {}", data.len()));
+        }
+        Ok(Self::try_from_slice(
+            &data[Self::DISCRIMINATOR_LEN..Self::LEN],
+        )?)
+    }
+
+    /// Vault, token program and mint on each side of a swap that sells
+    /// `input_mint`, ordered (input, output)
+    pub fn swap_sides(&self, input_mint: &Pubkey) -> Result<(CpmmSide, CpmmSide)> {
+        let side_0 = CpmmSide {
+            vault: self.token_0_vault,
+            token_program: self.token_0_program,
+            mint: self.token_0_mint,
+        };
+        let side_1 = CpmmSide {
+            vault: self.token_1_vault,
+            token_program: self.token_1_program,
+            mint: self.token_1_mint,
+        };
+        if *input_mint == self.token_0_mint {
+            Ok((side_0, side_1))
+        } else if *input_mint == self.token_1_mint {
+            Ok((side_1, side_0))
+        } else {
+            Err(anyhow!("Mint {} is not part of the pool", input_mint))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CpmmSide {
+    pub vault: Pubkey,
+    pub token_program: Pubkey,
+    pub mint: Pubkey,
+}
+
+pub async fn get_raydium_cpmm_pool(
+    rpc_client: &RpcClient,
+    pool_pubkey: &Pubkey,
+) -> Result<RaydiumCpmmLayout> {
+    let data = rpc_client.get_account_data(pool_pubkey).await?;
+    let layout = RaydiumCpmmLayout::parse(&data)?;
+    debug!("Parsed RaydiumCpmmLayout: {:?}", layout);
+    Ok(layout)
+}
+
+/// Tradable reserves of both vaults (accrued protocol and fund fees excluded)
+/// and the trade fee rate of the pool's AMM config
+pub async fn get_raydium_cpmm_reserves(
+    rpc_client: &RpcClient,
+    pool: &RaydiumCpmmLayout,
+) -> Result<(u64, u64, u64)> {
+    let accounts = rpc_client
+        .get_multiple_accounts(&[pool.token_0_vault, pool.token_1_vault, pool.amm_config])
+        .await?;
+    let [vault_0, vault_1, amm_config] = accounts.as_slice() else {
+        return Err(anyhow!("Unexpected number of accounts returned"));
+    };
+    let data = |account: &Option<solana_sdk::account::Account>| {
+        account
+            .as_ref()
+            .map(|a| a.data.clone())
+            .ok_or_else(|| anyhow!("CPMM pool account not found"))
+    };
+
+    let reserve_0 = token_account_amount(&data(vault_0)?)?
+        .saturating_sub(pool.protocol_fees_token_0 + pool.fund_fees_token_0);
+    let reserve_1 = token_account_amount(&data(vault_1)?)?
+        .saturating_sub(pool.protocol_fees_token_1 + pool.fund_fees_token_1);
+
+    // AmmConfig: discriminator, bump (u8), disable_create_pool (bool), index (u16), trade_fee_rate (u64)
+    let amm_config = data(amm_config)?;
+    let trade_fee_rate = u64::from_le_bytes(
+        amm_config
+            .get(12..20)
+            .ok_or_else(|| anyhow!("Invalid AMM config length: {}", amm_config.len()))?
+            .try_into()?,
+    );
+
+    Ok((reserve_0, reserve_1, trade_fee_rate))
+}
+
+/// Reserves of a CPMM pool for a swap of `input_mint`, input side first, and
+/// its fee rate, read from its vaults
+pub async fn get_raydium_cpmm_swap_reserves(
+    rpc_client: &RpcClient,
+    pool: &RaydiumCpmmLayout,
+    input_mint: &Pubkey,
+) -> Result<(u64, u64, u64)> {
+    let (input, _) = pool.swap_sides(input_mint)?;
+    let (reserve_0, reserve_1, fee_rate) = get_raydium_cpmm_reserves(rpc_client, pool).await?;
+    if input.mint == pool.token_0_mint {
+        Ok((reserve_0, reserve_1, fee_rate))
+    } else {
+        Ok((reserve_1, reserve_0, fee_rate))
+    }
+}
+
+/// Minimum output of swapping `amount_in` of `input_mint` through a CPMM
+/// pool, `slippage_bps` below the quote from its current vault balances
+pub async fn raydium_cpmm_minimum_amount_out(
+    rpc_client: &RpcClient,
+    pool: &RaydiumCpmmLayout,
+    input_mint: &Pubkey,
+    amount_in: u64,
+    slippage_bps: u16,
+) -> Result<u64> {
+    let (reserve_in, reserve_out, fee_rate) =
+        get_raydium_cpmm_swap_reserves(rpc_client, pool, input_mint).await?;
+    Ok(apply_slippage(
+        constant_product_amount_out(
+            amount_in,
+            reserve_in,
+            reserve_out,
+            fee_rate,
+            RAYDIUM_CPMM_FEE_RATE_DENOMINATOR,
+        ),
+        slippage_bps,
+    ))
+}
+
+/// Amount of an SPL token (or Token-2022) account, which sits right after
+/// the mint and owner
+pub fn token_account_amount(data: &[u8]) -> Result<u64> {
+    Ok(u64::from_le_bytes(
+        data.get(64..72)
+            .ok_or_else(|| anyhow!("Invalid token account length: {}", data.len()))?
+            .try_into()?,
+    ))
+}
+
+/// Constant product (x * y = k) output for `amount_in` after the input fee
+pub fn constant_product_amount_out(
+    amount_in: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    fee_numerator: u64,
+    fee_denominator: u64,
+) -> u64 {
+    if fee_denominator == 0 || reserve_in == 0 {
+        return 0;
+    }
+    let fee_numerator = fee_numerator.min(fee_denominator);
+    let amount_in_after_fee =
+        amount_in as u128 * (fee_denominator - fee_numerator) as u128 / fee_denominator as u128;
+    (reserve_out as u128 * amount_in_after_fee / (reserve_in as u128 + amount_in_after_fee)) as u64
+}
+
+pub fn apply_slippage(amount_out: u64, slippage_bps: u16) -> u64 {
+    let slippage_bps = slippage_bps.min(10_000) as u128;
+    (amount_out as u128 * (10_000 - slippage_bps) / 10_000) as u64
+}
+
+/// Interact With Raydium CPMM (CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C), `swap_base_input`
+/// Input Accounts
+/// #1 - Payer (Signer)
+/// #2 - Authority
+/// #3 - Amm Config
+/// #4 - Pool State (Writable)
+/// #5 - Input Token Account (Writable)
+/// #6 - Output Token Account (Writable)
+/// #7 - Input Vault (Writable)
+/// #8 - Output Vault (Writable)
+/// #9 - Input Token Program
+/// #10 - Output Token Program
+/// #11 - Input Token Mint
+/// #12 - Output Token Mint
+/// #13 - Observation State (Writable)
+#[allow(clippy::too_many_arguments)]
+pub fn make_raydium_cpmm_swap_ix(
+    pool_pubkey: Pubkey,
+    pool: &RaydiumCpmmLayout,
+    input_mint: &Pubkey,
+    user_input_token_account: Pubkey,
+    user_output_token_account: Pubkey,
+    owner: Pubkey,
+    amount_in: u64,
+    minimum_amount_out: u64,
+) -> Result<Instruction> {
+    let (input, output) = pool.swap_sides(input_mint)?;
+
+    let accounts = vec![
+        AccountMeta::new(owner, true),
+        AccountMeta::new_readonly(Pubkey::from_str(RAYDIUM_CPMM_AUTHORITY)?, false),
+        AccountMeta::new_readonly(pool.amm_config, false),
+        AccountMeta::new(pool_pubkey, false),
+        AccountMeta::new(user_input_token_account, false),
+        AccountMeta::new(user_output_token_account, false),
+        AccountMeta::new(input.vault, false),
+        AccountMeta::new(output.vault, false),
+        AccountMeta::new_readonly(input.token_program, false),
+        AccountMeta::new_readonly(output.token_program, false),
+        AccountMeta::new_readonly(input.mint, false),
+        AccountMeta::new_readonly(output.mint, false),
+        AccountMeta::new(pool.observation_key, false),
+    ];
+
+    let mut data = RAYDIUM_CPMM_SWAP_BASE_INPUT.to_vec();
+    data.extend_from_slice(&amount_in.to_le_bytes());
+    data.extend_from_slice(&minimum_amount_out.to_le_bytes());
+
+    Ok(Instruction {
+        program_id: Pubkey::from_str(RAYDIUM_CPMM_PROGRAM)?,
+        accounts,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A V4 pool of a graduated pump.fun token against WSOL, 79 SOL and
+    /// 206M tokens in its vaults, with the lifetime volumes the old
+    /// calculation mistook for reserves
+    fn graduated_pool() -> RaydiumPoolLayout {
+        let mut pool = RaydiumPoolLayout::parse(&[0; RaydiumPoolLayout::LEN]).unwrap();
+        pool.base_decimal = 6;
+        pool.quote_decimal = 9;
+        pool.swap_fee_numerator = 25;
+        pool.swap_fee_denominator = 10_000;
+        pool.base_need_take_pnl = 1_000_000;
+        pool.quote_need_take_pnl = 5_000_000;
+        pool.swap_base_in_amount = 9_000_000_000_000_000;
+        pool.swap_quote_out_amount = 3_000_000_000_000;
+        pool.base_mint = Pubkey::new_unique();
+        pool.quote_mint = spl_token::native_mint::id();
+        pool
+    }
+
+    const BASE_VAULT: u64 = 206_000_000_000_000;
+    const QUOTE_VAULT: u64 = 79_005_000_000;
+
+    #[test]
+    fn test_v4_swap_reserves() {
+        let pool = graduated_pool();
+        let wsol = spl_token::native_mint::id();
+        // The owner's PnL is taken out of both vaults
+        assert_eq!(
+            v4_swap_reserves(&pool, BASE_VAULT, QUOTE_VAULT, &wsol).unwrap(),
+            (79_000_000_000, 205_999_999_000_000)
+        );
+        assert_eq!(
+            v4_swap_reserves(&pool, BASE_VAULT, QUOTE_VAULT, &pool.base_mint).unwrap(),
+            (205_999_999_000_000, 79_000_000_000)
+        );
+        assert!(v4_swap_reserves(&pool, BASE_VAULT, QUOTE_VAULT, &Pubkey::new_unique()).is_err());
+    }
+
+    #[test]
+    fn test_v4_minimum_amount_out() {
+        let pool = graduated_pool();
+        let wsol = spl_token::native_mint::id();
+
+        // Buying with 1 SOL: ~2.57M tokens before slippage, 5% below
+        let buy = v4_minimum_amount_out(&pool, BASE_VAULT, QUOTE_VAULT, &wsol, 1_000_000_000, 500)
+            .unwrap();
+        assert_eq!(buy, 2_440_210_619_736);
+
+        // Selling those tokens back gets less than the SOL spent
+        let sell = v4_minimum_amount_out(
+            &pool,
+            BASE_VAULT,
+            QUOTE_VAULT,
+            &pool.base_mint,
+            2_568_642_757_617,
+            0,
+        )
+        .unwrap();
+        assert_eq!(sell, 970_527_983);
+
+        // No slippage allowed is the quote itself
+        let quote = constant_product_amount_out(
+            1_000_000_000,
+            79_000_000_000,
+            205_999_999_000_000,
+            25,
+            10_000,
+        );
+        assert_eq!(
+            v4_minimum_amount_out(&pool, BASE_VAULT, QUOTE_VAULT, &wsol, 1_000_000_000, 0).unwrap(),
+            quote
+        );
+    }
+}
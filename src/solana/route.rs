@@ -0,0 +1,215 @@
+use anyhow::{anyhow, Result};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+use crate::solana::dexscreener::PairInfo;
+use crate::solana::raydium::{RAYDIUM_CPMM_PROGRAM, RAYDIUM_V4_PROGRAM};
+
+pub const RAYDIUM_CLMM_PROGRAM: &str = "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK";
+pub const PUMP_AMM_PROGRAM: &str = "pAMMBay6oceH9fJKBRHGP5D4bD4sWpmSwMn52FMfXEA";
+pub const ORCA_WHIRLPOOL_PROGRAM: &str = "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc";
+pub const METEORA_DLMM_PROGRAM: &str = "LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo";
+pub const METEORA_AMM_PROGRAM: &str = "Eo7WjKq67rjJQSZxS6z3YkapzY3eMj6Xy8X5EQVn5UaB";
+
+/// Program owning a pool account, it decides which layout parser and swap
+/// builder apply to the pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolProgram {
+    RaydiumAmmV4,
+    RaydiumCpmm,
+    RaydiumClmm,
+    PumpAmm,
+    OrcaWhirlpool,
+    MeteoraDlmm,
+    MeteoraAmm,
+}
+
+impl PoolProgram {
+    pub const ALL: [PoolProgram; 7] = [
+        PoolProgram::RaydiumAmmV4,
+        PoolProgram::RaydiumCpmm,
+        PoolProgram::RaydiumClmm,
+        PoolProgram::PumpAmm,
+        PoolProgram::OrcaWhirlpool,
+        PoolProgram::MeteoraDlmm,
+        PoolProgram::MeteoraAmm,
+    ];
+
+    pub fn program_id(&self) -> &'static str {
+        match self {
+            PoolProgram::RaydiumAmmV4 => RAYDIUM_V4_PROGRAM,
+            PoolProgram::RaydiumCpmm => RAYDIUM_CPMM_PROGRAM,
+            PoolProgram::RaydiumClmm => RAYDIUM_CLMM_PROGRAM,
+            PoolProgram::PumpAmm => PUMP_AMM_PROGRAM,
+            PoolProgram::OrcaWhirlpool => ORCA_WHIRLPOOL_PROGRAM,
+            PoolProgram::MeteoraDlmm => METEORA_DLMM_PROGRAM,
+            PoolProgram::MeteoraAmm => METEORA_AMM_PROGRAM,
+        }
+    }
+
+    pub fn from_owner(owner: &Pubkey) -> Option<Self> {
+        let owner = owner.to_string();
+        Self::ALL
+            .into_iter()
+            .find(|program| program.program_id() == owner)
+    }
+
+    /// Whether pools of this program can be priced and swapped through
+    pub fn is_supported(&self) -> bool {
+        matches!(
+            self,
+            PoolProgram::RaydiumAmmV4
+                | PoolProgram::RaydiumCpmm
+                | PoolProgram::OrcaWhirlpool
+                | PoolProgram::MeteoraDlmm
+        )
+    }
+}
+
+impl fmt::Display for PoolProgram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PoolProgram::RaydiumAmmV4 => "Raydium AMM v4",
+            PoolProgram::RaydiumCpmm => "Raydium CPMM",
+            PoolProgram::RaydiumClmm => "Raydium CLMM",
+            PoolProgram::PumpAmm => "Pump AMM",
+            PoolProgram::OrcaWhirlpool => "Orca Whirlpool",
+            PoolProgram::MeteoraDlmm => "Meteora DLMM",
+            PoolProgram::MeteoraAmm => "Meteora AMM",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Program of the pool at `pool` from the owner of its account
+pub async fn detect_pool_program(rpc_client: &RpcClient, pool: &Pubkey) -> Result<PoolProgram> {
+    let owner = rpc_client.get_account(pool).await?.owner;
+    PoolProgram::from_owner(&owner)
+        .ok_or_else(|| anyhow!("Pool {} is owned by unknown program {}", pool, owner))
+}
+
+/// Most liquid Solana pair of `mint` on DexScreener
+pub fn primary_pair<'a>(mint: &str, pairs: &'a [PairInfo]) -> Option<&'a PairInfo> {
+    pairs
+        .iter()
+        .filter(|pair| pair.chain_id == "solana")
+        .filter(|pair| pair.base_token.address == mint || pair.quote_token.address == mint)
+        .max_by(|a, b| a.liquidity.usd.total_cmp(&b.liquidity.usd))
+}
+
+/// Solana pairs of `mint` against wrapped SOL. Swaps are built with SOL on
+/// one side only, pools quoting `mint` in USDC or another token can't be
+/// traded.
+pub fn sol_pairs<'a>(mint: &'a str, pairs: &'a [PairInfo]) -> impl Iterator<Item = &'a PairInfo> {
+    let wsol = spl_token::native_mint::id().to_string();
+    pairs
+        .iter()
+        .filter(|pair| pair.chain_id == "solana")
+        .filter(move |pair| {
+            (pair.base_token.address == mint && pair.quote_token.address == wsol)
+                || (pair.quote_token.address == mint && pair.base_token.address == wsol)
+        })
+}
+
+/// None of the SOL pairs of a mint is a pool that can be traded, or it has
+/// none. Failing to look a pool up is a different error, the pool may be
+/// supported.
+#[derive(Debug, Error)]
+#[error("No supported pool for {mint}, found [{}]", found.join(", "))]
+pub struct NoSupportedPool {
+    pub mint: String,
+    /// The pairs that are not, and why
+    pub found: Vec<String>,
+}
+
+/// Most liquid SOL pool of `mint` among DexScreener's pairs that can be
+/// traded, whatever DEX it is listed under. Fails with [`NoSupportedPool`]
+/// only when every pair was looked up.
+pub async fn find_supported_pool(
+    rpc_client: &RpcClient,
+    mint: &str,
+    pairs: &[PairInfo],
+) -> Result<(Pubkey, PoolProgram)> {
+    let mut candidates: Vec<&PairInfo> = sol_pairs(mint, pairs).collect();
+    candidates.sort_by(|a, b| b.liquidity.usd.total_cmp(&a.liquidity.usd));
+
+    let mut unsupported = Vec::new();
+    let mut lookup_error = None;
+    for pair in candidates {
+        let Ok(pool) = Pubkey::from_str(&pair.pair_address) else {
+            unsupported.push(format!("bad pair address {}", pair.pair_address));
+            continue;
+        };
+        let owner = match rpc_client.get_account(&pool).await {
+            Ok(account) => account.owner,
+            Err(e) => {
+                lookup_error = Some(anyhow!("Failed to look up pool {}: {}", pool, e));
+                continue;
+            }
+        };
+        match PoolProgram::from_owner(&owner) {
+            Some(program) if program.is_supported() => return Ok((pool, program)),
+            Some(program) => unsupported.push(format!("{} ({})", pool, program)),
+            None => unsupported.push(format!("{} (owned by {})", pool, owner)),
+        }
+    }
+    if let Some(e) = lookup_error {
+        return Err(e);
+    }
+    Err(NoSupportedPool {
+        mint: mint.to_string(),
+        found: unsupported,
+    }
+    .into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_owner() {
+        for program in PoolProgram::ALL {
+            let owner = Pubkey::from_str(program.program_id()).unwrap();
+            assert_eq!(PoolProgram::from_owner(&owner), Some(program));
+        }
+        assert_eq!(PoolProgram::from_owner(&Pubkey::new_unique()), None);
+        assert!(PoolProgram::RaydiumCpmm.is_supported());
+        assert!(PoolProgram::OrcaWhirlpool.is_supported());
+        assert!(PoolProgram::MeteoraDlmm.is_supported());
+        assert!(!PoolProgram::MeteoraAmm.is_supported());
+    }
+
+    #[test]
+    fn test_sol_pairs() {
+        let pair = |base: &str, quote: &str| {
+            serde_json::from_value::<PairInfo>(serde_json::json!({
+                "chainId": "solana",
+                "dexId": "orca",
+                "url": "",
+                "pairAddress": Pubkey::new_unique().to_string(),
+                "baseToken": {"address": base, "name": base, "symbol": base},
+                "quoteToken": {"address": quote, "name": quote, "symbol": quote},
+                "priceNative": "0",
+                "priceUsd": "0",
+                "liquidity": {"usd": 0.0, "base": 0.0, "quote": 0.0},
+                "volume": {"h24": 0.0, "h6": 0.0, "h1": 0.0, "m5": 0.0},
+            }))
+            .unwrap()
+        };
+        let wsol = spl_token::native_mint::id().to_string();
+        let pairs = vec![
+            pair("mint", &wsol),
+            pair("mint", "usdc"),
+            pair(&wsol, "mint"),
+            pair("other", &wsol),
+        ];
+        let found: Vec<&PairInfo> = sol_pairs("mint", &pairs).collect();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].pair_address, pairs[0].pair_address);
+        assert_eq!(found[1].pair_address, pairs[2].pair_address);
+    }
+}
@@ -0,0 +1,270 @@
+use async_trait::async_trait;
+use futures::future::select_ok;
+use serde_json::Value;
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind, Result as ClientResult},
+    http_sender::HttpSender,
+    nonblocking::rpc_client::RpcClient,
+    rpc_client::RpcClientConfig,
+    rpc_request::{RpcError, RpcRequest},
+    rpc_sender::{RpcSender, RpcTransportStats},
+};
+use solana_sdk::commitment_config::CommitmentConfig;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::config::{redact_url, RpcConfig};
+
+/// Weight of the latest request in the latency and error averages
+const EWMA_ALPHA: f64 = 0.2;
+/// A fully failing endpoint scores as this many times its latency
+const ERROR_PENALTY: f64 = 20.0;
+/// Endpoints unused for this long are tried again as if healthy, so one
+/// that recovered gets back into rotation
+const RECOVERY_AFTER: Duration = Duration::from_secs(30);
+/// JSON-RPC error of a node behind the cluster
+const NODE_UNHEALTHY: i64 = -32005;
+
+/// Latency and error rate of one endpoint, as moving averages
+#[derive(Debug, Clone, Default)]
+pub struct Health {
+    pub latency_ms: f64,
+    pub error_rate: f64,
+    pub requests: u64,
+    pub errors: u64,
+    last_used: Option<Instant>,
+}
+
+impl Health {
+    fn record(&mut self, elapsed: Duration, ok: bool, now: Instant) {
+        let latency_ms = elapsed.as_secs_f64() * 1000.0;
+        let error = if ok { 0.0 } else { 1.0 };
+        if self.requests == 0 {
+            self.latency_ms = latency_ms;
+            self.error_rate = error;
+        } else {
+            self.latency_ms += EWMA_ALPHA * (latency_ms - self.latency_ms);
+            self.error_rate += EWMA_ALPHA * (error - self.error_rate);
+        }
+        self.requests += 1;
+        self.errors += u64::from(!ok);
+        self.last_used = Some(now);
+    }
+
+    /// Lower is healthier. Unused endpoints score 0 so they get sampled.
+    pub fn score(&self, now: Instant) -> f64 {
+        match self.last_used {
+            None => 0.0,
+            Some(last_used) if now.duration_since(last_used) >= RECOVERY_AFTER => self.latency_ms,
+            Some(_) => self.latency_ms * (1.0 + ERROR_PENALTY * self.error_rate),
+        }
+    }
+}
+
+struct Endpoint {
+    url: String,
+    sender: HttpSender,
+    health: Mutex<Health>,
+}
+
+impl Endpoint {
+    /// Send to this endpoint, recording how it went. Answers of the node,
+    /// errors included, count as healthy, only transport failures and
+    /// unhealthy nodes don't.
+    async fn send(&self, request: RpcRequest, params: Value) -> ClientResult<Value> {
+        let started = Instant::now();
+        let result = self.sender.send(request, params).await;
+        let ok = !result.as_ref().is_err_and(is_endpoint_fault);
+        self.health
+            .lock()
+            .unwrap()
+            .record(started.elapsed(), ok, Instant::now());
+        if !ok {
+            tracing::debug!("{} {} failed: {:?}", redact_url(&self.url), request, result);
+        }
+        result
+    }
+}
+
+fn is_endpoint_fault(error: &ClientError) -> bool {
+    match error.kind() {
+        ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_) => true,
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. }) => {
+            *code == NODE_UNHEALTHY
+        }
+        ClientErrorKind::RpcError(RpcError::RpcRequestError(_)) => true,
+        _ => false,
+    }
+}
+
+/// Endpoints of `SOLANA_RPC_URLS` with their health, shared by every client
+/// [`make_rpc_client`] makes
+pub struct RpcPool {
+    endpoints: Vec<Arc<Endpoint>>,
+}
+
+impl RpcPool {
+    pub fn new(urls: &[String]) -> Self {
+        Self {
+            endpoints: urls
+                .iter()
+                .map(|url| {
+                    Arc::new(Endpoint {
+                        url: url.clone(),
+                        sender: HttpSender::new(url.clone()),
+                        health: Mutex::default(),
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    /// Endpoints from the healthiest down
+    fn ranked(&self) -> Vec<Arc<Endpoint>> {
+        let now = Instant::now();
+        let mut ranked: Vec<(f64, Arc<Endpoint>)> = self
+            .endpoints
+            .iter()
+            .map(|endpoint| (endpoint.health.lock().unwrap().score(now), endpoint.clone()))
+            .collect();
+        ranked.sort_by(|a, b| a.0.total_cmp(&b.0));
+        ranked.into_iter().map(|(_, endpoint)| endpoint).collect()
+    }
+
+    /// URL and health of every endpoint, healthiest first
+    pub fn health(&self) -> Vec<(String, Health)> {
+        self.ranked()
+            .iter()
+            .map(|endpoint| {
+                (
+                    redact_url(&endpoint.url),
+                    endpoint.health.lock().unwrap().clone(),
+                )
+            })
+            .collect()
+    }
+
+    /// Try the endpoints from the healthiest down until one answers
+    async fn read(&self, request: RpcRequest, params: Value) -> ClientResult<Value> {
+        let mut last_error = None;
+        for endpoint in self.ranked() {
+            match endpoint.send(request, params.clone()).await {
+                Err(e) if is_endpoint_fault(&e) => last_error = Some(e),
+                result => return result,
+            }
+        }
+        Err(last_error
+            .unwrap_or_else(|| ClientErrorKind::Custom("No RPC endpoint".to_string()).into()))
+    }
+
+    /// Send to every endpoint at once and return the first success. The
+    /// other sends run on, a transaction reaching more nodes is more likely
+    /// to land.
+    async fn broadcast(&self, request: RpcRequest, params: Value) -> ClientResult<Value> {
+        let sends = self.endpoints.iter().map(|endpoint| {
+            let endpoint = endpoint.clone();
+            let params = params.clone();
+            let handle = tokio::spawn(async move { endpoint.send(request, params).await });
+            Box::pin(async move {
+                handle
+                    .await
+                    .map_err(|e| ClientError::from(ClientErrorKind::Custom(e.to_string())))?
+            })
+        });
+        select_ok(sends).await.map(|(value, _)| value)
+    }
+}
+
+/// [`RpcSender`] over an [`RpcPool`]: reads go to the healthiest endpoint,
+/// failing over on transport errors, transactions go to all of them
+struct FailoverSender {
+    pool: Arc<RpcPool>,
+}
+
+#[async_trait]
+impl RpcSender for FailoverSender {
+    async fn send(&self, request: RpcRequest, params: Value) -> ClientResult<Value> {
+        match request {
+            RpcRequest::SendTransaction => self.pool.broadcast(request, params).await,
+            _ => self.pool.read(request, params).await,
+        }
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        let mut stats = RpcTransportStats::default();
+        for endpoint in &self.pool.endpoints {
+            let endpoint_stats = endpoint.sender.get_transport_stats();
+            stats.request_count += endpoint_stats.request_count;
+            stats.elapsed_time += endpoint_stats.elapsed_time;
+            stats.rate_limited_time += endpoint_stats.rate_limited_time;
+        }
+        stats
+    }
+
+    fn url(&self) -> String {
+        self.pool
+            .ranked()
+            .first()
+            .map(|endpoint| endpoint.url.clone())
+            .unwrap_or_default()
+    }
+}
+
+static POOL: OnceLock<Option<Arc<RpcPool>>> = OnceLock::new();
+
+/// The shared pool, `None` without `SOLANA_RPC_URLS`
+pub fn rpc_pool() -> Option<Arc<RpcPool>> {
+    POOL.get_or_init(|| match RpcConfig::from_env() {
+        Ok(Some(config)) => {
+            tracing::info!("{}", config);
+            Some(Arc::new(RpcPool::new(&config.urls)))
+        }
+        Ok(None) => None,
+        Err(e) => {
+            tracing::error!("Invalid RPC config, using SOLANA_RPC_URL: {:?}", e);
+            None
+        }
+    })
+    .clone()
+}
+
+/// RPC client over every endpoint of `SOLANA_RPC_URLS`, or over
+/// `SOLANA_RPC_URL` when it's not set
+pub fn make_rpc_client() -> RpcClient {
+    match rpc_pool() {
+        Some(pool) => RpcClient::new_sender(
+            FailoverSender { pool },
+            RpcClientConfig::with_commitment(CommitmentConfig::default()),
+        ),
+        None => listen_kit::solana::util::make_rpc_client(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health_score() {
+        let now = Instant::now();
+        let mut fast = Health::default();
+        let mut slow = Health::default();
+        assert_eq!(fast.score(now), 0.0);
+
+        fast.record(Duration::from_millis(50), true, now);
+        slow.record(Duration::from_millis(200), true, now);
+        assert!(fast.score(now) < slow.score(now));
+
+        // Failures outweigh the latency advantage
+        for _ in 0..3 {
+            fast.record(Duration::from_millis(50), false, now);
+        }
+        assert!(fast.error_rate > 0.4);
+        assert!(fast.score(now) > slow.score(now));
+        assert_eq!(fast.errors, 3);
+
+        // Until it sat unused long enough to be tried again
+        let later = now + RECOVERY_AFTER;
+        assert!(fast.score(later) < slow.score(later));
+    }
+}
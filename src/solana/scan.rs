@@ -0,0 +1,202 @@
+use anyhow::Result;
+use serde::Serialize;
+use solana_sdk::{native_token::lamports_to_sol, pubkey::Pubkey};
+use std::collections::BTreeMap;
+
+use crate::solana::housekeeping::{token_accounts, TokenAccount};
+use crate::solana::price;
+use crate::solana::rpc::make_rpc_client;
+use crate::solana::token_registry::token_registry;
+
+/// Balance of one mint in the wallet, over all its token accounts
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TokenHolding {
+    pub mint: String,
+    /// `None` for mints without metadata
+    pub symbol: Option<String>,
+    pub decimals: u8,
+    /// Raw token amount
+    pub amount: u64,
+    /// USD price of a whole token, `None` when no source prices it
+    pub price_usd: Option<f64>,
+    pub value_usd: Option<f64>,
+    pub value_sol: Option<f64>,
+}
+
+impl TokenHolding {
+    /// Amount in whole tokens
+    pub fn ui_amount(&self) -> f64 {
+        self.amount as f64 / 10f64.powi(self.decimals as i32)
+    }
+}
+
+/// Everything a wallet holds, valued at the prices of the feed
+#[derive(Debug, Clone, Serialize)]
+pub struct Portfolio {
+    pub owner: String,
+    pub sol_lamports: u64,
+    pub sol_price_usd: Option<f64>,
+    /// Most valuable first, unpriced tokens last
+    pub tokens: Vec<TokenHolding>,
+    /// SOL and the priced tokens
+    pub total_usd: Option<f64>,
+    pub total_sol: f64,
+}
+
+/// Non-empty balances per mint as (mint, raw amount, decimals), accounts of
+/// the same mint summed
+pub fn balances_by_mint(accounts: &[TokenAccount]) -> Vec<(String, u64, u8)> {
+    let mut balances: BTreeMap<&str, (u64, u8)> = BTreeMap::new();
+    for account in accounts.iter().filter(|account| account.amount > 0) {
+        let balance = balances
+            .entry(&account.mint)
+            .or_insert((0, account.decimals));
+        balance.0 = balance.0.saturating_add(account.amount);
+    }
+    balances
+        .into_iter()
+        .map(|(mint, (amount, decimals))| (mint.to_string(), amount, decimals))
+        .collect()
+}
+
+/// Value the holdings and add them up with the SOL balance
+fn portfolio(
+    owner: &Pubkey,
+    sol_lamports: u64,
+    sol_price_usd: Option<f64>,
+    mut tokens: Vec<TokenHolding>,
+) -> Portfolio {
+    for token in &mut tokens {
+        token.value_usd = token.price_usd.map(|price| token.ui_amount() * price);
+        token.value_sol = token
+            .value_usd
+            .zip(sol_price_usd)
+            .map(|(value, sol_price)| value / sol_price);
+    }
+    tokens.sort_by(|a, b| {
+        b.value_usd
+            .unwrap_or(-1.0)
+            .total_cmp(&a.value_usd.unwrap_or(-1.0))
+    });
+    let sol = lamports_to_sol(sol_lamports);
+    let tokens_sol: f64 = tokens.iter().filter_map(|token| token.value_sol).sum();
+    Portfolio {
+        owner: owner.to_string(),
+        sol_lamports,
+        sol_price_usd,
+        total_usd: sol_price_usd.map(|price| (sol + tokens_sol) * price),
+        total_sol: sol + tokens_sol,
+        tokens,
+    }
+}
+
+/// SOL and every token balance of `owner`: its token accounts of both token
+/// programs, symbols from the token registry and prices from the feed. The
+/// metadata of the mints is read in batches of accounts and their prices
+/// in batches of the price sources, not one lookup per mint.
+pub async fn get_portfolio(owner: &Pubkey) -> Result<Portfolio> {
+    let rpc_client = make_rpc_client();
+    let feed = price::feed();
+    let (sol_lamports, accounts, sol_price_usd) = tokio::join!(
+        rpc_client.get_balance(owner),
+        token_accounts(&rpc_client, owner),
+        feed.sol_price_usd()
+    );
+    let (sol_lamports, accounts) = (sol_lamports?, accounts?);
+    let sol_price_usd = sol_price_usd
+        .inspect_err(|e| tracing::warn!("No SOL price for the portfolio: {:?}", e))
+        .ok();
+
+    let balances = balances_by_mint(&accounts);
+    let mints: Vec<String> = balances.iter().map(|(mint, _, _)| mint.clone()).collect();
+    let (infos, prices) = tokio::join!(token_registry().get_many(&mints), feed.prices_usd(&mints));
+    let tokens = balances
+        .into_iter()
+        .zip(infos.into_iter().zip(prices))
+        .map(
+            |((mint, amount, decimals), (info, price_usd))| TokenHolding {
+                symbol: info.ok().and_then(|info| info.symbol),
+                decimals,
+                amount,
+                price_usd: price_usd.ok(),
+                value_usd: None,
+                value_sol: None,
+                mint,
+            },
+        )
+        .collect();
+    Ok(portfolio(owner, sol_lamports, sol_price_usd, tokens))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(mint: &str, amount: u64) -> TokenAccount {
+        TokenAccount {
+            address: Pubkey::new_unique(),
+            token_program: spl_token::id(),
+            mint: mint.to_string(),
+            amount,
+            decimals: 6,
+            lamports: 2_039_280,
+            frozen: false,
+            withheld: 0,
+        }
+    }
+
+    fn holding(mint: &str, amount: u64, price_usd: Option<f64>) -> TokenHolding {
+        TokenHolding {
+            mint: mint.to_string(),
+            symbol: None,
+            decimals: 6,
+            amount,
+            price_usd,
+            value_usd: None,
+            value_sol: None,
+        }
+    }
+
+    #[test]
+    fn test_balances_by_mint() {
+        let accounts = vec![
+            account("wif", 1_000_000),
+            account("empty", 0),
+            account("bonk", 5),
+            account("wif", 500_000),
+        ];
+        assert_eq!(
+            balances_by_mint(&accounts),
+            vec![
+                ("bonk".to_string(), 5, 6),
+                ("wif".to_string(), 1_500_000, 6)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_portfolio_values() {
+        let owner = Pubkey::new_unique();
+        let tokens = vec![
+            holding("unpriced", 1_000_000, None),
+            holding("small", 1_000_000, Some(1.0)),
+            holding("large", 2_000_000, Some(50.0)),
+        ];
+        let wallet = portfolio(&owner, 2_000_000_000, Some(100.0), tokens);
+        let mints: Vec<&str> = wallet.tokens.iter().map(|t| t.mint.as_str()).collect();
+        assert_eq!(mints, vec!["large", "small", "unpriced"]);
+        assert_eq!(wallet.tokens[0].value_usd, Some(100.0));
+        assert_eq!(wallet.tokens[0].value_sol, Some(1.0));
+        assert_eq!(wallet.tokens[2].value_usd, None);
+        assert!((wallet.total_sol - 3.01).abs() < 1e-9);
+        assert!((wallet.total_usd.unwrap() - 301.0).abs() < 1e-9);
+
+        // Without a SOL price the tokens still have a USD value
+        let tokens = vec![holding("small", 1_000_000, Some(1.0))];
+        let wallet = portfolio(&owner, 2_000_000_000, None, tokens);
+        assert_eq!(wallet.tokens[0].value_usd, Some(1.0));
+        assert_eq!(wallet.tokens[0].value_sol, None);
+        assert_eq!(wallet.total_usd, None);
+        assert_eq!(wallet.total_sol, 2.0);
+    }
+}
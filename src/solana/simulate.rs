@@ -0,0 +1,60 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use listen_kit::signer::TransactionSigner;
+use solana_sdk::{pubkey::Pubkey, transaction::Transaction};
+use std::str::FromStr;
+use thiserror::Error;
+
+use crate::format;
+use crate::solana::rpc::make_rpc_client;
+use crate::solana::transaction::simulate_effects;
+use crate::tg_copy::journal;
+
+/// What a swap fails with in `TRADE_MODE=simulate`, once it was simulated
+#[derive(Debug, Error)]
+#[error("simulated only, TRADE_MODE=simulate sends nothing")]
+pub struct NotSent;
+
+/// Signer of `TRADE_MODE=simulate`. Transactions are simulated against the
+/// current chain state and what they would do is logged, nothing is signed
+/// or sent. Replaces the signer that sends, the policy and tracking
+/// signers still wrap it.
+pub struct SimulatingSigner {
+    pubkey: String,
+}
+
+impl SimulatingSigner {
+    pub fn new(pubkey: String) -> Self {
+        Self { pubkey }
+    }
+}
+
+#[async_trait]
+impl TransactionSigner for SimulatingSigner {
+    fn pubkey(&self) -> String {
+        self.pubkey.clone()
+    }
+
+    async fn sign_and_send_solana_transaction(&self, tx: &mut Transaction) -> Result<String> {
+        let owner = Pubkey::from_str(&self.pubkey)?;
+        let effects = simulate_effects(&make_rpc_client(), tx, &owner).await?;
+        let tokens = effects
+            .token_changes
+            .iter()
+            .map(|(mint, change)| format!("{} {:+}", mint, change))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let summary = format!(
+            "SOL {}, tokens [{}], {} compute units",
+            format::signed_lamports(effects.sol_change as i64),
+            tokens,
+            effects
+                .units_consumed
+                .map_or("unknown".to_string(), |units| units.to_string())
+        );
+        tracing::info!("Simulated, not sent: {}", summary);
+        tracing::debug!("Simulation logs: {:?}", effects.logs);
+        journal::pass("simulate", summary);
+        Err(NotSent.into())
+    }
+}
@@ -0,0 +1,233 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use solana_sdk::{account::Account, pubkey::Pubkey};
+use spl_token_2022::{extension::StateWithExtensions, state::Mint};
+use std::str::FromStr;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use crate::solana::cache::TtlCache;
+use crate::solana::rpc::make_rpc_client;
+
+/// Metaplex token metadata program
+pub const TOKEN_METADATA_PROGRAM: &str = "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s";
+
+const CAPACITY: usize = 5_000;
+/// Supply only changes for mints with an authority left, an hour old is
+/// close enough for market caps
+const TTL: Duration = Duration::from_secs(3_600);
+const NEGATIVE_TTL: Duration = Duration::from_secs(30);
+/// `getMultipleAccounts` takes 100 accounts, two per mint
+const MINTS_PER_REQUEST: usize = 50;
+
+static REGISTRY: LazyLock<TokenRegistry> = LazyLock::new(TokenRegistry::default);
+
+/// The registry shared by every caller in the process
+pub fn token_registry() -> &'static TokenRegistry {
+    &REGISTRY
+}
+
+/// What the chain says about a mint
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TokenInfo {
+    pub mint: String,
+    /// From the Metaplex metadata, `None` for mints without any
+    pub symbol: Option<String>,
+    pub name: Option<String>,
+    pub decimals: u8,
+    /// Raw total supply
+    pub supply: u64,
+}
+
+impl TokenInfo {
+    /// Total supply in whole tokens
+    pub fn ui_supply(&self) -> f64 {
+        self.supply as f64 / 10f64.powi(self.decimals as i32)
+    }
+
+    /// Fully diluted market cap at `price_usd` per whole token
+    pub fn market_cap(&self, price_usd: f64) -> f64 {
+        self.ui_supply() * price_usd
+    }
+
+    /// The symbol, or the start of the mint for logs
+    pub fn label(&self) -> String {
+        self.symbol
+            .clone()
+            .unwrap_or_else(|| self.mint.chars().take(6).collect())
+    }
+}
+
+/// Address of the Metaplex metadata account of `mint`
+pub fn metadata_address(mint: &Pubkey) -> Result<Pubkey> {
+    let program = Pubkey::from_str(TOKEN_METADATA_PROGRAM)?;
+    Ok(Pubkey::find_program_address(&[b"metadata", program.as_ref(), mint.as_ref()], &program).0)
+}
+
+/// Borsh string of the metadata, its fixed size padding trimmed
+fn read_string(data: &[u8], offset: &mut usize) -> Option<String> {
+    let len = u32::from_le_bytes(data.get(*offset..*offset + 4)?.try_into().ok()?) as usize;
+    *offset += 4;
+    let bytes = data.get(*offset..*offset + len)?;
+    *offset += len;
+    let value = String::from_utf8_lossy(bytes)
+        .trim_matches(char::from(0))
+        .trim()
+        .to_string();
+    Some(value)
+}
+
+/// Name and symbol of a Metaplex metadata account: key, update authority
+/// and mint come first, then name, symbol and URI
+pub fn parse_metadata(data: &[u8]) -> Option<(String, String)> {
+    let mut offset = 1 + 32 + 32;
+    let name = read_string(data, &mut offset)?;
+    let symbol = read_string(data, &mut offset)?;
+    Some((name, symbol))
+}
+
+/// Symbol, decimals and supply of every mint the bot touches, read from
+/// the chain once and cached, so logs show symbols and market caps are
+/// computed from the real supply
+pub struct TokenRegistry {
+    cache: TtlCache<TokenInfo>,
+}
+
+impl Default for TokenRegistry {
+    fn default() -> Self {
+        Self {
+            cache: TtlCache::new(CAPACITY, TTL, NEGATIVE_TTL),
+        }
+    }
+}
+
+impl TokenRegistry {
+    pub async fn get(&self, mint: &str) -> Result<TokenInfo> {
+        self.cache.get_or_fetch(mint, fetch_token_info(mint)).await
+    }
+
+    /// Symbol of `mint`, `None` when it has no metadata or the lookup failed
+    pub async fn symbol(&self, mint: &str) -> Option<String> {
+        self.get(mint).await.ok()?.symbol
+    }
+
+    pub async fn decimals(&self, mint: &str) -> Result<u8> {
+        Ok(self.get(mint).await?.decimals)
+    }
+
+    /// [`Self::get`] of every mint in `mints`, in their order, the ones not
+    /// cached read together
+    pub async fn get_many(&self, mints: &[String]) -> Vec<Result<TokenInfo>> {
+        self.cache.get_or_fetch_many(mints, fetch_token_infos).await
+    }
+}
+
+/// The mint and its metadata account, in one request
+async fn fetch_token_info(mint: &str) -> Result<TokenInfo> {
+    let mint_key = Pubkey::from_str(mint)?;
+    let accounts = make_rpc_client()
+        .get_multiple_accounts(&[mint_key, metadata_address(&mint_key)?])
+        .await?;
+    let mut accounts = accounts.into_iter();
+    token_info(mint, accounts.next().flatten(), accounts.next().flatten())
+}
+
+/// Every mint and metadata account of `mints`, [`MINTS_PER_REQUEST`] mints
+/// a request
+async fn fetch_token_infos(mints: Vec<String>) -> Vec<Result<TokenInfo>> {
+    let rpc_client = make_rpc_client();
+    let mut infos = Vec::with_capacity(mints.len());
+    for chunk in mints.chunks(MINTS_PER_REQUEST) {
+        let accounts = async {
+            let keys = chunk
+                .iter()
+                .map(|mint| {
+                    let mint_key = Pubkey::from_str(mint)?;
+                    Ok([mint_key, metadata_address(&mint_key)?])
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok::<_, anyhow::Error>(rpc_client.get_multiple_accounts(&keys.concat()).await?)
+        }
+        .await;
+        match accounts {
+            Ok(accounts) => {
+                let mut accounts = accounts.into_iter();
+                for mint in chunk {
+                    let (mint_account, metadata) = (accounts.next(), accounts.next());
+                    infos.push(token_info(mint, mint_account.flatten(), metadata.flatten()));
+                }
+            }
+            Err(e) => {
+                infos.extend(chunk.iter().map(|mint| Err(anyhow!("{}: {:#}", mint, e))));
+            }
+        }
+    }
+    infos
+}
+
+/// Decimals and supply of the mint account, name and symbol of its
+/// metadata account when it has one
+fn token_info(
+    mint: &str,
+    mint_account: Option<Account>,
+    metadata: Option<Account>,
+) -> Result<TokenInfo> {
+    let mint_account = mint_account.ok_or_else(|| anyhow!("Mint {} not found", mint))?;
+    if mint_account.owner != spl_token::id() && mint_account.owner != spl_token_2022::id() {
+        return Err(anyhow!("{} is not a token mint", mint));
+    }
+    let state = StateWithExtensions::<Mint>::unpack(&mint_account.data)?;
+    let metadata = metadata.and_then(|account| parse_metadata(&account.data));
+    let (name, symbol) = match metadata {
+        Some((name, symbol)) => (
+            Some(name).filter(|n| !n.is_empty()),
+            Some(symbol).filter(|s| !s.is_empty()),
+        ),
+        None => (None, None),
+    };
+    Ok(TokenInfo {
+        mint: mint.to_string(),
+        symbol,
+        name,
+        decimals: state.base.decimals,
+        supply: state.base.supply,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_metadata() {
+        let mut data = vec![4];
+        data.extend([1; 64]);
+        let mut string = |value: &str, padded: usize| {
+            data.extend((padded as u32).to_le_bytes());
+            data.extend(value.as_bytes());
+            data.extend(vec![0; padded - value.len()]);
+        };
+        string("dogwifhat", 32);
+        string("WIF", 10);
+        string("https://example.com", 200);
+        assert_eq!(
+            parse_metadata(&data),
+            Some(("dogwifhat".to_string(), "WIF".to_string()))
+        );
+        assert_eq!(parse_metadata(&data[..70]), None);
+    }
+
+    #[test]
+    fn test_market_cap() {
+        let info = TokenInfo {
+            mint: "EKpQGSJtjMFqKZ9KQanSqYXRcF8fBopzLHYxdM65zcjm".to_string(),
+            symbol: None,
+            name: None,
+            decimals: 6,
+            supply: 1_000_000_000_000_000,
+        };
+        assert_eq!(info.ui_supply(), 1e9);
+        assert!((info.market_cap(0.00012) - 120_000.0).abs() < 1e-6);
+        assert_eq!(info.label(), "EKpQGS");
+    }
+}
@@ -1,16 +1,19 @@
-use crate::solana::{raydium::get_serum_accounts, util::generate_random_seed};
+use crate::solana::{
+    raydium::get_serum_accounts,
+    transaction::{ata_with_create_ix, TxBuilder},
+};
 use anyhow::Result;
 use solana_client::nonblocking::rpc_client::RpcClient;
-use solana_sdk::{
-    instruction::Instruction, program_pack::Pack, pubkey::Pubkey, system_instruction,
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey};
+use spl_associated_token_account::{
+    get_associated_token_address, get_associated_token_address_with_program_id,
 };
-use spl_associated_token_account::get_associated_token_address;
-use spl_token::{self, instruction as token_instruction};
 
 use std::str::FromStr;
 
 use super::raydium::{
-    calculate_minimum_amount_out, extract_raydium_accounts, get_raydium_pool, make_raydium_swap_ix,
+    extract_raydium_accounts, get_raydium_cpmm_pool, get_raydium_pool, make_raydium_cpmm_swap_ix,
+    make_raydium_swap_ix, raydium_cpmm_minimum_amount_out, raydium_v4_minimum_amount_out,
 };
 
 pub async fn create_raydium_sol_swap_ix(
@@ -33,50 +36,21 @@ pub async fn create_raydium_sol_swap_ix(
     let serum_accounts = get_serum_accounts(rpc_client, raydium_accounts.serum_market).await?;
     // tracing::info!("SerumAccounts {:?}", serum_accounts);
 
-    // Generate seed for temporary WSOL account
-    let seed = &generate_random_seed();
-
-    // Derive temporary WSOL account with seed
-    let user_source_token_account = Pubkey::create_with_seed(owner, seed, &spl_token::id())?;
-
-    // Calculate rent-exempt balance for token account
-    let rent = rpc_client
-        .get_minimum_balance_for_rent_exemption(spl_token::state::Account::LEN)
-        .await?;
-
-    // Create temporary WSOL account
-    ixs.push(system_instruction::create_account_with_seed(
-        owner,
-        &user_source_token_account,
-        owner,
-        seed,
-        amount_in + rent, // Total amount: swap amount + rent
-        spl_token::state::Account::LEN as u64,
-        &spl_token::id(),
-    ));
-
-    // Initialize WSOL account
-    ixs.push(token_instruction::initialize_account(
-        &spl_token::id(),
-        &user_source_token_account,
-        &spl_token::native_mint::id(),
-        owner,
-    )?);
+    let (builder, user_source_token_account) = TxBuilder::default().wrap_sol(owner, amount_in)?;
 
     // Generate user ATA for destination token
-    let user_destination_token_account = get_associated_token_address(owner, &destination_token);
-
-    ixs.push(
-        spl_associated_token_account::instruction::create_associated_token_account_idempotent(
-            &owner,
-            &owner,
-            &destination_token,
-            &spl_token::id(),
-        ),
-    );
+    let (user_destination_token_account, create_ata) =
+        ata_with_create_ix(owner, &destination_token, &spl_token::id());
+    ixs.push(create_ata);
 
-    let minimum_amount_out =
-        calculate_minimum_amount_out(&pool_accounts, amount_in, slippage_bps as f64);
+    let minimum_amount_out = raydium_v4_minimum_amount_out(
+        rpc_client,
+        &pool_accounts,
+        &spl_token::native_mint::id(),
+        amount_in,
+        slippage_bps,
+    )
+    .await?;
 
     ixs.push(make_raydium_swap_ix(
         raydium_accounts,
@@ -88,21 +62,15 @@ pub async fn create_raydium_sol_swap_ix(
         minimum_amount_out,
     )?);
 
-    // 4. Close temporary WSOL account to recover rent
-    ixs.push(token_instruction::close_account(
-        &spl_token::id(),
-        &user_source_token_account,
-        owner,
-        owner,
-        &[owner],
-    )?);
-
-    Ok(ixs)
+    Ok(builder.instructions(ixs).build())
 }
 
+/// Sell `amount_in` of `source_token` for SOL. Without `slippage_bps` the
+/// swap accepts any output.
 pub async fn create_raydium_token_swap_ix(
     pool_address: String,
     amount_in: u64,
+    slippage_bps: Option<u16>,
     source_token: Pubkey,
     rpc_client: &RpcClient,
     owner: &Pubkey,
@@ -119,40 +87,18 @@ pub async fn create_raydium_token_swap_ix(
     let serum_accounts = get_serum_accounts(rpc_client, raydium_accounts.serum_market).await?;
     // tracing::info!("SerumAccounts {:?}", serum_accounts);
 
-    // Generate seed for temporary WSOL account
-    let seed = &generate_random_seed();
-
-    // Derive temporary WSOL account with seed
-    let user_destination_token_account = Pubkey::create_with_seed(owner, seed, &spl_token::id())?;
-
-    // Calculate rent-exempt balance for token account
-    let rent = rpc_client
-        .get_minimum_balance_for_rent_exemption(spl_token::state::Account::LEN)
-        .await?;
-
-    // Create temporary WSOL account
-    ixs.push(system_instruction::create_account_with_seed(
-        owner,
-        &user_destination_token_account,
-        owner,
-        seed,
-        rent,
-        spl_token::state::Account::LEN as u64,
-        &spl_token::id(),
-    ));
-
-    // Initialize WSOL account
-    ixs.push(token_instruction::initialize_account(
-        &spl_token::id(),
-        &user_destination_token_account,
-        &spl_token::native_mint::id(),
-        owner,
-    )?);
+    let (builder, user_destination_token_account) = TxBuilder::default().wrap_sol(owner, 0)?;
 
     // Generate user ATA for destination token
     let user_source_token_account = get_associated_token_address(owner, &source_token);
 
-    let minimum_amount_out = 0;
+    let minimum_amount_out = match slippage_bps {
+        Some(bps) => {
+            raydium_v4_minimum_amount_out(rpc_client, &pool_accounts, &source_token, amount_in, bps)
+                .await?
+        }
+        None => 0,
+    };
 
     ixs.push(make_raydium_swap_ix(
         raydium_accounts,
@@ -164,14 +110,88 @@ pub async fn create_raydium_token_swap_ix(
         minimum_amount_out,
     )?);
 
-    // 4. Close temporary WSOL account to recover rent
-    ixs.push(token_instruction::close_account(
-        &spl_token::id(),
-        &user_destination_token_account,
-        owner,
-        owner,
-        &[owner],
+    Ok(builder.instructions(ixs).build())
+}
+
+pub async fn create_raydium_cpmm_sol_swap_ix(
+    pool_address: String,
+    amount_in: u64,
+    slippage_bps: u16,
+    destination_token: Pubkey,
+    rpc_client: &RpcClient,
+    owner: &Pubkey,
+) -> Result<Vec<Instruction>> {
+    let mut ixs = vec![];
+
+    let pool_pubkey = Pubkey::from_str(&pool_address)?;
+    let pool = get_raydium_cpmm_pool(rpc_client, &pool_pubkey).await?;
+    let wsol = spl_token::native_mint::id();
+    let (_, output) = pool.swap_sides(&wsol)?;
+
+    let (builder, user_source_token_account) = TxBuilder::default().wrap_sol(owner, amount_in)?;
+
+    // CPMM pools may hold Token-2022 mints, so the ATA has to be derived
+    // with the mint's token program
+    let (user_destination_token_account, create_ata) =
+        ata_with_create_ix(owner, &destination_token, &output.token_program);
+    ixs.push(create_ata);
+
+    let minimum_amount_out =
+        raydium_cpmm_minimum_amount_out(rpc_client, &pool, &wsol, amount_in, slippage_bps).await?;
+
+    ixs.push(make_raydium_cpmm_swap_ix(
+        pool_pubkey,
+        &pool,
+        &wsol,
+        user_source_token_account,
+        user_destination_token_account,
+        *owner,
+        amount_in,
+        minimum_amount_out,
+    )?);
+
+    Ok(builder.instructions(ixs).build())
+}
+
+/// Sell `amount_in` of `source_token` for SOL through a CPMM pool. Without
+/// `slippage_bps` the swap accepts any output.
+pub async fn create_raydium_cpmm_token_swap_ix(
+    pool_address: String,
+    amount_in: u64,
+    slippage_bps: Option<u16>,
+    source_token: Pubkey,
+    rpc_client: &RpcClient,
+    owner: &Pubkey,
+) -> Result<Vec<Instruction>> {
+    let mut ixs = vec![];
+
+    let pool_pubkey = Pubkey::from_str(&pool_address)?;
+    let pool = get_raydium_cpmm_pool(rpc_client, &pool_pubkey).await?;
+    let (input, _) = pool.swap_sides(&source_token)?;
+
+    let (builder, user_destination_token_account) = TxBuilder::default().wrap_sol(owner, 0)?;
+
+    let user_source_token_account =
+        get_associated_token_address_with_program_id(owner, &source_token, &input.token_program);
+
+    let minimum_amount_out = match slippage_bps {
+        Some(bps) => {
+            raydium_cpmm_minimum_amount_out(rpc_client, &pool, &source_token, amount_in, bps)
+                .await?
+        }
+        None => 0,
+    };
+
+    ixs.push(make_raydium_cpmm_swap_ix(
+        pool_pubkey,
+        &pool,
+        &source_token,
+        user_source_token_account,
+        user_destination_token_account,
+        *owner,
+        amount_in,
+        minimum_amount_out,
     )?);
 
-    Ok(ixs)
+    Ok(builder.instructions(ixs).build())
 }
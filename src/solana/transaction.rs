@@ -0,0 +1,1180 @@
+use anyhow::{anyhow, Result};
+use listen_kit::signer::SignerContext;
+use listen_kit::solana::util::execute_solana_transaction_with_tip;
+use serde::{Deserialize, Serialize};
+use solana_account_decoder::{UiAccountData, UiAccountEncoding};
+use solana_client::{
+    nonblocking::rpc_client::RpcClient,
+    rpc_config::{
+        RpcSendTransactionConfig, RpcSimulateTransactionAccountsConfig,
+        RpcSimulateTransactionConfig, RpcTransactionConfig,
+    },
+    rpc_request::TokenAccountsFilter,
+};
+use solana_sdk::{
+    account::Account,
+    commitment_config::CommitmentConfig,
+    compute_budget,
+    hash::Hash,
+    instruction::{AccountMeta, CompiledInstruction, Instruction},
+    message::Message,
+    nonce,
+    packet::PACKET_DATA_SIZE,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::Signature,
+    system_instruction::{self, SystemInstruction},
+    system_program,
+    transaction::{Transaction, VersionedTransaction},
+};
+use solana_transaction_status::{
+    option_serializer::OptionSerializer, TransactionStatus, UiTransactionEncoding,
+    UiTransactionStatusMeta, UiTransactionTokenBalance,
+};
+use spl_associated_token_account::{
+    get_associated_token_address_with_program_id,
+    instruction::create_associated_token_account_idempotent,
+};
+use spl_token_2022::{extension::StateWithExtensions, state::Account as TokenAccount};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+use crate::solana::util::generate_random_seed;
+
+/// Reason a transaction failed in simulation, decoded from the program logs
+#[derive(Debug, Error)]
+pub enum SimulationError {
+    #[error("slippage exceeded")]
+    SlippageExceeded { logs: Vec<String> },
+    #[error("insufficient funds")]
+    InsufficientFunds { logs: Vec<String> },
+    #[error("bonding curve complete")]
+    BondingCurveComplete { logs: Vec<String> },
+    #[error("simulation failed: {error}")]
+    Other { error: String, logs: Vec<String> },
+}
+
+impl SimulationError {
+    pub fn decode(error: String, logs: Vec<String>) -> Self {
+        let matches = |needles: &[&str]| {
+            logs.iter().any(|line| {
+                let line = line.to_lowercase();
+                needles.iter().any(|needle| line.contains(needle))
+            })
+        };
+
+        if matches(&[
+            "exceeds desired slippage limit",
+            "toomuchsolrequired",
+            "toolittlesolreceived",
+            "slippage",
+        ]) {
+            SimulationError::SlippageExceeded { logs }
+        } else if matches(&["insufficient funds", "insufficient lamports"])
+            || error.contains("InsufficientFunds")
+        {
+            SimulationError::InsufficientFunds { logs }
+        } else if matches(&["bondingcurvecomplete"]) {
+            SimulationError::BondingCurveComplete { logs }
+        } else {
+            SimulationError::Other { error, logs }
+        }
+    }
+
+    pub fn logs(&self) -> &[String] {
+        match self {
+            SimulationError::SlippageExceeded { logs }
+            | SimulationError::InsufficientFunds { logs }
+            | SimulationError::BondingCurveComplete { logs }
+            | SimulationError::Other { logs, .. } => logs,
+        }
+    }
+}
+
+/// Associated token account of `owner` for `mint` of `token_program`, and
+/// the instruction creating it. [`TxBuilder::skip_existing_atas`] leaves the
+/// instruction out when the account exists.
+pub fn ata_with_create_ix(
+    owner: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+) -> (Pubkey, Instruction) {
+    let ata = get_associated_token_address_with_program_id(owner, mint, token_program);
+    let create = create_associated_token_account_idempotent(owner, owner, mint, token_program);
+    (ata, create)
+}
+
+/// Account an associated token account program instruction creates, `None`
+/// for other instructions. Data is empty or `0` for `Create`, `1` for
+/// `CreateIdempotent`.
+fn created_ata(ix: &Instruction) -> Option<Pubkey> {
+    if ix.program_id != spl_associated_token_account::id() || ix.data.len() > 1 {
+        return None;
+    }
+    if ix.data.first().is_some_and(|kind| *kind > 1) {
+        return None;
+    }
+    ix.accounts.get(1).map(|account| account.pubkey)
+}
+
+/// Instructions of a swap transaction in the order they have to go: compute
+/// budget first, then those of the venue, then the closes of the temporary
+/// WSOL accounts of [`Self::wrap_sol`]. The tip is appended after them when
+/// the transaction is sent. A token account is created at most once, and
+/// [`Self::skip_existing_atas`] drops the creations of those the wallet
+/// already has, which cost compute units for nothing.
+#[derive(Debug, Clone, Default)]
+pub struct TxBuilder {
+    compute_budget: Vec<Instruction>,
+    ixs: Vec<Instruction>,
+    unwraps: Vec<Instruction>,
+}
+
+impl TxBuilder {
+    pub fn new(ixs: Vec<Instruction>) -> Self {
+        Self::default().instructions(ixs)
+    }
+
+    /// Append `ixs`. Compute budget instructions among them, like those of
+    /// venue builders, are moved in front.
+    pub fn instructions(mut self, ixs: impl IntoIterator<Item = Instruction>) -> Self {
+        for ix in ixs {
+            if ix.program_id == compute_budget::id() {
+                self.compute_budget.push(ix);
+            } else if created_ata(&ix).is_some_and(|ata| self.created_atas().contains(&ata)) {
+                continue;
+            } else {
+                self.ixs.push(ix);
+            }
+        }
+        self
+    }
+
+    /// Create a temporary WSOL account of `owner` holding `lamports` above
+    /// its rent, for a swap to pay SOL from or receive it in. It is closed
+    /// after every other instruction, which unwraps what it holds back to
+    /// the wallet along with the rent. Returns the account.
+    pub fn wrap_sol(mut self, owner: &Pubkey, lamports: u64) -> Result<(Self, Pubkey)> {
+        let seed = generate_random_seed();
+        let account = Pubkey::create_with_seed(owner, &seed, &spl_token::id())?;
+        let rent = Rent::default().minimum_balance(spl_token::state::Account::LEN);
+        self.ixs.push(system_instruction::create_account_with_seed(
+            owner,
+            &account,
+            owner,
+            &seed,
+            lamports + rent,
+            spl_token::state::Account::LEN as u64,
+            &spl_token::id(),
+        ));
+        self.ixs.push(spl_token::instruction::initialize_account(
+            &spl_token::id(),
+            &account,
+            &spl_token::native_mint::id(),
+            owner,
+        )?);
+        self.unwraps.push(spl_token::instruction::close_account(
+            &spl_token::id(),
+            &account,
+            owner,
+            owner,
+            &[owner],
+        )?);
+        Ok((self, account))
+    }
+
+    /// Replace the compute budget instructions, a transaction may only carry
+    /// one of each
+    pub fn compute_budget(mut self, ixs: Vec<Instruction>) -> Self {
+        self.compute_budget = ixs;
+        self
+    }
+
+    fn created_atas(&self) -> Vec<Pubkey> {
+        self.ixs.iter().filter_map(created_ata).collect()
+    }
+
+    fn skip_atas(mut self, existing: &[Pubkey]) -> Self {
+        self.ixs
+            .retain(|ix| created_ata(ix).map_or(true, |ata| !existing.contains(&ata)));
+        self
+    }
+
+    /// Leave out the creations of token accounts that exist, looked up in a
+    /// single `get_multiple_accounts`. One closed before the transaction
+    /// lands, by housekeeping say, makes it fail. When the lookup fails the
+    /// creations are kept, they are idempotent.
+    pub async fn skip_existing_atas(self, rpc_client: &RpcClient) -> Self {
+        let atas = self.created_atas();
+        if atas.is_empty() {
+            return self;
+        }
+        let accounts = match rpc_client.get_multiple_accounts(&atas).await {
+            Ok(accounts) => accounts,
+            Err(e) => {
+                tracing::warn!("Failed to look up token accounts {:?}: {}", atas, e);
+                return self;
+            }
+        };
+        let existing: Vec<Pubkey> = atas
+            .into_iter()
+            .zip(accounts)
+            .filter_map(|(ata, account)| account.map(|_| ata))
+            .collect();
+        if !existing.is_empty() {
+            tracing::debug!("Token accounts {:?} exist, not creating them", existing);
+        }
+        self.skip_atas(&existing)
+    }
+
+    pub fn build(self) -> Vec<Instruction> {
+        self.compute_budget
+            .into_iter()
+            .chain(self.ixs)
+            .chain(self.unwraps)
+            .collect()
+    }
+}
+
+/// Seed the durable nonce accounts of a wallet are derived with, followed by
+/// their index, so they need no key of their own
+const NONCE_SEED: &str = "durable-nonce";
+
+fn nonce_seed(index: usize) -> String {
+    format!("{}-{}", NONCE_SEED, index)
+}
+
+/// Address of the `index`th durable nonce account of `authority`
+pub fn nonce_address(authority: &Pubkey, index: usize) -> Result<Pubkey> {
+    Ok(Pubkey::create_with_seed(
+        authority,
+        &nonce_seed(index),
+        &system_program::id(),
+    )?)
+}
+
+/// Create and initialize the `index`th nonce account of `authority`, paid
+/// by it
+pub fn create_nonce_account_ixs(
+    authority: &Pubkey,
+    index: usize,
+    rent_lamports: u64,
+) -> Result<Vec<Instruction>> {
+    Ok(system_instruction::create_nonce_account_with_seed(
+        authority,
+        &nonce_address(authority, index)?,
+        authority,
+        &nonce_seed(index),
+        authority,
+        rent_lamports,
+    ))
+}
+
+/// Nonce stored in a nonce account, `None` until it is initialized
+pub fn parse_nonce(data: &[u8]) -> Option<Hash> {
+    let versions: nonce::state::Versions = bincode::deserialize(data).ok()?;
+    match versions.state() {
+        nonce::state::State::Initialized(data) => Some(data.blockhash()),
+        nonce::state::State::Uninitialized => None,
+    }
+}
+
+/// Current nonce of `nonce_account`, `None` while it doesn't exist
+pub async fn get_nonce(rpc_client: &RpcClient, nonce_account: &Pubkey) -> Result<Option<Hash>> {
+    let account = rpc_client
+        .get_account_with_commitment(nonce_account, CommitmentConfig::confirmed())
+        .await?
+        .value;
+    Ok(account.and_then(|account| parse_nonce(&account.data)))
+}
+
+/// Nonce account `tx` advances, when that's its first instruction
+pub fn nonce_account_of(tx: &Transaction) -> Option<Pubkey> {
+    let keys = &tx.message.account_keys;
+    let ix = tx.message.instructions.first()?;
+    if keys.get(ix.program_id_index as usize) != Some(&system_program::id()) {
+        return None;
+    }
+    match bincode::deserialize(&ix.data).ok()? {
+        SystemInstruction::AdvanceNonceAccount => keys.get(*ix.accounts.first()? as usize).copied(),
+        _ => None,
+    }
+}
+
+/// Instructions a message was compiled from
+fn decompile(message: &Message) -> Vec<Instruction> {
+    let keys = &message.account_keys;
+    message
+        .instructions
+        .iter()
+        .map(|ix| Instruction {
+            program_id: keys[ix.program_id_index as usize],
+            accounts: ix
+                .accounts
+                .iter()
+                .map(|&i| AccountMeta {
+                    pubkey: keys[i as usize],
+                    is_signer: message.is_signer(i as usize),
+                    is_writable: message.is_maybe_writable(i as usize, None),
+                })
+                .collect(),
+            data: ix.data.clone(),
+        })
+        .collect()
+}
+
+/// `tx` advancing `nonce_account` first, with its `nonce` for a blockhash:
+/// valid until the nonce is advanced instead of for ~150 slots. Unsigned,
+/// `None` when it no longer fits in a packet.
+pub fn with_durable_nonce(
+    tx: &Transaction,
+    nonce_account: &Pubkey,
+    authority: &Pubkey,
+    nonce: Hash,
+) -> Option<Transaction> {
+    let payer = tx.message.account_keys.first()?;
+    let mut message = Message::new_with_nonce(
+        decompile(&tx.message),
+        Some(payer),
+        nonce_account,
+        authority,
+    );
+    message.recent_blockhash = nonce;
+    let tx = Transaction::new_unsigned(message);
+    (bincode::serialized_size(&tx).ok()? as usize <= PACKET_DATA_SIZE).then_some(tx)
+}
+
+/// How long an account stays leased while its nonce doesn't move: past the
+/// time a swap waits for its transaction before advancing the nonce
+const NONCE_LEASE_TIMEOUT: Duration = Duration::from_secs(180);
+
+/// Nonce an account was leased with
+#[derive(Debug, Clone, Copy)]
+struct NonceLease {
+    nonce: Hash,
+    at: Instant,
+}
+
+/// The durable nonce accounts of a wallet, each leased to one transaction at
+/// a time: of two sharing a nonce only one could land. An account is free
+/// again once its nonce moved, when the transaction landed or the nonce was
+/// advanced.
+#[derive(Debug, Clone)]
+pub struct NoncePool {
+    authority: Pubkey,
+    accounts: Vec<Pubkey>,
+    next: Arc<AtomicUsize>,
+    leases: Arc<Mutex<HashMap<Pubkey, NonceLease>>>,
+}
+
+impl NoncePool {
+    pub fn new(authority: Pubkey, count: usize) -> Result<Self> {
+        Ok(Self {
+            authority,
+            accounts: (0..count)
+                .map(|index| nonce_address(&authority, index))
+                .collect::<Result<_>>()?,
+            next: Arc::default(),
+            leases: Arc::default(),
+        })
+    }
+
+    pub fn authority(&self) -> &Pubkey {
+        &self.authority
+    }
+
+    /// Lease the next account in turn no transaction in flight uses, with
+    /// its current nonce. `None` while every account is in use.
+    pub async fn lease(&self, rpc_client: &RpcClient) -> Result<Option<(Pubkey, Hash)>> {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        for offset in 0..self.accounts.len() {
+            let nonce_account = self.accounts[(start + offset) % self.accounts.len()];
+            let Some(nonce) = get_nonce(rpc_client, &nonce_account).await? else {
+                tracing::warn!("Nonce account {} is not initialized", nonce_account);
+                continue;
+            };
+            if self.try_lease(nonce_account, nonce, Instant::now()) {
+                return Ok(Some((nonce_account, nonce)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Lease `nonce_account` at `nonce` unless it's leased at that nonce
+    /// already and not for too long
+    fn try_lease(&self, nonce_account: Pubkey, nonce: Hash, now: Instant) -> bool {
+        let mut leases = self.leases.lock().unwrap();
+        if let Some(lease) = leases.get(&nonce_account) {
+            if lease.nonce == nonce && now.duration_since(lease.at) < NONCE_LEASE_TIMEOUT {
+                return false;
+            }
+        }
+        leases.insert(nonce_account, NonceLease { nonce, at: now });
+        true
+    }
+
+    /// Give back a leased account whose nonce went unused
+    pub fn release(&self, nonce_account: &Pubkey) {
+        self.leases.lock().unwrap().remove(nonce_account);
+    }
+
+    /// Create the accounts of the pool that don't exist yet, in one
+    /// transaction of the signer context. Each holds the rent of a nonce
+    /// account, ~0.0015 SOL.
+    pub async fn create_missing(&self, rpc_client: &RpcClient, tip_lamports: u64) -> Result<()> {
+        let existing = rpc_client.get_multiple_accounts(&self.accounts).await?;
+        let missing: Vec<usize> = existing
+            .iter()
+            .enumerate()
+            .filter_map(|(index, account)| account.is_none().then_some(index))
+            .collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+        let rent_lamports = rpc_client
+            .get_minimum_balance_for_rent_exemption(nonce::state::State::size())
+            .await?;
+        let mut ixs = Vec::new();
+        for &index in &missing {
+            ixs.extend(create_nonce_account_ixs(
+                &self.authority,
+                index,
+                rent_lamports,
+            )?);
+        }
+        let tx_sig =
+            execute_solana_transaction_with_tip(move |_| async move { Ok(ixs) }, tip_lamports)
+                .await?;
+        tracing::info!(
+            "Created {} durable nonce accounts: {}",
+            missing.len(),
+            tx_sig
+        );
+        Ok(())
+    }
+}
+
+/// Advance `nonce_account` of the wallet of the signer context, so no
+/// transaction signed with its current nonce can land anymore
+pub async fn advance_nonce(nonce_account: Pubkey, tip_lamports: u64) -> Result<String> {
+    execute_solana_transaction_with_tip(
+        move |owner| async move {
+            Ok(vec![system_instruction::advance_nonce_account(
+                &nonce_account,
+                &owner,
+            )])
+        },
+        tip_lamports,
+    )
+    .await
+}
+
+/// Give up on the transactions signed with `nonce` of `nonce_account`:
+/// advance it and wait until it moved. The advance runs in a task of its
+/// own, so it is no attempt of the swap being given up on.
+pub async fn abandon_nonce(
+    rpc_client: &RpcClient,
+    nonce_account: &Pubkey,
+    nonce: &Hash,
+    tip_lamports: u64,
+) -> Result<()> {
+    const POLL_INTERVAL: Duration = Duration::from_secs(2);
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    let signer = SignerContext::current().await;
+    let tx_sig = tokio::spawn(SignerContext::with_signer(
+        signer,
+        advance_nonce(*nonce_account, tip_lamports),
+    ))
+    .await??;
+    let started = Instant::now();
+    while can_still_land(rpc_client, nonce, Some(nonce_account)).await? {
+        if started.elapsed() >= TIMEOUT {
+            return Err(anyhow!(
+                "Nonce of {} did not move in {:?} after {}",
+                nonce_account,
+                TIMEOUT,
+                tx_sig
+            ));
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+    Ok(())
+}
+
+/// Simulate the instructions as a transaction paid by `payer`. Signatures are
+/// not verified and the blockhash is replaced by the node, so nothing needs
+/// to be signed and nothing is spent. Returns the compute units consumed,
+/// when the node reports them.
+pub async fn simulate_instructions(
+    rpc_client: &RpcClient,
+    payer: &Pubkey,
+    ixs: &[Instruction],
+) -> Result<Option<u64>> {
+    let tx = Transaction::new_unsigned(Message::new(ixs, Some(payer)));
+    let result = rpc_client
+        .simulate_transaction_with_config(
+            &tx,
+            RpcSimulateTransactionConfig {
+                sig_verify: false,
+                replace_recent_blockhash: true,
+                commitment: Some(CommitmentConfig::processed()),
+                ..Default::default()
+            },
+        )
+        .await?
+        .value;
+
+    if let Some(err) = result.err {
+        let error = SimulationError::decode(err.to_string(), result.logs.unwrap_or_default());
+        tracing::warn!("Simulation failed: {}, logs: {:?}", error, error.logs());
+        return Err(error.into());
+    }
+
+    tracing::debug!("Simulation ok, units consumed: {:?}", result.units_consumed);
+    Ok(result.units_consumed)
+}
+
+/// SOL leaving `owner` if `tx` lands, from a simulation of it
+pub async fn simulate_sol_outflow(
+    rpc_client: &RpcClient,
+    tx: &Transaction,
+    owner: &Pubkey,
+) -> Result<u64> {
+    let before = rpc_client.get_balance(owner).await?;
+    let result = rpc_client
+        .simulate_transaction_with_config(
+            tx,
+            RpcSimulateTransactionConfig {
+                sig_verify: false,
+                replace_recent_blockhash: true,
+                commitment: Some(CommitmentConfig::processed()),
+                accounts: Some(RpcSimulateTransactionAccountsConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    addresses: vec![owner.to_string()],
+                }),
+                ..Default::default()
+            },
+        )
+        .await?
+        .value;
+    if let Some(err) = result.err {
+        return Err(anyhow!("Simulation failed: {}", err));
+    }
+    let after = result
+        .accounts
+        .and_then(|accounts| accounts.into_iter().next().flatten())
+        .map(|account| account.lamports)
+        .ok_or_else(|| anyhow!("Simulation did not return the wallet account"))?;
+    Ok(before.saturating_sub(after))
+}
+
+/// What a transaction would do to its payer's wallet
+#[derive(Debug, Default)]
+pub struct SimulatedEffects {
+    pub units_consumed: Option<u64>,
+    /// Lamports, negative when spent. Fees are not included.
+    pub sol_change: i128,
+    /// Raw token amounts of the wallet's token accounts, per mint
+    pub token_changes: Vec<(Pubkey, i128)>,
+    pub logs: Vec<String>,
+}
+
+/// Mint, owner and raw amount of a token account of either token program
+fn token_account_amount(program: &Pubkey, data: &[u8]) -> Option<(Pubkey, Pubkey, u64)> {
+    if *program != spl_token::id() && *program != spl_token_2022::id() {
+        return None;
+    }
+    let account = StateWithExtensions::<TokenAccount>::unpack(data).ok()?;
+    Some((account.base.mint, account.base.owner, account.base.amount))
+}
+
+/// The balance changes of `owner` if `tx` lands, from a simulation that
+/// returns every account the transaction touches
+pub async fn simulate_effects(
+    rpc_client: &RpcClient,
+    tx: &Transaction,
+    owner: &Pubkey,
+) -> Result<SimulatedEffects> {
+    let keys = &tx.message.account_keys;
+    let before = rpc_client.get_multiple_accounts(keys).await?;
+    let result = rpc_client
+        .simulate_transaction_with_config(
+            tx,
+            RpcSimulateTransactionConfig {
+                sig_verify: false,
+                replace_recent_blockhash: true,
+                commitment: Some(CommitmentConfig::processed()),
+                accounts: Some(RpcSimulateTransactionAccountsConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    addresses: keys.iter().map(|key| key.to_string()).collect(),
+                }),
+                ..Default::default()
+            },
+        )
+        .await?
+        .value;
+    let logs = result.logs.unwrap_or_default();
+    if let Some(err) = result.err {
+        return Err(SimulationError::decode(err.to_string(), logs).into());
+    }
+
+    let mut effects = SimulatedEffects {
+        units_consumed: result.units_consumed,
+        logs,
+        ..Default::default()
+    };
+    let after = result.accounts.unwrap_or_default();
+    for (i, key) in keys.iter().enumerate() {
+        let before = before.get(i).cloned().flatten();
+        let after: Option<Account> = after.get(i).cloned().flatten().and_then(|a| a.decode());
+        if key == owner {
+            effects.sol_change = after.as_ref().map_or(0, |a| a.lamports as i128)
+                - before.as_ref().map_or(0, |a| a.lamports as i128);
+            continue;
+        }
+        let amount = |account: &Option<Account>| {
+            account
+                .as_ref()
+                .and_then(|a| token_account_amount(&a.owner, &a.data))
+                .filter(|(_, token_owner, _)| token_owner == owner)
+        };
+        let (before, after) = (amount(&before), amount(&after));
+        let Some(mint) = after.or(before).map(|(mint, _, _)| mint) else {
+            continue;
+        };
+        let change =
+            after.map_or(0, |(_, _, a)| a as i128) - before.map_or(0, |(_, _, a)| a as i128);
+        if change != 0 {
+            effects.token_changes.push((mint, change));
+        }
+    }
+    Ok(effects)
+}
+
+/// How a sent transaction ended
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TxStatus {
+    Confirmed,
+    /// Landed with an error, nothing but the fee was spent
+    Failed,
+    /// Its blockhash expired before it landed, it never will
+    Expired,
+    /// Only simulated, `TRADE_MODE=simulate` sends nothing
+    Simulated,
+}
+
+impl fmt::Display for TxStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxStatus::Confirmed => write!(f, "confirmed"),
+            TxStatus::Failed => write!(f, "failed"),
+            TxStatus::Expired => write!(f, "expired"),
+            TxStatus::Simulated => write!(f, "simulated"),
+        }
+    }
+}
+
+/// A swap whose transaction did not confirm
+#[derive(Debug, Error)]
+#[error("transaction {signature} {status}{}", error.as_ref().map_or(String::new(), |e| format!(": {}", e)))]
+pub struct TxNotConfirmed {
+    pub signature: String,
+    pub status: TxStatus,
+    /// Error of a failed transaction
+    pub error: Option<String>,
+}
+
+/// Final status of a transaction from its signature status, `None` while it
+/// can still confirm
+pub fn final_status(status: Option<&TransactionStatus>) -> Option<(TxStatus, Option<String>)> {
+    let status = status?;
+    if let Some(error) = &status.err {
+        return Some((TxStatus::Failed, Some(error.to_string())));
+    }
+    status
+        .satisfies_commitment(CommitmentConfig::confirmed())
+        .then_some((TxStatus::Confirmed, None))
+}
+
+/// Whether a transaction with `blockhash` can still land: the blockhash is
+/// valid, or for a durable nonce transaction, its nonce wasn't advanced
+pub async fn can_still_land(
+    rpc_client: &RpcClient,
+    blockhash: &Hash,
+    nonce_account: Option<&Pubkey>,
+) -> Result<bool> {
+    match nonce_account {
+        Some(nonce_account) => Ok(get_nonce(rpc_client, nonce_account).await? == Some(*blockhash)),
+        None => Ok(rpc_client
+            .is_blockhash_valid(blockhash, CommitmentConfig::processed())
+            .await?),
+    }
+}
+
+/// Wait until `signature` is confirmed, failed or expired with `blockhash`.
+/// While it is pending the signed `transaction`, when known, is sent again
+/// every few seconds, in case the first send was dropped on the way to the
+/// leader. Fails with [`TxNotConfirmed`] unless confirmed. A transaction
+/// of `nonce_account` can't expire by itself, its nonce is advanced when it
+/// is given up on, paying `tip_lamports`.
+pub async fn confirm_transaction(
+    rpc_client: &RpcClient,
+    signature: &Signature,
+    blockhash: &Hash,
+    nonce_account: Option<&Pubkey>,
+    transaction: Option<&Transaction>,
+    tip_lamports: u64,
+) -> Result<()> {
+    const POLL_INTERVAL: Duration = Duration::from_secs(2);
+    const REBROADCAST_INTERVAL: Duration = Duration::from_secs(4);
+    /// Past the ~60-90 s a blockhash lives, for RPCs lagging behind
+    const TIMEOUT: Duration = Duration::from_secs(120);
+
+    let started = Instant::now();
+    let mut last_sent = Instant::now();
+    let not_confirmed = |status, error| TxNotConfirmed {
+        signature: signature.to_string(),
+        status,
+        error,
+    };
+    loop {
+        let statuses = rpc_client
+            .get_signature_statuses(&[*signature])
+            .await?
+            .value;
+        match final_status(statuses.first().and_then(Option::as_ref)) {
+            Some((TxStatus::Confirmed, _)) => return Ok(()),
+            Some((status, error)) => return Err(not_confirmed(status, error).into()),
+            None => {}
+        }
+        if !can_still_land(rpc_client, blockhash, nonce_account).await? {
+            // It may have landed between the two calls
+            let statuses = rpc_client
+                .get_signature_statuses(&[*signature])
+                .await?
+                .value;
+            return match final_status(statuses.first().and_then(Option::as_ref)) {
+                Some((TxStatus::Confirmed, _)) => Ok(()),
+                Some((status, error)) => Err(not_confirmed(status, error).into()),
+                None => Err(not_confirmed(TxStatus::Expired, None).into()),
+            };
+        }
+        if started.elapsed() >= TIMEOUT {
+            tracing::warn!(
+                "Transaction {} still pending after {:?}",
+                signature,
+                TIMEOUT
+            );
+            // A durable nonce transaction would stay valid, it is only given
+            // up on once its nonce moved on
+            if let Some(nonce_account) = nonce_account {
+                abandon_nonce(rpc_client, nonce_account, blockhash, tip_lamports).await?;
+                let statuses = rpc_client
+                    .get_signature_statuses(&[*signature])
+                    .await?
+                    .value;
+                if let Some((TxStatus::Confirmed, _)) =
+                    final_status(statuses.first().and_then(Option::as_ref))
+                {
+                    return Ok(());
+                }
+            }
+            return Err(not_confirmed(TxStatus::Expired, None).into());
+        }
+        if let Some(transaction) = transaction {
+            if last_sent.elapsed() >= REBROADCAST_INTERVAL {
+                last_sent = Instant::now();
+                let config = RpcSendTransactionConfig {
+                    skip_preflight: true,
+                    max_retries: Some(0),
+                    ..Default::default()
+                };
+                if let Err(e) = rpc_client
+                    .send_transaction_with_config(transaction, config)
+                    .await
+                {
+                    tracing::debug!("Rebroadcast of {} failed: {:?}", signature, e);
+                }
+            }
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// A landed transaction with its metadata. Retries for a while since
+/// freshly confirmed transactions take a moment to become queryable.
+pub(crate) async fn get_landed_transaction(
+    rpc_client: &RpcClient,
+    signature: &str,
+) -> Result<(VersionedTransaction, UiTransactionStatusMeta)> {
+    const MAX_RETRIES: u32 = 10;
+    let signature = Signature::from_str(signature)?;
+    let config = RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::Base64),
+        commitment: Some(CommitmentConfig::confirmed()),
+        max_supported_transaction_version: Some(0),
+    };
+
+    let mut last_error = None;
+    for _ in 0..MAX_RETRIES {
+        match rpc_client
+            .get_transaction_with_config(&signature, config)
+            .await
+        {
+            Ok(tx) => {
+                let meta = tx
+                    .transaction
+                    .meta
+                    .ok_or_else(|| anyhow!("Transaction {} has no metadata", signature))?;
+                let transaction = tx
+                    .transaction
+                    .transaction
+                    .decode()
+                    .ok_or_else(|| anyhow!("Transaction {} could not be decoded", signature))?;
+                return Ok((transaction, meta));
+            }
+            Err(e) => {
+                last_error = Some(e);
+                tokio::time::sleep(Duration::from_millis(500)).await;
+            }
+        }
+    }
+    Err(anyhow!(
+        "Failed to fetch transaction {}: {:?}",
+        signature,
+        last_error
+    ))
+}
+
+async fn get_landed_transaction_meta(
+    rpc_client: &RpcClient,
+    signature: &str,
+) -> Result<UiTransactionStatusMeta> {
+    Ok(get_landed_transaction(rpc_client, signature).await?.1)
+}
+
+/// Lamports gained (positive) or spent (negative) by the fee payer of a
+/// landed transaction
+pub async fn get_fee_payer_balance_change(rpc_client: &RpcClient, signature: &str) -> Result<i64> {
+    let meta = get_landed_transaction_meta(rpc_client, signature).await?;
+    let (Some(pre), Some(post)) = (meta.pre_balances.first(), meta.post_balances.first()) else {
+        return Err(anyhow!("Transaction {} has no balances", signature));
+    };
+    Ok(*post as i64 - *pre as i64)
+}
+
+/// Lamports of a top-level SOL transfer by the fee payer, the first account
+fn payer_transfer_lamports(keys: &[Pubkey], ix: &CompiledInstruction) -> Option<u64> {
+    if keys.get(ix.program_id_index as usize) != Some(&system_program::id())
+        || ix.accounts.first() != Some(&0)
+    {
+        return None;
+    }
+    match bincode::deserialize(&ix.data).ok()? {
+        SystemInstruction::Transfer { lamports } => Some(lamports),
+        _ => None,
+    }
+}
+
+/// Lamports the swap of a landed transaction gained (positive) or spent
+/// (negative) for its fee payer: the balance change without the fee and the
+/// SOL the payer transferred, a tip say
+pub async fn get_swap_sol_change(rpc_client: &RpcClient, signature: &str) -> Result<i64> {
+    let (transaction, meta) = get_landed_transaction(rpc_client, signature).await?;
+    let (Some(pre), Some(post)) = (meta.pre_balances.first(), meta.post_balances.first()) else {
+        return Err(anyhow!("Transaction {} has no balances", signature));
+    };
+    let keys = transaction.message.static_account_keys();
+    let transferred: u64 = transaction
+        .message
+        .instructions()
+        .iter()
+        .filter_map(|ix| payer_transfer_lamports(keys, ix))
+        .sum();
+    Ok(*post as i64 - *pre as i64 + meta.fee as i64 + transferred as i64)
+}
+
+/// Fee paid by a landed transaction, base and priority fee together, and
+/// its number of signatures
+pub async fn get_transaction_fee(rpc_client: &RpcClient, signature: &str) -> Result<(u64, usize)> {
+    let (transaction, meta) = get_landed_transaction(rpc_client, signature).await?;
+    Ok((meta.fee, transaction.signatures.len()))
+}
+
+/// Raw amount of `mint` held by `owner` in the given token balances
+fn token_amount(
+    balances: OptionSerializer<Vec<UiTransactionTokenBalance>>,
+    owner: &str,
+    mint: &str,
+) -> Result<i128> {
+    Option::<Vec<_>>::from(balances)
+        .unwrap_or_default()
+        .iter()
+        .filter(|b| {
+            b.mint == mint
+                && Option::<&String>::from(b.owner.as_ref()).map(String::as_str) == Some(owner)
+        })
+        .map(|b| Ok(b.ui_token_amount.amount.parse::<i128>()?))
+        .sum()
+}
+
+/// Tokens of `mint` gained (positive) or sent (negative) by `owner` in a
+/// landed transaction. Unlike the wallet balance this is what the
+/// transaction itself moved, after any transfer fee.
+pub async fn get_token_balance_change(
+    rpc_client: &RpcClient,
+    signature: &str,
+    owner: &Pubkey,
+    mint: &Pubkey,
+) -> Result<i128> {
+    let meta = get_landed_transaction_meta(rpc_client, signature).await?;
+    let (owner, mint) = (owner.to_string(), mint.to_string());
+    let pre = token_amount(meta.pre_token_balances, &owner, &mint)?;
+    let post = token_amount(meta.post_token_balances, &owner, &mint)?;
+    Ok(post - pre)
+}
+
+/// Decimals of `mint`
+pub async fn get_mint_decimals(rpc_client: &RpcClient, mint: &Pubkey) -> Result<u8> {
+    Ok(rpc_client.get_token_supply(mint).await?.decimals)
+}
+
+/// Raw amount of `mint` in all token accounts of `owner`, zero when it has
+/// none
+pub async fn get_wallet_token_amount(
+    rpc_client: &RpcClient,
+    owner: &Pubkey,
+    mint: &Pubkey,
+) -> Result<u64> {
+    rpc_client
+        .get_token_accounts_by_owner(owner, TokenAccountsFilter::Mint(*mint))
+        .await?
+        .iter()
+        .map(|keyed| match &keyed.account.data {
+            UiAccountData::Json(account) => Ok(account.parsed["info"]["tokenAmount"]["amount"]
+                .as_str()
+                .ok_or_else(|| anyhow!("Token account {} has no amount", keyed.pubkey))?
+                .parse::<u64>()?),
+            _ => Err(anyhow!("Token account {} was not parsed", keyed.pubkey)),
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_raydium_slippage() {
+        let logs = vec![
+            "Program 675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8 invoke [1]".to_string(),
+            "Program log: Error: exceeds desired slippage limit".to_string(),
+        ];
+        let error = SimulationError::decode("custom program error: 0x1e".to_string(), logs);
+        assert!(matches!(error, SimulationError::SlippageExceeded { .. }));
+    }
+
+    #[test]
+    fn test_decode_insufficient_lamports() {
+        let logs = vec!["Transfer: insufficient lamports 1000, need 5000".to_string()];
+        let error = SimulationError::decode("custom program error: 0x1".to_string(), logs);
+        assert!(matches!(error, SimulationError::InsufficientFunds { .. }));
+    }
+
+    #[test]
+    fn test_final_status() {
+        use solana_sdk::transaction::TransactionError;
+        use solana_transaction_status::TransactionConfirmationStatus;
+
+        let status = |err: Option<TransactionError>, confirmation| TransactionStatus {
+            slot: 1,
+            confirmations: Some(0),
+            status: err.clone().map_or(Ok(()), Err),
+            err,
+            confirmation_status: Some(confirmation),
+        };
+        assert_eq!(final_status(None), None);
+        assert_eq!(
+            final_status(Some(&status(
+                None,
+                TransactionConfirmationStatus::Processed
+            ))),
+            None
+        );
+        assert_eq!(
+            final_status(Some(&status(
+                None,
+                TransactionConfirmationStatus::Confirmed
+            ))),
+            Some((TxStatus::Confirmed, None))
+        );
+        // A failed transaction is final as soon as it is processed
+        let failed = final_status(Some(&status(
+            Some(TransactionError::InsufficientFundsForFee),
+            TransactionConfirmationStatus::Processed,
+        )));
+        assert_eq!(failed.map(|(status, _)| status), Some(TxStatus::Failed));
+    }
+
+    #[test]
+    fn test_decode_unknown() {
+        let error = SimulationError::decode("AccountNotFound".to_string(), vec![]);
+        assert!(matches!(error, SimulationError::Other { .. }));
+    }
+
+    #[test]
+    fn test_tx_builder() {
+        use solana_sdk::compute_budget::ComputeBudgetInstruction;
+
+        let owner = Pubkey::new_unique();
+        let (mint, other_mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (ata, create) = ata_with_create_ix(&owner, &mint, &spl_token::id());
+        let (other_ata, create_other) = ata_with_create_ix(&owner, &other_mint, &spl_token::id());
+        let swap = Instruction::new_with_bytes(Pubkey::new_unique(), &[7], vec![]);
+        let limit = ComputeBudgetInstruction::set_compute_unit_limit(100_000);
+
+        let builder = TxBuilder::new(vec![
+            create.clone(),
+            limit.clone(),
+            create_other.clone(),
+            swap.clone(),
+        ])
+        // A venue creating the same account again
+        .instructions([create.clone()]);
+        assert_eq!(builder.created_atas(), vec![ata, other_ata]);
+
+        let ixs = builder.skip_atas(&[ata]).build();
+        assert_eq!(ixs, vec![limit.clone(), create_other, swap.clone()]);
+
+        let budget = ComputeBudgetInstruction::set_compute_unit_price(5);
+        let ixs = TxBuilder::new(vec![swap.clone(), limit])
+            .compute_budget(vec![budget.clone()])
+            .build();
+        assert_eq!(ixs, vec![budget, swap]);
+    }
+
+    #[test]
+    fn test_wrap_sol() {
+        let owner = Pubkey::new_unique();
+        let swap = Instruction::new_with_bytes(Pubkey::new_unique(), &[7], vec![]);
+        let (builder, wsol) = TxBuilder::default().wrap_sol(&owner, 1_000).unwrap();
+        let ixs = builder.instructions([swap.clone()]).build();
+        assert_eq!(ixs.len(), 4);
+        // Created and initialized before the swap, closed after it
+        assert_eq!(ixs[0].program_id, system_program::id());
+        assert_eq!(ixs[0].accounts[1].pubkey, wsol);
+        assert_eq!(ixs[1].program_id, spl_token::id());
+        assert_eq!(ixs[2], swap);
+        assert_eq!(ixs[3].program_id, spl_token::id());
+        assert_eq!(ixs[3].accounts[0].pubkey, wsol);
+    }
+
+    #[test]
+    fn test_with_durable_nonce() {
+        let owner = Pubkey::new_unique();
+        let nonce_account = nonce_address(&owner, 0).unwrap();
+        assert_ne!(nonce_account, nonce_address(&owner, 1).unwrap());
+
+        let ixs = vec![
+            compute_budget::ComputeBudgetInstruction::set_compute_unit_limit(80_000),
+            system_instruction::transfer(&owner, &Pubkey::new_unique(), 1_000),
+        ];
+        let tx = Transaction::new_unsigned(Message::new(&ixs, Some(&owner)));
+        assert_eq!(nonce_account_of(&tx), None);
+
+        let nonce = Hash::new_unique();
+        let durable = with_durable_nonce(&tx, &nonce_account, &owner, nonce).unwrap();
+        assert_eq!(durable.message.recent_blockhash, nonce);
+        assert_eq!(nonce_account_of(&durable), Some(nonce_account));
+        assert_eq!(decompile(&durable.message)[1..], ixs[..]);
+
+        // No room left for the advance
+        let large = Instruction::new_with_bytes(
+            Pubkey::new_unique(),
+            &[0; 1_100],
+            vec![AccountMeta::new(owner, true)],
+        );
+        let tx = Transaction::new_unsigned(Message::new(&[large], Some(&owner)));
+        assert!(with_durable_nonce(&tx, &nonce_account, &owner, nonce).is_none());
+    }
+
+    #[test]
+    fn test_nonce_leases() {
+        let pool = NoncePool::new(Pubkey::new_unique(), 2).unwrap();
+        let nonce_account = pool.accounts[0];
+        let (nonce, now) = (Hash::new_unique(), Instant::now());
+        assert!(pool.try_lease(nonce_account, nonce, now));
+        // In use until its nonce moves
+        assert!(!pool.try_lease(nonce_account, nonce, now));
+        assert!(pool.try_lease(nonce_account, Hash::new_unique(), now));
+
+        let nonce = Hash::new_unique();
+        assert!(pool.try_lease(nonce_account, nonce, now));
+        assert!(pool.try_lease(nonce_account, nonce, now + NONCE_LEASE_TIMEOUT));
+        pool.release(&nonce_account);
+        assert!(pool.try_lease(nonce_account, nonce, now));
+    }
+
+    #[test]
+    fn test_parse_nonce() {
+        use nonce::state::{Data, DurableNonce, State, Versions};
+
+        let data = Data::new(
+            Pubkey::new_unique(),
+            DurableNonce::from_blockhash(&Hash::new_unique()),
+            5_000,
+        );
+        let nonce = data.blockhash();
+        let account = bincode::serialize(&Versions::new(State::Initialized(data))).unwrap();
+        assert_eq!(parse_nonce(&account), Some(nonce));
+        let account = bincode::serialize(&Versions::new(State::Uninitialized)).unwrap();
+        assert_eq!(parse_nonce(&account), None);
+        assert_eq!(parse_nonce(&[1, 2]), None);
+    }
+
+    #[test]
+    fn test_payer_transfer_lamports() {
+        let (payer, tip_account) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let message = Message::new(
+            &[
+                system_instruction::transfer(&payer, &tip_account, 1_000),
+                system_instruction::transfer(&tip_account, &payer, 7),
+                Instruction::new_with_bytes(Pubkey::new_unique(), &[2, 0, 0, 0], vec![]),
+            ],
+            Some(&payer),
+        );
+        let transfers: Vec<Option<u64>> = message
+            .instructions
+            .iter()
+            .map(|ix| payer_transfer_lamports(&message.account_keys, ix))
+            .collect();
+        assert_eq!(transfers, vec![Some(1_000), None, None]);
+    }
+
+    #[test]
+    fn test_token_account_amount() {
+        let (mint, owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            mint,
+            owner,
+            amount: 42,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        assert_eq!(
+            token_account_amount(&spl_token::id(), &data),
+            Some((mint, owner, 42))
+        );
+        assert_eq!(token_account_amount(&Pubkey::new_unique(), &data), None);
+    }
+}
@@ -0,0 +1,54 @@
+use anyhow::Result;
+use listen_kit::solana::util::execute_solana_transaction_with_tip;
+use solana_sdk::{pubkey::Pubkey, system_instruction};
+
+/// Fee of a transaction signed by the wallet alone
+pub const SIGNATURE_FEE_LAMPORTS: u64 = 5_000;
+
+/// Send `lamports` of SOL from the wallet of the signer context to
+/// `destination`, returns the signature
+pub async fn transfer_sol(destination: Pubkey, lamports: u64, tip_lamports: u64) -> Result<String> {
+    execute_solana_transaction_with_tip(
+        move |owner| async move {
+            Ok(vec![system_instruction::transfer(
+                &owner,
+                &destination,
+                lamports,
+            )])
+        },
+        tip_lamports,
+    )
+    .await
+}
+
+/// Lamports to move out of a wallet holding `balance` so it's left with
+/// `threshold` once the transfer paid `cost`. `None` when the excess is
+/// below `min_lamports`, not worth a transaction.
+pub fn excess_lamports(balance: u64, threshold: u64, cost: u64, min_lamports: u64) -> Option<u64> {
+    balance
+        .checked_sub(threshold.saturating_add(cost))
+        .filter(|excess| *excess > 0 && *excess >= min_lamports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_excess_lamports() {
+        let sol = 1_000_000_000;
+        let cost = SIGNATURE_FEE_LAMPORTS + 100_000;
+        assert_eq!(
+            excess_lamports(7 * sol, 5 * sol, cost, sol / 100),
+            Some(2 * sol - cost)
+        );
+        // At or under the threshold
+        assert_eq!(excess_lamports(5 * sol, 5 * sol, cost, 0), None);
+        assert_eq!(excess_lamports(4 * sol, 5 * sol, cost, 0), None);
+        // Dust
+        assert_eq!(
+            excess_lamports(5 * sol + cost + 1_000, 5 * sol, cost, sol / 100),
+            None
+        );
+    }
+}
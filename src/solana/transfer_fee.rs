@@ -0,0 +1,41 @@
+use anyhow::Result;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{clock::Epoch, pubkey::Pubkey};
+use spl_token_2022::{
+    extension::{transfer_fee::TransferFeeConfig, BaseStateWithExtensions, StateWithExtensions},
+    state::Mint,
+};
+
+/// Transfer fee in basis points a Token-2022 mint charges in `epoch`.
+/// `None` for SPL token mints and mints without the extension.
+pub fn parse_transfer_fee_bps(owner: &Pubkey, data: &[u8], epoch: Epoch) -> Result<Option<u16>> {
+    if *owner != spl_token_2022::id() {
+        return Ok(None);
+    }
+    let mint = StateWithExtensions::<Mint>::unpack(data)?;
+    let Ok(config) = mint.get_extension::<TransferFeeConfig>() else {
+        return Ok(None);
+    };
+    let bps = u16::from(config.get_epoch_fee(epoch).transfer_fee_basis_points);
+    Ok((bps > 0).then_some(bps))
+}
+
+/// Current transfer fee of `mint`, see [`parse_transfer_fee_bps`]
+pub async fn get_transfer_fee_bps(rpc_client: &RpcClient, mint: &Pubkey) -> Result<Option<u16>> {
+    let account = rpc_client.get_account(mint).await?;
+    let epoch = rpc_client.get_epoch_info().await?.epoch;
+    parse_transfer_fee_bps(&account.owner, &account.data, epoch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spl_token_mints_have_no_fee() {
+        assert_eq!(
+            parse_transfer_fee_bps(&spl_token::id(), &[0; 82], 0).unwrap(),
+            None
+        );
+    }
+}
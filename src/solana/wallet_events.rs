@@ -0,0 +1,187 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A transaction of a Helius enhanced transaction webhook, only the fields
+/// telling which balances it changed
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HeliusTransaction {
+    pub signature: String,
+    #[serde(default)]
+    pub account_data: Vec<AccountData>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountData {
+    pub account: String,
+    #[serde(default)]
+    pub native_balance_change: i64,
+    #[serde(default)]
+    pub token_balance_changes: Vec<TokenBalanceChange>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenBalanceChange {
+    pub user_account: String,
+    pub mint: String,
+}
+
+/// Balances of a wallet a transaction changed
+#[derive(Debug, Default, PartialEq)]
+pub struct WalletTouch {
+    pub sol: bool,
+    pub mints: Vec<String>,
+}
+
+impl HeliusTransaction {
+    /// What the transaction changed for `wallet`, `None` when it didn't
+    /// touch the wallet at all
+    pub fn touched(&self, wallet: &str) -> Option<WalletTouch> {
+        let mut touch = WalletTouch::default();
+        for account in &self.account_data {
+            if account.account == wallet && account.native_balance_change != 0 {
+                touch.sol = true;
+            }
+            for change in &account.token_balance_changes {
+                if change.user_account == wallet && !touch.mints.contains(&change.mint) {
+                    touch.mints.push(change.mint.clone());
+                }
+            }
+        }
+        (touch.sol || !touch.mints.is_empty()).then_some(touch)
+    }
+}
+
+/// Holdings of the positions of a mint cut down to the `held` tokens the
+/// wallet has left, in proportion to their size
+pub fn shrink_holdings(holdings: &[u64], held: u64) -> Vec<u64> {
+    let tracked: u64 = holdings.iter().sum();
+    if held >= tracked {
+        return holdings.to_vec();
+    }
+    let mut shrunk: Vec<u64> = holdings
+        .iter()
+        .map(|&h| (h as u128 * held as u128 / tracked as u128) as u64)
+        .collect();
+    // Rounding leftovers go to the largest position
+    let rest = held - shrunk.iter().sum::<u64>();
+    if let Some(largest) = (0..holdings.len()).max_by_key(|&i| holdings[i]) {
+        shrunk[largest] += rest;
+    }
+    shrunk
+}
+
+/// SOL balances of the signer wallets, kept current by the wallet webhook
+/// so buys don't have to ask the RPC first. Entries older than `max_age`
+/// are read again, in case webhook deliveries were lost.
+pub struct WalletBalances {
+    max_age: Duration,
+    sol: Mutex<HashMap<String, (u64, Instant)>>,
+    /// Mints with a swap of ours in progress, and how many
+    swapping: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl WalletBalances {
+    pub fn new(max_age: Duration) -> Self {
+        Self {
+            max_age,
+            sol: Mutex::default(),
+            swapping: Arc::default(),
+        }
+    }
+
+    pub fn sol_lamports(&self, wallet: &str) -> Option<u64> {
+        self.sol
+            .lock()
+            .unwrap()
+            .get(wallet)
+            .filter(|(_, updated)| updated.elapsed() < self.max_age)
+            .map(|(lamports, _)| *lamports)
+    }
+
+    pub fn set_sol_lamports(&self, wallet: &str, lamports: u64) {
+        self.sol
+            .lock()
+            .unwrap()
+            .insert(wallet.to_string(), (lamports, Instant::now()));
+    }
+
+    /// Mark a swap of `mint` in progress until the guard is dropped. The
+    /// balances it changes are forgotten then, the positions it books are
+    /// not reconciled with the wallet meanwhile.
+    pub fn swapping(self: &Arc<Self>, mint: &str) -> SwapGuard {
+        *self
+            .swapping
+            .lock()
+            .unwrap()
+            .entry(mint.to_string())
+            .or_default() += 1;
+        SwapGuard {
+            balances: Arc::clone(self),
+            mint: mint.to_string(),
+        }
+    }
+
+    pub fn is_swapping(&self, mint: &str) -> bool {
+        self.swapping.lock().unwrap().contains_key(mint)
+    }
+}
+
+pub struct SwapGuard {
+    balances: Arc<WalletBalances>,
+    mint: String,
+}
+
+impl Drop for SwapGuard {
+    fn drop(&mut self) {
+        let mut swapping = self.balances.swapping.lock().unwrap();
+        if let Some(count) = swapping.get_mut(&self.mint) {
+            *count -= 1;
+            if *count == 0 {
+                swapping.remove(&self.mint);
+            }
+        }
+        drop(swapping);
+        self.balances.sol.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_touched() {
+        let tx: HeliusTransaction = serde_json::from_value(serde_json::json!({
+            "signature": "5x",
+            "type": "TRANSFER",
+            "accountData": [
+                { "account": "wallet", "nativeBalanceChange": -5000, "tokenBalanceChanges": [] },
+                { "account": "ata", "nativeBalanceChange": 0, "tokenBalanceChanges": [
+                    { "userAccount": "wallet", "tokenAccount": "ata", "mint": "mint",
+                      "rawTokenAmount": { "tokenAmount": "-100", "decimals": 6 } }
+                ] }
+            ]
+        }))
+        .unwrap();
+        assert_eq!(
+            tx.touched("wallet"),
+            Some(WalletTouch {
+                sol: true,
+                mints: vec!["mint".to_string()],
+            })
+        );
+        assert_eq!(tx.touched("other"), None);
+    }
+
+    #[test]
+    fn test_shrink_holdings() {
+        assert_eq!(shrink_holdings(&[100, 50], 200), vec![100, 50]);
+        assert_eq!(shrink_holdings(&[100, 50], 100), vec![67, 33]);
+        assert_eq!(shrink_holdings(&[100, 50], 0), vec![0, 0]);
+    }
+}
@@ -0,0 +1,214 @@
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use solana_sdk::transaction::Transaction;
+
+use crate::config::PrivyConfig;
+
+/// A server wallet of the Privy app
+#[derive(Debug, Clone, Deserialize)]
+pub struct PrivyWallet {
+    pub id: String,
+    pub address: String,
+    pub chain_type: String,
+}
+
+/// Privy REST API client for the wallets of one app. Requests authenticate
+/// with the app ID and secret, so only wallets owned by the app itself can
+/// be used: wallets with an owner or a policy also need an authorization
+/// signature.
+pub struct WalletManager {
+    client: Client,
+    config: PrivyConfig,
+}
+
+impl WalletManager {
+    pub fn new(config: PrivyConfig) -> Self {
+        Self {
+            client: Client::new(),
+            config,
+        }
+    }
+
+    fn request(&self, request: RequestBuilder) -> RequestBuilder {
+        request
+            .basic_auth(&self.config.app_id, Some(&self.config.app_secret))
+            .header("privy-app-id", &self.config.app_id)
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/v1/{}", self.config.api_url.trim_end_matches('/'), path)
+    }
+
+    async fn send(&self, request: RequestBuilder, what: &str) -> Result<Value> {
+        let response = self.request(request).send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+        parse_response(status, &body, what)
+    }
+
+    /// Create a Solana wallet owned by the app, `session_signers` (key
+    /// quorum IDs) may sign with it too
+    pub async fn create_wallet(&self, session_signers: &[String]) -> Result<PrivyWallet> {
+        let mut request = json!({ "chain_type": "solana" });
+        if !session_signers.is_empty() {
+            request["additional_signers"] = additional_signers(session_signers);
+        }
+        let body = self
+            .send(
+                self.client.post(self.url("wallets")).json(&request),
+                "wallet creation",
+            )
+            .await?;
+        Ok(serde_json::from_value(body)?)
+    }
+
+    /// Let the key quorums `session_signers` sign with `wallet_id`, in place
+    /// of the signers it had
+    pub async fn set_session_signers(
+        &self,
+        wallet_id: &str,
+        session_signers: &[String],
+    ) -> Result<PrivyWallet> {
+        let body = self
+            .send(
+                self.client
+                    .patch(self.url(&format!("wallets/{}", wallet_id)))
+                    .json(&json!({ "additional_signers": additional_signers(session_signers) })),
+                "session signer update",
+            )
+            .await?;
+        Ok(serde_json::from_value(body)?)
+    }
+
+    pub async fn wallet(&self, wallet_id: &str) -> Result<PrivyWallet> {
+        let body = self
+            .send(
+                self.client.get(self.url(&format!("wallets/{}", wallet_id))),
+                "wallet lookup",
+            )
+            .await?;
+        let wallet: PrivyWallet = serde_json::from_value(body)?;
+        if wallet.chain_type != "solana" {
+            return Err(anyhow!(
+                "Privy wallet {} is a {} wallet, not a Solana one",
+                wallet_id,
+                wallet.chain_type
+            ));
+        }
+        Ok(wallet)
+    }
+
+    /// Have `wallet_id` sign `tx`. Signatures already on it are kept, the
+    /// transaction is not sent.
+    pub async fn sign_transaction(&self, wallet_id: &str, tx: &Transaction) -> Result<Transaction> {
+        let body = self
+            .send(
+                self.client
+                    .post(self.url(&format!("wallets/{}/rpc", wallet_id)))
+                    .json(&json!({
+                        "method": "signTransaction",
+                        "params": {
+                            "transaction": STANDARD.encode(bincode::serialize(tx)?),
+                            "encoding": "base64",
+                        },
+                    })),
+                "signTransaction",
+            )
+            .await?;
+        signed_transaction(&body)
+    }
+}
+
+/// The JSON body of a successful response. Error bodies need not be JSON,
+/// they are reported as is with the status.
+fn parse_response(status: StatusCode, body: &str, what: &str) -> Result<Value> {
+    if !status.is_success() {
+        return Err(anyhow!("Privy {} failed ({}): {}", what, status, body));
+    }
+    serde_json::from_str(body)
+        .map_err(|e| anyhow!("Privy {} returned invalid JSON ({}): {}", what, e, body))
+}
+
+fn additional_signers(session_signers: &[String]) -> Value {
+    session_signers
+        .iter()
+        .map(|signer_id| json!({ "signer_id": signer_id }))
+        .collect()
+}
+
+fn signed_transaction(body: &Value) -> Result<Transaction> {
+    let signed = body["data"]["signed_transaction"]
+        .as_str()
+        .ok_or_else(|| anyhow!("Privy signTransaction returned no transaction: {}", body))?;
+    Ok(bincode::deserialize(&STANDARD.decode(signed)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{hash::Hash, signature::Keypair, signer::Signer, system_instruction};
+
+    fn manager() -> WalletManager {
+        WalletManager::new(PrivyConfig {
+            app_id: "app".to_string(),
+            app_secret: "secret".to_string(),
+            wallet_id: None,
+            api_url: "https://api.privy.io/".to_string(),
+        })
+    }
+
+    #[test]
+    fn test_requests() {
+        let manager = manager();
+        assert_eq!(manager.url("wallets"), "https://api.privy.io/v1/wallets");
+        let request = manager
+            .request(manager.client.get(manager.url("wallets/w1")))
+            .build()
+            .unwrap();
+        assert_eq!(request.headers()["privy-app-id"], "app");
+        assert_eq!(
+            request.headers()["authorization"],
+            format!("Basic {}", STANDARD.encode("app:secret"))
+        );
+        assert_eq!(
+            additional_signers(&["quorum".to_string()]),
+            json!([{ "signer_id": "quorum" }])
+        );
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let body = parse_response(StatusCode::OK, r#"{"id":"w1"}"#, "wallet lookup").unwrap();
+        assert_eq!(body["id"], "w1");
+
+        // The status is reported even when the error body isn't JSON
+        let error = parse_response(StatusCode::BAD_GATEWAY, "<html>", "wallet lookup")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("502"), "{}", error);
+        assert!(parse_response(StatusCode::OK, "<html>", "wallet lookup").is_err());
+    }
+
+    #[test]
+    fn test_signed_transaction() {
+        let payer = Keypair::new();
+        let tx = Transaction::new_signed_with_payer(
+            &[system_instruction::transfer(
+                &payer.pubkey(),
+                &payer.pubkey(),
+                1,
+            )],
+            Some(&payer.pubkey()),
+            &[&payer],
+            Hash::new_unique(),
+        );
+        let body = json!({
+            "data": { "signed_transaction": STANDARD.encode(bincode::serialize(&tx).unwrap()) }
+        });
+        assert_eq!(signed_transaction(&body).unwrap(), tx);
+        assert!(signed_transaction(&json!({ "data": {} })).is_err());
+    }
+}
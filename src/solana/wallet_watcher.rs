@@ -0,0 +1,466 @@
+use anyhow::{anyhow, Result};
+use futures::StreamExt;
+use solana_client::{
+    nonblocking::pubsub_client::PubsubClient,
+    rpc_config::{RpcTransactionLogsConfig, RpcTransactionLogsFilter},
+};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_transaction_status::{
+    option_serializer::OptionSerializer, UiTransactionStatusMeta, UiTransactionTokenBalance,
+};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio::time;
+
+use crate::notify::market::market_summary;
+use crate::solana::cache::cached_search_ticker;
+use crate::solana::price::sol_price_usd;
+use crate::solana::raydium::{RAYDIUM_CPMM_PROGRAM, RAYDIUM_V4_PROGRAM};
+use crate::solana::route::{
+    METEORA_AMM_PROGRAM, METEORA_DLMM_PROGRAM, ORCA_WHIRLPOOL_PROGRAM, PUMP_AMM_PROGRAM,
+    RAYDIUM_CLMM_PROGRAM,
+};
+use crate::solana::rpc::make_rpc_client;
+use crate::solana::token_registry::token_registry;
+use crate::solana::transaction::get_landed_transaction;
+use crate::solana::ws::PUMP_PROGRAM;
+use crate::tg_copy::parse_trade::{CloseTrade, OpenTrade, OperationType, Trade};
+
+pub const JUPITER_V6_PROGRAM: &str = "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4";
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Programs a swap of a watched wallet goes through, transactions invoking
+/// none of them are not fetched
+const SWAP_PROGRAMS: [&str; 9] = [
+    PUMP_PROGRAM,
+    PUMP_AMM_PROGRAM,
+    RAYDIUM_V4_PROGRAM,
+    RAYDIUM_CPMM_PROGRAM,
+    RAYDIUM_CLMM_PROGRAM,
+    ORCA_WHIRLPOOL_PROGRAM,
+    METEORA_DLMM_PROGRAM,
+    METEORA_AMM_PROGRAM,
+    JUPITER_V6_PROGRAM,
+];
+
+/// A wallet whose swaps are copied as signals of `strategy`, given as
+/// `strategy=address`
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchedWallet {
+    pub strategy: String,
+    pub address: String,
+}
+
+impl FromStr for WatchedWallet {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (strategy, address) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected strategy=address, got '{}'", s))?;
+        let address = address.trim();
+        solana_sdk::pubkey::Pubkey::from_str(address)
+            .map_err(|e| anyhow!("Invalid wallet address '{}': {}", address, e))?;
+        Ok(Self {
+            strategy: strategy.trim().to_string(),
+            address: address.to_string(),
+        })
+    }
+}
+
+/// A swap of a watched wallet turned into a signal
+#[derive(Debug)]
+pub struct WalletSignal {
+    /// `wallet:<address>`
+    pub source: String,
+    pub trade: Trade,
+}
+
+/// Tokens of one mint a transaction moved for the wallet, raw amounts
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenDelta {
+    pub mint: String,
+    pub decimals: u8,
+    pub pre: u64,
+    pub post: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SwapSide {
+    Buy,
+    Sell,
+}
+
+/// A swap of SOL for a token or back, as seen from the wallet
+#[derive(Debug, Clone, PartialEq)]
+pub struct WalletSwap {
+    pub side: SwapSide,
+    pub mint: String,
+    pub decimals: u8,
+    /// Raw tokens bought or sold
+    pub token_amount: u64,
+    /// Tokens held before the swap
+    pub tokens_before: u64,
+    /// Lamports spent or received, fees excluded
+    pub sol_lamports: u64,
+}
+
+impl WalletSwap {
+    /// USD per whole token
+    pub fn price_usd(&self, sol_price_usd: f64) -> f64 {
+        let tokens = self.token_amount as f64 / 10f64.powi(self.decimals as i32);
+        if tokens == 0.0 {
+            return 0.0;
+        }
+        self.sol_lamports as f64 / 1e9 * sol_price_usd / tokens
+    }
+
+    /// Share of its holdings the wallet sold, `None` when it sold them all
+    pub fn sold_fraction(&self) -> Option<f64> {
+        (self.side == SwapSide::Sell && self.token_amount < self.tokens_before)
+            .then(|| self.token_amount as f64 / self.tokens_before as f64)
+    }
+}
+
+/// The swap in a transaction, from the wallet's SOL change, wrapped SOL
+/// included, and its token changes. Token to token swaps and transfers are
+/// `None`.
+pub fn classify_swap(sol_delta: i128, tokens: &[TokenDelta]) -> Option<WalletSwap> {
+    let wsol = spl_token::native_mint::id().to_string();
+    let wsol_delta: i128 = tokens
+        .iter()
+        .filter(|t| t.mint == wsol)
+        .map(|t| t.post as i128 - t.pre as i128)
+        .sum();
+    let sol_delta = sol_delta + wsol_delta;
+    let token = tokens
+        .iter()
+        .filter(|t| t.mint != wsol && t.pre != t.post)
+        .max_by_key(|t| (t.post as i128 - t.pre as i128).unsigned_abs())?;
+    let token_delta = token.post as i128 - token.pre as i128;
+    let side = match (token_delta > 0, sol_delta) {
+        (true, delta) if delta < 0 => SwapSide::Buy,
+        (false, delta) if delta > 0 => SwapSide::Sell,
+        _ => return None,
+    };
+    Some(WalletSwap {
+        side,
+        mint: token.mint.clone(),
+        decimals: token.decimals,
+        token_amount: token_delta.unsigned_abs() as u64,
+        tokens_before: token.pre,
+        sol_lamports: sol_delta.unsigned_abs() as u64,
+    })
+}
+
+fn owned_balances(
+    balances: &OptionSerializer<Vec<UiTransactionTokenBalance>>,
+    owner: &str,
+) -> Vec<(String, u8, u64)> {
+    Option::<&Vec<_>>::from(balances.as_ref())
+        .map(|balances| {
+            balances
+                .iter()
+                .filter(|b| {
+                    Option::<&String>::from(b.owner.as_ref()).map(String::as_str) == Some(owner)
+                })
+                .filter_map(|b| {
+                    Some((
+                        b.mint.clone(),
+                        b.ui_token_amount.decimals,
+                        b.ui_token_amount.amount.parse().ok()?,
+                    ))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Token changes of `owner` in a transaction, summed per mint
+fn token_deltas(meta: &UiTransactionStatusMeta, owner: &str) -> Vec<TokenDelta> {
+    let mut deltas: Vec<TokenDelta> = Vec::new();
+    let mut entry = |mint: String, decimals: u8| -> usize {
+        match deltas.iter().position(|d| d.mint == mint) {
+            Some(i) => i,
+            None => {
+                deltas.push(TokenDelta {
+                    mint,
+                    decimals,
+                    pre: 0,
+                    post: 0,
+                });
+                deltas.len() - 1
+            }
+        }
+    };
+    let mut pre = HashMap::new();
+    for (mint, decimals, amount) in owned_balances(&meta.pre_token_balances, owner) {
+        *pre.entry(entry(mint, decimals)).or_insert(0) += amount;
+    }
+    let mut post = HashMap::new();
+    for (mint, decimals, amount) in owned_balances(&meta.post_token_balances, owner) {
+        *post.entry(entry(mint, decimals)).or_insert(0) += amount;
+    }
+    for (i, delta) in deltas.iter_mut().enumerate() {
+        delta.pre = pre.get(&i).copied().unwrap_or(0);
+        delta.post = post.get(&i).copied().unwrap_or(0);
+    }
+    deltas
+}
+
+/// The swap `wallet` made in the transaction `signature`, if it made one
+async fn fetch_swap(wallet: &str, signature: &str) -> Result<Option<WalletSwap>> {
+    let (transaction, meta) = get_landed_transaction(&make_rpc_client(), signature).await?;
+    let Some(index) = transaction
+        .message
+        .static_account_keys()
+        .iter()
+        .position(|key| key.to_string() == wallet)
+    else {
+        return Ok(None);
+    };
+    let (Some(pre), Some(post)) = (meta.pre_balances.get(index), meta.post_balances.get(index))
+    else {
+        return Ok(None);
+    };
+    let mut sol_delta = *post as i128 - *pre as i128;
+    // The fee is no part of the price
+    if index == 0 {
+        sol_delta += meta.fee as i128;
+    }
+    Ok(classify_swap(sol_delta, &token_deltas(&meta, wallet)))
+}
+
+async fn token_symbol(mint: &str) -> String {
+    if let Some(symbol) = token_registry().symbol(mint).await {
+        return symbol;
+    }
+    cached_search_ticker(mint)
+        .await
+        .ok()
+        .and_then(|response| {
+            response
+                .pairs
+                .into_iter()
+                .find(|pair| pair.base_token.address == mint)
+        })
+        .map_or_else(
+            || mint.chars().take(6).collect(),
+            |pair| pair.base_token.symbol,
+        )
+}
+
+/// Turns the swaps of one wallet into signals, remembering its buy prices
+/// for the PnL of its sells
+struct SignalMaker {
+    wallet: WatchedWallet,
+    entry_prices: HashMap<String, f64>,
+}
+
+impl SignalMaker {
+    async fn signal(&mut self, swap: WalletSwap) -> Result<Trade> {
+        let price = swap.price_usd(sol_price_usd().await?);
+        let token = token_symbol(&swap.mint).await;
+        let strategy = self.wallet.strategy.clone();
+        Ok(match swap.side {
+            SwapSide::Buy => {
+                self.entry_prices.insert(swap.mint.clone(), price);
+                Trade::Open(OpenTrade {
+                    strategy,
+                    token,
+                    buy_price: price,
+                    num_buys: 1,
+                    total_buys: None,
+                    time_window: 0,
+                    market_cap: market_summary(&swap.mint)
+                        .await
+                        .and_then(|summary| summary.market_cap_usd)
+                        .unwrap_or(0.0),
+                    contract_address: swap.mint,
+                })
+            }
+            SwapSide::Sell => {
+                let entry_price = self.entry_prices.get(&swap.mint).copied().unwrap_or(0.0);
+                let sell_fraction = swap.sold_fraction();
+                if sell_fraction.is_none() {
+                    self.entry_prices.remove(&swap.mint);
+                }
+                Trade::Close(CloseTrade {
+                    strategy,
+                    op_type: OperationType::Manual,
+                    token,
+                    entry_price,
+                    exit_price: price,
+                    profit_pct: if entry_price > 0.0 {
+                        (price / entry_price - 1.0) * 100.0
+                    } else {
+                        0.0
+                    },
+                    contract_address: swap.mint,
+                    sell_fraction,
+                })
+            }
+        })
+    }
+}
+
+async fn watch_logs(
+    ws_url: &str,
+    maker: &mut SignalMaker,
+    signals: &mpsc::Sender<WalletSignal>,
+) -> Result<()> {
+    let client = PubsubClient::new(ws_url).await?;
+    let (mut stream, _unsubscribe) = client
+        .logs_subscribe(
+            RpcTransactionLogsFilter::Mentions(vec![maker.wallet.address.clone()]),
+            RpcTransactionLogsConfig {
+                commitment: Some(CommitmentConfig::confirmed()),
+            },
+        )
+        .await?;
+    tracing::info!(
+        "Watching wallet {} for {}",
+        maker.wallet.address,
+        maker.wallet.strategy
+    );
+
+    while let Some(response) = stream.next().await {
+        let logs = response.value;
+        let is_swap = logs.logs.iter().any(|line| {
+            SWAP_PROGRAMS
+                .iter()
+                .any(|program| line.starts_with(&format!("Program {} invoke", program)))
+        });
+        if logs.err.is_some() || !is_swap {
+            continue;
+        }
+        let swap = match fetch_swap(&maker.wallet.address, &logs.signature).await {
+            Ok(Some(swap)) => swap,
+            Ok(None) => continue,
+            Err(e) => {
+                tracing::warn!("Could not read watched swap {}: {:?}", logs.signature, e);
+                continue;
+            }
+        };
+        tracing::info!(
+            "Wallet {} swapped in {}: {:?}",
+            maker.wallet.address,
+            logs.signature,
+            swap
+        );
+        let trade = match maker.signal(swap).await {
+            Ok(trade) => trade,
+            Err(e) => {
+                tracing::warn!("Could not price watched swap {}: {:?}", logs.signature, e);
+                continue;
+            }
+        };
+        let signal = WalletSignal {
+            source: format!("wallet:{}", maker.wallet.address),
+            trade,
+        };
+        if signals.send(signal).await.is_err() {
+            return Ok(());
+        }
+    }
+    Err(anyhow!(
+        "Log subscription of {} closed",
+        maker.wallet.address
+    ))
+}
+
+/// Follow one wallet, resubscribing with backoff whenever the connection
+/// drops. Swaps made while disconnected are missed.
+async fn watch_wallet(ws_url: String, wallet: WatchedWallet, signals: mpsc::Sender<WalletSignal>) {
+    let mut maker = SignalMaker {
+        wallet,
+        entry_prices: HashMap::new(),
+    };
+    let mut delay = Duration::from_secs(1);
+    loop {
+        match watch_logs(&ws_url, &mut maker, &signals).await {
+            Ok(()) => return,
+            Err(e) => {
+                tracing::warn!(
+                    "Watching wallet {} failed, retrying in {:?}: {:?}",
+                    maker.wallet.address,
+                    delay,
+                    e
+                );
+                time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+            }
+        }
+    }
+}
+
+/// Copy the swaps of `wallets` as signals of their strategies into
+/// `signals` until `shutdown` flips
+pub async fn run_wallet_watcher(
+    ws_url: String,
+    wallets: Vec<WatchedWallet>,
+    signals: mpsc::Sender<WalletSignal>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let watchers: Vec<_> = wallets
+        .into_iter()
+        .map(|wallet| tokio::spawn(watch_wallet(ws_url.clone(), wallet, signals.clone())))
+        .collect();
+    let _ = shutdown.changed().await;
+    watchers.iter().for_each(|watcher| watcher.abort());
+    tracing::info!("Wallet watcher stopped");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delta(mint: &str, pre: u64, post: u64) -> TokenDelta {
+        TokenDelta {
+            mint: mint.to_string(),
+            decimals: 6,
+            pre,
+            post,
+        }
+    }
+
+    #[test]
+    fn test_classify_swap() {
+        // 1 SOL for 1M tokens on pump.fun
+        let buy = classify_swap(-1_000_000_000, &[delta("mint", 0, 1_000_000_000_000)]).unwrap();
+        assert_eq!(buy.side, SwapSide::Buy);
+        assert_eq!(buy.token_amount, 1_000_000_000_000);
+        assert_eq!(buy.price_usd(150.0), 0.00015);
+
+        // Half sold through Jupiter into wrapped SOL
+        let wsol = spl_token::native_mint::id().to_string();
+        let sell = classify_swap(
+            0,
+            &[
+                delta("mint", 1_000_000_000_000, 500_000_000_000),
+                delta(&wsol, 0, 2_000_000_000),
+            ],
+        )
+        .unwrap();
+        assert_eq!(sell.side, SwapSide::Sell);
+        assert_eq!(sell.tokens_before, 1_000_000_000_000);
+        assert_eq!(sell.sol_lamports, 2_000_000_000);
+        assert_eq!(sell.sold_fraction(), Some(0.5));
+        assert_eq!(buy.sold_fraction(), None);
+
+        // A transfer out moves no SOL
+        assert_eq!(classify_swap(0, &[delta("mint", 100, 0)]), None);
+    }
+
+    #[test]
+    fn test_watched_wallet() {
+        let wallet: WatchedWallet = "whale=9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM"
+            .parse()
+            .unwrap();
+        assert_eq!(wallet.strategy, "whale");
+        assert!("whale".parse::<WatchedWallet>().is_err());
+        assert!("whale=nope".parse::<WatchedWallet>().is_err());
+    }
+}
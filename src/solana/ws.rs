@@ -0,0 +1,419 @@
+use anyhow::{anyhow, Result};
+use futures::StreamExt;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    nonblocking::{pubsub_client::PubsubClient, rpc_client::RpcClient},
+    rpc_config::RpcAccountInfoConfig,
+};
+use solana_sdk::{account::Account, commitment_config::CommitmentConfig, pubkey::Pubkey};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use crate::solana::cache::cached_search_ticker;
+use crate::solana::raydium::{get_raydium_cpmm_pool, get_raydium_pool, token_account_amount};
+use crate::solana::route::{find_supported_pool, PoolProgram};
+use crate::solana::rpc::make_rpc_client;
+use crate::trade::meme_trader::MemeTrader;
+
+pub const PUMP_PROGRAM: &str = "6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P";
+const PUMP_TOKEN_DECIMALS: u8 = 6;
+const SYNC_INTERVAL: Duration = Duration::from_secs(30);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Reserves of the pool a token trades in, as last pushed by the node
+#[derive(Debug, Clone, Copy)]
+pub struct PoolSnapshot {
+    pub token_reserve: u64,
+    pub sol_reserve: u64,
+    pub token_decimals: u8,
+    pub updated_at: i64,
+}
+
+impl PoolSnapshot {
+    /// SOL per whole token
+    pub fn price_sol(&self) -> f64 {
+        if self.token_reserve == 0 {
+            return 0.0;
+        }
+        (self.sol_reserve as f64 / 1e9)
+            / (self.token_reserve as f64 / 10f64.powi(self.token_decimals as i32))
+    }
+}
+
+/// Latest pool state per mint, filled by account subscriptions so trading
+/// logic can read prices without hitting the RPC
+#[derive(Debug, Default)]
+pub struct PriceCache {
+    pools: RwLock<HashMap<String, PoolSnapshot>>,
+}
+
+impl PriceCache {
+    pub fn get(&self, mint: &str) -> Option<PoolSnapshot> {
+        self.pools
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(mint)
+            .copied()
+    }
+
+    /// Price in SOL if the snapshot is not older than `max_age`
+    pub fn price(&self, mint: &str, max_age: Duration) -> Option<f64> {
+        let snapshot = self.get(mint)?;
+        let age = chrono::Utc::now().timestamp() - snapshot.updated_at;
+        (age <= max_age.as_secs() as i64).then(|| snapshot.price_sol())
+    }
+
+    fn update(&self, mint: &str, token_reserve: u64, sol_reserve: u64, token_decimals: u8) {
+        self.pools
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(
+                mint.to_string(),
+                PoolSnapshot {
+                    token_reserve,
+                    sol_reserve,
+                    token_decimals,
+                    updated_at: chrono::Utc::now().timestamp(),
+                },
+            );
+    }
+
+    fn remove(&self, mint: &str) {
+        self.pools
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(mint);
+    }
+}
+
+/// Accounts whose changes move the price of a token
+#[derive(Debug, Clone, Copy)]
+enum PriceSource {
+    BondingCurve(Pubkey),
+    Vaults {
+        token_vault: Pubkey,
+        sol_vault: Pubkey,
+        token_decimals: u8,
+    },
+}
+
+pub(crate) fn bonding_curve_address(mint: &Pubkey) -> Result<Pubkey> {
+    let (address, _) = Pubkey::find_program_address(
+        &[b"bonding-curve", mint.as_ref()],
+        &Pubkey::from_str(PUMP_PROGRAM)?,
+    );
+    Ok(address)
+}
+
+/// (virtual token reserves, virtual SOL reserves, complete)
+pub(crate) fn parse_bonding_curve(data: &[u8]) -> Result<(u64, u64, bool)> {
+    let read = |offset: usize| -> Result<u64> {
+        Ok(u64::from_le_bytes(
+            data.get(offset..offset + 8)
+                .ok_or_else(|| anyhow!("Invalid bonding curve length: {}", data.len()))?
+                .try_into()?,
+        ))
+    };
+    let complete = *data
+        .get(48)
+        .ok_or_else(|| anyhow!("Invalid bonding curve length: {}", data.len()))?
+        != 0;
+    Ok((read(8)?, read(16)?, complete))
+}
+
+/// Use the bonding curve while the token is on Pump.fun, otherwise the
+/// vaults of its most liquid supported pool
+async fn resolve_price_source(rpc_client: &RpcClient, mint: &Pubkey) -> Result<PriceSource> {
+    let bonding_curve = bonding_curve_address(mint)?;
+    if let Some(account) = rpc_client
+        .get_account_with_commitment(&bonding_curve, CommitmentConfig::processed())
+        .await?
+        .value
+    {
+        let (_, _, complete) = parse_bonding_curve(&account.data)?;
+        if !complete {
+            return Ok(PriceSource::BondingCurve(bonding_curve));
+        }
+    }
+
+    let pairs = cached_search_ticker(&mint.to_string()).await?.pairs;
+    let (pool_pubkey, program) = find_supported_pool(rpc_client, &mint.to_string(), &pairs).await?;
+    let wsol = spl_token::native_mint::id();
+
+    let (vault_0, mint_0, decimals_0, vault_1, mint_1, decimals_1) = match program {
+        PoolProgram::RaydiumAmmV4 => {
+            let pool = get_raydium_pool(rpc_client, &pool_pubkey).await?;
+            (
+                pool.base_vault,
+                pool.base_mint,
+                pool.base_decimal as u8,
+                pool.quote_vault,
+                pool.quote_mint,
+                pool.quote_decimal as u8,
+            )
+        }
+        PoolProgram::RaydiumCpmm => {
+            let pool = get_raydium_cpmm_pool(rpc_client, &pool_pubkey).await?;
+            (
+                pool.token_0_vault,
+                pool.token_0_mint,
+                pool.mint_0_decimals,
+                pool.token_1_vault,
+                pool.token_1_mint,
+                pool.mint_1_decimals,
+            )
+        }
+        other => return Err(anyhow!("Prices of {} pools are not streamed", other)),
+    };
+
+    if mint_0 == *mint && mint_1 == wsol {
+        Ok(PriceSource::Vaults {
+            token_vault: vault_0,
+            sol_vault: vault_1,
+            token_decimals: decimals_0,
+        })
+    } else if mint_1 == *mint && mint_0 == wsol {
+        Ok(PriceSource::Vaults {
+            token_vault: vault_1,
+            sol_vault: vault_0,
+            token_decimals: decimals_1,
+        })
+    } else {
+        Err(anyhow!(
+            "Pool {} is not a SOL pair of {}",
+            pool_pubkey,
+            mint
+        ))
+    }
+}
+
+/// Price in SOL of `mint` read once from the reserves of its bonding curve
+/// or pool, for tokens whose price isn't streamed
+pub async fn pool_price_sol(rpc_client: &RpcClient, mint: &Pubkey) -> Result<f64> {
+    let (token_reserve, sol_reserve, token_decimals) =
+        match resolve_price_source(rpc_client, mint).await? {
+            PriceSource::BondingCurve(bonding_curve) => {
+                let data = rpc_client.get_account_data(&bonding_curve).await?;
+                let (token_reserve, sol_reserve, _) = parse_bonding_curve(&data)?;
+                (token_reserve, sol_reserve, PUMP_TOKEN_DECIMALS)
+            }
+            PriceSource::Vaults {
+                token_vault,
+                sol_vault,
+                token_decimals,
+            } => {
+                let accounts = rpc_client
+                    .get_multiple_accounts(&[token_vault, sol_vault])
+                    .await?;
+                let [Some(token), Some(sol)] = accounts.as_slice() else {
+                    return Err(anyhow!("Vaults of the pool of {} not found", mint));
+                };
+                (
+                    token_account_amount(&token.data)?,
+                    token_account_amount(&sol.data)?,
+                    token_decimals,
+                )
+            }
+        };
+    Ok(PoolSnapshot {
+        token_reserve,
+        sol_reserve,
+        token_decimals,
+        updated_at: chrono::Utc::now().timestamp(),
+    }
+    .price_sol())
+}
+
+fn subscribe_config() -> Option<RpcAccountInfoConfig> {
+    Some(RpcAccountInfoConfig {
+        encoding: Some(UiAccountEncoding::Base64),
+        commitment: Some(CommitmentConfig::processed()),
+        data_slice: None,
+        min_context_slot: None,
+    })
+}
+
+/// Stream updates of `source` into the cache. Returns `Ok` when the source
+/// is gone for good (bonding curve completed) and `Err` on disconnects.
+async fn watch_source(
+    ws_url: &str,
+    mint: &str,
+    source: PriceSource,
+    cache: &PriceCache,
+) -> Result<()> {
+    let client = PubsubClient::new(ws_url).await?;
+
+    match source {
+        PriceSource::BondingCurve(bonding_curve) => {
+            let (mut stream, _unsubscribe) = client
+                .account_subscribe(&bonding_curve, subscribe_config())
+                .await?;
+            while let Some(response) = stream.next().await {
+                let account: Account = response
+                    .value
+                    .decode()
+                    .ok_or_else(|| anyhow!("Undecodable bonding curve update"))?;
+                let (token_reserve, sol_reserve, complete) = parse_bonding_curve(&account.data)?;
+                if complete {
+                    tracing::info!("Bonding curve of {} completed, switching source", mint);
+                    return Ok(());
+                }
+                cache.update(mint, token_reserve, sol_reserve, PUMP_TOKEN_DECIMALS);
+            }
+        }
+        PriceSource::Vaults {
+            token_vault,
+            sol_vault,
+            token_decimals,
+        } => {
+            // Seed both sides, a quiet vault may not push an update for a while
+            let accounts = make_rpc_client()
+                .get_multiple_accounts(&[token_vault, sol_vault])
+                .await?;
+            let amount = |account: Option<&Account>| {
+                account
+                    .map(|a| token_account_amount(&a.data))
+                    .transpose()
+                    .map(|amount| amount.unwrap_or(0))
+            };
+            let mut token_reserve = amount(accounts.first().and_then(Option::as_ref))?;
+            let mut sol_reserve = amount(accounts.get(1).and_then(Option::as_ref))?;
+            cache.update(mint, token_reserve, sol_reserve, token_decimals);
+
+            let (token_stream, _unsubscribe_token) = client
+                .account_subscribe(&token_vault, subscribe_config())
+                .await?;
+            let (sol_stream, _unsubscribe_sol) = client
+                .account_subscribe(&sol_vault, subscribe_config())
+                .await?;
+            let mut updates = futures::stream::select(
+                token_stream.map(|response| (true, response)),
+                sol_stream.map(|response| (false, response)),
+            );
+
+            while let Some((is_token_vault, response)) = updates.next().await {
+                let account: Option<Account> = response.value.decode();
+                let amount = amount(account.as_ref())?;
+                if is_token_vault {
+                    token_reserve = amount;
+                } else {
+                    sol_reserve = amount;
+                }
+                cache.update(mint, token_reserve, sol_reserve, token_decimals);
+            }
+        }
+    }
+
+    Err(anyhow!("Subscription stream for {} closed", mint))
+}
+
+/// Keep the cache of one mint up to date, re-resolving the source and
+/// resubscribing with backoff whenever the connection drops
+async fn watch_token(ws_url: String, mint: String, cache: Arc<PriceCache>) {
+    let mut delay = Duration::from_secs(1);
+    loop {
+        let result = async {
+            let source =
+                resolve_price_source(&make_rpc_client(), &Pubkey::from_str(&mint)?).await?;
+            tracing::info!("Watching {} via {:?}", mint, source);
+            watch_source(&ws_url, &mint, source, &cache).await
+        }
+        .await;
+
+        match result {
+            Ok(()) => delay = Duration::from_secs(1),
+            Err(e) => {
+                tracing::warn!(
+                    "Price subscription for {} failed, retrying in {:?}: {:?}",
+                    mint,
+                    delay,
+                    e
+                );
+                time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+            }
+        }
+    }
+}
+
+/// Subscribe to the price accounts of every open position and drop the
+/// subscriptions of positions that were closed
+pub async fn run_price_stream(
+    trader: Arc<MemeTrader>,
+    ws_url: String,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    let cache = trader.price_cache();
+    let mut watchers: HashMap<String, JoinHandle<()>> = HashMap::new();
+    let mut interval = time::interval(SYNC_INTERVAL);
+    tracing::info!("Price stream started");
+
+    loop {
+        tokio::select! {
+            biased;
+            _ = shutdown.changed() => {
+                watchers.values().for_each(JoinHandle::abort);
+                tracing::info!("Price stream stopped");
+                return Ok(());
+            }
+            _ = interval.tick() => {}
+        }
+
+        let held: HashSet<String> = match trader.active_trades().load_all_trades().await {
+            Ok(trades) => trades.into_iter().map(|t| t.token_address).collect(),
+            Err(e) => {
+                tracing::error!("Price stream failed to load active trades: {:?}", e);
+                continue;
+            }
+        };
+
+        watchers.retain(|mint, handle| {
+            let keep = held.contains(mint) && !handle.is_finished();
+            if !keep {
+                handle.abort();
+                cache.remove(mint);
+            }
+            keep
+        });
+
+        for mint in held {
+            if !watchers.contains_key(&mint) {
+                let handle = tokio::spawn(watch_token(
+                    ws_url.clone(),
+                    mint.clone(),
+                    Arc::clone(&cache),
+                ));
+                watchers.insert(mint, handle);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bonding_curve_price() {
+        let mut data = vec![0u8; 49];
+        data[8..16].copy_from_slice(&1_000_000_000_000_000u64.to_le_bytes());
+        data[16..24].copy_from_slice(&30_000_000_000u64.to_le_bytes());
+        let (token_reserve, sol_reserve, complete) = parse_bonding_curve(&data).unwrap();
+        assert!(!complete);
+
+        let snapshot = PoolSnapshot {
+            token_reserve,
+            sol_reserve,
+            token_decimals: PUMP_TOKEN_DECIMALS,
+            updated_at: 0,
+        };
+        // 30 SOL against 1B tokens
+        assert!((snapshot.price_sol() - 0.00000003).abs() < 1e-15);
+        assert!(parse_bonding_curve(&data[..40]).is_err());
+    }
+}
@@ -0,0 +1,121 @@
+//! Storage of the signals (`trades`), positions (`active_trades`),
+//! `strategies` and buy cooldowns (`trade_cooldowns`), in MongoDB or in an
+//! SQLite file for single-host setups. Everything else stays in MongoDB.
+
+pub mod mongo;
+pub mod sqlite;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use bson::oid::ObjectId;
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::config::{DbBackend, DbConfig};
+use crate::tg_copy::active_trade::{ActiveTrade, RealizedPnl, TpLegFill};
+use crate::tg_copy::cooldown::TradeCooldown;
+use crate::tg_copy::db::TradeDocument;
+use crate::tg_copy::strategy::Strategy;
+
+/// A signal message was stored before
+#[derive(Debug, Error)]
+#[error("trade of message {0} already stored")]
+pub struct DuplicateTrade(pub i64);
+
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Create the indexes or migrate the schema
+    async fn setup(&self) -> Result<()>;
+
+    /// Fails with [`DuplicateTrade`] when the message was stored before
+    async fn insert_trade(&self, trade: &TradeDocument) -> Result<()>;
+    /// Put the PnL of our sell on the close record of `message_id`
+    async fn set_our_pnl(&self, message_id: i64, pnl: &RealizedPnl) -> Result<()>;
+    async fn last_message_id(&self) -> Result<Option<i64>>;
+    /// Newest first
+    async fn recent_trades(&self, strategy: Option<&str>, limit: i64)
+        -> Result<Vec<TradeDocument>>;
+    /// Strategy names of the trades without a `strategy_ref`
+    async fn untagged_strategy_names(&self) -> Result<Vec<String>>;
+    /// Set `strategy_ref` on the untagged trades of `strategy`, returns how
+    /// many were tagged
+    async fn tag_trades(&self, strategy: &str, strategy_ref: ObjectId) -> Result<u64>;
+
+    /// Returns the id of the new position
+    async fn insert_active_trade(&self, trade: &ActiveTrade) -> Result<ObjectId>;
+    /// Overwrite the stored position with id `trade.id`
+    async fn update_active_trade(&self, trade: &ActiveTrade) -> Result<()>;
+    async fn open_trades(&self) -> Result<Vec<ActiveTrade>>;
+    async fn open_trade(
+        &self,
+        token_address: &str,
+        strategy_id: &str,
+    ) -> Result<Option<ActiveTrade>>;
+    async fn remove_open_trade(&self, token_address: &str, strategy_id: &str) -> Result<()>;
+    /// Trades fully closed at or after `since` (unix seconds)
+    async fn closed_trades_since(&self, since: i64) -> Result<Vec<ActiveTrade>>;
+    /// Most recently closed position of `strategy_id` in `token_address`
+    async fn last_closed_trade(
+        &self,
+        token_address: &str,
+        strategy_id: &str,
+    ) -> Result<Option<ActiveTrade>>;
+    /// Book a sell against the open trade, closing it once nothing is left
+    async fn record_sell(
+        &self,
+        token_address: &str,
+        strategy_id: &str,
+        new_holdings: u64,
+        sol_received_lamports: u64,
+    ) -> Result<()>;
+    async fn update_holdings(
+        &self,
+        token_address: &str,
+        strategy_id: &str,
+        new_holdings: u64,
+    ) -> Result<()>;
+    /// Raise the highest price of the open trade, lower prices are ignored
+    async fn raise_highest_price(
+        &self,
+        token_address: &str,
+        strategy_id: &str,
+        price: f64,
+    ) -> Result<()>;
+    /// Register the stop of the open trade
+    async fn set_stop_price(
+        &self,
+        token_address: &str,
+        strategy_id: &str,
+        stop_price: f64,
+    ) -> Result<()>;
+    /// Append take profit legs to the trade with id `trade_id`, also once
+    /// their sell closed it
+    async fn record_tp_legs(&self, trade_id: ObjectId, legs: &[TpLegFill]) -> Result<()>;
+
+    async fn load_strategies(&self) -> Result<Vec<Strategy>>;
+    async fn insert_strategy(&self, strategy: &Strategy) -> Result<()>;
+
+    /// Cooldown with id `id`, expired ones may still be returned
+    async fn cooldown(&self, id: &str) -> Result<Option<TradeCooldown>>;
+    /// Insert or replace the cooldown with id `cooldown.id`, removed by the
+    /// storage some time after it expired
+    async fn save_cooldown(&self, cooldown: &TradeCooldown) -> Result<()>;
+    async fn remove_cooldown(&self, id: &str) -> Result<()>;
+}
+
+/// The storage selected by `DB_BACKEND`, set up and ready to use. `db`
+/// keeps the MongoDB collections of the MongoDB backend.
+pub async fn open_storage(cfg: &DbConfig, db: &mongodb::Database) -> Result<Arc<dyn Storage>> {
+    let storage: Arc<dyn Storage> = match cfg.backend {
+        DbBackend::Mongo => Arc::new(mongo::MongoStorage::new(db)),
+        DbBackend::Sqlite => {
+            tracing::info!(
+                "Storing trades, positions, strategies and cooldowns in {}",
+                cfg.sqlite_path
+            );
+            Arc::new(sqlite::SqliteStorage::open(&cfg.sqlite_path)?)
+        }
+    };
+    storage.setup().await?;
+    Ok(storage)
+}
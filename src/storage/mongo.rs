@@ -0,0 +1,425 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use bson::{doc, oid::ObjectId};
+use mongodb::{
+    error::{ErrorKind, WriteFailure},
+    options::{FindOneOptions, FindOptions, IndexOptions, UpdateOptions},
+    Collection, Database, IndexModel,
+};
+
+use super::{DuplicateTrade, Storage};
+use crate::tg_copy::active_trade::{ActiveTrade, RealizedPnl, TpLegFill};
+use crate::tg_copy::cooldown::TradeCooldown;
+use crate::tg_copy::db::TradeDocument;
+use crate::tg_copy::strategy::Strategy;
+
+/// Index expiring cooldowns after a fixed 30 s, before their length was set
+const FIXED_TTL_INDEX: &str = "last_trade_at_1";
+
+pub struct MongoStorage {
+    trades: Collection<TradeDocument>,
+    active_trades: Collection<ActiveTrade>,
+    strategies: Collection<Strategy>,
+    cooldowns: Collection<TradeCooldown>,
+}
+
+impl MongoStorage {
+    pub fn new(db: &Database) -> Self {
+        Self {
+            trades: db.collection("trades"),
+            active_trades: db.collection("active_trades"),
+            strategies: db.collection("strategies"),
+            cooldowns: db.collection("trade_cooldowns"),
+        }
+    }
+}
+
+const DUPLICATE_KEY: i32 = 11000;
+
+/// Whether `e` is a unique index rejecting a document stored before. For an
+/// unordered `insert_many` every rejected document must be one, the others
+/// were inserted.
+pub(crate) fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    match &*e.kind {
+        ErrorKind::Write(WriteFailure::WriteError(write_error)) => {
+            write_error.code == DUPLICATE_KEY
+        }
+        ErrorKind::BulkWrite(failure) => {
+            failure.write_concern_error.is_none()
+                && failure.write_errors.as_ref().is_some_and(|errors| {
+                    !errors.is_empty() && errors.iter().all(|e| e.code == DUPLICATE_KEY)
+                })
+        }
+        _ => false,
+    }
+}
+
+fn open_filter(token_address: &str, strategy_id: &str) -> bson::Document {
+    doc! {
+        "token_address": token_address,
+        "strategy_id": strategy_id,
+        "closed_at": null
+    }
+}
+
+#[async_trait]
+impl Storage for MongoStorage {
+    async fn setup(&self) -> Result<()> {
+        self.trades
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "message_id": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+                None,
+            )
+            .await?;
+        self.trades
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "strategy": 1, "token": 1 })
+                    .build(),
+                None,
+            )
+            .await?;
+        self.active_trades
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! {
+                        "token_address": 1,
+                        "strategy_id": 1
+                    })
+                    .build(),
+                None,
+            )
+            .await?;
+        self.active_trades
+            .create_index(
+                IndexModel::builder().keys(doc! { "closed_at": 1 }).build(),
+                None,
+            )
+            .await?;
+        // It would drop cooldowns longer than 30 s, a missing one is fine
+        let _ = self.cooldowns.drop_index(FIXED_TTL_INDEX, None).await;
+        // The TTL monitor sweeps once a minute
+        self.cooldowns
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "expires_at": 1 })
+                    .options(
+                        IndexOptions::builder()
+                            .expire_after(std::time::Duration::ZERO)
+                            .build(),
+                    )
+                    .build(),
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn insert_trade(&self, trade: &TradeDocument) -> Result<()> {
+        match self.trades.insert_one(trade, None).await {
+            Ok(_) => Ok(()),
+            Err(e) if is_duplicate_key(&e) => Err(DuplicateTrade(trade.message_id).into()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn set_our_pnl(&self, message_id: i64, pnl: &RealizedPnl) -> Result<()> {
+        self.trades
+            .update_one(
+                doc! { "message_id": message_id },
+                doc! { "$set": {
+                    "our_profit_pct": pnl.pct,
+                    "our_pnl_lamports": pnl.lamports,
+                } },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn last_message_id(&self) -> Result<Option<i64>> {
+        let options = FindOneOptions::builder()
+            .sort(doc! { "message_id": -1 })
+            .build();
+        let doc = self.trades.find_one(None, Some(options)).await?;
+        Ok(doc.map(|d| d.message_id))
+    }
+
+    async fn recent_trades(
+        &self,
+        strategy: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<TradeDocument>> {
+        let filter = match strategy {
+            Some(strategy) => doc! { "strategy": strategy },
+            None => doc! {},
+        };
+        let options = FindOptions::builder()
+            .sort(doc! { "message_id": -1 })
+            .limit(limit)
+            .build();
+
+        let mut cursor = self.trades.find(filter, options).await?;
+        let mut trades = Vec::new();
+        while cursor.advance().await? {
+            trades.push(cursor.deserialize_current()?);
+        }
+        Ok(trades)
+    }
+
+    async fn untagged_strategy_names(&self) -> Result<Vec<String>> {
+        let names = self
+            .trades
+            .distinct(
+                "strategy",
+                doc! { "strategy_ref": { "$exists": false } },
+                None,
+            )
+            .await?;
+        Ok(names
+            .iter()
+            .filter_map(|n| n.as_str().map(str::to_string))
+            .collect())
+    }
+
+    async fn tag_trades(&self, strategy: &str, strategy_ref: ObjectId) -> Result<u64> {
+        let result = self
+            .trades
+            .update_many(
+                doc! { "strategy": strategy, "strategy_ref": { "$exists": false } },
+                doc! { "$set": { "strategy_ref": strategy_ref } },
+                None,
+            )
+            .await?;
+        Ok(result.modified_count)
+    }
+
+    async fn insert_active_trade(&self, trade: &ActiveTrade) -> Result<ObjectId> {
+        let result = self.active_trades.insert_one(trade, None).await?;
+        Ok(result.inserted_id.as_object_id().unwrap())
+    }
+
+    async fn update_active_trade(&self, trade: &ActiveTrade) -> Result<()> {
+        self.active_trades
+            .update_one(
+                doc! { "_id": trade.id },
+                doc! { "$set": bson::to_document(trade)? },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn open_trades(&self) -> Result<Vec<ActiveTrade>> {
+        let mut trades = Vec::new();
+        let mut cursor = self
+            .active_trades
+            .find(doc! { "closed_at": null }, None)
+            .await?;
+
+        while cursor.advance().await? {
+            trades.push(cursor.deserialize_current()?);
+        }
+
+        Ok(trades)
+    }
+
+    async fn open_trade(
+        &self,
+        token_address: &str,
+        strategy_id: &str,
+    ) -> Result<Option<ActiveTrade>> {
+        self.active_trades
+            .find_one(open_filter(token_address, strategy_id), None)
+            .await
+            .map_err(Into::into)
+    }
+
+    async fn remove_open_trade(&self, token_address: &str, strategy_id: &str) -> Result<()> {
+        self.active_trades
+            .delete_one(open_filter(token_address, strategy_id), None)
+            .await?;
+        Ok(())
+    }
+
+    async fn closed_trades_since(&self, since: i64) -> Result<Vec<ActiveTrade>> {
+        let mut trades = Vec::new();
+        let mut cursor = self
+            .active_trades
+            .find(doc! { "closed_at": { "$gte": since } }, None)
+            .await?;
+
+        while cursor.advance().await? {
+            trades.push(cursor.deserialize_current()?);
+        }
+
+        Ok(trades)
+    }
+
+    async fn last_closed_trade(
+        &self,
+        token_address: &str,
+        strategy_id: &str,
+    ) -> Result<Option<ActiveTrade>> {
+        self.active_trades
+            .find_one(
+                doc! {
+                    "token_address": token_address,
+                    "strategy_id": strategy_id,
+                    "closed_at": { "$ne": null },
+                },
+                FindOneOptions::builder()
+                    .sort(doc! { "closed_at": -1 })
+                    .build(),
+            )
+            .await
+            .map_err(Into::into)
+    }
+
+    async fn record_sell(
+        &self,
+        token_address: &str,
+        strategy_id: &str,
+        new_holdings: u64,
+        sol_received_lamports: u64,
+    ) -> Result<()> {
+        let now = chrono::Utc::now().timestamp();
+        let mut set = doc! {
+            "remaining_holdings": new_holdings as i64,
+            "updated_at": now
+        };
+        if new_holdings == 0 {
+            set.insert("closed_at", now);
+        }
+        self.active_trades
+            .update_one(
+                open_filter(token_address, strategy_id),
+                doc! {
+                    "$set": set,
+                    "$inc": { "sol_received_lamports": sol_received_lamports as i64 }
+                },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn update_holdings(
+        &self,
+        token_address: &str,
+        strategy_id: &str,
+        new_holdings: u64,
+    ) -> Result<()> {
+        self.active_trades
+            .update_one(
+                open_filter(token_address, strategy_id),
+                doc! {
+                    "$set": {
+                        "remaining_holdings": new_holdings as i64,
+                        "updated_at": chrono::Utc::now().timestamp()
+                    }
+                },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn raise_highest_price(
+        &self,
+        token_address: &str,
+        strategy_id: &str,
+        price: f64,
+    ) -> Result<()> {
+        self.active_trades
+            .update_one(
+                open_filter(token_address, strategy_id),
+                doc! {
+                    "$max": { "highest_price": price },
+                    "$set": { "updated_at": chrono::Utc::now().timestamp() }
+                },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn set_stop_price(
+        &self,
+        token_address: &str,
+        strategy_id: &str,
+        stop_price: f64,
+    ) -> Result<()> {
+        self.active_trades
+            .update_one(
+                open_filter(token_address, strategy_id),
+                doc! {
+                    "$set": {
+                        "stop_price": stop_price,
+                        "updated_at": chrono::Utc::now().timestamp(),
+                    }
+                },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn record_tp_legs(&self, trade_id: ObjectId, legs: &[TpLegFill]) -> Result<()> {
+        self.active_trades
+            .update_one(
+                doc! { "_id": trade_id },
+                doc! {
+                    "$push": { "tp_legs_filled": { "$each": bson::to_bson(legs)? } },
+                    "$set": { "updated_at": chrono::Utc::now().timestamp() }
+                },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn load_strategies(&self) -> Result<Vec<Strategy>> {
+        let mut cursor = self.strategies.find(None, None).await?;
+        let mut strategies = Vec::new();
+
+        while cursor.advance().await? {
+            strategies.push(cursor.deserialize_current()?);
+        }
+        Ok(strategies)
+    }
+
+    async fn insert_strategy(&self, strategy: &Strategy) -> Result<()> {
+        self.strategies.insert_one(strategy, None).await?;
+        Ok(())
+    }
+
+    async fn cooldown(&self, id: &str) -> Result<Option<TradeCooldown>> {
+        Ok(self.cooldowns.find_one(doc! { "_id": id }, None).await?)
+    }
+
+    async fn save_cooldown(&self, cooldown: &TradeCooldown) -> Result<()> {
+        self.cooldowns
+            .update_one(
+                doc! { "_id": &cooldown.id },
+                doc! { "$set": {
+                    "contract_address": &cooldown.contract_address,
+                    "strategy": &cooldown.strategy,
+                    "last_trade_at": bson::DateTime::from_chrono(cooldown.last_trade_at),
+                    "cooldown_secs": cooldown.cooldown_secs,
+                    "expires_at": bson::DateTime::from_chrono(cooldown.expires_at()),
+                } },
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_cooldown(&self, id: &str) -> Result<()> {
+        self.cooldowns.delete_one(doc! { "_id": id }, None).await?;
+        Ok(())
+    }
+}
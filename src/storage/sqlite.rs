@@ -0,0 +1,725 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use bson::oid::ObjectId;
+use chrono::{TimeZone, Utc};
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension, Transaction};
+use serde::de::DeserializeOwned;
+use std::sync::{Arc, Mutex};
+
+use super::{DuplicateTrade, Storage};
+use crate::tg_copy::active_trade::{ActiveTrade, RealizedPnl, TpLegFill};
+use crate::tg_copy::cooldown::TradeCooldown;
+use crate::tg_copy::db::TradeDocument;
+use crate::tg_copy::strategy::Strategy;
+
+/// Schema changes in order, the schema version is kept in `user_version`.
+/// Documents are stored as JSON next to the columns they are queried by.
+const MIGRATIONS: &[&str] = &[
+    "
+    CREATE TABLE trades (
+        message_id INTEGER PRIMARY KEY,
+        strategy TEXT NOT NULL,
+        token TEXT NOT NULL,
+        strategy_ref TEXT,
+        doc TEXT NOT NULL
+    );
+    CREATE INDEX trades_strategy_token ON trades (strategy, token);
+
+    CREATE TABLE active_trades (
+        id TEXT PRIMARY KEY,
+        token_address TEXT NOT NULL,
+        strategy_id TEXT NOT NULL,
+        closed_at INTEGER,
+        doc TEXT NOT NULL
+    );
+    CREATE INDEX active_trades_position ON active_trades (token_address, strategy_id);
+    CREATE INDEX active_trades_closed_at ON active_trades (closed_at);
+
+    CREATE TABLE strategies (
+        id TEXT PRIMARY KEY,
+        strategy_id TEXT NOT NULL,
+        doc TEXT NOT NULL
+    );
+",
+    "
+    CREATE TABLE trade_cooldowns (
+        id TEXT PRIMARY KEY,
+        contract_address TEXT NOT NULL,
+        strategy TEXT NOT NULL,
+        last_trade_at INTEGER NOT NULL,
+        cooldown_secs INTEGER NOT NULL,
+        expires_at INTEGER NOT NULL
+    );
+",
+];
+
+/// Single file storage. Queries run on the blocking pool, one at a time.
+pub struct SqliteStorage {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStorage {
+    /// Open or create the database at `path`, `:memory:` for a throwaway one
+    pub fn open(path: &str) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    async fn with_conn<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    {
+        let conn = Arc::clone(&self.conn);
+        tokio::task::spawn_blocking(move || f(&mut conn.lock().unwrap())).await?
+    }
+}
+
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version as usize > MIGRATIONS.len() {
+        return Err(anyhow!(
+            "Database schema version {} is newer than this build knows ({})",
+            version,
+            MIGRATIONS.len()
+        ));
+    }
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        tracing::info!("Migrating the SQLite schema to version {}", i + 1);
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", (i + 1) as i64)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+fn from_json<T: DeserializeOwned>(doc: String) -> Result<T> {
+    Ok(serde_json::from_str(&doc)?)
+}
+
+fn query_docs<T: DeserializeOwned>(
+    conn: &Connection,
+    sql: &str,
+    params: impl rusqlite::Params,
+) -> Result<Vec<T>> {
+    let mut statement = conn.prepare(sql)?;
+    let docs = statement
+        .query_map(params, |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    docs.into_iter().map(from_json).collect()
+}
+
+fn write_active_trade(tx: &Transaction, trade: &ActiveTrade) -> Result<()> {
+    let id = trade
+        .id
+        .ok_or_else(|| anyhow!("Active trade of {} has no id", trade.token_address))?;
+    tx.execute(
+        "UPDATE active_trades SET closed_at = ?2, doc = ?3 WHERE id = ?1",
+        params![id.to_hex(), trade.closed_at, serde_json::to_string(trade)?],
+    )?;
+    Ok(())
+}
+
+/// Apply `update` to the open trade of the position, if there is one
+fn update_open_trade(
+    conn: &mut Connection,
+    token_address: &str,
+    strategy_id: &str,
+    update: impl FnOnce(&mut ActiveTrade),
+) -> Result<()> {
+    let tx = conn.transaction()?;
+    let doc: Option<String> = tx
+        .query_row(
+            "SELECT doc FROM active_trades
+             WHERE token_address = ?1 AND strategy_id = ?2 AND closed_at IS NULL",
+            params![token_address, strategy_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(doc) = doc {
+        let mut trade: ActiveTrade = from_json(doc)?;
+        update(&mut trade);
+        trade.updated_at = chrono::Utc::now().timestamp();
+        write_active_trade(&tx, &trade)?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    async fn setup(&self) -> Result<()> {
+        self.with_conn(migrate).await
+    }
+
+    async fn insert_trade(&self, trade: &TradeDocument) -> Result<()> {
+        let message_id = trade.message_id;
+        let strategy = trade.strategy.clone();
+        let token = trade.token.clone();
+        let strategy_ref = trade.strategy_ref.map(|id| id.to_hex());
+        let doc = serde_json::to_string(trade)?;
+        self.with_conn(move |conn| {
+            match conn.execute(
+                "INSERT INTO trades (message_id, strategy, token, strategy_ref, doc)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![message_id, strategy, token, strategy_ref, doc],
+            ) {
+                Ok(_) => Ok(()),
+                Err(rusqlite::Error::SqliteFailure(e, _))
+                    if e.code == ErrorCode::ConstraintViolation =>
+                {
+                    Err(DuplicateTrade(message_id).into())
+                }
+                Err(e) => Err(e.into()),
+            }
+        })
+        .await
+    }
+
+    async fn set_our_pnl(&self, message_id: i64, pnl: &RealizedPnl) -> Result<()> {
+        let pnl = *pnl;
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let doc: Option<String> = tx
+                .query_row(
+                    "SELECT doc FROM trades WHERE message_id = ?1",
+                    [message_id],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if let Some(doc) = doc {
+                let mut trade: TradeDocument = from_json(doc)?;
+                trade.our_profit_pct = pnl.pct;
+                trade.our_pnl_lamports = Some(pnl.lamports);
+                tx.execute(
+                    "UPDATE trades SET doc = ?2 WHERE message_id = ?1",
+                    params![message_id, serde_json::to_string(&trade)?],
+                )?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn last_message_id(&self) -> Result<Option<i64>> {
+        self.with_conn(|conn| {
+            Ok(conn.query_row("SELECT MAX(message_id) FROM trades", [], |row| row.get(0))?)
+        })
+        .await
+    }
+
+    async fn recent_trades(
+        &self,
+        strategy: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<TradeDocument>> {
+        let strategy = strategy.map(str::to_string);
+        self.with_conn(move |conn| match strategy {
+            Some(strategy) => query_docs(
+                conn,
+                "SELECT doc FROM trades WHERE strategy = ?1 ORDER BY message_id DESC LIMIT ?2",
+                params![strategy, limit],
+            ),
+            None => query_docs(
+                conn,
+                "SELECT doc FROM trades ORDER BY message_id DESC LIMIT ?1",
+                [limit],
+            ),
+        })
+        .await
+    }
+
+    async fn untagged_strategy_names(&self) -> Result<Vec<String>> {
+        self.with_conn(|conn| {
+            let mut statement =
+                conn.prepare("SELECT DISTINCT strategy FROM trades WHERE strategy_ref IS NULL")?;
+            let names = statement
+                .query_map([], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+            Ok(names)
+        })
+        .await
+    }
+
+    async fn tag_trades(&self, strategy: &str, strategy_ref: ObjectId) -> Result<u64> {
+        let strategy = strategy.to_string();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let untagged = query_docs::<TradeDocument>(
+                &tx,
+                "SELECT doc FROM trades WHERE strategy = ?1 AND strategy_ref IS NULL",
+                [&strategy],
+            )?;
+            let tagged = untagged.len() as u64;
+            for mut trade in untagged {
+                trade.strategy_ref = Some(strategy_ref);
+                tx.execute(
+                    "UPDATE trades SET strategy_ref = ?2, doc = ?3 WHERE message_id = ?1",
+                    params![
+                        trade.message_id,
+                        strategy_ref.to_hex(),
+                        serde_json::to_string(&trade)?
+                    ],
+                )?;
+            }
+            tx.commit()?;
+            Ok(tagged)
+        })
+        .await
+    }
+
+    async fn insert_active_trade(&self, trade: &ActiveTrade) -> Result<ObjectId> {
+        let id = ObjectId::new();
+        let mut trade = trade.clone();
+        trade.id = Some(id);
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO active_trades (id, token_address, strategy_id, closed_at, doc)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    id.to_hex(),
+                    trade.token_address,
+                    trade.strategy_id,
+                    trade.closed_at,
+                    serde_json::to_string(&trade)?
+                ],
+            )?;
+            Ok(id)
+        })
+        .await
+    }
+
+    async fn update_active_trade(&self, trade: &ActiveTrade) -> Result<()> {
+        let trade = trade.clone();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            write_active_trade(&tx, &trade)?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn open_trades(&self) -> Result<Vec<ActiveTrade>> {
+        self.with_conn(|conn| {
+            query_docs(
+                conn,
+                "SELECT doc FROM active_trades WHERE closed_at IS NULL",
+                [],
+            )
+        })
+        .await
+    }
+
+    async fn open_trade(
+        &self,
+        token_address: &str,
+        strategy_id: &str,
+    ) -> Result<Option<ActiveTrade>> {
+        let (token_address, strategy_id) = (token_address.to_string(), strategy_id.to_string());
+        self.with_conn(move |conn| {
+            Ok(query_docs(
+                conn,
+                "SELECT doc FROM active_trades
+                 WHERE token_address = ?1 AND strategy_id = ?2 AND closed_at IS NULL
+                 LIMIT 1",
+                params![token_address, strategy_id],
+            )?
+            .pop())
+        })
+        .await
+    }
+
+    async fn remove_open_trade(&self, token_address: &str, strategy_id: &str) -> Result<()> {
+        let (token_address, strategy_id) = (token_address.to_string(), strategy_id.to_string());
+        self.with_conn(move |conn| {
+            conn.execute(
+                "DELETE FROM active_trades WHERE id = (
+                     SELECT id FROM active_trades
+                     WHERE token_address = ?1 AND strategy_id = ?2 AND closed_at IS NULL
+                     LIMIT 1
+                 )",
+                params![token_address, strategy_id],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn closed_trades_since(&self, since: i64) -> Result<Vec<ActiveTrade>> {
+        self.with_conn(move |conn| {
+            query_docs(
+                conn,
+                "SELECT doc FROM active_trades WHERE closed_at >= ?1",
+                [since],
+            )
+        })
+        .await
+    }
+
+    async fn last_closed_trade(
+        &self,
+        token_address: &str,
+        strategy_id: &str,
+    ) -> Result<Option<ActiveTrade>> {
+        let (token_address, strategy_id) = (token_address.to_string(), strategy_id.to_string());
+        self.with_conn(move |conn| {
+            Ok(query_docs(
+                conn,
+                "SELECT doc FROM active_trades
+                 WHERE token_address = ?1 AND strategy_id = ?2 AND closed_at IS NOT NULL
+                 ORDER BY closed_at DESC LIMIT 1",
+                params![token_address, strategy_id],
+            )?
+            .pop())
+        })
+        .await
+    }
+
+    async fn record_sell(
+        &self,
+        token_address: &str,
+        strategy_id: &str,
+        new_holdings: u64,
+        sol_received_lamports: u64,
+    ) -> Result<()> {
+        let (token_address, strategy_id) = (token_address.to_string(), strategy_id.to_string());
+        self.with_conn(move |conn| {
+            update_open_trade(conn, &token_address, &strategy_id, |trade| {
+                trade.remaining_holdings = new_holdings;
+                trade.sol_received_lamports += sol_received_lamports;
+                if new_holdings == 0 {
+                    trade.closed_at = Some(chrono::Utc::now().timestamp());
+                }
+            })
+        })
+        .await
+    }
+
+    async fn update_holdings(
+        &self,
+        token_address: &str,
+        strategy_id: &str,
+        new_holdings: u64,
+    ) -> Result<()> {
+        let (token_address, strategy_id) = (token_address.to_string(), strategy_id.to_string());
+        self.with_conn(move |conn| {
+            update_open_trade(conn, &token_address, &strategy_id, |trade| {
+                trade.remaining_holdings = new_holdings;
+            })
+        })
+        .await
+    }
+
+    async fn raise_highest_price(
+        &self,
+        token_address: &str,
+        strategy_id: &str,
+        price: f64,
+    ) -> Result<()> {
+        let (token_address, strategy_id) = (token_address.to_string(), strategy_id.to_string());
+        self.with_conn(move |conn| {
+            update_open_trade(conn, &token_address, &strategy_id, |trade| {
+                trade.highest_price = trade.highest_price.max(price);
+            })
+        })
+        .await
+    }
+
+    async fn set_stop_price(
+        &self,
+        token_address: &str,
+        strategy_id: &str,
+        stop_price: f64,
+    ) -> Result<()> {
+        let (token_address, strategy_id) = (token_address.to_string(), strategy_id.to_string());
+        self.with_conn(move |conn| {
+            update_open_trade(conn, &token_address, &strategy_id, |trade| {
+                trade.stop_price = Some(stop_price);
+            })
+        })
+        .await
+    }
+
+    async fn record_tp_legs(&self, trade_id: ObjectId, legs: &[TpLegFill]) -> Result<()> {
+        let legs = legs.to_vec();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let doc: Option<String> = tx
+                .query_row(
+                    "SELECT doc FROM active_trades WHERE id = ?1",
+                    params![trade_id.to_hex()],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if let Some(doc) = doc {
+                let mut trade: ActiveTrade = from_json(doc)?;
+                trade.tp_legs_filled.extend(legs);
+                trade.updated_at = chrono::Utc::now().timestamp();
+                write_active_trade(&tx, &trade)?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn load_strategies(&self) -> Result<Vec<Strategy>> {
+        self.with_conn(|conn| query_docs(conn, "SELECT doc FROM strategies", []))
+            .await
+    }
+
+    async fn insert_strategy(&self, strategy: &Strategy) -> Result<()> {
+        let id = strategy.id.to_hex();
+        let strategy_id = strategy.strategy_id.clone();
+        let doc = serde_json::to_string(strategy)?;
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO strategies (id, strategy_id, doc) VALUES (?1, ?2, ?3)",
+                params![id, strategy_id, doc],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn cooldown(&self, id: &str) -> Result<Option<TradeCooldown>> {
+        let id = id.to_string();
+        self.with_conn(move |conn| {
+            let row = conn
+                .query_row(
+                    "SELECT contract_address, strategy, last_trade_at, cooldown_secs
+                     FROM trade_cooldowns WHERE id = ?1",
+                    params![id],
+                    |row| {
+                        Ok((
+                            row.get::<_, String>(0)?,
+                            row.get::<_, String>(1)?,
+                            row.get::<_, i64>(2)?,
+                            row.get::<_, i64>(3)?,
+                        ))
+                    },
+                )
+                .optional()?;
+            row.map(
+                |(contract_address, strategy, last_trade_at, cooldown_secs)| {
+                    let last_trade_at = Utc
+                        .timestamp_millis_opt(last_trade_at)
+                        .single()
+                        .ok_or_else(|| anyhow!("Cooldown {} has an invalid last trade", id))?;
+                    Ok(TradeCooldown {
+                        id: id.clone(),
+                        contract_address,
+                        strategy,
+                        last_trade_at,
+                        cooldown_secs,
+                    })
+                },
+            )
+            .transpose()
+        })
+        .await
+    }
+
+    async fn save_cooldown(&self, cooldown: &TradeCooldown) -> Result<()> {
+        let id = cooldown.id.clone();
+        let contract_address = cooldown.contract_address.clone();
+        let strategy = cooldown.strategy.clone();
+        let last_trade_at = cooldown.last_trade_at.timestamp_millis();
+        let cooldown_secs = cooldown.cooldown_secs;
+        let expires_at = cooldown.expires_at().timestamp_millis();
+        self.with_conn(move |conn| {
+            // No TTL index here, expired cooldowns go on the next write
+            conn.execute(
+                "DELETE FROM trade_cooldowns WHERE expires_at < ?1",
+                params![Utc::now().timestamp_millis()],
+            )?;
+            conn.execute(
+                "INSERT OR REPLACE INTO trade_cooldowns
+                 (id, contract_address, strategy, last_trade_at, cooldown_secs, expires_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    id,
+                    contract_address,
+                    strategy,
+                    last_trade_at,
+                    cooldown_secs,
+                    expires_at
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn remove_cooldown(&self, id: &str) -> Result<()> {
+        let id = id.to_string();
+        self.with_conn(move |conn| {
+            conn.execute("DELETE FROM trade_cooldowns WHERE id = ?1", params![id])?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tg_copy::db::TradeType;
+
+    fn trade(message_id: i64, strategy: &str) -> TradeDocument {
+        TradeDocument {
+            message_id,
+            date: Utc::now(),
+            strategy: strategy.to_string(),
+            token: "WIF".to_string(),
+            contract_address: "EKpQGSJtjMFqKZ9KQanSqYXRcF8fBopzLHYxdM65zcjm".to_string(),
+            trade_type: TradeType::Close,
+            original_message: String::new(),
+            op_type: Some("Tp".to_string()),
+            buy_price: None,
+            num_buys: None,
+            total_buys: None,
+            time_window: None,
+            market_cap: None,
+            entry_price: Some(0.001),
+            exit_price: Some(0.002),
+            profit_pct: Some(100.0),
+            strategy_ref: None,
+            our_profit_pct: None,
+            our_pnl_lamports: None,
+        }
+    }
+
+    async fn storage() -> SqliteStorage {
+        let storage = SqliteStorage::open(":memory:").unwrap();
+        storage.setup().await.unwrap();
+        // Migrations already applied are skipped
+        storage.setup().await.unwrap();
+        storage
+    }
+
+    #[tokio::test]
+    async fn test_trades() {
+        let storage = storage().await;
+        assert_eq!(storage.last_message_id().await.unwrap(), None);
+        storage.insert_trade(&trade(7, "degen")).await.unwrap();
+        storage.insert_trade(&trade(9, "safe")).await.unwrap();
+        let duplicate = storage.insert_trade(&trade(7, "degen")).await.unwrap_err();
+        assert!(duplicate.is::<DuplicateTrade>());
+        assert_eq!(storage.last_message_id().await.unwrap(), Some(9));
+
+        let pnl = RealizedPnl {
+            lamports: 5_000,
+            pct: Some(12.5),
+        };
+        storage.set_our_pnl(7, &pnl).await.unwrap();
+        let degen = storage.recent_trades(Some("degen"), 10).await.unwrap();
+        assert_eq!(degen.len(), 1);
+        assert_eq!(degen[0].our_pnl_lamports, Some(5_000));
+
+        let strategy_ref = ObjectId::new();
+        assert_eq!(storage.tag_trades("degen", strategy_ref).await.unwrap(), 1);
+        assert_eq!(
+            storage.untagged_strategy_names().await.unwrap(),
+            vec!["safe".to_string()]
+        );
+        let recent = storage.recent_trades(None, 10).await.unwrap();
+        assert_eq!(recent[0].message_id, 9);
+        assert_eq!(recent[1].strategy_ref, Some(strategy_ref));
+    }
+
+    #[tokio::test]
+    async fn test_active_trade_lifecycle() {
+        let storage = storage().await;
+        let mut trade = ActiveTrade::new(
+            "WIF".to_string(),
+            "mint".to_string(),
+            "degen".to_string(),
+            1_000,
+            0.001,
+        );
+        trade.id = Some(storage.insert_active_trade(&trade).await.unwrap());
+
+        storage
+            .raise_highest_price("mint", "degen", 0.003)
+            .await
+            .unwrap();
+        storage
+            .raise_highest_price("mint", "degen", 0.002)
+            .await
+            .unwrap();
+        storage
+            .set_stop_price("mint", "degen", 0.0007)
+            .await
+            .unwrap();
+        storage
+            .record_sell("mint", "degen", 400, 1_000_000)
+            .await
+            .unwrap();
+        let open = storage.open_trade("mint", "degen").await.unwrap().unwrap();
+        assert_eq!(open.id, trade.id);
+        assert_eq!(open.highest_price, 0.003);
+        assert_eq!(open.stop_price, Some(0.0007));
+        assert_eq!(open.remaining_holdings, 400);
+        assert_eq!(open.sol_received_lamports, 1_000_000);
+        assert!(storage
+            .last_closed_trade("mint", "degen")
+            .await
+            .unwrap()
+            .is_none());
+
+        storage
+            .record_sell("mint", "degen", 0, 500_000)
+            .await
+            .unwrap();
+        assert!(storage.open_trades().await.unwrap().is_empty());
+        let closed = storage.closed_trades_since(0).await.unwrap();
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].sol_received_lamports, 1_500_000);
+        let last = storage.last_closed_trade("mint", "degen").await.unwrap();
+        assert_eq!(last.unwrap().id, trade.id);
+    }
+
+    #[tokio::test]
+    async fn test_strategies_round_trip() {
+        let storage = storage().await;
+        let strategy = Strategy::placeholder("degen_calls");
+        storage.insert_strategy(&strategy).await.unwrap();
+        let strategies = storage.load_strategies().await.unwrap();
+        assert_eq!(strategies.len(), 1);
+        assert_eq!(strategies[0].id, strategy.id);
+        assert!(strategies[0].is_placeholder);
+    }
+
+    #[tokio::test]
+    async fn test_cooldowns_round_trip() {
+        let storage = storage().await;
+        let cooldown = TradeCooldown::new("mint", "degen", 30);
+        storage.save_cooldown(&cooldown).await.unwrap();
+        let saved = storage.cooldown(&cooldown.id).await.unwrap().unwrap();
+        assert_eq!(saved.contract_address, "mint");
+        assert_eq!(saved.strategy, "degen");
+        assert_eq!(saved.cooldown_secs, 30);
+        assert_eq!(
+            saved.last_trade_at.timestamp_millis(),
+            cooldown.last_trade_at.timestamp_millis()
+        );
+
+        // Saving again replaces it
+        storage
+            .save_cooldown(&TradeCooldown::new("mint", "degen", 60))
+            .await
+            .unwrap();
+        let saved = storage.cooldown(&cooldown.id).await.unwrap().unwrap();
+        assert_eq!(saved.cooldown_secs, 60);
+
+        storage.remove_cooldown(&cooldown.id).await.unwrap();
+        assert!(storage.cooldown(&cooldown.id).await.unwrap().is_none());
+    }
+}
@@ -1,13 +1,34 @@
-use std::ops::Mul;
-
 use anyhow::Result;
-use bson::{doc, oid::ObjectId};
-use mongodb::Collection;
-use mongodb::IndexModel;
+use bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 use super::parse_trade::OperationType;
-use super::strategy::Strategy;
+use super::strategy::{Strategy, TakeProfitCondition};
+use crate::storage::Storage;
+
+/// Our result of a sell, measured against our own cost basis rather than
+/// the prices quoted by the provider
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct RealizedPnl {
+    pub lamports: i64,
+    /// `None` when the cost basis is unknown
+    pub pct: Option<f64>,
+}
+
+/// A leg of the take profit ladder executed against a position
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TpLegFill {
+    /// `pnlPercentage` of the leg
+    pub pnl_percentage: i32,
+    pub token_amount: u64,
+    /// Share of the sell proceeds of the leg, when legs filled together
+    pub sol_received_lamports: u64,
+    /// `None` when the position was already below the leg's target
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx_sig: Option<String>,
+    pub filled_at: i64,
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ActiveTrade {
@@ -16,12 +37,34 @@ pub struct ActiveTrade {
     pub token_name: String,
     pub token_address: String,
     pub strategy_id: String,
+    /// Chat the signal came from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
     pub initial_holdings: u64,
     pub remaining_holdings: u64,
     pub entry_price: f64,
     pub highest_price: f64,
+    /// Stop registered on entry from the strategy's stop loss, the price
+    /// monitor sells the position below it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_price: Option<f64>,
     pub created_at: i64,
     pub updated_at: i64,
+    /// SOL paid for the position including fees, tips and rent
+    #[serde(default)]
+    pub sol_spent_lamports: u64,
+    /// SOL received from all sells so far, net of fees
+    #[serde(default)]
+    pub sol_received_lamports: u64,
+    /// Token-2022 transfer fee charged on the mint when bought
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transfer_fee_bps: Option<u16>,
+    /// Set once the whole position is sold, closed trades are kept for PnL
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub closed_at: Option<i64>,
+    /// Take profit legs executed so far, each leg fires once
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tp_legs_filled: Vec<TpLegFill>,
 }
 
 impl ActiveTrade {
@@ -38,15 +81,118 @@ impl ActiveTrade {
             token_name,
             token_address,
             strategy_id,
+            provider: None,
             initial_holdings,
             remaining_holdings: initial_holdings,
             entry_price,
             highest_price: entry_price,
+            stop_price: None,
             created_at: now,
             updated_at: now,
+            sol_spent_lamports: 0,
+            sol_received_lamports: 0,
+            transfer_fee_bps: None,
+            closed_at: None,
+            tp_legs_filled: Vec::new(),
+        }
+    }
+
+    pub fn realized_pnl_lamports(&self) -> i64 {
+        self.sol_received_lamports as i64 - self.sol_spent_lamports as i64
+    }
+
+    /// Price the position was left at on average, the entry price scaled
+    /// by what its sells returned on the SOL spent. `None` while it is open
+    /// or its cost is unknown.
+    pub fn exit_price(&self) -> Option<f64> {
+        self.closed_at?;
+        (self.sol_spent_lamports > 0).then(|| {
+            self.entry_price * self.sol_received_lamports as f64 / self.sol_spent_lamports as f64
+        })
+    }
+
+    /// PnL of the position once the rest of it sold for
+    /// `sol_received_lamports`
+    pub fn closed_pnl(&self, sol_received_lamports: u64) -> RealizedPnl {
+        let lamports = self.realized_pnl_lamports() + sol_received_lamports as i64;
+        RealizedPnl {
+            lamports,
+            pct: (self.sol_spent_lamports > 0)
+                .then(|| lamports as f64 * 100.0 / self.sol_spent_lamports as f64),
+        }
+    }
+
+    /// PnL of selling `token_amount` for `sol_received_lamports`, against the
+    /// share of the cost basis those tokens carry
+    pub fn sell_pnl(&self, token_amount: u64, sol_received_lamports: u64) -> RealizedPnl {
+        let cost = if self.initial_holdings == 0 {
+            0
+        } else {
+            (self.sol_spent_lamports as u128 * token_amount as u128 / self.initial_holdings as u128)
+                as u64
+        };
+        let lamports = sol_received_lamports as i64 - cost as i64;
+        RealizedPnl {
+            lamports,
+            pct: (cost > 0).then(|| lamports as f64 * 100.0 / cost as f64),
+        }
+    }
+
+    /// Cost basis of what is left of the position
+    pub fn remaining_cost_lamports(&self) -> u64 {
+        if self.initial_holdings == 0 {
+            return 0;
+        }
+        (self.sol_spent_lamports as u128 * self.remaining_holdings as u128
+            / self.initial_holdings as u128) as u64
+    }
+
+    /// Tokens making up `fraction` of what is left of the position, all of
+    /// it from 1 on
+    pub fn share_of_holdings(&self, fraction: f64) -> u64 {
+        if fraction >= 1.0 {
+            return self.remaining_holdings;
         }
+        (self.remaining_holdings as f64 * fraction.max(0.0)).round() as u64
     }
 
+    pub fn tp_leg_filled(&self, pnl_percentage: i32) -> bool {
+        self.tp_legs_filled
+            .iter()
+            .any(|leg| leg.pnl_percentage == pnl_percentage)
+    }
+
+    /// Legs of the take profit ladder reached at `profit_percentage` and not
+    /// filled yet, lowest first, with the tokens each sells to bring the
+    /// position down to its `targetOpenPercentage` of the initial holdings.
+    /// A leg whose target the position is already below sells nothing.
+    pub fn due_tp_legs(
+        &self,
+        conditions: &[TakeProfitCondition],
+        profit_percentage: f64,
+    ) -> Vec<(i32, u64)> {
+        let mut due: Vec<&TakeProfitCondition> = conditions
+            .iter()
+            .filter(|condition| profit_percentage >= condition.pnl_percentage as f64)
+            .filter(|condition| !self.tp_leg_filled(condition.pnl_percentage))
+            .collect();
+        due.sort_by_key(|condition| condition.pnl_percentage);
+
+        let mut remaining = self.remaining_holdings;
+        due.into_iter()
+            .map(|condition| {
+                let open_pct = condition.target_open_percentage.clamp(0, 100) as f64;
+                let target = (self.initial_holdings as f64 * open_pct / 100.0).round() as u64;
+                let amount = remaining.saturating_sub(target);
+                remaining -= amount;
+                (condition.pnl_percentage, amount)
+            })
+            .collect()
+    }
+
+    /// Tokens to sell on a stop loss or trailing stop signal, always what
+    /// is left after the take profit legs. Take profits go through
+    /// [`ActiveTrade::due_tp_legs`].
     pub fn calculate_sell_amount(
         &self,
         profit_percentage: f64,
@@ -79,22 +225,6 @@ impl ActiveTrade {
             return None;
         }
 
-        // Handle take profit conditions
-        if let Some(tp_conditions) = &sell_conditions.take_profit_conditions {
-            for condition in tp_conditions {
-                if profit_percentage >= (condition.pnl_percentage as f64) {
-                    tracing::info!("> Selected take profit: {}", condition.description);
-                    let percentage = condition.target_open_percentage as f64 / 100.0;
-                    let percentage_to_sell = 1f64 - percentage;
-                    tracing::info!("> Percentage to sell: {}%", percentage_to_sell.mul(100.0));
-                    let target_selling = (self.initial_holdings as f64)
-                        .mul(percentage_to_sell)
-                        .round() as u64;
-                    return Some(self.remaining_holdings.min(target_selling));
-                }
-            }
-        }
-
         None
     }
 
@@ -106,61 +236,35 @@ impl ActiveTrade {
     }
 }
 
+/// Positions, in the storage selected by `DB_BACKEND`
 pub struct ActiveTradeManager {
-    collection: Collection<ActiveTrade>,
+    storage: Arc<dyn Storage>,
 }
 
 impl ActiveTradeManager {
-    pub fn new(collection: Collection<ActiveTrade>) -> Self {
-        Self { collection }
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        Self { storage }
     }
 
     pub async fn save_trade(&self, trade: &mut ActiveTrade) -> Result<()> {
         trade.updated_at = chrono::Utc::now().timestamp();
 
-        // Find by both token_address and strategy_id
-        let filter = doc! {
-            "token_address": &trade.token_address,
-            "strategy_id": &trade.strategy_id
-        };
-
-        if let Some(id) = trade.id {
-            self.collection
-                .update_one(
-                    doc! { "_id": id },
-                    doc! { "$set": bson::to_document(&trade)? },
-                    None,
-                )
-                .await?;
+        if trade.id.is_some() {
+            self.storage.update_active_trade(trade).await
         } else {
-            let result = self.collection.insert_one(trade.clone(), None).await?;
-            trade.id = Some(result.inserted_id.as_object_id().unwrap());
+            trade.id = Some(self.storage.insert_active_trade(trade).await?);
+            Ok(())
         }
-        Ok(())
     }
 
     pub async fn load_all_trades(&self) -> Result<Vec<ActiveTrade>> {
-        let mut trades = Vec::new();
-        let mut cursor = self.collection.find(None, None).await?;
-
-        while cursor.advance().await? {
-            trades.push(cursor.deserialize_current()?);
-        }
-
-        Ok(trades)
+        self.storage.open_trades().await
     }
 
     pub async fn remove_trade(&self, token_address: &str, strategy_id: &str) -> Result<()> {
-        self.collection
-            .delete_one(
-                doc! {
-                    "token_address": token_address,
-                    "strategy_id": strategy_id
-                },
-                None,
-            )
-            .await?;
-        Ok(())
+        self.storage
+            .remove_open_trade(token_address, strategy_id)
+            .await
     }
 
     pub async fn get_trade(
@@ -168,16 +272,41 @@ impl ActiveTradeManager {
         token_address: &str,
         strategy_id: &str,
     ) -> Result<Option<ActiveTrade>> {
-        self.collection
-            .find_one(
-                doc! {
-                    "token_address": token_address,
-                    "strategy_id": strategy_id
-                },
-                None,
+        self.storage.open_trade(token_address, strategy_id).await
+    }
+
+    /// Trades fully closed at or after `since` (unix seconds)
+    pub async fn load_closed_since(&self, since: i64) -> Result<Vec<ActiveTrade>> {
+        self.storage.closed_trades_since(since).await
+    }
+
+    /// Last closed position of `strategy_id` in `token_address`
+    pub async fn last_closed(
+        &self,
+        token_address: &str,
+        strategy_id: &str,
+    ) -> Result<Option<ActiveTrade>> {
+        self.storage
+            .last_closed_trade(token_address, strategy_id)
+            .await
+    }
+
+    /// Book a sell against the open trade, closing it once nothing is left
+    pub async fn record_sell(
+        &self,
+        token_address: &str,
+        strategy_id: &str,
+        new_holdings: u64,
+        sol_received_lamports: u64,
+    ) -> Result<()> {
+        self.storage
+            .record_sell(
+                token_address,
+                strategy_id,
+                new_holdings,
+                sol_received_lamports,
             )
             .await
-            .map_err(Into::into)
     }
 
     pub async fn update_holdings(
@@ -186,36 +315,128 @@ impl ActiveTradeManager {
         strategy_id: &str,
         new_holdings: u64,
     ) -> Result<()> {
-        self.collection
-            .update_one(
-                doc! {
-                    "token_address": token_address,
-                    "strategy_id": strategy_id
-                },
-                doc! {
-                    "$set": {
-                        "remaining_holdings": new_holdings as i64,
-                        "updated_at": chrono::Utc::now().timestamp()
-                    }
-                },
-                None,
-            )
-            .await?;
-        Ok(())
-    }
-
-    pub async fn setup_indexes(&self) -> Result<()> {
-        self.collection
-            .create_index(
-                IndexModel::builder()
-                    .keys(doc! {
-                        "token_address": 1,
-                        "strategy_id": 1
-                    })
-                    .build(),
-                None,
-            )
-            .await?;
-        Ok(())
+        self.storage
+            .update_holdings(token_address, strategy_id, new_holdings)
+            .await
+    }
+
+    /// Record take profit legs executed against `trade`
+    pub async fn record_tp_legs(&self, trade: &ActiveTrade, legs: &[TpLegFill]) -> Result<()> {
+        let Some(trade_id) = trade.id else {
+            return Ok(());
+        };
+        self.storage.record_tp_legs(trade_id, legs).await
+    }
+
+    /// Raise the stored highest price of an open trade, lower prices are
+    /// ignored
+    pub async fn update_highest_price(
+        &self,
+        token_address: &str,
+        strategy_id: &str,
+        price: f64,
+    ) -> Result<()> {
+        self.storage
+            .raise_highest_price(token_address, strategy_id, price)
+            .await
+    }
+
+    pub async fn set_stop_price(
+        &self,
+        token_address: &str,
+        strategy_id: &str,
+        stop_price: f64,
+    ) -> Result<()> {
+        self.storage
+            .set_stop_price(token_address, strategy_id, stop_price)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sell_pnl_uses_share_of_cost_basis() {
+        let mut trade = ActiveTrade::new(
+            "TOKEN".to_string(),
+            "mint".to_string(),
+            "degen".to_string(),
+            1_000,
+            0.001,
+        );
+        trade.sol_spent_lamports = 100_000_000;
+
+        // Half the position sold for 75% of what all of it cost
+        let pnl = trade.sell_pnl(500, 75_000_000);
+        assert_eq!(pnl.lamports, 25_000_000);
+        assert_eq!(pnl.pct, Some(50.0));
+        // The rest sold later closes the position 40% up
+        trade.sol_received_lamports = 75_000_000;
+        let closed = trade.closed_pnl(65_000_000);
+        assert_eq!(closed.lamports, 40_000_000);
+        assert_eq!(closed.pct, Some(40.0));
+
+        trade.sol_spent_lamports = 0;
+        assert_eq!(trade.sell_pnl(500, 1).pct, None);
+    }
+
+    fn leg(pnl_percentage: i32, target_open_percentage: i32) -> TakeProfitCondition {
+        TakeProfitCondition {
+            pnl_percentage,
+            target_open_percentage,
+            description: format!("TP {}%", pnl_percentage),
+        }
+    }
+
+    #[test]
+    fn test_due_tp_legs_fire_once() {
+        let ladder = vec![leg(200, 0), leg(50, 70), leg(100, 40)];
+        let mut trade = ActiveTrade::new(
+            "TOKEN".to_string(),
+            "mint".to_string(),
+            "degen".to_string(),
+            1_000,
+            0.001,
+        );
+        assert!(trade.due_tp_legs(&ladder, 20.0).is_empty());
+        // A jump past two legs sells down to the deeper target
+        assert_eq!(
+            trade.due_tp_legs(&ladder, 120.0),
+            vec![(50, 300), (100, 300)]
+        );
+
+        assert_eq!(trade.due_tp_legs(&ladder, 60.0), vec![(50, 300)]);
+        trade.remaining_holdings = 700;
+        trade.tp_legs_filled.push(TpLegFill {
+            pnl_percentage: 50,
+            token_amount: 300,
+            sol_received_lamports: 0,
+            tx_sig: None,
+            filled_at: 0,
+        });
+        assert!(trade.due_tp_legs(&ladder, 60.0).is_empty());
+        assert_eq!(trade.due_tp_legs(&ladder, 150.0), vec![(100, 300)]);
+
+        // Sold down by hand below the next target
+        trade.remaining_holdings = 300;
+        assert_eq!(trade.due_tp_legs(&ladder, 150.0), vec![(100, 0)]);
+    }
+
+    #[test]
+    fn test_share_of_holdings() {
+        let mut trade = ActiveTrade::new(
+            "TOKEN".to_string(),
+            "mint".to_string(),
+            "degen".to_string(),
+            1_000,
+            0.001,
+        );
+        trade.remaining_holdings = 800;
+        assert_eq!(trade.share_of_holdings(0.25), 200);
+        assert_eq!(trade.share_of_holdings(1.5), 800);
+        assert_eq!(trade.share_of_holdings(-0.1), 0);
+        assert_eq!(trade.share_of_holdings(f64::NAN), 0);
     }
 }
@@ -0,0 +1,320 @@
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Utc};
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use grammers_client::grammers_tl_types::{self as tl, Deserializable, Serializable};
+use grammers_client::Client;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time;
+
+/// One line of the archive, TL objects in their wire format as base64
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Record {
+    Message {
+        at: DateTime<Utc>,
+        chat_id: i64,
+        tl: String,
+    },
+    Poll {
+        at: DateTime<Utc>,
+        chat_id: i64,
+        last_message_id: i64,
+        newest_message_id: i64,
+    },
+    Update {
+        at: DateTime<Utc>,
+        tl: String,
+    },
+}
+
+/// What the archive holds, in the order it was received
+#[derive(Debug)]
+pub enum ArchivedUpdate {
+    /// A message returned by a poll of the followed chat
+    Message {
+        at: DateTime<Utc>,
+        chat_id: i64,
+        message: tl::types::Message,
+    },
+    /// End of a poll, the messages since the previous one were fetched
+    /// together, newest first
+    Poll {
+        at: DateTime<Utc>,
+        chat_id: i64,
+        last_message_id: i64,
+        newest_message_id: i64,
+    },
+    /// Anything Telegram pushed to the session: new and edited messages,
+    /// deletions, pins, reactions...
+    Update {
+        at: DateTime<Utc>,
+        update: tl::enums::Update,
+    },
+}
+
+/// Appends everything the copier receives from Telegram to a gzip
+/// compressed JSON lines file. Every run adds a gzip member, so the archive
+/// stays readable after a crash up to the last flush.
+pub struct UpdateRecorder {
+    writer: Mutex<GzEncoder<File>>,
+}
+
+impl UpdateRecorder {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            writer: Mutex::new(GzEncoder::new(file, Compression::default())),
+        })
+    }
+
+    pub fn record_message(&self, chat_id: i64, message: &tl::types::Message) {
+        let message = tl::enums::Message::Message(message.clone());
+        self.write(
+            &Record::Message {
+                at: Utc::now(),
+                chat_id,
+                tl: STANDARD.encode(message.to_bytes()),
+            },
+            false,
+        );
+    }
+
+    /// Closes a poll and flushes its messages
+    pub fn record_poll(&self, chat_id: i64, last_message_id: i64, newest_message_id: i64) {
+        self.write(
+            &Record::Poll {
+                at: Utc::now(),
+                chat_id,
+                last_message_id,
+                newest_message_id,
+            },
+            true,
+        );
+    }
+
+    pub fn record_update(&self, update: &tl::enums::Update) {
+        self.write(
+            &Record::Update {
+                at: Utc::now(),
+                tl: STANDARD.encode(update.to_bytes()),
+            },
+            true,
+        );
+    }
+
+    /// A broken archive must not stop the copier, failures are only logged
+    fn write(&self, record: &Record, flush: bool) {
+        let mut writer = self.writer.lock().unwrap();
+        let result = serde_json::to_writer(&mut *writer, record)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| Ok(writer.write_all(b"\n")?))
+            .and_then(|_| match flush {
+                true => Ok(writer.flush()?),
+                false => Ok(()),
+            });
+        if let Err(e) = result {
+            tracing::error!("Failed to archive Telegram update: {:?}", e);
+        }
+    }
+}
+
+impl Drop for UpdateRecorder {
+    fn drop(&mut self) {
+        if let Ok(writer) = self.writer.get_mut() {
+            if let Err(e) = writer.try_finish() {
+                tracing::error!("Failed to close the update archive: {:?}", e);
+            }
+        }
+    }
+}
+
+/// Records the updates Telegram pushes to the session until `shutdown`
+/// flips. The copier polls its chat and otherwise leaves them unread.
+pub async fn run_update_recorder(
+    client: Client,
+    recorder: Arc<UpdateRecorder>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    tracing::info!("Recording raw Telegram updates");
+    loop {
+        tokio::select! {
+            biased;
+            _ = shutdown.changed() => {
+                tracing::info!("Update recorder stopped");
+                return;
+            }
+            update = client.next_raw_update() => match update {
+                Ok((update, _)) => recorder.record_update(&update),
+                Err(e) => {
+                    tracing::error!("Failed to receive Telegram updates: {:?}", e);
+                    time::sleep(Duration::from_secs(5)).await;
+                }
+            }
+        }
+    }
+}
+
+/// Read an archive written by [`UpdateRecorder`]. A truncated end, left by
+/// a crash, ends the archive with a warning.
+pub fn read_archive(path: impl AsRef<Path>) -> Result<Vec<ArchivedUpdate>> {
+    let reader = BufReader::new(MultiGzDecoder::new(File::open(path)?));
+    let mut updates = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                tracing::warn!("Archive ends in a broken record after line {}: {}", i, e);
+                break;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: Record = serde_json::from_str(&line)
+            .map_err(|e| anyhow!("Invalid archive line {}: {}", i + 1, e))?;
+        updates.push(decode_record(record)?);
+    }
+    Ok(updates)
+}
+
+fn decode_record(record: Record) -> Result<ArchivedUpdate> {
+    Ok(match record {
+        Record::Message {
+            at,
+            chat_id,
+            tl: encoded,
+        } => {
+            let message = match tl::enums::Message::from_bytes(&STANDARD.decode(encoded)?)? {
+                tl::enums::Message::Message(message) => message,
+                other => return Err(anyhow!("Unexpected archived message {:?}", other)),
+            };
+            ArchivedUpdate::Message {
+                at,
+                chat_id,
+                message,
+            }
+        }
+        Record::Poll {
+            at,
+            chat_id,
+            last_message_id,
+            newest_message_id,
+        } => ArchivedUpdate::Poll {
+            at,
+            chat_id,
+            last_message_id,
+            newest_message_id,
+        },
+        Record::Update { at, tl: encoded } => ArchivedUpdate::Update {
+            at,
+            update: tl::enums::Update::from_bytes(&STANDARD.decode(encoded)?)?,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: i32, text: &str) -> tl::types::Message {
+        tl::types::Message {
+            out: false,
+            mentioned: false,
+            media_unread: false,
+            silent: false,
+            post: true,
+            from_scheduled: false,
+            legacy: false,
+            edit_hide: false,
+            pinned: false,
+            noforwards: false,
+            invert_media: false,
+            offline: false,
+            id,
+            from_id: None,
+            from_boosts_applied: None,
+            peer_id: tl::enums::Peer::Channel(tl::types::PeerChannel {
+                channel_id: 1234567890,
+            }),
+            saved_peer_id: None,
+            fwd_from: None,
+            via_bot_id: None,
+            via_business_bot_id: None,
+            reply_to: None,
+            date: 1_714_566_896,
+            message: text.to_string(),
+            media: None,
+            reply_markup: None,
+            entities: None,
+            views: None,
+            forwards: None,
+            replies: None,
+            edit_date: None,
+            post_author: None,
+            grouped_id: None,
+            reactions: None,
+            restriction_reason: None,
+            ttl_period: None,
+            quick_reply_shortcut_id: None,
+            effect: None,
+            factcheck: None,
+        }
+    }
+
+    #[test]
+    fn test_archive_round_trip() {
+        let path = std::env::temp_dir().join(format!("updates-{}.jsonl.gz", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        // Two runs append two gzip members
+        let recorder = UpdateRecorder::open(&path).unwrap();
+        recorder.record_message(1234567890, &message(42, "🟢 BUY $MEME"));
+        recorder.record_poll(1234567890, 41, 42);
+        drop(recorder);
+        let recorder = UpdateRecorder::open(&path).unwrap();
+        recorder.record_update(&tl::enums::Update::DeleteMessages(
+            tl::types::UpdateDeleteMessages {
+                messages: vec![42],
+                pts: 7,
+                pts_count: 1,
+            },
+        ));
+        drop(recorder);
+
+        let updates = read_archive(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(updates.len(), 3);
+        match &updates[0] {
+            ArchivedUpdate::Message {
+                chat_id, message, ..
+            } => {
+                assert_eq!(*chat_id, 1234567890);
+                assert_eq!(message.id, 42);
+                assert_eq!(message.message, "🟢 BUY $MEME");
+            }
+            other => panic!("Expected a message, got {:?}", other),
+        }
+        assert!(matches!(
+            updates[1],
+            ArchivedUpdate::Poll {
+                last_message_id: 41,
+                newest_message_id: 42,
+                ..
+            }
+        ));
+        assert!(matches!(
+            &updates[2],
+            ArchivedUpdate::Update {
+                update: tl::enums::Update::DeleteMessages(_),
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,62 @@
+use anyhow::Result;
+use bson::oid::ObjectId;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::storage::Storage;
+use crate::tg_copy::db;
+use crate::tg_copy::strategy::{normalize_strategy_id, Strategy};
+
+#[derive(Debug, Default)]
+pub struct BackfillReport {
+    pub trades_tagged: u64,
+    pub placeholders_created: Vec<String>,
+}
+
+impl fmt::Display for BackfillReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "tagged {} trades, created {} placeholder strategies [{}]",
+            self.trades_tagged,
+            self.placeholders_created.len(),
+            self.placeholders_created.join(", ")
+        )
+    }
+}
+
+/// Assign every stored open/close signal that has no `strategy_ref` yet to
+/// its strategy document. Strategy names that don't exist in the collection
+/// get a placeholder document so no historical trade is left untagged.
+///
+/// Safe to run repeatedly, already tagged trades are skipped.
+pub async fn backfill_strategy_tags(storage: &dyn Storage) -> Result<BackfillReport> {
+    let mut known: HashMap<String, ObjectId> = db::load_strategies(storage)
+        .await?
+        .into_iter()
+        .map(|s| (normalize_strategy_id(&s.strategy_id), s.id))
+        .collect();
+
+    let names = storage.untagged_strategy_names().await?;
+
+    let mut report = BackfillReport::default();
+    for name in &names {
+        let strategy_ref = match known.get(&normalize_strategy_id(name)) {
+            Some(id) => *id,
+            None => {
+                let placeholder = Strategy::placeholder(name);
+                storage.insert_strategy(&placeholder).await?;
+                tracing::info!("Created placeholder strategy {}", name);
+                known.insert(normalize_strategy_id(name), placeholder.id);
+                report.placeholders_created.push(name.clone());
+                placeholder.id
+            }
+        };
+
+        let tagged = storage.tag_trades(name, strategy_ref).await?;
+        tracing::info!("Tagged {} trades of strategy {}", tagged, name);
+        report.trades_tagged += tagged;
+    }
+
+    Ok(report)
+}
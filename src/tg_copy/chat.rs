@@ -0,0 +1,194 @@
+use anyhow::{anyhow, Result};
+use grammers_client::grammers_tl_types as tl;
+use grammers_client::types::Chat;
+use grammers_client::Client;
+use std::convert::Infallible;
+use std::str::FromStr;
+
+/// Topic of the messages posted in a forum outside any other topic
+pub const GENERAL_TOPIC_ID: i32 = 1;
+
+/// A chat to follow: a group, channel or forum given by its numeric ID, an
+/// invite or public t.me link, or its name as shown in the dialogs
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChatRef {
+    Id(i64),
+    Invite(String),
+    Username(String),
+    Name(String),
+}
+
+impl FromStr for ChatRef {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Ok(id) = s.parse::<i64>() {
+            // Bot API IDs prefix channels with -100 and groups with -
+            let id = match s.strip_prefix("-100") {
+                Some(channel_id) if channel_id.len() > 3 => channel_id.parse().unwrap_or(id),
+                _ => id,
+            };
+            return Ok(ChatRef::Id(id.abs()));
+        }
+        if let Some(username) = s.strip_prefix('@') {
+            return Ok(ChatRef::Username(username.to_string()));
+        }
+
+        let link = s
+            .trim_start_matches("https://")
+            .trim_start_matches("http://");
+        let Some(path) = link
+            .strip_prefix("t.me/")
+            .or_else(|| link.strip_prefix("telegram.me/"))
+        else {
+            return Ok(ChatRef::Name(s.to_string()));
+        };
+        let path = path.split(['/', '?']).collect::<Vec<_>>();
+        Ok(match path.as_slice() {
+            ["joinchat", hash, ..] => ChatRef::Invite(hash.to_string()),
+            [name, ..] => match name.strip_prefix('+') {
+                Some(hash) => ChatRef::Invite(hash.to_string()),
+                None => ChatRef::Username(name.to_string()),
+            },
+            [] => ChatRef::Name(s.to_string()),
+        })
+    }
+}
+
+/// Resolve `chat` to a chat of the account. Invite links of chats not joined
+/// yet are accepted.
+pub async fn find_chat(client: &Client, chat: &ChatRef) -> Result<Chat> {
+    tracing::info!("Finding chat {:?}...", chat);
+    let id = match chat {
+        ChatRef::Username(username) => {
+            return client
+                .resolve_username(username)
+                .await?
+                .ok_or_else(|| anyhow!("No chat with username @{}", username));
+        }
+        ChatRef::Name(name) => {
+            let name = name.to_lowercase();
+            return find_dialog(client, |chat| chat.name().to_lowercase() == name).await;
+        }
+        ChatRef::Id(id) => *id,
+        ChatRef::Invite(hash) => invite_chat_id(client, hash).await?,
+    };
+    find_dialog(client, |chat| chat.id() == id).await
+}
+
+async fn find_dialog(client: &Client, matches: impl Fn(&Chat) -> bool) -> Result<Chat> {
+    let mut dialogs = client.iter_dialogs();
+    while let Some(dialog) = dialogs.next().await? {
+        if matches(dialog.chat()) {
+            return Ok(dialog.chat().clone());
+        }
+    }
+    Err(anyhow!("Chat not found in your dialogs"))
+}
+
+async fn invite_chat_id(client: &Client, hash: &str) -> Result<i64> {
+    let invite = client
+        .invoke(&tl::functions::messages::CheckChatInvite {
+            hash: hash.to_string(),
+        })
+        .await?;
+    match invite {
+        tl::enums::ChatInvite::Already(invite) => Ok(invite.chat.id()),
+        tl::enums::ChatInvite::Peek(invite) => Ok(invite.chat.id()),
+        tl::enums::ChatInvite::Invite(invite) => {
+            tracing::info!("Joining {} through its invite link", invite.title);
+            let updates = client
+                .invoke(&tl::functions::messages::ImportChatInvite {
+                    hash: hash.to_string(),
+                })
+                .await?;
+            let chats = match updates {
+                tl::enums::Updates::Updates(updates) => updates.chats,
+                tl::enums::Updates::Combined(updates) => updates.chats,
+                _ => Vec::new(),
+            };
+            chats
+                .first()
+                .map(tl::enums::Chat::id)
+                .ok_or_else(|| anyhow!("Joined through the invite link but got no chat back"))
+        }
+    }
+}
+
+/// Whether `message` was posted in forum topic `topic_id`
+pub fn in_topic(message: &tl::types::Message, topic_id: i32) -> bool {
+    topic_of(message.reply_to.as_ref()) == topic_id
+}
+
+fn topic_of(reply_to: Option<&tl::enums::MessageReplyHeader>) -> i32 {
+    match reply_to {
+        // Replies inside a topic point at the topic with `reply_to_top_id`,
+        // other messages of the topic reply to its creation message
+        Some(tl::enums::MessageReplyHeader::Header(header)) if header.forum_topic => header
+            .reply_to_top_id
+            .or(header.reply_to_msg_id)
+            .unwrap_or(GENERAL_TOPIC_ID),
+        _ => GENERAL_TOPIC_ID,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_chat_ref() {
+        let parse = |s: &str| s.parse::<ChatRef>().unwrap();
+        assert_eq!(parse("-1001234567890"), ChatRef::Id(1234567890));
+        assert_eq!(parse("-4242"), ChatRef::Id(4242));
+        assert_eq!(parse("1234567890"), ChatRef::Id(1234567890));
+        assert_eq!(
+            parse("https://t.me/+AbCdEf123"),
+            ChatRef::Invite("AbCdEf123".to_string())
+        );
+        assert_eq!(
+            parse("t.me/joinchat/AbCdEf123"),
+            ChatRef::Invite("AbCdEf123".to_string())
+        );
+        assert_eq!(
+            parse("https://t.me/degen_calls/12"),
+            ChatRef::Username("degen_calls".to_string())
+        );
+        assert_eq!(
+            parse("@degen_calls"),
+            ChatRef::Username("degen_calls".to_string())
+        );
+        assert_eq!(
+            parse(" Degen Calls "),
+            ChatRef::Name("Degen Calls".to_string())
+        );
+    }
+
+    #[test]
+    fn test_topic_of() {
+        let header = |forum_topic, reply_to_msg_id, reply_to_top_id| {
+            tl::enums::MessageReplyHeader::Header(tl::types::MessageReplyHeader {
+                reply_to_scheduled: false,
+                forum_topic,
+                quote: false,
+                reply_to_msg_id,
+                reply_to_peer_id: None,
+                reply_from: None,
+                reply_media: None,
+                reply_to_top_id,
+                quote_text: None,
+                quote_entities: None,
+                quote_offset: None,
+            })
+        };
+        assert_eq!(topic_of(None), GENERAL_TOPIC_ID);
+        assert_eq!(topic_of(Some(&header(true, Some(7), None))), 7);
+        assert_eq!(topic_of(Some(&header(true, Some(42), Some(7)))), 7);
+        // A reply in the General topic
+        assert_eq!(
+            topic_of(Some(&header(false, Some(42), None))),
+            GENERAL_TOPIC_ID
+        );
+    }
+}
@@ -0,0 +1,524 @@
+use anyhow::{anyhow, Result};
+use grammers_client::types::{Chat, InputMessage};
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time;
+
+use crate::admin::auth::{AccessControl, AuthError, Credential, Operator, Scope};
+use crate::config_file::LiveTradingConfig;
+use crate::format;
+use crate::notify::TradeEvent;
+use crate::tg_copy::active_trade::ActiveTrade;
+use crate::tg_copy::flood::FloodAwareClient;
+use crate::trade::bot_state::{BotState, BotStateStore};
+use crate::trade::meme_trader::{sell_outcome, MemeTrader};
+use crate::trade::risk::realized_pnl_today;
+
+const HELP: &str = "Commands:
+  /positions - open positions
+  /pnl [today] - realized PnL today and overall
+  /sell <mint> [pct%] [strategy] - sell a position, all of it and every strategy by default
+  /pause [strategy|all] [reason] - pause new buys
+  /resume [strategy|all] - lift a pause
+  /setsize <sol> - position size of new buys";
+
+/// A command sent to the command chat
+#[derive(Debug, Clone, PartialEq)]
+pub enum BotCommand {
+    Help,
+    Positions,
+    Pnl {
+        today: bool,
+    },
+    Sell {
+        mint: String,
+        pct: f64,
+        strategy: Option<String>,
+    },
+    /// Of every strategy when `strategy` is `None`
+    Pause {
+        strategy: Option<String>,
+        reason: Option<String>,
+    },
+    Resume {
+        strategy: Option<String>,
+    },
+    SetSize(f64),
+}
+
+/// `None` for `all`, which stands for every strategy
+fn strategy_arg(arg: Option<&str>) -> Option<String> {
+    arg.filter(|arg| !arg.eq_ignore_ascii_case("all"))
+        .map(str::to_string)
+}
+
+impl BotCommand {
+    /// `None` for messages that are not commands, an error for commands
+    /// that don't parse. `/pnl@some_bot` is read as `/pnl`, for commands
+    /// addressed to a companion bot in a group.
+    pub fn parse(text: &str) -> Option<Result<Self>> {
+        let mut args = text.split_whitespace();
+        let name = args.next()?.strip_prefix('/')?;
+        let name = name.split('@').next().unwrap_or_default().to_lowercase();
+        let args: Vec<&str> = args.collect();
+        Some(Self::parse_args(&name, &args))
+    }
+
+    fn parse_args(name: &str, args: &[&str]) -> Result<Self> {
+        match (name, args) {
+            ("help" | "start", _) => Ok(Self::Help),
+            ("positions", []) => Ok(Self::Positions),
+            ("pnl", []) => Ok(Self::Pnl { today: false }),
+            ("pnl", ["today"]) => Ok(Self::Pnl { today: true }),
+            ("sell", [mint, rest @ ..]) if rest.len() <= 2 => {
+                Pubkey::from_str(mint).map_err(|_| anyhow!("{} is not a mint address", mint))?;
+                let pct = match rest.first() {
+                    Some(pct) => pct
+                        .trim_end_matches('%')
+                        .parse::<f64>()
+                        .map_err(|_| anyhow!("Expected a percentage, got {}", pct))?,
+                    None => 100.0,
+                };
+                if !(pct > 0.0 && pct <= 100.0) {
+                    return Err(anyhow!("Percentage must be within (0, 100], got {}", pct));
+                }
+                Ok(Self::Sell {
+                    mint: mint.to_string(),
+                    pct,
+                    strategy: rest.get(1).map(|strategy| strategy.to_string()),
+                })
+            }
+            ("pause", args) => Ok(Self::Pause {
+                strategy: strategy_arg(args.first().copied()),
+                reason: (args.len() > 1).then(|| args[1..].join(" ")),
+            }),
+            ("resume", [] | [_]) => Ok(Self::Resume {
+                strategy: strategy_arg(args.first().copied()),
+            }),
+            ("setsize", [size]) => {
+                let size = size
+                    .trim_end_matches("SOL")
+                    .trim_end_matches("sol")
+                    .parse::<f64>()
+                    .map_err(|_| anyhow!("Expected a size in SOL, got {}", size))?;
+                if size.is_nan() || size <= 0.0 {
+                    return Err(anyhow!("Position size must be above 0, got {}", size));
+                }
+                Ok(Self::SetSize(size))
+            }
+            ("positions" | "pnl" | "sell" | "resume" | "setsize", _) => {
+                Err(anyhow!("Wrong arguments for /{}", name))
+            }
+            (name, _) => Err(anyhow!("Unknown command /{}", name)),
+        }
+    }
+
+    /// Action name in the audit collection
+    fn action(&self) -> &'static str {
+        match self {
+            Self::Help => "help",
+            Self::Positions => "positions",
+            Self::Pnl { .. } => "pnl",
+            Self::Sell { .. } => "sell",
+            Self::Pause { .. } => "pause",
+            Self::Resume { .. } => "resume",
+            Self::SetSize(_) => "set_size",
+        }
+    }
+
+    fn scope(&self) -> Scope {
+        match self {
+            Self::Help | Self::Positions | Self::Pnl { .. } => Scope::Read,
+            Self::Sell { .. } | Self::Pause { .. } | Self::Resume { .. } => Scope::Trade,
+            Self::SetSize(_) => Scope::Admin,
+        }
+    }
+
+    /// Selling a whole position is rate limited like the other
+    /// destructive commands
+    fn destructive(&self) -> bool {
+        matches!(self, Self::Sell { pct, .. } if *pct >= 100.0)
+    }
+}
+
+/// Runs the commands of the operators of `ADMIN_TELEGRAM_USERS` and answers
+/// them. Every command goes through [`AccessControl`], the ones changing
+/// anything end up in the audit collection.
+pub struct CommandRouter {
+    trader: Arc<MemeTrader>,
+    access: Arc<AccessControl>,
+    trading: LiveTradingConfig,
+    tip_lamports: u64,
+}
+
+impl CommandRouter {
+    pub fn new(
+        trader: Arc<MemeTrader>,
+        access: Arc<AccessControl>,
+        trading: LiveTradingConfig,
+        tip_lamports: u64,
+    ) -> Self {
+        Self {
+            trader,
+            access,
+            trading,
+            tip_lamports,
+        }
+    }
+
+    /// Answer to `text` sent by the Telegram user `user_id`. `None` for
+    /// messages that are not commands and for senders off the allowlist,
+    /// who get no answer at all.
+    pub async fn handle(&self, user_id: i64, text: &str) -> Option<String> {
+        let command = BotCommand::parse(text)?;
+        let (action, scope, destructive) = match &command {
+            Ok(command) => (command.action(), command.scope(), command.destructive()),
+            Err(_) => ("command", Scope::Read, false),
+        };
+        let operator = match self
+            .access
+            .authorize(
+                Credential::TelegramUser(user_id),
+                action,
+                scope,
+                destructive,
+            )
+            .await
+        {
+            Ok(operator) => operator,
+            Err(AuthError::UnknownOperator) => return None,
+            Err(e) => return Some(format!("Denied: {}", e)),
+        };
+        let command = match command {
+            Ok(command) => command,
+            Err(e) => return Some(format!("{}\n\n{}", e, HELP)),
+        };
+        Some(
+            self.run(&operator, command)
+                .await
+                .unwrap_or_else(|e| format!("Failed: {:#}", e)),
+        )
+    }
+
+    async fn run(&self, operator: &Operator, command: BotCommand) -> Result<String> {
+        match command {
+            BotCommand::Help => Ok(HELP.to_string()),
+            BotCommand::Positions => self.positions().await,
+            BotCommand::Pnl { today } => self.pnl(today).await,
+            BotCommand::Sell {
+                mint,
+                pct,
+                strategy,
+            } => self.sell(operator, &mint, pct, strategy.as_deref()).await,
+            BotCommand::Pause { strategy, reason } => {
+                self.pause(operator, strategy.as_deref(), reason).await
+            }
+            BotCommand::Resume { strategy } => self.resume(operator, strategy.as_deref()).await,
+            BotCommand::SetSize(size) => Ok(self.set_size(operator, size).await),
+        }
+    }
+
+    async fn positions(&self) -> Result<String> {
+        let trades = self.trader.active_trades().load_all_trades().await?;
+        if trades.is_empty() {
+            return Ok("No open positions".to_string());
+        }
+        Ok(trades
+            .iter()
+            .map(|trade| {
+                format!(
+                    "{} ({}) {}: {} left, cost {}",
+                    trade.token_name,
+                    trade.token_address,
+                    trade.strategy_id,
+                    format::share(
+                        trade.remaining_holdings as f64 / trade.initial_holdings.max(1) as f64
+                    ),
+                    format::lamports(trade.remaining_cost_lamports())
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+
+    async fn pnl(&self, today_only: bool) -> Result<String> {
+        let today = realized_pnl_today(&self.trader).await?;
+        if today_only {
+            return Ok(format!(
+                "Realized today: {}",
+                format::signed_lamports(today)
+            ));
+        }
+        let active_trades = self.trader.active_trades();
+        let total: i64 = active_trades
+            .load_closed_since(0)
+            .await?
+            .iter()
+            .map(ActiveTrade::realized_pnl_lamports)
+            .sum();
+        let open = active_trades.load_all_trades().await?;
+        Ok(format!(
+            "Realized today: {}\nRealized overall: {}\nOpen positions: {}, cost {}",
+            format::signed_lamports(today),
+            format::signed_lamports(total),
+            open.len(),
+            format::lamports(open.iter().map(ActiveTrade::remaining_cost_lamports).sum())
+        ))
+    }
+
+    async fn sell(
+        &self,
+        operator: &Operator,
+        mint: &str,
+        pct: f64,
+        strategy: Option<&str>,
+    ) -> Result<String> {
+        let result = self
+            .trader
+            .sell_mint(
+                mint,
+                pct,
+                strategy,
+                self.tip_lamports,
+                "Manual sell (Telegram)",
+            )
+            .await;
+        let outcome = match &result {
+            Ok(results) => sell_outcome(results),
+            Err(e) => format!("failed: {}", e),
+        };
+        self.access
+            .audit(
+                operator,
+                "sell",
+                &format!("{} {}% {}", mint, pct, strategy.unwrap_or("all")),
+                &outcome,
+            )
+            .await;
+        let results = result?;
+        if results.is_empty() {
+            return Ok(format!("No position in {} to sell", mint));
+        }
+        Ok(results
+            .iter()
+            .map(|(trade, fill)| match fill {
+                Ok(fill) => format!(
+                    "Sold {} of {} ({}): {}",
+                    fill.token_amount, trade.token_name, trade.strategy_id, fill.tx_sig
+                ),
+                Err(e) => format!(
+                    "Selling {} ({}) failed: {:#}",
+                    trade.token_name, trade.strategy_id, e
+                ),
+            })
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+
+    fn bot_state(&self) -> Result<&BotStateStore> {
+        self.trader
+            .bot_state()
+            .ok_or_else(|| anyhow!("Pause switches are not kept"))
+    }
+
+    /// `bot_state`, and the pauses of the gate (maintenance, daily loss)
+    fn pause_status(&self, bot_state: &BotState) -> String {
+        match self.trader.buy_gate().paused_reason() {
+            Some(_) => format!("{}\n{}", bot_state, self.trader.buy_gate()),
+            None => bot_state.to_string(),
+        }
+    }
+
+    async fn pause(
+        &self,
+        operator: &Operator,
+        strategy: Option<&str>,
+        reason: Option<String>,
+    ) -> Result<String> {
+        let reason = format!(
+            "paused by {}{}",
+            operator.id,
+            reason.map_or(String::new(), |r| format!(": {}", r))
+        );
+        let (bot_state, changed) = self.bot_state()?.pause(strategy, &reason).await?;
+        if changed {
+            self.trader.notify(TradeEvent::BuysPaused {
+                reason: match strategy {
+                    Some(strategy) => format!("{} {}", strategy, reason),
+                    None => reason.clone(),
+                },
+                breaker: None,
+            });
+        }
+        self.access
+            .audit(
+                operator,
+                "pause",
+                &format!("{} ({})", reason, strategy.unwrap_or("all strategies")),
+                if changed { "paused" } else { "already paused" },
+            )
+            .await;
+        Ok(self.pause_status(&bot_state))
+    }
+
+    async fn resume(&self, operator: &Operator, strategy: Option<&str>) -> Result<String> {
+        let (bot_state, changed) = self.bot_state()?.resume(strategy).await?;
+        if changed {
+            self.trader.notify(TradeEvent::BuysResumed {
+                reason: match strategy {
+                    Some(strategy) => format!("{} resumed by {}", strategy, operator.id),
+                    None => format!("resumed by {}", operator.id),
+                },
+            });
+        }
+        self.access
+            .audit(
+                operator,
+                "resume",
+                strategy.unwrap_or("all strategies"),
+                if changed { "resumed" } else { "was not paused" },
+            )
+            .await;
+        Ok(self.pause_status(&bot_state))
+    }
+
+    async fn set_size(&self, operator: &Operator, size: f64) -> String {
+        let mut before = 0.0;
+        let trading = self.trading.update(|trading| {
+            before = trading.position_size_sol;
+            trading.position_size_sol = size;
+        });
+        self.access
+            .audit(
+                operator,
+                "set_size",
+                &format!("{} -> {}", before, size),
+                "applied",
+            )
+            .await;
+        let mut reply = format!(
+            "Position size {} -> {}, until the config file changes it",
+            format::sol(before),
+            format::sol(size)
+        );
+        if trading.size_from_strategy || !trading.size_bands.is_empty() {
+            reply.push_str("\nStrategy sizes and size bands still take precedence");
+        }
+        reply
+    }
+}
+
+/// Newest message ID of `chat`, commands sent before it are not run
+async fn newest_message_id(client: &FloodAwareClient, chat: &Chat) -> Result<i64> {
+    Ok(client
+        .messages(chat, 0, 0, 1, "reading the command chat")
+        .await?
+        .first()
+        .map_or(0, |message| message.id() as i64))
+}
+
+/// Poll `chat` every `poll` for commands until `shutdown` flips and reply
+/// to each. Commands sent while the bot was down are not run.
+pub async fn run_command_chat(
+    client: FloodAwareClient,
+    chat: Chat,
+    router: CommandRouter,
+    poll: Duration,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    let mut last_message_id = loop {
+        match newest_message_id(&client, &chat).await {
+            Ok(id) => break id,
+            Err(e) => tracing::error!("Failed to read the command chat: {:?}", e),
+        }
+        tokio::select! {
+            _ = shutdown.changed() => return Ok(()),
+            () = time::sleep(poll) => {}
+        }
+    };
+    tracing::info!("Taking commands in {}", chat.name());
+    let mut interval = time::interval(poll);
+    loop {
+        tokio::select! {
+            biased;
+            _ = shutdown.changed() => {
+                tracing::info!("Command chat stopped");
+                return Ok(());
+            }
+            _ = interval.tick() => {}
+        }
+        let messages = match client
+            .messages(&chat, 0, last_message_id, usize::MAX, "polling commands")
+            .await
+        {
+            Ok(messages) => messages,
+            Err(e) => {
+                tracing::error!("Failed to poll the command chat: {:?}", e);
+                continue;
+            }
+        };
+        // Oldest first, a pause sent before a sell is in place by then
+        for message in messages.into_iter().rev() {
+            last_message_id = last_message_id.max(message.id() as i64);
+            let Some(sender) = message.sender() else {
+                continue;
+            };
+            let Some(reply) = router.handle(sender.id(), message.text()).await else {
+                continue;
+            };
+            let reply = InputMessage::text(reply).reply_to(Some(message.id()));
+            if let Err(e) = client.send_message(&chat, reply).await {
+                tracing::error!("Failed to answer command {}: {:?}", message.id(), e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINT: &str = "EKpQGSJtjMFqKZ9KQanSqYXRcF8fBopzLHYxdM65zcjm";
+
+    fn parse(text: &str) -> Result<BotCommand> {
+        BotCommand::parse(text).unwrap()
+    }
+
+    #[test]
+    fn test_parse_command() {
+        assert!(BotCommand::parse("CA: pump it").is_none());
+        assert_eq!(parse("/positions").unwrap(), BotCommand::Positions);
+        assert_eq!(
+            parse("/pnl@copy_bot today").unwrap(),
+            BotCommand::Pnl { today: true }
+        );
+        assert_eq!(
+            parse(&format!("/sell {} 50%", MINT)).unwrap(),
+            BotCommand::Sell {
+                mint: MINT.to_string(),
+                pct: 50.0,
+                strategy: None
+            }
+        );
+        assert!(parse(&format!("/sell {}", MINT)).unwrap().destructive());
+        assert!(parse(&format!("/sell {} 150%", MINT)).is_err());
+        assert!(parse("/sell WIF 50%").is_err());
+        assert_eq!(
+            parse("/pause strategy_x too many rugs").unwrap(),
+            BotCommand::Pause {
+                strategy: Some("strategy_x".to_string()),
+                reason: Some("too many rugs".to_string())
+            }
+        );
+        assert_eq!(
+            parse("/resume all").unwrap(),
+            BotCommand::Resume { strategy: None }
+        );
+        assert_eq!(parse("/setsize 0.2").unwrap(), BotCommand::SetSize(0.2));
+        assert!(parse("/setsize -1").is_err());
+        assert!(parse("/moon").is_err());
+    }
+}
@@ -0,0 +1,247 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::format;
+use crate::storage::Storage;
+use crate::tg_copy::active_trade::ActiveTrade;
+use crate::tg_copy::strategy::normalize_strategy_id;
+
+/// Default of `TRADE_COOLDOWN_SECS`: another open signal for a mint bought
+/// this recently is a duplicate
+pub const TRADE_COOLDOWN_SECS: i64 = 30;
+
+/// `TRADE_COOLDOWN_SECS`, a negative one is refused
+pub fn parse_cooldown_secs(value: &str) -> Result<i64> {
+    let secs: i64 = value.trim().parse()?;
+    if secs < 0 {
+        return Err(anyhow!(
+            "TRADE_COOLDOWN_SECS must be 0 or more, got {}",
+            secs
+        ));
+    }
+    Ok(secs)
+}
+
+/// Strategy names differing only in underscores share a cooldown
+fn cooldown_id(contract_address: &str, strategy: &str) -> String {
+    format!("{}:{}", contract_address, normalize_strategy_id(strategy))
+}
+
+/// Last buy of a mint by a strategy, removed by the storage once its
+/// cooldown passed
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TradeCooldown {
+    /// [`cooldown_id`] of the mint and strategy
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub contract_address: String,
+    pub strategy: String,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub last_trade_at: DateTime<Utc>,
+    /// Cooldown of the strategy that bought
+    #[serde(default = "default_cooldown_secs")]
+    pub cooldown_secs: i64,
+}
+
+fn default_cooldown_secs() -> i64 {
+    TRADE_COOLDOWN_SECS
+}
+
+impl TradeCooldown {
+    pub fn new(contract_address: &str, strategy: &str, cooldown_secs: i64) -> Self {
+        Self {
+            id: cooldown_id(contract_address, strategy),
+            contract_address: contract_address.to_string(),
+            strategy: strategy.to_string(),
+            last_trade_at: Utc::now(),
+            cooldown_secs,
+        }
+    }
+
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        self.last_trade_at + Duration::seconds(self.cooldown_secs)
+    }
+
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        now <= self.expires_at()
+    }
+}
+
+/// Whether a token whose position was closed may be bought again
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReentryPolicy {
+    Unlimited,
+    /// Not on the UTC day a position in it was closed
+    OncePerDay,
+    /// Only at a signal price below the previous exit
+    BelowExit,
+}
+
+impl FromStr for ReentryPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "unlimited" => Ok(ReentryPolicy::Unlimited),
+            "once_per_day" => Ok(ReentryPolicy::OncePerDay),
+            "below_exit" => Ok(ReentryPolicy::BelowExit),
+            _ => Err(anyhow!(
+                "Expected unlimited, once_per_day or below_exit, got '{}'",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ReentryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReentryPolicy::Unlimited => write!(f, "unlimited"),
+            ReentryPolicy::OncePerDay => write!(f, "once_per_day"),
+            ReentryPolicy::BelowExit => write!(f, "below_exit"),
+        }
+    }
+}
+
+impl ReentryPolicy {
+    /// Why buying at `price` after the position `last_closed` is not
+    /// allowed, `None` when it is
+    pub fn blocks(
+        &self,
+        last_closed: &ActiveTrade,
+        price: f64,
+        now: DateTime<Utc>,
+    ) -> Option<String> {
+        match self {
+            ReentryPolicy::Unlimited => None,
+            ReentryPolicy::OncePerDay => {
+                let closed_at = DateTime::from_timestamp(last_closed.closed_at?, 0)?;
+                (closed_at.date_naive() == now.date_naive())
+                    .then(|| format!("closed today at {}", closed_at.format("%H:%M UTC")))
+            }
+            ReentryPolicy::BelowExit => {
+                let exit_price = last_closed.exit_price()?;
+                (price >= exit_price).then(|| {
+                    format!(
+                        "{} is not below the exit at {}",
+                        format::price(price),
+                        format::price(exit_price)
+                    )
+                })
+            }
+        }
+    }
+}
+
+/// Dedup of open signals per mint and strategy, kept in the storage of
+/// `DB_BACKEND` so a signal bought right before a crash is not bought again
+/// after the restart. A strategy's cooldown doesn't hold back the signals
+/// of another.
+#[derive(Clone)]
+pub struct TradeCooldowns {
+    storage: Arc<dyn Storage>,
+}
+
+impl TradeCooldowns {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        Self { storage }
+    }
+
+    /// Cooldown a buy of `contract_address` by `strategy` would run into.
+    /// Expired cooldowns the storage didn't remove yet are ignored.
+    pub async fn active(
+        &self,
+        contract_address: &str,
+        strategy: &str,
+    ) -> Result<Option<TradeCooldown>> {
+        let cooldown = self
+            .storage
+            .cooldown(&cooldown_id(contract_address, strategy))
+            .await?;
+        Ok(cooldown.filter(|cooldown| cooldown.is_active(Utc::now())))
+    }
+
+    /// Skip open signals of `strategy` for `contract_address` for
+    /// `cooldown_secs`
+    pub async fn record(
+        &self,
+        contract_address: &str,
+        strategy: &str,
+        cooldown_secs: i64,
+    ) -> Result<()> {
+        self.storage
+            .save_cooldown(&TradeCooldown::new(
+                contract_address,
+                strategy,
+                cooldown_secs,
+            ))
+            .await
+    }
+
+    /// A closed position may be bought again right away
+    pub async fn clear(&self, contract_address: &str, strategy: &str) -> Result<()> {
+        self.storage
+            .remove_cooldown(&cooldown_id(contract_address, strategy))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cooldown_expires() {
+        let now = Utc::now();
+        let mut cooldown = TradeCooldown::new("mint", "degen", TRADE_COOLDOWN_SECS);
+        cooldown.last_trade_at = now - Duration::seconds(TRADE_COOLDOWN_SECS);
+        assert_eq!(cooldown.id, cooldown_id("mint", "degen"));
+        assert!(cooldown.is_active(now));
+        assert!(!cooldown.is_active(now + Duration::seconds(1)));
+
+        assert_eq!(
+            cooldown_id("mint", "degen_v2"),
+            cooldown_id("mint", "degenv2")
+        );
+        assert_ne!(cooldown_id("mint", "degen"), cooldown_id("mint", "safe"));
+        assert_eq!(parse_cooldown_secs("0").unwrap(), 0);
+        assert!(parse_cooldown_secs("-30").is_err());
+    }
+
+    #[test]
+    fn test_reentry_policy() {
+        let now = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let mut closed = ActiveTrade::new(
+            "WIF".to_string(),
+            "mint".to_string(),
+            "degen".to_string(),
+            1_000,
+            2.0,
+        );
+        closed.sol_spent_lamports = 100_000_000;
+        closed.sol_received_lamports = 150_000_000;
+        closed.closed_at = Some(now.timestamp() - 3600);
+
+        assert_eq!(closed.exit_price(), Some(3.0));
+        assert!(ReentryPolicy::Unlimited.blocks(&closed, 5.0, now).is_none());
+        assert!(ReentryPolicy::BelowExit.blocks(&closed, 2.9, now).is_none());
+        assert!(ReentryPolicy::BelowExit.blocks(&closed, 3.0, now).is_some());
+        assert!(ReentryPolicy::OncePerDay
+            .blocks(&closed, 1.0, now)
+            .is_some());
+        let next_day = now + Duration::days(1);
+        assert!(ReentryPolicy::OncePerDay
+            .blocks(&closed, 1.0, next_day)
+            .is_none());
+        assert_eq!(
+            "below_exit".parse::<ReentryPolicy>().unwrap(),
+            ReentryPolicy::BelowExit
+        );
+        assert!("sometimes".parse::<ReentryPolicy>().is_err());
+    }
+}
@@ -14,120 +14,175 @@
 //! message-[MSG_ID].[EXT]
 //!
 
-use crate::config::{DbConfig, TelegramConfig, TradingConfig};
-use crate::tg_copy::active_trade::{ActiveTrade, ActiveTradeManager};
-use crate::tg_copy::db::{self, TradeDocument};
+use crate::config::{DbConfig, HistoryConfig, TelegramConfig, TradingConfig};
+use crate::config_file::LiveTradingConfig;
+use crate::format;
+use crate::notify::market::market_summary;
+use crate::notify::TradeEvent;
+use crate::solana::attempts::MayStillLand;
+use crate::solana::rpc::make_rpc_client;
+use crate::solana::token_registry::token_registry;
+use crate::solana::transfer_fee::get_transfer_fee_bps;
+use crate::storage::{open_storage, Storage};
+use crate::tg_copy::active_trade::RealizedPnl;
+use crate::tg_copy::archive::UpdateRecorder;
+use crate::tg_copy::chat::{find_chat, in_topic};
+use crate::tg_copy::cooldown::{ReentryPolicy, TradeCooldowns};
+use crate::tg_copy::copy_trader::{CopyTrader, CopyTraderConfig};
+use crate::tg_copy::db::{self, CopierState, HistoryProgress, TradeFailure, TradeType};
+use crate::tg_copy::flood::FloodAwareClient;
+use crate::tg_copy::journal::{self, journaled, JournalEntry, Verdict};
+use crate::tg_copy::ocr;
 use crate::tg_copy::parse_trade::{parse_trade, Trade};
+use crate::tg_copy::signal_dedup::{SignalDedup, TELEGRAM_SOURCE};
 use crate::tg_copy::strategy::Strategy;
+use crate::tg_copy::strategy_manager::StrategyManager;
+use crate::tg_copy::ticker::resolve_ticker_close;
+use crate::tg_copy::unparsed::UnparsedMessages;
+use crate::trade::costs::estimate_trade_costs;
+use crate::trade::execution::Signal;
+use crate::trade::exposure::{capped_buy_lamports, mint_cost_lamports};
+use crate::trade::intent::intent_id;
 use crate::trade::meme_trader::MemeTrader;
-use anyhow::Result;
+use crate::trade::risk::StreakSizing;
+use crate::trade::sizing::position_size_sol;
+use crate::trade::tasks::TaskManager;
+use crate::trade::token_filter::TokenFilterSource;
+use crate::trade::trailing_stop::entry_stop_price;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use grammers_client::grammers_tl_types as tl;
 use grammers_client::types::Chat;
 use grammers_client::{Client, Config, SignInError};
 use grammers_session::Session;
 use listen_kit::signer::SignerContext;
 use listen_kit::solana::balance::get_balance;
-use listen_kit::solana::util::env;
 use mongodb::Collection;
-use solana_client::nonblocking::rpc_client::RpcClient;
-use solana_sdk::pubkey::Pubkey;
-use std::collections::HashMap;
+use solana_sdk::{
+    native_token::{lamports_to_sol, sol_to_lamports},
+    pubkey::Pubkey,
+};
 use std::io::{self, BufRead, Write};
 use std::str::FromStr;
 use std::sync::Arc;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use tokio::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::watch;
 use tokio::time;
 
 use super::parse_trade::{CloseTrade, OpenTrade};
 
-const SESSION_FILE: &str = "downloader.session";
+pub(super) const SESSION_FILE: &str = "downloader.session";
 
-#[derive(Debug)]
-struct TradeMemory {
-    last_trade_time: u64,
-    strategy: String,
+pub async fn async_main(shutdown: watch::Receiver<bool>) -> Result<()> {
+    let config = CopyTraderConfig::from_env()?;
+    tracing::info!("{}", config);
+    CopyTrader::new(config).await?.start(shutdown).await
 }
 
-pub async fn async_main() -> Result<()> {
-    // Load configurations
+/// Replay an archive of raw updates into the scratch database `db_name`,
+/// `DB_NAME` with a `_replay` suffix by default, so the live one is left
+/// alone. Notifications and event webhooks are off, and nothing is traded
+/// unless `trade` is set and `TRADE_ON` is true.
+pub async fn replay_updates(path: &str, db_name: Option<&str>, trade: bool) -> Result<()> {
+    let mut config = CopyTraderConfig::from_env()?;
+    let db_name = db_name.map_or_else(|| format!("{}_replay", config.db.db_name), str::to_string);
+    if db_name == config.db.db_name {
+        return Err(anyhow!(
+            "Replays don't go into the live database {}",
+            db_name
+        ));
+    }
+    config.db = config.db.with_db_name(&db_name);
+    config.notifier = None;
+    config.discord = None;
+    config.event_webhook = None;
+    config.trading.trade_on &= trade;
+    tracing::info!("{}", config);
+    CopyTrader::new(config).await?.replay(path).await
+}
+
+/// Store the trades of `chat_name` posted since the last run without
+/// trading on them, only those of `topic_id` in a forum group
+pub async fn download_chat(chat_name: &str, topic_id: Option<i32>) -> Result<()> {
     let db_config = DbConfig::from_env()?;
     let telegram_config = TelegramConfig::from_env()?;
-    let trading_config = TradingConfig::from_env()?;
+    let history_config = HistoryConfig::from_env()?;
 
-    // Print configs
-    tracing::info!("{}", db_config);
-    tracing::info!("{}", telegram_config);
-    tracing::info!("{}", trading_config);
+    let db_client = mongodb::Client::with_uri_str(&db_config.mongodb_uri).await?;
+    let db = db_client.database(&db_config.db_name);
+    let storage = open_storage(&db_config, &db).await?;
+    let state_collection = db.collection::<CopierState>("copier_state");
+    let progress_collection = db.collection::<HistoryProgress>("history_progress");
 
-    // Connect to MongoDB
-    let client = mongodb::Client::with_uri_str(&db_config.mongodb_uri).await?;
-    let db = client.database(&db_config.db_name);
-    let collection = db.collection::<TradeDocument>("trades");
-    let strategies_collection = db.collection::<Strategy>("strategies");
-    let strategies = db::load_strategies(&strategies_collection).await?;
+    let client = FloodAwareClient::new(connect_telegram(&telegram_config).await?, Arc::default());
+    let chat = find_chat(&client, &chat_name.parse()?).await?;
+    let last_message_id = resume_message_id(&*storage, &state_collection, &chat).await?;
+    tracing::info!(
+        "Downloading {} from message ID: {}",
+        chat_name,
+        last_message_id
+    );
 
-    tracing::info!("Strategies loaded: {:?}", strategies.len());
+    let result = process_historical_messages(
+        &client,
+        &*storage,
+        &state_collection,
+        &progress_collection,
+        &chat,
+        topic_id,
+        last_message_id,
+        &history_config,
+    )
+    .await
+    .map(|_| ());
 
-    // Setup indexes
-    db::setup_indexes(&collection).await?;
+    client.session().save_to_file(SESSION_FILE)?;
+    result
+}
 
-    // Initialize trade memory
-    let trade_memory: Arc<Mutex<HashMap<String, TradeMemory>>> =
-        Arc::new(Mutex::new(HashMap::new()));
+/// Log in to Telegram on the terminal once and save the session, for
+/// deployments that log in headless otherwise
+pub async fn login() -> Result<()> {
+    let telegram_config = TelegramConfig::from_env()?;
+    let client = connect_client(&telegram_config).await?;
+    if client.is_authorized().await? {
+        tracing::info!("Already logged in, the session in {} works", SESSION_FILE);
+        return Ok(());
+    }
+    handle_login(&client).await
+}
 
-    // Connect to Telegram
+/// Client of the saved session, logged in or not
+pub(super) async fn connect_client(telegram_config: &TelegramConfig) -> Result<Client> {
     tracing::info!("Connecting to Telegram...");
-    let client = Client::connect(Config {
+    Ok(Client::connect(Config {
         session: Session::load_file_or_create(SESSION_FILE)?,
         api_id: telegram_config.api_id,
         api_hash: telegram_config.api_hash.clone(),
         params: Default::default(),
     })
-    .await?;
+    .await?)
+}
 
+/// Logged in client, prompting for the login on the terminal when the
+/// session isn't. A headless login needs the copier's API, other commands
+/// ask for `copy-trade login` then.
+pub(super) async fn connect_telegram(telegram_config: &TelegramConfig) -> Result<Client> {
+    let client = connect_client(telegram_config).await?;
     if !client.is_authorized().await? {
+        if telegram_config.login.headless {
+            return Err(anyhow!(
+                "Telegram session is not logged in, run `copy-trade login` first"
+            ));
+        }
         tracing::info!("First time setup - need to log in!");
         handle_login(&client).await?;
     }
     tracing::info!("Connected!");
-
-    // Find the target group
-    let chat = find_group(&client, &telegram_config.group_name).await?;
-
-    // Get last processed message ID
-    let last_message_id = db::get_last_message_id(&collection).await?.unwrap_or(0);
-    tracing::info!("Starting from message ID: {}", last_message_id);
-
-    // Process historical messages first
-    process_historical_messages(&client, &collection, &chat, last_message_id).await?;
-
-    // Then start listening for new messages
-
-    let active_trades_collection = db.collection::<ActiveTrade>("active_trades");
-
-    // Setup indexes for active trades
-    let active_trade_manager = ActiveTradeManager::new(active_trades_collection.clone());
-    active_trade_manager.setup_indexes().await?;
-
-    // Update MemeTrader initialization
-    let trader = Arc::new(MemeTrader::new(active_trades_collection));
-
-    listen_for_new_messages(
-        &client,
-        &collection,
-        &chat,
-        &trading_config,
-        &telegram_config,
-        trade_memory,
-        trader,
-        strategies,
-    )
-    .await?;
-
-    Ok(())
+    Ok(client)
 }
 
-async fn handle_login(client: &Client) -> Result<()> {
+pub(super) async fn handle_login(client: &Client) -> Result<()> {
     tracing::info!("Signing in...");
     let phone = prompt("Enter your phone number (international format): ")?;
     let token = client.request_login_code(&phone).await?;
@@ -152,62 +207,311 @@ async fn handle_login(client: &Client) -> Result<()> {
     Ok(())
 }
 
-async fn find_group(client: &Client, group_name: &str) -> Result<Chat> {
-    tracing::info!("Finding group {}...", group_name);
-    let mut dialogs = client.iter_dialogs();
+/// Highest message ID seen so far, either stored as a trade or recorded in the
+/// chat checkpoint.
+pub(super) async fn resume_message_id(
+    storage: &dyn Storage,
+    state_collection: &Collection<CopierState>,
+    chat: &Chat,
+) -> Result<i64> {
+    let last_trade_id = db::get_last_message_id(storage).await?.unwrap_or(0);
+    let checkpoint = db::get_checkpoint(state_collection, chat.id())
+        .await?
+        .unwrap_or(0);
+    Ok(last_trade_id.max(checkpoint))
+}
 
-    while let Some(dialog) = dialogs.next().await? {
-        if dialog.chat().name().to_lowercase() == group_name.to_lowercase() {
-            return Ok(dialog.chat().clone());
+/// Store the trades posted after `last_message_id` and return the close
+/// signals among them with their messages, oldest first. The chat is read in
+/// chunks with flood waits slept off, and the position is saved after every
+/// chunk so an interrupted download resumes where it stopped.
+pub(super) async fn process_historical_messages(
+    client: &FloodAwareClient,
+    storage: &dyn Storage,
+    state_collection: &Collection<CopierState>,
+    progress_collection: &Collection<HistoryProgress>,
+    chat: &Chat,
+    topic_id: Option<i32>,
+    last_message_id: i64,
+    cfg: &HistoryConfig,
+) -> Result<Vec<(CloseTrade, Signal)>> {
+    let mut closes = Vec::new();
+    // An interrupted download already stored trades above its floor, so
+    // `last_message_id` would skip the messages it did not reach
+    let mut progress = match db::get_history_progress(progress_collection, chat.id()).await? {
+        Some(mut progress) => {
+            tracing::info!(
+                "Resuming history download of {} below message {} ({} messages processed)",
+                chat.name(),
+                progress.offset_id,
+                progress.processed
+            );
+            // Messages posted since the interrupted run come first
+            let mut newer = new_history_progress(chat, progress.newest_message_id);
+            download_history(
+                client,
+                storage,
+                None,
+                chat,
+                topic_id,
+                cfg,
+                &mut newer,
+                &mut closes,
+            )
+            .await?;
+            progress.newest_message_id = newer.newest_message_id;
+            progress
         }
+        None => new_history_progress(chat, last_message_id),
+    };
+
+    download_history(
+        client,
+        storage,
+        Some(progress_collection),
+        chat,
+        topic_id,
+        cfg,
+        &mut progress,
+        &mut closes,
+    )
+    .await?;
+
+    if progress.newest_message_id > progress.floor_message_id {
+        db::save_checkpoint(state_collection, chat.id(), progress.newest_message_id).await?;
     }
+    db::clear_history_progress(progress_collection, chat.id()).await?;
+    // Messages are iterated newest first
+    closes.reverse();
+    Ok(closes)
+}
 
-    Err(anyhow::anyhow!("Group not found in your dialogs"))
+fn new_history_progress(chat: &Chat, floor_message_id: i64) -> HistoryProgress {
+    HistoryProgress {
+        chat_id: chat.id(),
+        newest_message_id: floor_message_id,
+        offset_id: 0,
+        floor_message_id,
+        processed: 0,
+        updated_at: Utc::now().timestamp(),
+    }
 }
 
-async fn process_historical_messages(
-    client: &Client,
-    collection: &Collection<TradeDocument>,
+/// Store the trades between `progress.offset_id` (the newest message when 0)
+/// and `progress.floor_message_id`, saving `progress` after every chunk when
+/// `progress_collection` is given
+async fn download_history(
+    client: &FloodAwareClient,
+    storage: &dyn Storage,
+    progress_collection: Option<&Collection<HistoryProgress>>,
     chat: &Chat,
-    last_message_id: i64,
+    topic_id: Option<i32>,
+    cfg: &HistoryConfig,
+    progress: &mut HistoryProgress,
+    closes: &mut Vec<(CloseTrade, Signal)>,
 ) -> Result<()> {
-    let mut messages = client.iter_messages(chat.clone());
-    while let Some(message) = messages.next().await? {
-        if (message.id() as i64) <= last_message_id {
-            break;
-        }
-        let text = message.text();
-        tracing::info!("Processing message {} - {}", message.id(), text);
-        if let Some(trade) = parse_trade(text) {
-            db::store_trade_db(
-                collection,
+    let oldest_date = cfg
+        .max_days
+        .map(|days| Utc::now() - chrono::Duration::days(days));
+    loop {
+        let chunk = client
+            .messages(
+                chat,
+                progress.offset_id,
+                0,
+                cfg.chunk_size,
+                "downloading history",
+            )
+            .await?;
+        let Some(last) = chunk.last() else {
+            return Ok(());
+        };
+        let mut done = chunk.len() < cfg.chunk_size;
+        progress.offset_id = last.id() as i64;
+
+        for message in &chunk {
+            let message_id = message.id() as i64;
+            if message_id <= progress.floor_message_id
+                || oldest_date.is_some_and(|oldest| message.date() < oldest)
+            {
+                done = true;
+                break;
+            }
+            progress.newest_message_id = progress.newest_message_id.max(message_id);
+            progress.processed += 1;
+            if topic_id.is_some_and(|topic_id| !in_topic(&message.raw, topic_id)) {
+                continue;
+            }
+
+            let text = message.text();
+            tracing::debug!("Processing message {} - {}", message_id, text);
+            let Some(trade) = parse_trade(text) else {
+                continue;
+            };
+            if let Trade::Close(close_trade) = &trade {
+                let signal = Signal {
+                    message_id: Some(message_id),
+                    posted_at: message.date().into(),
+                    received_at: Utc::now(),
+                    started_at: Utc::now(),
+                    price: close_trade.exit_price,
+                };
+                closes.push((close_trade.clone(), signal));
+            }
+            match db::store_trade_db(
+                storage,
                 trade,
-                message.id() as i64,
+                message_id,
                 text.to_string(),
                 message.date().into(),
             )
-            .await?;
-            tracing::info!("Store message {}", message.id());
+            .await
+            {
+                Ok(()) => tracing::info!("Store message {}", message_id),
+                // Stored by the run that was interrupted
+                Err(e) if db::is_duplicate_trade(&e) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        log_history_progress(chat, progress, oldest_date);
+        if done {
+            return Ok(());
+        }
+        if let Some(progress_collection) = progress_collection {
+            progress.updated_at = Utc::now().timestamp();
+            db::save_history_progress(progress_collection, progress).await?;
         }
+        time::sleep(Duration::from_millis(cfg.chunk_delay_ms)).await;
     }
-    Ok(())
 }
 
-async fn listen_for_new_messages(
-    client: &Client,
-    collection: &Collection<TradeDocument>,
+fn log_history_progress(
     chat: &Chat,
+    progress: &HistoryProgress,
+    oldest_date: Option<DateTime<Utc>>,
+) {
+    // Message IDs grow by one per message, a good enough measure of the
+    // distance left unless the download is capped by date
+    let span = progress.newest_message_id - progress.floor_message_id;
+    let percent = match oldest_date {
+        None if span > 0 => format!(
+            " ({:.0}%)",
+            (progress.newest_message_id - progress.offset_id).max(0) as f64 * 100.0 / span as f64
+        ),
+        _ => String::new(),
+    };
+    tracing::info!(
+        "History of {}: {} messages processed, down to message {}{}",
+        chat.name(),
+        progress.processed,
+        progress.offset_id,
+        percent
+    );
+}
+
+/// Replay close signals posted during downtime against the positions still
+/// open. Exits are executed like live signals unless trading or
+/// `GAP_CLOSE_SELL` is off, then the operator is only alerted.
+pub(super) async fn replay_gap_closes(
+    closes: Vec<(CloseTrade, Signal)>,
+    cooldowns: TradeCooldowns,
+    trader: Arc<MemeTrader>,
     t_cfg: &TradingConfig,
+    strategies: &StrategyManager,
+    storage: &dyn Storage,
+    failures: &Collection<TradeFailure>,
+) {
+    for (close_trade, signal) in closes {
+        let open = match trader
+            .active_trades()
+            .get_trade(&close_trade.contract_address, &close_trade.strategy)
+            .await
+        {
+            Ok(open) => open,
+            Err(e) => {
+                tracing::error!(
+                    "Failed to look up position for missed close of {}: {:?}",
+                    close_trade.token,
+                    e
+                );
+                continue;
+            }
+        };
+        if open.is_none() {
+            continue;
+        }
+
+        tracing::warn!(
+            "Close signal for open position {} ({}) posted during downtime",
+            close_trade.token,
+            close_trade.strategy
+        );
+        if !(t_cfg.trade_on && t_cfg.gap_close_sell) {
+            trader.notify(TradeEvent::MissedClose {
+                token: close_trade.token.clone(),
+                token_address: close_trade.contract_address.clone(),
+                strategy: close_trade.strategy.clone(),
+                reason: close_trade.op_type.to_string(),
+            });
+            continue;
+        }
+
+        let token = close_trade.token.clone();
+        if let Err(e) = handle_close_trade(
+            close_trade,
+            signal,
+            cooldowns.clone(),
+            Arc::clone(&trader),
+            t_cfg,
+            strategies,
+            storage,
+            failures,
+        )
+        .await
+        {
+            tracing::error!("Missed close of {} failed: {:?}", token, e);
+        }
+    }
+}
+
+/// Polls the chat until `shutdown` flips. A poll that already started is always
+/// finished, including its DB writes and trade executions, so that the stored
+/// checkpoint never points past a message that wasn't fully handled. Flood
+/// waits delay the poll rather than end the listener.
+pub(super) async fn listen_for_new_messages(
+    client: &FloodAwareClient,
+    storage: &Arc<dyn Storage>,
+    unparsed: &UnparsedMessages,
+    state_collection: &Collection<CopierState>,
+    failures: &Collection<TradeFailure>,
+    chat: &Chat,
+    trading: &LiveTradingConfig,
     tg_cfg: &TelegramConfig,
-    trade_memory: Arc<Mutex<HashMap<String, TradeMemory>>>,
+    cooldowns: TradeCooldowns,
     trader: Arc<MemeTrader>,
-    strategies: Vec<Strategy>,
+    strategies: &StrategyManager,
+    token_filters: &TokenFilterSource,
+    signal_dedup: &SignalDedup,
+    tasks: &TaskManager,
+    recorder: Option<&UpdateRecorder>,
+    shutdown: &mut watch::Receiver<bool>,
 ) -> Result<()> {
     let mut interval = time::interval(Duration::from_secs(tg_cfg.pool_frequency));
     let mut counter = 0;
+    if tg_cfg.ocr_images && !ocr::available() {
+        tracing::warn!("OCR_IMAGES is set but the ocr feature is not built, photos are not read");
+    }
     tracing::info!("Listening for new messages...\n");
     loop {
-        interval.tick().await;
+        tokio::select! {
+            biased;
+            _ = shutdown.changed() => {
+                tracing::info!("Shutdown requested, stopped polling for new messages");
+                return Ok(());
+            }
+            _ = interval.tick() => {}
+        }
         if counter % 30 == 0 {
             tracing::info!(".");
         } else {
@@ -216,80 +520,288 @@ async fn listen_for_new_messages(
         }
         counter += 1;
 
-        let last_message_id = db::get_last_message_id(collection).await?.unwrap_or(0);
-        let mut messages = client.iter_messages(chat.clone());
+        let last_message_id = resume_message_id(&**storage, state_collection, chat).await?;
+        let mut newest_message_id = last_message_id;
+        let messages = client
+            .messages(chat, 0, last_message_id, usize::MAX, "polling new messages")
+            .await?;
 
-        while let Some(message) = messages.next().await? {
-            if (message.id() as i64) <= last_message_id {
-                break;
+        for message in messages {
+            newest_message_id = newest_message_id.max(message.id() as i64);
+            if let Some(recorder) = recorder {
+                recorder.record_message(chat.id(), &message.raw);
             }
+            let with_photo_ca = match tg_cfg.ocr_images && ocr::available() {
+                true => ocr::with_photo_ca(client, &message.raw).await,
+                false => None,
+            };
+            handle_new_message(
+                chat.id(),
+                with_photo_ca.as_ref().unwrap_or(&message.raw),
+                storage,
+                unparsed,
+                failures,
+                &trading.load(),
+                tg_cfg,
+                &cooldowns,
+                &trader,
+                strategies,
+                token_filters,
+                signal_dedup,
+                tasks,
+            )
+            .await;
+        }
 
-            let text = message.text();
-            if let Some(trade) = parse_trade(text) {
-                let trade_clone = trade.clone();
-                let collection_clone = collection.clone();
-                let message_id = message.id() as i64;
-                let text_clone = text.to_string();
-                let message_date = message.date();
-                let trader = Arc::clone(&trader);
-                let trade_memory = Arc::clone(&trade_memory);
-
-                // Spawn DB storage task
-                let db_task = tokio::spawn(async move {
-                    db::store_trade_db(
-                        &collection_clone,
-                        trade_clone,
+        // Trades of this poll finish before the checkpoint moves past them
+        tasks.wait_idle().await;
+        if let (Some(recorder), true) = (recorder, newest_message_id > last_message_id) {
+            recorder.record_poll(chat.id(), last_message_id, newest_message_id);
+        }
+
+        if newest_message_id > last_message_id {
+            db::save_checkpoint(state_collection, chat.id(), newest_message_id).await?;
+        }
+    }
+}
+
+/// Store `message` if it is a signal and hand its trade to `tasks`. Shared
+/// by the listener and the replay of an update archive.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn handle_new_message(
+    chat_id: i64,
+    message: &tl::types::Message,
+    storage: &Arc<dyn Storage>,
+    unparsed: &UnparsedMessages,
+    failures: &Collection<TradeFailure>,
+    t_cfg: &TradingConfig,
+    tg_cfg: &TelegramConfig,
+    cooldowns: &TradeCooldowns,
+    trader: &Arc<MemeTrader>,
+    strategies: &StrategyManager,
+    token_filters: &TokenFilterSource,
+    signal_dedup: &SignalDedup,
+    tasks: &TaskManager,
+) {
+    let received_at = Utc::now();
+    let message_id = message.id as i64;
+    let mut entry = JournalEntry::new(Some(message_id));
+    if tg_cfg
+        .topic_id
+        .is_some_and(|topic_id| !in_topic(message, topic_id))
+    {
+        return;
+    }
+    let message_date = DateTime::from_timestamp(message.date.into(), 0).unwrap_or_else(Utc::now);
+    // Closes naming the token by ticker only are matched to a position
+    let trade = match parse_trade(&message.message) {
+        Some(trade) => Some(trade),
+        None => resolve_ticker_close(&**storage, &message.message)
+            .await
+            .map(Trade::Close),
+    };
+    let Some(trade) = trade else {
+        // Kept for `reparse` once the parser reads its format
+        if let Err(e) = unparsed
+            .record(chat_id, message_id, &message.message, message_date)
+            .await
+        {
+            tracing::error!("Failed to keep unparsed message {}: {:?}", message_id, e);
+        }
+        if let Some(journal) = trader.journal() {
+            entry.step("parse", Verdict::Skip, Some("no signal".to_string()));
+            journal.write(&entry);
+        }
+        return;
+    };
+    entry.parsed(&trade);
+
+    // Stored before trading, the sell of a close signal adds our PnL to its
+    // record
+    if let Err(e) = db::store_trade_db(
+        &**storage,
+        trade.clone(),
+        message_id,
+        message.message.clone(),
+        message_date,
+    )
+    .await
+    {
+        tracing::error!("Failed to store message {}: {:?}", message_id, e);
+        entry.step("store", Verdict::Fail, Some(format!("{:#}", e)));
+    }
+
+    if !t_cfg.trade_on {
+        if let Some(journal) = trader.journal() {
+            entry.step(
+                "trade_on",
+                Verdict::Skip,
+                Some("TRADE_ON is off".to_string()),
+            );
+            journal.write(&entry);
+        }
+        return;
+    }
+    spawn_trade(
+        trade,
+        TELEGRAM_SOURCE,
+        entry,
+        message_date,
+        received_at,
+        storage,
+        failures,
+        t_cfg,
+        cooldowns,
+        trader,
+        strategies,
+        token_filters,
+        signal_dedup,
+        tasks,
+    )
+    .await;
+}
+
+/// Hand the trade of a signal read from `source` to `tasks`, once the
+/// signal was not seen from another source before. Trades of one token
+/// run in order.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn spawn_trade(
+    trade: Trade,
+    source: &str,
+    entry: JournalEntry,
+    posted_at: DateTime<Utc>,
+    received_at: DateTime<Utc>,
+    storage: &Arc<dyn Storage>,
+    failures: &Collection<TradeFailure>,
+    t_cfg: &TradingConfig,
+    cooldowns: &TradeCooldowns,
+    trader: &Arc<MemeTrader>,
+    strategies: &StrategyManager,
+    token_filters: &TokenFilterSource,
+    signal_dedup: &SignalDedup,
+    tasks: &TaskManager,
+) {
+    trader.signal_received(source, &trade);
+    let message_id = entry.message_id;
+    let origin = match message_id {
+        Some(message_id) => format!("message {}", message_id),
+        None => source.to_string(),
+    };
+    let context = match &trade {
+        Trade::Open(open) => format!("buy of {} ({})", open.token, origin),
+        Trade::Close(close) => format!("sell of {} ({})", close.token, origin),
+    };
+    let source = source.to_string();
+    let trader = Arc::clone(trader);
+    let cooldowns = cooldowns.clone();
+    let t_cfg = t_cfg.clone();
+    let signer = SignerContext::current().await;
+    let strategies = strategies.clone();
+    let storage = Arc::clone(storage);
+    let failures = failures.clone();
+    let token_filters = token_filters.clone();
+    let signal_dedup = signal_dedup.clone();
+    let journal = trader.journal().cloned();
+    let key = match &trade {
+        Trade::Open(open) => open.contract_address.clone(),
+        Trade::Close(close) => close.contract_address.clone(),
+    };
+    tasks
+        .spawn(
+            key,
+            context,
+            SignerContext::with_signer(signer, async move {
+                journaled(journal, entry, async {
+                    if !claim_signal(&signal_dedup, &trade, &source).await? {
+                        return Ok(());
+                    }
+                    handle_trade(
+                        trade,
                         message_id,
-                        text_clone,
-                        message_date.into(),
+                        posted_at,
+                        received_at,
+                        cooldowns,
+                        trader,
+                        &t_cfg,
+                        &strategies,
+                        &*storage,
+                        &failures,
+                        &token_filters,
                     )
                     .await
-                });
-
-                if t_cfg.trade_on {
-                    let trade_clone = trade.clone();
-                    let trader = Arc::clone(&trader);
-                    let trade_memory = Arc::clone(&trade_memory);
-                    let t_cfg = t_cfg.clone();
-                    let signer = SignerContext::current().await;
-                    let strategies = strategies.clone();
-                    let trade_task = tokio::spawn(SignerContext::with_signer(signer, async move {
-                        if let Err(e) =
-                            handle_trade(trade_clone, trade_memory, trader, &t_cfg, strategies)
-                                .await
-                        {
-                            tracing::error!("Error handling trade: {:?}", e);
-                        }
-                        Ok(())
-                    }));
-
-                    let _ = tokio::join!(db_task, trade_task);
-                }
-            }
-        }
-    }
+                })
+                .await
+            }),
+        )
+        .await;
 }
 
-async fn handle_trade(
+pub(super) async fn handle_trade(
     trade: Trade,
-    trade_memory: Arc<Mutex<HashMap<String, TradeMemory>>>,
+    message_id: Option<i64>,
+    posted_at: DateTime<Utc>,
+    received_at: DateTime<Utc>,
+    cooldowns: TradeCooldowns,
     trader: Arc<MemeTrader>,
     t_cfg: &TradingConfig,
-    strategies: Vec<Strategy>,
+    strategies: &StrategyManager,
+    storage: &dyn Storage,
+    failures: &Collection<TradeFailure>,
+    token_filters: &TokenFilterSource,
 ) -> Result<()> {
     match trade {
-        Trade::Open(open_trade) => handle_open_trade(open_trade, trade_memory, trader, t_cfg).await,
+        Trade::Open(open_trade) => {
+            let signal = Signal {
+                message_id,
+                posted_at,
+                received_at,
+                started_at: Utc::now(),
+                price: open_trade.buy_price,
+            };
+            handle_open_trade(
+                open_trade,
+                signal,
+                cooldowns,
+                trader,
+                t_cfg,
+                strategies,
+                failures,
+                token_filters,
+            )
+            .await
+        }
         Trade::Close(close_trade) => {
-            handle_close_trade(close_trade, trade_memory, trader, t_cfg, strategies).await
+            let signal = Signal {
+                message_id,
+                posted_at,
+                received_at,
+                started_at: Utc::now(),
+                price: close_trade.exit_price,
+            };
+            handle_close_trade(
+                close_trade,
+                signal,
+                cooldowns,
+                trader,
+                t_cfg,
+                strategies,
+                storage,
+                failures,
+            )
+            .await
         }
     }
 }
 
 async fn handle_open_trade(
-    open_trade: OpenTrade,
-    trade_memory: Arc<Mutex<HashMap<String, TradeMemory>>>,
+    mut open_trade: OpenTrade,
+    signal: Signal,
+    cooldowns: TradeCooldowns,
     trader: Arc<MemeTrader>,
     t_cfg: &TradingConfig,
+    strategies: &StrategyManager,
+    failures: &Collection<TradeFailure>,
+    token_filters: &TokenFilterSource,
 ) -> Result<()> {
     tracing::info!(
         "Buy signal received: {}, {}, {}",
@@ -298,32 +810,193 @@ async fn handle_open_trade(
         open_trade.contract_address
     );
 
-    if !should_execute_trade(&open_trade, &trade_memory).await {
+    if !should_execute_trade(&open_trade, &cooldowns).await? {
+        return Ok(());
+    }
+
+    if !passes_strategy_filter(&open_trade.strategy, t_cfg, strategies) {
+        return Ok(());
+    }
+
+    if !passes_reentry_policy(&open_trade, &trader, t_cfg, strategies).await? {
+        return Ok(());
+    }
+
+    // Reads the mint, only for signals the cheaper checks above let through
+    apply_real_market_cap(&mut open_trade).await;
+
+    if !passes_buy_conditions(&open_trade, strategies) {
+        return Ok(());
+    }
+
+    if let Some(reason) = token_filters
+        .load()
+        .await?
+        .check(&open_trade.contract_address, &open_trade.token)
+        .await
+    {
+        tracing::info!("Skipping buy of {}: {}", open_trade.token, reason);
+        journal::skip("token_filter", reason);
         return Ok(());
     }
 
-    if !passes_strategy_filter(&open_trade.strategy, t_cfg) {
+    if let Some(reason) = trader.buy_gate().paused_reason() {
+        tracing::info!(
+            "Skipping buy of {}, buys are paused: {}",
+            open_trade.token,
+            reason
+        );
+        journal::skip("buy_gate", reason);
         return Ok(());
     }
 
+    if let Some(bot_state) = trader.bot_state() {
+        if let Some(reason) = bot_state
+            .load()
+            .await?
+            .buy_paused_reason(&open_trade.strategy)
+        {
+            tracing::info!(
+                "Skipping buy of {}, paused by an operator: {}",
+                open_trade.token,
+                reason
+            );
+            journal::skip("bot_state", reason);
+            return Ok(());
+        }
+    }
+
+    let mut size_sol = position_size_sol(&open_trade, &strategies.all(), &trader, t_cfg).await?;
+    if let Some(loss_streaks) = trader.loss_streaks() {
+        match loss_streaks.sizing(&open_trade.strategy).await? {
+            StreakSizing::Scale(factor) if factor < 1.0 => {
+                tracing::info!(
+                    "{} is on a losing streak, position size {} SOL scaled by {}",
+                    open_trade.strategy,
+                    size_sol,
+                    factor
+                );
+                size_sol *= factor;
+            }
+            StreakSizing::Scale(_) => {}
+            StreakSizing::Paused { until } => {
+                tracing::info!(
+                    "Skipping buy of {}, {} is cooling down after a losing streak until {}",
+                    open_trade.token,
+                    open_trade.strategy,
+                    until
+                );
+                journal::skip("loss_streak", format!("paused until {}", until));
+                return Ok(());
+            }
+        }
+    }
+    journal::pass("sizing", format::sol(size_sol));
+
+    let Some(size_sol) = exposure_capped_size(&open_trade, &trader, t_cfg, size_sol).await? else {
+        return Ok(());
+    };
+
+    if !passes_capital_check(&open_trade, &trader, size_sol).await? {
+        return Ok(());
+    }
+
+    if !passes_transfer_fee_check(&open_trade, t_cfg).await? {
+        return Ok(());
+    }
+
+    if !passes_cost_check(&open_trade, t_cfg, size_sol).await? {
+        return Ok(());
+    }
+
+    // Last, the market cap is as recent as it gets right before the buy
+    if !passes_market_cap_recheck(&open_trade, t_cfg).await {
+        return Ok(());
+    }
+
+    // Claimed right before sending, a message reprocessed after a restart
+    // or seen twice is not bought again
+    let intent = match (trader.intents(), signal.message_id) {
+        (Some(intents), Some(message_id)) => {
+            if let Some(earlier) = intents
+                .begin(
+                    message_id,
+                    &open_trade.strategy,
+                    &open_trade.contract_address,
+                )
+                .await?
+            {
+                tracing::info!(
+                    "Skipping buy of {}, already executed: {}",
+                    open_trade.token,
+                    earlier
+                );
+                journal::skip("intent", earlier.to_string());
+                return Ok(());
+            }
+            Some((intents, intent_id(message_id, &open_trade.strategy)))
+        }
+        _ => None,
+    };
+
     match trader
         .meta_buy(
             open_trade.contract_address.as_str(),
             &open_trade.token,
             &open_trade.strategy,
-            t_cfg.position_size_sol,
+            size_sol,
             t_cfg.slippage_bps,
             t_cfg.tip_lamports,
             open_trade.buy_price,
+            Some(&signal),
         )
         .await
     {
-        Ok(tx_sig) => {
-            update_trade_memory(&open_trade, &trade_memory).await;
-            tracing::info!("Buy tx: https://solscan.io/tx/{}", tx_sig);
+        Ok(fill) => {
+            if let Some((intents, id)) = &intent {
+                if let Err(e) = intents.complete(id, &fill.tx_sig).await {
+                    tracing::error!("Failed to complete intent {}: {:?}", id, e);
+                }
+            }
+            register_entry_stop(&trader, &open_trade, strategies).await;
+            let cooldown_secs = strategies
+                .find(&open_trade.strategy)
+                .and_then(|strategy| strategy.cooldown_secs)
+                .unwrap_or(t_cfg.trade_cooldown_secs);
+            cooldowns
+                .record(
+                    &open_trade.contract_address,
+                    &open_trade.strategy,
+                    cooldown_secs,
+                )
+                .await?;
+            tracing::info!("Buy tx: https://solscan.io/tx/{}", fill.tx_sig);
+            journal::outcome(format!(
+                "bought {} tokens on {} in {}",
+                fill.token_amount, fill.venue, fill.tx_sig
+            ));
         }
         Err(e) => {
             tracing::error!("Buy transaction failed: {:?}", e);
+            journal::fail("buy", format!("{:#}", e));
+            // The buy settled its attempts, unless one may still land the
+            // intent stays pending for the next startup to reconcile
+            if let Some((intents, id)) = &intent {
+                if e.downcast_ref::<MayStillLand>().is_some() {
+                    tracing::warn!("Leaving intent {} pending, the buy may still land", id);
+                } else {
+                    intents.fail(id, &e).await?;
+                }
+            }
+            db::record_trade_failure(
+                failures,
+                &open_trade.strategy,
+                &open_trade.token,
+                &open_trade.contract_address,
+                TradeType::Open,
+                &e,
+            )
+            .await?;
         }
     }
 
@@ -332,10 +1005,13 @@ async fn handle_open_trade(
 
 async fn handle_close_trade(
     close_trade: CloseTrade,
-    trade_memory: Arc<Mutex<HashMap<String, TradeMemory>>>,
+    signal: Signal,
+    cooldowns: TradeCooldowns,
     trader: Arc<MemeTrader>,
     t_cfg: &TradingConfig,
-    strategies: Vec<Strategy>,
+    strategies: &StrategyManager,
+    storage: &dyn Storage,
+    failures: &Collection<TradeFailure>,
 ) -> Result<()> {
     tracing::info!(
         "Sell signal received: {}, {}, {}",
@@ -344,100 +1020,599 @@ async fn handle_close_trade(
         close_trade.contract_address
     );
 
-    if !passes_strategy_filter(&close_trade.strategy, t_cfg) {
+    if !passes_strategy_filter(&close_trade.strategy, t_cfg, strategies) {
         return Ok(());
     }
 
     let holdings = get_token_holdings(&close_trade.contract_address).await?;
     tracing::info!("holdings: {:?}", holdings);
 
-    let strategy = strategies
-        .iter()
-        .find(|s| s.strategy_id.replace("_", "") == close_trade.strategy)
-        .unwrap();
+    // Without its sell conditions the close sells the whole position
+    let strategy = strategies.find(&close_trade.strategy).unwrap_or_else(|| {
+        tracing::warn!(
+            "Strategy {} is not in the strategies collection",
+            close_trade.strategy
+        );
+        Strategy::placeholder(&close_trade.strategy)
+    });
 
-    match trader
-        .meta_sell(
-            close_trade.contract_address.as_str(),
-            &close_trade.strategy,
-            close_trade.profit_pct,
-            close_trade.op_type,
-            strategy,
-            t_cfg.tip_lamports,
-        )
-        .await
-    {
-        Ok(tx_sig) => {
-            tracing::info!("Sell tx: https://solscan.io/tx/{}", tx_sig);
+    let untracked_close_max_sol = match t_cfg.untracked_close_max_sol {
+        Some(max_sol) => trader
+            .active_trades()
+            .get_trade(&close_trade.contract_address, &close_trade.strategy)
+            .await?
+            .is_none()
+            .then_some(max_sol),
+        None => None,
+    };
+    let result = match untracked_close_max_sol {
+        Some(max_sol) => {
+            journal::pass("position", "none open, selling the untracked balance");
+            trader
+                .sell_untracked(
+                    &close_trade.contract_address,
+                    &close_trade.token,
+                    &close_trade.strategy,
+                    max_sol,
+                    t_cfg.tip_lamports,
+                    &close_trade.op_type.to_string(),
+                    &signal,
+                )
+                .await
+                .map(Some)
+        }
+        None => match close_trade.sell_fraction {
+            Some(fraction) => {
+                journal::pass(
+                    "position",
+                    format!("the provider sold {:.1}% of its holdings", fraction * 100.0),
+                );
+                trader
+                    .sell_fraction(
+                        &close_trade.contract_address,
+                        &close_trade.strategy,
+                        fraction,
+                        t_cfg.tip_lamports,
+                        Some(&signal),
+                    )
+                    .await
+                    .map(Some)
+            }
+            None => {
+                trader
+                    .meta_sell(
+                        close_trade.contract_address.as_str(),
+                        &close_trade.strategy,
+                        close_trade.profit_pct,
+                        close_trade.op_type.clone(),
+                        &strategy,
+                        t_cfg.tip_lamports,
+                        Some(&signal),
+                    )
+                    .await
+            }
+        },
+    };
+    match result {
+        Ok(None) => journal::outcome("take profit legs reached are already filled"),
+        Ok(Some(fill)) => {
+            tracing::info!("Sell tx: https://solscan.io/tx/{}", fill.tx_sig);
+            journal::outcome(format!(
+                "sold {} tokens on {} in {}",
+                fill.token_amount, fill.venue, fill.tx_sig
+            ));
+            if let Some(pnl) = fill.pnl {
+                record_our_pnl(&close_trade, &signal, &pnl, storage).await;
+            }
         }
         Err(e) => {
             tracing::error!("Sell transaction failed: {:?}", e);
+            journal::fail("sell", format!("{:#}", e));
+            db::record_trade_failure(
+                failures,
+                &close_trade.strategy,
+                &close_trade.token,
+                &close_trade.contract_address,
+                TradeType::Close,
+                &e,
+            )
+            .await?;
         }
     }
 
-    let mut memory = trade_memory.lock().await;
-    memory.remove(&close_trade.contract_address);
+    cooldowns
+        .clear(&close_trade.contract_address, &close_trade.strategy)
+        .await?;
 
     Ok(())
 }
 
-const TRADE_TIMEOUT_SECS: u64 = 30;
+/// Protect the position just bought from the start with the stop loss of
+/// its strategy, the price monitor sells it there even if the group never
+/// posts a stop
+async fn register_entry_stop(
+    trader: &MemeTrader,
+    open_trade: &OpenTrade,
+    strategies: &StrategyManager,
+) {
+    let Some(stop_price) = strategies
+        .find(&open_trade.strategy)
+        .and_then(|strategy| strategy.sell_conditions.stop_loss_condition)
+        .and_then(|sl| entry_stop_price(open_trade.buy_price, &sl))
+    else {
+        return;
+    };
+    match trader
+        .active_trades()
+        .set_stop_price(
+            &open_trade.contract_address,
+            &open_trade.strategy,
+            stop_price,
+        )
+        .await
+    {
+        Ok(()) => tracing::info!(
+            "Stop of {} ({}) set at {}",
+            open_trade.token,
+            open_trade.strategy,
+            format::price(stop_price)
+        ),
+        Err(e) => tracing::error!(
+            "Failed to set the stop of {} ({}): {:?}",
+            open_trade.token,
+            open_trade.strategy,
+            e
+        ),
+    }
+}
+
+/// The provider's PnL comes from its own entry and exit prices, ours from
+/// what we paid and received. Both are kept on the close record.
+async fn record_our_pnl(
+    close_trade: &CloseTrade,
+    signal: &Signal,
+    pnl: &RealizedPnl,
+    storage: &dyn Storage,
+) {
+    tracing::info!(
+        "PnL of {} ({}): provider {}, ours {} ({})",
+        close_trade.token,
+        close_trade.strategy,
+        format::percent(close_trade.profit_pct),
+        pnl.pct.map_or("unknown".to_string(), format::percent),
+        format::sol(pnl.lamports as f64 / 1e9)
+    );
+    let Some(message_id) = signal.message_id else {
+        return;
+    };
+    if let Err(e) = db::record_our_pnl(storage, message_id, pnl).await {
+        tracing::error!(
+            "Failed to record our PnL of message {}: {:?}",
+            message_id,
+            e
+        );
+    }
+}
+
+/// Whether `trade` is seen first. The mirror of a signal already handled
+/// from another source, or before a restart, is not traded again.
+pub(super) async fn claim_signal(
+    signal_dedup: &SignalDedup,
+    trade: &Trade,
+    source: &str,
+) -> Result<bool> {
+    match signal_dedup.claim(trade, source).await? {
+        Some(earlier) => {
+            tracing::info!(
+                "Skipping {:?} from {}, seen from {} at {}",
+                trade,
+                source,
+                earlier.source,
+                earlier.seen_at
+            );
+            journal::skip(
+                "source_dedup",
+                format!("seen from {} at {}", earlier.source, earlier.seen_at),
+            );
+            Ok(false)
+        }
+        None => {
+            journal::pass("source_dedup", source);
+            Ok(true)
+        }
+    }
+}
+
+async fn should_execute_trade(open_trade: &OpenTrade, cooldowns: &TradeCooldowns) -> Result<bool> {
+    match cooldowns
+        .active(&open_trade.contract_address, &open_trade.strategy)
+        .await?
+    {
+        Some(cooldown) => {
+            tracing::info!(
+                "Skipping duplicate trade for {}, bought for {} at {}",
+                open_trade.token,
+                cooldown.strategy,
+                cooldown.last_trade_at
+            );
+            journal::skip(
+                "dedup",
+                format!(
+                    "bought for {} at {}",
+                    cooldown.strategy, cooldown.last_trade_at
+                ),
+            );
+            Ok(false)
+        }
+        None => {
+            journal::pass("dedup", "no recent buy");
+            Ok(true)
+        }
+    }
+}
 
-async fn should_execute_trade(
+/// Whether the re-entry policy of the strategy, or `REENTRY_POLICY`, lets
+/// it buy a token it held before
+async fn passes_reentry_policy(
     open_trade: &OpenTrade,
-    trade_memory: &Arc<Mutex<HashMap<String, TradeMemory>>>,
-) -> bool {
-    let memory = trade_memory.lock().await;
-    let current_time = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
-
-    if let Some(last_trade) = memory.get(&open_trade.contract_address) {
-        if current_time - last_trade.last_trade_time > TRADE_TIMEOUT_SECS {
-            true
-        } else {
+    trader: &MemeTrader,
+    t_cfg: &TradingConfig,
+    strategies: &StrategyManager,
+) -> Result<bool> {
+    let policy = strategies
+        .find(&open_trade.strategy)
+        .and_then(|strategy| strategy.reentry_policy)
+        .unwrap_or(t_cfg.reentry_policy);
+    if policy == ReentryPolicy::Unlimited {
+        return Ok(true);
+    }
+    let Some(last_closed) = trader
+        .active_trades()
+        .last_closed(&open_trade.contract_address, &open_trade.strategy)
+        .await?
+    else {
+        journal::pass("reentry", "never held");
+        return Ok(true);
+    };
+    match policy.blocks(&last_closed, open_trade.buy_price, Utc::now()) {
+        Some(reason) => {
             tracing::info!(
-                "Skipping duplicate trade for {} (previous strategy: {})",
+                "Skipping re-entry of {} ({}, {}): {}",
                 open_trade.token,
-                last_trade.strategy
+                open_trade.strategy,
+                policy,
+                reason
             );
-            false
+            journal::skip("reentry", format!("{}: {}", policy, reason));
+            Ok(false)
+        }
+        None => {
+            journal::pass("reentry", policy.to_string());
+            Ok(true)
         }
+    }
+}
+
+/// With the filter on, only the strategies of `FILTER_STRATEGIES` are
+/// traded, or without it those of the strategies collection
+pub(crate) fn is_strategy_traded(
+    strategy: &str,
+    t_cfg: &TradingConfig,
+    strategies: &StrategyManager,
+) -> bool {
+    if !t_cfg.strategy_filter_on {
+        return true;
+    }
+    if t_cfg.filter_strategies.is_empty() {
+        strategies.is_known(strategy)
     } else {
-        true
+        t_cfg.filter_strategies.iter().any(|s| s == strategy)
     }
 }
 
-fn passes_strategy_filter(strategy: &str, t_cfg: &TradingConfig) -> bool {
+fn passes_strategy_filter(
+    strategy: &str,
+    t_cfg: &TradingConfig,
+    strategies: &StrategyManager,
+) -> bool {
     if !t_cfg.strategy_filter_on {
         return true;
     }
-    t_cfg.filter_strategies.iter().any(|s| s == strategy)
+    let passes = is_strategy_traded(strategy, t_cfg, strategies);
+    match passes {
+        true => journal::pass("strategy_filter", strategy),
+        false => journal::skip("strategy_filter", format!("{} is not traded", strategy)),
+    }
+    passes
 }
 
-async fn update_trade_memory(
+/// Skip signals that meet none of their strategy's buy conditions: market
+/// cap range, wallets, SOL bought and time window. Unknown strategies and
+/// those without conditions take every signal.
+fn passes_buy_conditions(open_trade: &OpenTrade, strategies: &StrategyManager) -> bool {
+    let Some(strategy) = strategies.find(&open_trade.strategy) else {
+        return true;
+    };
+    if strategy.buy_conditions.is_empty() {
+        return true;
+    }
+    match strategy.unmet_buy_conditions(open_trade) {
+        Some(reason) => {
+            tracing::info!(
+                "Skipping buy of {}, {} buy conditions not met: {}",
+                open_trade.token,
+                open_trade.strategy,
+                reason
+            );
+            journal::skip("buy_conditions", reason);
+            false
+        }
+        None => {
+            journal::pass(
+                "buy_conditions",
+                format!(
+                    "{} wallets, {} in {} s at {}",
+                    open_trade.num_buys,
+                    open_trade
+                        .total_buys
+                        .map_or_else(|| "unknown SOL".to_string(), format::sol),
+                    open_trade.time_window,
+                    format::market_cap(open_trade.market_cap)
+                ),
+            );
+            true
+        }
+    }
+}
+
+/// `size_sol` cut down to what `MAX_EXPOSURE_PER_TOKEN_SOL` leaves for the
+/// mint after the open positions of every strategy, `None` when nothing is
+/// left. Signals of one mint are traded one at a time, so the positions
+/// are up to date.
+async fn exposure_capped_size(
     open_trade: &OpenTrade,
-    trade_memory: &Arc<Mutex<HashMap<String, TradeMemory>>>,
-) {
-    let mut memory = trade_memory.lock().await;
-    memory.insert(
-        open_trade.contract_address.clone(),
-        TradeMemory {
-            last_trade_time: SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-            strategy: open_trade.strategy.clone(),
-        },
+    trader: &MemeTrader,
+    t_cfg: &TradingConfig,
+    size_sol: f64,
+) -> Result<Option<f64>> {
+    let Some(max_sol) = t_cfg.max_exposure_per_token_sol else {
+        return Ok(Some(size_sol));
+    };
+    let trades = trader.active_trades().load_all_trades().await?;
+    let open = mint_cost_lamports(&trades, &open_trade.contract_address);
+    let max = sol_to_lamports(max_sol);
+    let size = sol_to_lamports(size_sol);
+    let capped = capped_buy_lamports(open, max, size);
+    if capped == 0 {
+        tracing::info!(
+            "Skipping buy of {}: {} already open, limit {}",
+            open_trade.token,
+            format::lamports(open),
+            format::sol(max_sol)
+        );
+        journal::skip(
+            "exposure",
+            format!(
+                "{} open of {}",
+                format::lamports(open),
+                format::sol(max_sol)
+            ),
+        );
+        return Ok(None);
+    }
+    if capped < size {
+        tracing::info!(
+            "Buy of {} cut to {}: {} already open, limit {}",
+            open_trade.token,
+            format::lamports(capped),
+            format::lamports(open),
+            format::sol(max_sol)
+        );
+    }
+    journal::pass(
+        "exposure",
+        format!(
+            "{} with {} open of {}",
+            format::lamports(capped),
+            format::lamports(open),
+            format::sol(max_sol)
+        ),
     );
+    Ok(Some(lamports_to_sol(capped)))
+}
+
+/// Locked capital is enforced by the signer too, this skips buys that would
+/// be rejected there before any work is done for them
+async fn passes_capital_check(
+    open_trade: &OpenTrade,
+    trader: &MemeTrader,
+    size_sol: f64,
+) -> Result<bool> {
+    let Some(capital) = trader.capital() else {
+        return Ok(true);
+    };
+    let locked = capital.load().await?.locked_lamports;
+    if locked == 0 {
+        return Ok(true);
+    }
+
+    let signer = SignerContext::current().await;
+    let owner = Pubkey::from_str(signer.pubkey().as_str())?;
+    let free = make_rpc_client()
+        .get_balance(&owner)
+        .await?
+        .saturating_sub(locked);
+    if free < sol_to_lamports(size_sol) {
+        tracing::info!(
+            "Skipping trade for {}: only {} free besides {} of locked capital",
+            open_trade.token,
+            format::lamports(free),
+            format::lamports(locked)
+        );
+        journal::skip(
+            "capital",
+            format!(
+                "{} free besides {} locked",
+                format::lamports(free),
+                format::lamports(locked)
+            ),
+        );
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Skip tokens taxing transfers above `MAX_TRANSFER_FEE_BPS`, 0 skips every
+/// taxed token
+async fn passes_transfer_fee_check(open_trade: &OpenTrade, t_cfg: &TradingConfig) -> Result<bool> {
+    let Some(max_fee_bps) = t_cfg.max_transfer_fee_bps else {
+        return Ok(true);
+    };
+    let mint = Pubkey::from_str(&open_trade.contract_address)?;
+    let Some(fee_bps) = get_transfer_fee_bps(&make_rpc_client(), &mint).await? else {
+        return Ok(true);
+    };
+    if fee_bps > max_fee_bps {
+        tracing::info!(
+            "Skipping trade for {}: transfer fee of {} bps, max {} bps",
+            open_trade.token,
+            fee_bps,
+            max_fee_bps
+        );
+        journal::skip(
+            "transfer_fee",
+            format!("{} bps, max {} bps", fee_bps, max_fee_bps),
+        );
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Skip trades whose expected round-trip costs eat too much of the position
+async fn passes_cost_check(
+    open_trade: &OpenTrade,
+    t_cfg: &TradingConfig,
+    size_sol: f64,
+) -> Result<bool> {
+    let Some(max_cost_fraction) = t_cfg.max_cost_fraction else {
+        return Ok(true);
+    };
+
+    let signer = SignerContext::current().await;
+    let owner = Pubkey::from_str(signer.pubkey().as_str())?;
+    let costs = estimate_trade_costs(
+        &make_rpc_client(),
+        &owner,
+        &Pubkey::from_str(&open_trade.contract_address)?,
+        t_cfg.tip_lamports,
+    )
+    .await?;
+
+    let cost_fraction = costs.cost_fraction(sol_to_lamports(size_sol));
+    if cost_fraction > max_cost_fraction {
+        tracing::info!(
+            "Skipping trade for {}: costs are {:.2}% of the position, max {:.2}% ({})",
+            open_trade.token,
+            cost_fraction * 100.0,
+            max_cost_fraction * 100.0,
+            costs
+        );
+        journal::skip(
+            "cost",
+            format!(
+                "{:.2}% of the position, max {:.2}%: {}",
+                cost_fraction * 100.0,
+                max_cost_fraction * 100.0,
+                costs
+            ),
+        );
+        return Ok(false);
+    }
+
+    tracing::info!("Estimated trade costs for {}: {}", open_trade.token, costs);
+    journal::pass("cost", &costs);
+    Ok(true)
+}
+
+/// Replace the market cap of the message by the mint's total supply at the
+/// signal price, for the buy conditions, size bands and re-check. Kept when
+/// the mint can't be read or the signal has no price.
+async fn apply_real_market_cap(open_trade: &mut OpenTrade) {
+    if open_trade.buy_price <= 0.0 {
+        return;
+    }
+    let info = match token_registry().get(&open_trade.contract_address).await {
+        Ok(info) if info.supply > 0 => info,
+        Ok(_) => return,
+        Err(e) => {
+            tracing::warn!(
+                "Could not read mint {}, keeping the market cap of the message: {:?}",
+                open_trade.contract_address,
+                e
+            );
+            return;
+        }
+    };
+    let market_cap = info.market_cap(open_trade.buy_price);
+    let detail = format!(
+        "{} at {} supply, {} by the message",
+        format::market_cap(market_cap),
+        format::compact(info.ui_supply()),
+        format::market_cap(open_trade.market_cap)
+    );
+    tracing::info!("Market cap of {}: {}", info.label(), detail);
+    journal::pass("token", detail);
+    open_trade.market_cap = market_cap;
+}
+
+/// Skip tokens whose market cap ran past `MAX_MC_MULTIPLE` times the
+/// signal's, a buy that late usually lands in someone else's exit. The
+/// market cap comes from the token info caches, at most 30 s old.
+async fn passes_market_cap_recheck(open_trade: &OpenTrade, t_cfg: &TradingConfig) -> bool {
+    let Some(max_multiple) = t_cfg.max_mc_multiple else {
+        return true;
+    };
+    if open_trade.market_cap <= 0.0 {
+        return true;
+    }
+    let Some(market_cap) = market_summary(&open_trade.contract_address)
+        .await
+        .and_then(|summary| summary.market_cap_usd)
+    else {
+        tracing::warn!(
+            "No live market cap for {}, buying without the re-check",
+            open_trade.token
+        );
+        journal::pass("market_cap", "no live market cap");
+        return true;
+    };
+
+    let multiple = market_cap / open_trade.market_cap;
+    let detail = format!(
+        "{} is {:.2}x the signal's {}, max {}x",
+        format::market_cap(market_cap),
+        multiple,
+        format::market_cap(open_trade.market_cap),
+        max_multiple
+    );
+    if multiple > max_multiple {
+        tracing::info!(
+            "Skipping trade for {}: market cap {}",
+            open_trade.token,
+            detail
+        );
+        journal::skip("market_cap", detail);
+        return false;
+    }
+    journal::pass("market_cap", detail);
+    true
 }
 
 async fn get_token_holdings(contract_address: &str) -> Result<String> {
     let signer = SignerContext::current().await;
     let owner = Pubkey::from_str(signer.pubkey().as_str()).unwrap();
     get_balance(
-        &RpcClient::new(env("SOLANA_RPC_URL")),
+        &make_rpc_client(),
         &owner,
         &Pubkey::from_str(contract_address)?,
     )
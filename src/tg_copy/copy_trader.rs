@@ -0,0 +1,1163 @@
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use grammers_client::Client;
+use listen_kit::signer::SignerContext;
+use mongodb::{Collection, Database};
+use std::fmt;
+use std::future::{self, Future};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+
+use crate::admin::audit::AuditEntry;
+use crate::admin::auth::AccessControl;
+use crate::api::run_api_server;
+use crate::config::{
+    AdminConfig, ApiConfig, BalanceGuardConfig, CandleConfig, CommandChatConfig,
+    ComputeBudgetConfig, DbConfig, DecisionJournalConfig, DiscordConfig, EventWebhookConfig,
+    GrpcConfig, HistoryConfig, HousekeepingConfig, JupiterSwapConfig, LatencySloConfig,
+    LiquidityMonitorConfig, LossStreakConfig, MaintenanceConfig, NotifierConfig, PriceFeedConfig,
+    PriceStreamConfig, RiskConfig, SellRetryConfig, SignalWebhookConfig, SlippageConfig,
+    SweepConfig, TelegramConfig, TokenFilterConfig, TradingConfig, UpdateArchiveConfig,
+    UsdBackfillConfig, VenueSelectionConfig, WalletWatchConfig, WalletWebhookConfig,
+};
+use crate::config_file::{self, run_config_reload, LiveTradingConfig};
+#[cfg(feature = "grpc")]
+use crate::grpc::run_grpc_server;
+use crate::notify::discord::DiscordNotifier;
+use crate::notify::telegram::TelegramNotifier;
+use crate::notify::webhook::WebhookNotifier;
+use crate::notify::TradeEvent;
+use crate::solana::housekeeping::run_housekeeping;
+use crate::solana::price::{self, PriceAggregator};
+use crate::solana::wallet_events::WalletBalances;
+use crate::solana::wallet_watcher::{run_wallet_watcher, WalletSignal};
+use crate::solana::ws::{run_price_stream, PriceCache};
+use crate::storage::{open_storage, Storage};
+use crate::tg_copy::archive::{read_archive, run_update_recorder, ArchivedUpdate, UpdateRecorder};
+use crate::tg_copy::chat::find_chat;
+use crate::tg_copy::commands::{run_command_chat, CommandRouter};
+use crate::tg_copy::cooldown::TradeCooldowns;
+use crate::tg_copy::copier::{
+    claim_signal, connect_client, handle_login, handle_new_message, handle_trade,
+    listen_for_new_messages, process_historical_messages, replay_gap_closes, resume_message_id,
+    spawn_trade, SESSION_FILE,
+};
+use crate::tg_copy::db::{CopierState, HistoryProgress, TradeFailure};
+use crate::tg_copy::flood::{FloodAwareClient, FloodWaitStats};
+use crate::tg_copy::journal::{journaled, DecisionJournal, JournalEntry};
+use crate::tg_copy::parse_trade::Trade;
+use crate::tg_copy::relogin::{is_session_revoked, TelegramLogin};
+use crate::tg_copy::signal_dedup::SignalDedup;
+use crate::tg_copy::strategy_manager::{run_strategy_refresh, StrategyManager};
+use crate::tg_copy::unparsed::UnparsedMessages;
+use crate::trade::bot_state::BotStateStore;
+use crate::trade::candles::{run_candle_recorder, CandleStore};
+use crate::trade::capital::CapitalStore;
+use crate::trade::execution::ExecutionLog;
+use crate::trade::exposure::run_exposure_monitor;
+use crate::trade::intent::{reconcile_pending, ExecutionIntents};
+use crate::trade::latency::run_latency_digest;
+use crate::trade::liquidity_monitor::run_liquidity_monitor;
+use crate::trade::maintenance::run_maintenance_scheduler;
+use crate::trade::meme_trader::MemeTrader;
+use crate::trade::risk::{run_daily_loss_breaker, BalanceGuard, LossStreakGuard};
+use crate::trade::slippage::SlippageLog;
+use crate::trade::sweep::{run_profit_sweeper, SweepLog};
+use crate::trade::tasks::{TaskManager, TaskMetrics};
+use crate::trade::token_filter::TokenFilterSource;
+use crate::trade::trailing_stop::run_trailing_stop;
+use crate::trade::usd_backfill::run_usd_backfill;
+
+/// Gate source of pauses requested by the embedding service
+const GATE_SOURCE: &str = "embedder";
+/// Signal source of [`CopyTrader::inject_signal`]
+const INJECTED_SOURCE: &str = "injected";
+/// Swaps of watched wallets waiting to be queued
+const WALLET_SIGNAL_CAPACITY: usize = 64;
+/// Webhook and gRPC signals waiting to be queued, calls beyond are
+/// turned away
+const WEBHOOK_SIGNAL_CAPACITY: usize = 64;
+
+/// Everything the copier is configured with
+#[derive(Debug, Clone)]
+pub struct CopyTraderConfig {
+    /// Profile the copier runs for, when several run side by side
+    pub profile: Option<String>,
+    pub db: DbConfig,
+    pub telegram: TelegramConfig,
+    pub history: HistoryConfig,
+    pub trading: TradingConfig,
+    pub token_filter: TokenFilterConfig,
+    pub sell_retry: SellRetryConfig,
+    pub compute_budget: ComputeBudgetConfig,
+    pub price_feed: PriceFeedConfig,
+    pub venue_selection: Option<VenueSelectionConfig>,
+    pub jupiter: Option<JupiterSwapConfig>,
+    pub liquidity: Option<LiquidityMonitorConfig>,
+    pub housekeeping: Option<HousekeepingConfig>,
+    pub notifier: Option<NotifierConfig>,
+    pub discord: Option<DiscordConfig>,
+    pub event_webhook: Option<EventWebhookConfig>,
+    pub maintenance: Option<MaintenanceConfig>,
+    pub risk: Option<RiskConfig>,
+    pub loss_streak: Option<LossStreakConfig>,
+    pub balance_guard: Option<BalanceGuardConfig>,
+    pub price_stream: Option<PriceStreamConfig>,
+    pub usd_backfill: Option<UsdBackfillConfig>,
+    pub candles: Option<CandleConfig>,
+    pub slippage: Option<SlippageConfig>,
+    pub sweep: Option<SweepConfig>,
+    pub latency_slo: Option<LatencySloConfig>,
+    pub update_archive: Option<UpdateArchiveConfig>,
+    pub decision_journal: Option<DecisionJournalConfig>,
+    pub wallet_watch: Option<WalletWatchConfig>,
+    /// Served by the control API
+    pub wallet_webhook: Option<WalletWebhookConfig>,
+    /// Served by the control API
+    pub signal_webhook: Option<SignalWebhookConfig>,
+    /// The control API and the operators allowed to use it
+    pub api: Option<(ApiConfig, AdminConfig)>,
+    pub grpc: Option<(GrpcConfig, AdminConfig)>,
+    /// The command chat and the operators allowed to use it
+    pub commands: Option<(CommandChatConfig, AdminConfig)>,
+}
+
+/// Spawn `task`, logging the error it stops with. Tasks that bind a socket
+/// fail that way on a bad address.
+fn spawn_logged<F>(name: &'static str, task: F)
+where
+    F: Future<Output = Result<()>> + Send + 'static,
+{
+    tokio::spawn(async move {
+        if let Err(e) = task.await {
+            tracing::error!("{} stopped: {:?}", name, e);
+        }
+    });
+}
+
+impl fmt::Display for CopyTraderConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(profile) = &self.profile {
+            write!(f, "\nProfile: {}", profile)?;
+        }
+        write!(
+            f,
+            "{}{}{}{}{}{}{}{}",
+            self.db,
+            self.telegram,
+            self.history,
+            self.trading,
+            self.token_filter,
+            self.sell_retry,
+            self.compute_budget,
+            self.price_feed
+        )?;
+        if let Some(venue_selection) = &self.venue_selection {
+            write!(f, "{}", venue_selection)?;
+        }
+        if let Some(jupiter) = &self.jupiter {
+            write!(f, "{}", jupiter)?;
+        }
+        if let Some(liquidity) = &self.liquidity {
+            write!(f, "{}", liquidity)?;
+        }
+        if let Some(housekeeping) = &self.housekeeping {
+            write!(f, "{}", housekeeping)?;
+        }
+        if let Some(notifier) = &self.notifier {
+            write!(f, "{}", notifier)?;
+        }
+        if let Some(discord) = &self.discord {
+            write!(f, "{}", discord)?;
+        }
+        if let Some(event_webhook) = &self.event_webhook {
+            write!(f, "{}", event_webhook)?;
+        }
+        if let Some(maintenance) = &self.maintenance {
+            write!(f, "{}", maintenance)?;
+        }
+        if let Some(risk) = &self.risk {
+            write!(f, "{}", risk)?;
+        }
+        if let Some(loss_streak) = &self.loss_streak {
+            write!(f, "{}", loss_streak)?;
+        }
+        if let Some(balance_guard) = &self.balance_guard {
+            write!(f, "{}", balance_guard)?;
+        }
+        if let Some(price_stream) = &self.price_stream {
+            write!(f, "{}", price_stream)?;
+        }
+        if let Some(usd_backfill) = &self.usd_backfill {
+            write!(f, "{}", usd_backfill)?;
+        }
+        if let Some(candles) = &self.candles {
+            write!(f, "{}", candles)?;
+        }
+        if let Some(slippage) = &self.slippage {
+            write!(f, "{}", slippage)?;
+        }
+        if let Some(sweep) = &self.sweep {
+            write!(f, "{}", sweep)?;
+        }
+        if let Some(latency_slo) = &self.latency_slo {
+            write!(f, "{}", latency_slo)?;
+        }
+        if let Some(update_archive) = &self.update_archive {
+            write!(f, "{}", update_archive)?;
+        }
+        if let Some(decision_journal) = &self.decision_journal {
+            write!(f, "{}", decision_journal)?;
+        }
+        if let Some(wallet_watch) = &self.wallet_watch {
+            write!(f, "{}", wallet_watch)?;
+        }
+        if let Some(wallet_webhook) = &self.wallet_webhook {
+            write!(f, "{}", wallet_webhook)?;
+        }
+        if let Some(signal_webhook) = &self.signal_webhook {
+            write!(f, "{}", signal_webhook)?;
+        }
+        if let Some((api, admin)) = &self.api {
+            write!(f, "{}{}", api, admin)?;
+        }
+        if let Some((grpc, admin)) = &self.grpc {
+            write!(f, "{}", grpc)?;
+            if self.api.is_none() {
+                write!(f, "{}", admin)?;
+            }
+        }
+        if let Some((commands, admin)) = &self.commands {
+            write!(f, "{}", commands)?;
+            if self.api.is_none() && self.grpc.is_none() {
+                write!(f, "{}", admin)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CopyTraderConfig {
+    pub fn from_env() -> Result<Self> {
+        let api = match ApiConfig::from_env()? {
+            Some(api) => {
+                let admin = AdminConfig::from_env()?.ok_or_else(|| {
+                    anyhow!(
+                        "API_BIND_ADDR is set but ADMIN_API_TOKENS is not, the API would reject everyone"
+                    )
+                })?;
+                Some((api, admin))
+            }
+            None => None,
+        };
+        let grpc = match GrpcConfig::from_env()? {
+            Some(grpc) => {
+                let admin = AdminConfig::from_env()?.ok_or_else(|| {
+                    anyhow!(
+                        "GRPC_BIND_ADDR is set but ADMIN_API_TOKENS is not, the gRPC control plane would reject everyone"
+                    )
+                })?;
+                Some((grpc, admin))
+            }
+            None => None,
+        };
+        let commands = match CommandChatConfig::from_env()? {
+            Some(commands) => {
+                let admin = AdminConfig::from_env()?
+                    .filter(|admin| !admin.telegram_users.is_empty())
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "COMMAND_CHAT is set but ADMIN_TELEGRAM_USERS is not, every command would be ignored"
+                        )
+                    })?;
+                Some((commands, admin))
+            }
+            None => None,
+        };
+        let wallet_webhook = WalletWebhookConfig::from_env()?;
+        if wallet_webhook.is_some() && api.is_none() {
+            return Err(anyhow!(
+                "HELIUS_WEBHOOK_AUTH is set but API_BIND_ADDR is not, the webhook is served by the API"
+            ));
+        }
+        let signal_webhook = SignalWebhookConfig::from_env()?;
+        if signal_webhook.is_some() && api.is_none() {
+            return Err(anyhow!(
+                "SIGNAL_WEBHOOK_KEY is set but API_BIND_ADDR is not, the webhook is served by the API"
+            ));
+        }
+        Ok(Self {
+            profile: None,
+            db: DbConfig::from_env()?,
+            telegram: TelegramConfig::from_env()?,
+            history: HistoryConfig::from_env()?,
+            trading: TradingConfig::from_env()?,
+            token_filter: TokenFilterConfig::from_env()?,
+            sell_retry: SellRetryConfig::from_env()?,
+            compute_budget: ComputeBudgetConfig::from_env()?,
+            price_feed: PriceFeedConfig::from_env()?,
+            venue_selection: VenueSelectionConfig::from_env()?,
+            jupiter: JupiterSwapConfig::from_env()?,
+            liquidity: LiquidityMonitorConfig::from_env()?,
+            housekeeping: HousekeepingConfig::from_env()?,
+            notifier: NotifierConfig::from_env()?,
+            discord: DiscordConfig::from_env()?,
+            event_webhook: EventWebhookConfig::from_env()?,
+            maintenance: MaintenanceConfig::from_env()?,
+            risk: RiskConfig::from_env()?,
+            loss_streak: LossStreakConfig::from_env()?,
+            balance_guard: BalanceGuardConfig::from_env()?,
+            price_stream: PriceStreamConfig::from_env()?,
+            usd_backfill: UsdBackfillConfig::from_env()?,
+            candles: CandleConfig::from_env()?,
+            slippage: SlippageConfig::from_env()?,
+            sweep: SweepConfig::from_env()?,
+            latency_slo: LatencySloConfig::from_env()?,
+            update_archive: UpdateArchiveConfig::from_env()?,
+            decision_journal: DecisionJournalConfig::from_env()?,
+            wallet_watch: WalletWatchConfig::from_env()?,
+            wallet_webhook,
+            signal_webhook,
+            api,
+            grpc,
+            commands,
+        })
+    }
+}
+
+/// The copier as a library: trades the signals of a Telegram group with
+/// [`start`](Self::start), or signals handed to it with
+/// [`inject_signal`](Self::inject_signal) without Telegram at all. Trades
+/// are signed by the signer of the calling [`SignerContext`].
+pub struct CopyTrader {
+    config: CopyTraderConfig,
+    db: Database,
+    storage: Arc<dyn Storage>,
+    state: Collection<CopierState>,
+    history: Collection<HistoryProgress>,
+    failures: Collection<TradeFailure>,
+    unparsed: UnparsedMessages,
+    strategies: StrategyManager,
+    /// `config.trading` as reloaded from the config file
+    trading: LiveTradingConfig,
+    token_filters: TokenFilterSource,
+    cooldowns: TradeCooldowns,
+    signal_dedup: SignalDedup,
+    trader: Arc<MemeTrader>,
+    tasks: Arc<TaskManager>,
+    login: Arc<TelegramLogin>,
+    flood_waits: Arc<FloodWaitStats>,
+    /// Operators of the control API and the command chat
+    access: Option<Arc<AccessControl>>,
+}
+
+impl CopyTrader {
+    /// Connect to MongoDB, load the strategies and open positions and set up
+    /// the trader. Telegram is only connected by `start`.
+    pub async fn new(config: CopyTraderConfig) -> Result<Self> {
+        Self::with_price_cache(config, Arc::default()).await
+    }
+
+    /// Like [`new`](Self::new), pricing through `price_cache` shared with
+    /// other copiers of the process
+    pub async fn with_price_cache(
+        config: CopyTraderConfig,
+        price_cache: Arc<PriceCache>,
+    ) -> Result<Self> {
+        let client = mongodb::Client::with_uri_str(&config.db.mongodb_uri).await?;
+        let db = client.database(&config.db.db_name);
+        let storage = open_storage(&config.db, &db).await?;
+        let strategies = StrategyManager::load(Arc::clone(&storage)).await?;
+        tracing::info!("Strategies loaded: {:?}", strategies.all().len());
+
+        let cooldowns = TradeCooldowns::new(Arc::clone(&storage));
+        let signal_dedup = SignalDedup::new(db.collection("seen_signals"));
+        signal_dedup.setup_indexes().await?;
+        let unparsed = UnparsedMessages::new(db.collection("unparsed_messages"));
+        unparsed.setup_indexes().await?;
+
+        let intents = ExecutionIntents::new(db.collection("execution_intents"));
+        intents.setup_indexes().await?;
+
+        let mut executions = ExecutionLog::new(db.collection("executions"));
+        executions.setup_indexes().await?;
+        if let Some(profile) = &config.profile {
+            executions = executions.with_profile(profile.clone());
+        }
+        if let Some(latency_slo) = config.latency_slo.clone() {
+            executions = executions.with_latency_slo(latency_slo);
+        }
+
+        let mut trader = MemeTrader::new(Arc::clone(&storage))
+            .with_price_cache(price_cache)
+            .with_capital(CapitalStore::new(db.collection("capital")))
+            .with_bot_state(BotStateStore::new(db.collection("bot_state")))
+            .with_intents(intents)
+            .with_executions(executions)
+            .with_sell_retry(config.sell_retry.clone())
+            .with_compute_budget(config.compute_budget.clone())
+            .with_provider(config.telegram.group_name.clone());
+        if let Some(venue_selection_config) = config.venue_selection.clone() {
+            trader = trader.with_venue_selection(venue_selection_config);
+        }
+        if let Some(jupiter_config) = config.jupiter.clone() {
+            trader = trader.with_jupiter(jupiter_config);
+        }
+        if let Some(slippage_config) = config.slippage.clone() {
+            let slippage = SlippageLog::new(db.collection("slippage"), slippage_config);
+            slippage.setup_indexes().await?;
+            trader = trader.with_slippage_log(slippage);
+        }
+        if let Some(loss_streak_config) = config.loss_streak.clone() {
+            trader = trader.with_loss_streaks(LossStreakGuard::new(
+                db.collection("strategy_streaks"),
+                loss_streak_config,
+            ));
+        }
+        if let Some(balance_guard_config) = config.balance_guard.clone() {
+            trader = trader.with_balance_guard(BalanceGuard::new(
+                balance_guard_config,
+                config.trading.position_size_sol,
+            ));
+        }
+        if let Some(journal_config) = &config.decision_journal {
+            tracing::info!("Journaling decisions to {}", journal_config.path);
+            trader = trader.with_journal(Arc::new(DecisionJournal::open(&journal_config.path)?));
+        }
+        if let Some(wallet_webhook_config) = &config.wallet_webhook {
+            trader = trader.with_wallet_balances(Arc::new(WalletBalances::new(
+                Duration::from_secs(wallet_webhook_config.balance_max_age_secs),
+            )));
+        }
+        if let Some(notifier_config) = config.notifier.clone() {
+            trader = trader.with_notifier(TelegramNotifier::new(notifier_config)?);
+        }
+        if let Some(discord_config) = config.discord.clone() {
+            trader = trader.with_notifier(DiscordNotifier::new(discord_config)?);
+        }
+        if let Some(event_webhook_config) = config.event_webhook.clone() {
+            trader = trader.with_webhooks(WebhookNotifier::new(event_webhook_config)?);
+        }
+        // Pool prices come from the stream while it follows the position
+        price::install(PriceAggregator::new(
+            &config.price_feed,
+            Some(trader.price_cache()),
+        ));
+
+        Ok(Self {
+            token_filters: TokenFilterSource::new(
+                config.token_filter.clone(),
+                db.collection("token_filters"),
+            ),
+            storage,
+            state: db.collection("copier_state"),
+            history: db.collection("history_progress"),
+            failures: db.collection("trade_failures"),
+            unparsed,
+            strategies,
+            trading: LiveTradingConfig::new(config.trading.clone()),
+            cooldowns,
+            signal_dedup,
+            trader: Arc::new(trader),
+            tasks: Arc::new(TaskManager::new(
+                config.trading.max_concurrent_trades,
+                config.trading.trade_queue_capacity,
+            )),
+            login: Arc::new(
+                TelegramLogin::default().with_password(config.telegram.login.password.clone()),
+            ),
+            flood_waits: Arc::default(),
+            access: config
+                .api
+                .as_ref()
+                .map(|(_, admin)| admin)
+                .or(config.grpc.as_ref().map(|(_, admin)| admin))
+                .or(config.commands.as_ref().map(|(_, admin)| admin))
+                .map(|admin| {
+                    Arc::new(AccessControl::new(
+                        admin.clone(),
+                        db.collection::<AuditEntry>("admin_audit"),
+                    ))
+                }),
+            config,
+            db,
+        })
+    }
+
+    pub fn trader(&self) -> &Arc<MemeTrader> {
+        &self.trader
+    }
+
+    /// Login steps for a revoked Telegram session, for embedders without the
+    /// control API
+    pub fn telegram_login(&self) -> &Arc<TelegramLogin> {
+        &self.login
+    }
+
+    /// Trades of Telegram signals running or waiting for a slot
+    pub fn task_metrics(&self) -> TaskMetrics {
+        self.tasks.metrics()
+    }
+
+    /// Call `hook` with every trade event, next to the notifiers
+    pub fn on_event(&self, hook: impl Fn(&TradeEvent) + Send + Sync + 'static) {
+        self.trader.add_event_hook(Arc::new(hook));
+    }
+
+    /// Stop new buys, open positions keep being managed. Returns `false` if
+    /// already paused by the embedder.
+    pub fn pause(&self, reason: impl Into<String>) -> bool {
+        let reason = reason.into();
+        let changed = self.trader.buy_gate().pause(GATE_SOURCE, reason.clone());
+        if changed {
+            self.trader.notify(TradeEvent::BuysPaused {
+                reason,
+                breaker: None,
+            });
+        }
+        changed
+    }
+
+    /// Lift the pause of `pause`, other sources may still hold theirs
+    pub fn resume(&self) -> bool {
+        let changed = self.trader.buy_gate().resume(GATE_SOURCE);
+        if changed {
+            self.trader.notify(TradeEvent::BuysResumed {
+                reason: "resumed by the embedder".to_string(),
+            });
+        }
+        changed
+    }
+
+    /// Trade `trade` as if it was just posted, through the same checks as a
+    /// Telegram signal. Nothing is stored in the trades collection.
+    pub async fn inject_signal(&self, trade: Trade) -> Result<()> {
+        self.inject_signal_from(INJECTED_SOURCE, trade).await
+    }
+
+    /// [`inject_signal`](Self::inject_signal) for a signal read from
+    /// `source`, a mirror of the group for instance. The same signal from
+    /// the group and its mirrors is traded once, whichever comes first.
+    pub async fn inject_signal_from(&self, source: &str, trade: Trade) -> Result<()> {
+        if !self.trading.load().trade_on {
+            tracing::info!("Trading is off, ignoring injected {:?}", trade);
+            return Ok(());
+        }
+        let now = Utc::now();
+        self.trader.signal_received(source, &trade);
+        let mut entry = JournalEntry::new(None);
+        entry.parsed(&trade);
+        journaled(self.trader.journal().cloned(), entry, async {
+            if !claim_signal(&self.signal_dedup, &trade, source).await? {
+                return Ok(());
+            }
+            handle_trade(
+                trade,
+                None,
+                now,
+                now,
+                self.cooldowns.clone(),
+                Arc::clone(&self.trader),
+                &self.trading.load(),
+                &self.strategies,
+                &*self.storage,
+                &self.failures,
+                &self.token_filters,
+            )
+            .await
+        })
+        .await
+    }
+
+    /// Catch up on the group, start the background monitors and copy new
+    /// signals until `shutdown` flips
+    pub async fn start(&self, mut shutdown: watch::Receiver<bool>) -> Result<()> {
+        let config = &self.config;
+        let (webhook_signals, webhook_receiver) = self.webhook_channel();
+        let mut monitors = Some(webhook_signals);
+
+        let client = connect_client(&config.telegram).await?;
+        if !client.is_authorized().await? {
+            if config.telegram.login.headless {
+                // The login endpoints are served by the API
+                if let Some(webhook_signals) = monitors.take() {
+                    self.spawn_monitors(&shutdown, webhook_signals).await;
+                }
+                if !self.headless_login(&client, &mut shutdown).await? {
+                    return Ok(());
+                }
+            } else {
+                tracing::info!("First time setup - need to log in!");
+                handle_login(&client).await?;
+            }
+        }
+        tracing::info!("Connected!");
+        let result = self
+            .follow(client.clone(), monitors, webhook_receiver, shutdown)
+            .await;
+
+        // Persist the session so the next start resumes without logging in again
+        client.session().save_to_file(SESSION_FILE)?;
+        tracing::info!("Telegram session saved, shutdown complete");
+
+        result
+    }
+
+    /// Like [`start`](Self::start) with a client logged in already, shared
+    /// with other copiers of the process. The session is left to the
+    /// owner of the client to save.
+    pub async fn start_with_client(
+        &self,
+        client: Client,
+        shutdown: watch::Receiver<bool>,
+    ) -> Result<()> {
+        let (webhook_signals, webhook_receiver) = self.webhook_channel();
+        self.follow(client, Some(webhook_signals), webhook_receiver, shutdown)
+            .await
+    }
+
+    /// Channel of the signals posted to the API or submitted over gRPC with
+    /// their source, when either takes signals
+    #[allow(clippy::type_complexity)]
+    fn webhook_channel(
+        &self,
+    ) -> (
+        Option<mpsc::Sender<(&'static str, Trade)>>,
+        Option<mpsc::Receiver<(&'static str, Trade)>>,
+    ) {
+        if self.config.signal_webhook.is_none() && self.config.grpc.is_none() {
+            return (None, None);
+        }
+        let (signals, receiver) = mpsc::channel(WEBHOOK_SIGNAL_CAPACITY);
+        (Some(signals), Some(receiver))
+    }
+
+    /// Copy the signals of the group with the logged in `client` until
+    /// `shutdown` flips. `monitors` holds the sender of the API's signals
+    /// until the monitors were spawned.
+    async fn follow(
+        &self,
+        client: Client,
+        mut monitors: Option<Option<mpsc::Sender<(&'static str, Trade)>>>,
+        webhook_receiver: Option<mpsc::Receiver<(&'static str, Trade)>>,
+        mut shutdown: watch::Receiver<bool>,
+    ) -> Result<()> {
+        let config = &self.config;
+        let client = FloodAwareClient::new(client, Arc::clone(&self.flood_waits))
+            .with_shutdown(shutdown.clone());
+        let chat = find_chat(&client, &config.telegram.group_name.parse()?).await?;
+        if let Some(((commands, _), access)) = config.commands.as_ref().zip(self.access.clone()) {
+            let command_chat = find_chat(&client, &commands.chat.parse()?).await?;
+            let router = CommandRouter::new(
+                Arc::clone(&self.trader),
+                access,
+                self.trading.clone(),
+                config.trading.tip_lamports,
+            );
+            let signer = SignerContext::current().await;
+            tokio::spawn(SignerContext::with_signer(
+                signer,
+                run_command_chat(
+                    client.clone(),
+                    command_chat,
+                    router,
+                    Duration::from_secs(config.telegram.pool_frequency),
+                    shutdown.clone(),
+                ),
+            ));
+        }
+
+        // Before the history is reprocessed, so a buy interrupted by the
+        // last run is known to have landed or not
+        if let Some(intents) = self.trader.intents() {
+            reconcile_pending(intents, &self.trader).await?;
+        }
+
+        let last_message_id = resume_message_id(&*self.storage, &self.state, &chat).await?;
+        tracing::info!("Starting from message ID: {}", last_message_id);
+
+        // Process historical messages first
+        let gap_closes = process_historical_messages(
+            &client,
+            &*self.storage,
+            &self.state,
+            &self.history,
+            &chat,
+            config.telegram.topic_id,
+            last_message_id,
+            &config.history,
+        )
+        .await?;
+
+        if let Some(webhook_signals) = monitors.take() {
+            self.spawn_monitors(&shutdown, webhook_signals).await;
+        }
+
+        // Exit positions whose close signal was posted while we were down
+        replay_gap_closes(
+            gap_closes,
+            self.cooldowns.clone(),
+            Arc::clone(&self.trader),
+            &self.trading.load(),
+            &self.strategies,
+            &*self.storage,
+            &self.failures,
+        )
+        .await;
+
+        // Then start listening for new messages
+
+        if let Some(price_stream_config) = &config.price_stream {
+            tokio::spawn(run_price_stream(
+                Arc::clone(&self.trader),
+                price_stream_config.ws_url.clone(),
+                shutdown.clone(),
+            ));
+
+            // Take profit ladders and trailing stops follow the streamed prices
+            let trader = Arc::clone(&self.trader);
+            let strategies = self.strategies.clone();
+            let tip_lamports = config.trading.tip_lamports;
+            let shutdown = shutdown.clone();
+            let signer = SignerContext::current().await;
+            tokio::spawn(SignerContext::with_signer(signer, async move {
+                run_trailing_stop(trader, strategies, tip_lamports, shutdown).await
+            }));
+        }
+
+        let recorder = match &config.update_archive {
+            Some(archive_config) => {
+                let recorder = Arc::new(UpdateRecorder::open(&archive_config.path)?);
+                tokio::spawn(run_update_recorder(
+                    Client::clone(&client),
+                    Arc::clone(&recorder),
+                    shutdown.clone(),
+                ));
+                Some(recorder)
+            }
+            None => None,
+        };
+
+        let wallet_signals = match &config.wallet_watch {
+            Some(watch_config) => {
+                let (signals, receiver) = mpsc::channel(WALLET_SIGNAL_CAPACITY);
+                tokio::spawn(run_wallet_watcher(
+                    watch_config.ws_url.clone(),
+                    watch_config.wallets.clone(),
+                    signals,
+                    shutdown.clone(),
+                ));
+                Some(receiver)
+            }
+            None => None,
+        };
+
+        let listen = async {
+            loop {
+                let result = listen_for_new_messages(
+                    &client,
+                    &self.storage,
+                    &self.unparsed,
+                    &self.state,
+                    &self.failures,
+                    &chat,
+                    &self.trading,
+                    &config.telegram,
+                    self.cooldowns.clone(),
+                    Arc::clone(&self.trader),
+                    &self.strategies,
+                    &self.token_filters,
+                    &self.signal_dedup,
+                    &self.tasks,
+                    recorder.as_deref(),
+                    &mut shutdown,
+                )
+                .await;
+                match result {
+                    Err(e) if is_session_revoked(&e) => {
+                        if !self.wait_for_relogin(&client, e, &mut shutdown).await? {
+                            break Ok(());
+                        }
+                    }
+                    result => break result,
+                }
+            }
+        };
+        tokio::select! {
+            result = listen => result,
+            () = self.trade_wallet_signals(wallet_signals) => Ok(()),
+            () = self.trade_webhook_signals(webhook_receiver) => Ok(()),
+        }
+    }
+
+    /// Queue the swaps of the watched wallets like the signals of the group.
+    /// Never returns, without a wallet watcher there is nothing to wait for.
+    async fn trade_wallet_signals(&self, signals: Option<mpsc::Receiver<WalletSignal>>) {
+        if let Some(mut signals) = signals {
+            while let Some(signal) = signals.recv().await {
+                self.queue_signal(&signal.source, signal.trade).await;
+            }
+        }
+        future::pending().await
+    }
+
+    /// Queue the signals posted to `POST /signal` or submitted over gRPC
+    /// like the signals of the group. Never returns, like
+    /// [`trade_wallet_signals`](Self::trade_wallet_signals).
+    async fn trade_webhook_signals(&self, signals: Option<mpsc::Receiver<(&'static str, Trade)>>) {
+        if let Some(mut signals) = signals {
+            while let Some((source, trade)) = signals.recv().await {
+                self.queue_signal(source, trade).await;
+            }
+        }
+        future::pending().await
+    }
+
+    /// Put `trade` read from `source` on the trade queue, through the same
+    /// dedup, checks and execution as the signals of the group
+    async fn queue_signal(&self, source: &str, trade: Trade) {
+        if !self.trading.load().trade_on {
+            tracing::info!("Trading is off, ignoring {:?} from {}", trade, source);
+            return;
+        }
+        let mut entry = JournalEntry::new(None);
+        entry.parsed(&trade);
+        let now = Utc::now();
+        spawn_trade(
+            trade,
+            source,
+            entry,
+            now,
+            now,
+            &self.storage,
+            &self.failures,
+            &self.trading.load(),
+            &self.cooldowns,
+            &self.trader,
+            &self.strategies,
+            &self.token_filters,
+            &self.signal_dedup,
+            &self.tasks,
+        )
+        .await;
+    }
+
+    /// Feed the polled messages of an archive written with `RAW_UPDATE_LOG`
+    /// through the listener, poll by poll, to reproduce how they were
+    /// handled. Signals are stored and, with trading on, traded; the
+    /// checkpoint is left alone.
+    pub async fn replay(&self, path: &str) -> Result<()> {
+        let updates = read_archive(path)?;
+        tracing::info!("Replaying {} archived updates from {}", updates.len(), path);
+        let (mut messages, mut polls) = (0, 0);
+        for update in updates {
+            match update {
+                ArchivedUpdate::Message {
+                    chat_id, message, ..
+                } => {
+                    tracing::info!("Message {}: {}", message.id, message.message);
+                    handle_new_message(
+                        chat_id,
+                        &message,
+                        &self.storage,
+                        &self.unparsed,
+                        &self.failures,
+                        &self.trading.load(),
+                        &self.config.telegram,
+                        &self.cooldowns,
+                        &self.trader,
+                        &self.strategies,
+                        &self.token_filters,
+                        &self.signal_dedup,
+                        &self.tasks,
+                    )
+                    .await;
+                    messages += 1;
+                }
+                ArchivedUpdate::Poll {
+                    at,
+                    last_message_id,
+                    newest_message_id,
+                    ..
+                } => {
+                    self.tasks.wait_idle().await;
+                    tracing::info!(
+                        "Poll of {} done, messages {} to {}",
+                        at,
+                        last_message_id + 1,
+                        newest_message_id
+                    );
+                    polls += 1;
+                }
+                ArchivedUpdate::Update { at, update } => {
+                    tracing::debug!("Update at {}: {:?}", at, update);
+                }
+            }
+        }
+        self.tasks.wait_idle().await;
+        tracing::info!("Replayed {} messages of {} polls", messages, polls);
+        Ok(())
+    }
+
+    /// Log in a session that never was without a terminal: the code is
+    /// requested for `TG_PHONE` or through the API, and read from
+    /// `TG_LOGIN_CODE_FILE` or the API. Returns `false` on shutdown.
+    async fn headless_login(
+        &self,
+        client: &Client,
+        shutdown: &mut watch::Receiver<bool>,
+    ) -> Result<bool> {
+        let login_config = &self.config.telegram.login;
+        if self.config.api.is_none() && login_config.code_file.is_none() {
+            return Err(anyhow!(
+                "Telegram session is not logged in, a headless login needs API_BIND_ADDR or TG_LOGIN_CODE_FILE, or run `copy-trade login` first"
+            ));
+        }
+        tracing::warn!("Telegram session is not logged in, waiting for a headless login");
+        self.login.revoked(client.clone()).await;
+        if let Some(phone) = &login_config.phone {
+            match self.login.request_code(phone).await {
+                Ok(_) => tracing::info!("Login code sent to the Telegram apps of {}", phone),
+                Err(e) => tracing::error!("Failed to request a login code: {:?}", e),
+            }
+        }
+
+        let signed_in = self.login.wait_signed_in();
+        let code_file = async {
+            if let Some(path) = &login_config.code_file {
+                self.login.poll_code_file(path).await;
+            }
+        };
+        tokio::pin!(signed_in, code_file);
+        // Polling the file stops at a sign in or a password to wait for,
+        // either way signing in is reported by `wait_signed_in`
+        let mut polling = true;
+        loop {
+            tokio::select! {
+                biased;
+                _ = shutdown.changed() => return Ok(false),
+                _ = &mut signed_in => break,
+                _ = &mut code_file, if polling => polling = false,
+            }
+        }
+        client.session().save_to_file(SESSION_FILE)?;
+        tracing::info!("Telegram session logged in");
+        Ok(true)
+    }
+
+    /// Hold signal copying until an operator logged the revoked session in
+    /// again, monitors keep running meanwhile. Returns `false` on shutdown.
+    async fn wait_for_relogin(
+        &self,
+        client: &Client,
+        error: anyhow::Error,
+        shutdown: &mut watch::Receiver<bool>,
+    ) -> Result<bool> {
+        let group = self.config.telegram.group_name.clone();
+        tracing::error!(
+            "Telegram session revoked, waiting for a new login: {:?}",
+            error
+        );
+        self.trader.notify(TradeEvent::SessionRevoked {
+            group: group.clone(),
+            error: error.to_string(),
+        });
+        self.login.revoked(client.clone()).await;
+
+        tokio::select! {
+            biased;
+            _ = shutdown.changed() => Ok(false),
+            _ = self.login.wait_signed_in() => {
+                client.session().save_to_file(SESSION_FILE)?;
+                tracing::info!("Telegram session restored");
+                self.trader.notify(TradeEvent::SessionRestored { group });
+                Ok(true)
+            }
+        }
+    }
+
+    /// Monitors and the control API, they run until `shutdown` flips.
+    /// Signals posted to the API or submitted over gRPC go to
+    /// `webhook_signals`.
+    async fn spawn_monitors(
+        &self,
+        shutdown: &watch::Receiver<bool>,
+        webhook_signals: Option<mpsc::Sender<(&'static str, Trade)>>,
+    ) {
+        let config = &self.config;
+        if config.trading.strategy_refresh_secs > 0 {
+            tokio::spawn(run_strategy_refresh(
+                self.strategies.clone(),
+                Duration::from_secs(config.trading.strategy_refresh_secs),
+                shutdown.clone(),
+            ));
+        }
+
+        if let Some(liquidity_config) = config.liquidity.clone() {
+            let trader = Arc::clone(&self.trader);
+            let tip_lamports = config.trading.tip_lamports;
+            let shutdown = shutdown.clone();
+            let signer = SignerContext::current().await;
+            tokio::spawn(SignerContext::with_signer(signer, async move {
+                run_liquidity_monitor(trader, liquidity_config, tip_lamports, shutdown).await
+            }));
+        }
+
+        if let Some(housekeeping_config) = config.housekeeping.clone() {
+            let signer = SignerContext::current().await;
+            tokio::spawn(SignerContext::with_signer(
+                signer,
+                run_housekeeping(
+                    Arc::clone(&self.trader),
+                    Duration::from_secs(housekeeping_config.interval_secs),
+                    config.trading.tip_lamports,
+                    shutdown.clone(),
+                ),
+            ));
+        }
+
+        if let Some(sweep_config) = config.sweep.clone() {
+            let signer = SignerContext::current().await;
+            spawn_logged(
+                "Profit sweeper",
+                SignerContext::with_signer(
+                    signer,
+                    run_profit_sweeper(
+                        Arc::clone(&self.trader),
+                        SweepLog::new(self.db.collection("sweeps")),
+                        sweep_config,
+                        config.trading.tip_lamports,
+                        shutdown.clone(),
+                    ),
+                ),
+            );
+        }
+
+        if let Some(maintenance_config) = config.maintenance.clone() {
+            tokio::spawn(run_maintenance_scheduler(
+                Arc::clone(&self.trader),
+                maintenance_config,
+                shutdown.clone(),
+            ));
+        }
+
+        if let Some(risk_config) = config.risk.clone() {
+            tokio::spawn(run_daily_loss_breaker(
+                Arc::clone(&self.trader),
+                risk_config,
+                shutdown.clone(),
+            ));
+        }
+
+        if let Some(usd_backfill_config) = config.usd_backfill.clone() {
+            tokio::spawn(run_usd_backfill(
+                ExecutionLog::new(self.db.collection("executions")),
+                usd_backfill_config,
+                shutdown.clone(),
+            ));
+        }
+
+        if let Some(candle_config) = config.candles.clone() {
+            tokio::spawn(run_candle_recorder(
+                Arc::clone(&self.trader),
+                CandleStore::new(self.db.collection("candles")),
+                candle_config,
+                shutdown.clone(),
+            ));
+        }
+
+        if let Some(latency_slo_config) = config.latency_slo.clone() {
+            tokio::spawn(run_latency_digest(
+                Arc::clone(&self.trader),
+                ExecutionLog::new(self.db.collection("executions")),
+                latency_slo_config,
+                shutdown.clone(),
+            ));
+        }
+
+        #[cfg(feature = "grpc")]
+        if let Some((((grpc_config, _), access), signals)) = config
+            .grpc
+            .clone()
+            .zip(self.access.clone())
+            .zip(webhook_signals.clone())
+        {
+            let signer = SignerContext::current().await;
+            spawn_logged(
+                "gRPC server",
+                SignerContext::with_signer(
+                    signer,
+                    run_grpc_server(
+                        grpc_config.bind_addr,
+                        Arc::clone(&self.trader),
+                        access,
+                        signals,
+                        config.trading.tip_lamports,
+                        shutdown.clone(),
+                    ),
+                ),
+            );
+        }
+
+        if let Some(((api_config, _), access)) = config.api.clone().zip(self.access.clone()) {
+            let trader = Arc::clone(&self.trader);
+            let storage = Arc::clone(&self.storage);
+            let tasks = Arc::clone(&self.tasks);
+            let latency_slo = config.latency_slo.clone().map(|latency_slo| {
+                (
+                    ExecutionLog::new(self.db.collection("executions")),
+                    latency_slo,
+                )
+            });
+            let login = Arc::clone(&self.login);
+            let flood_waits = Arc::clone(&self.flood_waits);
+            let tip_lamports = config.trading.tip_lamports;
+            let wallet_webhook_auth = config
+                .wallet_webhook
+                .as_ref()
+                .map(|wallet_webhook| wallet_webhook.auth_header.clone());
+            let signal_webhook = config
+                .signal_webhook
+                .as_ref()
+                .zip(webhook_signals)
+                .map(|(signal_webhook, signals)| (signal_webhook.api_key.clone(), signals));
+            let shutdown = shutdown.clone();
+            let signer = SignerContext::current().await;
+            spawn_logged(
+                "API server",
+                SignerContext::with_signer(signer, async move {
+                    run_api_server(
+                        api_config.bind_addr,
+                        trader,
+                        access,
+                        storage,
+                        tasks,
+                        latency_slo,
+                        login,
+                        flood_waits,
+                        tip_lamports,
+                        wallet_webhook_auth,
+                        signal_webhook,
+                        shutdown,
+                    )
+                    .await
+                }),
+            );
+        }
+
+        if let Some(file) = config_file::loaded().filter(|file| file.reload_secs > 0) {
+            tokio::spawn(run_config_reload(
+                file,
+                self.trading.clone(),
+                config.profile.clone(),
+                shutdown.clone(),
+            ));
+        }
+
+        tokio::spawn(run_exposure_monitor(
+            Arc::clone(&self.trader),
+            shutdown.clone(),
+        ));
+    }
+}
@@ -1,8 +1,16 @@
 use anyhow::Result;
+use bson::oid::ObjectId;
 use chrono::{DateTime, Utc};
-use mongodb::{bson::doc, options::IndexOptions, Collection, IndexModel};
+use mongodb::{
+    bson::doc,
+    options::{ReplaceOptions, UpdateOptions},
+    Collection,
+};
 use serde::{Deserialize, Serialize};
 
+use crate::solana::transaction::SimulationError;
+use crate::storage::{DuplicateTrade, Storage};
+use crate::tg_copy::active_trade::RealizedPnl;
 use crate::tg_copy::parse_trade::Trade;
 use crate::tg_copy::strategy::Strategy;
 
@@ -29,28 +37,56 @@ pub struct TradeDocument {
     pub market_cap: Option<f64>,  // used for Open trades
     pub entry_price: Option<f64>, // used for Close trades
     pub exit_price: Option<f64>,  // used for Close trades
-    pub profit_pct: Option<f64>,  // used for Close trades
+    pub profit_pct: Option<f64>,  // used for Close trades, as reported by the provider
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strategy_ref: Option<ObjectId>, // `_id` of the matching strategy document
+    /// Close trades: PnL of our sell against our own entry, set once it executed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub our_profit_pct: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub our_pnl_lamports: Option<i64>,
 }
 
-pub async fn setup_indexes(collection: &Collection<TradeDocument>) -> Result<()> {
-    // Create indexes
-    let message_id_index = IndexModel::builder()
-        .keys(doc! { "message_id": 1 })
-        .options(IndexOptions::builder().unique(true).build())
-        .build();
-
-    let strategy_token_index = IndexModel::builder()
-        .keys(doc! { "strategy": 1, "token": 1 })
-        .build();
+/// Per-chat checkpoint of the last Telegram message the copier has seen,
+/// including messages that did not parse into a trade.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CopierState {
+    #[serde(rename = "_id")]
+    pub chat_id: i64,
+    pub last_message_id: i64,
+    pub updated_at: i64,
+}
 
-    collection.create_index(message_id_index, None).await?;
-    collection.create_index(strategy_token_index, None).await?;
+/// History download of a chat cut short, resumed from `offset_id` by the
+/// next run. Messages are downloaded newest first.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryProgress {
+    #[serde(rename = "_id")]
+    pub chat_id: i64,
+    /// Newest message of the download
+    pub newest_message_id: i64,
+    /// Oldest message handled so far, the download continues below it
+    pub offset_id: i64,
+    /// Messages up to this one were handled before the download started
+    pub floor_message_id: i64,
+    pub processed: u64,
+    pub updated_at: i64,
+}
 
-    Ok(())
+/// A buy or sell that could not be executed
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TradeFailure {
+    pub date: DateTime<Utc>,
+    pub strategy: String,
+    pub token: String,
+    pub contract_address: String,
+    pub trade_type: TradeType,
+    pub reason: String,
+    pub logs: Vec<String>, // program logs when the failure came from simulation
 }
 
 pub async fn store_trade_db(
-    collection: &Collection<TradeDocument>,
+    storage: &dyn Storage,
     trade: Trade,
     message_id: i64,
     original_message: String,
@@ -74,6 +110,9 @@ pub async fn store_trade_db(
             entry_price: None,
             exit_price: None,
             profit_pct: None,
+            strategy_ref: None,
+            our_profit_pct: None,
+            our_pnl_lamports: None,
         },
         Trade::Close(close) => TradeDocument {
             message_id,
@@ -92,6 +131,9 @@ pub async fn store_trade_db(
             entry_price: Some(close.entry_price),
             exit_price: Some(close.exit_price),
             profit_pct: Some(close.profit_pct),
+            strategy_ref: None,
+            our_profit_pct: None,
+            our_pnl_lamports: None,
         },
     };
     tracing::info!(
@@ -100,28 +142,114 @@ pub async fn store_trade_db(
         doc.trade_type,
         doc.token,
     );
-    collection.insert_one(doc, None).await?;
-    Ok(())
+    storage.insert_trade(&doc).await
 }
 
-pub async fn get_last_message_id(collection: &Collection<TradeDocument>) -> Result<Option<i64>> {
-    let options = mongodb::options::FindOneOptions::builder()
-        .sort(doc! { "message_id": -1 })
-        .build();
-
-    let doc = collection.find_one(None, Some(options)).await?;
-    Ok(doc.map(|d| d.message_id))
+/// Whether `e` is the storage rejecting a message stored before
+pub fn is_duplicate_trade(e: &anyhow::Error) -> bool {
+    e.is::<DuplicateTrade>()
 }
 
-pub async fn load_strategies(collection: &Collection<Strategy>) -> Result<Vec<Strategy>> {
-    let mut cursor = collection.find(None, None).await?;
-    let mut strategies = Vec::new();
+/// Put the PnL of our sell next to the provider's on the close record
+pub async fn record_our_pnl(
+    storage: &dyn Storage,
+    message_id: i64,
+    pnl: &RealizedPnl,
+) -> Result<()> {
+    storage.set_our_pnl(message_id, pnl).await
+}
 
-    while cursor.advance().await? {
-        let strategy = cursor.deserialize_current()?;
-        strategies.push(strategy);
-    }
+pub async fn get_last_message_id(storage: &dyn Storage) -> Result<Option<i64>> {
+    storage.last_message_id().await
+}
 
+pub async fn load_strategies(storage: &dyn Storage) -> Result<Vec<Strategy>> {
+    let strategies = storage.load_strategies().await?;
     tracing::info!("Loaded {} strategies from database", strategies.len());
     Ok(strategies)
 }
+
+pub async fn get_checkpoint(
+    collection: &Collection<CopierState>,
+    chat_id: i64,
+) -> Result<Option<i64>> {
+    let state = collection.find_one(doc! { "_id": chat_id }, None).await?;
+    Ok(state.map(|s| s.last_message_id))
+}
+
+pub async fn save_checkpoint(
+    collection: &Collection<CopierState>,
+    chat_id: i64,
+    last_message_id: i64,
+) -> Result<()> {
+    // Never move the checkpoint backwards
+    collection
+        .update_one(
+            doc! { "_id": chat_id },
+            doc! {
+                "$max": { "last_message_id": last_message_id },
+                "$set": { "updated_at": chrono::Utc::now().timestamp() }
+            },
+            UpdateOptions::builder().upsert(true).build(),
+        )
+        .await?;
+    Ok(())
+}
+
+pub async fn get_history_progress(
+    collection: &Collection<HistoryProgress>,
+    chat_id: i64,
+) -> Result<Option<HistoryProgress>> {
+    Ok(collection.find_one(doc! { "_id": chat_id }, None).await?)
+}
+
+pub async fn save_history_progress(
+    collection: &Collection<HistoryProgress>,
+    progress: &HistoryProgress,
+) -> Result<()> {
+    collection
+        .replace_one(
+            doc! { "_id": progress.chat_id },
+            progress,
+            ReplaceOptions::builder().upsert(true).build(),
+        )
+        .await?;
+    Ok(())
+}
+
+pub async fn clear_history_progress(
+    collection: &Collection<HistoryProgress>,
+    chat_id: i64,
+) -> Result<()> {
+    collection.delete_one(doc! { "_id": chat_id }, None).await?;
+    Ok(())
+}
+
+pub async fn record_trade_failure(
+    collection: &Collection<TradeFailure>,
+    strategy: &str,
+    token: &str,
+    contract_address: &str,
+    trade_type: TradeType,
+    error: &anyhow::Error,
+) -> Result<()> {
+    let (reason, logs) = match error.downcast_ref::<SimulationError>() {
+        Some(sim) => (sim.to_string(), sim.logs().to_vec()),
+        None => (error.to_string(), Vec::new()),
+    };
+    collection
+        .insert_one(
+            TradeFailure {
+                date: Utc::now(),
+                strategy: strategy.to_string(),
+                token: token.to_string(),
+                contract_address: contract_address.to_string(),
+                trade_type,
+                reason,
+                logs,
+            },
+            None,
+        )
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,177 @@
+use grammers_client::types::{Chat, Message};
+use grammers_client::{Client, InvocationError};
+use serde::Serialize;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time;
+
+/// Wait assumed when Telegram doesn't say how long
+const DEFAULT_FLOOD_WAIT_SECS: u32 = 60;
+
+/// Seconds Telegram asks to wait before calling again, `None` when `error`
+/// is not a flood wait
+pub fn flood_wait_secs(error: &InvocationError) -> Option<u32> {
+    match error {
+        InvocationError::Rpc(rpc) if rpc.is("FLOOD_WAIT") || rpc.is("SLOWMODE_WAIT") => {
+            Some(rpc.value.unwrap_or(DEFAULT_FLOOD_WAIT_SECS))
+        }
+        _ => None,
+    }
+}
+
+/// Flood waits slept off, shared by the clients of a run
+#[derive(Debug, Default)]
+pub struct FloodWaitStats {
+    waits: AtomicU64,
+    waited_secs: AtomicU64,
+    last_wait_secs: AtomicU64,
+}
+
+/// Snapshot of [`FloodWaitStats`], served by the API
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct FloodWaitMetrics {
+    pub flood_waits: u64,
+    pub flood_waited_secs: u64,
+    pub last_flood_wait_secs: u64,
+}
+
+impl FloodWaitStats {
+    fn record(&self, secs: u32) {
+        self.waits.fetch_add(1, Ordering::Relaxed);
+        self.waited_secs.fetch_add(secs as u64, Ordering::Relaxed);
+        self.last_wait_secs.store(secs as u64, Ordering::Relaxed);
+    }
+
+    pub fn metrics(&self) -> FloodWaitMetrics {
+        FloodWaitMetrics {
+            flood_waits: self.waits.load(Ordering::Relaxed),
+            flood_waited_secs: self.waited_secs.load(Ordering::Relaxed),
+            last_flood_wait_secs: self.last_wait_secs.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Telegram client whose message fetches sleep off flood waits and retry,
+/// instead of failing the poll or download that ran into them. grammers
+/// only sleeps off short waits by itself. Everything else goes to the
+/// wrapped [`Client`].
+#[derive(Clone)]
+pub struct FloodAwareClient {
+    client: Client,
+    stats: Arc<FloodWaitStats>,
+    shutdown: Option<watch::Receiver<bool>>,
+}
+
+impl Deref for FloodAwareClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        &self.client
+    }
+}
+
+impl FloodAwareClient {
+    pub fn new(client: Client, stats: Arc<FloodWaitStats>) -> Self {
+        Self {
+            client,
+            stats,
+            shutdown: None,
+        }
+    }
+
+    /// Give up waiting when `shutdown` flips, the flood wait error is
+    /// returned then
+    pub fn with_shutdown(mut self, shutdown: watch::Receiver<bool>) -> Self {
+        self.shutdown = Some(shutdown);
+        self
+    }
+
+    pub fn metrics(&self) -> FloodWaitMetrics {
+        self.stats.metrics()
+    }
+
+    /// Sleep off `error` when it is a flood wait, otherwise or on shutdown
+    /// return it
+    async fn wait_out(&self, error: InvocationError, what: &str) -> Result<(), InvocationError> {
+        let Some(secs) = flood_wait_secs(&error) else {
+            return Err(error);
+        };
+        tracing::warn!("Flood wait while {}, retrying in {} s", what, secs);
+        self.stats.record(secs);
+        let sleep = time::sleep(Duration::from_secs(secs as u64));
+        match self.shutdown.clone() {
+            Some(mut shutdown) => tokio::select! {
+                _ = shutdown.changed() => Err(error),
+                () = sleep => Ok(()),
+            },
+            None => {
+                sleep.await;
+                Ok(())
+            }
+        }
+    }
+
+    /// Up to `limit` messages of `chat` older than `offset_id` (the newest
+    /// when 0) and newer than `min_id`, newest first. A flood wait midway
+    /// resumes below the last message fetched.
+    pub async fn messages(
+        &self,
+        chat: &Chat,
+        offset_id: i64,
+        min_id: i64,
+        limit: usize,
+        what: &str,
+    ) -> Result<Vec<Message>, InvocationError> {
+        let mut fetched: Vec<Message> = Vec::new();
+        loop {
+            let offset_id = fetched.last().map_or(offset_id, |last| last.id() as i64);
+            let mut messages = self
+                .client
+                .iter_messages(chat.clone())
+                .limit(limit - fetched.len());
+            if offset_id > 0 {
+                messages = messages.offset_id(offset_id as i32);
+            }
+            let error = loop {
+                match messages.next().await {
+                    Ok(Some(message)) if message.id() as i64 > min_id => fetched.push(message),
+                    Ok(_) => return Ok(fetched),
+                    Err(e) => break e,
+                }
+            };
+            self.wait_out(error, what).await?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use grammers_client::grammers_tl_types as tl;
+
+    fn rpc_error(code: i32, message: &str) -> InvocationError {
+        InvocationError::Rpc(
+            tl::types::RpcError {
+                error_code: code,
+                error_message: message.to_string(),
+            }
+            .into(),
+        )
+    }
+
+    #[test]
+    fn test_flood_wait_secs() {
+        assert_eq!(
+            flood_wait_secs(&rpc_error(420, "FLOOD_WAIT_300")),
+            Some(300)
+        );
+        assert_eq!(flood_wait_secs(&rpc_error(420, "FLOOD_WAIT")), Some(60));
+        assert_eq!(
+            flood_wait_secs(&rpc_error(401, "AUTH_KEY_UNREGISTERED")),
+            None
+        );
+    }
+}
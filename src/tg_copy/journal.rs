@@ -0,0 +1,212 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::future::Future;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::tg_copy::parse_trade::Trade;
+
+tokio::task_local! {
+    static ENTRY: Arc<Mutex<JournalEntry>>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Pass,
+    /// The signal was dropped here on purpose
+    Skip,
+    Fail,
+}
+
+/// One check or action taken for a message
+#[derive(Debug, Clone, Serialize)]
+pub struct Step {
+    pub at: DateTime<Utc>,
+    pub step: &'static str,
+    pub verdict: Verdict,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// Everything decided for one message, a line of the journal
+#[derive(Debug, Clone, Serialize)]
+pub struct JournalEntry {
+    pub received_at: DateTime<Utc>,
+    /// `None` for signals injected without a message
+    pub message_id: Option<i64>,
+    /// `open` or `close`, `None` when the message is no signal
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signal: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contract_address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<String>,
+    pub steps: Vec<Step>,
+    /// What came of the message, the first skip or failure unless set
+    pub outcome: Option<String>,
+}
+
+impl JournalEntry {
+    pub fn new(message_id: Option<i64>) -> Self {
+        Self {
+            received_at: Utc::now(),
+            message_id,
+            signal: None,
+            token: None,
+            contract_address: None,
+            strategy: None,
+            steps: Vec::new(),
+            outcome: None,
+        }
+    }
+
+    /// Record the parsed signal
+    pub fn parsed(&mut self, trade: &Trade) {
+        let (signal, token, contract_address, strategy) = match trade {
+            Trade::Open(open) => ("open", &open.token, &open.contract_address, &open.strategy),
+            Trade::Close(close) => (
+                "close",
+                &close.token,
+                &close.contract_address,
+                &close.strategy,
+            ),
+        };
+        self.signal = Some(signal);
+        self.token = Some(token.clone());
+        self.contract_address = Some(contract_address.clone());
+        self.strategy = Some(strategy.clone());
+        self.step("parse", Verdict::Pass, Some(signal.to_string()));
+    }
+
+    pub fn step(&mut self, step: &'static str, verdict: Verdict, detail: Option<String>) {
+        if self.outcome.is_none() {
+            self.outcome = match verdict {
+                Verdict::Pass => None,
+                Verdict::Skip => Some(format!("skipped at {}", step)),
+                Verdict::Fail => Some(format!("failed at {}", step)),
+            };
+        }
+        self.steps.push(Step {
+            at: Utc::now(),
+            step,
+            verdict,
+            detail,
+        });
+    }
+}
+
+fn record(step: &'static str, verdict: Verdict, detail: Option<String>) {
+    let _ = ENTRY.try_with(|entry| entry.lock().unwrap().step(step, verdict, detail));
+}
+
+/// Note a check passed or an action taken for the message being handled.
+/// Does nothing outside of [`DecisionJournal::scope`].
+pub fn pass(step: &'static str, detail: impl fmt::Display) {
+    record(step, Verdict::Pass, Some(detail.to_string()))
+}
+
+/// Note why the message being handled is not traded
+pub fn skip(step: &'static str, reason: impl fmt::Display) {
+    record(step, Verdict::Skip, Some(reason.to_string()))
+}
+
+pub fn fail(step: &'static str, error: impl fmt::Display) {
+    record(step, Verdict::Fail, Some(error.to_string()))
+}
+
+/// Set what came of the message being handled
+pub fn outcome(outcome: impl fmt::Display) {
+    let _ = ENTRY.try_with(|entry| entry.lock().unwrap().outcome = Some(outcome.to_string()));
+}
+
+/// Append-only JSON lines file of what the copier decided for every
+/// message, to answer why a signal was or wasn't traded
+pub struct DecisionJournal {
+    file: Mutex<File>,
+}
+
+impl DecisionJournal {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self {
+            file: Mutex::new(OpenOptions::new().create(true).append(true).open(path)?),
+        })
+    }
+
+    /// Run `handle` collecting its steps into `entry`, written once it
+    /// finished. An error it returns is the outcome unless one was set.
+    pub async fn scope<T, F>(&self, entry: JournalEntry, handle: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        let entry = Arc::new(Mutex::new(entry));
+        let output = ENTRY.scope(Arc::clone(&entry), handle).await;
+        let mut entry = entry.lock().unwrap().clone();
+        if let Err(e) = &output {
+            entry.step("error", Verdict::Fail, Some(format!("{:#}", e)));
+        }
+        self.write(&entry);
+        output
+    }
+
+    /// A broken journal must not stop the copier, failures are only logged
+    pub fn write(&self, entry: &JournalEntry) {
+        let mut line = match serde_json::to_vec(entry) {
+            Ok(line) => line,
+            Err(e) => {
+                tracing::error!("Failed to serialize journal entry {:?}: {:?}", entry, e);
+                return;
+            }
+        };
+        line.push(b'\n');
+        // One write per line keeps lines of concurrent trades whole
+        if let Err(e) = self.file.lock().unwrap().write_all(&line) {
+            tracing::error!("Failed to write the decision journal: {:?}", e);
+        }
+    }
+}
+
+/// Run `handle` in a journal scope when there is a journal
+pub async fn journaled<T, F>(
+    journal: Option<Arc<DecisionJournal>>,
+    entry: JournalEntry,
+    handle: F,
+) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    match journal {
+        Some(journal) => journal.scope(entry, handle).await,
+        None => handle.await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_first_skip_is_the_outcome() {
+        let entry = Arc::new(Mutex::new(JournalEntry::new(Some(7))));
+        ENTRY
+            .scope(Arc::clone(&entry), async {
+                pass("strategy_filter", "degen");
+                skip("cooldown", "bought by scalp 5m ago");
+                skip("cost", "never reached");
+            })
+            .await;
+        let entry = entry.lock().unwrap();
+        assert_eq!(entry.steps.len(), 3);
+        assert_eq!(entry.steps[1].verdict, Verdict::Skip);
+        assert_eq!(entry.outcome.as_deref(), Some("skipped at cooldown"));
+
+        // Outside of a scope nothing is recorded
+        pass("strategy_filter", "degen");
+    }
+}
@@ -1,5 +1,20 @@
 pub mod active_trade;
+pub mod archive;
+pub mod backfill;
+pub mod chat;
+pub mod commands;
+pub mod cooldown;
 pub mod copier;
+pub mod copy_trader;
 pub mod db;
+pub mod flood;
+pub mod journal;
+pub mod ocr;
 pub mod parse_trade;
+pub mod profile;
+pub mod relogin;
+pub mod signal_dedup;
 pub mod strategy;
+pub mod strategy_manager;
+pub mod ticker;
+pub mod unparsed;
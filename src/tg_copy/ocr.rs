@@ -0,0 +1,206 @@
+use anyhow::Result;
+use grammers_client::grammers_tl_types as tl;
+use grammers_client::types::{Downloadable, Media, Photo};
+use grammers_client::Client;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use crate::solana::cache::cached_search_ticker;
+use crate::tg_copy::parse_trade::{extract_contract_address, parse_trade, Trade};
+
+/// Base58 addresses of 32 bytes in `text`, in order and without repeats.
+/// OCR keeps an address in one piece but may put punctuation around it.
+pub fn extract_mints(text: &str) -> Vec<String> {
+    let mut mints: Vec<String> = Vec::new();
+    for word in text.split(|c: char| !c.is_ascii_alphanumeric()) {
+        if (32..=44).contains(&word.len())
+            && bs58::decode(word)
+                .into_vec()
+                .is_ok_and(|bytes| bytes.len() == 32)
+            && !mints.iter().any(|mint| mint == word)
+        {
+            mints.push(word.to_string());
+        }
+    }
+    mints
+}
+
+/// Whether the caption's `ticker` is one of the `symbols` of a mint,
+/// ignoring case and a `$` in front
+pub fn ticker_matches(ticker: &str, symbols: &[String]) -> bool {
+    let ticker = ticker.trim_start_matches('$');
+    !ticker.is_empty()
+        && symbols
+            .iter()
+            .any(|symbol| symbol.trim_start_matches('$').eq_ignore_ascii_case(ticker))
+}
+
+/// Symbols DexScreener lists `mint` under
+async fn mint_symbols(mint: &str) -> Result<Vec<String>> {
+    let pairs = cached_search_ticker(mint).await?.pairs;
+    Ok(pairs
+        .into_iter()
+        .flat_map(|pair| [pair.base_token, pair.quote_token])
+        .filter(|token| token.address == mint)
+        .map(|token| token.symbol)
+        .collect())
+}
+
+/// Photo of `message`, `None` for text and other media
+fn message_photo(message: &tl::types::Message) -> Option<Media> {
+    match message.media.clone()? {
+        tl::enums::MessageMedia::Photo(photo) => Some(Media::Photo(Photo::from_raw_media(photo))),
+        _ => None,
+    }
+}
+
+/// Text read from the image at `path` by the `tesseract` command
+#[cfg(feature = "ocr")]
+async fn image_text(path: &Path) -> Result<String> {
+    use anyhow::anyhow;
+    use std::process::Command;
+
+    let path = path.to_path_buf();
+    let output = tokio::task::spawn_blocking(move || {
+        Command::new("tesseract").arg(path).arg("stdout").output()
+    })
+    .await?
+    .map_err(|e| anyhow!("Failed to run tesseract: {}", e))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "tesseract failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(not(feature = "ocr"))]
+async fn image_text(_path: &Path) -> Result<String> {
+    Err(anyhow::anyhow!("built without the ocr feature"))
+}
+
+/// Whether images can be read, warned about once at startup
+pub fn available() -> bool {
+    cfg!(feature = "ocr")
+}
+
+/// Addresses written in the photo of `message`, downloaded to a temporary
+/// file for the time of the OCR
+async fn photo_mints(client: &Client, media: Media, message_id: i32) -> Result<Vec<String>> {
+    let path = env::temp_dir().join(format!("signal-{}-{}.jpg", std::process::id(), message_id));
+    client
+        .download_media(&Downloadable::Media(media), &path)
+        .await?;
+    let text = image_text(&path).await;
+    let _ = fs::remove_file(&path);
+    Ok(extract_mints(&text?))
+}
+
+/// `message` with a `CA:` line for the address in its photo when the
+/// caption has none, so it parses like a signal with the address in the
+/// text. A misread address could be another token, it is only taken when
+/// DexScreener lists it under the caption's ticker. `None` when there is
+/// nothing to add: a caption with an address, no photo, no address or
+/// several in it, one not matching the ticker, or OCR unavailable or
+/// failing.
+pub async fn with_photo_ca(
+    client: &Client,
+    message: &tl::types::Message,
+) -> Option<tl::types::Message> {
+    if extract_contract_address(&message.message).is_some() {
+        return None;
+    }
+    let media = message_photo(message)?;
+    let mints = match photo_mints(client, media, message.id).await {
+        Ok(mints) => mints,
+        Err(e) => {
+            tracing::warn!(
+                "Could not read the photo of message {}: {:?}",
+                message.id,
+                e
+            );
+            return None;
+        }
+    };
+    let mint = match mints.as_slice() {
+        [] => return None,
+        [mint] => mint,
+        _ => {
+            tracing::warn!(
+                "Photo of message {} shows {} addresses, none taken: {}",
+                message.id,
+                mints.len(),
+                mints.join(", ")
+            );
+            return None;
+        }
+    };
+    let text = format!("{}\nCA: {}", message.message, mint);
+    let ticker = match parse_trade(&text)? {
+        Trade::Open(open) => open.token,
+        Trade::Close(close) => close.token,
+    };
+    let symbols = match mint_symbols(mint).await {
+        Ok(symbols) => symbols,
+        Err(e) => {
+            tracing::warn!(
+                "Could not check {} read from the photo of message {}: {:?}",
+                mint,
+                message.id,
+                e
+            );
+            return None;
+        }
+    };
+    if !ticker_matches(&ticker, &symbols) {
+        tracing::warn!(
+            "Photo of message {} shows {}, listed as [{}] rather than {}, not taken",
+            message.id,
+            mint,
+            symbols.join(", "),
+            ticker
+        );
+        return None;
+    }
+    tracing::info!(
+        "Read {} ({}) from the photo of message {}",
+        mint,
+        ticker,
+        message.id
+    );
+    let mut message = message.clone();
+    message.message = text;
+    Some(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_mints() {
+        let text = "$WIF\nCA: EKpQGSJtjMFqKZ9KQanSqYXRcF8fBopzLHYxdM65zcjm.\n\
+                    (EKpQGSJtjMFqKZ9KQanSqYXRcF8fBopzLHYxdM65zcjm) MC 2.1M";
+        assert_eq!(
+            extract_mints(text),
+            vec!["EKpQGSJtjMFqKZ9KQanSqYXRcF8fBopzLHYxdM65zcjm".to_string()]
+        );
+        // OCR misreading a character breaks the address rather than
+        // producing another one
+        assert!(extract_mints("EKpQGSJtjMFqKZ9KQanSqYXRcF8fBopzLHYxdM65zcjO").is_empty());
+        assert!(extract_mints("Buy Price: $0.0012").is_empty());
+    }
+
+    #[test]
+    fn test_ticker_matches() {
+        let symbols = vec!["WIF".to_string(), "SOL".to_string()];
+        assert!(ticker_matches("wif", &symbols));
+        assert!(ticker_matches("$WIF", &symbols));
+        assert!(!ticker_matches("BONK", &symbols));
+        assert!(!ticker_matches("$", &symbols));
+        assert!(!ticker_matches("WIF", &[]));
+    }
+}
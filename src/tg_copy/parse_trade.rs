@@ -35,7 +35,8 @@ impl ToString for OperationType {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct CloseTrade {
     pub strategy: String,
     pub op_type: OperationType,
@@ -44,9 +45,14 @@ pub struct CloseTrade {
     pub exit_price: f64,
     pub profit_pct: f64,
     pub contract_address: String,
+    /// Share of its holdings the provider sold, `None` for a full close.
+    /// Only copied wallets tell.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sell_fraction: Option<f64>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct OpenTrade {
     pub strategy: String,
     pub token: String,
@@ -58,13 +64,16 @@ pub struct OpenTrade {
     pub market_cap: f64,
 }
 
-#[derive(Debug, Clone)]
+/// Serialized with a `signal` tag, `{"signal": "open", "strategy": ...}`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "signal", rename_all = "snake_case")]
+#[non_exhaustive]
 pub enum Trade {
     Open(OpenTrade),
     Close(CloseTrade),
 }
 
-fn extract_contract_address(text: &str) -> Option<String> {
+pub(crate) fn extract_contract_address(text: &str) -> Option<String> {
     if let Some(ca_line) = text.lines().find(|line| line.contains("CA:")) {
         ca_line
             .split("CA:")
@@ -109,6 +118,21 @@ pub fn parse_trade(message: &str) -> Option<Trade> {
 }
 
 pub fn parse_trade_close(message: &str) -> Option<CloseTrade> {
+    let contract_address = extract_contract_address(message)?;
+    parse_close_fields(message, contract_address)
+}
+
+/// A close signal naming its token by ticker only, without a `CA:` line.
+/// `contract_address` is left empty, the ticker is resolved to a mint by
+/// [`resolve_ticker_close`](crate::tg_copy::ticker::resolve_ticker_close).
+pub fn parse_ticker_close(message: &str) -> Option<CloseTrade> {
+    if extract_contract_address(message).is_some() {
+        return None;
+    }
+    parse_close_fields(message, String::new())
+}
+
+fn parse_close_fields(message: &str, contract_address: String) -> Option<CloseTrade> {
     let lines: Vec<&str> = message.lines().collect();
 
     // First line should contain token name and operation type
@@ -160,7 +184,6 @@ pub fn parse_trade_close(message: &str) -> Option<CloseTrade> {
 
     let profit_pct = profit_str.parse::<f64>().ok().unwrap_or(0f64);
 
-    let contract_address = extract_contract_address(message)?;
     Some(CloseTrade {
         strategy,
         op_type,
@@ -169,6 +192,7 @@ pub fn parse_trade_close(message: &str) -> Option<CloseTrade> {
         exit_price,
         profit_pct,
         contract_address,
+        sell_fraction: None,
     })
 }
 
@@ -290,4 +314,22 @@ mod tests {
             "HXFuUcBQkcfUNksDkgxBVapg3coA4UsSxe6ny9WF"
         );
     }
+
+    #[test]
+    fn test_parse_ticker_close() {
+        let message = "🔴 ABYS TSL\nprereeeet\n└ $0.000583 → $0.000901 (+54.5%)";
+
+        assert!(parse_trade_close(message).is_none());
+        let trade = parse_ticker_close(message).unwrap();
+        assert_eq!(trade.op_type, OperationType::TrailingStopLoss);
+        assert_eq!(trade.token, "ABYS");
+        assert_eq!(trade.profit_pct, 54.5);
+        assert!(trade.contract_address.is_empty());
+
+        let with_ca = format!(
+            "{}\n└─ CA: HXFuUcBQkcfUNksDkgxBVapg3coA4UsSxe6ny9WFpump",
+            message
+        );
+        assert!(parse_ticker_close(&with_ca).is_none());
+    }
 }
@@ -0,0 +1,215 @@
+use anyhow::{anyhow, Result};
+use futures::future::try_join_all;
+use listen_kit::signer::{SignerContext, TransactionSigner};
+use std::env;
+use std::ffi::OsString;
+use std::future::Future;
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+use tokio::sync::{watch, Mutex, MutexGuard};
+
+use crate::config::{ProfilesConfig, TelegramConfig};
+use crate::solana::ws::PriceCache;
+use crate::tg_copy::copier::{connect_telegram, SESSION_FILE};
+use crate::tg_copy::copy_trader::{CopyTrader, CopyTraderConfig};
+
+const DEFAULT_SQLITE_PATH: &str = "copy_trade.db";
+
+static ENV_LOCK: LazyLock<Mutex<()>> = LazyLock::new(Mutex::default);
+
+/// Prefix of the variables setting `NAME` for profile `id` only
+pub fn profile_prefix(id: &str) -> String {
+    format!("PROFILE_{}_", id.to_uppercase())
+}
+
+/// What profile `id` sets out of the environment `vars`: its
+/// `PROFILE_<ID>_*` variables without the prefix, and unless it sets them,
+/// a database of its own next to the shared one
+pub fn profile_vars(id: &str, vars: &[(String, String)]) -> Vec<(String, String)> {
+    let prefix = profile_prefix(id);
+    let mut profile: Vec<(String, String)> = vars
+        .iter()
+        .filter_map(|(name, value)| {
+            name.strip_prefix(&prefix)
+                .filter(|name| !name.is_empty())
+                .map(|name| (name.to_string(), value.clone()))
+        })
+        .collect();
+    let shared = |name: &str| {
+        vars.iter()
+            .find(|(var, _)| var == name)
+            .map(|(_, value)| value.as_str())
+    };
+    let sets =
+        |profile: &[(String, String)], name: &str| profile.iter().any(|(var, _)| var == name);
+    if !sets(&profile, "DB_NAME") {
+        if let Some(db_name) = shared("DB_NAME") {
+            profile.push(("DB_NAME".to_string(), format!("{}_{}", db_name, id)));
+        }
+    }
+    if !sets(&profile, "SQLITE_PATH") {
+        let path = Path::new(shared("SQLITE_PATH").unwrap_or(DEFAULT_SQLITE_PATH));
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let file = match path.extension() {
+            Some(extension) => format!("{}_{}.{}", stem, id, extension.to_string_lossy()),
+            None => format!("{}_{}", stem, id),
+        };
+        profile.push((
+            "SQLITE_PATH".to_string(),
+            path.with_file_name(file).display().to_string(),
+        ));
+    }
+    profile
+}
+
+/// The environment with the settings of one profile, restored when
+/// dropped. Configs read from the environment meanwhile are the profile's.
+/// One profile is entered at a time and only by [`setup_profiles`], before
+/// any task that reads the environment is spawned: setting variables while
+/// another thread reads them is a data race.
+pub struct ProfileEnv {
+    previous: Vec<(String, Option<OsString>)>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl ProfileEnv {
+    pub async fn enter(id: &str) -> Self {
+        let lock = ENV_LOCK.lock().await;
+        let vars: Vec<(String, String)> = env::vars().collect();
+        let previous = profile_vars(id, &vars)
+            .into_iter()
+            .map(|(name, value)| {
+                let previous = env::var_os(&name);
+                env::set_var(&name, value);
+                (name, previous)
+            })
+            .collect();
+        Self {
+            previous,
+            _lock: lock,
+        }
+    }
+}
+
+impl Drop for ProfileEnv {
+    fn drop(&mut self) {
+        for (name, previous) in self.previous.drain(..).rev() {
+            match previous {
+                Some(value) => env::set_var(name, value),
+                None => env::remove_var(name),
+            }
+        }
+    }
+}
+
+/// A profile set up to run, its signer and settings read in its
+/// environment
+pub struct Profile {
+    pub signer: Arc<dyn TransactionSigner>,
+    config: CopyTraderConfig,
+}
+
+/// Build the signer and settings of every profile, `make_signer` runs in
+/// the profile's environment. Call at startup before spawning tasks, see
+/// [`ProfileEnv`].
+pub async fn setup_profiles<F, Fut>(
+    profiles: ProfilesConfig,
+    make_signer: F,
+) -> Result<Vec<Profile>>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<Arc<dyn TransactionSigner>>>,
+{
+    tracing::info!("{}", profiles);
+    let mut setup = Vec::new();
+    let mut bind_addrs = Vec::new();
+    for id in profiles.ids {
+        let env = ProfileEnv::enter(&id).await;
+        let signer = make_signer().await?;
+        let mut config = CopyTraderConfig::from_env()?;
+        drop(env);
+
+        config.profile = Some(id.clone());
+        // Every profile inherits the shared API_BIND_ADDR unless it sets
+        // its own
+        if let Some((api, _)) = &config.api {
+            if bind_addrs.contains(&api.bind_addr) {
+                return Err(anyhow!(
+                    "Profile {} serves the API on {} like another profile, set {}API_BIND_ADDR",
+                    id,
+                    api.bind_addr,
+                    profile_prefix(&id)
+                ));
+            }
+            bind_addrs.push(api.bind_addr.clone());
+        }
+        tracing::info!("Profile {} trades wallet {}{}", id, signer.pubkey(), config);
+        setup.push(Profile { signer, config });
+    }
+    Ok(setup)
+}
+
+/// Run a copier per profile until `shutdown` flips, all polling with one
+/// Telegram client and pricing through one cache. Each profile's trades are
+/// signed with its own signer.
+pub async fn run_profiles(profiles: Vec<Profile>, shutdown: watch::Receiver<bool>) -> Result<()> {
+    let price_cache = Arc::<PriceCache>::default();
+    let mut copiers = Vec::new();
+    for profile in profiles {
+        let copier = CopyTrader::with_price_cache(profile.config, Arc::clone(&price_cache)).await?;
+        copiers.push((profile.signer, copier));
+    }
+
+    let client = connect_telegram(&TelegramConfig::from_env()?).await?;
+    let result = try_join_all(copiers.iter().map(|(signer, copier)| {
+        SignerContext::with_signer(
+            Arc::clone(signer),
+            copier.start_with_client(client.clone(), shutdown.clone()),
+        )
+    }))
+    .await
+    .map(|_| ());
+
+    client.session().save_to_file(SESSION_FILE)?;
+    tracing::info!("Telegram session saved, shutdown complete");
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_profile_vars() {
+        let env = vars(&[
+            ("DB_NAME", "copy"),
+            ("POSITION_SIZE_SOL", "0.1"),
+            ("PROFILE_AGGRESSIVE_POSITION_SIZE_SOL", "0.5"),
+            ("PROFILE_AGGRESSIVE_SOLANA_PRIVATE_KEY", "key"),
+            ("PROFILE_SAFE_DB_NAME", "safe_db"),
+            ("PROFILE_SAFE_SQLITE_PATH", "/data/safe.db"),
+        ]);
+
+        let aggressive = profile_vars("aggressive", &env);
+        assert_eq!(
+            aggressive,
+            vars(&[
+                ("POSITION_SIZE_SOL", "0.5"),
+                ("SOLANA_PRIVATE_KEY", "key"),
+                ("DB_NAME", "copy_aggressive"),
+                ("SQLITE_PATH", "copy_trade_aggressive.db"),
+            ])
+        );
+        // Its own database when it names one
+        assert_eq!(
+            profile_vars("safe", &env),
+            vars(&[("DB_NAME", "safe_db"), ("SQLITE_PATH", "/data/safe.db")])
+        );
+    }
+}
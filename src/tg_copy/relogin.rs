@@ -0,0 +1,189 @@
+use anyhow::{anyhow, Result};
+use grammers_client::types::{LoginToken, PasswordToken};
+use grammers_client::{Client, InvocationError, SignInError};
+use serde::Serialize;
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::{Mutex, Notify};
+use tokio::{fs, time};
+
+/// How often a headless login looks for the code file
+const CODE_FILE_POLL: Duration = Duration::from_secs(2);
+
+/// RPC errors meaning the session's authorization is gone and only a new
+/// login brings it back
+const REVOKED_ERRORS: [&str; 3] = [
+    "AUTH_KEY_UNREGISTERED",
+    "SESSION_REVOKED",
+    "SESSION_EXPIRED",
+];
+
+/// Whether `e` comes from Telegram revoking the session
+pub fn is_session_revoked(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        cause
+            .downcast_ref::<InvocationError>()
+            .is_some_and(|e| REVOKED_ERRORS.iter().any(|name| e.is(name)))
+    })
+}
+
+enum LoginState {
+    /// The session works, nothing to do
+    Idle,
+    /// Revoked, waiting for the operator to request a code
+    Revoked(Client),
+    AwaitingCode(Client, LoginToken),
+    AwaitingPassword(Client, PasswordToken),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum LoginStep {
+    /// A code was sent to the account's Telegram apps
+    CodeSent,
+    PasswordRequired {
+        hint: Option<String>,
+    },
+    SignedIn,
+}
+
+/// Re-authentication of a revoked or never logged in Telegram session,
+/// completed by an operator step by step while the rest of the bot keeps
+/// running
+pub struct TelegramLogin {
+    state: Mutex<LoginState>,
+    signed_in: Notify,
+    /// Answers the password step without waiting for the operator
+    password: Option<String>,
+}
+
+impl Default for TelegramLogin {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(LoginState::Idle),
+            signed_in: Notify::new(),
+            password: None,
+        }
+    }
+}
+
+impl TelegramLogin {
+    pub fn with_password(mut self, password: Option<String>) -> Self {
+        self.password = password;
+        self
+    }
+
+    /// The session of `client` was revoked, accept login steps for it
+    pub async fn revoked(&self, client: Client) {
+        *self.state.lock().await = LoginState::Revoked(client);
+    }
+
+    /// Ask Telegram for a login code, also restarts a login gone wrong
+    pub async fn request_code(&self, phone: &str) -> Result<LoginStep> {
+        let mut state = self.state.lock().await;
+        let client = match &*state {
+            LoginState::Idle => return Err(anyhow!("The Telegram session is not revoked")),
+            LoginState::Revoked(client)
+            | LoginState::AwaitingCode(client, _)
+            | LoginState::AwaitingPassword(client, _) => client.clone(),
+        };
+        let token = client.request_login_code(phone).await?;
+        *state = LoginState::AwaitingCode(client, token);
+        Ok(LoginStep::CodeSent)
+    }
+
+    pub async fn sign_in(&self, code: &str) -> Result<LoginStep> {
+        let mut state = self.state.lock().await;
+        let LoginState::AwaitingCode(client, token) = &*state else {
+            return Err(anyhow!("No login code was requested"));
+        };
+        let client = client.clone();
+        match client.sign_in(token, code).await {
+            Ok(_) => {
+                *state = LoginState::Idle;
+                self.signed_in.notify_one();
+                Ok(LoginStep::SignedIn)
+            }
+            Err(SignInError::PasswordRequired(password_token)) => match &self.password {
+                Some(password) => {
+                    *state = LoginState::AwaitingPassword(client, password_token);
+                    self.finish_with_password(&mut state, password).await
+                }
+                None => {
+                    let hint = password_token.hint().map(str::to_string);
+                    *state = LoginState::AwaitingPassword(client, password_token);
+                    Ok(LoginStep::PasswordRequired { hint })
+                }
+            },
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub async fn check_password(&self, password: &str) -> Result<LoginStep> {
+        let mut state = self.state.lock().await;
+        self.finish_with_password(&mut state, password).await
+    }
+
+    async fn finish_with_password(
+        &self,
+        state: &mut LoginState,
+        password: &str,
+    ) -> Result<LoginStep> {
+        let (client, password_token) = match std::mem::replace(state, LoginState::Idle) {
+            LoginState::AwaitingPassword(client, password_token) => (client, password_token),
+            other => {
+                *state = other;
+                return Err(anyhow!("No password was asked for"));
+            }
+        };
+        // The token is consumed, after a wrong password the login starts over
+        // with a new code
+        *state = LoginState::Revoked(client.clone());
+        client
+            .check_password(password_token, password.trim())
+            .await?;
+        *state = LoginState::Idle;
+        self.signed_in.notify_one();
+        Ok(LoginStep::SignedIn)
+    }
+
+    /// Resolves once a revoked session is signed in again
+    pub async fn wait_signed_in(&self) {
+        self.signed_in.notified().await
+    }
+
+    /// Sign in with the code written to `path`, polled until a code
+    /// signs in. Each code read is removed so a stale one is not retried.
+    /// Returns when the login is signed in or waits for a password.
+    pub async fn poll_code_file(&self, path: &Path) {
+        loop {
+            time::sleep(CODE_FILE_POLL).await;
+            let code = match fs::read_to_string(path).await {
+                Ok(code) if !code.trim().is_empty() => code.trim().to_string(),
+                _ => continue,
+            };
+            if let Err(e) = fs::remove_file(path).await {
+                tracing::warn!(
+                    "Failed to remove login code file {}: {:?}",
+                    path.display(),
+                    e
+                );
+            }
+            match self.sign_in(&code).await {
+                Ok(LoginStep::PasswordRequired { hint }) => {
+                    tracing::warn!(
+                        "Telegram asks for the 2FA password (hint {}), set TG_PASSWORD or post it to /telegram/login/password",
+                        hint.as_deref().unwrap_or("none")
+                    );
+                    return;
+                }
+                Ok(_) => return,
+                Err(e) => tracing::error!(
+                    "Login with the code from {} failed: {:?}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use mongodb::{bson::doc, options::IndexOptions, Collection, IndexModel};
+use serde::{Deserialize, Serialize};
+use solana_sdk::hash::hashv;
+
+use crate::storage::mongo::is_duplicate_key;
+use crate::tg_copy::parse_trade::Trade;
+
+/// The same signal seen again within this long, from any source, is a
+/// mirror of the first
+pub const SIGNAL_DEDUP_WINDOW_SECS: i64 = 300;
+
+/// Source of signals read from the Telegram group
+pub const TELEGRAM_SOURCE: &str = "telegram";
+
+/// First sighting of a signal, removed by a TTL index once its window passed
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SeenSignal {
+    /// [`signal_fingerprint`] of the signal
+    #[serde(rename = "_id")]
+    pub fingerprint: String,
+    pub contract_address: String,
+    pub source: String,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub seen_at: DateTime<Utc>,
+}
+
+impl SeenSignal {
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        now - self.seen_at <= Duration::seconds(SIGNAL_DEDUP_WINDOW_SECS)
+    }
+}
+
+/// Hash of what a signal says rather than how it is worded, so the copy of
+/// a mirror reformatting the message matches the original
+pub fn signal_fingerprint(trade: &Trade) -> String {
+    let content = match trade {
+        Trade::Open(open) => format!(
+            "open|{}|{}|{}|{}|{}",
+            open.strategy.to_lowercase(),
+            open.contract_address,
+            open.buy_price,
+            open.market_cap,
+            open.num_buys
+        ),
+        Trade::Close(close) => format!(
+            "close|{}|{}|{:?}|{}|{}",
+            close.strategy.to_lowercase(),
+            close.contract_address,
+            close.op_type,
+            close.exit_price,
+            close.profit_pct
+        ),
+    };
+    hashv(&[content.as_bytes()]).to_string()
+}
+
+/// Dedup of signals across the sources they arrive from, a provider posting
+/// to the group and to a mirror is traded once. Kept in Mongo so a restart
+/// doesn't trade the mirror of a signal handled before it.
+#[derive(Clone)]
+pub struct SignalDedup {
+    collection: Collection<SeenSignal>,
+}
+
+impl SignalDedup {
+    pub fn new(collection: Collection<SeenSignal>) -> Self {
+        Self { collection }
+    }
+
+    pub async fn setup_indexes(&self) -> Result<()> {
+        let ttl_index = IndexModel::builder()
+            .keys(doc! { "seen_at": 1 })
+            .options(
+                IndexOptions::builder()
+                    .expire_after(std::time::Duration::from_secs(
+                        SIGNAL_DEDUP_WINDOW_SECS as u64,
+                    ))
+                    .build(),
+            )
+            .build();
+        self.collection.create_index(ttl_index, None).await?;
+        Ok(())
+    }
+
+    /// Claim `trade` for `source`. Returns the earlier sighting when the
+    /// signal was seen within the window, the trade is a duplicate then.
+    /// Concurrent claims of one signal are settled by the unique `_id`.
+    pub async fn claim(&self, trade: &Trade, source: &str) -> Result<Option<SeenSignal>> {
+        let now = Utc::now();
+        let contract_address = match trade {
+            Trade::Open(open) => &open.contract_address,
+            Trade::Close(close) => &close.contract_address,
+        };
+        let seen = SeenSignal {
+            fingerprint: signal_fingerprint(trade),
+            contract_address: contract_address.clone(),
+            source: source.to_string(),
+            seen_at: now,
+        };
+        match self.collection.insert_one(&seen, None).await {
+            Ok(_) => return Ok(None),
+            Err(e) if is_duplicate_key(&e) => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        // The TTL monitor only sweeps once a minute, an expired sighting
+        // still stored is taken over
+        let expired = self
+            .collection
+            .replace_one(
+                doc! {
+                    "_id": &seen.fingerprint,
+                    "seen_at": { "$lt": now - Duration::seconds(SIGNAL_DEDUP_WINDOW_SECS) },
+                },
+                &seen,
+                None,
+            )
+            .await?;
+        if expired.modified_count > 0 {
+            return Ok(None);
+        }
+        Ok(self
+            .collection
+            .find_one(doc! { "_id": &seen.fingerprint }, None)
+            .await?
+            .filter(|earlier| earlier.is_active(now)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tg_copy::parse_trade::OpenTrade;
+
+    fn open(strategy: &str, buy_price: f64) -> Trade {
+        Trade::Open(OpenTrade {
+            strategy: strategy.to_string(),
+            token: "PEPE".to_string(),
+            buy_price,
+            num_buys: 3,
+            total_buys: None,
+            time_window: 5,
+            contract_address: "mint".to_string(),
+            market_cap: 120_000.0,
+        })
+    }
+
+    #[test]
+    fn test_signal_fingerprint() {
+        assert_eq!(
+            signal_fingerprint(&open("Degen", 0.0012)),
+            signal_fingerprint(&open("degen", 0.0012))
+        );
+        assert_ne!(
+            signal_fingerprint(&open("degen", 0.0012)),
+            signal_fingerprint(&open("degen", 0.0013))
+        );
+    }
+}
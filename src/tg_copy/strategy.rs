@@ -1,6 +1,12 @@
+use anyhow::{anyhow, Result};
 use bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
 
+use crate::format;
+use crate::tg_copy::cooldown::ReentryPolicy;
+use crate::tg_copy::parse_trade::OpenTrade;
+use crate::trade::sizing::SizeBand;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Strategy {
     #[serde(rename = "_id")]
@@ -13,6 +19,78 @@ pub struct Strategy {
     pub buy_conditions: Vec<BuyCondition>,
     #[serde(rename = "sellConditions")]
     pub sell_conditions: SellConditions,
+    /// Position sizes by market cap, used over the buy conditions' amounts
+    /// with `SIZE_FROM_STRATEGY`
+    #[serde(rename = "sizeBands", default, skip_serializing_if = "Option::is_none")]
+    pub size_bands: Option<Vec<SizeBand>>,
+    /// Seconds open signals for a token it bought are skipped, over
+    /// `TRADE_COOLDOWN_SECS`
+    #[serde(
+        rename = "cooldownSecs",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub cooldown_secs: Option<i64>,
+    /// Over `REENTRY_POLICY`
+    #[serde(
+        rename = "reentryPolicy",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub reentry_policy: Option<ReentryPolicy>,
+    /// Created by the backfill for strategy names seen in signals but missing
+    /// from the collection
+    #[serde(rename = "isPlaceholder", default)]
+    pub is_placeholder: bool,
+}
+
+impl Strategy {
+    pub fn placeholder(strategy_id: &str) -> Self {
+        Self {
+            id: ObjectId::new(),
+            strategy_id: strategy_id.to_string(),
+            is_shaved: false,
+            buy_conditions: Vec::new(),
+            sell_conditions: SellConditions {
+                take_profit_conditions: None,
+                stop_loss_condition: None,
+                trailing_stop_loss_condition: None,
+            },
+            size_bands: None,
+            cooldown_secs: None,
+            reentry_policy: None,
+            is_placeholder: true,
+        }
+    }
+
+    /// Whether this strategy is the one named in a signal, which drops the
+    /// underscores of the stored id
+    pub fn matches(&self, signal_strategy: &str) -> bool {
+        normalize_strategy_id(&self.strategy_id) == normalize_strategy_id(signal_strategy)
+    }
+
+    /// Why `open_trade` meets none of the buy conditions, `None` when one
+    /// is met or there are none. The reason is the one of the first
+    /// condition whose market cap range fits, or the range otherwise.
+    pub fn unmet_buy_conditions(&self, open_trade: &OpenTrade) -> Option<String> {
+        if self.buy_conditions.is_empty() {
+            return None;
+        }
+        let unmet: Vec<String> = self
+            .buy_conditions
+            .iter()
+            .map(|condition| condition.unmet(open_trade))
+            .collect::<Option<_>>()?;
+        let in_range = self
+            .buy_conditions
+            .iter()
+            .position(|condition| condition.matches_market_cap(open_trade.market_cap));
+        unmet.into_iter().nth(in_range.unwrap_or(0))
+    }
+}
+
+pub fn normalize_strategy_id(strategy_id: &str) -> String {
+    strategy_id.replace('_', "")
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -27,13 +105,100 @@ pub struct BuyCondition {
     pub min_marketcap: u64,
     #[serde(rename = "maxMarketcap")]
     pub max_marketcap: Option<u64>,
+    /// Amount to buy in `buy_amount_unit`, SOL for older documents
     #[serde(rename = "solBuyAmount")]
     pub sol_buy_amount: f32,
+    #[serde(rename = "buyAmountUnit", default)]
+    pub buy_amount_unit: BuyAmountUnit,
     #[serde(rename = "top10MaxPercentage")]
     pub top10_max_percentage: f32,
     pub description: String,
 }
 
+impl BuyCondition {
+    pub fn matches_market_cap(&self, market_cap: f64) -> bool {
+        market_cap >= self.min_marketcap as f64
+            && self
+                .max_marketcap
+                .map_or(true, |max| market_cap <= max as f64)
+    }
+
+    /// First requirement `open_trade` falls short of, `None` when it meets
+    /// them all. Signals without a buy total skip that requirement, a
+    /// window of 0 takes any.
+    pub fn unmet(&self, open_trade: &OpenTrade) -> Option<String> {
+        if !self.matches_market_cap(open_trade.market_cap) {
+            return Some(format!(
+                "market cap {} outside {} to {}",
+                format::market_cap(open_trade.market_cap),
+                format::market_cap(self.min_marketcap as f64),
+                self.max_marketcap
+                    .map_or_else(|| "any".to_string(), |max| format::market_cap(max as f64))
+            ));
+        }
+        if (open_trade.num_buys as i64) < self.min_wallets as i64 {
+            return Some(format!(
+                "{} wallets bought, min {}",
+                open_trade.num_buys, self.min_wallets
+            ));
+        }
+        if let Some(total) = open_trade.total_buys {
+            if (total as f32) < self.min_sol_buy_delta {
+                return Some(format!(
+                    "{} bought, min {} SOL",
+                    format::sol(total),
+                    self.min_sol_buy_delta
+                ));
+            }
+        }
+        if self.time_window_seconds > 0
+            && open_trade.time_window as i64 > self.time_window_seconds as i64
+        {
+            return Some(format!(
+                "bought within {} s, max {} s",
+                open_trade.time_window, self.time_window_seconds
+            ));
+        }
+        None
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum BuyAmountUnit {
+    #[default]
+    Sol,
+    Usd,
+    /// Percent of the tradable SOL balance
+    EquityPercent,
+}
+
+impl BuyAmountUnit {
+    /// Convert `amount` to SOL. `sol_price_usd` is only needed for USD
+    /// amounts and `equity_sol` for equity percentages.
+    pub fn to_sol(
+        self,
+        amount: f64,
+        sol_price_usd: Option<f64>,
+        equity_sol: Option<f64>,
+    ) -> Result<f64> {
+        match self {
+            BuyAmountUnit::Sol => Ok(amount),
+            BuyAmountUnit::Usd => {
+                let price = sol_price_usd.ok_or_else(|| anyhow!("SOL price is unknown"))?;
+                if price <= 0.0 {
+                    return Err(anyhow!("Invalid SOL price {}", price));
+                }
+                Ok(amount / price)
+            }
+            BuyAmountUnit::EquityPercent => {
+                let equity = equity_sol.ok_or_else(|| anyhow!("Equity is unknown"))?;
+                Ok(equity * amount / 100.0)
+            }
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SellConditions {
     #[serde(rename = "takeProfitConditions")]
@@ -68,3 +233,73 @@ pub struct TrailingStopLossCondition {
     pub is_logarithmic: bool,
     pub description: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn condition(min_marketcap: u64, max_marketcap: Option<u64>) -> BuyCondition {
+        serde_json::from_value(serde_json::json!({
+            "timeWindowSeconds": 60,
+            "minSolBuyDelta": 2.0,
+            "minWallets": 3,
+            "minMarketcap": min_marketcap,
+            "maxMarketcap": max_marketcap,
+            "solBuyAmount": 0.5,
+            "top10MaxPercentage": 30.0,
+            "description": "test"
+        }))
+        .unwrap()
+    }
+
+    fn open_trade(market_cap: f64, num_buys: u32, total_buys: Option<f64>) -> OpenTrade {
+        OpenTrade {
+            strategy: "degen".to_string(),
+            token: "PEPE".to_string(),
+            buy_price: 0.0001,
+            num_buys,
+            total_buys,
+            time_window: 30,
+            contract_address: "mint".to_string(),
+            market_cap,
+        }
+    }
+
+    #[test]
+    fn test_unmet_buy_conditions() {
+        let mut strategy = Strategy::placeholder("degen");
+        assert_eq!(
+            strategy.unmet_buy_conditions(&open_trade(50_000.0, 1, None)),
+            None
+        );
+
+        strategy.buy_conditions = vec![condition(10_000, Some(100_000)), condition(100_000, None)];
+        assert_eq!(
+            strategy.unmet_buy_conditions(&open_trade(50_000.0, 5, Some(3.0))),
+            None
+        );
+        assert_eq!(
+            strategy.unmet_buy_conditions(&open_trade(50_000.0, 5, None)),
+            None
+        );
+        assert_eq!(
+            strategy.unmet_buy_conditions(&open_trade(500_000.0, 2, Some(3.0))),
+            Some("2 wallets bought, min 3".to_string())
+        );
+        assert_eq!(
+            strategy.unmet_buy_conditions(&open_trade(50_000.0, 5, Some(1.5))),
+            Some("1.5 SOL bought, min 2 SOL".to_string())
+        );
+        assert_eq!(
+            strategy.unmet_buy_conditions(&open_trade(5_000.0, 5, Some(3.0))),
+            Some("market cap $5k outside $10k to $100k".to_string())
+        );
+
+        let mut slow = open_trade(50_000.0, 5, Some(3.0));
+        slow.time_window = 120;
+        assert_eq!(
+            strategy.unmet_buy_conditions(&slow),
+            Some("bought within 120 s, max 60 s".to_string())
+        );
+    }
+}
@@ -0,0 +1,89 @@
+use anyhow::Result;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time;
+
+use crate::storage::Storage;
+use crate::tg_copy::db;
+use crate::tg_copy::strategy::Strategy;
+
+/// The strategies of the `strategies` collection, loaded at startup and
+/// refreshed by [`run_strategy_refresh`]. Signals name their strategy, its
+/// buy and sell conditions are looked up here. Clones share the strategies.
+#[derive(Clone)]
+pub struct StrategyManager {
+    storage: Arc<dyn Storage>,
+    strategies: Arc<RwLock<Arc<Vec<Strategy>>>>,
+}
+
+impl StrategyManager {
+    pub async fn load(storage: Arc<dyn Storage>) -> Result<Self> {
+        let strategies = db::load_strategies(&*storage).await?;
+        Ok(Self::with_strategies(storage, strategies))
+    }
+
+    pub fn with_strategies(storage: Arc<dyn Storage>, strategies: Vec<Strategy>) -> Self {
+        Self {
+            storage,
+            strategies: Arc::new(RwLock::new(Arc::new(strategies))),
+        }
+    }
+
+    /// Reload the collection, the strategies loaded before are kept when it
+    /// cannot be read
+    pub async fn refresh(&self) -> Result<usize> {
+        let strategies = self.storage.load_strategies().await?;
+        let count = strategies.len();
+        *self.strategies.write().unwrap() = Arc::new(strategies);
+        Ok(count)
+    }
+
+    /// The strategies as of now, later refreshes don't change them
+    pub fn all(&self) -> Arc<Vec<Strategy>> {
+        Arc::clone(&self.strategies.read().unwrap())
+    }
+
+    /// The strategy named in a signal
+    pub fn find(&self, signal_strategy: &str) -> Option<Strategy> {
+        self.strategies
+            .read()
+            .unwrap()
+            .iter()
+            .find(|s| s.matches(signal_strategy))
+            .cloned()
+    }
+
+    /// Whether the collection has a strategy of this name, placeholders
+    /// created by the backfill don't count
+    pub fn is_known(&self, signal_strategy: &str) -> bool {
+        self.find(signal_strategy)
+            .is_some_and(|strategy| !strategy.is_placeholder)
+    }
+}
+
+/// Reload the strategies every `interval` until `shutdown` flips, so edits
+/// of the collection apply without a restart
+pub async fn run_strategy_refresh(
+    strategies: StrategyManager,
+    interval: Duration,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut interval = time::interval(interval);
+    // The first tick fires at once, the strategies were just loaded
+    interval.tick().await;
+    loop {
+        tokio::select! {
+            biased;
+            _ = shutdown.changed() => {
+                tracing::info!("Strategy refresh stopped");
+                return;
+            }
+            _ = interval.tick() => {}
+        }
+        match strategies.refresh().await {
+            Ok(count) => tracing::debug!("Refreshed {} strategies", count),
+            Err(e) => tracing::error!("Failed to refresh strategies: {:?}", e),
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::BTreeSet;
+
+use crate::storage::Storage;
+use crate::tg_copy::active_trade::ActiveTrade;
+use crate::tg_copy::db::{TradeDocument, TradeType};
+use crate::tg_copy::parse_trade::{parse_ticker_close, CloseTrade};
+use crate::tg_copy::strategy::normalize_strategy_id;
+
+/// Stored signals of the strategy searched for the ticker when no open
+/// position has it
+const HISTORY_LIMIT: i64 = 500;
+
+/// Mint of `ticker` in `strategy`: the open position with that token name,
+/// otherwise the latest open signal of the strategy with that token. `None`
+/// when nothing matches or the open positions hold several mints with the
+/// ticker, a close of the wrong one is worse than a missed close.
+pub fn resolve_ticker(
+    ticker: &str,
+    strategy: &str,
+    open_trades: &[ActiveTrade],
+    history: &[TradeDocument],
+) -> Option<String> {
+    let strategy = normalize_strategy_id(strategy);
+    let open: BTreeSet<&str> = open_trades
+        .iter()
+        .filter(|trade| {
+            trade.token_name.eq_ignore_ascii_case(ticker)
+                && normalize_strategy_id(&trade.strategy_id) == strategy
+        })
+        .map(|trade| trade.token_address.as_str())
+        .collect();
+    match open.len() {
+        0 => {}
+        1 => return open.first().map(|mint| mint.to_string()),
+        _ => {
+            tracing::warn!(
+                "{} of {} matches several open positions: {}",
+                ticker,
+                strategy,
+                open.into_iter().collect::<Vec<_>>().join(", ")
+            );
+            return None;
+        }
+    }
+    history
+        .iter()
+        .filter(|doc| {
+            matches!(doc.trade_type, TradeType::Open)
+                && doc.token.eq_ignore_ascii_case(ticker)
+                && normalize_strategy_id(&doc.strategy) == strategy
+                && !doc.contract_address.is_empty()
+        })
+        .max_by_key(|doc| doc.message_id)
+        .map(|doc| doc.contract_address.clone())
+}
+
+/// A close signal of `message` that names its token by ticker only, with
+/// the mint resolved from the open positions and stored signals of its
+/// strategy
+pub async fn resolve_ticker_close(storage: &dyn Storage, message: &str) -> Option<CloseTrade> {
+    let mut close = parse_ticker_close(message)?;
+    let open_trades = match storage.open_trades().await {
+        Ok(open_trades) => open_trades,
+        Err(e) => {
+            tracing::error!("Failed to load open positions for {}: {:?}", close.token, e);
+            return None;
+        }
+    };
+    let history = match storage
+        .recent_trades(Some(&close.strategy), HISTORY_LIMIT)
+        .await
+    {
+        Ok(history) => history,
+        Err(e) => {
+            tracing::error!("Failed to load signals of {}: {:?}", close.strategy, e);
+            Vec::new()
+        }
+    };
+    let Some(mint) = resolve_ticker(&close.token, &close.strategy, &open_trades, &history) else {
+        tracing::warn!(
+            "Close of {} ({}) has no CA and no known mint, ignored",
+            close.token,
+            close.strategy
+        );
+        return None;
+    };
+    tracing::info!(
+        "Close of {} ({}) has no CA, resolved to {}",
+        close.token,
+        close.strategy,
+        mint
+    );
+    close.contract_address = mint;
+    Some(close)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn open_signal(message_id: i64, strategy: &str, token: &str, mint: &str) -> TradeDocument {
+        TradeDocument {
+            message_id,
+            date: Utc::now(),
+            strategy: strategy.to_string(),
+            token: token.to_string(),
+            contract_address: mint.to_string(),
+            trade_type: TradeType::Open,
+            original_message: String::new(),
+            op_type: None,
+            buy_price: None,
+            num_buys: None,
+            total_buys: None,
+            time_window: None,
+            market_cap: None,
+            entry_price: None,
+            exit_price: None,
+            profit_pct: None,
+            strategy_ref: None,
+            our_profit_pct: None,
+            our_pnl_lamports: None,
+        }
+    }
+
+    #[test]
+    fn test_resolve_ticker() {
+        let open = vec![
+            ActiveTrade::new(
+                "ABYS".into(),
+                "mint_a".into(),
+                "degen_v2".into(),
+                1_000,
+                0.001,
+            ),
+            ActiveTrade::new("WIF".into(), "mint_w".into(), "safe".into(), 1_000, 0.001),
+        ];
+        let history = vec![
+            open_signal(10, "degenv2", "PEPE", "mint_p_old"),
+            open_signal(20, "degenv2", "PEPE", "mint_p"),
+            open_signal(30, "safe", "PEPE", "mint_p_safe"),
+        ];
+
+        // Open positions first, scoped by strategy
+        assert_eq!(
+            resolve_ticker("abys", "degenv2", &open, &history),
+            Some("mint_a".to_string())
+        );
+        assert_eq!(resolve_ticker("WIF", "degenv2", &open, &history), None);
+        // Then the latest open signal of the strategy
+        assert_eq!(
+            resolve_ticker("PEPE", "degen_v2", &open, &history),
+            Some("mint_p".to_string())
+        );
+
+        // Two open positions with the ticker are ambiguous
+        let mut twins = open.clone();
+        twins.push(ActiveTrade::new(
+            "ABYS".into(),
+            "mint_a2".into(),
+            "degenv2".into(),
+            1_000,
+            0.001,
+        ));
+        assert_eq!(resolve_ticker("ABYS", "degenv2", &twins, &history), None);
+    }
+}
@@ -0,0 +1,140 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use mongodb::{
+    bson::doc,
+    options::{FindOptions, IndexOptions, ReplaceOptions},
+    Collection, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::tg_copy::parse_trade::{extract_contract_address, parse_trade, Trade};
+
+/// Messages with an address that still didn't parse, likely a new format
+pub const REASON_UNKNOWN_FORMAT: &str = "unknown_format";
+/// Messages without an address, mostly chatter
+pub const REASON_NO_ADDRESS: &str = "no_address";
+
+/// A message of the followed chat that `parse_trade` gave up on, kept to
+/// improve the parser and to be parsed again by `reparse`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnparsedMessage {
+    pub chat_id: i64,
+    pub message_id: i64,
+    pub text: String,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub date: DateTime<Utc>,
+    pub reason: String,
+}
+
+/// Why `text` didn't parse, `None` for messages without text which are not
+/// worth keeping
+pub fn unparsed_reason(text: &str) -> Option<&'static str> {
+    if text.trim().is_empty() {
+        return None;
+    }
+    Some(match extract_contract_address(text) {
+        Some(_) => REASON_UNKNOWN_FORMAT,
+        None => REASON_NO_ADDRESS,
+    })
+}
+
+/// The `unparsed_messages` collection
+#[derive(Clone)]
+pub struct UnparsedMessages {
+    collection: Collection<UnparsedMessage>,
+}
+
+impl UnparsedMessages {
+    pub fn new(collection: Collection<UnparsedMessage>) -> Self {
+        Self { collection }
+    }
+
+    pub async fn setup_indexes(&self) -> Result<()> {
+        let index = IndexModel::builder()
+            .keys(doc! { "chat_id": 1, "message_id": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        self.collection.create_index(index, None).await?;
+        Ok(())
+    }
+
+    /// Keep `text` of message `message_id` when it is worth it, a message
+    /// seen again replaces the one kept
+    pub async fn record(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+        text: &str,
+        date: DateTime<Utc>,
+    ) -> Result<()> {
+        let Some(reason) = unparsed_reason(text) else {
+            return Ok(());
+        };
+        let message = UnparsedMessage {
+            chat_id,
+            message_id,
+            text: text.to_string(),
+            date,
+            reason: reason.to_string(),
+        };
+        self.collection
+            .replace_one(
+                doc! { "chat_id": chat_id, "message_id": message_id },
+                &message,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Messages kept with `reason`, or all of them, oldest first
+    pub async fn load(&self, reason: Option<&str>) -> Result<Vec<UnparsedMessage>> {
+        let filter = match reason {
+            Some(reason) => doc! { "reason": reason },
+            None => doc! {},
+        };
+        let options = FindOptions::builder().sort(doc! { "date": 1 }).build();
+        let mut cursor = self.collection.find(filter, options).await?;
+        let mut messages = Vec::new();
+        while cursor.advance().await? {
+            messages.push(cursor.deserialize_current()?);
+        }
+        Ok(messages)
+    }
+
+    pub async fn remove(&self, message: &UnparsedMessage) -> Result<()> {
+        self.collection
+            .delete_one(
+                doc! { "chat_id": message.chat_id, "message_id": message.message_id },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+/// The kept messages the current parser reads as a trade
+pub fn reparse(messages: Vec<UnparsedMessage>) -> Vec<(UnparsedMessage, Trade)> {
+    messages
+        .into_iter()
+        .filter_map(|message| {
+            let trade = parse_trade(&message.text)?;
+            Some((message, trade))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unparsed_reason() {
+        assert_eq!(unparsed_reason(" \n"), None);
+        assert_eq!(unparsed_reason("gm everyone"), Some(REASON_NO_ADDRESS));
+        assert_eq!(
+            unparsed_reason("New gem $WIF\nCA: EKpQGSJtjMFqKZ9KQanSqYXRcF8fBopzLHYxdM65zcjm"),
+            Some(REASON_UNKNOWN_FORMAT)
+        );
+    }
+}
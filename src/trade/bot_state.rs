@@ -0,0 +1,179 @@
+use anyhow::Result;
+use bson::doc;
+use mongodb::{options::ReplaceOptions, Collection};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::tg_copy::strategy::normalize_strategy_id;
+
+const STATE_ID: &str = "bot_state";
+
+/// Pause switches set by operators, a single document so every process
+/// sharing the database sees them. Only buys are paused, open positions
+/// keep being managed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BotState {
+    pub global_paused: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub global_reason: Option<String>,
+    #[serde(default)]
+    pub paused_strategies: Vec<PausedStrategy>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PausedStrategy {
+    pub strategy: String,
+    pub reason: String,
+}
+
+impl BotState {
+    /// Why buys of `strategy` are paused, `None` when they are allowed
+    pub fn buy_paused_reason(&self, strategy: &str) -> Option<String> {
+        if self.global_paused {
+            return Some(self.global_reason.clone().unwrap_or_default());
+        }
+        self.paused_strategies
+            .iter()
+            .find(|paused| {
+                normalize_strategy_id(&paused.strategy) == normalize_strategy_id(strategy)
+            })
+            .map(|paused| format!("{} paused: {}", paused.strategy, paused.reason))
+    }
+
+    /// Returns `true` if `strategy`, or everything when `None`, was not
+    /// paused already
+    pub fn pause(&mut self, strategy: Option<&str>, reason: &str) -> bool {
+        match strategy {
+            None => {
+                let changed = !self.global_paused;
+                self.global_paused = true;
+                self.global_reason = Some(reason.to_string());
+                changed
+            }
+            Some(strategy) => {
+                let changed = !self.resume(Some(strategy));
+                self.paused_strategies.push(PausedStrategy {
+                    strategy: strategy.to_string(),
+                    reason: reason.to_string(),
+                });
+                changed
+            }
+        }
+    }
+
+    /// Returns `true` if `strategy`, or everything when `None`, was paused.
+    /// Resuming everything leaves the strategies paused on their own.
+    pub fn resume(&mut self, strategy: Option<&str>) -> bool {
+        match strategy {
+            None => {
+                let changed = self.global_paused;
+                self.global_paused = false;
+                self.global_reason = None;
+                changed
+            }
+            Some(strategy) => {
+                let before = self.paused_strategies.len();
+                self.paused_strategies.retain(|paused| {
+                    normalize_strategy_id(&paused.strategy) != normalize_strategy_id(strategy)
+                });
+                self.paused_strategies.len() < before
+            }
+        }
+    }
+}
+
+impl fmt::Display for BotState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.global_paused, &self.global_reason) {
+            (true, Some(reason)) => write!(f, "All buys paused: {}", reason)?,
+            (true, None) => write!(f, "All buys paused")?,
+            (false, _) => write!(f, "Buys active")?,
+        }
+        for paused in &self.paused_strategies {
+            write!(f, "\n  {} paused: {}", paused.strategy, paused.reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// The `bot_state` collection
+#[derive(Clone)]
+pub struct BotStateStore {
+    collection: Collection<BotState>,
+}
+
+impl BotStateStore {
+    pub fn new(collection: Collection<BotState>) -> Self {
+        Self { collection }
+    }
+
+    pub async fn load(&self) -> Result<BotState> {
+        Ok(self
+            .collection
+            .find_one(doc! { "_id": STATE_ID }, None)
+            .await?
+            .unwrap_or_default())
+    }
+
+    async fn save(&self, state: &BotState) -> Result<()> {
+        self.collection
+            .replace_one(
+                doc! { "_id": STATE_ID },
+                state,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Pause the buys of `strategy`, of every strategy when `None`. Returns
+    /// the new state and whether it changed.
+    pub async fn pause(&self, strategy: Option<&str>, reason: &str) -> Result<(BotState, bool)> {
+        let mut state = self.load().await?;
+        let changed = state.pause(strategy, reason);
+        self.save(&state).await?;
+        Ok((state, changed))
+    }
+
+    /// Lift the pause of `strategy`, the global one when `None`. Returns
+    /// the new state and whether it changed.
+    pub async fn resume(&self, strategy: Option<&str>) -> Result<(BotState, bool)> {
+        let mut state = self.load().await?;
+        let changed = state.resume(strategy);
+        if changed {
+            self.save(&state).await?;
+        }
+        Ok((state, changed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pause_switches() {
+        let mut state = BotState::default();
+        assert_eq!(state.buy_paused_reason("degen"), None);
+
+        assert!(state.pause(Some("degen_v2"), "drawdown"));
+        assert!(!state.pause(Some("degenv2"), "still drawdown"));
+        assert_eq!(state.paused_strategies.len(), 1);
+        assert_eq!(
+            state.buy_paused_reason("degenv2"),
+            Some("degenv2 paused: still drawdown".to_string())
+        );
+        assert_eq!(state.buy_paused_reason("safe"), None);
+
+        assert!(state.pause(None, "maintenance"));
+        assert_eq!(
+            state.buy_paused_reason("safe"),
+            Some("maintenance".to_string())
+        );
+        assert!(state.resume(None));
+        assert!(!state.resume(None));
+        assert!(state.buy_paused_reason("degen_v2").is_some());
+        assert!(state.resume(Some("degen_v2")));
+        assert_eq!(state.buy_paused_reason("degen_v2"), None);
+    }
+}
@@ -0,0 +1,278 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use mongodb::{
+    bson::doc,
+    options::{IndexOptions, InsertManyOptions},
+    Collection, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time;
+
+use crate::config::CandleConfig;
+use crate::solana::price;
+use crate::storage::mongo::is_duplicate_key;
+use crate::trade::meme_trader::MemeTrader;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// `1s`, `5s`, `1m` or `1h` in seconds
+pub fn parse_interval(value: &str) -> Result<u64> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| anyhow!("Candle interval '{}' has no unit (s, m, h)", value))?;
+    let (count, unit) = value.split_at(split);
+    let count: u64 = count.parse()?;
+    let secs = match unit {
+        "s" => count,
+        "m" => count * 60,
+        "h" => count * 3600,
+        _ => return Err(anyhow!("Unknown unit of candle interval '{}'", value)),
+    };
+    if secs == 0 {
+        return Err(anyhow!("Candle interval '{}' is empty", value));
+    }
+    Ok(secs)
+}
+
+/// `5` -> `5s`, `60` -> `1m`
+pub fn interval_name(secs: u64) -> String {
+    if secs % 3600 == 0 {
+        format!("{}h", secs / 3600)
+    } else if secs % 60 == 0 {
+        format!("{}m", secs / 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/// USD price path of a held token over one interval
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Candle {
+    pub token_address: String,
+    /// `1s`, `5s`, `1m`...
+    pub interval: String,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub start: DateTime<Utc>,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    /// Prices sampled into the candle
+    pub samples: u32,
+}
+
+impl Candle {
+    fn new(token_address: &str, interval_secs: u64, start: DateTime<Utc>, price: f64) -> Self {
+        Self {
+            token_address: token_address.to_string(),
+            interval: interval_name(interval_secs),
+            start,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            samples: 1,
+        }
+    }
+
+    fn add(&mut self, price: f64) {
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+        self.samples += 1;
+    }
+}
+
+/// Start of the `interval_secs` bucket `at` falls in
+fn bucket_start(at: DateTime<Utc>, interval_secs: u64) -> DateTime<Utc> {
+    let secs = at.timestamp();
+    let start = secs - secs.rem_euclid(interval_secs as i64);
+    DateTime::from_timestamp(start, 0).unwrap_or(at)
+}
+
+/// Candles being sampled, per mint and interval
+#[derive(Debug, Default)]
+pub struct CandleBuilder {
+    open: HashMap<(String, u64), Candle>,
+}
+
+impl CandleBuilder {
+    /// Add a price sampled `at`, returns the candles it completed
+    pub fn sample(
+        &mut self,
+        token_address: &str,
+        intervals_secs: &[u64],
+        price: f64,
+        at: DateTime<Utc>,
+    ) -> Vec<Candle> {
+        let mut completed = Vec::new();
+        for &interval_secs in intervals_secs {
+            let start = bucket_start(at, interval_secs);
+            let key = (token_address.to_string(), interval_secs);
+            match self.open.get_mut(&key) {
+                Some(candle) if candle.start == start => candle.add(price),
+                _ => {
+                    let candle = Candle::new(token_address, interval_secs, start, price);
+                    completed.extend(self.open.insert(key, candle));
+                }
+            }
+        }
+        completed
+    }
+
+    /// Close the candles of mints no longer held, returns them
+    pub fn retain(&mut self, held: &[String]) -> Vec<Candle> {
+        let closed: Vec<(String, u64)> = self
+            .open
+            .keys()
+            .filter(|(mint, _)| !held.contains(mint))
+            .cloned()
+            .collect();
+        closed
+            .into_iter()
+            .filter_map(|key| self.open.remove(&key))
+            .collect()
+    }
+}
+
+/// The `candles` collection
+#[derive(Clone)]
+pub struct CandleStore {
+    collection: Collection<Candle>,
+}
+
+impl CandleStore {
+    pub fn new(collection: Collection<Candle>) -> Self {
+        Self { collection }
+    }
+
+    pub async fn setup_indexes(&self) -> Result<()> {
+        let index = IndexModel::builder()
+            .keys(doc! { "token_address": 1, "interval": 1, "start": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        self.collection.create_index(index, None).await?;
+        Ok(())
+    }
+
+    /// Candles already stored, by a run before a restart, are skipped
+    pub async fn insert(&self, candles: &[Candle]) -> Result<()> {
+        if candles.is_empty() {
+            return Ok(());
+        }
+        let options = InsertManyOptions::builder().ordered(false).build();
+        match self.collection.insert_many(candles, options).await {
+            Ok(_) => Ok(()),
+            Err(e) if is_duplicate_key(&e) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Sample the USD price of every held token each second from the price
+/// feed, the streamed pool price first, and store its candles of every
+/// configured interval as they complete. The last candles of a position
+/// are stored once it is closed.
+pub async fn run_candle_recorder(
+    trader: Arc<MemeTrader>,
+    store: CandleStore,
+    cfg: CandleConfig,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    store.setup_indexes().await?;
+    let mut builder = CandleBuilder::default();
+    let mut interval = time::interval(SAMPLE_INTERVAL);
+    interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+    tracing::info!("Candle recorder started");
+
+    loop {
+        tokio::select! {
+            biased;
+            _ = shutdown.changed() => {
+                if let Err(e) = store.insert(&builder.retain(&[])).await {
+                    tracing::warn!("Failed to store the last candles: {:?}", e);
+                }
+                tracing::info!("Candle recorder stopped");
+                return Ok(());
+            }
+            _ = interval.tick() => {}
+        }
+
+        // A mint held by several strategies is sampled once
+        let held: Vec<String> = match trader.active_trades().load_all_trades().await {
+            Ok(trades) => trades
+                .into_iter()
+                .map(|t| t.token_address)
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect(),
+            Err(e) => {
+                tracing::error!("Candle recorder failed to load active trades: {:?}", e);
+                continue;
+            }
+        };
+        let mut completed = builder.retain(&held);
+        let feed = price::feed();
+        let now = Utc::now();
+        for mint in &held {
+            match feed.price_usd(mint).await {
+                Ok(price) => {
+                    completed.extend(builder.sample(mint, &cfg.intervals_secs, price, now))
+                }
+                Err(e) => tracing::debug!("Candle recorder could not price {}: {:?}", mint, e),
+            }
+        }
+        if let Err(e) = store.insert(&completed).await {
+            tracing::warn!("Failed to store {} candles: {:?}", completed.len(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("1s").unwrap(), 1);
+        assert_eq!(parse_interval("5s").unwrap(), 5);
+        assert_eq!(parse_interval("1m").unwrap(), 60);
+        assert_eq!(parse_interval("4h").unwrap(), 14_400);
+        assert!(parse_interval("5").is_err());
+        assert!(parse_interval("0s").is_err());
+        assert!(parse_interval("1d").is_err());
+        assert_eq!(interval_name(60), "1m");
+        assert_eq!(interval_name(90), "90s");
+    }
+
+    #[test]
+    fn test_candle_builder() {
+        let at = |secs| DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap();
+        let mut builder = CandleBuilder::default();
+        let intervals = [1, 5];
+
+        assert!(builder.sample("mint", &intervals, 1.0, at(0)).is_empty());
+        // The next second completes the 1s candle only
+        let completed = builder.sample("mint", &intervals, 3.0, at(1));
+        assert_eq!(completed, vec![Candle::new("mint", 1, at(0), 1.0)]);
+        builder.sample("mint", &intervals, 0.5, at(2));
+        builder.sample("mint", &intervals, 2.0, at(4));
+
+        let completed = builder.sample("mint", &intervals, 2.5, at(5));
+        let five = completed.iter().find(|c| c.interval == "5s").unwrap();
+        assert_eq!(five.start, at(0));
+        assert_eq!(
+            (five.open, five.high, five.low, five.close, five.samples),
+            (1.0, 3.0, 0.5, 2.0, 4)
+        );
+
+        // A position closed flushes its open candles
+        assert_eq!(builder.retain(&["other".to_string()]).len(), 2);
+        assert!(builder.retain(&[]).is_empty());
+    }
+}
@@ -0,0 +1,137 @@
+use anyhow::{anyhow, Result};
+use bson::doc;
+use mongodb::{options::ReplaceOptions, Collection};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+
+const STATE_ID: &str = "capital";
+/// Requested withdrawals have to be confirmed within this many seconds
+pub const WITHDRAWAL_CONFIRM_SECS: i64 = 600;
+
+/// Locked capital and the withdrawal in progress, a single document
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CapitalState {
+    /// SOL that trading must never spend
+    pub locked_lamports: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending_withdrawal: Option<Withdrawal>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Withdrawal {
+    pub code: String,
+    pub destination: String,
+    pub lamports: u64,
+    pub requested_at: i64,
+    pub confirmed: bool,
+}
+
+impl Withdrawal {
+    pub fn is_expired(&self, now: i64) -> bool {
+        now - self.requested_at > WITHDRAWAL_CONFIRM_SECS
+    }
+}
+
+pub struct CapitalStore {
+    collection: Collection<CapitalState>,
+}
+
+impl CapitalStore {
+    pub fn new(collection: Collection<CapitalState>) -> Self {
+        Self { collection }
+    }
+
+    pub async fn load(&self) -> Result<CapitalState> {
+        Ok(self
+            .collection
+            .find_one(doc! { "_id": STATE_ID }, None)
+            .await?
+            .unwrap_or_default())
+    }
+
+    async fn save(&self, state: &CapitalState) -> Result<()> {
+        self.collection
+            .replace_one(
+                doc! { "_id": STATE_ID },
+                state,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Set the amount of SOL trading has to leave untouched
+    pub async fn lock(&self, lamports: u64) -> Result<()> {
+        let mut state = self.load().await?;
+        state.locked_lamports = lamports;
+        self.save(&state).await
+    }
+
+    /// First step of a withdrawal, returns the code that confirms it
+    pub async fn request_withdrawal(
+        &self,
+        destination: &Pubkey,
+        lamports: u64,
+        allowlist: &[Pubkey],
+    ) -> Result<Withdrawal> {
+        if !allowlist.contains(destination) {
+            return Err(anyhow!(
+                "{} is not in WITHDRAW_ALLOWLIST, withdrawals can only go to allow-listed addresses",
+                destination
+            ));
+        }
+        if lamports == 0 {
+            return Err(anyhow!("Withdrawal amount must be positive"));
+        }
+
+        let withdrawal = Withdrawal {
+            code: format!("{:06}", rand::thread_rng().gen_range(0..1_000_000)),
+            destination: destination.to_string(),
+            lamports,
+            requested_at: chrono::Utc::now().timestamp(),
+            confirmed: false,
+        };
+        let mut state = self.load().await?;
+        state.pending_withdrawal = Some(withdrawal.clone());
+        self.save(&state).await?;
+        Ok(withdrawal)
+    }
+
+    /// Second step of a withdrawal, after which the signer accepts the
+    /// transfer it describes
+    pub async fn confirm_withdrawal(&self, code: &str) -> Result<Withdrawal> {
+        let mut state = self.load().await?;
+        let withdrawal = state
+            .pending_withdrawal
+            .as_mut()
+            .ok_or_else(|| anyhow!("No withdrawal is pending"))?;
+        if withdrawal.is_expired(chrono::Utc::now().timestamp()) {
+            return Err(anyhow!("The pending withdrawal expired, request it again"));
+        }
+        if withdrawal.code != code {
+            return Err(anyhow!("Wrong confirmation code"));
+        }
+        withdrawal.confirmed = true;
+        let withdrawal = withdrawal.clone();
+        self.save(&state).await?;
+        Ok(withdrawal)
+    }
+
+    /// Clear the executed withdrawal, the withdrawn SOL is released from the
+    /// lock first
+    pub async fn complete_withdrawal(&self, lamports: u64) -> Result<()> {
+        let mut state = self.load().await?;
+        state.pending_withdrawal = None;
+        state.locked_lamports = state.locked_lamports.saturating_sub(lamports);
+        self.save(&state).await
+    }
+
+    /// Returns whether a withdrawal was pending
+    pub async fn cancel_withdrawal(&self) -> Result<bool> {
+        let mut state = self.load().await?;
+        let cancelled = state.pending_withdrawal.take().is_some();
+        self.save(&state).await?;
+        Ok(cancelled)
+    }
+}
@@ -0,0 +1,239 @@
+use anyhow::Result;
+use serde::Serialize;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{commitment_config::CommitmentConfig, program_pack::Pack, pubkey::Pubkey};
+use spl_associated_token_account::get_associated_token_address;
+use std::fmt;
+
+use crate::format;
+use crate::solana::price::sol_price_usd;
+use crate::solana::transaction::get_transaction_fee;
+use crate::trade::execution::{usd_value, Execution};
+
+/// Lamports charged per transaction signature
+const BASE_FEE_LAMPORTS: u64 = 5_000;
+/// Rough compute budget used by a single swap transaction
+const ESTIMATED_SWAP_COMPUTE_UNITS: u64 = 200_000;
+
+/// Expected costs of a full round trip (buy now, sell later) in lamports.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TradeCostEstimate {
+    pub entry_fee_lamports: u64,
+    pub exit_fee_lamports: u64,
+    /// Part of the entry and exit fees paid for priority
+    pub priority_fee_lamports: u64,
+    pub tip_lamports: u64,
+    pub ata_rent_lamports: u64,
+    /// Only used to show the costs in USD, `None` when the feed failed
+    pub sol_price_usd: Option<f64>,
+}
+
+impl TradeCostEstimate {
+    pub fn total_lamports(&self) -> u64 {
+        self.entry_fee_lamports
+            + self.exit_fee_lamports
+            + self.tip_lamports
+            + self.ata_rent_lamports
+    }
+
+    /// Share of the position that is eaten by costs
+    pub fn cost_fraction(&self, position_lamports: u64) -> f64 {
+        if position_lamports == 0 {
+            return f64::INFINITY;
+        }
+        self.total_lamports() as f64 / position_lamports as f64
+    }
+}
+
+impl fmt::Display for TradeCostEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let price = self.sol_price_usd;
+        write!(
+            f,
+            "total {} (entry fee {}, exit fee {}, priority fees {}, tips {}, ATA rent {})",
+            format::lamports_usd(self.total_lamports(), price),
+            format::lamports_usd(self.entry_fee_lamports, price),
+            format::lamports_usd(self.exit_fee_lamports, price),
+            format::lamports_usd(self.priority_fee_lamports, price),
+            format::lamports_usd(self.tip_lamports, price),
+            format::lamports_usd(self.ata_rent_lamports, price)
+        )
+    }
+}
+
+/// What a landed buy or sell paid on top of the swapped amount. Serialized
+/// fields are available to the notification templates.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ExecutionCosts {
+    /// Transaction fee, base and priority fee together
+    pub fee_lamports: u64,
+    pub priority_fee_lamports: u64,
+    pub tip_lamports: u64,
+    pub total_lamports: u64,
+    /// USD price of SOL when the costs were read, `None` when the feed failed
+    pub sol_price_usd: Option<f64>,
+}
+
+impl fmt::Display for ExecutionCosts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let price = self.sol_price_usd;
+        write!(
+            f,
+            "{} (fee {}, priority fee {}, tip {})",
+            format::lamports_usd(self.total_lamports, price),
+            format::lamports_usd(self.fee_lamports, price),
+            format::lamports_usd(self.priority_fee_lamports, price),
+            format::lamports_usd(self.tip_lamports, price)
+        )
+    }
+}
+
+/// Costs of the landed transaction `tx_sig` sent with a tip of
+/// `tip_lamports`
+pub async fn execution_costs(
+    rpc_client: &RpcClient,
+    tx_sig: &str,
+    tip_lamports: u64,
+) -> Result<ExecutionCosts> {
+    let (fee_lamports, signatures) = get_transaction_fee(rpc_client, tx_sig).await?;
+    let sol_price_usd = match sol_price_usd().await {
+        Ok(price) => Some(price),
+        Err(e) => {
+            tracing::warn!("Could not price the costs of {} in USD: {:?}", tx_sig, e);
+            None
+        }
+    };
+    Ok(ExecutionCosts {
+        fee_lamports,
+        priority_fee_lamports: fee_lamports.saturating_sub(BASE_FEE_LAMPORTS * signatures as u64),
+        tip_lamports,
+        total_lamports: fee_lamports + tip_lamports,
+        sol_price_usd,
+    })
+}
+
+/// Fees and tips of a set of executions, for the daily digest
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CostReport {
+    /// Executions with known costs
+    pub fills: usize,
+    pub fee_lamports: u64,
+    pub priority_fee_lamports: u64,
+    pub tip_lamports: u64,
+    pub total_lamports: u64,
+    /// Each fill valued at the SOL price of its time, `None` when none was
+    /// priced
+    pub total_usd: Option<f64>,
+}
+
+pub fn cost_report(executions: &[Execution]) -> CostReport {
+    let mut report = CostReport::default();
+    for execution in executions {
+        let Some(fee_lamports) = execution.fee_lamports else {
+            continue;
+        };
+        let priority_fee_lamports = execution.priority_fee_lamports.unwrap_or(0);
+        let tip_lamports = execution.tip_lamports.unwrap_or(0);
+        let total_lamports = fee_lamports + tip_lamports;
+        report.fills += 1;
+        report.fee_lamports += fee_lamports;
+        report.priority_fee_lamports += priority_fee_lamports;
+        report.tip_lamports += tip_lamports;
+        report.total_lamports += total_lamports;
+        if let Some(price) = execution.sol_price_usd {
+            *report.total_usd.get_or_insert(0.0) += usd_value(total_lamports, price);
+        }
+    }
+    report
+}
+
+/// Estimate the costs of buying `mint` and selling it again later, assuming
+/// both legs pay the same tip and the current priority fee level.
+pub async fn estimate_trade_costs(
+    rpc_client: &RpcClient,
+    owner: &Pubkey,
+    mint: &Pubkey,
+    tip_lamports: u64,
+) -> Result<TradeCostEstimate> {
+    let priority_fee = estimate_priority_fee(rpc_client).await?;
+    let tx_fee = BASE_FEE_LAMPORTS + priority_fee;
+
+    let ata = get_associated_token_address(owner, mint);
+    let ata_exists = rpc_client
+        .get_account_with_commitment(&ata, CommitmentConfig::processed())
+        .await?
+        .value
+        .is_some();
+    let ata_rent_lamports = if ata_exists {
+        0
+    } else {
+        rpc_client
+            .get_minimum_balance_for_rent_exemption(spl_token::state::Account::LEN)
+            .await?
+    };
+
+    Ok(TradeCostEstimate {
+        entry_fee_lamports: tx_fee,
+        exit_fee_lamports: tx_fee,
+        priority_fee_lamports: priority_fee * 2,
+        tip_lamports: tip_lamports * 2,
+        ata_rent_lamports,
+        sol_price_usd: sol_price_usd().await.ok(),
+    })
+}
+
+/// Median recent prioritization fee applied to a typical swap, in lamports
+async fn estimate_priority_fee(rpc_client: &RpcClient) -> Result<u64> {
+    let mut micro_lamports_per_cu: Vec<u64> = rpc_client
+        .get_recent_prioritization_fees(&[])
+        .await?
+        .iter()
+        .map(|fee| fee.prioritization_fee)
+        .collect();
+    micro_lamports_per_cu.sort_unstable();
+
+    let median = micro_lamports_per_cu
+        .get(micro_lamports_per_cu.len() / 2)
+        .copied()
+        .unwrap_or(0);
+
+    Ok(median.saturating_mul(ESTIMATED_SWAP_COMPUTE_UNITS) / 1_000_000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tg_copy::db::TradeType;
+
+    fn execution(fee_lamports: Option<u64>, sol_price_usd: Option<f64>) -> Execution {
+        Execution {
+            message_id: Some(1),
+            reason: "Open signal".to_string(),
+            tx_sig: Some("sig".to_string()),
+            sol_price_usd,
+            fee_lamports,
+            priority_fee_lamports: fee_lamports.map(|fee| fee - 5_000),
+            tip_lamports: fee_lamports.map(|_| 1_000_000),
+            ..Execution::for_test(TradeType::Open, "WIF")
+        }
+    }
+
+    #[test]
+    fn test_cost_report() {
+        let report = cost_report(&[
+            execution(Some(105_000), Some(150.0)),
+            execution(Some(25_000), None),
+            // Failed or recorded before costs were
+            execution(None, Some(150.0)),
+        ]);
+        assert_eq!(report.fills, 2);
+        assert_eq!(report.fee_lamports, 130_000);
+        assert_eq!(report.priority_fee_lamports, 120_000);
+        assert_eq!(report.tip_lamports, 2_000_000);
+        assert_eq!(report.total_lamports, 2_130_000);
+        // Only the priced fill is valued
+        assert!((report.total_usd.unwrap() - 0.16575).abs() < 1e-9);
+
+        assert!(cost_report(&[]).total_usd.is_none());
+    }
+}
@@ -0,0 +1,472 @@
+use anyhow::Result;
+use chrono::{DateTime, SecondsFormat, Utc};
+use mongodb::{
+    bson::{doc, oid::ObjectId, Document},
+    options::FindOptions,
+    Collection, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::config::LatencySloConfig;
+use crate::solana::price::sol_price_usd;
+use crate::solana::simulate::NotSent;
+use crate::solana::token_registry::token_registry;
+use crate::solana::transaction::{TxNotConfirmed, TxStatus};
+use crate::tg_copy::db::TradeType;
+use crate::trade::costs::ExecutionCosts;
+use crate::trade::latency::{slo_breaches, StageLatency};
+
+/// Where a buy or sell was routed
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Venue {
+    PumpFun,
+    Raydium,
+    Orca,
+    Meteora,
+    /// Jupiter's route, when no supported pool was found
+    Jupiter,
+}
+
+impl fmt::Display for Venue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Venue::PumpFun => write!(f, "pump.fun"),
+            Venue::Raydium => write!(f, "Raydium"),
+            Venue::Orca => write!(f, "Orca"),
+            Venue::Meteora => write!(f, "Meteora"),
+            Venue::Jupiter => write!(f, "Jupiter"),
+        }
+    }
+}
+
+/// Telegram message a trade copies
+#[derive(Debug, Clone)]
+pub struct Signal {
+    /// `None` for signals injected without a message
+    pub message_id: Option<i64>,
+    pub posted_at: DateTime<Utc>,
+    /// When the listener saw the message
+    pub received_at: DateTime<Utc>,
+    /// When a trade worker picked it up
+    pub started_at: DateTime<Utc>,
+    /// USD price quoted by the message
+    pub price: f64,
+}
+
+/// What the bot actually did on a buy or sell, successful or not. Stored in
+/// the `executions` collection next to the signal's trade document.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Execution {
+    #[serde(rename = "_id", default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub date: DateTime<Utc>,
+    /// `None` for exits not triggered by a signal (manual, liquidity)
+    pub message_id: Option<i64>,
+    pub strategy: String,
+    pub token: String,
+    pub contract_address: String,
+    pub trade_type: TradeType,
+    pub reason: String,
+    pub tx_sig: Option<String>,
+    /// Whether the transaction confirmed, failed, expired or was only
+    /// simulated. Missing when nothing was sent and on records older than
+    /// the field.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx_status: Option<TxStatus>,
+    pub venue: Option<Venue>,
+    /// Raw token amount bought or sold
+    pub token_amount: Option<u64>,
+    /// SOL spent on a buy or received from a sell, fees included
+    pub sol_lamports: Option<u64>,
+    pub signal_price: Option<f64>,
+    /// USD price per token of the fill
+    pub effective_price: Option<f64>,
+    /// Percent the fill was worse than the signal price, negative when better
+    pub slippage_pct: Option<f64>,
+    /// From the message timestamp to the confirmed transaction
+    pub latency_ms: Option<i64>,
+    /// Where `latency_ms` went
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stages: Option<StageLatency>,
+    pub error: Option<String>,
+    /// USD price of SOL at `date`. Missing on records older than the field
+    /// until the USD backfill reaches them, `null` when no price was found.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sol_price_usd: Option<f64>,
+    /// `sol_lamports` in USD at `sol_price_usd`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usd_value: Option<f64>,
+    /// Transaction fee of the fill, base and priority fee together
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fee_lamports: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority_fee_lamports: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tip_lamports: Option<u64>,
+    /// Profile that traded, when several run side by side
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+}
+
+/// USD price per whole token of a fill
+pub fn effective_price_usd(
+    sol_lamports: u64,
+    token_amount: u64,
+    decimals: u8,
+    sol_price_usd: f64,
+) -> Option<f64> {
+    if token_amount == 0 {
+        return None;
+    }
+    let tokens = token_amount as f64 / 10f64.powi(decimals as i32);
+    Some(sol_lamports as f64 / 1e9 * sol_price_usd / tokens)
+}
+
+/// USD value of `sol_lamports` at `sol_price_usd`
+pub fn usd_value(sol_lamports: u64, sol_price_usd: f64) -> f64 {
+    sol_lamports as f64 / 1e9 * sol_price_usd
+}
+
+/// Percent `effective` is worse than `signal`: paying more on a buy,
+/// receiving less on a sell
+pub fn slippage_pct(trade_type: &TradeType, signal: f64, effective: f64) -> Option<f64> {
+    if signal <= 0.0 {
+        return None;
+    }
+    let pct = (effective - signal) / signal * 100.0;
+    Some(match trade_type {
+        TradeType::Open => pct,
+        TradeType::Close => -pct,
+    })
+}
+
+/// Outcome of a confirmed transaction to record
+pub struct ExecutedFill<'a> {
+    pub tx_sig: &'a str,
+    pub venue: Venue,
+    pub token_amount: u64,
+    pub sol_lamports: u64,
+    /// `None` when the transaction fee could not be read
+    pub costs: Option<ExecutionCosts>,
+    /// When the swap was requested, after the checks of the signal
+    pub submitted_at: DateTime<Utc>,
+    pub confirmed_at: DateTime<Utc>,
+}
+
+#[derive(Clone)]
+pub struct ExecutionLog {
+    collection: Collection<Execution>,
+    latency_slo: Option<LatencySloConfig>,
+    profile: Option<String>,
+}
+
+impl ExecutionLog {
+    pub fn new(collection: Collection<Execution>) -> Self {
+        Self {
+            collection,
+            latency_slo: None,
+            profile: None,
+        }
+    }
+
+    /// Tag every record with the profile that traded
+    pub fn with_profile(mut self, profile: String) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    /// Warn about buys filled slower than the SLO
+    pub fn with_latency_slo(mut self, latency_slo: LatencySloConfig) -> Self {
+        self.latency_slo = Some(latency_slo);
+        self
+    }
+
+    pub async fn setup_indexes(&self) -> Result<()> {
+        self.collection
+            .create_index(
+                IndexModel::builder().keys(doc! { "message_id": 1 }).build(),
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Record a buy or sell. `fill` is `Err` with the failure when nothing
+    /// was executed.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn record(
+        &self,
+        trade_type: TradeType,
+        strategy: &str,
+        token: &str,
+        contract_address: &str,
+        reason: &str,
+        signal: Option<&Signal>,
+        fill: Result<ExecutedFill<'_>, &anyhow::Error>,
+    ) {
+        let mut execution = Execution {
+            id: None,
+            date: Utc::now(),
+            message_id: signal.and_then(|s| s.message_id),
+            strategy: strategy.to_string(),
+            token: token.to_string(),
+            contract_address: contract_address.to_string(),
+            trade_type,
+            reason: reason.to_string(),
+            tx_sig: None,
+            tx_status: None,
+            venue: None,
+            token_amount: None,
+            sol_lamports: None,
+            signal_price: signal.map(|s| s.price),
+            effective_price: None,
+            slippage_pct: None,
+            latency_ms: None,
+            stages: None,
+            error: None,
+            sol_price_usd: None,
+            usd_value: None,
+            fee_lamports: None,
+            priority_fee_lamports: None,
+            tip_lamports: None,
+            profile: self.profile.clone(),
+        };
+        match fill {
+            Ok(fill) => {
+                execution.tx_sig = Some(fill.tx_sig.to_string());
+                execution.tx_status = Some(TxStatus::Confirmed);
+                execution.venue = Some(fill.venue);
+                execution.token_amount = Some(fill.token_amount);
+                execution.sol_lamports = Some(fill.sol_lamports);
+                if let Some(costs) = &fill.costs {
+                    execution.fee_lamports = Some(costs.fee_lamports);
+                    execution.priority_fee_lamports = Some(costs.priority_fee_lamports);
+                    execution.tip_lamports = Some(costs.tip_lamports);
+                }
+                execution.latency_ms =
+                    signal.map(|s| (fill.confirmed_at - s.posted_at).num_milliseconds());
+                execution.stages =
+                    signal.map(|s| StageLatency::new(s, fill.submitted_at, fill.confirmed_at));
+                if let Some((slo, latency_ms)) = self.latency_slo.as_ref().zip(execution.latency_ms)
+                {
+                    let breaches = slo_breaches(slo, latency_ms, execution.stages.as_ref());
+                    if matches!(execution.trade_type, TradeType::Open) && !breaches.is_empty() {
+                        tracing::warn!(
+                            "Buy of {} missed the latency SLO: {}",
+                            token,
+                            breaches.join(", ")
+                        );
+                    }
+                }
+                // Left unset when pricing fails, the USD backfill fills it later
+                let sol_price = match fill.costs.and_then(|costs| costs.sol_price_usd) {
+                    Some(sol_price) => Ok(sol_price),
+                    None => sol_price_usd().await,
+                };
+                match sol_price {
+                    Ok(sol_price) => {
+                        execution.sol_price_usd = Some(sol_price);
+                        execution.usd_value = Some(usd_value(fill.sol_lamports, sol_price));
+                        match fill_price_usd(
+                            contract_address,
+                            fill.sol_lamports,
+                            fill.token_amount,
+                            sol_price,
+                        )
+                        .await
+                        {
+                            Ok(price) => execution.effective_price = price,
+                            Err(e) => {
+                                tracing::warn!(
+                                    "Could not price the fill of {}: {:?}",
+                                    fill.tx_sig,
+                                    e
+                                )
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        tracing::warn!("Could not price the fill of {}: {:?}", fill.tx_sig, e)
+                    }
+                }
+                execution.slippage_pct = execution
+                    .signal_price
+                    .zip(execution.effective_price)
+                    .and_then(|(signal, effective)| {
+                        slippage_pct(&execution.trade_type, signal, effective)
+                    });
+            }
+            // Not a failure, the swap did what `TRADE_MODE=simulate` asks
+            Err(e) if e.downcast_ref::<NotSent>().is_some() => {
+                execution.tx_status = Some(TxStatus::Simulated);
+            }
+            Err(e) => {
+                if let Some(not_confirmed) = e.downcast_ref::<TxNotConfirmed>() {
+                    execution.tx_sig = Some(not_confirmed.signature.clone());
+                    execution.tx_status = Some(not_confirmed.status.clone());
+                }
+                execution.error = Some(format!("{:#}", e));
+            }
+        }
+
+        // The trade already happened, a lost record must not fail it
+        if let Err(e) = self.collection.insert_one(&execution, None).await {
+            tracing::error!("Failed to record execution {:?}: {:?}", execution, e);
+        }
+    }
+
+    /// Buys attempted since `since`, failed ones included
+    pub async fn buys_since(&self, since: DateTime<Utc>) -> Result<Vec<Execution>> {
+        self.find_since(doc! { "trade_type": "Open" }, since).await
+    }
+
+    /// Buys and sells attempted since `since`, failed ones included
+    pub async fn since(&self, since: DateTime<Utc>) -> Result<Vec<Execution>> {
+        self.find_since(doc! {}, since).await
+    }
+
+    async fn find_since(
+        &self,
+        mut filter: Document,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<Execution>> {
+        let mut executions = Vec::new();
+        // Dates are stored as RFC 3339 strings, which sort by time
+        filter.insert(
+            "date",
+            doc! { "$gte": since.to_rfc3339_opts(SecondsFormat::Secs, true) },
+        );
+        let mut cursor = self.collection.find(filter, None).await?;
+        while cursor.advance().await? {
+            executions.push(cursor.deserialize_current()?);
+        }
+        Ok(executions)
+    }
+
+    /// Executed buys and sells not valued in USD yet, oldest first
+    pub async fn unvalued(&self, limit: i64) -> Result<Vec<Execution>> {
+        let mut executions = Vec::new();
+        let mut cursor = self
+            .collection
+            .find(
+                doc! {
+                    "sol_lamports": { "$ne": null },
+                    "sol_price_usd": { "$exists": false },
+                },
+                FindOptions::builder()
+                    .sort(doc! { "date": 1 })
+                    .limit(limit)
+                    .build(),
+            )
+            .await?;
+        while cursor.advance().await? {
+            executions.push(cursor.deserialize_current()?);
+        }
+        Ok(executions)
+    }
+
+    /// Store the USD valuation of execution `id`. `None` marks a price that
+    /// cannot be found so the record is not tried again.
+    pub async fn set_usd_value(
+        &self,
+        id: ObjectId,
+        sol_lamports: u64,
+        sol_price_usd: Option<f64>,
+    ) -> Result<()> {
+        self.collection
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": {
+                    "sol_price_usd": sol_price_usd,
+                    "usd_value": sol_price_usd.map(|price| usd_value(sol_lamports, price)),
+                } },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+async fn fill_price_usd(
+    mint: &str,
+    sol_lamports: u64,
+    token_amount: u64,
+    sol_price_usd: f64,
+) -> Result<Option<f64>> {
+    let decimals = token_registry().decimals(mint).await?;
+    Ok(effective_price_usd(
+        sol_lamports,
+        token_amount,
+        decimals,
+        sol_price_usd,
+    ))
+}
+
+#[cfg(test)]
+impl Execution {
+    /// A `trade_type` of `token` by the "degen" strategy with nothing
+    /// executed, tests set the fields they look at
+    pub(crate) fn for_test(trade_type: TradeType, token: &str) -> Self {
+        Self {
+            id: None,
+            date: Utc::now(),
+            message_id: None,
+            strategy: "degen".to_string(),
+            token: token.to_string(),
+            contract_address: String::new(),
+            trade_type,
+            reason: String::new(),
+            tx_sig: None,
+            tx_status: None,
+            venue: None,
+            token_amount: None,
+            sol_lamports: None,
+            signal_price: None,
+            effective_price: None,
+            slippage_pct: None,
+            latency_ms: None,
+            stages: None,
+            error: None,
+            sol_price_usd: None,
+            usd_value: None,
+            fee_lamports: None,
+            priority_fee_lamports: None,
+            tip_lamports: None,
+            profile: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_effective_price() {
+        // 0.5 SOL at $200 for 1000 tokens of 6 decimals
+        assert_eq!(
+            effective_price_usd(500_000_000, 1_000_000_000, 6, 200.0),
+            Some(0.1)
+        );
+        assert_eq!(effective_price_usd(500_000_000, 0, 6, 200.0), None);
+    }
+
+    #[test]
+    fn test_slippage_direction() {
+        assert_eq!(
+            slippage_pct(&TradeType::Open, 1.0, 1.1).map(|p| p.round()),
+            Some(10.0)
+        );
+        assert_eq!(
+            slippage_pct(&TradeType::Close, 1.0, 0.9).map(|p| p.round()),
+            Some(10.0)
+        );
+        assert_eq!(
+            slippage_pct(&TradeType::Close, 1.0, 1.2).map(|p| p.round()),
+            Some(-20.0)
+        );
+        assert_eq!(slippage_pct(&TradeType::Open, 0.0, 1.0), None);
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time;
+
+use crate::format;
+use crate::tg_copy::active_trade::ActiveTrade;
+use crate::trade::meme_trader::MemeTrader;
+
+const REPORT_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Open exposure to one mint summed over every position holding it
+#[derive(Debug, Clone)]
+pub struct MintExposure {
+    pub token_name: String,
+    pub token_address: String,
+    pub strategies: BTreeSet<String>,
+    pub providers: BTreeSet<String>,
+    /// Cost basis of the remaining holdings
+    pub cost_lamports: u64,
+}
+
+impl MintExposure {
+    /// Held by more than one strategy or provider, which per-strategy limits
+    /// don't see
+    pub fn is_overlapping(&self) -> bool {
+        self.strategies.len() > 1 || self.providers.len() > 1
+    }
+}
+
+impl fmt::Display for MintExposure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}): {} across strategies [{}]",
+            self.token_name,
+            self.token_address,
+            format::lamports(self.cost_lamports),
+            self.strategies
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", ")
+        )?;
+        if !self.providers.is_empty() {
+            write!(
+                f,
+                " from [{}]",
+                self.providers
+                    .iter()
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Cost basis of the open positions in `mint`, over every strategy
+pub fn mint_cost_lamports(trades: &[ActiveTrade], mint: &str) -> u64 {
+    trades
+        .iter()
+        .filter(|trade| trade.token_address == mint)
+        .map(ActiveTrade::remaining_cost_lamports)
+        .sum()
+}
+
+/// Lamports a buy of `size_lamports` may spend with `open_lamports` already
+/// in its mint and `max_lamports` allowed there, 0 once the cap is reached
+pub fn capped_buy_lamports(open_lamports: u64, max_lamports: u64, size_lamports: u64) -> u64 {
+    max_lamports
+        .saturating_sub(open_lamports)
+        .min(size_lamports)
+}
+
+/// Exposure per mint, largest first
+pub fn exposure_by_mint(trades: &[ActiveTrade]) -> Vec<MintExposure> {
+    let mut by_mint: BTreeMap<&str, MintExposure> = BTreeMap::new();
+    for trade in trades {
+        let exposure = by_mint
+            .entry(&trade.token_address)
+            .or_insert_with(|| MintExposure {
+                token_name: trade.token_name.clone(),
+                token_address: trade.token_address.clone(),
+                strategies: BTreeSet::new(),
+                providers: BTreeSet::new(),
+                cost_lamports: 0,
+            });
+        exposure.strategies.insert(trade.strategy_id.clone());
+        exposure.providers.extend(trade.provider.clone());
+        exposure.cost_lamports += trade.remaining_cost_lamports();
+    }
+
+    let mut exposures: Vec<_> = by_mint.into_values().collect();
+    exposures.sort_by(|a, b| b.cost_lamports.cmp(&a.cost_lamports));
+    exposures
+}
+
+/// Logs the share of open exposure sitting in overlapping mints every few
+/// minutes
+pub async fn run_exposure_monitor(trader: Arc<MemeTrader>, mut shutdown: watch::Receiver<bool>) {
+    let mut interval = time::interval(REPORT_INTERVAL);
+    loop {
+        tokio::select! {
+            biased;
+            _ = shutdown.changed() => return,
+            _ = interval.tick() => {}
+        }
+
+        let trades = match trader.active_trades().load_all_trades().await {
+            Ok(trades) => trades,
+            Err(e) => {
+                tracing::error!("Exposure monitor failed to load active trades: {:?}", e);
+                continue;
+            }
+        };
+        let exposures = exposure_by_mint(&trades);
+        let total: u64 = exposures.iter().map(|e| e.cost_lamports).sum();
+        let overlapping: Vec<_> = exposures.iter().filter(|e| e.is_overlapping()).collect();
+        if overlapping.is_empty() {
+            continue;
+        }
+
+        let overlapping_total: u64 = overlapping.iter().map(|e| e.cost_lamports).sum();
+        tracing::info!(
+            "Overlapping exposure: {} mints, {} of {} open ({:.1}%)",
+            overlapping.len(),
+            format::lamports(overlapping_total),
+            format::lamports(total),
+            overlapping_total as f64 / total.max(1) as f64 * 100.0
+        );
+        for exposure in overlapping {
+            tracing::info!("  {}", exposure);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(mint: &str, strategy: &str, provider: &str, spent: u64) -> ActiveTrade {
+        let mut trade = ActiveTrade::new(
+            mint.to_string(),
+            mint.to_string(),
+            strategy.to_string(),
+            1_000,
+            1.0,
+        );
+        trade.provider = Some(provider.to_string());
+        trade.sol_spent_lamports = spent;
+        trade
+    }
+
+    #[test]
+    fn test_exposure_by_mint() {
+        let mut half_sold = trade("WIF", "degen", "alpha", 400);
+        half_sold.remaining_holdings = 500;
+        let trades = vec![
+            trade("BONK", "degen", "alpha", 100),
+            half_sold,
+            trade("WIF", "shaved", "beta", 300),
+        ];
+
+        let exposures = exposure_by_mint(&trades);
+        assert_eq!(exposures.len(), 2);
+        assert_eq!(exposures[0].token_address, "WIF");
+        assert_eq!(exposures[0].cost_lamports, 500);
+        assert_eq!(exposures[0].strategies.len(), 2);
+        assert_eq!(exposures[0].providers.len(), 2);
+        assert!(exposures[0].is_overlapping());
+        assert!(!exposures[1].is_overlapping());
+        assert_eq!(mint_cost_lamports(&trades, "WIF"), 500);
+    }
+
+    #[test]
+    fn test_capped_buy_lamports() {
+        assert_eq!(capped_buy_lamports(0, 1_000, 400), 400);
+        assert_eq!(capped_buy_lamports(800, 1_000, 400), 200);
+        assert_eq!(capped_buy_lamports(1_200, 1_000, 400), 0);
+    }
+}
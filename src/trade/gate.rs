@@ -0,0 +1,56 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::RwLock;
+
+/// Shared switch that blocks new buys while any source holds a pause.
+/// Sells are never gated, open positions keep being managed.
+#[derive(Debug, Default)]
+pub struct BuyGate {
+    /// source -> reason
+    pauses: RwLock<BTreeMap<String, String>>,
+}
+
+impl BuyGate {
+    /// Pause buys on behalf of `source`. Returns `true` if `source` was not
+    /// already holding a pause.
+    pub fn pause(&self, source: &str, reason: impl Into<String>) -> bool {
+        self.pauses
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(source.to_string(), reason.into())
+            .is_none()
+    }
+
+    /// Release the pause held by `source`. Returns `true` if it held one.
+    pub fn resume(&self, source: &str) -> bool {
+        self.pauses
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(source)
+            .is_some()
+    }
+
+    /// Why buys are paused, `None` when they are allowed
+    pub fn paused_reason(&self) -> Option<String> {
+        let pauses = self.pauses.read().unwrap_or_else(|e| e.into_inner());
+        if pauses.is_empty() {
+            return None;
+        }
+        Some(
+            pauses
+                .iter()
+                .map(|(source, reason)| format!("{}: {}", source, reason))
+                .collect::<Vec<_>>()
+                .join("; "),
+        )
+    }
+}
+
+impl fmt::Display for BuyGate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.paused_reason() {
+            Some(reason) => write!(f, "Buys paused ({})", reason),
+            None => write!(f, "Buys active"),
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use listen_kit::signer::SignerContext;
+use mongodb::{bson::doc, Collection, IndexModel};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::solana::rpc::make_rpc_client;
+use crate::solana::transaction::{get_wallet_token_amount, TxNotConfirmed};
+use crate::storage::mongo::is_duplicate_key;
+use crate::tg_copy::strategy::normalize_strategy_id;
+use crate::trade::meme_trader::MemeTrader;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IntentStatus {
+    /// Written before the buy was sent, a crash leaves it behind
+    Pending,
+    Completed,
+    /// The buy did not go through, the signal may be executed again
+    Failed,
+}
+
+impl fmt::Display for IntentStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntentStatus::Pending => write!(f, "pending"),
+            IntentStatus::Completed => write!(f, "completed"),
+            IntentStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// The buy of one signal message by one strategy, recorded before the
+/// transaction is sent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionIntent {
+    /// [`intent_id`] of the message and strategy
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub message_id: i64,
+    pub strategy: String,
+    pub contract_address: String,
+    pub status: IntentStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx_sig: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ExecutionIntent {
+    /// Pending and completed intents stop the message from being bought again
+    pub fn blocks_retry(&self) -> bool {
+        self.status != IntentStatus::Failed
+    }
+}
+
+impl fmt::Display for ExecutionIntent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "message {} of {} {} since {}",
+            self.message_id,
+            self.strategy,
+            self.status,
+            self.updated_at.format("%Y-%m-%d %H:%M:%S")
+        )?;
+        if let Some(tx_sig) = &self.tx_sig {
+            write!(f, " ({})", tx_sig)?;
+        }
+        Ok(())
+    }
+}
+
+/// Strategy names differing only in underscores are the same intent
+pub fn intent_id(message_id: i64, strategy: &str) -> String {
+    format!("{}:{}", message_id, normalize_strategy_id(strategy))
+}
+
+/// The `execution_intents` collection. A signal message is bought at most
+/// once per strategy, across reprocessing of the history and restarts:
+/// the intent is claimed before the buy is sent and only a failed one can
+/// be claimed again.
+#[derive(Clone)]
+pub struct ExecutionIntents {
+    collection: Collection<ExecutionIntent>,
+}
+
+impl ExecutionIntents {
+    pub fn new(collection: Collection<ExecutionIntent>) -> Self {
+        Self { collection }
+    }
+
+    pub async fn setup_indexes(&self) -> Result<()> {
+        let status_index = IndexModel::builder().keys(doc! { "status": 1 }).build();
+        self.collection.create_index(status_index, None).await?;
+        Ok(())
+    }
+
+    /// Claim the buy of `message_id` by `strategy`. Returns the intent in
+    /// the way when the message is already being or was bought, the buy
+    /// must not be sent then. Concurrent claims are settled by the unique
+    /// `_id`.
+    pub async fn begin(
+        &self,
+        message_id: i64,
+        strategy: &str,
+        contract_address: &str,
+    ) -> Result<Option<ExecutionIntent>> {
+        let now = Utc::now();
+        let intent = ExecutionIntent {
+            id: intent_id(message_id, strategy),
+            message_id,
+            strategy: strategy.to_string(),
+            contract_address: contract_address.to_string(),
+            status: IntentStatus::Pending,
+            tx_sig: None,
+            error: None,
+            created_at: now,
+            updated_at: now,
+        };
+        match self.collection.insert_one(&intent, None).await {
+            Ok(_) => return Ok(None),
+            Err(e) if is_duplicate_key(&e) => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        // A failed buy is retried by taking its intent over
+        let retried = self
+            .collection
+            .replace_one(
+                doc! { "_id": &intent.id, "status": IntentStatus::Failed.to_string() },
+                &intent,
+                None,
+            )
+            .await?;
+        if retried.modified_count > 0 {
+            return Ok(None);
+        }
+        Ok(self
+            .collection
+            .find_one(doc! { "_id": &intent.id }, None)
+            .await?
+            .filter(ExecutionIntent::blocks_retry))
+    }
+
+    async fn set_status(
+        &self,
+        id: &str,
+        status: IntentStatus,
+        tx_sig: Option<&str>,
+        error: Option<String>,
+    ) -> Result<()> {
+        let mut update = doc! {
+            "status": status.to_string(),
+            "updated_at": bson::DateTime::from_chrono(Utc::now()),
+        };
+        if let Some(tx_sig) = tx_sig {
+            update.insert("tx_sig", tx_sig);
+        }
+        if let Some(error) = error {
+            update.insert("error", error);
+        }
+        self.collection
+            .update_one(doc! { "_id": id }, doc! { "$set": update }, None)
+            .await?;
+        Ok(())
+    }
+
+    /// The buy confirmed with `tx_sig`
+    pub async fn complete(&self, id: &str, tx_sig: &str) -> Result<()> {
+        self.set_status(id, IntentStatus::Completed, Some(tx_sig), None)
+            .await
+    }
+
+    /// The buy failed with `error`, the message may be bought again
+    pub async fn fail(&self, id: &str, error: &anyhow::Error) -> Result<()> {
+        let tx_sig = error
+            .downcast_ref::<TxNotConfirmed>()
+            .map(|not_confirmed| not_confirmed.signature.as_str());
+        self.set_status(
+            id,
+            IntentStatus::Failed,
+            tx_sig,
+            Some(format!("{:#}", error)),
+        )
+        .await
+    }
+
+    pub async fn pending(&self) -> Result<Vec<ExecutionIntent>> {
+        let mut cursor = self
+            .collection
+            .find(doc! { "status": IntentStatus::Pending.to_string() }, None)
+            .await?;
+        let mut pending = Vec::new();
+        while cursor.advance().await? {
+            pending.push(cursor.deserialize_current()?);
+        }
+        Ok(pending)
+    }
+}
+
+/// Settle the intents a crash left pending, before any message is
+/// reprocessed. A buy that landed shows as an open position or as tokens
+/// in the wallet and completes its intent, one with neither is failed so
+/// the signal can be bought again. Call from a [`SignerContext`].
+pub async fn reconcile_pending(intents: &ExecutionIntents, trader: &MemeTrader) -> Result<()> {
+    let pending = intents.pending().await?;
+    if pending.is_empty() {
+        return Ok(());
+    }
+    tracing::warn!("{} buys pending from the last run", pending.len());
+    let owner = Pubkey::from_str(&SignerContext::current().await.pubkey())?;
+    let rpc_client = make_rpc_client();
+    for intent in pending {
+        let open = trader
+            .active_trades()
+            .get_trade(&intent.contract_address, &intent.strategy)
+            .await?
+            .is_some();
+        // No token account when the buy never landed
+        let holdings = if open {
+            0
+        } else {
+            get_wallet_token_amount(
+                &rpc_client,
+                &owner,
+                &Pubkey::from_str(&intent.contract_address)?,
+            )
+            .await?
+        };
+        if open || holdings > 0 {
+            tracing::warn!(
+                "Buy of {} landed before the restart, not buying it again{}",
+                intent,
+                if open {
+                    String::new()
+                } else {
+                    format!(", {} tokens held without a position", holdings)
+                }
+            );
+            intents
+                .set_status(&intent.id, IntentStatus::Completed, None, None)
+                .await?;
+        } else {
+            tracing::warn!("Buy of {} did not land, it may be retried", intent);
+            intents
+                .set_status(
+                    &intent.id,
+                    IntentStatus::Failed,
+                    None,
+                    Some("interrupted by a restart".to_string()),
+                )
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intent_id() {
+        assert_eq!(intent_id(42, "degen_v2"), intent_id(42, "degenv2"));
+        assert_ne!(intent_id(42, "degen"), intent_id(43, "degen"));
+        assert_ne!(intent_id(42, "degen"), intent_id(42, "safe"));
+
+        let now = Utc::now();
+        let mut intent = ExecutionIntent {
+            id: intent_id(42, "degen"),
+            message_id: 42,
+            strategy: "degen".to_string(),
+            contract_address: "mint".to_string(),
+            status: IntentStatus::Pending,
+            tx_sig: None,
+            error: None,
+            created_at: now,
+            updated_at: now,
+        };
+        assert!(intent.blocks_retry());
+        intent.status = IntentStatus::Completed;
+        assert!(intent.blocks_retry());
+        intent.status = IntentStatus::Failed;
+        assert!(!intent.blocks_retry());
+    }
+}
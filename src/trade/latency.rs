@@ -0,0 +1,421 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration as ChronoDuration, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time;
+
+use crate::config::LatencySloConfig;
+use crate::format;
+use crate::notify::TradeEvent;
+use crate::tg_copy::db::TradeType;
+use crate::trade::costs::cost_report;
+use crate::trade::execution::{Execution, ExecutionLog, Signal};
+use crate::trade::meme_trader::MemeTrader;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Legs of the hot path from the message timestamp to the confirmed fill
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    /// Posted until the listener saw the message
+    Receive,
+    /// Waiting for a trade worker
+    Queue,
+    /// Checks, sizing and filters before the swap
+    Prepare,
+    /// Quote, transaction and confirmation
+    Execute,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 4] = [Stage::Receive, Stage::Queue, Stage::Prepare, Stage::Execute];
+}
+
+impl FromStr for Stage {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "receive" => Ok(Stage::Receive),
+            "queue" => Ok(Stage::Queue),
+            "prepare" => Ok(Stage::Prepare),
+            "execute" => Ok(Stage::Execute),
+            other => Err(anyhow!(
+                "Unknown latency stage '{}', expected receive, queue, prepare or execute",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stage::Receive => write!(f, "receive"),
+            Stage::Queue => write!(f, "queue"),
+            Stage::Prepare => write!(f, "prepare"),
+            Stage::Execute => write!(f, "execute"),
+        }
+    }
+}
+
+/// Milliseconds spent in each stage of a fill, they add up to its latency
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct StageLatency {
+    pub receive_ms: i64,
+    pub queue_ms: i64,
+    pub prepare_ms: i64,
+    pub execute_ms: i64,
+}
+
+impl StageLatency {
+    pub fn new(signal: &Signal, submitted_at: DateTime<Utc>, confirmed_at: DateTime<Utc>) -> Self {
+        Self {
+            receive_ms: (signal.received_at - signal.posted_at).num_milliseconds(),
+            queue_ms: (signal.started_at - signal.received_at).num_milliseconds(),
+            prepare_ms: (submitted_at - signal.started_at).num_milliseconds(),
+            execute_ms: (confirmed_at - submitted_at).num_milliseconds(),
+        }
+    }
+
+    pub fn get(&self, stage: Stage) -> i64 {
+        match stage {
+            Stage::Receive => self.receive_ms,
+            Stage::Queue => self.queue_ms,
+            Stage::Prepare => self.prepare_ms,
+            Stage::Execute => self.execute_ms,
+        }
+    }
+}
+
+/// Share of the SLO a stage may use, `execute:1750`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StageBudget {
+    pub stage: Stage,
+    pub budget_ms: i64,
+}
+
+impl FromStr for StageBudget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (stage, budget_ms) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Invalid stage budget '{}', expected stage:ms", s))?;
+        Ok(Self {
+            stage: stage.parse()?,
+            budget_ms: budget_ms
+                .trim()
+                .parse()
+                .map_err(|e| anyhow!("Invalid budget of stage {}: {}", stage, e))?,
+        })
+    }
+}
+
+impl fmt::Display for StageBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.stage, self.budget_ms)
+    }
+}
+
+/// Comma separated budgets, `receive:1500,queue:250,prepare:500,execute:1750`
+pub fn parse_stage_budgets(s: &str) -> Result<Vec<StageBudget>> {
+    let budgets = s
+        .split(',')
+        .filter(|budget| !budget.trim().is_empty())
+        .map(str::parse)
+        .collect::<Result<Vec<StageBudget>>>()?;
+    for (i, budget) in budgets.iter().enumerate() {
+        if budgets[..i].iter().any(|b| b.stage == budget.stage) {
+            return Err(anyhow!("Stage {} has two budgets", budget.stage));
+        }
+    }
+    Ok(budgets)
+}
+
+/// How one stage did against its budget
+#[derive(Debug, Clone, Serialize)]
+pub struct StageReport {
+    pub stage: Stage,
+    pub budget_ms: Option<i64>,
+    pub p95_ms: Option<i64>,
+    /// Fills that spent more than the budget in the stage
+    pub over_budget: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SlowestFill {
+    pub token: String,
+    pub latency_ms: i64,
+}
+
+/// Compliance of filled buys with the signal-to-fill latency SLO
+#[derive(Debug, Clone, Serialize)]
+pub struct SloReport {
+    pub target_ms: i64,
+    pub objective: f64,
+    pub buys: usize,
+    pub within_target: usize,
+    /// `None` without buys
+    pub compliance: Option<f64>,
+    /// Whether the objective was met, vacuously without buys
+    pub met: bool,
+    pub p50_ms: Option<i64>,
+    pub p95_ms: Option<i64>,
+    /// Only fills that recorded their stages count here
+    pub stages: Vec<StageReport>,
+    pub slowest: Option<SlowestFill>,
+}
+
+/// Nearest-rank percentile of ascending `sorted`
+fn percentile(sorted: &[i64], q: f64) -> Option<i64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (q * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+/// SLO compliance of the buys in `executions`, failed ones and buys of
+/// signals without a timestamp are left out
+pub fn slo_report(cfg: &LatencySloConfig, executions: &[Execution]) -> SloReport {
+    let fills = executions
+        .iter()
+        .filter(|e| e.tx_sig.is_some())
+        .filter_map(|e| e.latency_ms.map(|latency_ms| (e, latency_ms)))
+        .collect::<Vec<_>>();
+
+    let mut latencies = fills.iter().map(|(_, ms)| *ms).collect::<Vec<_>>();
+    latencies.sort_unstable();
+    let within_target = latencies.iter().filter(|ms| **ms <= cfg.target_ms).count();
+    let compliance = (!fills.is_empty()).then(|| within_target as f64 / fills.len() as f64);
+
+    let stages = Stage::ALL
+        .iter()
+        .map(|stage| {
+            let budget_ms = cfg
+                .stage_budgets
+                .iter()
+                .find(|b| b.stage == *stage)
+                .map(|b| b.budget_ms);
+            let mut spent = fills
+                .iter()
+                .filter_map(|(e, _)| e.stages.map(|s| s.get(*stage)))
+                .collect::<Vec<_>>();
+            spent.sort_unstable();
+            StageReport {
+                stage: *stage,
+                budget_ms,
+                p95_ms: percentile(&spent, 0.95),
+                over_budget: budget_ms
+                    .map_or(0, |budget| spent.iter().filter(|ms| **ms > budget).count()),
+            }
+        })
+        .collect();
+
+    SloReport {
+        target_ms: cfg.target_ms,
+        objective: cfg.objective,
+        buys: fills.len(),
+        within_target,
+        compliance,
+        met: compliance.map_or(true, |c| c >= cfg.objective),
+        p50_ms: percentile(&latencies, 0.5),
+        p95_ms: percentile(&latencies, 0.95),
+        stages,
+        slowest: fills
+            .iter()
+            .max_by_key(|(_, ms)| *ms)
+            .map(|(e, ms)| SlowestFill {
+                token: e.token.clone(),
+                latency_ms: *ms,
+            }),
+    }
+}
+
+/// What made a fill miss the SLO, empty when it is within the target
+pub fn slo_breaches(
+    cfg: &LatencySloConfig,
+    latency_ms: i64,
+    stages: Option<&StageLatency>,
+) -> Vec<String> {
+    if latency_ms <= cfg.target_ms {
+        return Vec::new();
+    }
+    let mut breaches = vec![format!(
+        "{} over the {} target",
+        format::millis(latency_ms),
+        format::millis(cfg.target_ms)
+    )];
+    if let Some(stages) = stages {
+        breaches.extend(
+            cfg.stage_budgets
+                .iter()
+                .filter(|b| stages.get(b.stage) > b.budget_ms)
+                .map(|b| {
+                    format!(
+                        "{} took {} of its {}",
+                        b.stage,
+                        format::millis(stages.get(b.stage)),
+                        format::millis(b.budget_ms)
+                    )
+                }),
+        );
+    }
+    breaches
+}
+
+/// Sends the SLO compliance of the last 24 hours of buys once a day at
+/// `LATENCY_DIGEST_HOUR` UTC
+pub async fn run_latency_digest(
+    trader: Arc<MemeTrader>,
+    executions: ExecutionLog,
+    cfg: LatencySloConfig,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut interval = time::interval(CHECK_INTERVAL);
+    let mut last_sent = None;
+    tracing::info!("Latency digest scheduled at {:02}:00 UTC", cfg.digest_hour);
+
+    loop {
+        tokio::select! {
+            biased;
+            _ = shutdown.changed() => {
+                tracing::info!("Latency digest stopped");
+                return;
+            }
+            _ = interval.tick() => {}
+        }
+
+        let now = Utc::now();
+        if now.hour() != cfg.digest_hour || last_sent == Some(now.date_naive()) {
+            continue;
+        }
+        let executed = match executions.since(now - ChronoDuration::days(1)).await {
+            Ok(executed) => executed,
+            Err(e) => {
+                tracing::error!("Latency digest failed to load executions: {:?}", e);
+                continue;
+            }
+        };
+        let costs = cost_report(&executed);
+        tracing::info!("Execution costs of the last 24h: {:?}", costs);
+        let buys: Vec<Execution> = executed
+            .into_iter()
+            .filter(|e| matches!(e.trade_type, TradeType::Open))
+            .collect();
+        let report = slo_report(&cfg, &buys);
+        tracing::info!("Latency SLO of the last 24h: {:?}", report);
+        trader.notify(TradeEvent::DailyDigest {
+            day: now.date_naive().to_string(),
+            failed_buys: buys.iter().filter(|e| e.error.is_some()).count(),
+            latency: report,
+            costs,
+        });
+        last_sent = Some(now.date_naive());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buy(token: &str, latency_ms: Option<i64>, execute_ms: i64) -> Execution {
+        Execution {
+            message_id: Some(1),
+            reason: "Open signal".to_string(),
+            tx_sig: latency_ms.map(|_| "sig".to_string()),
+            latency_ms,
+            stages: latency_ms.map(|latency_ms| StageLatency {
+                receive_ms: latency_ms - execute_ms,
+                queue_ms: 0,
+                prepare_ms: 0,
+                execute_ms,
+            }),
+            ..Execution::for_test(TradeType::Open, token)
+        }
+    }
+
+    fn config() -> LatencySloConfig {
+        LatencySloConfig {
+            target_ms: 4000,
+            objective: 0.95,
+            stage_budgets: parse_stage_budgets("receive:1500, execute:2000").unwrap(),
+            digest_hour: 0,
+        }
+    }
+
+    #[test]
+    fn test_parse_stage_budgets() {
+        assert_eq!(
+            parse_stage_budgets("queue:250,execute:1750").unwrap(),
+            vec![
+                StageBudget {
+                    stage: Stage::Queue,
+                    budget_ms: 250
+                },
+                StageBudget {
+                    stage: Stage::Execute,
+                    budget_ms: 1750
+                },
+            ]
+        );
+        assert!(parse_stage_budgets("").unwrap().is_empty());
+        assert!(parse_stage_budgets("send:100").is_err());
+        assert!(parse_stage_budgets("queue:100,queue:200").is_err());
+    }
+
+    #[test]
+    fn test_slo_report() {
+        let mut executions = (0..18)
+            .map(|i| buy("WIF", Some(1000 + i * 50), 500))
+            .collect::<Vec<_>>();
+        executions.push(buy("BONK", Some(6000), 4500));
+        executions.push(buy("POPCAT", Some(4500), 1000));
+        // Failed buys don't count
+        executions.push(buy("MEW", None, 0));
+
+        let report = slo_report(&config(), &executions);
+        assert_eq!(report.buys, 20);
+        assert_eq!(report.within_target, 18);
+        assert_eq!(report.compliance, Some(0.9));
+        assert!(!report.met);
+        assert_eq!(report.p50_ms, Some(1450));
+        assert_eq!(report.p95_ms, Some(4500));
+        assert_eq!(report.slowest.as_ref().unwrap().token, "BONK");
+        let execute = &report.stages[3];
+        assert_eq!(execute.stage, Stage::Execute);
+        assert_eq!(execute.budget_ms, Some(2000));
+        assert_eq!(execute.over_budget, 1);
+        // POPCAT waited 3.5s to be seen
+        assert_eq!(report.stages[0].over_budget, 1);
+        assert_eq!(report.stages[1].budget_ms, None);
+
+        let empty = slo_report(&config(), &[]);
+        assert_eq!(empty.compliance, None);
+        assert!(empty.met);
+    }
+
+    #[test]
+    fn test_slo_breaches() {
+        let stages = StageLatency {
+            receive_ms: 500,
+            queue_ms: 0,
+            prepare_ms: 200,
+            execute_ms: 4300,
+        };
+        assert!(slo_breaches(&config(), 3900, Some(&stages)).is_empty());
+        assert_eq!(
+            slo_breaches(&config(), 5000, Some(&stages)),
+            vec![
+                "5s over the 4s target".to_string(),
+                "execute took 4.3s of its 2s".to_string()
+            ]
+        );
+    }
+}
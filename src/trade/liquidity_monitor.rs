@@ -0,0 +1,110 @@
+use anyhow::{anyhow, Result};
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time;
+
+use crate::config::LiquidityMonitorConfig;
+use crate::format;
+use crate::solana::cache::cached_search_ticker;
+use crate::solana::rpc::make_rpc_client;
+use crate::tg_copy::active_trade::ActiveTrade;
+use crate::trade::meme_trader::MemeTrader;
+
+/// Periodically compares every held position with the depth of its deepest
+/// pool. Positions that make up too large a share of the pool are reported
+/// and, if configured, reduced by one tranche per check.
+pub async fn run_liquidity_monitor(
+    trader: Arc<MemeTrader>,
+    cfg: LiquidityMonitorConfig,
+    tip_lamports: u64,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    let mut interval = time::interval(Duration::from_secs(cfg.check_interval_secs));
+    tracing::info!("Liquidity monitor started");
+
+    loop {
+        tokio::select! {
+            biased;
+            _ = shutdown.changed() => {
+                tracing::info!("Liquidity monitor stopped");
+                return Ok(());
+            }
+            _ = interval.tick() => {}
+        }
+
+        let trades = match trader.active_trades().load_all_trades().await {
+            Ok(trades) => trades,
+            Err(e) => {
+                tracing::error!("Liquidity monitor failed to load active trades: {:?}", e);
+                continue;
+            }
+        };
+
+        for trade in trades {
+            if let Err(e) = check_position(&trader, &trade, &cfg, tip_lamports).await {
+                tracing::warn!(
+                    "Liquidity check failed for {} ({}): {:?}",
+                    trade.token_name,
+                    trade.token_address,
+                    e
+                );
+            }
+        }
+    }
+}
+
+async fn check_position(
+    trader: &MemeTrader,
+    trade: &ActiveTrade,
+    cfg: &LiquidityMonitorConfig,
+    tip_lamports: u64,
+) -> Result<()> {
+    let dex_info = cached_search_ticker(&trade.token_address).await?;
+    let pool_depth = dex_info
+        .pairs
+        .iter()
+        .filter(|pair| pair.base_token.address == trade.token_address)
+        .map(|pair| pair.liquidity.base)
+        .fold(0f64, f64::max);
+    if pool_depth <= 0f64 {
+        return Err(anyhow!("No pool liquidity reported"));
+    }
+
+    let decimals = make_rpc_client()
+        .get_token_supply(&Pubkey::from_str(&trade.token_address)?)
+        .await?
+        .decimals;
+    let position = trade.remaining_holdings as f64 / 10f64.powi(decimals as i32);
+    let pool_share_pct = position / pool_depth * 100.0;
+
+    if pool_share_pct < cfg.max_pool_share_pct {
+        return Ok(());
+    }
+
+    tracing::warn!(
+        "Liquidity draining for {} ({}): position {} is {:.2}% of pool depth {} (max {:.2}%)",
+        trade.token_name,
+        trade.strategy_id,
+        format::compact(position),
+        pool_share_pct,
+        format::compact(pool_depth),
+        cfg.max_pool_share_pct
+    );
+
+    if let Some(tranche_pct) = cfg.exit_tranche_pct {
+        let tranche = ((trade.remaining_holdings as f64) * tranche_pct / 100.0).ceil() as u64;
+        let fill = trader
+            .sell_position(trade, tranche.max(1), tip_lamports, "Liquidity exit")
+            .await?;
+        tracing::info!(
+            "Liquidity exit tranche sold for {}: https://solscan.io/tx/{}",
+            trade.token_name,
+            fill.tx_sig
+        );
+    }
+
+    Ok(())
+}
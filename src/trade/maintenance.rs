@@ -0,0 +1,239 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, NaiveTime, Utc, Weekday};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time;
+
+use crate::config::MaintenanceConfig;
+use crate::notify::TradeEvent;
+use crate::trade::meme_trader::MemeTrader;
+
+const GATE_SOURCE: &str = "maintenance";
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Recurring UTC window during which no new positions are opened, e.g.
+/// `sleep=23:00-07:00` or `rpc=sun 02:00-04:00` or `provider=mon-fri 12:00-12:30`.
+/// Windows that cross midnight belong to the day they start on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaintenanceWindow {
+    pub name: String,
+    /// `None` means every day
+    pub days: Option<Vec<Weekday>>,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl MaintenanceWindow {
+    fn runs_on(&self, day: Weekday) -> bool {
+        self.days.as_ref().map_or(true, |days| days.contains(&day))
+    }
+
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        let t = now.time();
+        let today = now.weekday();
+        if self.start <= self.end {
+            self.runs_on(today) && self.start <= t && t < self.end
+        } else {
+            (self.runs_on(today) && t >= self.start) || (self.runs_on(today.pred()) && t < self.end)
+        }
+    }
+
+    /// Next time this window opens after `now`
+    pub fn next_start(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        (0..=7).find_map(|offset| {
+            let date = (now + ChronoDuration::days(offset)).date_naive();
+            let start = date.and_time(self.start).and_utc();
+            (start > now && self.runs_on(date.weekday())).then_some(start)
+        })
+    }
+}
+
+impl FromStr for MaintenanceWindow {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, spec) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected name=[days ]HH:MM-HH:MM, got '{}'", s))?;
+        let spec = spec.trim();
+        let (days, hours) = match spec.rsplit_once(' ') {
+            Some((days, hours)) => (Some(parse_days(days.trim())?), hours),
+            None => (None, spec),
+        };
+        let (start, end) = hours
+            .split_once('-')
+            .ok_or_else(|| anyhow!("Expected HH:MM-HH:MM, got '{}'", hours))?;
+
+        Ok(Self {
+            name: name.trim().to_string(),
+            days,
+            start: NaiveTime::parse_from_str(start.trim(), "%H:%M")?,
+            end: NaiveTime::parse_from_str(end.trim(), "%H:%M")?,
+        })
+    }
+}
+
+impl fmt::Display for MaintenanceWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}=", self.name)?;
+        if let Some(days) = &self.days {
+            let days: Vec<String> = days.iter().map(|d| d.to_string()).collect();
+            write!(f, "{} ", days.join(","))?;
+        }
+        write!(
+            f,
+            "{}-{} UTC",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+/// `mon-fri`, `sat,sun` or `mon`
+fn parse_days(s: &str) -> Result<Vec<Weekday>> {
+    let parse =
+        |d: &str| Weekday::from_str(d.trim()).map_err(|_| anyhow!("Unknown weekday '{}'", d));
+    let mut days = Vec::new();
+    for part in s.split(',') {
+        match part.split_once('-') {
+            Some((from, to)) => {
+                let (mut day, to) = (parse(from)?, parse(to)?);
+                days.push(day);
+                while day != to {
+                    day = day.succ();
+                    days.push(day);
+                }
+            }
+            None => days.push(parse(part)?),
+        }
+    }
+    Ok(days)
+}
+
+pub fn active_window(
+    windows: &[MaintenanceWindow],
+    now: DateTime<Utc>,
+) -> Option<&MaintenanceWindow> {
+    windows.iter().find(|w| w.is_active(now))
+}
+
+/// Human readable state of the schedule, for logs and the status command
+pub fn maintenance_status(windows: &[MaintenanceWindow], now: DateTime<Utc>) -> String {
+    if let Some(window) = active_window(windows, now) {
+        return format!(
+            "In maintenance window '{}' until {} UTC",
+            window.name,
+            window.end.format("%H:%M")
+        );
+    }
+    windows
+        .iter()
+        .filter_map(|w| w.next_start(now).map(|start| (start, w)))
+        .min_by_key(|(start, _)| *start)
+        .map_or(
+            "No maintenance windows scheduled".to_string(),
+            |(start, w)| {
+                format!(
+                    "Next maintenance window '{}' at {}",
+                    w.name,
+                    start.format("%a %H:%M UTC")
+                )
+            },
+        )
+}
+
+/// Pauses new buys while a maintenance window is open and resumes them once
+/// it closes, notifying on every transition.
+pub async fn run_maintenance_scheduler(
+    trader: Arc<MemeTrader>,
+    cfg: MaintenanceConfig,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    let mut interval = time::interval(CHECK_INTERVAL);
+    tracing::info!(
+        "Maintenance scheduler started: {}",
+        maintenance_status(&cfg.windows, Utc::now())
+    );
+
+    loop {
+        tokio::select! {
+            biased;
+            _ = shutdown.changed() => {
+                tracing::info!("Maintenance scheduler stopped");
+                return Ok(());
+            }
+            _ = interval.tick() => {}
+        }
+
+        let now = Utc::now();
+        match active_window(&cfg.windows, now) {
+            Some(window) => {
+                let reason = format!(
+                    "maintenance window '{}' until {} UTC",
+                    window.name,
+                    window.end.format("%H:%M")
+                );
+                if trader.buy_gate().pause(GATE_SOURCE, reason.clone()) {
+                    tracing::info!("Pausing buys: {}", reason);
+                    trader.notify(TradeEvent::BuysPaused {
+                        reason,
+                        breaker: None,
+                    });
+                }
+            }
+            None => {
+                if trader.buy_gate().resume(GATE_SOURCE) {
+                    let status = maintenance_status(&cfg.windows, now);
+                    tracing::info!("Resuming buys: {}", status);
+                    trader.notify(TradeEvent::BuysResumed {
+                        reason: format!("maintenance window ended. {}", status),
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_parse_window() {
+        let window: MaintenanceWindow = "provider=mon-fri 12:00-12:30".parse().unwrap();
+        assert_eq!(window.name, "provider");
+        assert_eq!(
+            window.days,
+            Some(vec![
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed,
+                Weekday::Thu,
+                Weekday::Fri
+            ])
+        );
+        assert_eq!(
+            window.to_string(),
+            "provider=Mon,Tue,Wed,Thu,Fri 12:00-12:30 UTC"
+        );
+        assert!("sleep=25:00-07:00".parse::<MaintenanceWindow>().is_err());
+        assert!("23:00-07:00".parse::<MaintenanceWindow>().is_err());
+    }
+
+    #[test]
+    fn test_overnight_window_belongs_to_start_day() {
+        let window: MaintenanceWindow = "sleep=fri 23:00-07:00".parse().unwrap();
+        // 2024-06-07 is a Friday
+        let at = |d, h| Utc.with_ymd_and_hms(2024, 6, d, h, 0, 0).unwrap();
+        assert!(window.is_active(at(7, 23)));
+        assert!(window.is_active(at(8, 6)));
+        assert!(!window.is_active(at(8, 7)));
+        assert!(!window.is_active(at(8, 23)));
+        assert!(!window.is_active(at(7, 6)));
+        assert_eq!(window.next_start(at(8, 12)), Some(at(14, 23)));
+    }
+}
@@ -1,35 +1,215 @@
 use anyhow::{anyhow, Result};
-use mongodb::Collection;
+use chrono::{DateTime, Utc};
 use serde::Serialize;
-use solana_sdk::{native_token::sol_to_lamports, pubkey::Pubkey};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{instruction::Instruction, native_token::sol_to_lamports, pubkey::Pubkey};
+use std::path::Path;
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use std::time::Duration;
 use tokio::time::sleep;
 use tracing::info;
 
 use crate::{
+    config::{ComputeBudgetConfig, JupiterSwapConfig, SellRetryConfig, VenueSelectionConfig},
+    format,
+    notify::{webhook::WebhookNotifier, EventHook, Notifier, TradeEvent},
     solana::{
-        dexscreener::{search_ticker, DexScreenerResponse},
-        trade_raydium::{create_raydium_sol_swap_ix, create_raydium_token_swap_ix},
+        attempts::{double_buy_excess, swap_on, TxAttempts},
+        best_venue::{select_pool, Route},
+        cache::{cached_fetch_metadata, cached_search_ticker},
+        compute_budget::with_compute_budget,
+        deploy_token::{deploy_pump_token, upload_metadata, DeployTokenParams},
+        dexscreener::{DexScreenerResponse, PairInfo},
+        jupiter::create_jupiter_swap_ix,
+        meteora::{create_meteora_sol_swap_ix, create_meteora_token_swap_ix},
+        orca::{create_orca_sol_swap_ix, create_orca_token_swap_ix},
+        price::sol_price_usd,
+        quoter::{Quote, Quoter, Side},
+        route::{
+            detect_pool_program, find_supported_pool, primary_pair, NoSupportedPool, PoolProgram,
+        },
+        rpc::make_rpc_client,
+        simulate::NotSent,
+        token_registry::token_registry,
+        trade_raydium::{
+            create_raydium_cpmm_sol_swap_ix, create_raydium_cpmm_token_swap_ix,
+            create_raydium_sol_swap_ix, create_raydium_token_swap_ix,
+        },
+        transaction::{
+            get_fee_payer_balance_change, get_swap_sol_change, get_token_balance_change,
+            get_wallet_token_amount, simulate_instructions, TxBuilder,
+        },
+        transfer_fee::get_transfer_fee_bps,
+        wallet_events::{shrink_holdings, SwapGuard, WalletBalances, WalletTouch},
+        ws::PriceCache,
+    },
+    tg_copy::{
+        db::TradeType,
+        parse_trade::{OperationType, Trade},
+        strategy::{Strategy, TakeProfitCondition},
     },
-    tg_copy::{parse_trade::OperationType, strategy::Strategy},
 };
 
 use listen_kit::{
     signer::SignerContext,
     solana::{
         balance::get_balance,
-        pump::{fetch_metadata, PumpTokenInfo},
+        pump::PumpTokenInfo,
         trade_pump::{create_buy_pump_fun_ix, create_sell_pump_fun_ix},
-        util::{execute_solana_transaction_with_tip, make_rpc_client},
+        util::execute_solana_transaction_with_tip,
     },
 };
 
-use crate::tg_copy::active_trade::{ActiveTrade, ActiveTradeManager};
+use crate::storage::Storage;
+use crate::tg_copy::active_trade::{ActiveTrade, ActiveTradeManager, RealizedPnl, TpLegFill};
+use crate::tg_copy::journal::{self, DecisionJournal};
+use crate::trade::bot_state::BotStateStore;
+use crate::trade::capital::CapitalStore;
+use crate::trade::costs::{execution_costs, ExecutionCosts};
+use crate::trade::execution::{effective_price_usd, ExecutedFill, ExecutionLog, Signal, Venue};
+use crate::trade::gate::BuyGate;
+use crate::trade::intent::ExecutionIntents;
+use crate::trade::risk::{BalanceGuard, LossStreakGuard, StreakAction};
+use crate::trade::sell_retry::slippage_schedule;
+use crate::trade::slippage::{realized_slippage_bps, SlippageLog, SlippageSample};
 
 pub struct MemeTrader {
     active_trades: Arc<ActiveTradeManager>,
+    notifiers: Vec<Arc<dyn Notifier>>,
+    /// Also among the notifiers, kept for the signals it receives
+    webhooks: Option<WebhookNotifier>,
+    buy_gate: BuyGate,
+    price_cache: Arc<PriceCache>,
+    capital: Option<CapitalStore>,
+    bot_state: Option<BotStateStore>,
+    intents: Option<ExecutionIntents>,
+    provider: Option<String>,
+    executions: Option<ExecutionLog>,
+    loss_streaks: Option<LossStreakGuard>,
+    balance_guard: Option<BalanceGuard>,
+    journal: Option<Arc<DecisionJournal>>,
+    wallet_balances: Option<Arc<WalletBalances>>,
+    sell_retry: Option<SellRetryConfig>,
+    compute_budget: Option<ComputeBudgetConfig>,
+    venue_selection: Option<VenueSelectionConfig>,
+    jupiter: Option<JupiterSwapConfig>,
+    slippage: Option<SlippageLog>,
+    event_hooks: RwLock<Vec<EventHook>>,
+}
+
+/// Outcome of an executed buy or sell
+#[derive(Debug, Clone)]
+pub struct Fill {
+    pub tx_sig: String,
+    /// Raw amount of tokens bought or sold
+    pub token_amount: u64,
+    pub venue: Venue,
+    /// SOL spent or received, fees included
+    pub sol_lamports: u64,
+    /// Fee and tip of the transaction, `None` when the fee could not be read
+    pub costs: Option<ExecutionCosts>,
+    pub submitted_at: DateTime<Utc>,
+    pub confirmed_at: DateTime<Utc>,
+    /// Our PnL of a sell, `None` for buys and when the proceeds are unknown
+    pub pnl: Option<RealizedPnl>,
+}
+
+/// What a [`MemeTrader::sell_mint`] did, for audits: how many positions
+/// sold and why the others didn't
+pub fn sell_outcome(results: &[(ActiveTrade, Result<Fill>)]) -> String {
+    let sold = results.iter().filter(|(_, fill)| fill.is_ok()).count();
+    let failed: Vec<String> = results
+        .iter()
+        .filter_map(|(trade, fill)| {
+            let e = fill.as_ref().err()?;
+            Some(format!("{}: {:#}", trade.strategy_id, e))
+        })
+        .collect();
+    if failed.is_empty() {
+        format!("{} sells", sold)
+    } else {
+        format!(
+            "{} sells, {} failed: {}",
+            sold,
+            failed.len(),
+            failed.join("; ")
+        )
+    }
+}
+
+impl Fill {
+    fn executed(&self) -> ExecutedFill<'_> {
+        ExecutedFill {
+            tx_sig: &self.tx_sig,
+            venue: self.venue,
+            token_amount: self.token_amount,
+            sol_lamports: self.sol_lamports,
+            costs: self.costs,
+            submitted_at: self.submitted_at,
+            confirmed_at: self.confirmed_at,
+        }
+    }
+}
+
+/// Fee and tip of a landed swap, logged in lamports and USD
+async fn fill_costs(
+    rpc_client: &RpcClient,
+    tx_sig: &str,
+    tip_lamports: u64,
+) -> Option<ExecutionCosts> {
+    match execution_costs(rpc_client, tx_sig, tip_lamports).await {
+        Ok(costs) => {
+            tracing::info!("Costs of {}: {}", tx_sig, costs);
+            Some(costs)
+        }
+        Err(e) => {
+            tracing::warn!("Could not read the costs of {}: {:?}", tx_sig, e);
+            None
+        }
+    }
+}
+
+/// Swap instructions of `venue` ready to send: creations of token accounts
+/// the wallet has are left out, then the compute budget is set from a
+/// simulation
+async fn swap_ixs(
+    rpc_client: &RpcClient,
+    owner: &Pubkey,
+    compute_budget: Option<&ComputeBudgetConfig>,
+    venue: Venue,
+    buy: bool,
+    ixs: Vec<Instruction>,
+) -> Result<Vec<Instruction>> {
+    let ixs = TxBuilder::new(ixs)
+        .skip_existing_atas(rpc_client)
+        .await
+        .build();
+    let units = simulate_instructions(rpc_client, owner, &ixs).await?;
+    Ok(with_compute_budget(compute_budget, venue, buy, units, ixs))
+}
+
+/// Where a buy or sell is sent
+#[derive(Debug, Clone)]
+enum Target {
+    /// Pump.fun bonding curve of a token not graduated yet
+    BondingCurve,
+    /// Raydium pool of a graduated pump.fun token
+    Raydium(String),
+    Route(Route),
+}
+
+impl Target {
+    fn venue(&self) -> Venue {
+        match self {
+            Target::BondingCurve => Venue::PumpFun,
+            Target::Raydium(_) => Venue::Raydium,
+            Target::Route(Route::Jupiter) => Venue::Jupiter,
+            Target::Route(Route::Pool(_, PoolProgram::OrcaWhirlpool)) => Venue::Orca,
+            Target::Route(Route::Pool(_, PoolProgram::MeteoraDlmm)) => Venue::Meteora,
+            Target::Route(Route::Pool(..)) => Venue::Raydium,
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -39,12 +219,199 @@ pub enum TokenInfo {
 }
 
 impl MemeTrader {
-    pub fn new(collection: Collection<ActiveTrade>) -> Self {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
         Self {
-            active_trades: Arc::new(ActiveTradeManager::new(collection)),
+            active_trades: Arc::new(ActiveTradeManager::new(storage)),
+            notifiers: Vec::new(),
+            webhooks: None,
+            buy_gate: BuyGate::default(),
+            price_cache: Arc::default(),
+            capital: None,
+            bot_state: None,
+            intents: None,
+            provider: None,
+            executions: None,
+            loss_streaks: None,
+            balance_guard: None,
+            journal: None,
+            wallet_balances: None,
+            sell_retry: None,
+            compute_budget: None,
+            venue_selection: None,
+            jupiter: None,
+            slippage: None,
+            event_hooks: RwLock::default(),
+        }
+    }
+
+    /// Send trade events to `notifier` too, next to the sinks already set
+    pub fn with_notifier(mut self, notifier: impl Notifier + 'static) -> Self {
+        self.notifiers.push(Arc::new(notifier));
+        self
+    }
+
+    /// Post lifecycle events to the configured webhook URLs
+    pub fn with_webhooks(mut self, webhooks: WebhookNotifier) -> Self {
+        self.webhooks = Some(webhooks.clone());
+        self.with_notifier(webhooks)
+    }
+
+    /// Share the prices streamed for another trader of the process
+    pub fn with_price_cache(mut self, price_cache: Arc<PriceCache>) -> Self {
+        self.price_cache = price_cache;
+        self
+    }
+
+    pub fn with_capital(mut self, capital: CapitalStore) -> Self {
+        self.capital = Some(capital);
+        self
+    }
+
+    /// Operator pause switches checked before every buy
+    pub fn with_bot_state(mut self, bot_state: BotStateStore) -> Self {
+        self.bot_state = Some(bot_state);
+        self
+    }
+
+    /// Buy every signal message at most once per strategy
+    pub fn with_intents(mut self, intents: ExecutionIntents) -> Self {
+        self.intents = Some(intents);
+        self
+    }
+
+    /// Name of the signal source, recorded on every new position
+    pub fn with_provider(mut self, provider: String) -> Self {
+        self.provider = Some(provider);
+        self
+    }
+
+    /// Record every buy and sell with its outcome
+    pub fn with_executions(mut self, executions: ExecutionLog) -> Self {
+        self.executions = Some(executions);
+        self
+    }
+
+    /// Count losers per strategy and shrink or pause it after a streak
+    pub fn with_loss_streaks(mut self, loss_streaks: LossStreakGuard) -> Self {
+        self.loss_streaks = Some(loss_streaks);
+        self
+    }
+
+    /// Check the wallet balance before each buy
+    pub fn with_balance_guard(mut self, balance_guard: BalanceGuard) -> Self {
+        self.balance_guard = Some(balance_guard);
+        self
+    }
+
+    /// Journal what is decided for every signal
+    pub fn with_journal(mut self, journal: Arc<DecisionJournal>) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    pub fn journal(&self) -> Option<&Arc<DecisionJournal>> {
+        self.journal.as_ref()
+    }
+
+    /// Keep wallet balances from the wallet webhook
+    pub fn with_wallet_balances(mut self, wallet_balances: Arc<WalletBalances>) -> Self {
+        self.wallet_balances = Some(wallet_balances);
+        self
+    }
+
+    /// Bound sell slippage and retry failed sells with more of it. Without
+    /// it sells accept any output and are not retried.
+    pub fn with_sell_retry(mut self, sell_retry: SellRetryConfig) -> Self {
+        self.sell_retry = Some(sell_retry);
+        self
+    }
+
+    /// Set the compute unit limit and price of swaps. Without it they get
+    /// whatever budget the instruction builders ask for.
+    pub fn with_compute_budget(mut self, compute_budget: ComputeBudgetConfig) -> Self {
+        self.compute_budget = Some(compute_budget);
+        self
+    }
+
+    /// Quote the pools of graduated tokens and swap on the best one
+    pub fn with_venue_selection(mut self, venue_selection: VenueSelectionConfig) -> Self {
+        self.venue_selection = Some(venue_selection);
+        self
+    }
+
+    /// Swap tokens without a supported pool along Jupiter's route
+    pub fn with_jupiter(mut self, jupiter: JupiterSwapConfig) -> Self {
+        self.jupiter = Some(jupiter);
+        self
+    }
+
+    /// Measure every fill against its quote, and adapt the slippage of
+    /// buys to it when configured
+    pub fn with_slippage_log(mut self, slippage: SlippageLog) -> Self {
+        self.slippage = Some(slippage);
+        self
+    }
+
+    pub fn loss_streaks(&self) -> Option<&LossStreakGuard> {
+        self.loss_streaks.as_ref()
+    }
+
+    pub fn capital(&self) -> Option<&CapitalStore> {
+        self.capital.as_ref()
+    }
+
+    pub fn bot_state(&self) -> Option<&BotStateStore> {
+        self.bot_state.as_ref()
+    }
+
+    pub fn intents(&self) -> Option<&ExecutionIntents> {
+        self.intents.as_ref()
+    }
+
+    pub fn notify(&self, event: TradeEvent) {
+        for hook in self
+            .event_hooks
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+        {
+            hook(&event);
+        }
+        for notifier in &self.notifiers {
+            notifier.notify(&event);
+        }
+    }
+
+    /// Tell the webhooks about a signal of `source` about to be checked and
+    /// traded
+    pub fn signal_received(&self, source: &str, trade: &Trade) {
+        if let Some(webhooks) = &self.webhooks {
+            webhooks.signal_received(source, trade);
         }
     }
 
+    /// Also hand every event to `hook`, it runs on the trading path and
+    /// must not block
+    pub fn add_event_hook(&self, hook: EventHook) {
+        self.event_hooks
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(hook);
+    }
+
+    pub fn active_trades(&self) -> &ActiveTradeManager {
+        &self.active_trades
+    }
+
+    pub fn buy_gate(&self) -> &BuyGate {
+        &self.buy_gate
+    }
+
+    /// Pool prices of held tokens, kept current by the price stream
+    pub fn price_cache(&self) -> Arc<PriceCache> {
+        Arc::clone(&self.price_cache)
+    }
+
     /// Retry getting balance with exponential backoff
     async fn get_balance_with_retry(
         owner: &Pubkey,
@@ -94,34 +461,534 @@ impl MemeTrader {
         slippage_bps: u16,
         tip_lamports: u64,
         entry_price: f64,
-    ) -> Result<String> {
-        let tx_sig = self
-            .buy_impl(token_address, sol_amount, slippage_bps, tip_lamports)
-            .await?;
+        signal: Option<&Signal>,
+    ) -> Result<Fill> {
+        let sized = self.guarded_sol_amount(sol_amount, tip_lamports).await;
+        match &sized {
+            Ok(sized) if self.balance_guard.is_some() => {
+                journal::pass("balance", format::sol(*sized))
+            }
+            Ok(_) => {}
+            Err(e) => journal::fail("balance", format!("{:#}", e)),
+        }
+        let sol_amount = *sized.as_ref().unwrap_or(&sol_amount);
+        let result = match sized {
+            Ok(sol_amount) => {
+                self.buy_and_record(
+                    token_address,
+                    token_name,
+                    strategy_id,
+                    sol_amount,
+                    slippage_bps,
+                    tip_lamports,
+                    entry_price,
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
+
+        match &result {
+            Ok(fill) => self.notify(TradeEvent::BuyExecuted {
+                token: token_name.to_string(),
+                token_address: token_address.to_string(),
+                strategy: strategy_id.to_string(),
+                sol_amount,
+                token_amount: fill.token_amount,
+                price: entry_price,
+                tx_sig: fill.tx_sig.clone(),
+                costs: fill.costs,
+            }),
+            // Simulated as asked, the journal has what it would have done
+            Err(e) if e.downcast_ref::<NotSent>().is_some() => {}
+            Err(e) => self.notify(TradeEvent::BuyFailed {
+                token: token_name.to_string(),
+                token_address: token_address.to_string(),
+                strategy: strategy_id.to_string(),
+                error: e.to_string(),
+            }),
+        }
+        if let Some(executions) = &self.executions {
+            executions
+                .record(
+                    TradeType::Open,
+                    strategy_id,
+                    token_name,
+                    token_address,
+                    "Open signal",
+                    signal,
+                    result.as_ref().map(Fill::executed),
+                )
+                .await;
+        }
 
+        result
+    }
+
+    /// `sol_amount` as allowed by the balance guard, the wallet keeping its
+    /// reserve and locked capital
+    async fn guarded_sol_amount(&self, sol_amount: f64, tip_lamports: u64) -> Result<f64> {
+        let Some(balance_guard) = &self.balance_guard else {
+            return Ok(sol_amount);
+        };
+        let locked_lamports = match &self.capital {
+            Some(capital) => capital.load().await?.locked_lamports,
+            None => 0,
+        };
+        let balance = self.sol_balance().await?;
+        balance_guard.check_buy(sol_amount, tip_lamports, balance, locked_lamports)
+    }
+
+    /// Lamports of the signer, from the webhook fed balances while they are
+    /// fresh
+    async fn sol_balance(&self) -> Result<u64> {
         let owner = SignerContext::current().await.pubkey();
+        let cached = self
+            .wallet_balances
+            .as_ref()
+            .and_then(|balances| balances.sol_lamports(&owner));
+        if let Some(lamports) = cached {
+            return Ok(lamports);
+        }
+        let lamports = make_rpc_client()
+            .get_balance(&Pubkey::from_str(&owner)?)
+            .await?;
+        if let Some(balances) = &self.wallet_balances {
+            balances.set_sol_lamports(&owner, lamports);
+        }
+        Ok(lamports)
+    }
 
-        let holdings = Self::get_balance_with_retry(
-            &Pubkey::from_str(&owner)?,
-            token_address,
-            10,                         // max_retries
-            Duration::from_millis(500), // initial_delay
-        )
-        .await?;
+    /// Marks a swap of `mint` in progress while the guard lives
+    fn swapping(&self, mint: &str) -> Option<SwapGuard> {
+        self.wallet_balances
+            .as_ref()
+            .map(|balances| balances.swapping(mint))
+    }
+
+    /// Refresh the balances a wallet webhook event touched. Positions in
+    /// a mint the wallet holds fewer tokens of than they account for, sent
+    /// away or sold elsewhere, are cut down to what is left.
+    pub async fn apply_wallet_event(&self, wallet: &str, touch: &WalletTouch) -> Result<()> {
+        let Some(balances) = &self.wallet_balances else {
+            return Ok(());
+        };
+        let rpc_client = make_rpc_client();
+        let owner = Pubkey::from_str(wallet)?;
+        if touch.sol {
+            balances.set_sol_lamports(wallet, rpc_client.get_balance(&owner).await?);
+        }
+        for mint in &touch.mints {
+            // Our own swap books its positions once it is done
+            if balances.is_swapping(mint) {
+                continue;
+            }
+            let held =
+                get_wallet_token_amount(&rpc_client, &owner, &Pubkey::from_str(mint)?).await?;
+            self.reconcile_holdings(mint, held).await?;
+        }
+        Ok(())
+    }
+
+    async fn reconcile_holdings(&self, mint: &str, held: u64) -> Result<()> {
+        let trades: Vec<ActiveTrade> = self
+            .active_trades
+            .load_all_trades()
+            .await?
+            .into_iter()
+            .filter(|trade| trade.token_address == mint)
+            .collect();
+        let holdings: Vec<u64> = trades.iter().map(|t| t.remaining_holdings).collect();
+        for (trade, shrunk) in trades.iter().zip(shrink_holdings(&holdings, held)) {
+            if shrunk == trade.remaining_holdings {
+                continue;
+            }
+            tracing::warn!(
+                "Wallet holds fewer {} than tracked, position of {} cut from {} to {}",
+                trade.token_name,
+                trade.strategy_id,
+                trade.remaining_holdings,
+                shrunk
+            );
+            if shrunk == 0 {
+                // Nothing left to sell, the position is closed without proceeds
+                self.active_trades
+                    .record_sell(mint, &trade.strategy_id, 0, 0)
+                    .await?;
+            } else {
+                self.active_trades
+                    .update_holdings(mint, &trade.strategy_id, shrunk)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn buy_and_record(
+        &self,
+        token_address: &str,
+        token_name: &str,
+        strategy_id: &str,
+        sol_amount: f64,
+        slippage_bps: u16,
+        tip_lamports: u64,
+        entry_price: f64,
+    ) -> Result<Fill> {
+        let _swap = self.swapping(token_address);
+        let owner = Pubkey::from_str(&SignerContext::current().await.pubkey())?;
+        let mint = Pubkey::from_str(token_address)?;
+        let rpc_client = make_rpc_client();
+        let amount_in = sol_to_lamports(sol_amount);
+        let target = self.target(token_address, Side::Buy, amount_in).await?;
+        let slippage_bps = self
+            .buy_slippage_bps(token_address, target.venue(), slippage_bps)
+            .await;
+        // Before the buy is sent, while the reserves are those it trades
+        // against
+        let quote = self
+            .slippage_quote(token_address, Side::Buy, amount_in, &target)
+            .await;
+
+        // The balance is read while the buy is sent, to tell a double fill
+        // from tokens held before
+        let attempts = Arc::new(TxAttempts::default());
+        let submitted_at = Utc::now();
+        let (held_before, result) = tokio::join!(
+            get_wallet_token_amount(&rpc_client, &owner, &mint),
+            attempts.track(self.buy_on(
+                token_address,
+                target,
+                sol_amount,
+                slippage_bps,
+                tip_lamports
+            ))
+        );
+        // Nothing is recorded before the buy confirmed
+        let result = match result {
+            Ok((tx_sig, venue)) => attempts
+                .confirm(&rpc_client, &tx_sig, tip_lamports)
+                .await
+                .map(|()| (tx_sig, venue)),
+            Err(e) => Err(e),
+        };
+        let (tx_sig, venue) = match result {
+            Ok(result) => result,
+            Err(e) => match Self::landed_attempt(&attempts, &rpc_client, &e, tip_lamports).await? {
+                Some(landed) => landed,
+                None => return Err(e),
+            },
+        };
+        journal::pass("venue", format!("{} in {}", venue, tx_sig));
+        let confirmed_at = Utc::now();
+
+        // What the transaction delivered, net of transfer fees and of tokens
+        // already held, with the wallet balance as a fallback
+        let holdings: u64 =
+            match get_token_balance_change(&rpc_client, &tx_sig, &owner, &mint).await {
+                Ok(received) if received > 0 => received as u64,
+                result => {
+                    if let Err(e) = result {
+                        tracing::warn!("Could not read tokens received in {}: {:?}", tx_sig, e);
+                    }
+                    Self::get_balance_with_retry(
+                        &owner,
+                        token_address,
+                        10,                         // max_retries
+                        Duration::from_millis(500), // initial_delay
+                    )
+                    .await?
+                    .parse()?
+                }
+            };
 
         tracing::info!("Holdings: {}", holdings);
+        if let Some(quote) = quote {
+            self.record_slippage(quote, holdings, venue, &tx_sig, Some(slippage_bps))
+                .await;
+        }
+
+        let transfer_fee_bps = get_transfer_fee_bps(&rpc_client, &mint)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::warn!("Could not read transfer fee of {}: {:?}", token_address, e);
+                None
+            });
+        if let Some(bps) = transfer_fee_bps {
+            tracing::warn!("{} charges a transfer fee of {} bps", token_name, bps);
+        }
+        let sol_spent_lamports = match get_fee_payer_balance_change(&rpc_client, &tx_sig).await {
+            Ok(change) => change.unsigned_abs(),
+            Err(e) => {
+                tracing::warn!("Could not read SOL spent on {}: {:?}", tx_sig, e);
+                sol_to_lamports(sol_amount)
+            }
+        };
+        // What the buy actually cost per token received, slippage, fees and
+        // transfer fees included, the signal's price when it can't be known
+        let paid_price =
+            match tokio::join!(token_registry().decimals(token_address), sol_price_usd()) {
+                (Ok(decimals), Ok(sol_price)) => {
+                    effective_price_usd(sol_spent_lamports, holdings, decimals, sol_price)
+                }
+                (decimals, sol_price) => {
+                    if let Some(e) = decimals.err().or(sol_price.err()) {
+                        tracing::warn!("Could not price the buy of {}: {:?}", token_name, e);
+                    }
+                    None
+                }
+            };
+        let entry_price = paid_price.unwrap_or(entry_price);
 
         let mut active_trade = ActiveTrade::new(
             token_name.to_string(),
             token_address.to_string(),
             strategy_id.to_string(),
-            holdings.parse()?,
+            holdings,
             entry_price,
         );
+        active_trade.provider = self.provider.clone();
+        active_trade.transfer_fee_bps = transfer_fee_bps;
+        active_trade.sol_spent_lamports = sol_spent_lamports;
 
         self.active_trades.save_trade(&mut active_trade).await?;
 
-        Ok(tx_sig)
+        match held_before {
+            Ok(held_before) => {
+                if let Err(e) = self
+                    .correct_double_buy(&attempts, &active_trade, held_before, tip_lamports)
+                    .await
+                {
+                    tracing::error!("Double buy check of {} failed: {:?}", token_name, e);
+                }
+            }
+            Err(e) => tracing::warn!(
+                "Could not read the balance of {} before the buy, double buys go unchecked: {:?}",
+                token_name,
+                e
+            ),
+        }
+
+        Ok(Fill {
+            costs: fill_costs(&rpc_client, &tx_sig, tip_lamports).await,
+            tx_sig,
+            token_amount: active_trade.initial_holdings,
+            venue,
+            sol_lamports: active_trade.sol_spent_lamports,
+            submitted_at,
+            confirmed_at,
+            pnl: None,
+        })
+    }
+
+    /// Sell what a resend racing the original, or a second attempt, bought on
+    /// top of the position. Waits for the pending attempts, then compares the
+    /// wallet with its balance before the buy.
+    async fn correct_double_buy(
+        &self,
+        attempts: &TxAttempts,
+        active_trade: &ActiveTrade,
+        held_before: u64,
+        tip_lamports: u64,
+    ) -> Result<()> {
+        let rpc_client = make_rpc_client();
+        let owner = Pubkey::from_str(&SignerContext::current().await.pubkey())?;
+        let mint = Pubkey::from_str(&active_trade.token_address)?;
+        let landed = attempts.settle(&rpc_client, tip_lamports).await?;
+        let held = get_wallet_token_amount(&rpc_client, &owner, &mint).await?;
+        let Some(excess) = double_buy_excess(held_before, active_trade.initial_holdings, held)
+        else {
+            return Ok(());
+        };
+
+        tracing::warn!(
+            "Buy of {} filled more than once ({} transactions landed), selling {} excess tokens",
+            active_trade.token_name,
+            landed.len(),
+            excess
+        );
+        let reason = "Double buy";
+        let submitted_at = Utc::now();
+        let result = match self
+            .sell_with_retries(&active_trade.token_address, excess, tip_lamports, None)
+            .await
+        {
+            Ok((tx_sig, venue)) => Ok(Fill {
+                sol_lamports: get_fee_payer_balance_change(&rpc_client, &tx_sig)
+                    .await
+                    .map_or(0, |change| change.max(0) as u64),
+                costs: fill_costs(&rpc_client, &tx_sig, tip_lamports).await,
+                tx_sig,
+                token_amount: excess,
+                venue,
+                submitted_at,
+                confirmed_at: Utc::now(),
+                pnl: None,
+            }),
+            Err(e) => Err(e),
+        };
+
+        self.notify(match &result {
+            Ok(fill) => TradeEvent::SellExecuted {
+                token: active_trade.token_name.clone(),
+                token_address: active_trade.token_address.clone(),
+                strategy: active_trade.strategy_id.clone(),
+                reason: reason.to_string(),
+                token_amount: excess,
+                remaining_amount: active_trade.remaining_holdings,
+                tx_sig: fill.tx_sig.clone(),
+                costs: fill.costs,
+                pnl: None,
+            },
+            Err(e) => TradeEvent::SellFailed {
+                token: active_trade.token_name.clone(),
+                token_address: active_trade.token_address.clone(),
+                strategy: active_trade.strategy_id.clone(),
+                reason: reason.to_string(),
+                error: e.to_string(),
+            },
+        });
+        if let Some(executions) = &self.executions {
+            executions
+                .record(
+                    TradeType::Close,
+                    &active_trade.strategy_id,
+                    &active_trade.token_name,
+                    &active_trade.token_address,
+                    reason,
+                    None,
+                    result.as_ref().map(Fill::executed),
+                )
+                .await;
+        }
+        result.map(|_| ())
+    }
+
+    /// Sell tokens of `token_address` held by the wallet but by no open
+    /// position, for a close signal whose open was missed. Sells at most
+    /// `max_sol` worth at the signal price.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn sell_untracked(
+        &self,
+        token_address: &str,
+        token_name: &str,
+        strategy_id: &str,
+        max_sol: f64,
+        tip_lamports: u64,
+        reason: &str,
+        signal: &Signal,
+    ) -> Result<Fill> {
+        let reason = format!("{} (untracked)", reason);
+        let result = self
+            .sell_untracked_balance(token_address, max_sol, tip_lamports, signal)
+            .await;
+
+        self.notify(match &result {
+            Ok((fill, remaining_amount)) => TradeEvent::SellExecuted {
+                token: token_name.to_string(),
+                token_address: token_address.to_string(),
+                strategy: strategy_id.to_string(),
+                reason: reason.clone(),
+                token_amount: fill.token_amount,
+                remaining_amount: *remaining_amount,
+                tx_sig: fill.tx_sig.clone(),
+                costs: fill.costs,
+                pnl: None,
+            },
+            Err(e) => TradeEvent::SellFailed {
+                token: token_name.to_string(),
+                token_address: token_address.to_string(),
+                strategy: strategy_id.to_string(),
+                reason: reason.clone(),
+                error: e.to_string(),
+            },
+        });
+        if let Some(executions) = &self.executions {
+            executions
+                .record(
+                    TradeType::Close,
+                    strategy_id,
+                    token_name,
+                    token_address,
+                    &reason,
+                    Some(signal),
+                    result.as_ref().map(|(fill, _)| fill.executed()),
+                )
+                .await;
+        }
+        result.map(|(fill, _)| fill)
+    }
+
+    /// Returns the fill and the untracked tokens left after it
+    async fn sell_untracked_balance(
+        &self,
+        token_address: &str,
+        max_sol: f64,
+        tip_lamports: u64,
+        signal: &Signal,
+    ) -> Result<(Fill, u64)> {
+        let _swap = self.swapping(token_address);
+        let rpc_client = make_rpc_client();
+        let owner = Pubkey::from_str(&SignerContext::current().await.pubkey())?;
+        let mint = Pubkey::from_str(token_address)?;
+
+        let held = get_wallet_token_amount(&rpc_client, &owner, &mint).await?;
+        let tracked: u64 = self
+            .active_trades
+            .load_all_trades()
+            .await?
+            .iter()
+            .filter(|trade| trade.token_address == token_address)
+            .map(|trade| trade.remaining_holdings)
+            .sum();
+        let untracked = held.saturating_sub(tracked);
+        if untracked == 0 {
+            return Err(anyhow!(
+                "No active trade found and no untracked balance of {}",
+                token_address
+            ));
+        }
+        if signal.price <= 0.0 {
+            return Err(anyhow!(
+                "Close signal of {} has no price to cap the untracked sell",
+                token_address
+            ));
+        }
+
+        let decimals = token_registry().decimals(token_address).await?;
+        let max_tokens = max_sol * sol_price_usd().await? / signal.price;
+        let max_amount = (max_tokens * 10f64.powi(decimals as i32)) as u64;
+        let sell_amount = untracked.min(max_amount);
+        if sell_amount == 0 {
+            return Err(anyhow!(
+                "Untracked balance of {} is worth nothing within {}",
+                token_address,
+                format::sol(max_sol)
+            ));
+        }
+        tracing::warn!(
+            "No position in {}, selling {} of {} untracked tokens",
+            token_address,
+            sell_amount,
+            untracked
+        );
+
+        let submitted_at = Utc::now();
+        let (tx_sig, venue) = self
+            .sell_with_retries(token_address, sell_amount, tip_lamports, None)
+            .await?;
+        let fill = Fill {
+            sol_lamports: get_fee_payer_balance_change(&rpc_client, &tx_sig)
+                .await
+                .map_or(0, |change| change.max(0) as u64),
+            costs: fill_costs(&rpc_client, &tx_sig, tip_lamports).await,
+            tx_sig,
+            token_amount: sell_amount,
+            venue,
+            submitted_at,
+            confirmed_at: Utc::now(),
+            pnl: None,
+        };
+        Ok((fill, untracked - sell_amount))
     }
 
     /// Meta sell function is all ecompasing sell function.
@@ -133,15 +1000,28 @@ impl MemeTrader {
         op_type: OperationType,
         strategy: &Strategy,
         tip_lamports: u64,
-    ) -> Result<String> {
+        signal: Option<&Signal>,
+    ) -> Result<Option<Fill>> {
         let active_trade = self
-            .active_trades
-            .get_trade(token_address, strategy_id)
-            .await?
-            .ok_or_else(|| anyhow!("No active trade found for token and strategy"))?;
-
+            .active_trade_to_sell(token_address, strategy_id, &op_type)
+            .await?;
         tracing::info!("Active trade: {:?}", active_trade);
 
+        if op_type == OperationType::TakeProfit {
+            if let Some(conditions) = &strategy.sell_conditions.take_profit_conditions {
+                return self
+                    .sell_tp_legs(
+                        &active_trade,
+                        conditions,
+                        profit_percentage,
+                        tip_lamports,
+                        signal,
+                    )
+                    .await;
+            }
+        }
+
+        let reason = op_type.to_string();
         let sell_amount =
             match active_trade.calculate_sell_amount(profit_percentage, op_type, strategy) {
                 Some(amount) => amount,
@@ -156,72 +1036,915 @@ impl MemeTrader {
 
         tracing::info!("Sell amount: {:?}", sell_amount);
 
-        let tx_sig = self
-            .sell_impl(token_address, sell_amount, tip_lamports)
+        self.sell_and_report(&active_trade, sell_amount, tip_lamports, &reason, signal)
+            .await
+            .map(Some)
+    }
+
+    /// Sell `fraction` of the position of `token_address` held for
+    /// `strategy_id`, the share of its holdings a copied wallet sold. From 1
+    /// on the whole position is sold.
+    pub async fn sell_fraction(
+        &self,
+        token_address: &str,
+        strategy_id: &str,
+        fraction: f64,
+        tip_lamports: u64,
+        signal: Option<&Signal>,
+    ) -> Result<Fill> {
+        let op_type = OperationType::Manual;
+        let active_trade = self
+            .active_trade_to_sell(token_address, strategy_id, &op_type)
+            .await?;
+        let sell_amount = active_trade.share_of_holdings(fraction);
+        if sell_amount == 0 {
+            return Err(anyhow!(
+                "A fraction of {} of the position is no token",
+                fraction
+            ));
+        }
+        tracing::info!(
+            "Selling {:.1}% of the position: {} of {}",
+            fraction * 100.0,
+            sell_amount,
+            active_trade.remaining_holdings
+        );
+        self.sell_and_report(
+            &active_trade,
+            sell_amount,
+            tip_lamports,
+            &op_type.to_string(),
+            signal,
+        )
+        .await
+    }
+
+    /// Position of `token_address` for `strategy_id`, a missing one is
+    /// reported as a failed sell
+    async fn active_trade_to_sell(
+        &self,
+        token_address: &str,
+        strategy_id: &str,
+        op_type: &OperationType,
+    ) -> Result<ActiveTrade> {
+        match self
+            .active_trades
+            .get_trade(token_address, strategy_id)
+            .await?
+        {
+            Some(active_trade) => Ok(active_trade),
+            None => {
+                let error = anyhow!("No active trade found for token and strategy");
+                self.notify(TradeEvent::SellFailed {
+                    token: token_address.to_string(),
+                    token_address: token_address.to_string(),
+                    strategy: strategy_id.to_string(),
+                    reason: op_type.to_string(),
+                    error: error.to_string(),
+                });
+                Err(error)
+            }
+        }
+    }
+
+    /// Sell `active_trade` down through the legs of its take profit ladder
+    /// reached at `profit_percentage`, in one sell when several are due.
+    /// The legs are recorded on the position so each fires once. `None` when
+    /// no leg is due or the position is already below their targets.
+    pub async fn sell_tp_legs(
+        &self,
+        active_trade: &ActiveTrade,
+        conditions: &[TakeProfitCondition],
+        profit_percentage: f64,
+        tip_lamports: u64,
+        signal: Option<&Signal>,
+    ) -> Result<Option<Fill>> {
+        let legs = active_trade.due_tp_legs(conditions, profit_percentage);
+        if legs.is_empty() {
+            return Ok(None);
+        }
+        let sell_amount: u64 = legs.iter().map(|(_, amount)| amount).sum();
+        for (pnl_percentage, amount) in &legs {
+            tracing::info!(
+                "> Take profit leg at {}% of {} ({}): selling {}",
+                pnl_percentage,
+                active_trade.token_name,
+                active_trade.strategy_id,
+                amount
+            );
+        }
+
+        let fill = if sell_amount > 0 {
+            let reason = OperationType::TakeProfit.to_string();
+            Some(
+                self.sell_and_report(active_trade, sell_amount, tip_lamports, &reason, signal)
+                    .await?,
+            )
+        } else {
+            None
+        };
+        let filled_at = Utc::now().timestamp();
+        let fills: Vec<TpLegFill> = legs
+            .into_iter()
+            .map(|(pnl_percentage, token_amount)| TpLegFill {
+                pnl_percentage,
+                token_amount,
+                sol_received_lamports: fill.as_ref().map_or(0, |fill| {
+                    (fill.sol_lamports as u128 * token_amount as u128 / sell_amount as u128) as u64
+                }),
+                tx_sig: fill.as_ref().map(|fill| fill.tx_sig.clone()),
+                filled_at,
+            })
+            .collect();
+        self.active_trades
+            .record_tp_legs(active_trade, &fills)
+            .await?;
+        Ok(fill)
+    }
+
+    /// Sell part of an active trade and update its remaining holdings,
+    /// removing the trade once nothing is left. `reason` is only used for
+    /// notifications.
+    pub async fn sell_position(
+        &self,
+        active_trade: &ActiveTrade,
+        sell_amount: u64,
+        tip_lamports: u64,
+        reason: &str,
+    ) -> Result<Fill> {
+        self.sell_and_report(active_trade, sell_amount, tip_lamports, reason, None)
+            .await
+    }
+
+    async fn sell_and_report(
+        &self,
+        active_trade: &ActiveTrade,
+        sell_amount: u64,
+        tip_lamports: u64,
+        reason: &str,
+        signal: Option<&Signal>,
+    ) -> Result<Fill> {
+        let result = self
+            .sell_and_record(active_trade, sell_amount, tip_lamports)
+            .await;
+
+        self.notify(match &result {
+            Ok(fill) => TradeEvent::SellExecuted {
+                token: active_trade.token_name.clone(),
+                token_address: active_trade.token_address.clone(),
+                strategy: active_trade.strategy_id.clone(),
+                reason: reason.to_string(),
+                token_amount: fill.token_amount,
+                remaining_amount: active_trade.remaining_holdings - fill.token_amount,
+                tx_sig: fill.tx_sig.clone(),
+                costs: fill.costs,
+                pnl: (fill.token_amount == active_trade.remaining_holdings && fill.pnl.is_some())
+                    .then(|| active_trade.closed_pnl(fill.sol_lamports)),
+            },
+            Err(e) => TradeEvent::SellFailed {
+                token: active_trade.token_name.clone(),
+                token_address: active_trade.token_address.clone(),
+                strategy: active_trade.strategy_id.clone(),
+                reason: reason.to_string(),
+                error: e.to_string(),
+            },
+        });
+        if let Some(executions) = &self.executions {
+            executions
+                .record(
+                    TradeType::Close,
+                    &active_trade.strategy_id,
+                    &active_trade.token_name,
+                    &active_trade.token_address,
+                    reason,
+                    signal,
+                    result.as_ref().map(Fill::executed),
+                )
+                .await;
+        }
+
+        result
+    }
+
+    /// Sell `pct` percent of every open position in `mint`, or only of the
+    /// one of `strategy`. Used by the manual control interfaces. A failed
+    /// sell of one strategy doesn't stop the others, each position comes
+    /// back with its fill or error; it's an error only when none sold.
+    pub async fn sell_mint(
+        &self,
+        mint: &str,
+        pct: f64,
+        strategy: Option<&str>,
+        tip_lamports: u64,
+        reason: &str,
+    ) -> Result<Vec<(ActiveTrade, Result<Fill>)>> {
+        if !(pct > 0.0 && pct <= 100.0) {
+            return Err(anyhow!("Percentage must be within (0, 100], got {}", pct));
+        }
+
+        let trades: Vec<ActiveTrade> = self
+            .active_trades
+            .load_all_trades()
+            .await?
+            .into_iter()
+            .filter(|t| t.token_address == mint)
+            .filter(|t| strategy.map_or(true, |s| t.strategy_id == s))
+            .collect();
+        if trades.is_empty() {
+            return Err(anyhow!("No open position in {}", mint));
+        }
+
+        let mut results = Vec::new();
+        for trade in trades {
+            let amount = if pct >= 100.0 {
+                trade.remaining_holdings
+            } else {
+                (trade.remaining_holdings as f64 * pct / 100.0).round() as u64
+            };
+            let fill = self
+                .sell_position(&trade, amount, tip_lamports, reason)
+                .await;
+            results.push((trade, fill));
+        }
+        if results.iter().all(|(_, fill)| fill.is_err()) {
+            return Err(anyhow!("{}", sell_outcome(&results)));
+        }
+        Ok(results)
+    }
+
+    /// Sell through the slippage schedule, each attempt re-quotes the pool.
+    /// With `quote` each attempt is also quoted on its venue right before
+    /// it's sent, the quote of the last one is left there.
+    async fn sell_with_retries(
+        &self,
+        token_address: &str,
+        token_amount: u64,
+        tip_lamports: u64,
+        mut quote: Option<&mut Option<Quote>>,
+    ) -> Result<(String, Venue)> {
+        let schedule = match &self.sell_retry {
+            Some(sell_retry) => slippage_schedule(sell_retry),
+            None => vec![None],
+        };
+        // A failed attempt may still land, a resend would then sell twice
+        let attempts = Arc::new(TxAttempts::default());
+        let rpc_client = make_rpc_client();
+        let mut last_error = None;
+        for (attempt, slippage_bps) in schedule.into_iter().enumerate() {
+            if let Some(e) = &last_error {
+                if let Some(landed) =
+                    Self::landed_attempt(&attempts, &rpc_client, e, tip_lamports).await?
+                {
+                    return Ok(landed);
+                }
+                tracing::warn!(
+                    "Sell attempt {} of {} failed, retrying with {} slippage: {:?}",
+                    attempt,
+                    token_address,
+                    slippage_bps.map_or("unbounded".to_string(), |bps| format!("{} bps", bps)),
+                    e
+                );
+            }
+            let target = match self.target(token_address, Side::Sell, token_amount).await {
+                Ok(target) => target,
+                Err(e) => {
+                    last_error = Some(e);
+                    continue;
+                }
+            };
+            if let Some(quote) = quote.as_deref_mut() {
+                *quote = self
+                    .slippage_quote(token_address, Side::Sell, token_amount, &target)
+                    .await;
+            }
+            match attempts
+                .track(self.sell_on(
+                    token_address,
+                    target,
+                    token_amount,
+                    slippage_bps,
+                    tip_lamports,
+                ))
+                .await
+            {
+                Ok((tx_sig, venue)) => {
+                    match attempts.confirm(&rpc_client, &tx_sig, tip_lamports).await {
+                        Ok(()) => {
+                            journal::pass("venue", format!("{} in {}", venue, tx_sig));
+                            return Ok((tx_sig, venue));
+                        }
+                        Err(e) => last_error = Some(e),
+                    }
+                }
+                // More slippage simulates the same
+                Err(e) if e.downcast_ref::<NotSent>().is_some() => return Err(e),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        let error = last_error.expect("the slippage schedule is never empty");
+        match Self::landed_attempt(&attempts, &rpc_client, &error, tip_lamports).await? {
+            Some(landed) => Ok(landed),
+            None => Err(error),
+        }
+    }
+
+    /// The transaction of a failed buy or sell that landed anyway, once no
+    /// attempt can land anymore
+    async fn landed_attempt(
+        attempts: &TxAttempts,
+        rpc_client: &RpcClient,
+        error: &anyhow::Error,
+        tip_lamports: u64,
+    ) -> Result<Option<(String, Venue)>> {
+        let landed = attempts.settle(rpc_client, tip_lamports).await?;
+        let Some(attempt) = landed.first() else {
+            return Ok(None);
+        };
+        tracing::warn!(
+            "Attempt {} landed on {} although the swap failed: {:?}",
+            attempt.signature,
+            attempt.venue,
+            error
+        );
+        Ok(Some((attempt.signature.to_string(), attempt.venue)))
+    }
+
+    async fn sell_and_record(
+        &self,
+        active_trade: &ActiveTrade,
+        sell_amount: u64,
+        tip_lamports: u64,
+    ) -> Result<Fill> {
+        let _swap = self.swapping(&active_trade.token_address);
+        let sell_amount = sell_amount.min(active_trade.remaining_holdings);
+        let submitted_at = Utc::now();
+        let mut quote = None;
+        let (tx_sig, venue) = self
+            .sell_with_retries(
+                &active_trade.token_address,
+                sell_amount,
+                tip_lamports,
+                Some(&mut quote),
+            )
+            .await?;
+        let confirmed_at = Utc::now();
+
+        let rpc_client = make_rpc_client();
+        if let Some(quote) = quote {
+            match get_swap_sol_change(&rpc_client, &tx_sig).await {
+                Ok(change) => {
+                    self.record_slippage(quote, change.max(0) as u64, venue, &tx_sig, None)
+                        .await
+                }
+                Err(e) => tracing::warn!("Could not read the swap output of {}: {:?}", tx_sig, e),
+            }
+        }
+        let sol_received = match get_fee_payer_balance_change(&rpc_client, &tx_sig).await {
+            Ok(change) => Some(change.max(0) as u64),
+            Err(e) => {
+                tracing::warn!("Could not read SOL received from {}: {:?}", tx_sig, e);
+                None
+            }
+        };
+        // Unknown proceeds count as zero so the daily loss limit errs on the safe side
+        let sol_received_lamports = sol_received.unwrap_or(0);
+
+        // Update the trade, it is closed once nothing remains
+        let new_holdings = active_trade.remaining_holdings - sell_amount;
+        self.active_trades
+            .record_sell(
+                &active_trade.token_address,
+                &active_trade.strategy_id,
+                new_holdings,
+                sol_received_lamports,
+            )
             .await?;
+        if new_holdings == 0 {
+            let pnl = active_trade.realized_pnl_lamports() + sol_received_lamports as i64;
+            self.record_closed_trade(&active_trade.strategy_id, pnl)
+                .await;
+        }
+
+        Ok(Fill {
+            costs: fill_costs(&rpc_client, &tx_sig, tip_lamports).await,
+            tx_sig,
+            token_amount: sell_amount,
+            venue,
+            sol_lamports: sol_received_lamports,
+            submitted_at,
+            confirmed_at,
+            pnl: sol_received.map(|sol| active_trade.sell_pnl(sell_amount, sol)),
+        })
+    }
+
+    /// Slippage of a buy of `token_address` on `venue`, adapted to its
+    /// recent buys there
+    async fn buy_slippage_bps(&self, token_address: &str, venue: Venue, configured: u16) -> u16 {
+        let Some(slippage) = &self.slippage else {
+            return configured;
+        };
+        let bps = slippage
+            .buy_slippage_bps(token_address, venue, configured)
+            .await;
+        if bps != configured {
+            journal::pass("slippage", format!("{} bps instead of {}", bps, configured));
+        }
+        bps
+    }
+
+    /// Quote of a swap about to be sent to `target`, to measure its fill
+    /// against. `None` without a slippage log.
+    async fn slippage_quote(
+        &self,
+        mint: &str,
+        side: Side,
+        amount_in: u64,
+        target: &Target,
+    ) -> Option<Quote> {
+        self.slippage.as_ref()?;
+        match self.quote_target(mint, side, amount_in, target).await {
+            Ok(quote) => Some(quote),
+            Err(e) => {
+                tracing::debug!("Could not quote the {} of {}: {:?}", side, mint, e);
+                None
+            }
+        }
+    }
+
+    async fn quote_target(
+        &self,
+        mint: &str,
+        side: Side,
+        amount_in: u64,
+        target: &Target,
+    ) -> Result<Quote> {
+        let quoter = Quoter::new(make_rpc_client());
+        match target {
+            Target::BondingCurve => quoter.quote_bonding_curve(mint, side, amount_in).await,
+            Target::Raydium(pool) => {
+                quoter
+                    .quote_pool(mint, side, amount_in, &Pubkey::from_str(pool)?)
+                    .await
+            }
+            Target::Route(Route::Pool(pool, _)) => {
+                quoter.quote_pool(mint, side, amount_in, pool).await
+            }
+            Target::Route(Route::Jupiter) => {
+                let api_url = &self.jupiter_config()?.api_url;
+                quoter
+                    .with_jupiter(format!("{}/quote", api_url.trim_end_matches('/')))
+                    .quote_jupiter(mint, side, amount_in)
+                    .await
+            }
+        }
+    }
+
+    /// Store how far the output of a fill was from its quote
+    async fn record_slippage(
+        &self,
+        quote: Quote,
+        actual_out: u64,
+        venue: Venue,
+        tx_sig: &str,
+        tolerance_bps: Option<u16>,
+    ) {
+        let Some(slippage) = &self.slippage else {
+            return;
+        };
+        let Some(slippage_bps) = realized_slippage_bps(quote.amount_out, actual_out) else {
+            return;
+        };
+        let sample = SlippageSample {
+            date: Utc::now(),
+            token_address: quote.mint,
+            venue,
+            side: quote.side,
+            quoted_out: quote.amount_out,
+            actual_out,
+            slippage_bps,
+            tolerance_bps,
+            tx_sig: tx_sig.to_string(),
+        };
+        if let Err(e) = slippage.record(&sample).await {
+            tracing::warn!("Failed to store the slippage of {}: {:?}", tx_sig, e);
+        }
+    }
+
+    async fn record_closed_trade(&self, strategy_id: &str, pnl_lamports: i64) {
+        let Some(loss_streaks) = &self.loss_streaks else {
+            return;
+        };
+        match loss_streaks.record_close(strategy_id, pnl_lamports).await {
+            Ok(Some(reason)) => {
+                tracing::warn!("Losing streak: {}", reason);
+                if loss_streaks.action() == StreakAction::Pause {
+                    self.notify(TradeEvent::BuysPaused {
+                        reason,
+                        breaker: Some("loss_streak".to_string()),
+                    });
+                }
+            }
+            Ok(None) => {}
+            Err(e) => tracing::error!("Failed to record result of {}: {:?}", strategy_id, e),
+        }
+    }
+
+    /// Get information about a meme token from either Pump.fun or Dexscreener.
+    /// Graduated pump.fun tokens whose primary DexScreener pair is on Meteora
+    /// are traded there instead of their Raydium pool.
+    pub async fn get_token_info(&self, token_address: &str) -> Result<TokenInfo> {
+        // Try Pump.fun first
+        let pump_result = match Pubkey::from_str(token_address) {
+            Ok(mint) => cached_fetch_metadata(&mint).await,
+            Err(_) => Err(anyhow!("Invalid Solana address format")),
+        };
+
+        // If Pump.fun fails, try Dexscreener
+        if pump_result.is_err() {
+            let dex_info = cached_search_ticker(token_address).await?;
+            if dex_info.pairs.is_empty() {
+                return Err(anyhow!("No trading pair found"));
+            }
+            tracing::info!("Dexscreener pairs: {:?}", dex_info.pairs);
+            Ok(TokenInfo::Dexscreener(dex_info))
+        } else {
+            let pump_info = pump_result.unwrap();
+            if pump_info.complete {
+                if let Ok(dex_info) = cached_search_ticker(token_address).await {
+                    if primary_pair(token_address, &dex_info.pairs)
+                        .is_some_and(|pair| pair.dex_id == "meteora")
+                    {
+                        tracing::info!("Pump.fun: complete, primary pair is on Meteora");
+                        return Ok(TokenInfo::Dexscreener(dex_info));
+                    }
+                }
+            }
+            Ok(TokenInfo::Pump(pump_info))
+        }
+    }
+
+    /// Launch a token on Pump.fun with `image` as its picture and buy
+    /// `initial_buy_sol` worth of it in the creating transaction. The dev
+    /// buy isn't tracked as a position. Returns the mint.
+    pub async fn deploy_pump_token(
+        &self,
+        name: &str,
+        symbol: &str,
+        image: &Path,
+        initial_buy_sol: f64,
+    ) -> Result<Pubkey> {
+        let params = DeployTokenParams {
+            name: name.to_string(),
+            symbol: symbol.to_string(),
+            description: String::new(),
+            twitter: None,
+            telegram: None,
+            website: None,
+        };
+        let uri = upload_metadata(&params, image).await?;
+        info!("Pump.fun: uploaded metadata of {} to {}", symbol, uri);
+        let (mint, tx_sig) = deploy_pump_token(
+            &params,
+            &uri,
+            sol_to_lamports(initial_buy_sol),
+            self.compute_budget.as_ref(),
+        )
+        .await?;
+        info!(
+            "Pump.fun: deployed {} as {} with a {} buy: {}",
+            symbol,
+            mint,
+            format::sol(initial_buy_sol),
+            tx_sig
+        );
+        Ok(mint)
+    }
+
+    /// Buy a token on Pump.fun
+    pub async fn buy_pump_fun(
+        &self,
+        token_address: &str,
+        sol_amount: f64,
+        slippage_bps: u16,
+        tip_lamports: u64,
+    ) -> Result<String> {
+        info!(
+            "Pump.fun: try buying {} worth of token {}",
+            format::sol(sol_amount),
+            token_address
+        );
+        let token_address = token_address.to_string();
+
+        let compute_budget = self.compute_budget.clone();
+        execute_solana_transaction_with_tip(
+            move |owner| async move {
+                let rpc_client = make_rpc_client();
+                let ixs = create_buy_pump_fun_ix(
+                    token_address.to_string(),
+                    sol_to_lamports(sol_amount),
+                    slippage_bps,
+                    &rpc_client,
+                    &owner,
+                )
+                .await?;
+                swap_ixs(
+                    &rpc_client,
+                    &owner,
+                    compute_budget.as_ref(),
+                    Venue::PumpFun,
+                    true,
+                    ixs,
+                )
+                .await
+            },
+            tip_lamports,
+        )
+        .await
+    }
+
+    /// Sell a token on Pump.fun
+    pub async fn sell_pump_fun(
+        &self,
+        token_address: &str,
+        token_amount: u64,
+        tip_lamports: u64,
+    ) -> Result<String> {
+        info!("Selling {} tokens of {}", token_amount, token_address);
+
+        let token_address = token_address.to_string();
+        let compute_budget = self.compute_budget.clone();
+        execute_solana_transaction_with_tip(
+            move |owner| async move {
+                let ixs = create_sell_pump_fun_ix(token_address.to_string(), token_amount, &owner)
+                    .await?;
+                swap_ixs(
+                    &make_rpc_client(),
+                    &owner,
+                    compute_budget.as_ref(),
+                    Venue::PumpFun,
+                    false,
+                    ixs,
+                )
+                .await
+            },
+            tip_lamports,
+        )
+        .await
+    }
+
+    pub async fn buy_raydium(
+        &self,
+        token_address: &str,
+        raydium_pool: &str,
+        sol_amount: f64,
+        slippage_bps: u16,
+        tip_lamports: u64,
+    ) -> Result<String> {
+        info!(
+            "Raydium: try buying {} worth of token {}",
+            format::sol(sol_amount),
+            token_address
+        );
+        let raydium_pool = raydium_pool.to_string();
+        let token_address = token_address.to_string();
+
+        let compute_budget = self.compute_budget.clone();
+        execute_solana_transaction_with_tip(
+            move |owner| async move {
+                let rpc_client = make_rpc_client();
+                let program =
+                    detect_pool_program(&rpc_client, &Pubkey::from_str(&raydium_pool)?).await?;
+                let token = Pubkey::from_str(token_address.as_str())?;
+                let ixs = match program {
+                    PoolProgram::RaydiumAmmV4 => {
+                        create_raydium_sol_swap_ix(
+                            raydium_pool,
+                            sol_to_lamports(sol_amount),
+                            slippage_bps,
+                            token,
+                            &rpc_client,
+                            &owner,
+                        )
+                        .await?
+                    }
+                    PoolProgram::RaydiumCpmm => {
+                        create_raydium_cpmm_sol_swap_ix(
+                            raydium_pool,
+                            sol_to_lamports(sol_amount),
+                            slippage_bps,
+                            token,
+                            &rpc_client,
+                            &owner,
+                        )
+                        .await?
+                    }
+                    other => {
+                        return Err(anyhow!("Swaps through {} pools are not supported", other))
+                    }
+                };
+                swap_ixs(
+                    &rpc_client,
+                    &owner,
+                    compute_budget.as_ref(),
+                    Venue::Raydium,
+                    true,
+                    ixs,
+                )
+                .await
+            },
+            tip_lamports,
+        )
+        .await
+    }
+
+    pub async fn sell_raydium(
+        &self,
+        token_address: &str,
+        raydium_pool: &str,
+        token_amount: u64,
+        slippage_bps: Option<u16>,
+        tip_lamports: u64,
+    ) -> Result<String> {
+        info!(
+            "Raydium: try selling {} tokens of {} on Raydium pool {}",
+            token_amount, token_address, raydium_pool
+        );
+        let raydium_pool = raydium_pool.to_string();
+        let token_address = token_address.to_string();
+
+        let compute_budget = self.compute_budget.clone();
+        execute_solana_transaction_with_tip(
+            move |owner| async move {
+                let rpc_client = make_rpc_client();
+                let program =
+                    detect_pool_program(&rpc_client, &Pubkey::from_str(&raydium_pool)?).await?;
+                let token = Pubkey::from_str(token_address.as_str())?;
+                let ixs = match program {
+                    PoolProgram::RaydiumAmmV4 => {
+                        create_raydium_token_swap_ix(
+                            raydium_pool,
+                            token_amount,
+                            slippage_bps,
+                            token,
+                            &rpc_client,
+                            &owner,
+                        )
+                        .await?
+                    }
+                    PoolProgram::RaydiumCpmm => {
+                        create_raydium_cpmm_token_swap_ix(
+                            raydium_pool,
+                            token_amount,
+                            slippage_bps,
+                            token,
+                            &rpc_client,
+                            &owner,
+                        )
+                        .await?
+                    }
+                    other => {
+                        return Err(anyhow!("Swaps through {} pools are not supported", other))
+                    }
+                };
+                swap_ixs(
+                    &rpc_client,
+                    &owner,
+                    compute_budget.as_ref(),
+                    Venue::Raydium,
+                    false,
+                    ixs,
+                )
+                .await
+            },
+            tip_lamports,
+        )
+        .await
+    }
+
+    pub async fn buy_orca(
+        &self,
+        token_address: &str,
+        whirlpool: &str,
+        sol_amount: f64,
+        slippage_bps: u16,
+        tip_lamports: u64,
+    ) -> Result<String> {
+        info!(
+            "Orca: try buying {} worth of token {}",
+            format::sol(sol_amount),
+            token_address
+        );
+        let whirlpool = whirlpool.to_string();
+        let token_address = token_address.to_string();
 
-        // Update or remove the trade based on remaining holdings
-        let new_holdings = active_trade.remaining_holdings - sell_amount;
-        if new_holdings == 0 {
-            self.active_trades
-                .remove_trade(token_address, strategy_id)
-                .await?;
-        } else {
-            self.active_trades
-                .update_holdings(token_address, strategy_id, new_holdings)
+        let compute_budget = self.compute_budget.clone();
+        execute_solana_transaction_with_tip(
+            move |owner| async move {
+                let rpc_client = make_rpc_client();
+                let ixs = create_orca_sol_swap_ix(
+                    whirlpool,
+                    sol_to_lamports(sol_amount),
+                    slippage_bps,
+                    Pubkey::from_str(token_address.as_str())?,
+                    &rpc_client,
+                    &owner,
+                )
                 .await?;
-        }
-
-        Ok(tx_sig)
+                swap_ixs(
+                    &rpc_client,
+                    &owner,
+                    compute_budget.as_ref(),
+                    Venue::Orca,
+                    true,
+                    ixs,
+                )
+                .await
+            },
+            tip_lamports,
+        )
+        .await
     }
 
-    /// Get information about a meme token from either Pump.fun or Dexscreener
-    pub async fn get_token_info(&self, token_address: &str) -> Result<TokenInfo> {
-        // Try Pump.fun first
-        let pump_result = match Pubkey::from_str(token_address) {
-            Ok(mint) => fetch_metadata(&mint).await,
-            Err(_) => Err(anyhow!("Invalid Solana address format")),
-        };
+    pub async fn sell_orca(
+        &self,
+        token_address: &str,
+        whirlpool: &str,
+        token_amount: u64,
+        slippage_bps: Option<u16>,
+        tip_lamports: u64,
+    ) -> Result<String> {
+        info!(
+            "Orca: try selling {} tokens of {} on Whirlpool {}",
+            token_amount, token_address, whirlpool
+        );
+        let whirlpool = whirlpool.to_string();
+        let token_address = token_address.to_string();
 
-        // If Pump.fun fails, try Dexscreener
-        if pump_result.is_err() {
-            let dex_info = search_ticker(token_address.to_string()).await?;
-            let dex_info_clone = dex_info.clone();
-            let pairs = dex_info_clone
-                .pairs
-                .into_iter()
-                .find(|pair| pair.dex_id == "raydium") // we currently support only Raydium besids Pump.fun
-                .ok_or_else(|| anyhow!("No Raydium trading pair found"))?;
-            tracing::info!("Dexscreener pairs: {:?}", pairs);
-            Ok(TokenInfo::Dexscreener(dex_info))
-        } else {
-            Ok(TokenInfo::Pump(pump_result.unwrap()))
-        }
+        let compute_budget = self.compute_budget.clone();
+        execute_solana_transaction_with_tip(
+            move |owner| async move {
+                let rpc_client = make_rpc_client();
+                let ixs = create_orca_token_swap_ix(
+                    whirlpool,
+                    token_amount,
+                    slippage_bps,
+                    Pubkey::from_str(token_address.as_str())?,
+                    &rpc_client,
+                    &owner,
+                )
+                .await?;
+                swap_ixs(
+                    &rpc_client,
+                    &owner,
+                    compute_budget.as_ref(),
+                    Venue::Orca,
+                    false,
+                    ixs,
+                )
+                .await
+            },
+            tip_lamports,
+        )
+        .await
     }
 
-    /// Buy a token on Pump.fun
-    pub async fn buy_pump_fun(
+    pub async fn buy_meteora(
         &self,
         token_address: &str,
+        lb_pair: &str,
         sol_amount: f64,
         slippage_bps: u16,
         tip_lamports: u64,
     ) -> Result<String> {
         info!(
-            "Pump.fun: try buying {} SOL worth of token {}",
-            sol_amount, token_address
+            "Meteora: try buying {} worth of token {}",
+            format::sol(sol_amount),
+            token_address
         );
+        let lb_pair = lb_pair.to_string();
         let token_address = token_address.to_string();
 
+        let compute_budget = self.compute_budget.clone();
         execute_solana_transaction_with_tip(
             move |owner| async move {
-                create_buy_pump_fun_ix(
-                    token_address.to_string(),
+                let rpc_client = make_rpc_client();
+                let ixs = create_meteora_sol_swap_ix(
+                    lb_pair,
                     sol_to_lamports(sol_amount),
                     slippage_bps,
-                    &make_rpc_client(),
+                    Pubkey::from_str(token_address.as_str())?,
+                    &rpc_client,
                     &owner,
                 )
+                .await?;
+                swap_ixs(
+                    &rpc_client,
+                    &owner,
+                    compute_budget.as_ref(),
+                    Venue::Meteora,
+                    true,
+                    ixs,
+                )
                 .await
             },
             tip_lamports,
@@ -229,50 +1952,86 @@ impl MemeTrader {
         .await
     }
 
-    /// Sell a token on Pump.fun
-    pub async fn sell_pump_fun(
+    pub async fn sell_meteora(
         &self,
         token_address: &str,
+        lb_pair: &str,
         token_amount: u64,
+        slippage_bps: Option<u16>,
         tip_lamports: u64,
     ) -> Result<String> {
-        info!("Selling {} tokens of {}", token_amount, token_address);
-
+        info!(
+            "Meteora: try selling {} tokens of {} on DLMM pool {}",
+            token_amount, token_address, lb_pair
+        );
+        let lb_pair = lb_pair.to_string();
         let token_address = token_address.to_string();
+
+        let compute_budget = self.compute_budget.clone();
         execute_solana_transaction_with_tip(
             move |owner| async move {
-                create_sell_pump_fun_ix(token_address.to_string(), token_amount, &owner).await
+                let rpc_client = make_rpc_client();
+                let ixs = create_meteora_token_swap_ix(
+                    lb_pair,
+                    token_amount,
+                    slippage_bps,
+                    Pubkey::from_str(token_address.as_str())?,
+                    &rpc_client,
+                    &owner,
+                )
+                .await?;
+                swap_ixs(
+                    &rpc_client,
+                    &owner,
+                    compute_budget.as_ref(),
+                    Venue::Meteora,
+                    false,
+                    ixs,
+                )
+                .await
             },
             tip_lamports,
         )
         .await
     }
 
-    pub async fn buy_raydium(
+    pub async fn buy_jupiter(
         &self,
+        config: &JupiterSwapConfig,
         token_address: &str,
-        raydium_pool: &str,
         sol_amount: f64,
         slippage_bps: u16,
         tip_lamports: u64,
     ) -> Result<String> {
         info!(
-            "Raydium: try buying {} SOL worth of token {}",
-            sol_amount, token_address
+            "Jupiter: try buying {} worth of token {}",
+            format::sol(sol_amount),
+            token_address
         );
-        let raydium_pool = raydium_pool.to_string();
+        let api_url = config.api_url.clone();
         let token_address = token_address.to_string();
 
+        let compute_budget = self.compute_budget.clone();
         execute_solana_transaction_with_tip(
             move |owner| async move {
-                create_raydium_sol_swap_ix(
-                    raydium_pool,
+                let rpc_client = make_rpc_client();
+                let ixs = create_jupiter_swap_ix(
+                    &api_url,
+                    &token_address,
                     sol_to_lamports(sol_amount),
+                    Side::Buy,
                     slippage_bps,
-                    Pubkey::from_str(token_address.as_str())?,
-                    &make_rpc_client(),
                     &owner,
                 )
+                .await?;
+                swap_ixs(
+                    &rpc_client,
+                    &owner,
+                    compute_budget.as_ref(),
+                    Venue::Jupiter,
+                    true,
+                    ixs,
+                )
                 .await
             },
             tip_lamports,
@@ -280,28 +2039,43 @@ impl MemeTrader {
         .await
     }
 
-    pub async fn sell_raydium(
+    /// Without `slippage_bps` the swap accepts any output, like on the
+    /// pools
+    pub async fn sell_jupiter(
         &self,
+        config: &JupiterSwapConfig,
         token_address: &str,
-        raydium_pool: &str,
         token_amount: u64,
+        slippage_bps: Option<u16>,
         tip_lamports: u64,
     ) -> Result<String> {
         info!(
-            "Raydium: try selling {} tokens of {} on Raydium pool {}",
-            token_amount, token_address, raydium_pool
+            "Jupiter: try selling {} tokens of {}",
+            token_amount, token_address
         );
-        let raydium_pool = raydium_pool.to_string();
+        let api_url = config.api_url.clone();
         let token_address = token_address.to_string();
 
+        let compute_budget = self.compute_budget.clone();
         execute_solana_transaction_with_tip(
             move |owner| async move {
-                create_raydium_token_swap_ix(
-                    raydium_pool,
-                    token_amount as u64,
-                    Pubkey::from_str(token_address.as_str())?, // Token
-                    &make_rpc_client(),
+                let rpc_client = make_rpc_client();
+                let ixs = create_jupiter_swap_ix(
+                    &api_url,
+                    &token_address,
+                    token_amount,
+                    Side::Sell,
+                    slippage_bps.unwrap_or(10_000),
+                    &owner,
+                )
+                .await?;
+                swap_ixs(
+                    &rpc_client,
                     &owner,
+                    compute_budget.as_ref(),
+                    Venue::Jupiter,
+                    false,
+                    ixs,
                 )
                 .await
             },
@@ -310,136 +2084,335 @@ impl MemeTrader {
         .await
     }
 
-    /// Internal buy implementation that handles the actual trading logic
-    async fn buy_impl(
+    fn jupiter_config(&self) -> Result<&JupiterSwapConfig> {
+        self.jupiter
+            .as_ref()
+            .ok_or_else(|| anyhow!("Jupiter swaps are off, set JUPITER_FALLBACK=true"))
+    }
+
+    /// Venue of `token_address` quoting the most for `amount_in`, among
+    /// `pools`, the pairs DexScreener lists and Jupiter when it swaps.
+    /// `pairs` are looked up when not given.
+    async fn best_pool(
+        &self,
+        config: &VenueSelectionConfig,
+        token_address: &str,
+        pools: &[Pubkey],
+        pairs: Option<&[PairInfo]>,
+        amount_in: u64,
+        buy: bool,
+    ) -> Result<Route> {
+        let looked_up;
+        let pairs = match pairs {
+            Some(pairs) => pairs,
+            None => {
+                looked_up = cached_search_ticker(token_address)
+                    .await
+                    .map(|dex_info| dex_info.pairs)
+                    .unwrap_or_default();
+                &looked_up
+            }
+        };
+        select_pool(
+            &make_rpc_client(),
+            token_address,
+            pools,
+            pairs,
+            amount_in,
+            buy,
+            config.jupiter_quote_url.as_deref(),
+            self.jupiter.is_some(),
+        )
+        .await
+    }
+
+    /// Buy along `route`
+    async fn buy_on_route(
         &self,
         token_address: &str,
+        route: Route,
         sol_amount: f64,
         slippage_bps: u16,
         tip_lamports: u64,
-    ) -> Result<String> {
-        let token_info = self.get_token_info(token_address).await;
-        tracing::info!("buy_impl/Token info: {:?}", token_info);
-
-        match token_info {
-            Ok(TokenInfo::Pump(pump_info)) => {
-                match pump_info.complete {
-                    true => tracing::info!(
-                        "Pump.fun: complete, buying from Raydium; pool {}",
-                        pump_info.raydium_pool
-                    ),
-                    false => tracing::info!(
-                        "Pump.fun: incomplete, bonding curve {}",
-                        pump_info.bonding_curve
-                    ),
-                }
-
-                if !pump_info.complete {
-                    self.buy_pump_fun(token_address, sol_amount, slippage_bps, tip_lamports)
-                        .await
-                } else {
-                    self.buy_raydium(
+    ) -> Result<(String, Venue)> {
+        let (pool, program) = match route {
+            Route::Pool(pool, program) => (pool.to_string(), program),
+            Route::Jupiter => {
+                return swap_on(
+                    Venue::Jupiter,
+                    self.buy_jupiter(
+                        self.jupiter_config()?,
                         token_address,
-                        pump_info.raydium_pool.as_str(),
                         sol_amount,
                         slippage_bps,
                         tip_lamports,
-                    )
-                    .await
-                }
+                    ),
+                )
+                .await
             }
-
-            Ok(TokenInfo::Dexscreener(dex_info)) => {
-                let pairs = dex_info
-                    .pairs
-                    .into_iter()
-                    .find(|pair| pair.dex_id == "raydium") // we currently support only Raydium besids Pump.fun
-                    .ok_or_else(|| anyhow!("No Raydium trading pair found"))?;
-                tracing::info!(
-                    "According to Dexscreener token is on Raydium, pool {}",
-                    &pairs.pair_address
-                );
-                // For now, we'll just return an error since Dexscreener selling is not implemented
-                self.buy_raydium(
-                    token_address,
-                    &pairs.pair_address,
-                    sol_amount,
-                    slippage_bps,
-                    tip_lamports,
+        };
+        match program {
+            PoolProgram::OrcaWhirlpool => {
+                swap_on(
+                    Venue::Orca,
+                    self.buy_orca(token_address, &pool, sol_amount, slippage_bps, tip_lamports),
+                )
+                .await
+            }
+            PoolProgram::MeteoraDlmm => {
+                swap_on(
+                    Venue::Meteora,
+                    self.buy_meteora(token_address, &pool, sol_amount, slippage_bps, tip_lamports),
                 )
                 .await
             }
             _ => {
-                tracing::info!(
-                    "Token info not found on Pump.fun or Dexscreener. Fallback to Pump.fun"
-                );
-                self.buy_pump_fun(token_address, sol_amount, slippage_bps, tip_lamports)
-                    .await
+                swap_on(
+                    Venue::Raydium,
+                    self.buy_raydium(token_address, &pool, sol_amount, slippage_bps, tip_lamports),
+                )
+                .await
             }
         }
     }
 
-    /// Internal sell implementation that handles the actual trading logic
-    async fn sell_impl(
+    /// Sell along `route`
+    async fn sell_on_route(
         &self,
         token_address: &str,
+        route: Route,
         token_amount: u64,
+        slippage_bps: Option<u16>,
         tip_lamports: u64,
-    ) -> Result<String> {
+    ) -> Result<(String, Venue)> {
+        let (pool, program) = match route {
+            Route::Pool(pool, program) => (pool.to_string(), program),
+            Route::Jupiter => {
+                return swap_on(
+                    Venue::Jupiter,
+                    self.sell_jupiter(
+                        self.jupiter_config()?,
+                        token_address,
+                        token_amount,
+                        slippage_bps,
+                        tip_lamports,
+                    ),
+                )
+                .await
+            }
+        };
+        match program {
+            PoolProgram::OrcaWhirlpool => {
+                swap_on(
+                    Venue::Orca,
+                    self.sell_orca(
+                        token_address,
+                        &pool,
+                        token_amount,
+                        slippage_bps,
+                        tip_lamports,
+                    ),
+                )
+                .await
+            }
+            PoolProgram::MeteoraDlmm => {
+                swap_on(
+                    Venue::Meteora,
+                    self.sell_meteora(
+                        token_address,
+                        &pool,
+                        token_amount,
+                        slippage_bps,
+                        tip_lamports,
+                    ),
+                )
+                .await
+            }
+            _ => {
+                swap_on(
+                    Venue::Raydium,
+                    self.sell_raydium(
+                        token_address,
+                        &pool,
+                        token_amount,
+                        slippage_bps,
+                        tip_lamports,
+                    ),
+                )
+                .await
+            }
+        }
+    }
+
+    /// Where a swap of `amount_in` of `token_address` goes. With venue
+    /// selection graduated tokens go to the pool quoting the most, otherwise
+    /// to their Raydium pool or the most liquid one. Tokens without a
+    /// supported pool go through Jupiter when it's set, not those whose pools
+    /// failed to be looked up.
+    async fn target(&self, token_address: &str, side: Side, amount_in: u64) -> Result<Target> {
         let token_info = self.get_token_info(token_address).await;
+        tracing::info!("Token info of {}: {:?}", token_address, token_info);
 
         match token_info {
             Ok(TokenInfo::Pump(pump_info)) => {
                 match pump_info.complete {
                     true => tracing::info!(
-                        "Pump.fun: complete, selling on Raydium; pool {}",
+                        "Pump.fun: complete, {} on Raydium; pool {}",
+                        side,
                         pump_info.raydium_pool
                     ),
                     false => tracing::info!(
-                        "Pump.fun: incomplete, selling on bonding curve {}",
+                        "Pump.fun: incomplete, {} on bonding curve {}",
+                        side,
                         pump_info.bonding_curve
                     ),
                 }
 
                 if !pump_info.complete {
-                    self.sell_pump_fun(token_address, token_amount, tip_lamports)
+                    return Ok(Target::BondingCurve);
+                }
+                if let Some(config) = &self.venue_selection {
+                    let pools: Vec<Pubkey> = Pubkey::from_str(&pump_info.raydium_pool)
+                        .into_iter()
+                        .collect();
+                    match self
+                        .best_pool(
+                            config,
+                            token_address,
+                            &pools,
+                            None,
+                            amount_in,
+                            side == Side::Buy,
+                        )
                         .await
-                } else {
-                    self.sell_raydium(
-                        token_address,
-                        pump_info.raydium_pool.as_str(),
-                        token_amount,
-                        tip_lamports,
-                    )
-                    .await
+                    {
+                        Ok(route) => return Ok(Target::Route(route)),
+                        Err(e) => tracing::warn!("Venue selection failed: {:?}", e),
+                    }
                 }
+                Ok(Target::Raydium(pump_info.raydium_pool))
             }
             Ok(TokenInfo::Dexscreener(dex_info)) => {
-                let pairs = dex_info
-                    .pairs
-                    .into_iter()
-                    .find(|pair| pair.dex_id == "raydium") // we currently support only Raydium besids Pump.fun
-                    .ok_or_else(|| anyhow!("No Raydium trading pair found"))?;
+                let route = self
+                    .pool_of_pairs(token_address, &dex_info.pairs, amount_in, side == Side::Buy)
+                    .await;
+                match (route, &self.jupiter) {
+                    (Ok(route), _) => Ok(Target::Route(route)),
+                    (Err(e), Some(_)) if e.downcast_ref::<NoSupportedPool>().is_some() => {
+                        tracing::info!("{}, the {} goes through Jupiter", e, side);
+                        Ok(Target::Route(Route::Jupiter))
+                    }
+                    (Err(e), _) => Err(e),
+                }
+            }
+            _ => {
                 tracing::info!(
-                    "According to Dexscreener token is on Raydium, pool {}",
-                    &pairs.pair_address
+                    "Token info not found on Pump.fun or Dexscreener. Fallback to Pump.fun"
                 );
-                // For now, we'll just return an error since Dexscreener selling is not implemented
-                self.sell_raydium(
+                Ok(Target::BondingCurve)
+            }
+        }
+    }
+
+    /// Route among DexScreener's `pairs` to swap `amount_in` along: the
+    /// best quote with venue selection, the most liquid supported pool
+    /// without it or when no venue could be quoted
+    async fn pool_of_pairs(
+        &self,
+        token_address: &str,
+        pairs: &[PairInfo],
+        amount_in: u64,
+        buy: bool,
+    ) -> Result<Route> {
+        if let Some(config) = &self.venue_selection {
+            match self
+                .best_pool(config, token_address, &[], Some(pairs), amount_in, buy)
+                .await
+            {
+                Ok(route) => return Ok(route),
+                Err(e) => tracing::warn!("Venue selection failed: {:?}", e),
+            }
+        }
+        let (pool, program) = find_supported_pool(&make_rpc_client(), token_address, pairs).await?;
+        tracing::info!(
+            "According to Dexscreener token is on {}, pool {}",
+            program,
+            pool
+        );
+        Ok(Route::Pool(pool, program))
+    }
+
+    /// Buy on `target`
+    async fn buy_on(
+        &self,
+        token_address: &str,
+        target: Target,
+        sol_amount: f64,
+        slippage_bps: u16,
+        tip_lamports: u64,
+    ) -> Result<(String, Venue)> {
+        match target {
+            Target::BondingCurve => {
+                swap_on(
+                    Venue::PumpFun,
+                    self.buy_pump_fun(token_address, sol_amount, slippage_bps, tip_lamports),
+                )
+                .await
+            }
+            Target::Raydium(pool) => {
+                swap_on(
+                    Venue::Raydium,
+                    self.buy_raydium(token_address, &pool, sol_amount, slippage_bps, tip_lamports),
+                )
+                .await
+            }
+            Target::Route(route) => {
+                self.buy_on_route(token_address, route, sol_amount, slippage_bps, tip_lamports)
+                    .await
+            }
+        }
+    }
+
+    /// Sell on `target`. Pump.fun bonding curve sells ignore `slippage_bps`.
+    async fn sell_on(
+        &self,
+        token_address: &str,
+        target: Target,
+        token_amount: u64,
+        slippage_bps: Option<u16>,
+        tip_lamports: u64,
+    ) -> Result<(String, Venue)> {
+        match target {
+            Target::BondingCurve => {
+                swap_on(
+                    Venue::PumpFun,
+                    self.sell_pump_fun(token_address, token_amount, tip_lamports),
+                )
+                .await
+            }
+            Target::Raydium(pool) => {
+                swap_on(
+                    Venue::Raydium,
+                    self.sell_raydium(
+                        token_address,
+                        &pool,
+                        token_amount,
+                        slippage_bps,
+                        tip_lamports,
+                    ),
+                )
+                .await
+            }
+            Target::Route(route) => {
+                self.sell_on_route(
                     token_address,
-                    &pairs.pair_address,
+                    route,
                     token_amount,
+                    slippage_bps,
                     tip_lamports,
                 )
                 .await
             }
-            _ => {
-                tracing::info!(
-                    "Token info not found on Pump.fun or Dexscreener. Fallback to Pump.fun"
-                );
-                self.sell_pump_fun(token_address, token_amount, tip_lamports)
-                    .await
-            }
         }
     }
 }
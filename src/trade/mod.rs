@@ -1 +1,22 @@
+pub mod bot_state;
+pub mod candles;
+pub mod capital;
+pub mod costs;
+pub mod execution;
+pub mod exposure;
+pub mod gate;
+pub mod intent;
+pub mod latency;
+pub mod liquidity_monitor;
+pub mod maintenance;
 pub mod meme_trader;
+pub mod report;
+pub mod risk;
+pub mod sell_retry;
+pub mod sizing;
+pub mod slippage;
+pub mod sweep;
+pub mod tasks;
+pub mod token_filter;
+pub mod trailing_stop;
+pub mod usd_backfill;
@@ -0,0 +1,237 @@
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::tg_copy::db::TradeType;
+use crate::trade::execution::Execution;
+
+/// Buckets the closed trades are summed into
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Period {
+    Day,
+    Week,
+    /// Everything in one row per strategy
+    All,
+}
+
+impl Period {
+    fn bucket(&self, at: DateTime<Utc>) -> String {
+        match self {
+            Period::Day => at.format("%Y-%m-%d").to_string(),
+            Period::Week => at.format("%G-W%V").to_string(),
+            Period::All => "all".to_string(),
+        }
+    }
+}
+
+/// A position from its first buy until sells took back every token bought,
+/// rebuilt from the executions of one strategy and mint
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClosedTrade {
+    pub strategy: String,
+    pub token: String,
+    pub opened_at: DateTime<Utc>,
+    pub closed_at: DateTime<Utc>,
+    pub sol_spent_lamports: u64,
+    pub sol_received_lamports: u64,
+    /// Transaction fees and tips of its buys and sells, part of the SOL
+    /// spent and received already
+    pub fees_lamports: u64,
+}
+
+impl ClosedTrade {
+    pub fn pnl_lamports(&self) -> i64 {
+        self.sol_received_lamports as i64 - self.sol_spent_lamports as i64
+    }
+}
+
+#[derive(Default)]
+struct OpenPosition {
+    token: String,
+    opened_at: Option<DateTime<Utc>>,
+    bought: u64,
+    sold: u64,
+    sol_spent_lamports: u64,
+    sol_received_lamports: u64,
+    fees_lamports: u64,
+}
+
+/// Closed trades of the executed buys and sells, failed attempts are
+/// skipped. Sells without a buy before them, of untracked balances, and
+/// positions still open are left out.
+pub fn closed_trades(executions: &[Execution]) -> Vec<ClosedTrade> {
+    let mut executions: Vec<&Execution> = executions
+        .iter()
+        .filter(|e| e.error.is_none() && e.tx_sig.is_some())
+        .collect();
+    executions.sort_by_key(|e| e.date);
+
+    let mut open: HashMap<(&str, &str), OpenPosition> = HashMap::new();
+    let mut closed = Vec::new();
+    for execution in executions {
+        let key = (
+            execution.strategy.as_str(),
+            execution.contract_address.as_str(),
+        );
+        let tokens = execution.token_amount.unwrap_or(0);
+        let sol = execution.sol_lamports.unwrap_or(0);
+        let fees = execution.fee_lamports.unwrap_or(0) + execution.tip_lamports.unwrap_or(0);
+        match execution.trade_type {
+            TradeType::Open => {
+                let position = open.entry(key).or_default();
+                position.token = execution.token.clone();
+                position.opened_at.get_or_insert(execution.date);
+                position.bought += tokens;
+                position.sol_spent_lamports += sol;
+                position.fees_lamports += fees;
+            }
+            TradeType::Close => {
+                let Some(position) = open.get_mut(&key) else {
+                    continue;
+                };
+                position.sold += tokens;
+                position.sol_received_lamports += sol;
+                position.fees_lamports += fees;
+                if position.sold < position.bought {
+                    continue;
+                }
+                let position = open.remove(&key).unwrap();
+                closed.push(ClosedTrade {
+                    strategy: execution.strategy.clone(),
+                    token: position.token,
+                    opened_at: position.opened_at.unwrap_or(execution.date),
+                    closed_at: execution.date,
+                    sol_spent_lamports: position.sol_spent_lamports,
+                    sol_received_lamports: position.sol_received_lamports,
+                    fees_lamports: position.fees_lamports,
+                });
+            }
+        }
+    }
+    closed
+}
+
+/// Realized PnL of one strategy in one period
+#[derive(Debug, Clone, PartialEq)]
+pub struct StrategyReport {
+    /// Day, week or `all`, of when the trades closed
+    pub period: String,
+    pub strategy: String,
+    pub trades: usize,
+    pub winners: usize,
+    pub pnl_lamports: i64,
+    pub fees_lamports: u64,
+    pub avg_hold_secs: i64,
+    /// Token and PnL of the best and worst trade
+    pub best: (String, i64),
+    pub worst: (String, i64),
+}
+
+impl StrategyReport {
+    pub fn hit_rate_pct(&self) -> f64 {
+        self.winners as f64 * 100.0 / self.trades.max(1) as f64
+    }
+}
+
+/// Closed trades summed per period and strategy, oldest period first
+pub fn aggregate(trades: &[ClosedTrade], period: Period) -> Vec<StrategyReport> {
+    let mut groups: BTreeMap<(String, &str), Vec<&ClosedTrade>> = BTreeMap::new();
+    for trade in trades {
+        groups
+            .entry((period.bucket(trade.closed_at), trade.strategy.as_str()))
+            .or_default()
+            .push(trade);
+    }
+    groups
+        .into_iter()
+        .map(|((period, strategy), trades)| {
+            let best = trades.iter().max_by_key(|t| t.pnl_lamports()).unwrap();
+            let worst = trades.iter().min_by_key(|t| t.pnl_lamports()).unwrap();
+            StrategyReport {
+                period,
+                strategy: strategy.to_string(),
+                trades: trades.len(),
+                winners: trades.iter().filter(|t| t.pnl_lamports() > 0).count(),
+                pnl_lamports: trades.iter().map(|t| t.pnl_lamports()).sum(),
+                fees_lamports: trades.iter().map(|t| t.fees_lamports).sum(),
+                avg_hold_secs: trades
+                    .iter()
+                    .map(|t| (t.closed_at - t.opened_at).num_seconds())
+                    .sum::<i64>()
+                    / trades.len() as i64,
+                best: (best.token.clone(), best.pnl_lamports()),
+                worst: (worst.token.clone(), worst.pnl_lamports()),
+            }
+        })
+        .collect()
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// The report as CSV, amounts in lamports
+pub fn to_csv(reports: &[StrategyReport]) -> String {
+    let mut csv = String::from(
+        "period,strategy,trades,hit_rate_pct,pnl_lamports,fees_lamports,avg_hold_secs,\
+         best_token,best_pnl_lamports,worst_token,worst_pnl_lamports\n",
+    );
+    for report in reports {
+        csv.push_str(&format!(
+            "{},{},{},{:.1},{},{},{},{},{},{},{}\n",
+            report.period,
+            csv_field(&report.strategy),
+            report.trades,
+            report.hit_rate_pct(),
+            report.pnl_lamports,
+            report.fees_lamports,
+            report.avg_hold_secs,
+            csv_field(&report.best.0),
+            report.best.1,
+            csv_field(&report.worst.0),
+            report.worst.1
+        ));
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn execution(trade_type: TradeType, hour: u32, tokens: u64, sol: u64) -> Execution {
+        Execution {
+            date: Utc.with_ymd_and_hms(2025, 3, 1, hour, 0, 0).unwrap(),
+            contract_address: "mint".to_string(),
+            tx_sig: Some("sig".to_string()),
+            token_amount: Some(tokens),
+            sol_lamports: Some(sol),
+            fee_lamports: Some(5_000),
+            ..Execution::for_test(trade_type, "PEPE")
+        }
+    }
+
+    #[test]
+    fn test_closed_trades() {
+        let executions = vec![
+            execution(TradeType::Open, 1, 1_000, 100_000_000),
+            execution(TradeType::Close, 2, 500, 80_000_000),
+            execution(TradeType::Close, 3, 500, 70_000_000),
+            // Still open
+            execution(TradeType::Open, 4, 1_000, 100_000_000),
+        ];
+        let trades = closed_trades(&executions);
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].pnl_lamports(), 50_000_000);
+        assert_eq!(trades[0].fees_lamports, 15_000);
+
+        let reports = aggregate(&trades, Period::Day);
+        assert_eq!(reports[0].period, "2025-03-01");
+        assert_eq!(reports[0].avg_hold_secs, 2 * 3600);
+        assert_eq!(reports[0].hit_rate_pct(), 100.0);
+    }
+}
@@ -0,0 +1,388 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use mongodb::{bson::doc, options::ReplaceOptions, Collection};
+use serde::{Deserialize, Serialize};
+use solana_sdk::native_token::{lamports_to_sol, sol_to_lamports};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time;
+
+use crate::config::{BalanceGuardConfig, LossStreakConfig, RiskConfig};
+use crate::format;
+use crate::notify::TradeEvent;
+use crate::trade::meme_trader::MemeTrader;
+
+const GATE_SOURCE: &str = "daily_loss";
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Realized PnL of all trades closed since 00:00 UTC today, in lamports
+pub async fn realized_pnl_today(trader: &MemeTrader) -> Result<i64> {
+    let day_start = Utc::now()
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .expect("midnight is valid")
+        .and_utc()
+        .timestamp();
+    Ok(trader
+        .active_trades()
+        .load_closed_since(day_start)
+        .await?
+        .iter()
+        .map(|trade| trade.realized_pnl_lamports())
+        .sum())
+}
+
+/// Circuit breaker that stops new buys once today's realized losses reach
+/// `MAX_DAILY_LOSS_SOL` and lets them through again after the UTC day rolls
+/// over.
+pub async fn run_daily_loss_breaker(
+    trader: Arc<MemeTrader>,
+    cfg: RiskConfig,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    let max_loss_lamports = sol_to_lamports(cfg.max_daily_loss_sol) as i64;
+    let mut interval = time::interval(CHECK_INTERVAL);
+    tracing::info!("Daily loss breaker started");
+
+    loop {
+        tokio::select! {
+            biased;
+            _ = shutdown.changed() => {
+                tracing::info!("Daily loss breaker stopped");
+                return Ok(());
+            }
+            _ = interval.tick() => {}
+        }
+
+        let pnl = match realized_pnl_today(&trader).await {
+            Ok(pnl) => pnl,
+            Err(e) => {
+                tracing::error!("Daily loss breaker failed to load closed trades: {:?}", e);
+                continue;
+            }
+        };
+
+        if -pnl >= max_loss_lamports {
+            let tomorrow = (Utc::now() + ChronoDuration::days(1)).date_naive();
+            let reason = format!(
+                "daily loss limit hit, realized {} today (limit {}), resuming {} 00:00 UTC",
+                format::lamports(pnl.unsigned_abs()),
+                format::sol(cfg.max_daily_loss_sol),
+                tomorrow
+            );
+            if trader.buy_gate().pause(GATE_SOURCE, reason.clone()) {
+                tracing::warn!("Pausing buys: {}", reason);
+                trader.notify(TradeEvent::BuysPaused {
+                    reason,
+                    breaker: Some(GATE_SOURCE.to_string()),
+                });
+            }
+        } else if trader.buy_gate().resume(GATE_SOURCE) {
+            tracing::info!("Daily loss reset, resuming buys");
+            trader.notify(TradeEvent::BuysResumed {
+                reason: "new UTC day, daily loss limit reset".to_string(),
+            });
+        }
+    }
+}
+
+/// What happens to a strategy after `LOSS_STREAK_MAX` consecutive losers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StreakAction {
+    /// Halve its position size until a winner, again on every further streak
+    Halve,
+    /// Skip its buys for the cooldown
+    Pause,
+}
+
+impl FromStr for StreakAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "halve" => Ok(StreakAction::Halve),
+            "pause" => Ok(StreakAction::Pause),
+            _ => Err(anyhow!("Expected halve or pause, got '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for StreakAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreakAction::Halve => write!(f, "halve"),
+            StreakAction::Pause => write!(f, "pause"),
+        }
+    }
+}
+
+/// Losing streak of one strategy, persisted so a restart keeps reduced sizes
+/// and cooldowns
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StrategyStreak {
+    #[serde(rename = "_id")]
+    pub strategy: String,
+    pub consecutive_losses: u32,
+    /// Multiplier applied to the strategy's position size
+    pub size_factor: f64,
+    /// Unix seconds until which buys of the strategy are skipped
+    pub paused_until: Option<i64>,
+}
+
+impl StrategyStreak {
+    pub fn new(strategy: &str) -> Self {
+        Self {
+            strategy: strategy.to_string(),
+            consecutive_losses: 0,
+            size_factor: 1.0,
+            paused_until: None,
+        }
+    }
+
+    /// Count a closed trade. A winner resets the streak and the size, the
+    /// `max_losses`th loser in a row applies the action and starts counting
+    /// anew. Returns whether the action was applied.
+    pub fn record(&mut self, pnl_lamports: i64, cfg: &LossStreakConfig, now: i64) -> bool {
+        if pnl_lamports >= 0 {
+            *self = Self::new(&self.strategy);
+            return false;
+        }
+        self.consecutive_losses += 1;
+        if self.consecutive_losses < cfg.max_losses {
+            return false;
+        }
+        self.consecutive_losses = 0;
+        match cfg.action {
+            StreakAction::Halve => self.size_factor /= 2.0,
+            StreakAction::Pause => self.paused_until = Some(now + cfg.cooldown_secs),
+        }
+        true
+    }
+
+    pub fn is_paused(&self, now: i64) -> bool {
+        self.paused_until.is_some_and(|until| now < until)
+    }
+}
+
+/// Position size of a strategy after its losing streak
+pub enum StreakSizing {
+    Scale(f64),
+    Paused { until: i64 },
+}
+
+/// Tracks losing streaks per strategy in the `strategy_streaks` collection
+#[derive(Clone)]
+pub struct LossStreakGuard {
+    collection: Collection<StrategyStreak>,
+    cfg: LossStreakConfig,
+}
+
+impl LossStreakGuard {
+    pub fn new(collection: Collection<StrategyStreak>, cfg: LossStreakConfig) -> Self {
+        Self { collection, cfg }
+    }
+
+    pub fn action(&self) -> StreakAction {
+        self.cfg.action
+    }
+
+    async fn load(&self, strategy: &str) -> Result<StrategyStreak> {
+        Ok(self
+            .collection
+            .find_one(doc! { "_id": strategy }, None)
+            .await?
+            .unwrap_or_else(|| StrategyStreak::new(strategy)))
+    }
+
+    /// How a buy of `strategy` is sized right now
+    pub async fn sizing(&self, strategy: &str) -> Result<StreakSizing> {
+        let streak = self.load(strategy).await?;
+        let now = Utc::now().timestamp();
+        Ok(match streak.paused_until {
+            Some(until) if streak.is_paused(now) => StreakSizing::Paused { until },
+            _ => StreakSizing::Scale(streak.size_factor),
+        })
+    }
+
+    /// Count a fully closed trade of `strategy`. Returns a description of the
+    /// action when the loss completed a streak.
+    pub async fn record_close(&self, strategy: &str, pnl_lamports: i64) -> Result<Option<String>> {
+        let mut streak = self.load(strategy).await?;
+        let triggered = streak.record(pnl_lamports, &self.cfg, Utc::now().timestamp());
+        self.collection
+            .replace_one(
+                doc! { "_id": strategy },
+                &streak,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+
+        if !triggered {
+            return Ok(None);
+        }
+        Ok(Some(match streak.paused_until {
+            Some(until) if self.cfg.action == StreakAction::Pause => format!(
+                "{} losers in a row on {}, buys paused until {} UTC",
+                self.cfg.max_losses,
+                strategy,
+                DateTime::from_timestamp(until, 0)
+                    .map_or(until.to_string(), |t| t.format("%H:%M").to_string())
+            ),
+            _ => format!(
+                "{} losers in a row on {}, position size cut to {}%",
+                self.cfg.max_losses,
+                strategy,
+                streak.size_factor * 100.0
+            ),
+        }))
+    }
+}
+
+/// Checks the wallet of the signer before each buy. Buys that would eat
+/// into `BALANCE_RESERVE_SOL` are refused, and with `POSITION_SIZE_PCT` the
+/// size is scaled so that `POSITION_SIZE_SOL` becomes that percentage of
+/// the free balance, other sizes keeping their ratio to it.
+#[derive(Debug, Clone)]
+pub struct BalanceGuard {
+    cfg: BalanceGuardConfig,
+    position_size_sol: f64,
+}
+
+impl BalanceGuard {
+    pub fn new(cfg: BalanceGuardConfig, position_size_sol: f64) -> Self {
+        Self {
+            cfg,
+            position_size_sol,
+        }
+    }
+
+    /// Lamports buys may spend out of `balance_lamports`, after locked
+    /// capital and the reserve
+    pub fn free_lamports(&self, balance_lamports: u64, locked_lamports: u64) -> u64 {
+        balance_lamports
+            .saturating_sub(locked_lamports)
+            .saturating_sub(sol_to_lamports(self.cfg.reserve_sol))
+    }
+
+    /// SOL to spend on a buy requested at `sol_amount`, given the wallet
+    pub fn size_buy(
+        &self,
+        sol_amount: f64,
+        tip_lamports: u64,
+        balance_lamports: u64,
+        locked_lamports: u64,
+    ) -> Result<f64> {
+        let free = self.free_lamports(balance_lamports, locked_lamports);
+        let sol_amount = match self.cfg.size_pct {
+            Some(pct) if self.position_size_sol > 0.0 => {
+                sol_amount / self.position_size_sol * lamports_to_sol(free) * pct / 100.0
+            }
+            _ => sol_amount,
+        };
+        if free == 0 || sol_to_lamports(sol_amount) + tip_lamports > free {
+            return Err(anyhow!(
+                "Buy of {} with a tip of {} would leave less than the reserve of {}, {} free",
+                format::sol(sol_amount),
+                format::lamports(tip_lamports),
+                format::sol(self.cfg.reserve_sol),
+                format::lamports(free)
+            ));
+        }
+        Ok(sol_amount)
+    }
+
+    /// [`size_buy`](Self::size_buy), logging a scaled size
+    pub fn check_buy(
+        &self,
+        sol_amount: f64,
+        tip_lamports: u64,
+        balance: u64,
+        locked_lamports: u64,
+    ) -> Result<f64> {
+        let size = self.size_buy(sol_amount, tip_lamports, balance, locked_lamports)?;
+        if size != sol_amount {
+            tracing::info!(
+                "Buy of {} scaled to {} of a balance of {}",
+                format::sol(sol_amount),
+                format::sol(size),
+                format::lamports(balance)
+            );
+        }
+        Ok(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg(action: StreakAction) -> LossStreakConfig {
+        LossStreakConfig {
+            max_losses: 2,
+            action,
+            cooldown_secs: 600,
+        }
+    }
+
+    #[test]
+    fn test_halve_until_a_winner() {
+        let cfg = cfg(StreakAction::Halve);
+        let mut streak = StrategyStreak::new("degen");
+        assert!(!streak.record(-1, &cfg, 0));
+        assert!(streak.record(-1, &cfg, 0));
+        assert_eq!(streak.size_factor, 0.5);
+        assert!(!streak.record(-1, &cfg, 0));
+        assert!(streak.record(-1, &cfg, 0));
+        assert_eq!(streak.size_factor, 0.25);
+        assert!(!streak.record(1, &cfg, 0));
+        assert_eq!(streak, StrategyStreak::new("degen"));
+    }
+
+    #[test]
+    fn test_pause_for_cooldown() {
+        let cfg = cfg(StreakAction::Pause);
+        let mut streak = StrategyStreak::new("degen");
+        streak.record(-1, &cfg, 1_000);
+        assert!(streak.record(-1, &cfg, 1_000));
+        assert!(streak.is_paused(1_599));
+        assert!(!streak.is_paused(1_600));
+        assert_eq!(streak.size_factor, 1.0);
+    }
+
+    #[test]
+    fn test_balance_guard_keeps_the_reserve() {
+        let guard = BalanceGuard::new(
+            BalanceGuardConfig {
+                reserve_sol: 0.05,
+                size_pct: None,
+            },
+            0.1,
+        );
+        assert_eq!(guard.free_lamports(1_000_000_000, 200_000_000), 750_000_000);
+        assert_eq!(guard.free_lamports(60_000_000, 20_000_000), 0);
+        assert_eq!(guard.size_buy(0.1, 1_000_000, 200_000_000, 0).unwrap(), 0.1);
+        // 0.1 SOL and the tip fit, but not with 0.05 SOL left over
+        assert!(guard.size_buy(0.1, 1_000_000, 150_000_000, 0).is_err());
+        assert!(guard.size_buy(0.1, 0, 200_000_000, 100_000_000).is_err());
+    }
+
+    #[test]
+    fn test_balance_guard_scales_to_free_balance() {
+        let guard = BalanceGuard::new(
+            BalanceGuardConfig {
+                reserve_sol: 0.1,
+                size_pct: Some(10.0),
+            },
+            0.2,
+        );
+        // 10% of the 2 SOL free for POSITION_SIZE_SOL
+        assert!((guard.size_buy(0.2, 0, 2_100_000_000, 0).unwrap() - 0.2).abs() < 1e-9);
+        // A band or streak size of half of it stays half
+        assert!((guard.size_buy(0.1, 0, 2_100_000_000, 0).unwrap() - 0.1).abs() < 1e-9);
+        assert!((guard.size_buy(0.2, 0, 1_100_000_000, 0).unwrap() - 0.1).abs() < 1e-9);
+        assert!(guard.size_buy(0.2, 0, 100_000_000, 0).is_err());
+    }
+}
@@ -0,0 +1,50 @@
+use crate::config::SellRetryConfig;
+
+/// Slippage of each sell attempt, `None` is the final attempt without a
+/// minimum output, only with `force_exit`. Slippage doubles per attempt up
+/// to the ceiling.
+pub fn slippage_schedule(cfg: &SellRetryConfig) -> Vec<Option<u16>> {
+    let mut schedule: Vec<Option<u16>> = (0..cfg.attempts)
+        .map(|attempt| {
+            let bps = (cfg.slippage_bps as u64) << attempt.min(16);
+            Some(bps.min(cfg.max_slippage_bps as u64) as u16)
+        })
+        .collect();
+    schedule.dedup();
+    if schedule.is_empty() {
+        schedule.push(Some(cfg.slippage_bps.min(cfg.max_slippage_bps)));
+    }
+    if cfg.force_exit {
+        schedule.push(None);
+    }
+    schedule
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg(attempts: u32, force_exit: bool) -> SellRetryConfig {
+        SellRetryConfig {
+            slippage_bps: 500,
+            max_slippage_bps: 1_500,
+            attempts,
+            force_exit,
+        }
+    }
+
+    #[test]
+    fn test_escalates_to_ceiling_then_forces_exit() {
+        assert_eq!(
+            slippage_schedule(&cfg(4, true)),
+            vec![Some(500), Some(1_000), Some(1_500), None]
+        );
+        assert_eq!(
+            slippage_schedule(&cfg(2, false)),
+            vec![Some(500), Some(1_000)]
+        );
+        // No attempts still sells once, with a minimum output unless forced
+        assert_eq!(slippage_schedule(&cfg(0, false)), vec![Some(500)]);
+        assert_eq!(slippage_schedule(&cfg(0, true)), vec![Some(500), None]);
+    }
+}
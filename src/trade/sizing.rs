@@ -0,0 +1,442 @@
+use anyhow::{anyhow, Result};
+use listen_kit::signer::SignerContext;
+use serde::{Deserialize, Serialize};
+use solana_sdk::{native_token::lamports_to_sol, pubkey::Pubkey};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::config::TradingConfig;
+use crate::format;
+use crate::notify::market::market_summary;
+use crate::solana::price::sol_price_usd;
+use crate::solana::rpc::make_rpc_client;
+use crate::tg_copy::parse_trade::OpenTrade;
+use crate::tg_copy::strategy::{BuyAmountUnit, BuyCondition, Strategy};
+use crate::trade::meme_trader::MemeTrader;
+
+/// Position size for signals below a market cap, e.g. `100k:0.2` buys 0.2
+/// SOL of tokens under $100k and `*:1` 1 SOL of anything. Bands are listed
+/// from the lowest bound up, the first one above the market cap applies.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SizeBand {
+    /// Exclusive upper bound in USD, `None` for the last, open-ended band
+    #[serde(rename = "maxMarketcap", default)]
+    pub max_market_cap: Option<f64>,
+    #[serde(rename = "solAmount")]
+    pub sol_amount: f64,
+}
+
+impl FromStr for SizeBand {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (bound, sol_amount) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Expected <market cap>:<SOL>, got '{}'", s))?;
+        let bound = bound.trim();
+        let max_market_cap = match bound {
+            "*" => None,
+            _ => Some(parse_usd_amount(bound)?),
+        };
+        Ok(Self {
+            max_market_cap,
+            sol_amount: sol_amount.trim().parse()?,
+        })
+    }
+}
+
+impl fmt::Display for SizeBand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.max_market_cap {
+            Some(max) => write!(f, "< {}: {} SOL", format::market_cap(max), self.sol_amount),
+            None => write!(f, "rest: {} SOL", self.sol_amount),
+        }
+    }
+}
+
+/// `250k` -> `250000.0`, suffixes k, m and b in any case
+fn parse_usd_amount(s: &str) -> Result<f64> {
+    let s = s.trim().trim_start_matches('$');
+    let (number, scale) = match s.chars().last().map(|c| c.to_ascii_lowercase()) {
+        Some('k') => (&s[..s.len() - 1], 1e3),
+        Some('m') => (&s[..s.len() - 1], 1e6),
+        Some('b') => (&s[..s.len() - 1], 1e9),
+        _ => (s, 1.0),
+    };
+    let value: f64 = number
+        .parse()
+        .map_err(|_| anyhow!("Invalid market cap '{}'", s))?;
+    Ok(value * scale)
+}
+
+/// Comma separated bands like `100k:0.2,500k:0.5,*:1`, bounds ascending
+pub fn parse_size_bands(s: &str) -> Result<Vec<SizeBand>> {
+    let bands = s
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::parse)
+        .collect::<Result<Vec<SizeBand>>>()?;
+    for pair in bands.windows(2) {
+        let ascending = match (pair[0].max_market_cap, pair[1].max_market_cap) {
+            (Some(lower), Some(upper)) => lower < upper,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        if !ascending {
+            return Err(anyhow!(
+                "Size bands must be listed by ascending market cap with * last, got '{}'",
+                s
+            ));
+        }
+    }
+    Ok(bands)
+}
+
+/// Band of `market_cap`, `None` when it is above the highest bound
+pub fn select_size_band(bands: &[SizeBand], market_cap: f64) -> Option<&SizeBand> {
+    bands
+        .iter()
+        .find(|band| band.max_market_cap.map_or(true, |max| market_cap < max))
+}
+
+/// Position sized as a share of the SOL the signal reports was bought,
+/// clamped to `min_sol..=max_sol`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CopyRatio {
+    pub ratio: f64,
+    pub min_sol: f64,
+    pub max_sol: Option<f64>,
+}
+
+impl CopyRatio {
+    /// Rejects a ratio that isn't a positive number and bounds that cross
+    pub fn new(ratio: f64, min_sol: f64, max_sol: Option<f64>) -> Result<Self> {
+        if !ratio.is_finite() || ratio <= 0.0 {
+            return Err(anyhow!(
+                "COPY_RATIO must be a positive number, got {}",
+                ratio
+            ));
+        }
+        if !min_sol.is_finite() || min_sol < 0.0 {
+            return Err(anyhow!(
+                "COPY_RATIO_MIN_SOL must be 0 or more, got {}",
+                min_sol
+            ));
+        }
+        if let Some(max_sol) = max_sol {
+            if !max_sol.is_finite() || max_sol < min_sol {
+                return Err(anyhow!(
+                    "COPY_RATIO_MAX_SOL {} is below COPY_RATIO_MIN_SOL {}",
+                    max_sol,
+                    min_sol
+                ));
+            }
+        }
+        Ok(Self {
+            ratio,
+            min_sol,
+            max_sol,
+        })
+    }
+
+    pub fn size_sol(&self, total_buys: f64) -> f64 {
+        let size = (self.ratio * total_buys).max(self.min_sol);
+        self.max_sol.map_or(size, |max| size.min(max))
+    }
+}
+
+impl fmt::Display for CopyRatio {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x of the signal's buys, min {} SOL",
+            self.ratio, self.min_sol
+        )?;
+        match self.max_sol {
+            Some(max) => write!(f, ", max {} SOL", max),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Which market cap picks the size band
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BandMarketCap {
+    /// Market cap quoted by the signal
+    Signal,
+    /// Current market cap from DexScreener or pump.fun, the signal's when
+    /// neither knows the token
+    Live,
+}
+
+impl FromStr for BandMarketCap {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "signal" => Ok(BandMarketCap::Signal),
+            "live" => Ok(BandMarketCap::Live),
+            _ => Err(anyhow!("Expected signal or live, got '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for BandMarketCap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BandMarketCap::Signal => write!(f, "signal"),
+            BandMarketCap::Live => write!(f, "live"),
+        }
+    }
+}
+
+async fn band_market_cap(open_trade: &OpenTrade, source: BandMarketCap) -> f64 {
+    if source == BandMarketCap::Signal {
+        return open_trade.market_cap;
+    }
+    match market_summary(&open_trade.contract_address)
+        .await
+        .and_then(|summary| summary.market_cap_usd)
+    {
+        Some(market_cap) => market_cap,
+        None => {
+            tracing::warn!(
+                "No live market cap for {}, sizing from the signal's",
+                open_trade.token
+            );
+            open_trade.market_cap
+        }
+    }
+}
+
+/// Size from the band of the signal's market cap, `None` when no band
+/// covers it
+async fn band_size_sol(
+    open_trade: &OpenTrade,
+    bands: &[SizeBand],
+    source: BandMarketCap,
+    origin: &str,
+) -> Option<f64> {
+    let market_cap = band_market_cap(open_trade, source).await;
+    let Some(band) = select_size_band(bands, market_cap) else {
+        tracing::info!(
+            "No size band of {} covers a market cap of {}",
+            origin,
+            format::market_cap(market_cap)
+        );
+        return None;
+    };
+    tracing::info!(
+        "Position size for {} at a market cap of {} from {} band {}",
+        open_trade.token,
+        format::market_cap(market_cap),
+        origin,
+        band
+    );
+    Some(band.sol_amount)
+}
+
+/// First buy condition of the strategy whose market cap range contains the
+/// signal's
+pub fn select_buy_condition(strategy: &Strategy, market_cap: f64) -> Option<&BuyCondition> {
+    strategy
+        .buy_conditions
+        .iter()
+        .find(|condition| condition.matches_market_cap(market_cap))
+}
+
+/// SOL balance trading may use, locked capital excluded
+async fn equity_sol(trader: &MemeTrader) -> Result<f64> {
+    let owner = Pubkey::from_str(&SignerContext::current().await.pubkey())?;
+    let balance = make_rpc_client().get_balance(&owner).await?;
+    let locked = match trader.capital() {
+        Some(capital) => capital.load().await?.locked_lamports,
+        None => 0,
+    };
+    Ok(lamports_to_sol(balance.saturating_sub(locked)))
+}
+
+/// SOL to spend on a buy signal. With `SIZE_FROM_STRATEGY` the strategy's
+/// `sizeBands`, or else the buy amount of its matching buy condition converted
+/// from its unit, decide. Otherwise and when neither matches `COPY_RATIO` of
+/// the signal's buys, then the band of `SIZE_BANDS` applies, and without one
+/// `POSITION_SIZE_SOL`.
+pub async fn position_size_sol(
+    open_trade: &OpenTrade,
+    strategies: &[Strategy],
+    trader: &MemeTrader,
+    t_cfg: &TradingConfig,
+) -> Result<f64> {
+    if t_cfg.size_from_strategy {
+        if let Some(size) = strategy_size_sol(open_trade, strategies, trader, t_cfg).await? {
+            return Ok(size);
+        }
+    }
+    if let Some(copy_ratio) = &t_cfg.copy_ratio {
+        match open_trade.total_buys {
+            Some(total_buys) => {
+                let size = copy_ratio.size_sol(total_buys);
+                tracing::info!(
+                    "Position size for {} from {} SOL of buys at {}: {} SOL",
+                    open_trade.token,
+                    total_buys,
+                    copy_ratio,
+                    size
+                );
+                return Ok(size);
+            }
+            None => tracing::info!(
+                "Signal of {} states no buy size, not sized by COPY_RATIO",
+                open_trade.token
+            ),
+        }
+    }
+    if !t_cfg.size_bands.is_empty() {
+        if let Some(size) = band_size_sol(
+            open_trade,
+            &t_cfg.size_bands,
+            t_cfg.size_bands_market_cap,
+            "SIZE_BANDS",
+        )
+        .await
+        {
+            return Ok(size);
+        }
+    }
+    Ok(t_cfg.position_size_sol)
+}
+
+async fn strategy_size_sol(
+    open_trade: &OpenTrade,
+    strategies: &[Strategy],
+    trader: &MemeTrader,
+    t_cfg: &TradingConfig,
+) -> Result<Option<f64>> {
+    let strategy = strategies.iter().find(|s| s.matches(&open_trade.strategy));
+    if let Some(bands) = strategy
+        .and_then(|s| s.size_bands.as_deref())
+        .filter(|bands| !bands.is_empty())
+    {
+        let origin = format!("strategy {}", open_trade.strategy);
+        return Ok(band_size_sol(open_trade, bands, t_cfg.size_bands_market_cap, &origin).await);
+    }
+    let Some(condition) = strategy.and_then(|s| select_buy_condition(s, open_trade.market_cap))
+    else {
+        tracing::info!(
+            "No buy condition of {} matches a market cap of {}",
+            open_trade.strategy,
+            open_trade.market_cap
+        );
+        return Ok(None);
+    };
+
+    let unit = condition.buy_amount_unit;
+    let sol_price = match unit {
+        BuyAmountUnit::Usd => Some(sol_price_usd().await?),
+        _ => None,
+    };
+    let equity = match unit {
+        BuyAmountUnit::EquityPercent => Some(equity_sol(trader).await?),
+        _ => None,
+    };
+    let size = unit.to_sol(condition.sol_buy_amount as f64, sol_price, equity)?;
+    tracing::info!(
+        "Position size for {} from '{}': {} {:?} = {} SOL",
+        open_trade.token,
+        condition.description,
+        condition.sol_buy_amount,
+        unit,
+        size
+    );
+    Ok(Some(size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buy_amount_units() {
+        assert_eq!(BuyAmountUnit::Sol.to_sol(0.5, None, None).unwrap(), 0.5);
+        assert_eq!(
+            BuyAmountUnit::Usd.to_sol(50.0, Some(200.0), None).unwrap(),
+            0.25
+        );
+        assert_eq!(
+            BuyAmountUnit::EquityPercent
+                .to_sol(2.0, None, Some(10.0))
+                .unwrap(),
+            0.2
+        );
+        assert!(BuyAmountUnit::Usd.to_sol(50.0, None, None).is_err());
+    }
+
+    #[test]
+    fn test_copy_ratio() {
+        let copy_ratio = CopyRatio {
+            ratio: 0.05,
+            min_sol: 0.1,
+            max_sol: Some(1.0),
+        };
+        assert_eq!(copy_ratio.size_sol(8.0), 0.4);
+        assert_eq!(copy_ratio.size_sol(1.0), 0.1);
+        assert_eq!(copy_ratio.size_sol(100.0), 1.0);
+
+        let uncapped = CopyRatio {
+            max_sol: None,
+            ..copy_ratio
+        };
+        assert_eq!(uncapped.size_sol(100.0), 5.0);
+
+        assert_eq!(CopyRatio::new(0.05, 0.1, Some(1.0)).unwrap(), copy_ratio);
+        assert!(CopyRatio::new(0.0, 0.1, None).is_err());
+        assert!(CopyRatio::new(-0.05, 0.1, None).is_err());
+        assert!(CopyRatio::new(f64::NAN, 0.1, None).is_err());
+        assert!(CopyRatio::new(0.05, 1.0, Some(0.5)).is_err());
+    }
+
+    #[test]
+    fn test_size_bands() {
+        let bands = parse_size_bands("100k:0.2, 500K:0.5, *:1").unwrap();
+        assert_eq!(
+            bands[0],
+            SizeBand {
+                max_market_cap: Some(100_000.0),
+                sol_amount: 0.2
+            }
+        );
+        let size = |market_cap| select_size_band(&bands, market_cap).map(|b| b.sol_amount);
+        assert_eq!(size(40_000.0), Some(0.2));
+        assert_eq!(size(100_000.0), Some(0.5));
+        assert_eq!(size(2_000_000.0), Some(1.0));
+
+        let capped = parse_size_bands("100k:0.2,1.5m:0.5").unwrap();
+        assert_eq!(
+            select_size_band(&capped, 2_000_000.0).map(|b| b.sol_amount),
+            None
+        );
+
+        assert!(parse_size_bands("500k:0.5,100k:0.2").is_err());
+        assert!(parse_size_bands("*:1,100k:0.2").is_err());
+        assert!(parse_size_bands("100k=0.2").is_err());
+    }
+
+    #[test]
+    fn test_unit_defaults_to_sol() {
+        let condition: BuyCondition = serde_json::from_value(serde_json::json!({
+            "timeWindowSeconds": 60,
+            "minSolBuyDelta": 1.0,
+            "minWallets": 3,
+            "minMarketcap": 10_000,
+            "maxMarketcap": 100_000,
+            "solBuyAmount": 0.5,
+            "top10MaxPercentage": 30.0,
+            "description": "small caps"
+        }))
+        .unwrap();
+        assert_eq!(condition.buy_amount_unit, BuyAmountUnit::Sol);
+        assert!(condition.matches_market_cap(50_000.0));
+        assert!(!condition.matches_market_cap(500_000.0));
+    }
+}
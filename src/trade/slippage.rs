@@ -0,0 +1,162 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use mongodb::{bson::doc, options::FindOptions, Collection, IndexModel};
+use serde::{Deserialize, Serialize};
+
+use crate::config::SlippageConfig;
+use crate::solana::quoter::Side;
+use crate::trade::execution::Venue;
+
+/// Buys of a token on a venue the adaptive slippage looks at, latest first
+const RECENT_FILLS: i64 = 5;
+
+/// Basis points `actual` is short of `quoted`, negative when the fill got
+/// more than quoted
+pub fn realized_slippage_bps(quoted: u64, actual: u64) -> Option<i64> {
+    if quoted == 0 {
+        return None;
+    }
+    Some(((quoted as i128 - actual as i128) * 10_000 / quoted as i128) as i64)
+}
+
+/// Slippage for the next buy of a token: the worst of its recent buys plus
+/// the margin, within the configured bounds. `None` without fills.
+pub fn adapted_slippage_bps(recent_bps: &[i64], cfg: &SlippageConfig) -> Option<u16> {
+    let worst = recent_bps.iter().copied().max()?.max(0);
+    let bps = worst.saturating_add(cfg.margin_bps as i64);
+    Some(bps.clamp(cfg.min_bps as i64, cfg.max_bps as i64) as u16)
+}
+
+/// Output of a confirmed swap against its quote
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlippageSample {
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub date: DateTime<Utc>,
+    pub token_address: String,
+    pub venue: Venue,
+    pub side: Side,
+    /// Tokens for a buy, lamports for a sell
+    pub quoted_out: u64,
+    /// What the transaction delivered, before fees and tips
+    pub actual_out: u64,
+    /// Negative when the fill got more than quoted
+    pub slippage_bps: i64,
+    /// Slippage the swap allowed, unknown for sells through the retry
+    /// schedule
+    pub tolerance_bps: Option<u16>,
+    pub tx_sig: String,
+}
+
+/// The `slippage` collection, with the slippage of recent buys of a token on
+/// a venue for its next buys there. Sells, filled through the retry
+/// schedule at far wider slippage, don't widen buys.
+#[derive(Clone)]
+pub struct SlippageLog {
+    collection: Collection<SlippageSample>,
+    cfg: SlippageConfig,
+}
+
+impl SlippageLog {
+    pub fn new(collection: Collection<SlippageSample>, cfg: SlippageConfig) -> Self {
+        Self { collection, cfg }
+    }
+
+    pub async fn setup_indexes(&self) -> Result<()> {
+        let index = IndexModel::builder()
+            .keys(doc! { "token_address": 1, "side": 1, "venue": 1, "date": -1 })
+            .build();
+        self.collection.create_index(index, None).await?;
+        Ok(())
+    }
+
+    pub async fn record(&self, sample: &SlippageSample) -> Result<()> {
+        tracing::info!(
+            "Realized slippage of {} {} on {}: {} bps ({} quoted, {} out)",
+            sample.side,
+            sample.token_address,
+            sample.venue,
+            sample.slippage_bps,
+            sample.quoted_out,
+            sample.actual_out
+        );
+        self.collection.insert_one(sample, None).await?;
+        Ok(())
+    }
+
+    async fn recent_bps(&self, token_address: &str, venue: Venue) -> Result<Vec<i64>> {
+        let options = FindOptions::builder()
+            .sort(doc! { "date": -1 })
+            .limit(RECENT_FILLS)
+            .build();
+        let filter = doc! {
+            "token_address": token_address,
+            "side": bson::to_bson(&Side::Buy)?,
+            "venue": bson::to_bson(&venue)?,
+        };
+        let mut cursor = self.collection.find(filter, options).await?;
+        let mut recent = Vec::new();
+        while cursor.advance().await? {
+            recent.push(cursor.deserialize_current()?.slippage_bps);
+        }
+        Ok(recent)
+    }
+
+    /// Slippage of the next buy of `token_address` on `venue`, `configured`
+    /// unless adaptive slippage is on and the token was bought there before
+    pub async fn buy_slippage_bps(
+        &self,
+        token_address: &str,
+        venue: Venue,
+        configured: u16,
+    ) -> u16 {
+        if !self.cfg.adaptive {
+            return configured;
+        }
+        match self.recent_bps(token_address, venue).await {
+            Ok(recent) => adapted_slippage_bps(&recent, &self.cfg).unwrap_or(configured),
+            Err(e) => {
+                tracing::warn!(
+                    "Could not read the slippage of {} on {}, using {} bps: {:?}",
+                    token_address,
+                    venue,
+                    configured,
+                    e
+                );
+                configured
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_realized_slippage_bps() {
+        assert_eq!(realized_slippage_bps(1_000, 950), Some(500));
+        assert_eq!(realized_slippage_bps(1_000, 1_010), Some(-100));
+        assert_eq!(realized_slippage_bps(0, 10), None);
+    }
+
+    #[test]
+    fn test_adapted_slippage_bps() {
+        let cfg = SlippageConfig {
+            adaptive: true,
+            min_bps: 100,
+            max_bps: 3_000,
+            margin_bps: 150,
+        };
+        assert_eq!(adapted_slippage_bps(&[], &cfg), None);
+        // Launch: fills far off their quotes widen it
+        assert_eq!(adapted_slippage_bps(&[800, 1_200, 300], &cfg), Some(1_350));
+        assert_eq!(adapted_slippage_bps(&[9_000], &cfg), Some(3_000));
+        // Later: fills at or better than quoted tighten it to the floor
+        assert_eq!(adapted_slippage_bps(&[-40, -200], &cfg), Some(150));
+        let cfg = SlippageConfig {
+            margin_bps: 0,
+            ..cfg
+        };
+        assert_eq!(adapted_slippage_bps(&[-40], &cfg), Some(100));
+    }
+}
@@ -0,0 +1,164 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use listen_kit::signer::SignerContext;
+use mongodb::{bson::doc, Collection, IndexModel};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{native_token::sol_to_lamports, pubkey::Pubkey};
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
+
+use crate::config::SweepConfig;
+use crate::format;
+use crate::notify::TradeEvent;
+use crate::solana::rpc::make_rpc_client;
+use crate::solana::transfer::{excess_lamports, transfer_sol, SIGNATURE_FEE_LAMPORTS};
+use crate::trade::meme_trader::MemeTrader;
+
+/// Profitable closes waiting for a sweep, more than that are swept together
+const PENDING_CLOSES: usize = 16;
+
+/// SOL moved from the hot wallet to the cold wallet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sweep {
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub date: DateTime<Utc>,
+    pub lamports: u64,
+    /// Hot wallet balance before the sweep
+    pub balance_lamports: u64,
+    pub threshold_lamports: u64,
+    pub destination: String,
+    /// Token of the profitable close that triggered it
+    pub token: Option<String>,
+    pub tx_sig: String,
+}
+
+/// The `sweeps` collection
+#[derive(Clone)]
+pub struct SweepLog {
+    collection: Collection<Sweep>,
+}
+
+impl SweepLog {
+    pub fn new(collection: Collection<Sweep>) -> Self {
+        Self { collection }
+    }
+
+    pub async fn setup_indexes(&self) -> Result<()> {
+        let index = IndexModel::builder().keys(doc! { "date": -1 }).build();
+        self.collection.create_index(index, None).await?;
+        Ok(())
+    }
+
+    pub async fn record(&self, sweep: &Sweep) -> Result<()> {
+        self.collection.insert_one(sweep, None).await?;
+        Ok(())
+    }
+}
+
+/// After every close with a realized profit, send the SOL above
+/// `SWEEP_THRESHOLD_SOL` to the cold wallet so a compromised or misbehaving
+/// hot wallet only ever risks the threshold. Needs the signer context.
+pub async fn run_profit_sweeper(
+    trader: Arc<MemeTrader>,
+    log: SweepLog,
+    cfg: SweepConfig,
+    tip_lamports: u64,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    log.setup_indexes().await?;
+    let (closes_tx, mut closes) = mpsc::channel(PENDING_CLOSES);
+    trader.add_event_hook(Arc::new(move |event: &TradeEvent| {
+        if let TradeEvent::SellExecuted {
+            token,
+            pnl: Some(pnl),
+            ..
+        } = event
+        {
+            if pnl.lamports > 0 {
+                // Full means a sweep is pending already
+                let _ = closes_tx.try_send(token.clone());
+            }
+        }
+    }));
+    tracing::info!("Profit sweeper started");
+
+    loop {
+        let mut token = tokio::select! {
+            biased;
+            _ = shutdown.changed() => {
+                tracing::info!("Profit sweeper stopped");
+                return Ok(());
+            }
+            token = closes.recv() => match token {
+                Some(token) => token,
+                None => return Ok(()),
+            },
+        };
+        while let Ok(next) = closes.try_recv() {
+            token = next;
+        }
+
+        if let Err(e) = sweep(&trader, &log, &cfg, tip_lamports, token).await {
+            tracing::error!("Profit sweep failed: {:?}", e);
+        }
+    }
+}
+
+async fn sweep(
+    trader: &MemeTrader,
+    log: &SweepLog,
+    cfg: &SweepConfig,
+    tip_lamports: u64,
+    token: String,
+) -> Result<()> {
+    let owner = Pubkey::from_str(&SignerContext::current().await.pubkey())?;
+    if owner == cfg.cold_wallet {
+        tracing::warn!("COLD_WALLET_ADDRESS is the trading wallet, nothing swept");
+        return Ok(());
+    }
+    let balance = make_rpc_client().get_balance(&owner).await?;
+    let threshold = sol_to_lamports(cfg.threshold_sol);
+    let Some(lamports) = excess_lamports(
+        balance,
+        threshold,
+        SIGNATURE_FEE_LAMPORTS + tip_lamports,
+        sol_to_lamports(cfg.min_sol),
+    ) else {
+        tracing::debug!(
+            "Balance of {} is within the sweep threshold of {}",
+            format::lamports(balance),
+            format::lamports(threshold)
+        );
+        return Ok(());
+    };
+
+    let tx_sig = transfer_sol(cfg.cold_wallet, lamports, tip_lamports).await?;
+    tracing::info!(
+        "Swept {} of profits to {}: {}",
+        format::lamports(lamports),
+        cfg.cold_wallet,
+        tx_sig
+    );
+    let sweep = Sweep {
+        date: Utc::now(),
+        lamports,
+        balance_lamports: balance,
+        threshold_lamports: threshold,
+        destination: cfg.cold_wallet.to_string(),
+        token: Some(token),
+        tx_sig,
+    };
+    if let Err(e) = log.record(&sweep).await {
+        tracing::warn!("Failed to record the sweep {}: {:?}", sweep.tx_sig, e);
+    }
+    trader.notify(TradeEvent::ProfitSwept {
+        lamports: sweep.lamports,
+        balance_lamports: sweep.balance_lamports,
+        threshold_lamports: sweep.threshold_lamports,
+        destination: sweep.destination,
+        token: sweep.token,
+        tx_sig: sweep.tx_sig,
+    });
+    Ok(())
+}
@@ -0,0 +1,362 @@
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use serde::Serialize;
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex, Notify, OwnedSemaphorePermit, Semaphore};
+
+/// Counters of the trade queue and its workers, served by the API
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskMetrics {
+    pub max_concurrent: usize,
+    pub queue_capacity: usize,
+    /// In the queue, not picked up by a worker yet
+    pub queued: usize,
+    /// Held back behind an earlier signal of the same token
+    pub waiting: usize,
+    /// Executing on a worker
+    pub in_flight: usize,
+    pub completed: u64,
+    pub failed: u64,
+    pub panicked: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    /// Spawned and not finished, wherever they are
+    pending: AtomicUsize,
+    queued: AtomicUsize,
+    waiting: AtomicUsize,
+    in_flight: AtomicUsize,
+    completed: AtomicU64,
+    failed: AtomicU64,
+    panicked: AtomicU64,
+}
+
+struct Job {
+    key: String,
+    context: String,
+    task: BoxFuture<'static, Result<()>>,
+    /// Room taken in the queue until the job runs
+    _slot: OwnedSemaphorePermit,
+}
+
+/// State the workers share with the manager
+#[derive(Default)]
+struct Shared {
+    counters: Counters,
+    /// Tokens with a signal on a worker, and the signals held back behind it
+    busy_keys: std::sync::Mutex<HashMap<String, VecDeque<Job>>>,
+    idle: Notify,
+}
+
+/// Runs trade executions on a pool of `max_concurrent` workers fed by a
+/// bounded queue. Signals with the same key run one after the other, in the
+/// order they were spawned, so two signals of a token can't buy it twice; a
+/// worker holding the token runs them while the others keep draining the
+/// queue. Signals held back that way still count against the queue
+/// capacity. Errors and panics are logged with the signal they belong to.
+pub struct TaskManager {
+    max_concurrent: usize,
+    queue_capacity: usize,
+    queue: mpsc::Sender<Job>,
+    /// One permit per signal queued or held back, not running yet
+    slots: Arc<Semaphore>,
+    shared: Arc<Shared>,
+}
+
+impl TaskManager {
+    /// Start the workers, they stop once the manager is dropped
+    pub fn new(max_concurrent: usize, queue_capacity: usize) -> Self {
+        let max_concurrent = max_concurrent.max(1);
+        let queue_capacity = queue_capacity.max(1);
+        let (queue, receiver) = mpsc::channel(queue_capacity);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let shared = Arc::new(Shared::default());
+        for _ in 0..max_concurrent {
+            tokio::spawn(run_worker(Arc::clone(&receiver), Arc::clone(&shared)));
+        }
+        Self {
+            max_concurrent,
+            queue_capacity,
+            queue,
+            slots: Arc::new(Semaphore::new(queue_capacity)),
+            shared,
+        }
+    }
+
+    /// Queue `task` to run after the earlier tasks of `key`, waiting only
+    /// while the queue is full, held back signals included. `context` names
+    /// the signal in the logs.
+    pub async fn spawn<F>(&self, key: String, context: String, task: F)
+    where
+        F: Future<Output = Result<()>> + Send + 'static,
+    {
+        let slot = Arc::clone(&self.slots)
+            .acquire_owned()
+            .await
+            .expect("the queue slots are never closed");
+        let counters = &self.shared.counters;
+        counters.pending.fetch_add(1, Ordering::SeqCst);
+        counters.queued.fetch_add(1, Ordering::Relaxed);
+        let job = Job {
+            key,
+            context,
+            task: task.boxed(),
+            _slot: slot,
+        };
+        if let Err(e) = self.queue.send(job).await {
+            // Only when every worker is gone, which catching panics prevents
+            tracing::error!("Trade queue closed, dropping {}", e.0.context);
+            counters.queued.fetch_sub(1, Ordering::Relaxed);
+            self.shared.finish();
+        }
+    }
+
+    /// Wait until every spawned task finished
+    pub async fn wait_idle(&self) {
+        loop {
+            let notified = self.shared.idle.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if self.shared.counters.pending.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    pub fn metrics(&self) -> TaskMetrics {
+        let counters = &self.shared.counters;
+        TaskMetrics {
+            max_concurrent: self.max_concurrent,
+            queue_capacity: self.queue_capacity,
+            queued: counters.queued.load(Ordering::Relaxed),
+            waiting: counters.waiting.load(Ordering::Relaxed),
+            in_flight: counters.in_flight.load(Ordering::Relaxed),
+            completed: counters.completed.load(Ordering::Relaxed),
+            failed: counters.failed.load(Ordering::Relaxed),
+            panicked: counters.panicked.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Shared {
+    /// Take `job` to run unless a signal of its token is running, then it's
+    /// held back until that one finished
+    fn claim(&self, job: Job) -> Option<Job> {
+        let mut busy_keys = self.busy_keys.lock().unwrap();
+        match busy_keys.get_mut(&job.key) {
+            Some(held) => {
+                self.counters.waiting.fetch_add(1, Ordering::Relaxed);
+                held.push_back(job);
+                None
+            }
+            None => {
+                busy_keys.insert(job.key.clone(), VecDeque::new());
+                Some(job)
+            }
+        }
+    }
+
+    /// The next signal of `key` held back, the token is free without one
+    fn next_of(&self, key: &str) -> Option<Job> {
+        let mut busy_keys = self.busy_keys.lock().unwrap();
+        let next = busy_keys.get_mut(key).and_then(VecDeque::pop_front);
+        match next {
+            Some(_) => {
+                self.counters.waiting.fetch_sub(1, Ordering::Relaxed);
+            }
+            None => {
+                busy_keys.remove(key);
+            }
+        }
+        next
+    }
+
+    fn finish(&self) {
+        if self.counters.pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.idle.notify_waiters();
+        }
+    }
+}
+
+async fn run_worker(receiver: Arc<Mutex<mpsc::Receiver<Job>>>, shared: Arc<Shared>) {
+    loop {
+        // Claimed while holding the receiver so signals of a token keep the
+        // queue order
+        let job = {
+            let mut receiver = receiver.lock().await;
+            let Some(job) = receiver.recv().await else {
+                return;
+            };
+            shared.counters.queued.fetch_sub(1, Ordering::Relaxed);
+            shared.claim(job)
+        };
+        let mut next = job;
+        while let Some(Job {
+            key,
+            context,
+            task,
+            _slot: slot,
+        }) = next
+        {
+            // Running, the job makes room in the queue
+            drop(slot);
+            run_job(&shared, &context, task).await;
+            next = shared.next_of(&key);
+        }
+    }
+}
+
+async fn run_job(shared: &Shared, context: &str, task: BoxFuture<'static, Result<()>>) {
+    let counters = &shared.counters;
+    counters.in_flight.fetch_add(1, Ordering::Relaxed);
+    match AssertUnwindSafe(task).catch_unwind().await {
+        Ok(Ok(())) => {
+            counters.completed.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(Err(e)) => {
+            counters.failed.fetch_add(1, Ordering::Relaxed);
+            tracing::error!("Trade task for {} failed: {:?}", context, e);
+        }
+        Err(panic) => {
+            counters.panicked.fetch_add(1, Ordering::Relaxed);
+            tracing::error!(
+                "Trade task for {} panicked: {}",
+                context,
+                panic_message(panic.as_ref())
+            );
+        }
+    }
+    counters.in_flight.fetch_sub(1, Ordering::Relaxed);
+    shared.finish();
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "non-string panic payload"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_bounds_and_isolates_panics() {
+        let manager = TaskManager::new(2, 16);
+        let peak = Arc::new(AtomicUsize::new(0));
+        let running = Arc::new(AtomicUsize::new(0));
+        for i in 0..6 {
+            let peak = Arc::clone(&peak);
+            let running = Arc::clone(&running);
+            manager
+                .spawn(
+                    format!("token {}", i),
+                    format!("signal {}", i),
+                    async move {
+                        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        peak.fetch_max(now, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                        running.fetch_sub(1, Ordering::SeqCst);
+                        match i {
+                            0 => panic!("boom"),
+                            1 => Err(anyhow!("no route")),
+                            _ => Ok(()),
+                        }
+                    },
+                )
+                .await;
+        }
+        manager.wait_idle().await;
+
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        let metrics = manager.metrics();
+        assert_eq!(metrics.queued, 0);
+        assert_eq!(metrics.waiting, 0);
+        assert_eq!(metrics.in_flight, 0);
+        assert_eq!(metrics.completed, 4);
+        assert_eq!(metrics.failed, 1);
+        assert_eq!(metrics.panicked, 1);
+    }
+
+    #[tokio::test]
+    async fn test_serializes_signals_of_a_token() {
+        let manager = TaskManager::new(4, 2);
+        let order = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        // More signals than the queue holds, spawn waits for room
+        for i in 0..6 {
+            let order = Arc::clone(&order);
+            let running = Arc::clone(&running);
+            let peak = Arc::clone(&peak);
+            manager
+                .spawn("MEME".to_string(), format!("signal {}", i), async move {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                    order.lock().unwrap().push(i);
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    Ok(())
+                })
+                .await;
+        }
+        manager.wait_idle().await;
+
+        assert_eq!(peak.load(Ordering::SeqCst), 1);
+        assert_eq!(*order.lock().unwrap(), vec![0, 1, 2, 3, 4, 5]);
+        assert!(manager.shared.busy_keys.lock().unwrap().is_empty());
+        assert_eq!(manager.metrics().completed, 6);
+    }
+
+    #[tokio::test]
+    async fn test_held_back_signals_fill_the_queue() {
+        let manager = TaskManager::new(4, 2);
+        let release = Arc::new(Notify::new());
+        let started = Arc::new(Notify::new());
+        {
+            let (release, started) = (Arc::clone(&release), Arc::clone(&started));
+            manager
+                .spawn("MEME".to_string(), "signal 0".to_string(), async move {
+                    started.notify_one();
+                    release.notified().await;
+                    Ok(())
+                })
+                .await;
+        }
+        started.notified().await;
+        // Held back behind the first, they take the whole queue
+        for i in 1..3 {
+            manager
+                .spawn("MEME".to_string(), format!("signal {}", i), async {
+                    Ok(())
+                })
+                .await;
+        }
+        let spawn_other = manager.spawn("OTHER".to_string(), "other".to_string(), async { Ok(()) });
+        tokio::pin!(spawn_other);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(20), spawn_other.as_mut())
+                .await
+                .is_err()
+        );
+
+        release.notify_one();
+        spawn_other.await;
+        manager.wait_idle().await;
+        assert_eq!(manager.metrics().completed, 4);
+    }
+}
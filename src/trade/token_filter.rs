@@ -0,0 +1,192 @@
+use anyhow::{Context, Result};
+use bson::doc;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::fs;
+use std::str::FromStr;
+
+use crate::config::TokenFilterConfig;
+use crate::solana::cache::cached_fetch_metadata;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterList {
+    Blacklist,
+    Whitelist,
+}
+
+/// Entry of the `token_filters` collection. `value` is a mint address, a
+/// token symbol or a deployer wallet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenFilterEntry {
+    pub list: FilterList,
+    pub value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// Blacklist and whitelist merged from the configured files and the
+/// `token_filters` collection. Addresses match exactly, symbols
+/// case-insensitively and with or without a leading `$`.
+#[derive(Debug, Default)]
+pub struct TokenFilter {
+    blacklist: Vec<String>,
+    whitelist: Vec<String>,
+}
+
+fn normalize(value: &str) -> String {
+    value.trim().trim_start_matches('$').to_string()
+}
+
+/// One entry per line, `#` starts a comment
+fn read_list(path: &str) -> Result<Vec<String>> {
+    Ok(fs::read_to_string(path)
+        .with_context(|| format!("Failed to read token list {}", path))?
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(normalize)
+        .collect())
+}
+
+/// The configured list files and the `token_filters` collection
+#[derive(Clone)]
+pub struct TokenFilterSource {
+    cfg: TokenFilterConfig,
+    collection: Collection<TokenFilterEntry>,
+}
+
+impl TokenFilterSource {
+    pub fn new(cfg: TokenFilterConfig, collection: Collection<TokenFilterEntry>) -> Self {
+        Self { cfg, collection }
+    }
+
+    /// Read both sources, called per signal so edits apply without a restart
+    pub async fn load(&self) -> Result<TokenFilter> {
+        let mut filter = TokenFilter::default();
+        if let Some(path) = &self.cfg.blacklist_file {
+            filter.blacklist = read_list(path)?;
+        }
+        if let Some(path) = &self.cfg.whitelist_file {
+            filter.whitelist = read_list(path)?;
+        }
+
+        let mut cursor = self.collection.find(doc! {}, None).await?;
+        while cursor.advance().await? {
+            let entry = cursor.deserialize_current()?;
+            match entry.list {
+                FilterList::Blacklist => filter.blacklist.push(normalize(&entry.value)),
+                FilterList::Whitelist => filter.whitelist.push(normalize(&entry.value)),
+            }
+        }
+        Ok(filter)
+    }
+}
+
+impl TokenFilter {
+    /// Wallet entries can only be told apart from mints by looking the
+    /// deployer up, which is skipped when no entry is an address
+    fn has_addresses(&self) -> bool {
+        self.blacklist
+            .iter()
+            .chain(&self.whitelist)
+            .any(|value| Pubkey::from_str(value).is_ok())
+    }
+
+    /// Reason to skip the token, `None` if it may be bought
+    pub fn rejection(&self, mint: &str, symbol: &str, deployer: Option<&str>) -> Option<String> {
+        // Base58 is case-sensitive, addresses are compared parsed
+        let addresses: Vec<Pubkey> = [Some(mint), deployer]
+            .into_iter()
+            .flatten()
+            .filter_map(|address| Pubkey::from_str(address.trim()).ok())
+            .collect();
+        let symbol = normalize(symbol);
+        let matches = |value: &str| match Pubkey::from_str(value) {
+            Ok(address) => addresses.contains(&address),
+            Err(_) => value.eq_ignore_ascii_case(&symbol),
+        };
+        let hit = |list: &[String]| list.iter().find(|value| matches(value)).cloned();
+
+        if let Some(value) = hit(&self.blacklist) {
+            return Some(format!("'{}' is blacklisted", value));
+        }
+        if !self.whitelist.is_empty() && hit(&self.whitelist).is_none() {
+            return Some("not on the whitelist".to_string());
+        }
+        None
+    }
+
+    /// Like [`Self::rejection`], fetching the deployer when wallet entries
+    /// exist. Tokens without pump.fun metadata have no known deployer.
+    pub async fn check(&self, mint: &str, symbol: &str) -> Option<String> {
+        if self.blacklist.is_empty() && self.whitelist.is_empty() {
+            return None;
+        }
+
+        let deployer = if self.has_addresses() {
+            match Pubkey::from_str(mint) {
+                Ok(mint) => cached_fetch_metadata(&mint)
+                    .await
+                    .ok()
+                    .map(|info| info.creator),
+                Err(_) => None,
+            }
+        } else {
+            None
+        };
+        self.rejection(mint, symbol, deployer.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINT: &str = "EKpQGSJtjMFqKZ9KQanSqYXRcF8fBopzLHYxdM65zcjm";
+    const DEPLOYER: &str = "9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM";
+
+    fn filter(blacklist: &[&str], whitelist: &[&str]) -> TokenFilter {
+        TokenFilter {
+            blacklist: blacklist.iter().map(|v| normalize(v)).collect(),
+            whitelist: whitelist.iter().map(|v| normalize(v)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_blacklist() {
+        assert!(filter(&[MINT], &[]).rejection(MINT, "WIF", None).is_some());
+        assert!(filter(&["$wif"], &[])
+            .rejection(MINT, "WIF", None)
+            .is_some());
+        assert!(filter(&[DEPLOYER], &[])
+            .rejection(MINT, "WIF", Some(DEPLOYER))
+            .is_some());
+        assert!(filter(&["BONK"], &[])
+            .rejection(MINT, "WIF", None)
+            .is_none());
+        // Another case is another address
+        assert!(filter(&[&MINT.to_lowercase()], &[])
+            .rejection(MINT, "WIF", None)
+            .is_none());
+    }
+
+    #[test]
+    fn test_whitelist() {
+        assert!(filter(&[], &["WIF"])
+            .rejection(MINT, "$WIF", None)
+            .is_none());
+        assert!(filter(&[], &["BONK"])
+            .rejection(MINT, "WIF", None)
+            .is_some());
+        // Deployer unknown, so a wallet-only whitelist rejects
+        assert!(filter(&[], &[DEPLOYER])
+            .rejection(MINT, "WIF", None)
+            .is_some());
+        // Blacklist wins
+        assert!(filter(&["WIF"], &["WIF"])
+            .rejection(MINT, "WIF", None)
+            .is_some());
+    }
+}
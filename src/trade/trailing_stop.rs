@@ -0,0 +1,253 @@
+use anyhow::Result;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time;
+
+use crate::solana::price;
+use crate::tg_copy::active_trade::ActiveTrade;
+use crate::tg_copy::parse_trade::OperationType;
+use crate::tg_copy::strategy::{StopLossCondition, Strategy, TrailingStopLossCondition};
+use crate::tg_copy::strategy_manager::StrategyManager;
+use crate::trade::meme_trader::MemeTrader;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Drop from the highest price, in percent, at which the stop triggers. In
+/// the logarithmic mode the trail tightens as the position gains, it is
+/// divided by `1 + ln(highest / entry)`.
+pub fn trail_pct(tsl: &TrailingStopLossCondition, entry_price: f64, highest_price: f64) -> f64 {
+    let pct = tsl.trailing_stop_loss_percentage as f64;
+    if !tsl.is_logarithmic || entry_price <= 0.0 {
+        return pct;
+    }
+    pct / (1.0 + (highest_price / entry_price).ln().max(0.0))
+}
+
+/// Price `stop_loss_percentage` below `entry_price`, the stop of a
+/// position bought there. `None` without a usable entry price or loss.
+pub fn entry_stop_price(entry_price: f64, sl: &StopLossCondition) -> Option<f64> {
+    let pct = sl.stop_loss_percentage.unsigned_abs() as f64;
+    if entry_price <= 0.0 || pct == 0.0 || pct >= 100.0 {
+        return None;
+    }
+    Some(entry_price * (1.0 - pct / 100.0))
+}
+
+/// Percent `price` is below `highest_price`
+pub fn drawdown_pct(highest_price: f64, price: f64) -> f64 {
+    if highest_price <= 0.0 {
+        return 0.0;
+    }
+    (highest_price - price) / highest_price * 100.0
+}
+
+/// Percent `price` is above `entry_price`
+pub fn profit_pct(entry_price: f64, price: f64) -> f64 {
+    if entry_price <= 0.0 {
+        return 0.0;
+    }
+    (price - entry_price) / entry_price * 100.0
+}
+
+/// Follow every open position with the price feed, the streamed pool price
+/// first. Positions are sold whole at their stop registered on entry. Legs
+/// of the take profit ladder of its strategy are sold as the price reaches
+/// them, each once. Its `highest_price` is raised and the rest sold once it
+/// falls further below than the trailing stop allows. Positions without a
+/// stop, either condition or a price are left alone.
+pub async fn run_trailing_stop(
+    trader: Arc<MemeTrader>,
+    strategies: StrategyManager,
+    tip_lamports: u64,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    let mut interval = time::interval(CHECK_INTERVAL);
+    tracing::info!("Trailing stop started");
+
+    loop {
+        tokio::select! {
+            biased;
+            _ = shutdown.changed() => {
+                tracing::info!("Trailing stop stopped");
+                return Ok(());
+            }
+            _ = interval.tick() => {}
+        }
+
+        let trades = match trader.active_trades().load_all_trades().await {
+            Ok(trades) => trades,
+            Err(e) => {
+                tracing::error!("Trailing stop failed to load active trades: {:?}", e);
+                continue;
+            }
+        };
+        if trades.is_empty() {
+            continue;
+        }
+        // Signal prices are in USD, the feed converts pool prices
+        let feed = price::feed();
+        let strategies = strategies.all();
+        for trade in trades {
+            let Some(strategy) = strategies.iter().find(|s| s.matches(&trade.strategy_id)) else {
+                continue;
+            };
+            let conditions = &strategy.sell_conditions;
+            if trade.stop_price.is_none()
+                && conditions.trailing_stop_loss_condition.is_none()
+                && conditions.take_profit_conditions.is_none()
+            {
+                continue;
+            }
+            let price = match feed.price_usd(&trade.token_address).await {
+                Ok(price) => price,
+                Err(e) => {
+                    tracing::debug!(
+                        "Trailing stop could not price {}: {:?}",
+                        trade.token_name,
+                        e
+                    );
+                    continue;
+                }
+            };
+            if let Err(e) = check_position(&trader, trade, strategy, price, tip_lamports).await {
+                tracing::warn!("Trailing stop check failed: {:?}", e);
+            }
+        }
+    }
+}
+
+async fn check_position(
+    trader: &MemeTrader,
+    mut trade: ActiveTrade,
+    strategy: &Strategy,
+    price: f64,
+    tip_lamports: u64,
+) -> Result<()> {
+    if let Some(stop_price) = trade.stop_price.filter(|stop| price <= *stop) {
+        let profit = profit_pct(trade.entry_price, price);
+        tracing::warn!(
+            "Stop loss hit for {} ({}): {} at or below its stop of {}, {:.2}% from entry",
+            trade.token_name,
+            trade.strategy_id,
+            price,
+            stop_price,
+            profit
+        );
+        trader
+            .meta_sell(
+                &trade.token_address,
+                &trade.strategy_id,
+                profit,
+                OperationType::StopLoss,
+                strategy,
+                tip_lamports,
+                None,
+            )
+            .await?;
+        return Ok(());
+    }
+
+    if let Some(ladder) = &strategy.sell_conditions.take_profit_conditions {
+        let profit = profit_pct(trade.entry_price, price);
+        if let Some(fill) = trader
+            .sell_tp_legs(&trade, ladder, profit, tip_lamports, None)
+            .await?
+        {
+            tracing::info!(
+                "Take profit leg sold {} of {} ({}) at {:.2}% in {}",
+                fill.token_amount,
+                trade.token_name,
+                trade.strategy_id,
+                profit,
+                fill.tx_sig
+            );
+            // The trailing stop goes on with what is left on the next check
+            return Ok(());
+        }
+    }
+
+    let Some(tsl) = &strategy.sell_conditions.trailing_stop_loss_condition else {
+        return Ok(());
+    };
+    if price > trade.highest_price {
+        trader
+            .active_trades()
+            .update_highest_price(&trade.token_address, &trade.strategy_id, price)
+            .await?;
+        trade.update_highest_price(price);
+        return Ok(());
+    }
+
+    let drawdown = drawdown_pct(trade.highest_price, price);
+    let trail = trail_pct(tsl, trade.entry_price, trade.highest_price);
+    if drawdown < trail {
+        return Ok(());
+    }
+
+    tracing::warn!(
+        "Trailing stop hit for {} ({}): {:.2}% below its high of {}, trail {:.2}%",
+        trade.token_name,
+        trade.strategy_id,
+        drawdown,
+        trade.highest_price,
+        trail
+    );
+    trader
+        .meta_sell(
+            &trade.token_address,
+            &trade.strategy_id,
+            -drawdown,
+            OperationType::TrailingStopLoss,
+            strategy,
+            tip_lamports,
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tsl(is_logarithmic: bool) -> TrailingStopLossCondition {
+        TrailingStopLossCondition {
+            trailing_stop_loss_percentage: 20.0,
+            is_logarithmic,
+            description: "trail".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_linear_trail() {
+        assert_eq!(trail_pct(&tsl(false), 1.0, 10.0), 20.0);
+        assert_eq!(drawdown_pct(2.0, 1.5), 25.0);
+        assert_eq!(drawdown_pct(2.0, 2.5), -25.0);
+        assert_eq!(profit_pct(2.0, 5.0), 150.0);
+    }
+
+    #[test]
+    fn test_entry_stop_price() {
+        let sl = |stop_loss_percentage| StopLossCondition {
+            stop_loss_percentage,
+            description: "stop".to_string(),
+        };
+        assert_eq!(entry_stop_price(2.0, &sl(25)), Some(1.5));
+        // Losses may be written negative
+        assert_eq!(entry_stop_price(2.0, &sl(-25)), Some(1.5));
+        assert_eq!(entry_stop_price(2.0, &sl(0)), None);
+        assert_eq!(entry_stop_price(0.0, &sl(25)), None);
+    }
+
+    #[test]
+    fn test_logarithmic_trail_tightens() {
+        let tsl = tsl(true);
+        assert_eq!(trail_pct(&tsl, 1.0, 1.0), 20.0);
+        assert_eq!(trail_pct(&tsl, 1.0, 0.5), 20.0);
+        let doubled = trail_pct(&tsl, 1.0, 2.0);
+        let tenfold = trail_pct(&tsl, 1.0, 10.0);
+        assert!(doubled < 20.0 && tenfold < doubled);
+        assert!((doubled - 20.0 / (1.0 + 2f64.ln())).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,177 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time;
+
+use crate::config::UsdBackfillConfig;
+use crate::trade::execution::ExecutionLog;
+
+/// Values executions recorded without a USD price, e.g. before prices were
+/// recorded, at the SOL price of the minute they happened. Requests to the
+/// price API are spread to stay under its rate limit and every execution is
+/// stored once valued, so a restart picks up where the last run stopped.
+pub async fn run_usd_backfill(
+    executions: ExecutionLog,
+    cfg: UsdBackfillConfig,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    let client = Client::new();
+    let request_delay = Duration::from_secs(60) / cfg.requests_per_min;
+    let mut interval = time::interval(Duration::from_secs(cfg.interval_secs));
+    tracing::info!("USD backfill started");
+
+    loop {
+        tokio::select! {
+            biased;
+            _ = shutdown.changed() => {
+                tracing::info!("USD backfill stopped");
+                return Ok(());
+            }
+            _ = interval.tick() => {}
+        }
+
+        // Prices of the minutes already asked for in this round
+        let mut prices = HashMap::new();
+        let mut valued = 0;
+        'round: loop {
+            let batch = match executions.unvalued(cfg.batch_size).await {
+                Ok(batch) => batch,
+                Err(e) => {
+                    tracing::error!("Failed to load executions to value: {:?}", e);
+                    break 'round;
+                }
+            };
+            if batch.is_empty() {
+                break;
+            }
+
+            for execution in batch {
+                let (Some(id), Some(sol_lamports)) = (execution.id, execution.sol_lamports) else {
+                    continue;
+                };
+                let minute = minute_start_ms(execution.date);
+                let price = match prices.get(&minute) {
+                    Some(price) => *price,
+                    None => {
+                        tokio::select! {
+                            biased;
+                            _ = shutdown.changed() => {
+                                tracing::info!("USD backfill stopped");
+                                return Ok(());
+                            }
+                            _ = time::sleep(request_delay) => {}
+                        }
+                        match historical_sol_price_usd(&client, &cfg.price_url, minute).await {
+                            Ok(price) => {
+                                prices.insert(minute, price);
+                                price
+                            }
+                            // Rate limited or down, the next round retries
+                            Err(e) => {
+                                tracing::warn!(
+                                    "Could not get the SOL price of {}: {:?}",
+                                    execution.date,
+                                    e
+                                );
+                                break 'round;
+                            }
+                        }
+                    }
+                };
+                if price.is_none() {
+                    tracing::warn!("No SOL price for {}, leaving it unvalued", execution.date);
+                }
+                if let Err(e) = executions.set_usd_value(id, sol_lamports, price).await {
+                    tracing::error!("Failed to store the USD value of execution {}: {:?}", id, e);
+                    break 'round;
+                }
+                valued += 1;
+            }
+        }
+        if valued > 0 {
+            tracing::info!("Valued {} executions in USD", valued);
+        }
+    }
+}
+
+fn minute_start_ms(date: DateTime<Utc>) -> i64 {
+    let ms = date.timestamp_millis();
+    ms - ms.rem_euclid(60_000)
+}
+
+/// Opening SOL/USDT price of the minute starting at `minute_ms`, `None`
+/// when the API has no candle for it
+async fn historical_sol_price_usd(
+    client: &Client,
+    price_url: &str,
+    minute_ms: i64,
+) -> Result<Option<f64>> {
+    let klines: Vec<Vec<Value>> = client
+        .get(price_url)
+        .query(&[
+            ("symbol", "SOLUSDT".to_string()),
+            ("interval", "1m".to_string()),
+            ("startTime", minute_ms.to_string()),
+            ("limit", "1".to_string()),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    kline_open_price(&klines, minute_ms)
+}
+
+/// Klines are arrays of `[open time, open, high, low, close, ...]` with the
+/// prices as strings. The API answers with the next candle when the minute
+/// itself has none, which is no price for that minute.
+fn kline_open_price(klines: &[Vec<Value>], minute_ms: i64) -> Result<Option<f64>> {
+    let Some(kline) = klines.first() else {
+        return Ok(None);
+    };
+    if kline.first().and_then(Value::as_i64) != Some(minute_ms) {
+        return Ok(None);
+    }
+    let open = kline
+        .get(1)
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow!("Unexpected kline {:?}", kline))?;
+    Ok(Some(open.parse()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_minute_start() {
+        let date = DateTime::parse_from_rfc3339("2024-05-01T12:34:56.789Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(minute_start_ms(date), 1_714_566_840_000);
+    }
+
+    #[test]
+    fn test_kline_open_price() {
+        let klines: Vec<Vec<Value>> = serde_json::from_value(json!([[
+            1_714_566_840_000i64,
+            "142.51000000",
+            "142.80000000",
+            "142.40000000",
+            "142.70000000",
+            "1234.5"
+        ]]))
+        .unwrap();
+        assert_eq!(
+            kline_open_price(&klines, 1_714_566_840_000).unwrap(),
+            Some(142.51)
+        );
+        assert_eq!(kline_open_price(&klines, 1_714_566_780_000).unwrap(), None);
+        assert_eq!(kline_open_price(&[], 1_714_566_840_000).unwrap(), None);
+    }
+}